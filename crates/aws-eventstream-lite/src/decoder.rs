@@ -30,8 +30,10 @@
 //!                  └────────────┘
 //! ```
 
-use super::error::{ParseError, ParseResult};
-use super::frame::{parse_frame, Frame, PRELUDE_SIZE};
+use crate::crc::crc32;
+use crate::error::{ParseError, ParseResult};
+use crate::frame::{parse_frame, Frame, PRELUDE_SIZE};
+use alloc::string::ToString;
 use bytes::{Buf, BytesMut};
 
 /// 默认最大缓冲区大小 (16 MB)
@@ -43,6 +45,40 @@ pub const DEFAULT_MAX_ERRORS: usize = 5;
 /// 默认初始缓冲区容量
 pub const DEFAULT_BUFFER_CAPACITY: usize = 8192;
 
+/// 超出缓冲区上限时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowStrategy {
+    /// 返回 `BufferOverflow` 错误，声明长度超限的帧使解码器停止
+    #[default]
+    Error,
+    /// 丢弃超出上限的数据，声明长度超限的帧整帧跳过后继续解码
+    Truncate,
+}
+
+/// 解码器缓冲配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderConfig {
+    /// 初始缓冲区容量（不超过 `max_buffer_size`）
+    pub initial_capacity: usize,
+    /// 最大缓冲字节数，同时也是允许的最大帧长度
+    pub max_buffer_size: usize,
+    /// 最大连续错误数
+    pub max_errors: usize,
+    /// 超出上限时的处理策略
+    pub overflow: OverflowStrategy,
+}
+
+impl Default for DecoderConfig {
+    fn default() -> Self {
+        Self {
+            initial_capacity: DEFAULT_BUFFER_CAPACITY,
+            max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
+            max_errors: DEFAULT_MAX_ERRORS,
+            overflow: OverflowStrategy::Error,
+        }
+    }
+}
+
 /// 解码器状态
 ///
 /// 采用四态模型，参考 kiro-kt 的设计：
@@ -69,7 +105,7 @@ pub enum DecoderState {
 /// # Example
 ///
 /// ```rust,ignore
-/// use kiro_rs::kiro::parser::EventStreamDecoder;
+/// use aws_eventstream_lite::EventStreamDecoder;
 ///
 /// let mut decoder = EventStreamDecoder::new();
 ///
@@ -97,6 +133,10 @@ pub struct EventStreamDecoder {
     max_errors: usize,
     /// 最大缓冲区大小
     max_buffer_size: usize,
+    /// 超出上限时的处理策略
+    overflow: OverflowStrategy,
+    /// 正在跳过的超限帧剩余字节数
+    discard_remaining: usize,
     /// 跳过的字节数（用于调试）
     bytes_skipped: usize,
 }
@@ -107,6 +147,7 @@ impl Default for EventStreamDecoder {
     }
 }
 
+#[allow(dead_code)]
 impl EventStreamDecoder {
     /// 创建新的解码器
     pub fn new() -> Self {
@@ -115,26 +156,33 @@ impl EventStreamDecoder {
 
     /// 创建具有指定缓冲区大小的解码器
     pub fn with_capacity(capacity: usize) -> Self {
-        Self {
-            buffer: BytesMut::with_capacity(capacity),
-            state: DecoderState::Ready,
-            frames_decoded: 0,
-            error_count: 0,
-            max_errors: DEFAULT_MAX_ERRORS,
-            max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
-            bytes_skipped: 0,
-        }
+        Self::from_config(DecoderConfig {
+            initial_capacity: capacity,
+            ..DecoderConfig::default()
+        })
     }
 
     /// 创建具有自定义配置的解码器
     pub fn with_config(capacity: usize, max_errors: usize, max_buffer_size: usize) -> Self {
+        Self::from_config(DecoderConfig {
+            initial_capacity: capacity,
+            max_buffer_size,
+            max_errors,
+            overflow: OverflowStrategy::Error,
+        })
+    }
+
+    /// 按缓冲配置创建解码器
+    pub fn from_config(config: DecoderConfig) -> Self {
         Self {
-            buffer: BytesMut::with_capacity(capacity),
+            buffer: BytesMut::with_capacity(config.initial_capacity.min(config.max_buffer_size)),
             state: DecoderState::Ready,
             frames_decoded: 0,
             error_count: 0,
-            max_errors,
-            max_buffer_size,
+            max_errors: config.max_errors,
+            max_buffer_size: config.max_buffer_size,
+            overflow: config.overflow,
+            discard_remaining: 0,
             bytes_skipped: 0,
         }
     }
@@ -143,15 +191,34 @@ impl EventStreamDecoder {
     ///
     /// # Returns
     /// - `Ok(())` - 数据已添加到缓冲区
-    /// - `Err(BufferOverflow)` - 缓冲区已满
+    /// - `Err(BufferOverflow)` - 缓冲区已满（`Truncate` 策略下丢弃超出部分并返回 `Ok`）
     pub fn feed(&mut self, data: &[u8]) -> ParseResult<()> {
+        // 跳过正在丢弃的超限帧
+        let discard = self.discard_remaining.min(data.len());
+        self.discard_remaining -= discard;
+        let mut data = &data[discard..];
+
         // 检查缓冲区大小限制
         let new_size = self.buffer.len() + data.len();
         if new_size > self.max_buffer_size {
-            return Err(ParseError::BufferOverflow {
-                size: new_size,
-                max: self.max_buffer_size,
-            });
+            match self.overflow {
+                OverflowStrategy::Error => {
+                    return Err(ParseError::BufferOverflow {
+                        size: new_size,
+                        max: self.max_buffer_size,
+                    });
+                }
+                OverflowStrategy::Truncate => {
+                    let keep = self.max_buffer_size - self.buffer.len();
+                    self.bytes_skipped += data.len() - keep;
+                    tracing::warn!(
+                        "缓冲区已满: 丢弃 {} 字节 (最大 {})",
+                        data.len() - keep,
+                        self.max_buffer_size
+                    );
+                    data = &data[..keep];
+                }
+            }
         }
 
         self.buffer.extend_from_slice(data);
@@ -185,6 +252,39 @@ impl EventStreamDecoder {
             return Ok(None);
         }
 
+        // 帧声明的长度超过缓冲区上限时，不等待数据到齐
+        if let Some(total_length) = self.oversized_frame() {
+            match self.overflow {
+                OverflowStrategy::Error => {
+                    self.state = DecoderState::Stopped;
+                    tracing::error!(
+                        "解码器停止: 帧长度 {} 字节超过缓冲区上限 {}",
+                        total_length,
+                        self.max_buffer_size
+                    );
+                    return Err(ParseError::BufferOverflow {
+                        size: total_length,
+                        max: self.max_buffer_size,
+                    });
+                }
+                OverflowStrategy::Truncate => {
+                    let available = self.buffer.len().min(total_length);
+                    self.buffer.advance(available);
+                    self.discard_remaining = total_length - available;
+                    self.bytes_skipped += total_length;
+                    tracing::warn!(
+                        "跳过超限帧: {} 字节 (最大 {})",
+                        total_length,
+                        self.max_buffer_size
+                    );
+                    if self.buffer.is_empty() {
+                        self.state = DecoderState::Ready;
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+
         // 转移到 Parsing 状态
         self.state = DecoderState::Parsing;
 
@@ -228,6 +328,26 @@ impl EventStreamDecoder {
         }
     }
 
+    /// 缓冲区开头的帧声明长度超过上限时返回该长度
+    ///
+    /// 只信任通过 Prelude CRC 校验的长度，避免把损坏数据当作超限帧
+    fn oversized_frame(&self) -> Option<usize> {
+        if self.buffer.len() < PRELUDE_SIZE {
+            return None;
+        }
+        let word = |i: usize| {
+            u32::from_be_bytes([
+                self.buffer[i],
+                self.buffer[i + 1],
+                self.buffer[i + 2],
+                self.buffer[i + 3],
+            ])
+        };
+        let total_length = word(0) as usize;
+        (total_length > self.max_buffer_size && crc32(&self.buffer[..8]) == word(8))
+            .then_some(total_length)
+    }
+
     /// 创建解码迭代器
     pub fn decode_iter(&mut self) -> DecodeIter<'_> {
         DecodeIter { decoder: self }
@@ -311,6 +431,7 @@ impl EventStreamDecoder {
     pub fn reset(&mut self) {
         self.buffer.clear();
         self.state = DecoderState::Ready;
+        self.discard_remaining = 0;
         self.frames_decoded = 0;
         self.error_count = 0;
         self.bytes_skipped = 0;
@@ -419,6 +540,74 @@ mod tests {
         assert!(matches!(result, Err(ParseError::BufferOverflow { .. })));
     }
 
+    /// 构造只有 prelude 的帧头（声明指定的总长度）
+    fn prelude(total_length: u32) -> Vec<u8> {
+        let mut bytes = total_length.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        bytes.extend_from_slice(&crc32(&bytes).to_be_bytes());
+        bytes
+    }
+
+    /// 构造不含头部的完整帧
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = prelude((PRELUDE_SIZE + payload.len() + 4) as u32);
+        bytes.extend_from_slice(payload);
+        bytes.extend_from_slice(&crc32(&bytes).to_be_bytes());
+        bytes
+    }
+
+    fn config(overflow: OverflowStrategy) -> DecoderConfig {
+        DecoderConfig {
+            initial_capacity: 1024 * 1024,
+            max_buffer_size: 64,
+            max_errors: DEFAULT_MAX_ERRORS,
+            overflow,
+        }
+    }
+
+    #[test]
+    fn test_decoder_from_config_caps_capacity() {
+        let decoder = EventStreamDecoder::from_config(config(OverflowStrategy::Error));
+        assert!(decoder.buffer.capacity() < 1024 * 1024);
+    }
+
+    #[test]
+    fn test_decoder_oversized_frame_error() {
+        let mut decoder = EventStreamDecoder::from_config(config(OverflowStrategy::Error));
+        decoder.feed(&prelude(16 * 1024 * 1024)).unwrap();
+        let result = decoder.decode();
+        assert!(matches!(
+            result,
+            Err(ParseError::BufferOverflow { size, max: 64 }) if size == 16 * 1024 * 1024
+        ));
+        assert!(decoder.is_stopped());
+    }
+
+    #[test]
+    fn test_decoder_oversized_frame_truncate() {
+        let mut decoder = EventStreamDecoder::from_config(config(OverflowStrategy::Truncate));
+        // 超限帧的剩余字节分多次到达，应被丢弃而不缓冲
+        decoder.feed(&prelude(100)).unwrap();
+        assert!(matches!(decoder.decode(), Ok(None)));
+        decoder.feed(&[0u8; 50]).unwrap();
+        let mut rest = vec![0u8; 38];
+        rest.extend(frame(b"ok"));
+        decoder.feed(&rest).unwrap();
+
+        let frame = decoder.decode().unwrap().unwrap();
+        assert_eq!(frame.payload, b"ok");
+        assert_eq!(decoder.bytes_skipped(), 100);
+        assert_eq!(decoder.buffer_len(), 0);
+    }
+
+    #[test]
+    fn test_decoder_truncate_on_feed() {
+        let mut decoder = EventStreamDecoder::from_config(config(OverflowStrategy::Truncate));
+        decoder.feed(&[0u8; 100]).unwrap();
+        assert_eq!(decoder.buffer_len(), 64);
+        assert_eq!(decoder.bytes_skipped(), 36);
+    }
+
     #[test]
     fn test_decoder_insufficient_data() {
         let mut decoder = EventStreamDecoder::new();
@@ -1,6 +1,7 @@
 //! AWS Event Stream 解析错误定义
 
-use std::fmt;
+use alloc::string::String;
+use core::fmt;
 
 /// 解析错误类型
 #[derive(Debug)]
@@ -22,8 +23,10 @@ pub enum ParseError {
     /// 无效的消息类型
     InvalidMessageType(String),
     /// Payload 反序列化失败
+    #[cfg(feature = "json")]
     PayloadDeserialize(serde_json::Error),
     /// IO 错误
+    #[cfg(feature = "std")]
     Io(std::io::Error),
     /// 连续错误过多，解码器已停止
     TooManyErrors { count: usize, last_error: String },
@@ -31,6 +34,7 @@ pub enum ParseError {
     BufferOverflow { size: usize, max: usize },
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for ParseError {}
 
 impl fmt::Display for ParseError {
@@ -62,7 +66,9 @@ impl fmt::Display for ParseError {
                 write!(f, "消息长度过小: {} 字节 (最小 {})", length, min)
             }
             Self::InvalidMessageType(t) => write!(f, "无效的消息类型: {}", t),
+            #[cfg(feature = "json")]
             Self::PayloadDeserialize(e) => write!(f, "Payload 反序列化失败: {}", e),
+            #[cfg(feature = "std")]
             Self::Io(e) => write!(f, "IO 错误: {}", e),
             Self::TooManyErrors { count, last_error } => {
                 write!(
@@ -78,12 +84,14 @@ impl fmt::Display for ParseError {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for ParseError {
     fn from(e: std::io::Error) -> Self {
         Self::Io(e)
     }
 }
 
+#[cfg(feature = "json")]
 impl From<serde_json::Error> for ParseError {
     fn from(e: serde_json::Error) -> Self {
         Self::PayloadDeserialize(e)
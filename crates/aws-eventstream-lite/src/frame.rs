@@ -16,9 +16,11 @@
 //! - Payload: 载荷数据（通常是 JSON）
 //! - Message CRC: 整个消息（不含 Message CRC 自身）的 CRC32 校验
 
-use super::crc::crc32;
-use super::error::{ParseError, ParseResult};
-use super::header::{parse_headers, Headers};
+use crate::crc::crc32;
+use crate::error::{ParseError, ParseResult};
+use crate::header::{parse_headers, Headers};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 
 /// Prelude 固定大小 (12 字节)
 pub const PRELUDE_SIZE: usize = 12;
@@ -50,6 +52,7 @@ impl Frame {
     }
 
     /// 将 payload 解析为 JSON
+    #[cfg(feature = "json")]
     pub fn payload_as_json<T: serde::de::DeserializeOwned>(&self) -> ParseResult<T> {
         serde_json::from_slice(&self.payload).map_err(ParseError::PayloadDeserialize)
     }
@@ -166,7 +169,7 @@ mod tests {
     #[test]
     fn test_frame_message_too_small() {
         // 构造一个 total_length = 10 的 prelude (小于最小值)
-        let mut buffer = vec![0u8; 16];
+        let mut buffer = alloc::vec![0u8; 16];
         buffer[0..4].copy_from_slice(&10u32.to_be_bytes()); // total_length
         buffer[4..8].copy_from_slice(&0u32.to_be_bytes()); // header_length
         let prelude_crc = crc32(&buffer[0..8]);
@@ -2,8 +2,10 @@
 //!
 //! 实现 AWS Event Stream 协议的头部解析功能
 
-use super::error::{ParseError, ParseResult};
-use std::collections::HashMap;
+use crate::error::{ParseError, ParseResult};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
 
 /// 头部值类型标识
 ///
@@ -72,14 +74,14 @@ impl HeaderValue {
 /// 消息头部集合
 #[derive(Debug, Clone, Default)]
 pub struct Headers {
-    inner: HashMap<String, HeaderValue>,
+    inner: BTreeMap<String, HeaderValue>,
 }
 
 impl Headers {
     /// 创建空的头部集合
     pub fn new() -> Self {
         Self {
-            inner: HashMap::new(),
+            inner: BTreeMap::new(),
         }
     }
 
@@ -0,0 +1,35 @@
+//! AWS Event Stream 解析器
+//!
+//! 提供对 AWS Event Stream 协议的解析支持，不依赖 HTTP 客户端或服务端框架，
+//! 关闭默认特性后可在 `no_std` + `alloc` 环境下使用。
+//!
+//! # 特性
+//! - `std`（默认）：为 [`ParseError`] 实现 `std::error::Error`，支持 IO 错误转换
+//! - `json`（默认）：提供 [`Frame::payload_as_json`]
+//!
+//! # 使用示例
+//! ```rust
+//! use aws_eventstream_lite::EventStreamDecoder;
+//!
+//! let mut decoder = EventStreamDecoder::new();
+//! decoder.feed(&[]).unwrap();
+//! for frame in decoder.decode_iter() {
+//!     let frame = frame.unwrap();
+//!     println!("{:?}", frame.event_type());
+//! }
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod crc;
+pub mod decoder;
+pub mod error;
+pub mod frame;
+pub mod header;
+
+pub use decoder::{DecoderConfig, DecoderState, EventStreamDecoder, OverflowStrategy};
+pub use error::{ParseError, ParseResult};
+pub use frame::{parse_frame, Frame};
+pub use header::{HeaderValue, Headers};
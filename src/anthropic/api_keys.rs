@@ -0,0 +1,524 @@
+//! 托管 API Key
+//!
+//! 运营方通过管理 API 为用户签发、吊销 API Key，无需修改配置或重启服务。
+//! Key 只以 SHA-256 哈希保存在存储后端中，明文只在创建时返回一次。
+//! 每个 Key 可以限制每分钟请求数、可用模型和请求时长，并可在内存中保留最近的请求记录。
+//! 存储与其他实例共享时定期同步其他实例签发和吊销的 Key；保存前先合并存储中的记录，
+//! Key 只会被吊销而不会被删除，因此合并不会丢失其他实例的修改。
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::model::config::{ClientToolPolicy, Config, RequestTimeouts};
+use crate::storage::{LocalRateLimiter, RateLimiter, Storage};
+
+use super::recent::{RecentRequest, RecentRequests};
+
+/// 托管 Key 的保存文件名
+const API_KEYS_FILE: &str = "api_keys.json";
+
+/// 托管 Key 的前缀
+const KEY_PREFIX: &str = "sk-kiro-";
+
+/// 列表中展示的 Key 前缀长度（字符）
+const DISPLAY_PREFIX_CHARS: usize = 12;
+
+/// 保存的托管 Key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiKeyRecord {
+    #[serde(flatten)]
+    info: ApiKeyInfo,
+    /// Key 的 SHA-256 哈希（十六进制）
+    key_hash: String,
+}
+
+/// 托管 Key 的元数据（不含 Key 本身）
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyInfo {
+    pub id: String,
+    /// 使用者
+    pub owner: String,
+    /// Key 前缀，便于在列表中辨认
+    pub prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// 吊销时间，吊销后的 Key 无法认证
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// 每分钟请求数上限（None 表示不限制）
+    pub rate_limit_per_minute: Option<u32>,
+    /// 允许使用的模型（为空表示不限制，以 `*` 结尾表示前缀匹配）
+    pub allowed_models: Vec<String>,
+    /// 请求时长限制（覆盖全局配置）
+    #[serde(flatten)]
+    pub timeouts: RequestTimeouts,
+    /// 客户端工具策略（覆盖全局配置）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_tools: Option<ClientToolPolicy>,
+}
+
+impl ApiKeyInfo {
+    /// 是否允许使用指定模型
+    pub fn allows_model(&self, model: &str) -> bool {
+        self.allowed_models.is_empty()
+            || self
+                .allowed_models
+                .iter()
+                .any(|allowed| match allowed.strip_suffix('*') {
+                    Some(prefix) => model.starts_with(prefix),
+                    None => model == allowed,
+                })
+    }
+}
+
+/// 创建托管 Key 的参数
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewApiKey {
+    pub owner: String,
+    #[serde(default)]
+    pub rate_limit_per_minute: Option<u32>,
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    #[serde(flatten)]
+    pub timeouts: RequestTimeouts,
+    #[serde(default)]
+    pub client_tools: Option<ClientToolPolicy>,
+}
+
+/// 托管 Key 认证结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyCheck {
+    /// 不是托管 Key（或已吊销）
+    Unknown,
+    /// 超出每分钟请求数上限，附带距下一个窗口的秒数
+    RateLimited(u64),
+    Allowed(Box<ApiKeyInfo>),
+}
+
+/// 托管 Key 存储
+pub struct ApiKeyStore {
+    keys: RwLock<HashMap<String, ApiKeyRecord>>,
+    /// 每个 Key 每分钟的请求数计数（配置 Redis 时在实例间共享）
+    limiter: Arc<dyn RateLimiter>,
+    /// 最近使用时间有未保存的修改
+    dirty: AtomicBool,
+    /// 持久化存储（None 时只保存在内存中）
+    storage: Option<Arc<dyn Storage>>,
+    /// 每个 Key 的最近请求记录（未启用时为 None）
+    recent: Option<Arc<RecentRequests>>,
+}
+
+impl Default for ApiKeyStore {
+    fn default() -> Self {
+        Self {
+            keys: RwLock::default(),
+            limiter: Arc::new(LocalRateLimiter::default()),
+            dirty: AtomicBool::default(),
+            storage: None,
+            recent: None,
+        }
+    }
+}
+
+impl ApiKeyStore {
+    /// 加载存储中保存的托管 Key
+    pub async fn load(storage: Arc<dyn Storage>) -> Self {
+        let mut keys = HashMap::new();
+        match storage.get_json::<Vec<ApiKeyRecord>>(API_KEYS_FILE).await {
+            Ok(Some(saved)) => {
+                tracing::info!("从存储加载了 {} 个托管 API Key", saved.len());
+                keys.extend(saved.into_iter().map(|r| (r.info.id.clone(), r)));
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("读取托管 API Key 失败: {}", e),
+        }
+        Self {
+            keys: RwLock::new(keys),
+            storage: Some(storage),
+            ..Default::default()
+        }
+    }
+
+    /// 存储后端与其他实例共享时，定期同步其他实例签发和吊销的 Key
+    pub fn spawn_sync(self: &Arc<Self>, config: &Config) {
+        let shared = self.storage.as_ref().is_some_and(|s| s.shared());
+        let Some(redis) = config.redis.as_ref().filter(|_| shared) else {
+            return;
+        };
+        let period = std::time::Duration::from_secs(redis.sync_secs.max(1));
+        let store = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(store) = store.upgrade() else {
+                    return;
+                };
+                if let Err(e) = store.sync().await {
+                    tracing::warn!("同步托管 API Key 失败: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 从存储读取其他实例签发或吊销的 Key 并合并到本实例
+    pub async fn sync(&self) -> anyhow::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        if let Some(saved) = storage.get_json::<Vec<ApiKeyRecord>>(API_KEYS_FILE).await? {
+            merge(&mut self.keys.write().unwrap(), saved);
+        }
+        Ok(())
+    }
+
+    /// 设置限流计数后端
+    pub fn with_rate_limiter(mut self, limiter: Arc<dyn RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// 为每个 Key 保留最近 `capacity` 次请求（0 表示不记录）
+    pub fn with_recent_requests(mut self, capacity: usize) -> Self {
+        self.recent = (capacity > 0).then(|| Arc::new(RecentRequests::new(capacity)));
+        self
+    }
+
+    /// 最近请求记录（未启用时为 None）
+    pub fn recent(&self) -> Option<Arc<RecentRequests>> {
+        self.recent.clone()
+    }
+
+    /// Key 的最近请求，最新的在前；未启用记录或 Key 不存在时返回 None
+    pub fn recent_requests(&self, id: &str) -> Option<Vec<RecentRequest>> {
+        let recent = self.recent.as_ref()?;
+        self.keys
+            .read()
+            .unwrap()
+            .contains_key(id)
+            .then(|| recent.get(id))
+    }
+
+    /// 按创建时间排序的全部托管 Key（包括已吊销的）
+    pub fn list(&self) -> Vec<ApiKeyInfo> {
+        let mut keys: Vec<ApiKeyInfo> = self
+            .keys
+            .read()
+            .unwrap()
+            .values()
+            .map(|r| r.info.clone())
+            .collect();
+        keys.sort_by_key(|k| k.created_at);
+        keys
+    }
+
+    /// 签发新 Key，返回元数据和明文 Key（明文不会保存）
+    pub async fn create(&self, new: NewApiKey) -> anyhow::Result<(ApiKeyInfo, String)> {
+        let key = format!(
+            "{}{}{}",
+            KEY_PREFIX,
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let info = ApiKeyInfo {
+            id: uuid::Uuid::new_v4().to_string(),
+            owner: new.owner,
+            prefix: key.chars().take(DISPLAY_PREFIX_CHARS).collect(),
+            created_at: Utc::now(),
+            last_used_at: None,
+            revoked_at: None,
+            rate_limit_per_minute: new.rate_limit_per_minute,
+            allowed_models: new.allowed_models,
+            timeouts: new.timeouts,
+            client_tools: new.client_tools,
+        };
+        let record = ApiKeyRecord {
+            info: info.clone(),
+            key_hash: hash_key(&key),
+        };
+        self.keys.write().unwrap().insert(info.id.clone(), record);
+        self.save().await?;
+        tracing::info!("签发托管 API Key: {} ({})", info.prefix, info.owner);
+        Ok((info, key))
+    }
+
+    /// 吊销 Key，不存在或已吊销时返回 false
+    pub async fn revoke(&self, id: &str) -> anyhow::Result<bool> {
+        // 先同步，以便吊销其他实例刚签发的 Key
+        self.sync().await?;
+        {
+            let mut keys = self.keys.write().unwrap();
+            match keys.get_mut(id) {
+                Some(record) if record.info.revoked_at.is_none() => {
+                    record.info.revoked_at = Some(Utc::now());
+                    tracing::info!("吊销托管 API Key: {}", record.info.prefix);
+                }
+                _ => return Ok(false),
+            }
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    /// 认证托管 Key，通过时计入当前分钟的请求数并更新最近使用时间
+    pub async fn authenticate(&self, key: &str, now: DateTime<Utc>) -> KeyCheck {
+        if !key.starts_with(KEY_PREFIX) {
+            return KeyCheck::Unknown;
+        }
+        let hash = hash_key(key);
+        let Some(info) = self
+            .keys
+            .read()
+            .unwrap()
+            .values()
+            .find(|r| r.key_hash == hash && r.info.revoked_at.is_none())
+            .map(|r| r.info.clone())
+        else {
+            return KeyCheck::Unknown;
+        };
+
+        if let Some(limit) = info.rate_limit_per_minute {
+            let minute = now.timestamp().div_euclid(60);
+            if self.limiter.hit(&info.id, minute).await > u64::from(limit) {
+                return KeyCheck::RateLimited((60 - now.timestamp().rem_euclid(60)) as u64);
+            }
+        }
+
+        let mut keys = self.keys.write().unwrap();
+        let Some(record) = keys.get_mut(&info.id) else {
+            return KeyCheck::Unknown;
+        };
+        record.info.last_used_at = Some(now);
+        self.dirty.store(true, Ordering::Relaxed);
+        KeyCheck::Allowed(Box::new(record.info.clone()))
+    }
+
+    /// 保存最近使用时间（没有未保存的修改时跳过）
+    pub async fn persist(&self) {
+        if !self.dirty.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = self.save().await {
+            tracing::warn!("保存托管 API Key 失败: {}", e);
+        }
+    }
+
+    async fn save(&self) -> anyhow::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        // 合并其他实例保存的记录，避免覆盖它们签发或吊销的 Key
+        self.sync().await?;
+        // 先清除标记再取快照，写入期间的新修改会重新标记；写入失败时恢复标记，下次继续保存
+        self.dirty.store(false, Ordering::Relaxed);
+        let content = {
+            let keys = self.keys.read().unwrap();
+            let mut records: Vec<&ApiKeyRecord> = keys.values().collect();
+            records.sort_by_key(|r| r.info.created_at);
+            serde_json::to_vec_pretty(&records)?
+        };
+        if let Err(e) = storage.put(API_KEYS_FILE, content).await {
+            self.dirty.store(true, Ordering::Relaxed);
+            return Err(e);
+        }
+        Ok(())
+    }
+}
+
+/// 合并存储中的记录：补充本地缺少的 Key，吊销时间取较早的，最近使用时间取较晚的
+fn merge(keys: &mut HashMap<String, ApiKeyRecord>, saved: Vec<ApiKeyRecord>) {
+    for record in saved {
+        match keys.entry(record.info.id.clone()) {
+            Entry::Vacant(entry) => {
+                entry.insert(record);
+            }
+            Entry::Occupied(mut entry) => {
+                let local = &mut entry.get_mut().info;
+                local.revoked_at = match (local.revoked_at, record.info.revoked_at) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                local.last_used_at = local.last_used_at.max(record.info.last_used_at);
+            }
+        }
+    }
+}
+
+fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStorage;
+
+    #[tokio::test]
+    async fn test_create_authenticate_revoke() {
+        let dir = std::env::temp_dir().join(format!("kiro-api-keys-{}", uuid::Uuid::new_v4()));
+        let storage: Arc<dyn Storage> = Arc::new(FileStorage::new(dir.clone()));
+        let store = ApiKeyStore::load(storage.clone()).await;
+        let (info, key) = store
+            .create(NewApiKey {
+                owner: "alice".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(key.starts_with(&info.prefix));
+
+        // 只保存哈希
+        let saved = std::fs::read_to_string(dir.join(API_KEYS_FILE)).unwrap();
+        assert!(!saved.contains(&key));
+        assert!(saved.contains(&hash_key(&key)));
+
+        let reloaded = ApiKeyStore::load(storage).await;
+        let KeyCheck::Allowed(used) = reloaded.authenticate(&key, Utc::now()).await else {
+            panic!("托管 Key 认证失败");
+        };
+        assert_eq!(used.owner, "alice");
+        assert_eq!(
+            reloaded.authenticate("sk-kiro-wrong", Utc::now()).await,
+            KeyCheck::Unknown
+        );
+
+        assert!(reloaded.revoke(&info.id).await.unwrap());
+        assert!(!reloaded.revoke(&info.id).await.unwrap());
+        assert_eq!(
+            reloaded.authenticate(&key, Utc::now()).await,
+            KeyCheck::Unknown
+        );
+        assert!(reloaded.list()[0].revoked_at.is_some());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_shared_keys_sync_between_instances() {
+        let storage: Arc<dyn Storage> = Arc::new(crate::storage::MemoryStorage::default());
+        let first = ApiKeyStore::load(storage.clone()).await;
+        let second = ApiKeyStore::load(storage.clone()).await;
+        let new_key = |owner: &str| NewApiKey {
+            owner: owner.to_string(),
+            ..Default::default()
+        };
+
+        // 其他实例签发的 Key 同步后可用
+        let (alice, alice_key) = first.create(new_key("alice")).await.unwrap();
+        assert_eq!(
+            second.authenticate(&alice_key, Utc::now()).await,
+            KeyCheck::Unknown
+        );
+        second.sync().await.unwrap();
+        assert!(matches!(
+            second.authenticate(&alice_key, Utc::now()).await,
+            KeyCheck::Allowed(_)
+        ));
+
+        // 另一个实例保存最近使用时间时保留其间签发和吊销的 Key
+        let (_, bob_key) = first.create(new_key("bob")).await.unwrap();
+        assert!(first.revoke(&alice.id).await.unwrap());
+        second.persist().await;
+        let restarted = ApiKeyStore::load(storage).await;
+        assert!(matches!(
+            restarted.authenticate(&bob_key, Utc::now()).await,
+            KeyCheck::Allowed(_)
+        ));
+        assert_eq!(
+            restarted.authenticate(&alice_key, Utc::now()).await,
+            KeyCheck::Unknown
+        );
+
+        // 吊销同步到另一个实例
+        assert_eq!(
+            second.authenticate(&alice_key, Utc::now()).await,
+            KeyCheck::Unknown
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_and_allowed_models() {
+        let store = ApiKeyStore::default();
+        let (info, key) = store
+            .create(NewApiKey {
+                owner: "bob".to_string(),
+                rate_limit_per_minute: Some(2),
+                allowed_models: vec![
+                    "claude-sonnet-*".to_string(),
+                    "claude-haiku-4-5".to_string(),
+                ],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let now = DateTime::parse_from_rfc3339("2026-01-01T00:00:50Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert!(matches!(
+            store.authenticate(&key, now).await,
+            KeyCheck::Allowed(_)
+        ));
+        assert!(matches!(
+            store.authenticate(&key, now).await,
+            KeyCheck::Allowed(_)
+        ));
+        assert_eq!(
+            store.authenticate(&key, now).await,
+            KeyCheck::RateLimited(10)
+        );
+        // 下一分钟重新计数
+        let next = now + chrono::Duration::seconds(10);
+        assert!(matches!(
+            store.authenticate(&key, next).await,
+            KeyCheck::Allowed(_)
+        ));
+
+        assert!(info.allows_model("claude-sonnet-4-5-20250929"));
+        assert!(info.allows_model("claude-haiku-4-5"));
+        assert!(!info.allows_model("claude-opus-4-5"));
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_override_defaults() {
+        let new: NewApiKey = serde_json::from_value(serde_json::json!({
+            "owner": "batch",
+            "maxRequestSecs": 600
+        }))
+        .unwrap();
+        let store = ApiKeyStore::default();
+        let (info, _) = store.create(new).await.unwrap();
+
+        let value = serde_json::to_value(&info).unwrap();
+        assert_eq!(value["maxRequestSecs"], 600);
+        assert!(value.get("streamIdleTimeoutSecs").is_none());
+
+        let defaults = RequestTimeouts {
+            max_request_secs: Some(60),
+            stream_idle_timeout_secs: Some(30),
+        };
+        let timeouts = info.timeouts.or(defaults);
+        assert_eq!(
+            timeouts.max_request(),
+            Some(std::time::Duration::from_secs(600))
+        );
+        assert_eq!(
+            timeouts.stream_idle(),
+            Some(std::time::Duration::from_secs(30))
+        );
+
+        let unlimited = RequestTimeouts {
+            max_request_secs: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(unlimited.or(defaults).max_request(), None);
+    }
+}
@@ -0,0 +1,166 @@
+//! `anthropic-beta` 请求头
+//!
+//! SDK 通过该请求头（逗号分隔，可出现多次）开启 beta 功能。已识别的 beta 分为两类：
+//! - 兼容：代理的行为与 Anthropic 一致或该功能不影响结果（如 token-efficient tools），
+//!   其中 `output-128k-2025-02-19` 提高 `max_tokens` 上限
+//! - 不支持：上游无法提供（如 1M 上下文），直接返回 `invalid_request_error`，
+//!   避免客户端以为功能已生效
+//!
+//! 未识别的 beta 按配置项 `unknownBetaPolicy` 忽略（记录警告）或拒绝。
+
+use crate::model::config::UnknownBetaPolicy;
+
+/// 请求头名称
+pub const HEADER: &str = "anthropic-beta";
+
+/// 未开启扩展输出时 `max_tokens` 的上限
+const STANDARD_MAX_OUTPUT_TOKENS: i32 = 64_000;
+
+/// 开启 `output-128k-2025-02-19` 后 `max_tokens` 的上限
+const EXTENDED_MAX_OUTPUT_TOKENS: i32 = 128_000;
+
+/// 已识别的 beta 功能
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BetaFeature {
+    ClaudeCode,
+    InterleavedThinking,
+    FineGrainedToolStreaming,
+    TokenEfficientTools,
+    PromptCaching,
+    FilesApi,
+    /// 扩展输出（最多 128k tokens）
+    ExtendedOutput,
+    /// 1M 上下文窗口
+    Context1m,
+    ComputerUse,
+}
+
+impl BetaFeature {
+    const ALL: [Self; 9] = [
+        Self::ClaudeCode,
+        Self::InterleavedThinking,
+        Self::FineGrainedToolStreaming,
+        Self::TokenEfficientTools,
+        Self::PromptCaching,
+        Self::FilesApi,
+        Self::ExtendedOutput,
+        Self::Context1m,
+        Self::ComputerUse,
+    ];
+
+    /// 请求头中的名称
+    pub fn flag(&self) -> &'static str {
+        match self {
+            Self::ClaudeCode => "claude-code-20250219",
+            Self::InterleavedThinking => "interleaved-thinking-2025-05-14",
+            Self::FineGrainedToolStreaming => "fine-grained-tool-streaming-2025-05-14",
+            Self::TokenEfficientTools => "token-efficient-tools-2025-02-19",
+            Self::PromptCaching => "prompt-caching-2024-07-31",
+            Self::FilesApi => "files-api-2025-04-14",
+            Self::ExtendedOutput => "output-128k-2025-02-19",
+            Self::Context1m => "context-1m-2025-08-07",
+            Self::ComputerUse => "computer-use-2025-01-24",
+        }
+    }
+
+    pub fn parse(flag: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.flag() == flag)
+    }
+
+    /// 代理能否提供与 Anthropic 兼容的行为
+    pub fn supported(&self) -> bool {
+        !matches!(self, Self::Context1m | Self::ComputerUse)
+    }
+}
+
+/// 请求开启的 beta 功能
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnthropicBetas {
+    features: Vec<BetaFeature>,
+    unknown: Vec<String>,
+}
+
+impl AnthropicBetas {
+    /// 解析全部 `anthropic-beta` 请求头的值
+    pub fn parse<'a>(values: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut betas = Self::default();
+        for flag in values.into_iter().flat_map(|v| v.split(',')) {
+            let flag = flag.trim();
+            if flag.is_empty() {
+                continue;
+            }
+            match BetaFeature::parse(flag) {
+                Some(feature) if !betas.features.contains(&feature) => betas.features.push(feature),
+                Some(_) => {}
+                None => betas.unknown.push(flag.to_string()),
+            }
+        }
+        betas
+    }
+
+    pub fn has(&self, feature: BetaFeature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// 检查是否可以处理，返回错误信息时拒绝请求
+    pub fn check(&self, policy: UnknownBetaPolicy) -> Result<(), String> {
+        let unsupported: Vec<&str> = self
+            .features
+            .iter()
+            .filter(|f| !f.supported())
+            .map(|f| f.flag())
+            .collect();
+        if !unsupported.is_empty() {
+            return Err(format!("不支持的 {}: {}", HEADER, unsupported.join(", ")));
+        }
+        if self.unknown.is_empty() {
+            return Ok(());
+        }
+        match policy {
+            UnknownBetaPolicy::Ignore => {
+                tracing::warn!("忽略未识别的 {}: {}", HEADER, self.unknown.join(", "));
+                Ok(())
+            }
+            UnknownBetaPolicy::Reject => {
+                Err(format!("未识别的 {}: {}", HEADER, self.unknown.join(", ")))
+            }
+        }
+    }
+
+    /// `max_tokens` 上限
+    pub fn max_output_tokens(&self) -> i32 {
+        if self.has(BetaFeature::ExtendedOutput) {
+            EXTENDED_MAX_OUTPUT_TOKENS
+        } else {
+            STANDARD_MAX_OUTPUT_TOKENS
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_check() {
+        let betas = AnthropicBetas::parse([
+            "claude-code-20250219, interleaved-thinking-2025-05-14",
+            "token-efficient-tools-2025-02-19,,claude-code-20250219",
+        ]);
+        assert!(betas.has(BetaFeature::TokenEfficientTools));
+        assert_eq!(betas.check(UnknownBetaPolicy::Reject), Ok(()));
+        assert_eq!(betas.max_output_tokens(), STANDARD_MAX_OUTPUT_TOKENS);
+
+        let extended = AnthropicBetas::parse(["output-128k-2025-02-19"]);
+        assert_eq!(extended.max_output_tokens(), EXTENDED_MAX_OUTPUT_TOKENS);
+
+        let unknown = AnthropicBetas::parse(["some-future-beta-2030-01-01"]);
+        assert_eq!(unknown.check(UnknownBetaPolicy::Ignore), Ok(()));
+        let err = unknown.check(UnknownBetaPolicy::Reject).unwrap_err();
+        assert!(err.contains("some-future-beta-2030-01-01"));
+
+        // 已识别但不支持的 beta 总是拒绝
+        let unsupported = AnthropicBetas::parse(["context-1m-2025-08-07"]);
+        assert!(unsupported.check(UnknownBetaPolicy::Ignore).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! 请求内容块类型注册表
+//!
+//! 转换器只理解注册表中的内容块类型。Anthropic 新增的类型在转换前按配置处理
+//! （丢弃、作为文本传给模型或拒绝请求），不会导致反序列化失败或被静默丢弃。
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use crate::model::config::{Config, UnknownBlockPolicy};
+
+use super::converter::ConversionError;
+use super::types::{ContentBlock, MessagesRequest};
+
+/// 转换器支持的内容块类型
+pub const KNOWN_BLOCK_TYPES: &[&str] = &[
+    "text",
+    "image",
+    "document",
+    "thinking",
+    "redacted_thinking",
+    "tool_use",
+    "tool_result",
+];
+
+/// 请求中的内容块
+#[derive(Debug)]
+pub enum InputBlock {
+    /// 已注册的类型
+    Known(Box<ContentBlock>),
+    /// 未注册的类型，保留原始 JSON
+    Unknown { block_type: String, raw: Value },
+}
+
+impl InputBlock {
+    /// 解析内容块，缺少 `type` 或已注册类型的字段无效时返回 None
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let block_type = value.get("type").and_then(|v| v.as_str())?;
+        if KNOWN_BLOCK_TYPES.contains(&block_type) {
+            return serde_json::from_value(value.clone())
+                .ok()
+                .map(|block| Self::Known(Box::new(block)));
+        }
+        Some(Self::Unknown {
+            block_type: block_type.to_string(),
+            raw: value.clone(),
+        })
+    }
+}
+
+/// 未知内容块的处理策略
+#[derive(Debug, Clone, Default)]
+pub struct BlockPolicy {
+    /// 默认处理方式
+    pub default: UnknownBlockPolicy,
+    /// 按类型覆盖的处理方式
+    pub overrides: HashMap<String, UnknownBlockPolicy>,
+}
+
+impl From<&Config> for BlockPolicy {
+    fn from(config: &Config) -> Self {
+        Self {
+            default: config.unknown_block_policy,
+            overrides: config.unknown_block_policies.clone(),
+        }
+    }
+}
+
+impl BlockPolicy {
+    /// 指定类型的处理方式
+    pub fn for_type(&self, block_type: &str) -> UnknownBlockPolicy {
+        self.overrides
+            .get(block_type)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// 按策略处理消息中的未知内容块
+pub fn apply_block_policy(
+    req: &mut MessagesRequest,
+    policy: &BlockPolicy,
+) -> Result<(), ConversionError> {
+    for msg in &mut req.messages {
+        let Value::Array(blocks) = &mut msg.content else {
+            continue;
+        };
+        let mut kept = Vec::with_capacity(blocks.len());
+        for block in blocks.drain(..) {
+            let Some(InputBlock::Unknown { block_type, raw }) = InputBlock::from_value(&block)
+            else {
+                kept.push(block);
+                continue;
+            };
+            match policy.for_type(&block_type) {
+                UnknownBlockPolicy::Drop => {
+                    tracing::warn!("丢弃未知类型的内容块: {}", block_type);
+                }
+                UnknownBlockPolicy::Text => {
+                    let text = format!("[{}]\n{}", block_type, raw);
+                    kept.push(json!({"type": "text", "text": text}));
+                }
+                UnknownBlockPolicy::Reject => {
+                    return Err(ConversionError::UnsupportedContentBlock(block_type));
+                }
+            }
+        }
+        *blocks = kept;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": [{
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "look"},
+                    {"type": "search_result", "title": "doc"},
+                    {"type": "container_upload", "file_id": "f1"}
+                ]
+            }]
+        }))
+        .unwrap()
+    }
+
+    fn block_types(req: &MessagesRequest) -> Vec<&str> {
+        req.messages[0]
+            .content
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|b| b["type"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_from_value() {
+        let known = InputBlock::from_value(&json!({"type": "text", "text": "hi"}));
+        assert!(matches!(known, Some(InputBlock::Known(b)) if b.text.as_deref() == Some("hi")));
+        let unknown = InputBlock::from_value(&json!({"type": "search_result", "title": "t"}));
+        assert!(matches!(unknown, Some(InputBlock::Unknown { raw, .. }) if raw["title"] == "t"));
+        assert!(InputBlock::from_value(&json!({"text": "no type"})).is_none());
+    }
+
+    #[test]
+    fn test_apply_block_policy() {
+        let mut req = request();
+        apply_block_policy(&mut req, &BlockPolicy::default()).unwrap();
+        assert_eq!(block_types(&req), vec!["text"]);
+
+        let policy = BlockPolicy {
+            default: UnknownBlockPolicy::Drop,
+            overrides: HashMap::from([("search_result".to_string(), UnknownBlockPolicy::Text)]),
+        };
+        let mut req = request();
+        apply_block_policy(&mut req, &policy).unwrap();
+        assert_eq!(block_types(&req), vec!["text", "text"]);
+        let text = req.messages[0].content[1]["text"].as_str().unwrap();
+        assert!(text.starts_with("[search_result]\n") && text.contains("\"title\":\"doc\""));
+
+        let policy = BlockPolicy {
+            default: UnknownBlockPolicy::Reject,
+            ..policy
+        };
+        let err = apply_block_policy(&mut request(), &policy).unwrap_err();
+        assert!(
+            matches!(err, ConversionError::UnsupportedContentBlock(t) if t == "container_upload")
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! `/v1/messages` 请求体读取
+//!
+//! 携带多张 base64 图片的请求体可达数十 MB。读取时逐块累计大小，Content-Length 或累计大小
+//! 超出上限后立即拒绝，不再接收剩余数据；上一块处理完之前不会读取下一块，客户端上传受背压控制。
+//! 较大的请求体在阻塞线程池中反序列化，避免解析数 MB 的 base64 字符串时占住异步运行时。
+
+use axum::{
+    body::Body,
+    extract::{FromRequest, Request},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+
+use super::middleware::AppState;
+use super::types::ErrorResponse;
+
+/// 默认请求体上限（与 Anthropic API 的 32 MB 上限一致）
+pub const DEFAULT_MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+/// 超过该大小的请求体在阻塞线程池中反序列化
+const BLOCKING_PARSE_THRESHOLD: usize = 512 * 1024;
+
+/// 读取请求体失败
+#[derive(Debug)]
+pub enum BodyError {
+    /// 超出大小上限
+    TooLarge { limit: usize },
+    /// 客户端连接中断等读取错误
+    Read(String),
+}
+
+impl IntoResponse for BodyError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::TooLarge { limit } => {
+                let error = ErrorResponse::new(
+                    "request_too_large",
+                    format!("请求体超过上限 {} MB", limit / (1024 * 1024)),
+                );
+                (StatusCode::PAYLOAD_TOO_LARGE, Json(error)).into_response()
+            }
+            Self::Read(message) => {
+                let error = ErrorResponse::new(
+                    "invalid_request_error",
+                    format!("读取请求体失败: {}", message),
+                );
+                (StatusCode::BAD_REQUEST, Json(error)).into_response()
+            }
+        }
+    }
+}
+
+/// 逐块读取请求体，超出 `limit` 字节时立即返回 [`BodyError::TooLarge`]
+pub async fn read_body(headers: &HeaderMap, body: Body, limit: usize) -> Result<Bytes, BodyError> {
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if declared.is_some_and(|len| len > limit as u64) {
+        return Err(BodyError::TooLarge { limit });
+    }
+
+    let mut chunks: Vec<Bytes> = Vec::new();
+    let mut received = 0usize;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| BodyError::Read(e.to_string()))?;
+        received += chunk.len();
+        if received > limit {
+            return Err(BodyError::TooLarge { limit });
+        }
+        chunks.push(chunk);
+    }
+
+    // 中间件转发的请求体只有一块，直接复用避免复制
+    if chunks.len() == 1 {
+        return Ok(chunks.pop().unwrap_or_default());
+    }
+    let mut buffer = BytesMut::with_capacity(received);
+    for chunk in chunks {
+        buffer.extend_from_slice(&chunk);
+    }
+    Ok(buffer.freeze())
+}
+
+/// 反序列化 JSON，较大的请求体在阻塞线程池中解析
+pub async fn parse_json<T>(bytes: Bytes) -> Result<T, serde_json::Error>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    if bytes.len() < BLOCKING_PARSE_THRESHOLD {
+        return serde_json::from_slice(&bytes);
+    }
+    tokio::task::spawn_blocking(move || serde_json::from_slice(&bytes))
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// 按 [`AppState::max_body_bytes`] 限制大小的 JSON 提取器
+///
+/// 与 `axum::Json` 相同，要求 `Content-Type: application/json`；数据类型不匹配返回 422，
+/// 语法错误返回 400
+pub struct LimitedJson<T>(pub T);
+
+impl<T> FromRequest<AppState> for LimitedJson<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        let (parts, body) = req.into_parts();
+        if !is_json(&parts.headers) {
+            let error = ErrorResponse::new(
+                "invalid_request_error",
+                "请求头 Content-Type 必须为 application/json",
+            );
+            return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(error)).into_response());
+        }
+
+        let bytes = read_body(&parts.headers, body, state.max_body_bytes)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        match parse_json(bytes).await {
+            Ok(value) => Ok(Self(value)),
+            Err(e) => {
+                let status = match e.classify() {
+                    serde_json::error::Category::Data => StatusCode::UNPROCESSABLE_ENTITY,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                let error =
+                    ErrorResponse::new("invalid_request_error", format!("请求体解析失败: {}", e));
+                Err((status, Json(error)).into_response())
+            }
+        }
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|mime| {
+            let mime = mime.trim().to_ascii_lowercase();
+            mime == "application/json"
+                || (mime.starts_with("application/") && mime.ends_with("+json"))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    fn chunked(chunks: Vec<&'static [u8]>) -> Body {
+        Body::from_stream(stream::iter(
+            chunks
+                .into_iter()
+                .map(|c| Ok::<_, std::io::Error>(Bytes::from_static(c))),
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_read_body_enforces_limit_while_streaming() {
+        let headers = HeaderMap::new();
+        let bytes = read_body(&headers, chunked(vec![b"ab", b"cd"]), 4)
+            .await
+            .unwrap();
+        assert_eq!(&bytes[..], b"abcd");
+
+        let result = read_body(&headers, chunked(vec![b"ab", b"cd", b"e"]), 4).await;
+        assert!(matches!(result, Err(BodyError::TooLarge { limit: 4 })));
+    }
+
+    #[tokio::test]
+    async fn test_read_body_rejects_declared_length_before_reading() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_LENGTH, "100".parse().unwrap());
+        // 请求体本身为空：超限由 Content-Length 判断，不读取数据
+        let result = read_body(&headers, Body::empty(), 10).await;
+        assert!(matches!(result, Err(BodyError::TooLarge { limit: 10 })));
+    }
+
+    #[tokio::test]
+    async fn test_limited_json_rejections() {
+        let state = AppState::new("key").with_max_body_bytes(64);
+        let request = |content_type: &str, body: &'static str| {
+            Request::builder()
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let LimitedJson(value) = LimitedJson::<serde_json::Value>::from_request(
+            request("application/json", "{\"a\":1}"),
+            &state,
+        )
+        .await
+        .unwrap();
+        assert_eq!(value["a"], 1);
+
+        let status = |result: Result<LimitedJson<Vec<u8>>, Response>| match result {
+            Ok(_) => StatusCode::OK,
+            Err(response) => response.status(),
+        };
+        let long = "[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]";
+        assert_eq!(
+            status(LimitedJson::from_request(request("application/json", long), &state).await),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            status(LimitedJson::from_request(request("text/plain", "[1]"), &state).await),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+        assert_eq!(
+            status(LimitedJson::from_request(request("application/json", "[1,"), &state).await),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(LimitedJson::from_request(request("application/json", "{}"), &state).await),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+}
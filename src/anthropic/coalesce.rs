@@ -0,0 +1,332 @@
+//! 流式请求合并
+//!
+//! 多个客户端同时提交完全相同的流式请求时（常见于重试风暴），
+//! 只调用一次上游 API，并通过 broadcast channel 将 SSE 事件分发给所有等待的客户端。
+//!
+//! 首个请求（Leader）直接读取上游流，边输出边广播给后加入的请求（Follower），上游流的读取速度
+//! 由 Leader 的客户端决定；Leader 断开时上游请求随之取消。Follower 落后过多或 Leader 中途断开时
+//! 收到 `error` 事件而不是被静默截断。已广播内容超过 [`MAX_HISTORY_BYTES`] 后不再接受新的 Follower。
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+
+use super::stream::SseEvent;
+
+/// broadcast channel 容量
+const BROADCAST_CAPACITY: usize = 1024;
+
+/// 保留的已发送事件总字节数上限，超过后新的相同请求不再合并
+const MAX_HISTORY_BYTES: usize = 1024 * 1024;
+
+/// 进行中的流
+struct InflightStream {
+    /// 已发送的事件（供后加入的客户端补发）
+    history: Vec<Bytes>,
+    /// 已发送事件的总字节数
+    history_bytes: usize,
+    /// 事件广播
+    tx: broadcast::Sender<Bytes>,
+}
+
+type InflightMap = Arc<Mutex<HashMap<String, Arc<Mutex<InflightStream>>>>>;
+
+/// 流式请求合并器
+#[derive(Default)]
+pub struct StreamCoalescer {
+    inflight: InflightMap,
+}
+
+/// 加入合并器的结果
+pub enum Coalesced {
+    /// 首个请求，负责调用上游并发布事件
+    Leader(Publisher),
+    /// 相同请求正在进行中，订阅其事件
+    Follower(Subscription),
+}
+
+impl StreamCoalescer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 计算请求的合并键
+    pub fn key_for(body: &[u8]) -> String {
+        hex::encode(Sha256::digest(body))
+    }
+
+    /// 按请求键加入合并器
+    pub fn join(&self, key: &str) -> Coalesced {
+        let mut inflight = self.inflight.lock().unwrap();
+
+        if let Some(entry) = inflight.get(key) {
+            let entry = entry.lock().unwrap();
+            return Coalesced::Follower(Subscription {
+                history: entry.history.clone().into(),
+                rx: entry.tx.subscribe(),
+                done: false,
+            });
+        }
+
+        let (tx, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let entry = Arc::new(Mutex::new(InflightStream {
+            history: Vec::new(),
+            history_bytes: 0,
+            tx,
+        }));
+        inflight.insert(key.to_string(), entry.clone());
+
+        Coalesced::Leader(Publisher {
+            key: key.to_string(),
+            entry,
+            inflight: self.inflight.clone(),
+            published: false,
+            finished: false,
+            detached: false,
+        })
+    }
+
+    /// 当前进行中的合并流数量
+    #[allow(dead_code)]
+    pub fn inflight_count(&self) -> usize {
+        self.inflight.lock().unwrap().len()
+    }
+}
+
+/// 事件发布者
+///
+/// Drop 时从合并器中移除，订阅者随后收到流结束；已发布过事件但流未正常结束时，
+/// 订阅者先收到 `error` 事件
+pub struct Publisher {
+    key: String,
+    entry: Arc<Mutex<InflightStream>>,
+    inflight: InflightMap,
+    /// 是否已发布过事件（未发布就结束时订阅者改为独立处理）
+    published: bool,
+    /// 上游流是否已正常结束
+    finished: bool,
+    /// 已因历史过大从合并器中移除
+    detached: bool,
+}
+
+impl Publisher {
+    /// 发布一个 SSE 事件
+    pub fn publish(&mut self, bytes: Bytes) {
+        self.published = true;
+        if !self.detached
+            && self.entry.lock().unwrap().history_bytes + bytes.len() > MAX_HISTORY_BYTES
+        {
+            // 不再保留历史，新的相同请求独立处理（先移除再清空，避免新请求拿到不完整的历史）
+            self.detach();
+            self.detached = true;
+            self.entry.lock().unwrap().history = Vec::new();
+        }
+        let mut entry = self.entry.lock().unwrap();
+        if !self.detached {
+            entry.history_bytes += bytes.len();
+            entry.history.push(bytes.clone());
+        }
+        // 没有订阅者时发送失败，忽略即可
+        let _ = entry.tx.send(bytes);
+    }
+
+    /// Leader 直接输出的流：透传每个事件并广播给订阅者，流结束时标记为正常结束
+    pub fn tee<S, E>(self, stream: S) -> impl Stream<Item = Result<Bytes, E>>
+    where
+        S: Stream<Item = Result<Bytes, E>>,
+    {
+        stream::unfold(
+            (Box::pin(stream), self),
+            |(mut stream, mut publisher)| async move {
+                match stream.next().await {
+                    Some(item) => {
+                        if let Ok(bytes) = &item {
+                            publisher.publish(bytes.clone());
+                        }
+                        Some((item, (stream, publisher)))
+                    }
+                    None => {
+                        publisher.finish();
+                        None
+                    }
+                }
+            },
+        )
+    }
+
+    /// 标记上游流已正常结束
+    fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// 从合并器中移除，之后的相同请求不再加入
+    fn detach(&self) {
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight
+            .get(&self.key)
+            .is_some_and(|entry| Arc::ptr_eq(entry, &self.entry))
+        {
+            inflight.remove(&self.key);
+        }
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        self.detach();
+        if self.published && !self.finished {
+            let entry = self.entry.lock().unwrap();
+            let _ = entry.tx.send(interrupted_event("合并的上游流式请求已中断"));
+        }
+    }
+}
+
+/// 合并流中断时发给订阅者的终止事件
+fn interrupted_event(message: &str) -> Bytes {
+    let event = SseEvent::new(
+        "error",
+        json!({
+            "type": "error",
+            "error": {"type": "api_error", "message": message}
+        }),
+    );
+    Bytes::from(event.to_sse_string())
+}
+
+/// 事件订阅
+pub struct Subscription {
+    history: std::collections::VecDeque<Bytes>,
+    rx: broadcast::Receiver<Bytes>,
+    /// 已发送终止事件
+    done: bool,
+}
+
+impl Subscription {
+    /// 获取下一个事件，流结束时返回 None；落后过多时返回 `error` 事件后结束
+    pub async fn next(&mut self) -> Option<Bytes> {
+        if let Some(bytes) = self.history.pop_front() {
+            return Some(bytes);
+        }
+        if self.done {
+            return None;
+        }
+        match self.rx.recv().await {
+            Ok(bytes) => Some(bytes),
+            Err(broadcast::error::RecvError::Closed) => None,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                tracing::warn!("合并流订阅者落后 {} 个事件，提前结束", n);
+                self.done = true;
+                Some(interrupted_event("客户端读取过慢，合并的流式响应已中断"))
+            }
+        }
+    }
+
+    /// 转换为 SSE 字节流
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        stream::unfold(self, |mut sub| async move {
+            sub.next().await.map(|bytes| (Ok(bytes), sub))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_follower_receives_history_and_live_events() {
+        let coalescer = StreamCoalescer::new();
+        let key = StreamCoalescer::key_for(b"request");
+
+        let Coalesced::Leader(publisher) = coalescer.join(&key) else {
+            panic!("首个请求应为 Leader");
+        };
+        let upstream = stream::iter(["a", "b"].map(|s| Ok::<_, Infallible>(Bytes::from(s))));
+        let mut leader = Box::pin(publisher.tee(upstream));
+        assert_eq!(leader.next().await, Some(Ok(Bytes::from("a"))));
+
+        let Coalesced::Follower(mut follower) = coalescer.join(&key) else {
+            panic!("相同请求应为 Follower");
+        };
+        assert_eq!(leader.next().await, Some(Ok(Bytes::from("b"))));
+        assert_eq!(leader.next().await, None);
+        drop(leader);
+
+        assert_eq!(follower.next().await, Some(Bytes::from("a")));
+        assert_eq!(follower.next().await, Some(Bytes::from("b")));
+        assert_eq!(follower.next().await, None);
+        assert_eq!(coalescer.inflight_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_and_lagging_followers_get_error_event() {
+        let coalescer = StreamCoalescer::new();
+        let key = StreamCoalescer::key_for(b"request");
+
+        // Leader 中途断开
+        let Coalesced::Leader(mut publisher) = coalescer.join(&key) else {
+            panic!("首个请求应为 Leader");
+        };
+        publisher.publish(Bytes::from("a"));
+        let Coalesced::Follower(mut follower) = coalescer.join(&key) else {
+            panic!("相同请求应为 Follower");
+        };
+        drop(publisher);
+        assert_eq!(follower.next().await, Some(Bytes::from("a")));
+        let event = follower.next().await.unwrap();
+        assert!(event.starts_with(b"event: error\n"));
+        assert_eq!(follower.next().await, None);
+
+        // 订阅者落后超过广播容量
+        let Coalesced::Leader(mut publisher) = coalescer.join(&key) else {
+            panic!("首个请求应为 Leader");
+        };
+        let Coalesced::Follower(mut follower) = coalescer.join(&key) else {
+            panic!("相同请求应为 Follower");
+        };
+        for _ in 0..=BROADCAST_CAPACITY {
+            publisher.publish(Bytes::from("x"));
+        }
+        let mut last = None;
+        while let Some(bytes) = follower.next().await {
+            last = Some(bytes);
+        }
+        assert!(last.unwrap().starts_with(b"event: error\n"));
+
+        // 历史超过上限后不再接受新的 Follower
+        publisher.publish(Bytes::from(vec![b'x'; MAX_HISTORY_BYTES]));
+        assert!(matches!(coalescer.join(&key), Coalesced::Leader(_)));
+    }
+
+    #[tokio::test]
+    async fn test_different_requests_not_coalesced() {
+        let coalescer = StreamCoalescer::new();
+
+        let first = coalescer.join(&StreamCoalescer::key_for(b"one"));
+        let second = coalescer.join(&StreamCoalescer::key_for(b"two"));
+
+        assert!(matches!(first, Coalesced::Leader(_)));
+        assert!(matches!(second, Coalesced::Leader(_)));
+    }
+
+    #[tokio::test]
+    async fn test_leader_dropped_without_events() {
+        let coalescer = StreamCoalescer::new();
+        let key = StreamCoalescer::key_for(b"request");
+
+        let leader = coalescer.join(&key);
+        let Coalesced::Follower(mut follower) = coalescer.join(&key) else {
+            panic!("相同请求应为 Follower");
+        };
+        drop(leader);
+
+        assert_eq!(follower.next().await, None);
+        assert!(matches!(coalescer.join(&key), Coalesced::Leader(_)));
+    }
+}
@@ -0,0 +1,185 @@
+//! 截断续写
+//!
+//! `POST /v1/messages?continue=true` 的非流式响应因 max_tokens 截断时，
+//! 把已生成的文本作为末尾的 assistant 消息再次请求，并把各轮内容拼接为一条响应。
+//! 续写请求的转换见 `converter` 中的 `CONTINUATION_PROMPT`。
+
+use axum::body::{to_bytes, Body};
+use axum::http::{response::Parts, StatusCode};
+use axum::response::Response;
+use serde_json::{json, Value};
+
+use super::types::MessagesRequest;
+
+/// 单个请求最多自动续写的轮数
+pub const MAX_CONTINUATIONS: usize = 3;
+
+/// 非流式响应体的读取上限
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// 读取成功响应的 JSON 响应体，失败或非 JSON 时原样返回响应
+pub async fn read_json(response: Response) -> Result<(Parts, Value), Response> {
+    if response.status() != StatusCode::OK {
+        return Err(response);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, MAX_RESPONSE_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("读取响应体失败，跳过续写: {}", e);
+            return Err(Response::from_parts(parts, Body::empty()));
+        }
+    };
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => Ok((parts, value)),
+        Err(_) => Err(Response::from_parts(parts, Body::from(bytes))),
+    }
+}
+
+/// 重新组装响应
+pub fn into_response(parts: Parts, body: &Value) -> Response {
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+/// 响应因 max_tokens 截断且只包含文本时，返回本轮生成的文本
+///
+/// 包含工具调用的响应不续写：截断的工具参数无法可靠拼接
+pub fn truncated_text(body: &Value) -> Option<String> {
+    if body["stop_reason"] != "max_tokens" {
+        return None;
+    }
+    let blocks = body["content"].as_array()?;
+    let mut text = String::new();
+    for block in blocks {
+        if block["type"] != "text" {
+            return None;
+        }
+        text.push_str(block["text"].as_str()?);
+    }
+    (!text.is_empty()).then_some(text)
+}
+
+/// 把截断的文本接到请求末尾的 assistant 消息上，末尾不是 assistant 时追加一条
+///
+/// 直接拼接到已有消息中，不能新增相邻的 assistant 消息：转换时相邻的同角色消息之间会补换行
+pub fn append_partial(payload: &mut MessagesRequest, text: &str) {
+    if let Some(last) = payload
+        .messages
+        .last_mut()
+        .filter(|m| m.role == "assistant")
+    {
+        match &mut last.content {
+            Value::String(content) => content.push_str(text),
+            Value::Array(blocks) => match blocks.last_mut() {
+                Some(block) if block["type"] == "text" => {
+                    let joined = format!("{}{}", block["text"].as_str().unwrap_or(""), text);
+                    block["text"] = Value::String(joined);
+                }
+                _ => blocks.push(json!({"type": "text", "text": text})),
+            },
+            content => *content = Value::String(text.to_string()),
+        }
+        return;
+    }
+    payload.messages.push(super::types::Message {
+        role: "assistant".to_string(),
+        content: Value::String(text.to_string()),
+    });
+}
+
+/// 把续写轮次的响应拼接到已有响应上
+///
+/// 文本接到上一轮最后的文本块后，其他内容块追加在后面；
+/// stop_reason 取最后一轮，output_tokens 累加，input_tokens 保留第一轮
+pub fn stitch(body: &mut Value, next: &Value) {
+    if let (Some(content), Some(next_content)) =
+        (body["content"].as_array_mut(), next["content"].as_array())
+    {
+        for block in next_content {
+            match content.last_mut() {
+                Some(last) if last["type"] == "text" && block["type"] == "text" => {
+                    let joined = format!(
+                        "{}{}",
+                        last["text"].as_str().unwrap_or(""),
+                        block["text"].as_str().unwrap_or("")
+                    );
+                    last["text"] = Value::String(joined);
+                }
+                _ => content.push(block.clone()),
+            }
+        }
+    }
+    body["stop_reason"] = next["stop_reason"].clone();
+    let output_tokens = body["usage"]["output_tokens"].as_i64().unwrap_or(0)
+        + next["usage"]["output_tokens"].as_i64().unwrap_or(0);
+    body["usage"]["output_tokens"] = json!(output_tokens);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content: Value, stop_reason: &str, output_tokens: i64) -> Value {
+        json!({
+            "type": "message",
+            "content": content,
+            "stop_reason": stop_reason,
+            "usage": {"input_tokens": 10, "output_tokens": output_tokens}
+        })
+    }
+
+    #[test]
+    fn test_continuation_round_trip() {
+        let first = response(
+            json!([{"type": "text", "text": "Once upon"}]),
+            "max_tokens",
+            5,
+        );
+        assert_eq!(truncated_text(&first).as_deref(), Some("Once upon"));
+        let with_tool = response(
+            json!([{"type": "text", "text": "a"}, {"type": "tool_use", "id": "t", "name": "x", "input": {}}]),
+            "max_tokens",
+            5,
+        );
+        assert!(truncated_text(&with_tool).is_none());
+        assert!(truncated_text(&response(
+            json!([{"type": "text", "text": "a"}]),
+            "end_turn",
+            1
+        ))
+        .is_none());
+
+        // 客户端预填的 assistant 消息上继续拼接
+        let mut payload: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 10,
+            "messages": [
+                {"role": "user", "content": "Write a story"},
+                {"role": "assistant", "content": [{"type": "text", "text": "Title: "}]}
+            ]
+        }))
+        .unwrap();
+        append_partial(&mut payload, "Once upon");
+        append_partial(&mut payload, " a time");
+        assert_eq!(payload.messages.len(), 2);
+        assert_eq!(
+            payload.messages[1].content[0]["text"],
+            "Title: Once upon a time"
+        );
+
+        let mut body = first;
+        stitch(
+            &mut body,
+            &response(json!([{"type": "text", "text": " a time"}]), "end_turn", 3),
+        );
+        assert_eq!(
+            body["content"],
+            json!([{"type": "text", "text": "Once upon a time"}])
+        );
+        assert_eq!(body["stop_reason"], "end_turn");
+        assert_eq!(
+            body["usage"],
+            json!({"input_tokens": 10, "output_tokens": 8})
+        );
+    }
+}
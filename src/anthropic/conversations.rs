@@ -0,0 +1,244 @@
+//! 会话缓存
+//!
+//! 客户端通过 `x-kiro-conversation-id` 请求头标识会话时，同一会话的后续请求复用首次请求生成的
+//! `conversationId` 和 `agentContinuationId`，与 Kiro IDE 在一次对话中保持相同的会话 ID 一致。
+//!
+//! 缓存按最近访问淘汰（LRU），条数不超过 `conversationCacheSize`，超过 `conversationTtlSecs`
+//! 未访问的条目视为过期。键按调用方（托管 API Key 前缀、工作区名称）隔离，不同租户使用相同的
+//! 会话 ID 不会互相复用。命中、未命中、淘汰和过期次数可通过管理 API `GET /api/conversations` 查看。
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::model::config::Config;
+
+/// 一个会话复用的上游 ID
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationEntry {
+    pub conversation_id: String,
+    pub continuation_id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    /// 复用次数
+    pub hits: u64,
+}
+
+impl ConversationEntry {
+    pub fn new(conversation_id: impl Into<String>, continuation_id: impl Into<String>) -> Self {
+        let now = Utc::now();
+        Self {
+            conversation_id: conversation_id.into(),
+            continuation_id: continuation_id.into(),
+            created_at: now,
+            last_used_at: now,
+            hits: 0,
+        }
+    }
+}
+
+/// 缓存统计
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationCacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub ttl_secs: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// 因条数达到上限被淘汰的条目数
+    pub evictions: u64,
+    /// 因超过 TTL 未访问被移除的条目数
+    pub expirations: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// 会话键 -> (条目, 最近访问时间, 最近访问序号)
+    entries: HashMap<String, (ConversationEntry, Instant, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    expirations: u64,
+}
+
+/// 按会话键保存上游 ID 的 LRU 缓存，容量为 0 时禁用
+pub struct ConversationCache {
+    capacity: usize,
+    ttl: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl Default for ConversationCache {
+    fn default() -> Self {
+        Self::new(0, Duration::ZERO)
+    }
+}
+
+impl From<&Config> for ConversationCache {
+    fn from(config: &Config) -> Self {
+        Self::new(
+            config.conversation_cache_size,
+            Duration::from_secs(config.conversation_ttl_secs),
+        )
+    }
+}
+
+impl ConversationCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            inner: Mutex::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// 查找会话并刷新访问时间，过期的条目被移除并计为未命中
+    pub fn get(&self, key: &str) -> Option<ConversationEntry> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let (now, tick, ttl) = (Instant::now(), inner.tick, self.ttl);
+        match inner.entries.get_mut(key) {
+            Some((_, used_at, _)) if now.duration_since(*used_at) > ttl => {
+                inner.entries.remove(key);
+                inner.expirations += 1;
+                inner.misses += 1;
+                None
+            }
+            Some((entry, used_at, used)) => {
+                *used_at = now;
+                *used = tick;
+                entry.last_used_at = Utc::now();
+                entry.hits += 1;
+                let entry = entry.clone();
+                inner.hits += 1;
+                Some(entry)
+            }
+            None => {
+                inner.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// 保存会话，达到容量时先移除过期条目，仍然已满则淘汰最久未访问的条目
+    pub fn insert(&self, key: String, entry: ConversationEntry) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let now = Instant::now();
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&key) {
+            let before = inner.entries.len();
+            let ttl = self.ttl;
+            inner
+                .entries
+                .retain(|_, (_, used_at, _)| now.duration_since(*used_at) <= ttl);
+            inner.expirations += (before - inner.entries.len()) as u64;
+        }
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(&key) {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, _, used))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+                inner.evictions += 1;
+            }
+        }
+        let tick = inner.tick;
+        inner.entries.insert(key, (entry, now, tick));
+    }
+
+    /// 所有未过期的条目，最近访问的在前
+    pub fn list(&self) -> Vec<(String, ConversationEntry)> {
+        let inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        let mut entries: Vec<_> = inner
+            .entries
+            .iter()
+            .filter(|(_, (_, used_at, _))| now.duration_since(*used_at) <= self.ttl)
+            .map(|(key, (entry, _, used))| (*used, key.clone(), entry.clone()))
+            .collect();
+        entries.sort_by_key(|(used, _, _)| std::cmp::Reverse(*used));
+        entries
+            .into_iter()
+            .map(|(_, key, entry)| (key, entry))
+            .collect()
+    }
+
+    /// 移除一个会话
+    pub fn remove(&self, key: &str) -> bool {
+        self.inner.lock().unwrap().entries.remove(key).is_some()
+    }
+
+    /// 清空缓存，返回移除的条目数（统计计数保留）
+    pub fn clear(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let count = inner.entries.len();
+        inner.entries.clear();
+        count
+    }
+
+    pub fn stats(&self) -> ConversationCacheStats {
+        let inner = self.inner.lock().unwrap();
+        ConversationCacheStats {
+            entries: inner.entries.len(),
+            capacity: self.capacity,
+            ttl_secs: self.ttl.as_secs(),
+            hits: inner.hits,
+            misses: inner.misses,
+            evictions: inner.evictions,
+            expirations: inner.expirations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_ttl_and_metrics() {
+        let cache = ConversationCache::new(2, Duration::from_secs(60));
+        cache.insert("a".to_string(), ConversationEntry::new("conv-a", "cont-a"));
+        cache.insert("b".to_string(), ConversationEntry::new("conv-b", "cont-b"));
+        assert_eq!(cache.get("a").unwrap().conversation_id, "conv-a");
+
+        // b 最久未访问，被淘汰
+        cache.insert("c".to_string(), ConversationEntry::new("conv-c", "cont-c"));
+        assert!(cache.get("b").is_none());
+        let keys: Vec<String> = cache.list().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["c", "a"]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 1, 1));
+        assert!(cache.remove("a"));
+        assert_eq!(cache.clear(), 1);
+
+        // TTL 为 0 时条目立即过期
+        let cache = ConversationCache::new(2, Duration::ZERO);
+        cache.insert("a".to_string(), ConversationEntry::new("conv-a", "cont-a"));
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.stats().expirations, 1);
+
+        let disabled = ConversationCache::default();
+        disabled.insert("a".to_string(), ConversationEntry::new("conv-a", "cont-a"));
+        assert!(disabled.get("a").is_none());
+    }
+}
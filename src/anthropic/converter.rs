@@ -2,6 +2,9 @@
 //!
 //! 负责将 Anthropic API 请求格式转换为 Kiro API 请求格式
 
+use std::collections::HashSet;
+
+use base64::Engine;
 use uuid::Uuid;
 
 use crate::kiro::model::requests::conversation::{
@@ -11,8 +14,28 @@ use crate::kiro::model::requests::conversation::{
 use crate::kiro::model::requests::tool::{
     InputSchema, Tool, ToolResult, ToolSpecification, ToolUseEntry,
 };
+use crate::model::config::{AgentMode, Config, SystemPromptPosition};
+
+use super::blocks::InputBlock;
+use super::files::FileStore;
+use super::tool_alias::ToolAliases;
+use super::types::{ImageSource, MessagesRequest, SystemMessage, Thinking};
 
-use super::types::{ContentBlock, MessagesRequest, Thinking};
+/// Kiro 拒绝空 content，以下占位文本用于没有可见文本的消息
+///
+/// 只有 tool_result 的 user 消息（工具调用后的常见回合）
+const TOOL_RESULTS_PLACEHOLDER: &str = "Tool results are provided in the context.";
+/// 只有图片的 user 消息
+const IMAGES_PLACEHOLDER: &str = "See the attached image(s).";
+/// 完全为空（或只有空白）的 user 消息
+const EMPTY_USER_PLACEHOLDER: &str = "continue";
+/// 只有 tool_use 的 assistant 消息
+const TOOL_USES_PLACEHOLDER: &str = "I'll use the tools.";
+/// 完全为空的 assistant 消息
+const EMPTY_ASSISTANT_PLACEHOLDER: &str = "OK";
+/// 续写请求：末尾是（可能被 max_tokens 截断的）assistant 消息，或其后只跟一个空的 user 回合
+const CONTINUATION_PROMPT: &str =
+    "Continue your previous response exactly where it stopped. Do not repeat any text that was already written.";
 
 /// 模型映射：将 Anthropic 模型名映射到 Kiro 模型 ID
 ///
@@ -39,6 +62,10 @@ pub fn map_model(model: &str) -> Option<String> {
 pub struct ConversionResult {
     /// 转换后的 Kiro 请求
     pub conversation_state: ConversationState,
+    /// 工具名称别名（响应中的 tool_use 需映射回原始名称）
+    pub tool_aliases: ToolAliases,
+    /// 因超出大小限制被截断的 tool_result
+    pub truncated: ToolResultTruncation,
 }
 
 /// 转换错误
@@ -46,6 +73,15 @@ pub struct ConversionResult {
 pub enum ConversionError {
     UnsupportedModel(String),
     EmptyMessages,
+    FileNotFound(String),
+    /// 内容块类型不受支持（按配置拒绝）
+    UnsupportedContentBlock(String),
+    /// 工具定义超出限制（限制名称、实际值、上限）
+    ToolLimitExceeded {
+        limit: &'static str,
+        actual: usize,
+        max: usize,
+    },
 }
 
 impl std::fmt::Display for ConversionError {
@@ -53,12 +89,331 @@ impl std::fmt::Display for ConversionError {
         match self {
             ConversionError::UnsupportedModel(model) => write!(f, "模型不支持: {}", model),
             ConversionError::EmptyMessages => write!(f, "消息列表为空"),
+            ConversionError::FileNotFound(id) => write!(f, "文件不存在: {}", id),
+            ConversionError::UnsupportedContentBlock(block_type) => {
+                write!(f, "不支持的内容块类型: {}", block_type)
+            }
+            ConversionError::ToolLimitExceeded { limit, actual, max } => {
+                write!(f, "工具定义超出 {} 限制: {} > {}", limit, actual, max)
+            }
         }
     }
 }
 
 impl std::error::Error for ConversionError {}
 
+impl ConversionError {
+    /// 错误类型标识（用于遥测）
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ConversionError::UnsupportedModel(_) => "unsupported_model",
+            ConversionError::EmptyMessages => "empty_messages",
+            ConversionError::FileNotFound(_) => "file_not_found",
+            ConversionError::UnsupportedContentBlock(_) => "unsupported_content_block",
+            ConversionError::ToolLimitExceeded { .. } => "tool_limit_exceeded",
+        }
+    }
+
+    /// 出错的请求字段路径（用于遥测）
+    pub fn field_path(&self) -> &'static str {
+        match self {
+            ConversionError::UnsupportedModel(_) => "model",
+            ConversionError::EmptyMessages => "messages",
+            ConversionError::FileNotFound(_) => "messages[].content[].source.file_id",
+            ConversionError::UnsupportedContentBlock(_) => "messages[].content[].type",
+            ConversionError::ToolLimitExceeded { .. } => "tools",
+        }
+    }
+}
+
+/// 将内容块中 `source.type == "file"` 的 `file_id` 引用解析为内联 base64 数据
+pub async fn resolve_file_references(
+    messages: &mut [super::types::Message],
+    store: &FileStore,
+) -> Result<(), ConversionError> {
+    for msg in messages.iter_mut() {
+        let Some(blocks) = msg.content.as_array_mut() else {
+            continue;
+        };
+        for block in blocks.iter_mut() {
+            let file_id = match block.get("source") {
+                Some(source) if source.get("type").and_then(|v| v.as_str()) == Some("file") => {
+                    source
+                        .get("file_id")
+                        .and_then(|v| v.as_str())
+                        .map(str::to_string)
+                }
+                _ => None,
+            };
+            let Some(file_id) = file_id else {
+                continue;
+            };
+
+            let (metadata, data) = store
+                .read(&file_id)
+                .await
+                .ok_or_else(|| ConversionError::FileNotFound(file_id.clone()))?;
+            block["source"] = serde_json::json!({
+                "type": "base64",
+                "media_type": metadata.mime_type,
+                "data": base64::engine::general_purpose::STANDARD.encode(data),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// 将运营方配置的系统提示词合并到请求的 system 中
+pub fn inject_system_prompt(
+    req: &mut MessagesRequest,
+    prompt: &str,
+    position: SystemPromptPosition,
+) {
+    if prompt.trim().is_empty() {
+        return;
+    }
+    let block = SystemMessage {
+        text: prompt.to_string(),
+    };
+    let system = req.system.get_or_insert_with(Vec::new);
+    match position {
+        SystemPromptPosition::Prepend => system.insert(0, block),
+        SystemPromptPosition::Append => system.push(block),
+    }
+}
+
+/// 工具列表和工具结果限制
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ToolLimits {
+    /// 最大工具数量
+    pub max_tools: Option<usize>,
+    /// 工具定义序列化后的最大字节数
+    pub max_bytes: Option<usize>,
+    /// 超出限制时按相关性裁剪
+    pub prune: bool,
+    /// 单个 tool_result 内容的最大字节数
+    pub max_result_bytes: Option<usize>,
+    /// 截断 tool_result 时保留的结尾字节数
+    pub result_tail_bytes: usize,
+}
+
+impl From<&Config> for ToolLimits {
+    fn from(config: &Config) -> Self {
+        Self {
+            max_tools: config.max_tools,
+            max_bytes: config.max_tools_bytes,
+            prune: config.prune_tools,
+            max_result_bytes: config.max_tool_result_bytes,
+            result_tail_bytes: config.tool_result_tail_bytes,
+        }
+    }
+}
+
+/// tool_result 截断统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ToolResultTruncation {
+    /// 被截断的 tool_result 数量
+    pub results: usize,
+    /// 移除的字节数
+    pub bytes: usize,
+}
+
+impl ToolLimits {
+    /// 检查工具数量和字节数，返回第一个超出的限制
+    fn check(&self, count: usize, bytes: usize) -> Result<(), ConversionError> {
+        if let Some(max) = self.max_tools.filter(|&max| count > max) {
+            return Err(ConversionError::ToolLimitExceeded {
+                limit: "maxTools",
+                actual: count,
+                max,
+            });
+        }
+        if let Some(max) = self.max_bytes.filter(|&max| bytes > max) {
+            return Err(ConversionError::ToolLimitExceeded {
+                limit: "maxToolsBytes",
+                actual: bytes,
+                max,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// 对工具列表应用数量和大小限制
+///
+/// 启用裁剪时按相关性保留工具：对话中已调用或 tool_choice 指定的工具必须保留，
+/// 其次是名称在消息或系统提示中出现过的工具，最后按原始顺序填充剩余额度
+pub fn apply_tool_limits(
+    req: &mut MessagesRequest,
+    limits: &ToolLimits,
+) -> Result<(), ConversionError> {
+    let Some(tools) = &req.tools else {
+        return Ok(());
+    };
+    let tools: Vec<_> = tools
+        .iter()
+        .filter(|t| !is_unsupported_tool(&t.name))
+        .collect();
+    let sizes: Vec<usize> = tools
+        .iter()
+        .map(|t| serde_json::to_vec(t).map(|v| v.len()).unwrap_or(0))
+        .collect();
+
+    let result = limits.check(tools.len(), sizes.iter().sum());
+    if result.is_ok() || !limits.prune {
+        return result;
+    }
+
+    let used = used_tool_names(req);
+    let haystack = conversation_text(req);
+    let mut ranked: Vec<(u8, usize)> = tools
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let rank = if used.contains(t.name.as_str()) {
+                0
+            } else if haystack.contains(&t.name) {
+                1
+            } else {
+                2
+            };
+            (rank, i)
+        })
+        .collect();
+    ranked.sort();
+
+    let mut kept = Vec::new();
+    let mut kept_bytes = 0;
+    for (rank, i) in ranked {
+        let fits = limits.check(kept.len() + 1, kept_bytes + sizes[i]);
+        match fits {
+            Ok(()) => {
+                kept.push(i);
+                kept_bytes += sizes[i];
+            }
+            // 已调用的工具不能裁剪，否则历史中的 tool_use 无法对应工具定义
+            Err(e) if rank == 0 => return Err(e),
+            Err(_) => {}
+        }
+    }
+    kept.sort();
+
+    tracing::info!(
+        "工具列表超出限制，已从 {} 个裁剪为 {} 个",
+        tools.len(),
+        kept.len()
+    );
+    let pruned = kept.into_iter().map(|i| tools[i].clone()).collect();
+    req.tools = Some(pruned);
+    Ok(())
+}
+
+/// 截断超出 `max_result_bytes` 的 tool_result 内容
+///
+/// 保留开头和最多 `result_tail_bytes` 字节的结尾（报错信息、汇总行通常在末尾），中间替换为
+/// 截断说明，截断后的内容不超过上限。数组内容先按转换规则拼接为文本再截断
+pub fn truncate_tool_results(
+    req: &mut MessagesRequest,
+    limits: &ToolLimits,
+) -> ToolResultTruncation {
+    let mut truncation = ToolResultTruncation::default();
+    let Some(max) = limits.max_result_bytes else {
+        return truncation;
+    };
+    for msg in req.messages.iter_mut().filter(|m| m.role == "user") {
+        let serde_json::Value::Array(blocks) = &mut msg.content else {
+            continue;
+        };
+        for block in blocks.iter_mut().filter(|b| b["type"] == "tool_result") {
+            let content = extract_tool_result_content(&block.get("content").cloned());
+            if content.len() <= max {
+                continue;
+            }
+            let (truncated, removed) = truncate_middle(&content, max, limits.result_tail_bytes);
+            tracing::info!(
+                "tool_result {} 超出 {} 字节，已截断 {} 字节",
+                block["tool_use_id"].as_str().unwrap_or("-"),
+                max,
+                removed
+            );
+            block["content"] = serde_json::Value::String(truncated);
+            truncation.results += 1;
+            truncation.bytes += removed;
+        }
+    }
+    truncation
+}
+
+/// 保留开头和结尾，中间替换为截断说明，返回截断后的文本和移除的字节数
+fn truncate_middle(text: &str, max: usize, tail: usize) -> (String, usize) {
+    let notice = |removed: usize| format!("\n\n[... {} bytes truncated ...]\n\n", removed);
+    // 以原文长度估算说明文字的长度（实际移除的字节数位数不会更多）
+    let notice_len = notice(text.len()).len();
+    let budget = max.saturating_sub(notice_len);
+    let tail = tail.min(max / 2).min(budget);
+    let mut head_end = budget - tail;
+    while !text.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = text.len() - tail;
+    while !text.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    let removed = tail_start - head_end;
+    let truncated = format!(
+        "{}{}{}",
+        &text[..head_end],
+        notice(removed),
+        &text[tail_start..]
+    );
+    (truncated, removed)
+}
+
+/// 收集对话中已调用的工具和 tool_choice 指定的工具
+fn used_tool_names(req: &MessagesRequest) -> HashSet<String> {
+    let mut names: HashSet<String> = req
+        .messages
+        .iter()
+        .flat_map(|msg| block_slice(&msg.content))
+        .filter(|block| block.get("type").and_then(|v| v.as_str()) == Some("tool_use"))
+        .filter_map(|block| block.get("name").and_then(|v| v.as_str()))
+        .map(str::to_string)
+        .collect();
+    if let Some(name) = req
+        .tool_choice
+        .as_ref()
+        .and_then(|choice| choice.get("name"))
+        .and_then(|v| v.as_str())
+    {
+        names.insert(name.to_string());
+    }
+    names
+}
+
+/// 拼接消息和系统提示的文本，用于判断工具名是否被提及
+fn conversation_text(req: &MessagesRequest) -> String {
+    let mut parts: Vec<&str> = req
+        .system
+        .iter()
+        .flatten()
+        .map(|s| s.text.as_str())
+        .collect();
+    for msg in &req.messages {
+        match &msg.content {
+            serde_json::Value::String(s) => parts.push(s),
+            content => {
+                parts.extend(
+                    block_slice(content)
+                        .iter()
+                        .filter_map(|block| block.get("text").or_else(|| block.get("content")))
+                        .filter_map(|v| v.as_str()),
+                );
+            }
+        }
+    }
+    parts.join("\n")
+}
+
 /// 将 Anthropic 请求转换为 Kiro 请求
 pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, ConversionError> {
     // 1. 映射模型
@@ -70,16 +425,19 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         return Err(ConversionError::EmptyMessages);
     }
 
-    // 2.1 合并末尾连续的 user 消息（并行 tool_result 往往会拆成多个 user 消息）
-    let mut current_start = req.messages.len();
-    while current_start > 0 && req.messages[current_start - 1].role == "user" {
+    // 2.1 合并连续的同角色消息（Kiro 要求 user/assistant 严格交替）
+    let messages = merge_consecutive_roles(&req.messages);
+
+    // 2.2 末尾的 user 消息作为 current_message
+    let mut current_start = messages.len();
+    while current_start > 0 && messages[current_start - 1].role == "user" {
         current_start -= 1;
     }
-    let current_user_messages = &req.messages[current_start..];
+    let current_user_messages = &messages[current_start..];
     
-    // 2.2 检查是否末尾是 assistant 消息（用于标题生成等场景）
+    // 2.3 检查是否末尾是 assistant 消息（用于标题生成等场景）
     let ends_with_assistant = current_user_messages.is_empty() 
-        && req.messages.last().map(|m| m.role == "assistant").unwrap_or(false);
+        && messages.last().map(|m| m.role == "assistant").unwrap_or(false);
 
     // 3. 生成会话 ID 和代理 ID
     let conversation_id = Uuid::new_v4().to_string();
@@ -90,13 +448,22 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
 
     // 5. 处理末尾的 user 消息组作为 current_message
     let (text_content, images, tool_results) = if ends_with_assistant {
-        // 末尾是 assistant 消息，自动补一个 "continue" 请求
-        // 这种情况通常是 Claude Code 的辅助请求（标题生成、摘要等）
-        tracing::info!("消息末尾是 assistant，自动补充 continue 请求（可能是标题生成等辅助功能）");
-        ("continue".to_string(), Vec::new(), Vec::new())
+        // 末尾是 assistant 消息，自动补一个续写请求
+        // 这种情况通常是 max_tokens 截断后的续写，或 Claude Code 的辅助请求（标题生成、摘要等）
+        tracing::info!("消息末尾是 assistant，自动补充续写请求");
+        (CONTINUATION_PROMPT.to_string(), Vec::new(), Vec::new())
     } else {
         let current_refs: Vec<&super::types::Message> = current_user_messages.iter().collect();
-        let merged_current = merge_user_messages(&current_refs, &model_id)?;
+        let mut merged_current = merge_user_messages(&current_refs, &model_id)?;
+        // assistant 之后只有一个空的 user 回合：客户端在截断的回复后请求续写
+        let message = &mut merged_current.user_input_message;
+        if current_start > 0
+            && message.content == EMPTY_USER_PLACEHOLDER
+            && message.images.is_empty()
+            && message.user_input_message_context.tool_results.is_empty()
+        {
+            message.content = CONTINUATION_PROMPT.to_string();
+        }
         (
             merged_current.user_input_message.content.clone(),
             merged_current.user_input_message.images.clone(),
@@ -137,14 +504,14 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     // 9. 构建历史消息（排除 current_message 对应的末尾 user 消息组）
     // 如果末尾是 assistant，则所有消息都作为历史
     let history_end = if ends_with_assistant {
-        req.messages.len()
+        messages.len()
     } else {
         current_start
     };
     let history_req = MessagesRequest {
         model: req.model.clone(),
         max_tokens: req.max_tokens,
-        messages: req.messages[..history_end].to_vec(),
+        messages: messages[..history_end].to_vec(),
         stream: req.stream,
         system: req.system.clone(),
         tools: req.tools.clone(),
@@ -154,14 +521,22 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     let history = build_history(&history_req, &model_id)?;
 
     // 10. 构建 ConversationState
-    let conversation_state = ConversationState::new(conversation_id)
+    let mut conversation_state = ConversationState::new(conversation_id)
         .with_agent_continuation_id(agent_continuation_id)
-        .with_agent_task_type("vibe")
+        .with_agent_task_type(AgentMode::default().as_str())
         .with_chat_trigger_type(chat_trigger_type)
         .with_current_message(current_message)
         .with_history(history);
 
-    Ok(ConversionResult { conversation_state })
+    // 11. 将上游不接受的工具名称替换为别名
+    let tool_aliases = ToolAliases::from_request(req);
+    tool_aliases.apply(&mut conversation_state);
+
+    Ok(ConversionResult {
+        conversation_state,
+        tool_aliases,
+        truncated: ToolResultTruncation::default(),
+    })
 }
 
 /// 确定聊天触发类型
@@ -188,14 +563,23 @@ fn process_message_content(
 
     match content {
         serde_json::Value::String(s) => {
-            text_parts.push(s.clone());
+            if !s.trim().is_empty() {
+                text_parts.push(s.clone());
+            }
         }
-        serde_json::Value::Array(arr) => {
-            for item in arr {
-                if let Ok(block) = serde_json::from_value::<ContentBlock>(item.clone()) {
+        content => {
+            for item in block_slice(content) {
+                // tool_result 直接从原始 JSON 转换，避免其它字段解析失败导致错误标记丢失
+                if item.get("type").and_then(|v| v.as_str()) == Some("tool_result") {
+                    if let Some(result) = convert_tool_result(item) {
+                        tool_results.push(result);
+                    }
+                    continue;
+                }
+                if let Some(InputBlock::Known(block)) = InputBlock::from_value(item) {
                     match block.block_type.as_str() {
                         "text" => {
-                            if let Some(text) = block.text {
+                            if let Some(text) = block.text.filter(|t| !t.trim().is_empty()) {
                                 text_parts.push(text);
                             }
                         }
@@ -206,22 +590,10 @@ fn process_message_content(
                                 }
                             }
                         }
-                        "tool_result" => {
-                            if let Some(tool_use_id) = block.tool_use_id {
-                                let result_content = extract_tool_result_content(&block.content);
-                                let is_error = block.is_error.unwrap_or(false);
-
-                                let mut result = if is_error {
-                                    ToolResult::error(&tool_use_id, result_content)
-                                } else {
-                                    ToolResult::success(&tool_use_id, result_content)
-                                };
-                                result.status =
-                                    Some(if is_error { "error" } else { "success" }.to_string());
-
-                                tool_results.push(result);
-                            }
-                        }
+                        "document" => match block.source.as_ref().and_then(decode_text_document) {
+                            Some(text) => text_parts.push(text),
+                            None => tracing::warn!("不支持的文档类型，已忽略"),
+                        },
                         "tool_use" => {
                             // tool_use 在 assistant 消息中处理，这里忽略
                         }
@@ -230,12 +602,52 @@ fn process_message_content(
                 }
             }
         }
-        _ => {}
     }
 
     Ok((text_parts.join("\n"), images, tool_results))
 }
 
+/// 转换 tool_result 内容块
+///
+/// `is_error: true` 映射为 status "error" 的 Kiro 工具结果（兼容字符串 "true"），
+/// 错误结果没有内容时补充说明文本，保证模型能看到工具执行失败
+fn convert_tool_result(item: &serde_json::Value) -> Option<ToolResult> {
+    let Some(tool_use_id) = item.get("tool_use_id").and_then(|v| v.as_str()) else {
+        tracing::warn!("tool_result 缺少 tool_use_id，已忽略");
+        return None;
+    };
+    let is_error = match item.get("is_error") {
+        Some(serde_json::Value::Bool(b)) => *b,
+        Some(serde_json::Value::String(s)) => s.eq_ignore_ascii_case("true"),
+        _ => false,
+    };
+    let content = extract_tool_result_content(&item.get("content").cloned());
+
+    if !is_error {
+        return Some(ToolResult::success(tool_use_id, content));
+    }
+    let message = if content.trim().is_empty() {
+        "Tool execution failed".to_string()
+    } else {
+        content
+    };
+    Some(ToolResult::error(tool_use_id, message))
+}
+
+/// 解码文本类文档内容（纯文本或 base64 编码的 text/*）
+fn decode_text_document(source: &ImageSource) -> Option<String> {
+    match source.source_type.as_str() {
+        "text" => Some(source.data.clone()),
+        "base64" if source.media_type.starts_with("text/") => {
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(&source.data)
+                .ok()?;
+            String::from_utf8(bytes).ok()
+        }
+        _ => None,
+    }
+}
+
 /// 从 media_type 获取图片格式
 fn get_image_format(media_type: &str) -> Option<String> {
     match media_type {
@@ -330,7 +742,7 @@ fn build_history(req: &MessagesRequest, model_id: &str) -> Result<Vec<Message>,
             .collect::<Vec<_>>()
             .join("\n");
 
-        if !system_content.is_empty() {
+        if !system_content.trim().is_empty() {
             // 注入thinking标签到系统消息最前面（如果需要且不存在）
             let final_content = if let Some(ref prefix) = thinking_prefix {
                 if !has_thinking_tags(&system_content) {
@@ -410,6 +822,48 @@ fn build_history(req: &MessagesRequest, model_id: &str) -> Result<Vec<Message>,
     Ok(history)
 }
 
+/// 合并连续的同角色消息
+///
+/// 客户端常发送连续的同角色消息，如 Claude Code 在 tool_result 之后追加的
+/// system-reminder，或被拆成多条的 assistant 文本和 tool_use。
+/// 连续消息的内容块按顺序拼接（tool_result、tool_use 随之合并）；assistant 文本块
+/// 在转换时直接相连，因此相邻 assistant 消息之间补一个换行，避免文本粘连
+fn merge_consecutive_roles(messages: &[super::types::Message]) -> Vec<super::types::Message> {
+    let mut merged: Vec<super::types::Message> = Vec::with_capacity(messages.len());
+    for msg in messages {
+        match merged.last_mut() {
+            Some(last) if last.role == msg.role => {
+                let mut blocks = content_blocks(&last.content);
+                if msg.role == "assistant" {
+                    blocks.push(serde_json::json!({"type": "text", "text": "\n"}));
+                }
+                blocks.extend(content_blocks(&msg.content));
+                last.content = serde_json::Value::Array(blocks);
+            }
+            _ => merged.push(msg.clone()),
+        }
+    }
+    merged
+}
+
+/// 将消息内容统一为内容块数组
+fn content_blocks(content: &serde_json::Value) -> Vec<serde_json::Value> {
+    match content {
+        serde_json::Value::String(s) if s.is_empty() => Vec::new(),
+        serde_json::Value::String(s) => vec![serde_json::json!({"type": "text", "text": s})],
+        _ => block_slice(content).to_vec(),
+    }
+}
+
+/// 消息内容中的内容块（单个内容块对象视为只有一个元素的数组，字符串内容没有内容块）
+fn block_slice(content: &serde_json::Value) -> &[serde_json::Value] {
+    match content {
+        serde_json::Value::Array(blocks) => blocks,
+        serde_json::Value::Object(_) => std::slice::from_ref(content),
+        _ => &[],
+    }
+}
+
 /// 合并多个 user 消息
 fn merge_user_messages(
     messages: &[&super::types::Message],
@@ -428,8 +882,12 @@ fn merge_user_messages(
         all_tool_results.extend(tool_results);
     }
 
-    let content = content_parts.join("\n");
-    // 保留文本内容，即使有工具结果也不丢弃用户文本
+    let mut content = content_parts.join("\n");
+    // 保留文本内容，即使有工具结果也不丢弃用户文本；没有文本时使用占位内容
+    if content.is_empty() {
+        content =
+            user_placeholder(!all_tool_results.is_empty(), !all_images.is_empty()).to_string();
+    }
     let mut user_msg = UserMessage::new(&content, model_id);
 
     if !all_images.is_empty() {
@@ -447,6 +905,17 @@ fn merge_user_messages(
     })
 }
 
+/// 没有可见文本的 user 消息使用的占位内容
+fn user_placeholder(has_tool_results: bool, has_images: bool) -> &'static str {
+    if has_tool_results {
+        TOOL_RESULTS_PLACEHOLDER
+    } else if has_images {
+        IMAGES_PLACEHOLDER
+    } else {
+        EMPTY_USER_PLACEHOLDER
+    }
+}
+
 /// 转换 assistant 消息
 fn convert_assistant_message(
     msg: &super::types::Message,
@@ -459,9 +928,9 @@ fn convert_assistant_message(
         serde_json::Value::String(s) => {
             text_content = s.clone();
         }
-        serde_json::Value::Array(arr) => {
-            for item in arr {
-                if let Ok(block) = serde_json::from_value::<ContentBlock>(item.clone()) {
+        content => {
+            for item in block_slice(content) {
+                if let Some(InputBlock::Known(block)) = InputBlock::from_value(item) {
                     match block.block_type.as_str() {
                         "thinking" => {
                             if let Some(thinking) = block.thinking {
@@ -491,7 +960,6 @@ fn convert_assistant_message(
                 }
             }
         }
-        _ => {}
     }
 
     // 组合 thinking 和 text 内容
@@ -505,6 +973,13 @@ fn convert_assistant_message(
         } else {
             format!("<thinking>{}</thinking>", thinking_content)
         }
+    } else if text_content.trim().is_empty() {
+        // 只有 tool_use（或内容为空）的 assistant 回合
+        if tool_uses.is_empty() {
+            EMPTY_ASSISTANT_PLACEHOLDER.to_string()
+        } else {
+            TOOL_USES_PLACEHOLDER.to_string()
+        }
     } else {
         text_content
     };
@@ -664,4 +1139,555 @@ mod tests {
             _ => panic!("expected assistant message"),
         }
     }
+
+    fn tool_result_request(batches: Vec<serde_json::Value>) -> MessagesRequest {
+        let mut messages = vec![types::Message {
+            role: "user".to_string(),
+            content: json!("run tools"),
+        }];
+        for batch in batches {
+            messages.push(types::Message {
+                role: "assistant".to_string(),
+                content: json!([{
+                    "type": "tool_use",
+                    "id": "toolu_x",
+                    "name": "bash",
+                    "input": {}
+                }]),
+            });
+            messages.push(types::Message {
+                role: "user".to_string(),
+                content: batch,
+            });
+        }
+        MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            stream: false,
+            system: None,
+            tools: None,
+            tool_choice: None,
+            thinking: None,
+            messages,
+        }
+    }
+
+    fn mixed_tool_results() -> serde_json::Value {
+        json!([
+            {"type": "tool_result", "tool_use_id": "toolu_ok", "content": "done"},
+            {
+                "type": "tool_result",
+                "tool_use_id": "toolu_err",
+                "content": [{"type": "text", "text": "permission denied"}],
+                "is_error": true
+            },
+            {"type": "tool_result", "tool_use_id": "toolu_empty", "is_error": "true"}
+        ])
+    }
+
+    fn assert_mixed_tool_results(results: &[ToolResult]) {
+        let value = serde_json::to_value(results).unwrap();
+        assert_eq!(value[0]["toolUseId"], "toolu_ok");
+        assert_eq!(value[0]["status"], "success");
+        assert!(value[0].get("isError").is_none());
+
+        assert_eq!(value[1]["status"], "error");
+        assert_eq!(value[1]["isError"], true);
+        assert_eq!(value[1]["content"][0]["text"], "permission denied");
+
+        // 没有内容的错误结果也要让模型知道执行失败
+        assert_eq!(value[2]["status"], "error");
+        assert_eq!(value[2]["isError"], true);
+        assert_eq!(value[2]["content"][0]["text"], "Tool execution failed");
+    }
+
+    #[test]
+    fn test_mixed_tool_results_in_current_message_keep_error_status() {
+        let req = tool_result_request(vec![mixed_tool_results()]);
+        let res = convert_request(&req).unwrap();
+
+        assert_mixed_tool_results(
+            &res.conversation_state
+                .current_message
+                .user_input_message
+                .user_input_message_context
+                .tool_results,
+        );
+    }
+
+    #[test]
+    fn test_mixed_tool_results_in_history_keep_error_status() {
+        let req = tool_result_request(vec![mixed_tool_results(), json!("next step")]);
+        let res = convert_request(&req).unwrap();
+
+        let results = res
+            .conversation_state
+            .history
+            .iter()
+            .find_map(|msg| match msg {
+                crate::kiro::model::requests::conversation::Message::User(u) => {
+                    let results = &u.user_input_message.user_input_message_context.tool_results;
+                    (!results.is_empty()).then(|| results.clone())
+                }
+                _ => None,
+            })
+            .expect("history should contain tool results");
+        assert_mixed_tool_results(&results);
+    }
+
+    #[test]
+    fn test_decode_text_document() {
+        let source = ImageSource {
+            source_type: "base64".to_string(),
+            media_type: "text/plain".to_string(),
+            data: "aGVsbG8=".to_string(),
+        };
+        assert_eq!(decode_text_document(&source), Some("hello".to_string()));
+
+        let pdf = ImageSource {
+            source_type: "base64".to_string(),
+            media_type: "application/pdf".to_string(),
+            data: "aGVsbG8=".to_string(),
+        };
+        assert_eq!(decode_text_document(&pdf), None);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_file_references() {
+        let dir = std::env::temp_dir().join(format!("kiro-files-{}", Uuid::new_v4()));
+        let store = FileStore::new(&dir);
+        let metadata = store.save("a.png", "image/png", b"png").await.unwrap();
+
+        let mut messages = vec![types::Message {
+            role: "user".to_string(),
+            content: json!([
+                {"type": "image", "source": {"type": "file", "file_id": metadata.id}},
+                {"type": "text", "text": "describe"}
+            ]),
+        }];
+        resolve_file_references(&mut messages, &store)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            messages[0].content[0]["source"],
+            json!({"type": "base64", "media_type": "image/png", "data": "cG5n"})
+        );
+
+        let mut missing = vec![types::Message {
+            role: "user".to_string(),
+            content: json!([{"type": "image", "source": {"type": "file", "file_id": "file_missing"}}]),
+        }];
+        assert!(matches!(
+            resolve_file_references(&mut missing, &store).await,
+            Err(ConversionError::FileNotFound(_))
+        ));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_inject_system_prompt() {
+        let mut req: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "hi"}],
+            "system": [{"type": "text", "text": "client"}]
+        }))
+        .unwrap();
+
+        inject_system_prompt(&mut req, "house rules", SystemPromptPosition::Prepend);
+        inject_system_prompt(&mut req, "footer", SystemPromptPosition::Append);
+        inject_system_prompt(&mut req, "  ", SystemPromptPosition::Append);
+
+        let texts: Vec<&str> = req
+            .system
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["house rules", "client", "footer"]);
+
+        let mut req: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        inject_system_prompt(&mut req, "house rules", SystemPromptPosition::Append);
+        assert_eq!(req.system.unwrap()[0].text, "house rules");
+    }
+
+    /// Claude Code 常见的交错模式：assistant 文本与 tool_use 拆成两条消息，
+    /// tool_result 之后追加 system-reminder 作为单独的 user 消息
+    fn interleaved_request() -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "messages": [
+                {"role": "user", "content": "fix the build"},
+                {"role": "assistant", "content": "Let me look."},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "ok"}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "<system-reminder>todo list</system-reminder>"}
+                ]},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t2", "name": "bash", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t2", "content": "built"}
+                ]},
+                {"role": "user", "content": "now run the tests"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_consecutive_same_role_messages_are_merged() {
+        let state = convert_request(&interleaved_request())
+            .unwrap()
+            .conversation_state;
+
+        let roles: Vec<&str> = state
+            .history
+            .iter()
+            .map(|m| match m {
+                Message::User(_) => "user",
+                Message::Assistant(_) => "assistant",
+            })
+            .collect();
+        assert_eq!(roles, vec!["user", "assistant", "user", "assistant"]);
+
+        // 拆开的 assistant 文本和 tool_use 合并为一条，后一条不再被丢弃
+        let Message::Assistant(first) = &state.history[1] else {
+            panic!("expected assistant message");
+        };
+        let first = &first.assistant_response_message;
+        assert_eq!(first.content, "Let me look.\n");
+        assert_eq!(first.tool_uses.as_ref().unwrap()[0].tool_use_id, "t1");
+
+        // tool_result 与随后的 system-reminder 合并为一条 user 消息
+        let Message::User(results) = &state.history[2] else {
+            panic!("expected user message");
+        };
+        let results = &results.user_input_message;
+        assert!(results.content.contains("system-reminder"));
+        assert_eq!(
+            results.user_input_message_context.tool_results[0].tool_use_id,
+            "t1"
+        );
+
+        // 末尾的 tool_result 与用户文本合并为 current_message
+        let current = &state.current_message.user_input_message;
+        assert_eq!(current.content, "now run the tests");
+        assert_eq!(
+            current.user_input_message_context.tool_results[0].tool_use_id,
+            "t2"
+        );
+    }
+
+    #[test]
+    fn test_merge_consecutive_roles() {
+        let merged = merge_consecutive_roles(&interleaved_request().messages);
+        let roles: Vec<&str> = merged.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(
+            roles,
+            vec!["user", "assistant", "user", "assistant", "user"]
+        );
+        assert_eq!(merged[4].content.as_array().unwrap().len(), 2);
+    }
+
+    fn request_with_tools(names: &[&str]) -> MessagesRequest {
+        let tools: Vec<_> = names
+            .iter()
+            .map(|name| json!({"name": name, "description": "", "input_schema": {}}))
+            .collect();
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "messages": [
+                {"role": "user", "content": "please grep the logs"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "ok"}
+                ]}
+            ],
+            "tools": tools
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_tool_limits_rejects_without_pruning() {
+        let mut req = request_with_tools(&["deploy", "format", "read"]);
+        let limits = ToolLimits {
+            max_tools: Some(2),
+            ..Default::default()
+        };
+        match apply_tool_limits(&mut req, &limits) {
+            Err(ConversionError::ToolLimitExceeded { limit, actual, max }) => {
+                assert_eq!((limit, actual, max), ("maxTools", 3, 2));
+            }
+            other => panic!("应超出 maxTools 限制: {:?}", other),
+        }
+
+        let limits = ToolLimits {
+            max_bytes: Some(10),
+            ..Default::default()
+        };
+        assert!(matches!(
+            apply_tool_limits(&mut req, &limits),
+            Err(ConversionError::ToolLimitExceeded {
+                limit: "maxToolsBytes",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_apply_tool_limits_prunes_by_relevance() {
+        let mut req = request_with_tools(&["deploy", "grep", "format", "read"]);
+        let limits = ToolLimits {
+            max_tools: Some(2),
+            prune: true,
+            ..Default::default()
+        };
+        apply_tool_limits(&mut req, &limits).unwrap();
+
+        let names: Vec<&str> = req
+            .tools
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["grep", "read"]);
+
+        let mut req = request_with_tools(&["deploy", "read"]);
+        let limits = ToolLimits {
+            max_tools: Some(0),
+            prune: true,
+            ..Default::default()
+        };
+        assert!(apply_tool_limits(&mut req, &limits).is_err());
+    }
+
+    #[test]
+    fn test_truncate_tool_results() {
+        let output = ["a".repeat(5000), "é".repeat(1000), "z".repeat(100)].concat();
+        let mut req: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "messages": [
+                {"role": "user", "content": "run it"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t1", "name": "shell", "input": {}},
+                    {"type": "tool_use", "id": "t2", "name": "shell", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": [
+                        {"type": "text", "text": output}
+                    ]},
+                    {"type": "tool_result", "tool_use_id": "t2", "content": "short"}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let mut limits = ToolLimits::default();
+        assert_eq!(
+            truncate_tool_results(&mut req, &limits),
+            ToolResultTruncation::default()
+        );
+
+        limits.max_result_bytes = Some(1024);
+        limits.result_tail_bytes = 200;
+        let truncation = truncate_tool_results(&mut req, &limits);
+        assert_eq!(truncation.results, 1);
+
+        let content = req.messages[2].content[0]["content"].as_str().unwrap();
+        assert!(content.len() <= 1024);
+        assert!(content.starts_with("aaa"));
+        assert!(content.ends_with(&format!("éé{}", "z".repeat(100))));
+        assert!(content.contains(&format!("[... {} bytes truncated ...]", truncation.bytes)));
+        assert_eq!(req.messages[2].content[1]["content"], "short");
+    }
+
+    #[test]
+    fn test_bare_object_content() {
+        let req: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "messages": [
+                {"role": "user", "content": {"type": "text", "text": "first"}},
+                {"role": "assistant", "content": {"type": "text", "text": "reply"}},
+                {"role": "user", "content": {"type": "text", "text": "second"}}
+            ]
+        }))
+        .unwrap();
+        assert!(req.messages[0].content.is_array());
+
+        let result = convert_request(&req).unwrap();
+        let state = &result.conversation_state;
+        assert_eq!(state.current_message.user_input_message.content, "second");
+        let history = serde_json::to_value(&state.history).unwrap();
+        assert_eq!(history[0]["userInputMessage"]["content"], "first");
+        assert_eq!(history[1]["assistantResponseMessage"]["content"], "reply");
+    }
+
+    fn request_from_messages(messages: serde_json::Value) -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 100,
+            "messages": messages
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_empty_and_whitespace_content_use_placeholder() {
+        for content in [
+            json!(""),
+            json!("  \n\t"),
+            json!([{"type": "text", "text": " "}]),
+        ] {
+            let req = request_from_messages(json!([
+                {"role": "user", "content": content.clone()},
+                {"role": "assistant", "content": ""},
+                {"role": "user", "content": content}
+            ]));
+            let result = convert_request(&req).unwrap();
+            let state = &result.conversation_state;
+            // assistant 之后的空 user 回合视为续写请求
+            assert_eq!(
+                state.current_message.user_input_message.content,
+                CONTINUATION_PROMPT
+            );
+            let history = serde_json::to_value(&state.history).unwrap();
+            assert_eq!(
+                history[0]["userInputMessage"]["content"],
+                EMPTY_USER_PLACEHOLDER
+            );
+            assert_eq!(
+                history[1]["assistantResponseMessage"]["content"],
+                EMPTY_ASSISTANT_PLACEHOLDER
+            );
+        }
+    }
+
+    #[test]
+    fn test_truncated_assistant_continuation() {
+        // 截断的 assistant 消息在末尾，或其后跟一个空的 user 回合
+        for messages in [
+            json!([
+                {"role": "user", "content": "Write a story"},
+                {"role": "assistant", "content": "Once upon a"}
+            ]),
+            json!([
+                {"role": "user", "content": "Write a story"},
+                {"role": "assistant", "content": "Once upon a"},
+                {"role": "user", "content": ""}
+            ]),
+        ] {
+            let result = convert_request(&request_from_messages(messages)).unwrap();
+            let state = &result.conversation_state;
+            assert_eq!(
+                state.current_message.user_input_message.content,
+                CONTINUATION_PROMPT
+            );
+            let history = serde_json::to_value(&state.history).unwrap();
+            assert_eq!(history.as_array().unwrap().len(), 2);
+            assert_eq!(
+                history[1]["assistantResponseMessage"]["content"],
+                "Once upon a"
+            );
+        }
+
+        // 只有空 user 消息的新对话不是续写
+        let result = convert_request(&request_from_messages(json!([
+            {"role": "user", "content": ""}
+        ])))
+        .unwrap();
+        let current = &result.conversation_state.current_message;
+        assert_eq!(current.user_input_message.content, EMPTY_USER_PLACEHOLDER);
+    }
+
+    #[test]
+    fn test_whitespace_text_blocks_are_dropped() {
+        let req = request_from_messages(json!([{
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "\n\n"},
+                {"type": "text", "text": "hello"},
+                {"type": "text", "text": "  "}
+            ]
+        }]));
+        let result = convert_request(&req).unwrap();
+        assert_eq!(
+            result
+                .conversation_state
+                .current_message
+                .user_input_message
+                .content,
+            "hello"
+        );
+    }
+
+    #[test]
+    fn test_tool_only_turns_use_placeholder() {
+        let req = tool_result_request(vec![
+            json!([{"type": "tool_result", "tool_use_id": "toolu_x", "content": "a"}]),
+            json!([
+                {"type": "tool_result", "tool_use_id": "toolu_x", "content": "b"},
+                {"type": "text", "text": " "}
+            ]),
+        ]);
+        let result = convert_request(&req).unwrap();
+        let state = &result.conversation_state;
+
+        let current = &state.current_message.user_input_message;
+        assert_eq!(current.content, TOOL_RESULTS_PLACEHOLDER);
+        assert_eq!(current.user_input_message_context.tool_results.len(), 1);
+
+        let history = serde_json::to_value(&state.history).unwrap();
+        assert_eq!(
+            history[1]["assistantResponseMessage"]["content"],
+            TOOL_USES_PLACEHOLDER
+        );
+        assert_eq!(
+            history[2]["userInputMessage"]["content"],
+            TOOL_RESULTS_PLACEHOLDER
+        );
+        assert_eq!(
+            history[2]["userInputMessage"]["userInputMessageContext"]["toolResults"][0]
+                ["toolUseId"],
+            "toolu_x"
+        );
+    }
+
+    #[test]
+    fn test_invalid_content_shapes() {
+        let parse = |content: serde_json::Value| {
+            serde_json::from_value::<MessagesRequest>(json!({
+                "model": "claude-sonnet-4",
+                "max_tokens": 100,
+                "messages": [{"role": "user", "content": content}]
+            }))
+            .unwrap_err()
+            .to_string()
+        };
+        assert!(parse(json!(42)).contains("实际为 number"));
+        assert!(parse(json!(null)).contains("实际为 null"));
+        assert!(parse(json!({"text": "hi"})).contains("缺少字符串类型的 type 字段"));
+        assert!(parse(json!(["hi"])).contains("content[0] 必须是内容块对象，实际为 string"));
+    }
 }
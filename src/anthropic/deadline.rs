@@ -0,0 +1,220 @@
+//! 请求截止时间
+//!
+//! 客户端可以通过 `x-request-deadline-ms` 请求头限制一次请求的总耗时（毫秒），包括排队、
+//! 内置工具轮次和续写轮次；未指定时使用托管 API Key、工作区或全局配置的 `maxRequestSecs`，
+//! 两者同时存在时取较早者。剩余时间不足以完成下一次上游调用（按已完成调用的最长耗时估算）
+//! 时不再发起新的调用，超时则返回 504 `timeout_error`，错误信息中附带请求已进行到的阶段。
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use tokio::time::Instant;
+
+use super::types::ErrorResponse;
+
+/// 请求头名称
+pub const HEADER: &str = "x-request-deadline-ms";
+
+/// 截止时间的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DeadlineSource {
+    /// `x-request-deadline-ms` 请求头
+    Header,
+    /// `maxRequestSecs` 配置
+    Config,
+}
+
+/// 请求截止时间
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    at: Instant,
+    budget: Duration,
+    source: DeadlineSource,
+}
+
+impl Deadline {
+    /// 解析 `x-request-deadline-ms` 请求头，值不是正整数时返回错误信息
+    pub fn requested(headers: &HeaderMap) -> Result<Option<Duration>, String> {
+        let Some(value) = headers.get(HEADER) else {
+            return Ok(None);
+        };
+        value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .filter(|&ms| ms > 0)
+            .map(|ms| Some(Duration::from_millis(ms)))
+            .ok_or_else(|| format!("{} 必须是正整数（毫秒）", HEADER))
+    }
+
+    /// 从请求开始时间计算截止时间，请求头和配置都未限制时为 None
+    pub fn resolve(
+        start: std::time::Instant,
+        requested: Option<Duration>,
+        configured: Option<Duration>,
+    ) -> Option<Self> {
+        let (budget, source) = match (requested, configured) {
+            (Some(r), Some(c)) if c < r => (c, DeadlineSource::Config),
+            (Some(r), _) => (r, DeadlineSource::Header),
+            (None, Some(c)) => (c, DeadlineSource::Config),
+            (None, None) => return None,
+        };
+        Some(Self {
+            at: Instant::from_std(start) + budget,
+            budget,
+            source,
+        })
+    }
+
+    pub fn at(&self) -> Instant {
+        self.at
+    }
+
+    /// 剩余时间是否足以完成一次预计耗时为 `estimate` 的上游调用
+    pub fn allows(&self, estimate: Duration) -> bool {
+        let remaining = self.at.saturating_duration_since(Instant::now());
+        !remaining.is_zero() && remaining > estimate
+    }
+
+    /// 超过截止时间的 504 timeout_error 响应，错误信息中附带请求进度
+    pub fn timeout_response(&self, progress: &Progress) -> Response {
+        let limit = match self.source {
+            DeadlineSource::Header => {
+                format!(
+                    "the deadline of {}ms set by {}",
+                    self.budget.as_millis(),
+                    HEADER
+                )
+            }
+            DeadlineSource::Config => {
+                format!("the maximum duration of {}s", self.budget.as_secs())
+            }
+        };
+        let message = format!("Request exceeded {} ({})", limit, progress.summary());
+        tracing::warn!("{}", message);
+        (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(ErrorResponse::new("timeout_error", message)),
+        )
+            .into_response()
+    }
+}
+
+/// 请求处理阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Stage {
+    /// 等待执行名额
+    #[default]
+    Queued,
+    /// 已选定账号，转换请求
+    Preparing,
+    /// 内置工具循环
+    ServerTools,
+    /// 等待上游响应
+    Upstream,
+    /// 续写截断的响应
+    Continuation,
+}
+
+impl Stage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Preparing => "preparing",
+            Self::ServerTools => "server_tools",
+            Self::Upstream => "upstream",
+            Self::Continuation => "continuation",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProgressState {
+    stage: Stage,
+    account: Option<String>,
+    upstream_calls: u32,
+    slowest_call: Duration,
+}
+
+/// 请求进度，超时时作为诊断信息返回
+#[derive(Debug, Default)]
+pub struct Progress {
+    state: Mutex<ProgressState>,
+}
+
+impl Progress {
+    pub fn stage(&self, stage: Stage) {
+        self.state.lock().unwrap().stage = stage;
+    }
+
+    pub fn account(&self, name: &str) {
+        self.state.lock().unwrap().account = Some(name.to_string());
+    }
+
+    /// 记录一次已完成的上游调用
+    pub fn record_call(&self, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.upstream_calls += 1;
+        state.slowest_call = state.slowest_call.max(elapsed);
+    }
+
+    /// 下一次上游调用的预计耗时（已完成调用的最长耗时）
+    pub fn estimate(&self) -> Duration {
+        self.state.lock().unwrap().slowest_call
+    }
+
+    fn summary(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut summary = format!(
+            "stage: {}, upstream calls completed: {}",
+            state.stage.as_str(),
+            state.upstream_calls
+        );
+        if let Some(account) = &state.account {
+            summary.push_str(&format!(", account: {}", account));
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_deadline_resolution_and_budget() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Deadline::requested(&headers), Ok(None));
+        headers.insert(HEADER, "1500".parse().unwrap());
+        let requested = Deadline::requested(&headers).unwrap();
+        assert_eq!(requested, Some(Duration::from_millis(1500)));
+        headers.insert(HEADER, "0".parse().unwrap());
+        assert!(Deadline::requested(&headers).is_err());
+
+        let start = std::time::Instant::now();
+        assert!(Deadline::resolve(start, None, None).is_none());
+        // 较早的截止时间生效
+        let deadline = Deadline::resolve(start, requested, Some(Duration::from_secs(60))).unwrap();
+        assert_eq!(deadline.source, DeadlineSource::Header);
+        let deadline = Deadline::resolve(start, requested, Some(Duration::from_secs(1))).unwrap();
+        assert_eq!(deadline.source, DeadlineSource::Config);
+
+        assert!(deadline.allows(Duration::from_millis(100)));
+        assert!(!deadline.allows(Duration::from_secs(5)));
+
+        let progress = Progress::default();
+        progress.account("acc-1");
+        progress.stage(Stage::Upstream);
+        progress.record_call(Duration::from_millis(300));
+        progress.record_call(Duration::from_millis(200));
+        assert_eq!(progress.estimate(), Duration::from_millis(300));
+        assert_eq!(
+            progress.summary(),
+            "stage: upstream, upstream calls completed: 2, account: acc-1"
+        );
+        let response = deadline.timeout_response(&progress);
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+    }
+}
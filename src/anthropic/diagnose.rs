@@ -0,0 +1,310 @@
+//! 上游请求格式错误诊断
+//!
+//! Kiro 校验请求失败时只返回笼统的 "Improperly formed request"。按常见原因检查转换后的
+//! Kiro 请求（工具定义过大、内容为空、图片格式不支持），给出最可能出错的字段。
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::Value;
+
+/// 上游请求格式错误的标志文本
+const MALFORMED_MARKER: &str = "Improperly formed request";
+
+/// 工具描述的最大字符数
+const MAX_TOOL_DESCRIPTION_CHARS: usize = 10_000;
+
+/// 工具 input_schema 序列化后的最大字节数
+const MAX_TOOL_SCHEMA_BYTES: usize = 32 * 1024;
+
+/// Kiro 支持的图片格式
+const IMAGE_FORMATS: &[&str] = &["png", "jpeg", "gif", "webp"];
+
+/// 日志中保留的字符串长度，超出部分截断
+const LOG_STRING_CHARS: usize = 200;
+
+/// 上游错误是否为请求格式错误
+pub fn is_malformed_request(error: &str) -> bool {
+    error.contains(MALFORMED_MARKER)
+}
+
+/// 可能导致格式错误的字段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suspect {
+    /// 字段路径（Kiro 请求中的 JSON 路径）
+    pub field: String,
+    pub reason: String,
+}
+
+/// 按可能性从高到低列出请求中可疑的字段
+pub fn diagnose(request_body: &str) -> Vec<Suspect> {
+    let Ok(request) = serde_json::from_str::<Value>(request_body) else {
+        return Vec::new();
+    };
+    let state = &request["conversationState"];
+    let mut tools = Vec::new();
+    let mut contents = Vec::new();
+    let mut images = Vec::new();
+
+    let current = "conversationState.currentMessage.userInputMessage";
+    let mut user_messages = vec![(
+        current.to_string(),
+        &state["currentMessage"]["userInputMessage"],
+    )];
+    for (i, message) in state["history"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let path = format!("conversationState.history[{}]", i);
+        if let Some(user) = message.get("userInputMessage") {
+            user_messages.push((format!("{}.userInputMessage", path), user));
+        } else if let Some(assistant) = message.get("assistantResponseMessage") {
+            let has_tool_uses = assistant["toolUses"]
+                .as_array()
+                .is_some_and(|t| !t.is_empty());
+            if is_blank(&assistant["content"]) && !has_tool_uses {
+                contents.push(Suspect {
+                    field: format!("{}.assistantResponseMessage.content", path),
+                    reason: "助手消息内容为空".to_string(),
+                });
+            }
+        }
+    }
+
+    for (path, message) in user_messages {
+        let context = &message["userInputMessageContext"];
+        for (i, tool) in context["tools"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            check_tool(
+                &format!("{}.userInputMessageContext.tools[{}]", path, i),
+                tool,
+                &mut tools,
+            );
+        }
+        let has_tool_results = context["toolResults"]
+            .as_array()
+            .is_some_and(|r| !r.is_empty());
+        let message_images = message["images"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        if is_blank(&message["content"]) && !has_tool_results && message_images.is_empty() {
+            contents.push(Suspect {
+                field: format!("{}.content", path),
+                reason: "用户消息内容为空".to_string(),
+            });
+        }
+        for (i, image) in message_images.iter().enumerate() {
+            check_image(&format!("{}.images[{}]", path, i), image, &mut images);
+        }
+    }
+
+    tools.into_iter().chain(contents).chain(images).collect()
+}
+
+fn is_blank(value: &Value) -> bool {
+    value.as_str().is_none_or(|s| s.trim().is_empty())
+}
+
+fn check_tool(path: &str, tool: &Value, suspects: &mut Vec<Suspect>) {
+    let spec = &tool["toolSpecification"];
+    let name = spec["name"].as_str().unwrap_or_default();
+    let description_chars = spec["description"]
+        .as_str()
+        .map_or(0, |d| d.chars().count());
+    if description_chars > MAX_TOOL_DESCRIPTION_CHARS {
+        suspects.push(Suspect {
+            field: format!("{}.toolSpecification.description", path),
+            reason: format!(
+                "工具 {} 的描述过长（{} 字符，建议不超过 {}）",
+                name, description_chars, MAX_TOOL_DESCRIPTION_CHARS
+            ),
+        });
+    }
+    let schema_bytes = serde_json::to_string(&spec["inputSchema"]).map_or(0, |s| s.len());
+    if schema_bytes > MAX_TOOL_SCHEMA_BYTES {
+        suspects.push(Suspect {
+            field: format!("{}.toolSpecification.inputSchema", path),
+            reason: format!(
+                "工具 {} 的 input_schema 过大（{} 字节，建议不超过 {}）",
+                name, schema_bytes, MAX_TOOL_SCHEMA_BYTES
+            ),
+        });
+    }
+}
+
+fn check_image(path: &str, image: &Value, suspects: &mut Vec<Suspect>) {
+    let format = image["format"].as_str().unwrap_or_default();
+    if !IMAGE_FORMATS.contains(&format) {
+        suspects.push(Suspect {
+            field: format!("{}.format", path),
+            reason: format!(
+                "图片格式不支持: {}（支持 {}）",
+                format,
+                IMAGE_FORMATS.join(", ")
+            ),
+        });
+    }
+    let bytes = image["source"]["bytes"].as_str().unwrap_or_default();
+    if bytes.is_empty() || STANDARD.decode(bytes).is_err() {
+        suspects.push(Suspect {
+            field: format!("{}.source.bytes", path),
+            reason: "图片数据为空或不是有效的 base64".to_string(),
+        });
+    }
+}
+
+/// 返回给客户端的错误信息
+pub fn error_message(suspects: &[Suspect]) -> String {
+    match suspects.first() {
+        Some(suspect) => format!(
+            "上游拒绝了格式不正确的请求，最可能的原因: {}（{}）",
+            suspect.reason, suspect.field
+        ),
+        None => "上游拒绝了格式不正确的请求，未能定位具体字段".to_string(),
+    }
+}
+
+/// 脱敏后的请求体，用于调试日志：隐藏 profileArn 和图片数据，截断过长的字符串
+pub fn redact(request_body: &str) -> String {
+    match serde_json::from_str::<Value>(request_body) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => "[无法解析的请求体]".to_string(),
+    }
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match (key.as_str(), &*value) {
+                    ("profileArn", Value::String(_)) => *value = "[REDACTED]".into(),
+                    ("bytes", Value::String(s)) => *value = format!("[{} bytes]", s.len()).into(),
+                    _ => redact_value(value),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        Value::String(s) if s.chars().count() > LOG_STRING_CHARS => {
+            let total = s.chars().count();
+            let kept: String = s.chars().take(LOG_STRING_CHARS).collect();
+            *s = format!("{}…（共 {} 字符）", kept, total);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(current: Value, history: Value) -> String {
+        json!({
+            "conversationState": {
+                "conversationId": "c",
+                "currentMessage": {"userInputMessage": current},
+                "history": history
+            },
+            "profileArn": "arn:aws:codewhisperer:us-east-1:123:profile/abc"
+        })
+        .to_string()
+    }
+
+    fn fields(body: &str) -> Vec<String> {
+        diagnose(body).into_iter().map(|s| s.field).collect()
+    }
+
+    #[test]
+    fn test_is_malformed_request() {
+        assert!(is_malformed_request(
+            r#"API 请求失败: 400 Bad Request {"message":"Improperly formed request.","reason":null}"#
+        ));
+        assert!(!is_malformed_request("API 请求失败: 429 Too Many Requests"));
+    }
+
+    #[test]
+    fn test_diagnose_oversized_tool_schema_first() {
+        let schema = json!({"json": {"type": "object", "description": "x".repeat(40_000)}});
+        let body = request(
+            json!({
+                "content": "",
+                "modelId": "m",
+                "userInputMessageContext": {
+                    "tools": [
+                        {"toolSpecification": {"name": "ok", "description": "d", "inputSchema": {"json": {}}}},
+                        {"toolSpecification": {"name": "big", "description": "d", "inputSchema": schema}}
+                    ]
+                }
+            }),
+            json!([]),
+        );
+        assert_eq!(
+            fields(&body),
+            vec![
+                "conversationState.currentMessage.userInputMessage.userInputMessageContext.tools[1].toolSpecification.inputSchema",
+                "conversationState.currentMessage.userInputMessage.content",
+            ]
+        );
+        assert!(error_message(&diagnose(&body)).contains("工具 big 的 input_schema 过大"));
+    }
+
+    #[test]
+    fn test_diagnose_empty_content_and_images() {
+        let body = request(
+            json!({
+                "content": "look",
+                "modelId": "m",
+                "images": [{"format": "bmp", "source": {"bytes": "aGk="}}],
+                "userInputMessageContext": {}
+            }),
+            json!([
+                {"userInputMessage": {"content": "hi", "modelId": "m", "userInputMessageContext": {}}},
+                {"assistantResponseMessage": {"content": " "}}
+            ]),
+        );
+        assert_eq!(
+            fields(&body),
+            vec![
+                "conversationState.history[1].assistantResponseMessage.content",
+                "conversationState.currentMessage.userInputMessage.images[0].format",
+            ]
+        );
+
+        let clean = request(
+            json!({"content": "hi", "modelId": "m", "userInputMessageContext": {}}),
+            json!([]),
+        );
+        assert!(diagnose(&clean).is_empty());
+        assert_eq!(
+            error_message(&[]),
+            "上游拒绝了格式不正确的请求，未能定位具体字段"
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let body = request(
+            json!({
+                "content": "y".repeat(500),
+                "images": [{"format": "png", "source": {"bytes": "aGVsbG8="}}]
+            }),
+            json!([]),
+        );
+        let redacted: Value = serde_json::from_str(&redact(&body)).unwrap();
+        assert_eq!(redacted["profileArn"], "[REDACTED]");
+        let message = &redacted["conversationState"]["currentMessage"]["userInputMessage"];
+        assert_eq!(message["images"][0]["source"]["bytes"], "[8 bytes]");
+        assert!(message["content"]
+            .as_str()
+            .unwrap()
+            .ends_with("（共 500 字符）"));
+    }
+}
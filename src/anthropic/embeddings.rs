@@ -0,0 +1,163 @@
+//! OpenAI 兼容的 embeddings 端点
+//!
+//! Kiro 上游不提供 embeddings，`POST /v1/embeddings` 只做转发：
+//! 配置了外部服务时原样转发请求和响应，否则返回 OpenAI 格式的不支持错误，
+//! 让启动时探测 embeddings 能力的客户端得到明确结果而不是 404。
+
+use axum::{
+    body::Body,
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::http_client::{build_client, ProxyConfig};
+use crate::model::config::Config;
+
+/// 外部 embeddings 服务请求超时（秒）
+const EMBEDDINGS_TIMEOUT_SECS: u64 = 60;
+
+/// 外部 embeddings 服务转发器
+pub struct EmbeddingsProxy {
+    url: String,
+    api_key: Option<String>,
+    model: Option<String>,
+    client: Client,
+}
+
+impl EmbeddingsProxy {
+    /// 从全局配置创建，未配置服务地址时返回 None
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let url = config.embeddings_url.clone()?;
+        let proxy = ProxyConfig::from_config(config);
+        let client = match build_client(proxy.as_ref(), EMBEDDINGS_TIMEOUT_SECS) {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("创建 embeddings HTTP 客户端失败: {}", e);
+                return None;
+            }
+        };
+        Some(Self {
+            url,
+            api_key: config.embeddings_api_key.clone(),
+            model: config.embeddings_model.clone(),
+            client,
+        })
+    }
+
+    /// 转发请求，返回上游的状态码和响应体
+    pub async fn forward(&self, body: Value) -> Response {
+        let mut request = self.client.post(&self.url).json(&self.prepare_body(body));
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::error!("embeddings 服务调用失败: {}", e);
+                return openai_error(
+                    StatusCode::BAD_GATEWAY,
+                    "api_error",
+                    None,
+                    format!("embeddings 服务调用失败: {}", e),
+                );
+            }
+        };
+
+        let status =
+            StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+        match response.bytes().await {
+            Ok(bytes) => (
+                status,
+                [(header::CONTENT_TYPE, "application/json")],
+                Body::from(bytes),
+            )
+                .into_response(),
+            Err(e) => openai_error(
+                StatusCode::BAD_GATEWAY,
+                "api_error",
+                None,
+                format!("读取 embeddings 响应失败: {}", e),
+            ),
+        }
+    }
+
+    /// 按配置覆盖模型名
+    fn prepare_body(&self, mut body: Value) -> Value {
+        if let (Some(model), Some(obj)) = (&self.model, body.as_object_mut()) {
+            obj.insert("model".to_string(), json!(model));
+        }
+        body
+    }
+}
+
+/// 未配置外部服务时返回的错误
+pub fn embeddings_not_supported() -> Response {
+    openai_error(
+        StatusCode::NOT_IMPLEMENTED,
+        "invalid_request_error",
+        Some("embeddings_not_supported"),
+        "Embeddings are not supported by this proxy; configure embeddingsUrl to forward them to an external provider",
+    )
+}
+
+/// OpenAI 格式的错误响应
+fn openai_error(
+    status: StatusCode,
+    error_type: &str,
+    code: Option<&str>,
+    message: impl Into<String>,
+) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": {
+                "message": message.into(),
+                "type": error_type,
+                "param": null,
+                "code": code,
+            }
+        })),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_requires_url() {
+        assert!(EmbeddingsProxy::from_config(&Config::default()).is_none());
+    }
+
+    #[test]
+    fn test_prepare_body_overrides_model() {
+        let config = Config {
+            embeddings_url: Some("https://api.example.com/v1/embeddings".to_string()),
+            embeddings_model: Some("text-embedding-3-small".to_string()),
+            ..Config::default()
+        };
+        let proxy = EmbeddingsProxy::from_config(&config).unwrap();
+        let body = proxy.prepare_body(json!({"model": "claude-sonnet-4", "input": "hi"}));
+
+        assert_eq!(body["model"], "text-embedding-3-small");
+        assert_eq!(body["input"], "hi");
+    }
+
+    #[tokio::test]
+    async fn test_not_supported_is_openai_error() {
+        let response = embeddings_not_supported();
+        assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let value: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["error"]["type"], "invalid_request_error");
+        assert_eq!(value["error"]["code"], "embeddings_not_supported");
+        assert!(value["error"]["message"].is_string());
+    }
+}
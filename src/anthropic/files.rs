@@ -0,0 +1,134 @@
+//! Files API 本地存储
+//!
+//! 将 `POST /v1/files` 上传的文件保存到本地磁盘，
+//! 供后续 `image`/`document` 内容块通过 `file_id` 引用。
+//!
+//! 存储布局：`{dir}/{file_id}` 保存文件内容，`{dir}/{file_id}.json` 保存元数据。
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 文件元数据（与 Anthropic Files API 的 file 对象一致）
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileMetadata {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub downloadable: bool,
+}
+
+/// 本地文件存储
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// 校验文件 ID，防止路径穿越
+    fn is_valid_id(id: &str) -> bool {
+        id.strip_prefix("file_")
+            .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_alphanumeric()))
+    }
+
+    fn content_path(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    fn metadata_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// 保存上传的文件
+    pub async fn save(
+        &self,
+        filename: impl Into<String>,
+        mime_type: impl Into<String>,
+        data: &[u8],
+    ) -> anyhow::Result<FileMetadata> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let metadata = FileMetadata {
+            id: format!("file_{}", Uuid::new_v4().simple()),
+            object_type: "file".to_string(),
+            filename: filename.into(),
+            mime_type: mime_type.into(),
+            size_bytes: data.len() as u64,
+            created_at: chrono::Utc::now(),
+            downloadable: false,
+        };
+
+        tokio::fs::write(self.content_path(&metadata.id), data).await?;
+        tokio::fs::write(
+            self.metadata_path(&metadata.id),
+            serde_json::to_string_pretty(&metadata)?,
+        )
+        .await?;
+
+        tracing::info!(
+            "已保存文件 {} ({}, {} 字节)",
+            metadata.id,
+            metadata.filename,
+            metadata.size_bytes
+        );
+        Ok(metadata)
+    }
+
+    /// 获取文件元数据，不存在时返回 None
+    pub async fn get_metadata(&self, id: &str) -> Option<FileMetadata> {
+        if !Self::is_valid_id(id) {
+            return None;
+        }
+        let content = tokio::fs::read_to_string(self.metadata_path(id))
+            .await
+            .ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// 读取文件元数据和内容，不存在时返回 None
+    pub async fn read(&self, id: &str) -> Option<(FileMetadata, Vec<u8>)> {
+        let metadata = self.get_metadata(id).await?;
+        let data = tokio::fs::read(self.content_path(id)).await.ok()?;
+        Some((metadata, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store() -> FileStore {
+        FileStore::new(std::env::temp_dir().join(format!("kiro-files-{}", Uuid::new_v4())))
+    }
+
+    #[tokio::test]
+    async fn test_save_and_read() {
+        let store = temp_store();
+        let metadata = store.save("a.txt", "text/plain", b"hello").await.unwrap();
+
+        assert!(metadata.id.starts_with("file_"));
+        assert_eq!(metadata.size_bytes, 5);
+
+        let (read_metadata, data) = store.read(&metadata.id).await.unwrap();
+        assert_eq!(read_metadata, metadata);
+        assert_eq!(data, b"hello");
+
+        let _ = std::fs::remove_dir_all(&store.dir);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_id_rejected() {
+        let store = temp_store();
+        assert!(store.get_metadata("../config").await.is_none());
+        assert!(store.get_metadata("file_../x").await.is_none());
+        assert!(store.get_metadata("file_missing").await.is_none());
+    }
+}
@@ -0,0 +1,213 @@
+//! 转换器 golden 测试
+//!
+//! 从 `fixtures/` 目录加载成对的输入与期望输出并断言一致：
+//! - `fixtures/converter/{name}.request.json` → `{name}.kiro.json`：Anthropic 请求 → Kiro 请求
+//! - `fixtures/stream/{name}.events.json` → `{name}.sse.txt`：Kiro 事件 → SSE 文本
+//!
+//! 修改转换逻辑后，运行 `UPDATE_GOLDENS=1 cargo test golden` 重新生成期望输出，
+//! 并检查 diff 确认变化符合预期。
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::kiro::model::events::Event;
+
+use super::converter::convert_request;
+use super::stream::{SseEvent, StreamContext};
+use super::types::MessagesRequest;
+
+/// 替换随机生成的 ID，保证输出稳定
+const GOLDEN_UUID: &str = "00000000-0000-0000-0000-000000000000";
+const GOLDEN_MESSAGE_ID: &str = "msg_golden";
+
+/// 是否重新生成期望输出
+fn update_goldens() -> bool {
+    std::env::var("UPDATE_GOLDENS").is_ok_and(|v| v == "1" || v == "true")
+}
+
+fn fixtures_dir(kind: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(kind)
+}
+
+/// 列出目录下以指定后缀结尾的 fixture，返回 (名称, 路径)
+fn list_fixtures(dir: &Path, suffix: &str) -> Vec<(String, PathBuf)> {
+    let mut fixtures: Vec<(String, PathBuf)> = std::fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("读取 fixture 目录 {:?} 失败: {}", dir, e))
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|path| {
+            let name = path
+                .file_name()?
+                .to_str()?
+                .strip_suffix(suffix)?
+                .to_string();
+            Some((name, path))
+        })
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+/// 比较或更新期望输出，不一致时返回错误描述
+fn check_golden(expected_path: &Path, actual: &str) -> Result<(), String> {
+    if update_goldens() {
+        std::fs::write(expected_path, actual).map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+
+    let expected = std::fs::read_to_string(expected_path)
+        .map_err(|e| format!("{:?}: 读取期望输出失败: {}", expected_path, e))?;
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(format!(
+            "{:?} 不一致\n--- expected\n{}\n--- actual\n{}",
+            expected_path, expected, actual
+        ))
+    }
+}
+
+/// 汇总所有失败的 fixture
+fn assert_no_failures(kind: &str, count: usize, failures: Vec<String>) {
+    assert!(count > 0, "{} 目录下没有 fixture", kind);
+    assert!(
+        failures.is_empty(),
+        "{} 个 {} golden 测试失败（如变化符合预期，使用 UPDATE_GOLDENS=1 重新生成）:\n\n{}",
+        failures.len(),
+        kind,
+        failures.join("\n\n")
+    );
+}
+
+/// Anthropic 请求 → Kiro 请求
+fn render_converter_fixture(request_json: &str) -> Result<String, String> {
+    let request: MessagesRequest =
+        serde_json::from_str(request_json).map_err(|e| format!("解析请求失败: {}", e))?;
+    let mut state = convert_request(&request)
+        .map_err(|e| format!("转换失败: {}", e))?
+        .conversation_state;
+
+    state.conversation_id = GOLDEN_UUID.to_string();
+    if state.agent_continuation_id.is_some() {
+        state.agent_continuation_id = Some(GOLDEN_UUID.to_string());
+    }
+
+    let mut rendered = serde_json::to_string_pretty(&state).map_err(|e| e.to_string())?;
+    rendered.push('\n');
+    Ok(rendered)
+}
+
+#[test]
+fn golden_converter() {
+    let dir = fixtures_dir("converter");
+    let fixtures = list_fixtures(&dir, ".request.json");
+
+    let mut failures = Vec::new();
+    for (name, path) in &fixtures {
+        let result = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| render_converter_fixture(&json))
+            .and_then(|actual| check_golden(&dir.join(format!("{}.kiro.json", name)), &actual));
+        if let Err(e) = result {
+            failures.push(format!("[{}] {}", name, e));
+        }
+    }
+
+    assert_no_failures("converter", fixtures.len(), failures);
+}
+
+/// 流式 fixture：请求参数 + Kiro 事件序列
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamFixture {
+    model: String,
+    #[serde(default)]
+    input_tokens: i32,
+    #[serde(default)]
+    thinking: bool,
+    #[serde(default)]
+    max_tokens: i32,
+    events: Vec<FixtureEvent>,
+}
+
+/// Kiro 事件，`type` 为事件类型（与 `:event-type` 头一致）或 `error`/`exception`
+#[derive(Deserialize)]
+struct FixtureEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    #[serde(default)]
+    payload: serde_json::Value,
+}
+
+impl FixtureEvent {
+    fn into_event(self) -> Result<Event, String> {
+        let field = |name: &str| self.payload[name].as_str().unwrap_or_default().to_string();
+        let event = match self.event_type.as_str() {
+            "assistantResponseEvent" => Event::AssistantResponse(
+                serde_json::from_value(self.payload.clone()).map_err(|e| e.to_string())?,
+            ),
+            "toolUseEvent" => Event::ToolUse(
+                serde_json::from_value(self.payload.clone()).map_err(|e| e.to_string())?,
+            ),
+            "contextUsageEvent" => Event::ContextUsage(
+                serde_json::from_value(self.payload.clone()).map_err(|e| e.to_string())?,
+            ),
+            "meteringEvent" => Event::Metering(
+                serde_json::from_value(self.payload.clone()).map_err(|e| e.to_string())?,
+            ),
+            "error" => Event::Error {
+                error_code: field("errorCode"),
+                error_message: field("errorMessage"),
+            },
+            "exception" => Event::Exception {
+                exception_type: field("exceptionType"),
+                message: field("message"),
+            },
+            other => return Err(format!("未知的事件类型: {}", other)),
+        };
+        Ok(event)
+    }
+}
+
+/// Kiro 事件 → SSE 文本
+fn render_stream_fixture(fixture_json: &str) -> Result<String, String> {
+    let fixture: StreamFixture =
+        serde_json::from_str(fixture_json).map_err(|e| format!("解析 fixture 失败: {}", e))?;
+
+    let mut ctx =
+        StreamContext::new_with_thinking(&fixture.model, fixture.input_tokens, fixture.thinking)
+            .with_max_tokens(fixture.max_tokens);
+    ctx.message_id = GOLDEN_MESSAGE_ID.to_string();
+
+    let mut events: Vec<SseEvent> = ctx.generate_initial_events();
+    for fixture_event in fixture.events {
+        events.extend(ctx.process_kiro_event(&fixture_event.into_event()?));
+        if ctx.max_tokens_reached {
+            break;
+        }
+    }
+    events.extend(ctx.generate_final_events());
+
+    Ok(events.iter().map(SseEvent::to_sse_string).collect())
+}
+
+#[test]
+fn golden_stream() {
+    let dir = fixtures_dir("stream");
+    let fixtures = list_fixtures(&dir, ".events.json");
+
+    let mut failures = Vec::new();
+    for (name, path) in &fixtures {
+        let result = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| render_stream_fixture(&json))
+            .and_then(|actual| check_golden(&dir.join(format!("{}.sse.txt", name)), &actual));
+        if let Err(e) = result {
+            failures.push(format!("[{}] {}", name, e));
+        }
+    }
+
+    assert_no_failures("stream", fixtures.len(), failures);
+}
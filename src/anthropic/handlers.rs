@@ -2,38 +2,72 @@
 
 use std::convert::Infallible;
 
+use bytes::Bytes;
+
+use crate::kiro::chaos;
+use crate::kiro::error::KiroError;
 use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::conversation::Message;
 use crate::kiro::model::requests::kiro::KiroRequest;
-use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::parser::decoder::DecoderConfig;
+use crate::kiro::provider::KiroProvider;
+use crate::kiro::upstream_headers::UpstreamHeaders;
+use crate::model::config::{
+    AgentMode, ChaosConfig, ChatTriggerType, ClientToolMode, KiroOrigin, RequestPriority,
+    RequestTimeouts, SseConfig,
+};
+use crate::pool::in_flight::InFlightGuard;
+use crate::pool::{AccountPool, ClientInfo, PoolReadiness, Workspace};
 use crate::token;
 use axum::{
     body::Body,
-    extract::State,
-    http::{header, StatusCode},
+    extract::{Multipart, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Json, Response},
-    Json as JsonExtractor,
+    Extension, Json as JsonExtractor,
 };
-use bytes::Bytes;
-use futures::{stream, Stream, StreamExt};
+use futures::{stream, StreamExt};
 use serde_json::json;
 use std::time::Duration;
-use tokio::time::interval;
 use uuid::Uuid;
 
-use super::converter::{convert_request, ConversionError};
-use super::middleware::AppState;
-use super::stream::{SseEvent, StreamContext};
+use super::beta::{AnthropicBetas, BetaFeature};
+use super::blocks::apply_block_policy;
+use super::body::LimitedJson;
+use super::coalesce::{Coalesced, Publisher, StreamCoalescer};
+use super::continuation;
+use super::conversations::ConversationEntry;
+use super::converter::{
+    apply_tool_limits, convert_request, inject_system_prompt, map_model, resolve_file_references,
+    truncate_tool_results, ConversionError, ConversionResult, ToolResultTruncation,
+};
+use super::deadline::{Deadline, Progress, Stage};
+use super::diagnose;
+use super::embeddings::embeddings_not_supported;
+use super::middleware::{AppState, ManagedKey, TrustedKey};
+use super::pipeline;
+use super::postprocess::PostProcessConfig;
+use super::scheduler::Permit;
+use super::server_tools::{ServerTool, ServerTools, ToolTurn};
+use super::stream::{
+    resolve_stop_reason, strip_thinking_block, EventFilter, StreamContext, StreamFormat,
+};
+use super::telemetry::{ConverterFailure, FailureKind};
+use super::tool_alias::ToolAliases;
+use super::tool_policy::apply_client_tool_policy;
 use super::types::{
-    CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse,
+    CountTokensRequest, CountTokensResponse, DryRunResponse, ErrorResponse, EstimateResponse,
+    MessagesQuery, MessagesRequest, Model, ModelInfo, ModelsResponse, PoolEstimate, QuotaEstimate,
+    Thinking,
 };
+use super::version::AnthropicVersion;
 
-/// GET /v1/models
-///
-/// 返回可用的模型列表
-pub async fn get_models() -> impl IntoResponse {
-    tracing::info!("Received GET /v1/models request");
+/// 试运行响应中替代 profileArn 的占位符
+const REDACTED: &str = "[REDACTED]";
 
-    let models = vec![
+/// 模型目录
+fn model_catalog() -> Vec<Model> {
+    vec![
         Model {
             id: "claude-sonnet-4-5-20250929".to_string(),
             object: "model".to_string(),
@@ -61,24 +95,75 @@ pub async fn get_models() -> impl IntoResponse {
             model_type: "chat".to_string(),
             max_tokens: 32000,
         },
-    ];
+    ]
+}
+
+/// GET /v1/models
+///
+/// 返回可用的模型列表
+pub async fn get_models() -> impl IntoResponse {
+    tracing::info!("Received GET /v1/models request");
 
     Json(ModelsResponse {
         object: "list".to_string(),
-        data: models,
+        data: model_catalog(),
     })
 }
 
+/// GET /v1/models/{model_id}
+///
+/// 返回单个模型的信息（与 Anthropic 相同的格式）。目录中没有完全匹配的 ID 时，
+/// 按请求转换时的模型映射查找（如 `claude-sonnet-4-5` 返回 Sonnet 4.5 的条目）
+pub async fn get_model(Path(model_id): Path<String>) -> Response {
+    tracing::info!("Received GET /v1/models/{} request", model_id);
+
+    let catalog = model_catalog();
+    let model = catalog.iter().find(|m| m.id == model_id).or_else(|| {
+        let kiro_model = map_model(&model_id)?;
+        catalog
+            .iter()
+            .find(|m| map_model(&m.id).as_ref() == Some(&kiro_model))
+    });
+    match model {
+        Some(model) => Json(ModelInfo::from(model)).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(
+                "not_found_error",
+                format!("model: {}", model_id),
+            )),
+        )
+            .into_response(),
+    }
+}
+
 /// POST /v1/messages
 ///
 /// 创建消息（对话）
+#[allow(clippy::too_many_arguments)]
 pub async fn post_messages(
     State(state): State<AppState>,
-    JsonExtractor(payload): JsonExtractor<MessagesRequest>,
+    workspace: Option<Extension<Workspace>>,
+    version: Option<Extension<AnthropicVersion>>,
+    betas: Option<Extension<AnthropicBetas>>,
+    trusted: Option<Extension<TrustedKey>>,
+    managed: Option<Extension<ManagedKey>>,
+    headers: HeaderMap,
+    Query(query): Query<MessagesQuery>,
+    LimitedJson(mut payload): LimitedJson<MessagesRequest>,
 ) -> Response {
     let start_time = std::time::Instant::now();
 
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+    let anthropic_version = headers
+        .get("anthropic-version")
+        .and_then(|v| v.to_str().ok());
+
     tracing::info!(
+        user_agent = user_agent.unwrap_or("-"),
+        anthropic_version = anthropic_version.unwrap_or("-"),
         model = %payload.model,
         max_tokens = %payload.max_tokens,
         stream = %payload.stream,
@@ -86,70 +171,275 @@ pub async fn post_messages(
         "Received POST /v1/messages request"
     );
 
-    // 获取 provider：优先从账号池获取，否则使用单账号模式
-    let (provider, account_id, account_name, pool_ref) = if let Some(pool) = &state.account_pool {
-        match pool.select_account().await {
-            Some(selected) => (
-                selected.provider,
-                Some(selected.id),
-                selected.name,
-                Some(pool.clone()),
-            ),
-            None => {
-                tracing::error!("账号池中没有可用账号");
-                return (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(ErrorResponse::new(
-                        "service_unavailable",
-                        "No available accounts in pool",
-                    )),
-                )
-                    .into_response();
+    if let Some(response) = model_not_allowed(managed.as_ref(), &payload.model) {
+        return response;
+    }
+    let betas = betas.map(|Extension(b)| b).unwrap_or_default();
+    if let Some(response) = max_tokens_exceeded(&betas, payload.max_tokens) {
+        return response;
+    }
+    let requested_deadline = match Deadline::requested(&headers) {
+        Ok(requested) => requested,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", message)),
+            )
+                .into_response()
+        }
+    };
+
+    // 注入运营方配置的系统提示词（工作区配置优先于全局配置）
+    let system_prompt = workspace
+        .as_ref()
+        .and_then(|Extension(ws)| ws.system_prompt.as_deref())
+        .or(state.system_prompt.as_deref());
+    if let Some(prompt) = system_prompt {
+        inject_system_prompt(&mut payload, prompt, state.system_prompt_position);
+    }
+
+    // 代理模式：x-kiro-agent-mode 请求头优先，其次是模型名后缀，最后是配置默认值
+    let agent_mode = resolve_agent_mode(&headers, &mut payload, state.agent_mode);
+
+    // 可信密钥可以通过请求头覆盖 thinking 预算、agentTaskType、origin 和 chatTriggerType
+    let mut overrides = match apply_header_overrides(&headers, &mut payload, trusted.is_some()) {
+        Ok(overrides) => overrides,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", message)),
+            )
+                .into_response()
+        }
+    };
+
+    // 客户端工具策略：托管 API Key 的策略优先于全局配置
+    let client_tools = managed
+        .as_ref()
+        .and_then(|Extension(ManagedKey(info))| info.client_tools.clone())
+        .map(std::sync::Arc::new)
+        .unwrap_or_else(|| state.client_tools.clone());
+    let caller = match (&managed, &workspace) {
+        (Some(Extension(ManagedKey(info))), _) => info.prefix.as_str(),
+        (None, Some(Extension(ws))) => ws.name.as_str(),
+        (None, None) => "-",
+    };
+    if let Err(message) = apply_client_tool_policy(&mut payload, &client_tools, caller) {
+        tracing::warn!("{}", message);
+        return (
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse::new("permission_error", message)),
+        )
+            .into_response();
+    }
+
+    if query.dry_run {
+        return dry_run(&state, payload, agent_mode, &overrides).await;
+    }
+
+    // 同一会话复用上游会话 ID（试运行不写入会话缓存）
+    overrides.conversation = conversation_key(&headers, caller);
+
+    // 客户端分布统计（工作区请求也记录在主账号池中，按调用方区分）
+    let client = ClientInfo {
+        caller: match (&managed, &workspace) {
+            (Some(Extension(ManagedKey(info))), _) => format!("key:{}", info.id),
+            (None, Some(Extension(ws))) => format!("workspace:{}", ws.name),
+            (None, None) => "main".to_string(),
+        },
+        user_agent: user_agent.map(str::to_string),
+        anthropic_version: anthropic_version.map(str::to_string),
+    };
+    match &state.account_pool {
+        Some(pool) => pool.record_client(&client).await,
+        None => state.client_stats.lock().unwrap().record(&client),
+    }
+
+    // 流式响应格式：Accept 请求 NDJSON 时输出换行分隔的 JSON，否则为 SSE（按 API 版本调整）
+    let version = version.map(|Extension(v)| v).unwrap_or_default();
+    let stream_format =
+        StreamFormat::from_accept(headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()))
+            .for_version(version);
+
+    // 合并同一调用方完全相同的并发流式请求（仅 SSE，被合并的请求共享同一份已序列化的字节流）
+    let mut publisher = None;
+    if let (true, StreamFormat::Sse, Some(coalescer)) =
+        (payload.stream, stream_format, &state.stream_coalescer)
+    {
+        if let Some(key) = coalesce_key(&payload, agent_mode, caller, &overrides) {
+            match coalescer.join(&key) {
+                Coalesced::Leader(p) => publisher = Some(p),
+                Coalesced::Follower(mut subscription) => {
+                    if let Some(first) = subscription.next().await {
+                        tracing::info!("合并相同的并发流式请求");
+                        let stream = stream::iter([Ok::<_, Infallible>(first)])
+                            .chain(subscription.into_stream());
+                        return stream_response(
+                            Body::from_stream(stream),
+                            StreamFormat::Sse,
+                            &state.sse,
+                        );
+                    }
+                    tracing::debug!("被合并的请求未产生事件，独立处理");
+                }
             }
         }
-    } else {
-        // 单账号模式
-        match &state.kiro_provider {
-            Some(p) => (p.clone(), None, "单账号模式".to_string(), None),
-            None => {
-                tracing::error!("KiroProvider 未配置");
-                return (
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(ErrorResponse::new(
-                        "service_unavailable",
-                        "Kiro API provider not configured",
-                    )),
-                )
-                    .into_response();
+    }
+
+    // 截止时间：x-request-deadline-ms 请求头与时长限制（托管 API Key → 工作区 → 全局配置）中较早者
+    let timeouts = resolve_timeouts(&state, workspace.as_ref(), managed.as_ref());
+    let deadline = Deadline::resolve(start_time, requested_deadline, timeouts.max_request());
+    let progress = Progress::default();
+
+    // 上游并发已满时按优先级排队：x-priority 请求头优先，其次是工作区默认优先级
+    let mut permit = None;
+    if let Some(scheduler) = &state.scheduler {
+        let priority = request_priority(&headers, workspace.as_ref());
+        let acquired = match &deadline {
+            Some(deadline) => {
+                match tokio::time::timeout_at(deadline.at(), scheduler.acquire(priority)).await {
+                    Ok(acquired) => acquired,
+                    Err(_) => return deadline.timeout_response(&progress),
+                }
+            }
+            None => scheduler.acquire(priority).await,
+        };
+        match acquired {
+            Ok(p) => permit = Some(p),
+            Err(_) => {
+                let (interactive, batch) = scheduler.queued();
+                tracing::warn!(
+                    "{:?} 请求排队超时，拒绝请求（排队中: 交互式 {}, 批处理 {}）",
+                    priority,
+                    interactive,
+                    batch
+                );
+                return overloaded_response(POOL_SATURATED_MESSAGE, None);
             }
         }
-    };
+    }
 
-    // 获取 profile_arn
-    let profile_arn = state.profile_arn.clone();
+    // 工作区请求使用该工作区独立的账号池
+    let account_pool = match &workspace {
+        Some(Extension(ws)) => Some(ws.pool.clone()),
+        None => state.account_pool.clone(),
+    };
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
-        Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
+    // 获取 provider：优先从账号池获取，否则使用单账号模式
+    let (provider, account_id, account_name, pool_ref, in_flight) =
+        if let Some(pool) = &account_pool {
+            match pool.select_account().await {
+                Some(selected) => (
+                    selected.provider,
+                    Some(selected.id),
+                    selected.name,
+                    Some(pool.clone()),
+                    Some(selected.in_flight),
+                ),
+                None => {
+                    // 所有账号都在冷却、配额用尽或上游过载暂停期间快速返回 529，避免继续触发上游限流
+                    if let PoolReadiness::Saturated { retry_after } = pool.readiness().await {
+                        tracing::warn!("账号池已饱和，拒绝请求，预计恢复时间: {:?}", retry_after);
+                        return overloaded_response(POOL_SATURATED_MESSAGE, retry_after);
+                    }
+                    tracing::error!("账号池中没有可用账号");
+                    return (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        Json(ErrorResponse::new(
+                            "service_unavailable",
+                            "No available accounts in pool",
+                        )),
+                    )
+                        .into_response();
                 }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
+            }
+        } else {
+            // 单账号模式
+            match &state.kiro_provider {
+                Some(p) => (p.clone(), None, "单账号模式".to_string(), None, None),
+                None => {
+                    tracing::error!("KiroProvider 未配置");
+                    return (
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        Json(ErrorResponse::new(
+                            "service_unavailable",
+                            "Kiro API provider not configured",
+                        )),
+                    )
+                        .into_response();
                 }
+            }
+        };
+
+    progress.account(&account_name);
+    progress.stage(Stage::Preparing);
+
+    // 获取 profile_arn：优先使用配置，否则使用 Token 刷新时自动发现的值
+    let profile_arn = match state.profile_arn.clone() {
+        Some(arn) => Some(arn),
+        None => provider.profile_arn().await,
+    };
+
+    // 注入代理执行的内置工具（客户端已定义同名工具时不注入）
+    let server_tools = state
+        .server_tools
+        .clone()
+        .map(|tools| {
+            let injected = tools.inject(&mut payload);
+            (tools, injected)
+        })
+        .filter(|(_, injected)| !injected.is_empty());
+
+    // 解析 file_id 引用并转换请求
+    let conversion_result =
+        match prepare_request(&state, &mut payload, agent_mode, &overrides).await {
+            Ok(result) => result,
+            Err(response) => return response,
+        };
+    let truncated = conversion_result.truncated;
+
+    // 执行内置工具循环，最后一轮的上游响应交给常规流程输出
+    let (conversion_result, prefetched) = match server_tools {
+        Some((tools, injected)) => {
+            let account = account_id.as_deref().zip(pool_ref.as_deref());
+            let run = run_server_tools(
+                &state,
+                &tools,
+                &injected,
+                &provider,
+                profile_arn.clone(),
+                account,
+                &account_name,
+                &client,
+                &mut payload,
+                agent_mode,
+                &overrides,
+                conversion_result,
+                deadline.as_ref(),
+                &progress,
+            );
+            let result = match &deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline.at(), run).await {
+                    Ok(result) => result,
+                    Err(_) => return deadline.timeout_response(&progress),
+                },
+                None => run.await,
             };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
+            match result {
+                Ok((result, body, headers)) => (result, Some((body, headers))),
+                Err(response) => return response,
+            }
         }
+        None => (conversion_result, None),
     };
 
     // 构建 Kiro 请求
+    let mut tool_aliases = conversion_result.tool_aliases;
+    if client_tools.mode == ClientToolMode::Namespace {
+        tool_aliases = tool_aliases.with_namespace(&client_tools.prefix);
+    }
+    let tool_aliases = std::sync::Arc::new(tool_aliases);
+    let single_tool_call = payload.disable_parallel_tool_use();
     let kiro_request = KiroRequest {
         conversation_state: conversion_result.conversation_state,
         profile_arn: profile_arn.clone(),
@@ -159,6 +449,12 @@ pub async fn post_messages(
         Ok(body) => body,
         Err(e) => {
             tracing::error!("序列化请求失败: {}", e);
+            state.report_failure(ConverterFailure::new(
+                FailureKind::Serialization,
+                format!("{:?}", e.classify()).to_lowercase(),
+                "conversationState",
+                &payload.model,
+            ));
             return (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 Json(ErrorResponse::new(
@@ -172,48 +468,822 @@ pub async fn post_messages(
 
     tracing::debug!("Kiro request body: {}", request_body);
 
+    // ?continue=true 时保留请求用于续写（仅非流式）
+    let continuation = (query.auto_continue && !payload.stream).then(|| payload.clone());
+
     // 估算输入 tokens
     let input_tokens = token::count_all_tokens(
         payload.model.clone(),
         payload.system,
         payload.messages,
         payload.tools,
-    ) as i32;
+    )
+    .await as i32;
+
+    // 检查是否启用了thinking
+    let thinking_enabled = payload
+        .thinking
+        .as_ref()
+        .map(|t| t.thinking_type == "enabled")
+        .unwrap_or(false);
+
+    progress.stage(Stage::Upstream);
+    let handle = async {
+        if payload.stream {
+            // 流式响应
+            handle_stream_request(
+                provider,
+                &request_body,
+                agent_mode,
+                &payload.model,
+                input_tokens,
+                payload.max_tokens,
+                thinking_enabled,
+                state.event_filter,
+                state.post_process.clone(),
+                state.chaos.clone(),
+                state.decoder,
+                state.sse.clone(),
+                tool_aliases,
+                single_tool_call,
+                account_id,
+                account_name,
+                pool_ref,
+                client,
+                start_time,
+                publisher,
+                stream_format,
+                prefetched,
+                deadline.map(|d| d.at()),
+                timeouts.stream_idle(),
+            )
+            .await
+        } else {
+            // 非流式响应
+            let call_start = std::time::Instant::now();
+            let response = handle_non_stream_request(
+                provider.clone(),
+                &request_body,
+                agent_mode,
+                &payload.model,
+                input_tokens,
+                thinking_enabled,
+                state.event_filter,
+                state.post_process.clone(),
+                state.chaos.clone(),
+                state.decoder,
+                tool_aliases,
+                single_tool_call,
+                account_id.clone(),
+                account_name.clone(),
+                pool_ref.clone(),
+                client.clone(),
+                start_time,
+                prefetched,
+            )
+            .await;
+            progress.record_call(call_start.elapsed());
+            match continuation {
+                Some(payload) => {
+                    continue_truncated(
+                        &state,
+                        payload,
+                        response,
+                        provider,
+                        profile_arn,
+                        agent_mode,
+                        &overrides,
+                        account_id,
+                        account_name,
+                        pool_ref,
+                        &client,
+                        start_time,
+                        deadline,
+                        &progress,
+                    )
+                    .await
+                }
+                None => response,
+            }
+        }
+    };
+    let mut response = match &deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.at(), handle)
+            .await
+            .unwrap_or_else(|_| deadline.timeout_response(&progress)),
+        None => handle.await,
+    };
+    apply_truncation_headers(&mut response, truncated);
+    match (permit, in_flight) {
+        (None, None) => response,
+        guards => hold_until_body_end(response, guards),
+    }
+}
+
+/// 响应头：请求中被截断的 tool_result 数量
+const TRUNCATED_TOOL_RESULTS_HEADER: &str = "x-kiro-truncated-tool-results";
+
+/// 响应头：从 tool_result 中移除的字节数
+const TRUNCATED_BYTES_HEADER: &str = "x-kiro-truncated-bytes";
+
+/// 有 tool_result 因超出 `maxToolResultBytes` 被截断时添加截断统计响应头
+fn apply_truncation_headers(response: &mut Response, truncated: ToolResultTruncation) {
+    if truncated.results == 0 {
+        return;
+    }
+    let headers = response.headers_mut();
+    headers.insert(TRUNCATED_TOOL_RESULTS_HEADER, truncated.results.into());
+    headers.insert(TRUNCATED_BYTES_HEADER, truncated.bytes.into());
+}
+
+/// 让执行名额和账号的进行中计数随响应体存活，流式响应结束后才释放
+fn hold_until_body_end(
+    response: Response,
+    guards: (Option<Permit>, Option<InFlightGuard>),
+) -> Response {
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _ = &guards;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
+
+/// 托管 API Key 不允许使用请求的模型时返回 403 permission_error 响应
+fn model_not_allowed(managed: Option<&Extension<ManagedKey>>, model: &str) -> Option<Response> {
+    let Extension(ManagedKey(info)) = managed?;
+    if info.allows_model(model) {
+        return None;
+    }
+    tracing::warn!("托管 API Key {} 不允许使用模型: {}", info.prefix, model);
+    let error = ErrorResponse::new(
+        "permission_error",
+        format!("该 API Key 不允许使用模型: {}", model),
+    );
+    Some((StatusCode::FORBIDDEN, Json(error)).into_response())
+}
+
+/// `max_tokens` 超过上限（取决于是否开启扩展输出 beta）时返回 400 invalid_request_error 响应
+fn max_tokens_exceeded(betas: &AnthropicBetas, max_tokens: i32) -> Option<Response> {
+    let limit = betas.max_output_tokens();
+    if max_tokens <= limit {
+        return None;
+    }
+    let mut message = format!("max_tokens: {} > {}", max_tokens, limit);
+    if !betas.has(BetaFeature::ExtendedOutput) {
+        message.push_str(&format!(
+            "，更大的输出需要在 anthropic-beta 中开启 {}",
+            BetaFeature::ExtendedOutput.flag()
+        ));
+    }
+    let error = ErrorResponse::new("invalid_request_error", message);
+    Some((StatusCode::BAD_REQUEST, Json(error)).into_response())
+}
+
+/// 确定请求的时长限制（托管 API Key → 工作区 → 全局配置）
+fn resolve_timeouts(
+    state: &AppState,
+    workspace: Option<&Extension<Workspace>>,
+    managed: Option<&Extension<ManagedKey>>,
+) -> RequestTimeouts {
+    let mut timeouts = state.timeouts;
+    if let Some(Extension(ws)) = workspace {
+        timeouts = ws.timeouts.or(timeouts);
+    }
+    if let Some(Extension(ManagedKey(info))) = managed {
+        timeouts = info.timeouts.or(timeouts);
+    }
+    timeouts
+}
+
+/// 排队优先级：x-priority 请求头优先，其次是工作区默认优先级
+fn request_priority(
+    headers: &HeaderMap,
+    workspace: Option<&Extension<Workspace>>,
+) -> RequestPriority {
+    headers
+        .get("x-priority")
+        .and_then(|v| v.to_str().ok())
+        .and_then(RequestPriority::parse)
+        .or(workspace.map(|Extension(ws)| ws.priority))
+        .unwrap_or_default()
+}
+
+/// 账号池饱和时返回给客户端的错误信息
+const POOL_SATURATED_MESSAGE: &str = "All accounts are cooling down or over quota";
+
+/// 529 overloaded_error 响应，`retry_after` 写入 `Retry-After` 响应头
+fn overloaded_response(message: impl Into<String>, retry_after: Option<Duration>) -> Response {
+    let mut response = (
+        StatusCode::from_u16(529).unwrap(),
+        Json(ErrorResponse::new("overloaded_error", message)),
+    )
+        .into_response();
+    if let Some(retry_after) = retry_after {
+        // 向上取整到秒，至少 1 秒
+        let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, secs.max(1).into());
+    }
+    response
+}
+
+/// 截断过大的 tool_result、解析 file_id 引用、应用工具限制并转换请求，失败时返回 400 响应
+///
+/// `overrides` 为请求头指定的上游字段，未指定时 agentTaskType 与代理模式一致，origin 和
+/// chatTriggerType 使用配置值（chatTriggerType 未配置时保留转换器的推断结果）
+pub(super) async fn prepare_request(
+    state: &AppState,
+    payload: &mut MessagesRequest,
+    agent_mode: AgentMode,
+    overrides: &RequestOverrides,
+) -> Result<ConversionResult, Response> {
+    let truncated = truncate_tool_results(payload, &state.tool_limits);
+    let result = resolve_file_references_if_enabled(state, payload)
+        .await
+        .and_then(|_| apply_tool_limits(payload, &state.tool_limits))
+        .and_then(|_| apply_block_policy(payload, &state.block_policy))
+        .and_then(|_| convert_with_telemetry(state, payload))
+        .map(|mut result| {
+            result.truncated = truncated;
+            let conversation = &mut result.conversation_state;
+            let task_type = overrides
+                .task_type
+                .as_deref()
+                .unwrap_or(agent_mode.as_str());
+            conversation.agent_task_type = Some(task_type.to_string());
+            if let Some(trigger) = overrides.chat_trigger_type.or(state.chat_trigger_type) {
+                conversation.chat_trigger_type = Some(trigger.as_str().to_string());
+            }
+            if let Some(key) = &overrides.conversation {
+                match state.conversations.get(key) {
+                    Some(entry) => {
+                        conversation.conversation_id = entry.conversation_id;
+                        conversation.agent_continuation_id = Some(entry.continuation_id);
+                    }
+                    None => state.conversations.insert(
+                        key.clone(),
+                        ConversationEntry::new(
+                            conversation.conversation_id.clone(),
+                            conversation
+                                .agent_continuation_id
+                                .clone()
+                                .unwrap_or_default(),
+                        ),
+                    ),
+                }
+            }
+            let origin = overrides.origin.unwrap_or(state.origin).as_str();
+            conversation.current_message.user_input_message.origin = Some(origin.to_string());
+            for message in &mut conversation.history {
+                if let Message::User(user) = message {
+                    user.user_input_message.origin = Some(origin.to_string());
+                }
+            }
+            result
+        });
+    result.map_err(|e| {
+        state.report_failure(ConverterFailure::new(
+            FailureKind::Conversion,
+            e.kind(),
+            e.field_path(),
+            &payload.model,
+        ));
+        let (error_type, message) = match &e {
+            ConversionError::UnsupportedModel(model) => {
+                ("invalid_request_error", format!("模型不支持: {}", model))
+            }
+            ConversionError::EmptyMessages => ("invalid_request_error", "消息列表为空".to_string()),
+            ConversionError::FileNotFound(id) => {
+                ("invalid_request_error", format!("文件不存在: {}", id))
+            }
+            ConversionError::UnsupportedContentBlock(_)
+            | ConversionError::ToolLimitExceeded { .. } => ("invalid_request_error", e.to_string()),
+        };
+        tracing::warn!("请求转换失败: {}", e);
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new(error_type, message)),
+        )
+            .into_response()
+    })
+}
+
+/// 内置工具循环：模型只请求内置工具时由代理执行并继续对话
+///
+/// 中间轮次使用非流式调用，返回最后一轮的转换结果、上游响应体和上游响应头。
+/// 响应中混有客户端工具、达到轮次上限或剩余时间不足以完成下一轮时提前结束，由客户端处理其中的工具调用。
+/// 中间轮次各自计入请求记录和额度账本，最后一轮由常规流程记录
+#[allow(clippy::too_many_arguments)]
+async fn run_server_tools(
+    state: &AppState,
+    tools: &ServerTools,
+    injected: &[ServerTool],
+    provider: &KiroProvider,
+    profile_arn: Option<String>,
+    account: Option<(&str, &AccountPool)>,
+    account_name: &str,
+    client: &ClientInfo,
+    payload: &mut MessagesRequest,
+    agent_mode: AgentMode,
+    overrides: &RequestOverrides,
+    mut conversion: ConversionResult,
+    deadline: Option<&Deadline>,
+    progress: &Progress,
+) -> Result<(ConversionResult, Bytes, UpstreamHeaders), Response> {
+    progress.stage(Stage::ServerTools);
+    let mut iteration = 0;
+    loop {
+        let kiro_request = KiroRequest {
+            conversation_state: conversion.conversation_state.clone(),
+            profile_arn: profile_arn.clone(),
+        };
+        let request_body = serde_json::to_string(&kiro_request).map_err(|e| {
+            tracing::error!("序列化请求失败: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(
+                    "internal_error",
+                    format!("序列化请求失败: {}", e),
+                )),
+            )
+                .into_response()
+        })?;
+
+        let call_start = std::time::Instant::now();
+        let round = ServerToolRound {
+            account,
+            account_name,
+            client,
+            model: &payload.model,
+            start: call_start,
+        };
+        let response = match provider.call_api(&request_body, agent_mode).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::error!("Kiro API 调用失败: {}", e);
+                round.record_failure(&e).await;
+                return Err(upstream_failure(&e, &request_body));
+            }
+        };
+        let headers = UpstreamHeaders::capture(response.headers());
+        let body = match response.bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("读取响应体失败: {}", e);
+                let message = format!("读取响应失败: {}", e);
+                round.record_failure(&KiroError::from(e)).await;
+                return Err((
+                    StatusCode::BAD_GATEWAY,
+                    Json(ErrorResponse::new("api_error", message)),
+                )
+                    .into_response());
+            }
+        };
+        progress.record_call(call_start.elapsed());
+
+        let events = pipeline::watchdog(pipeline::decode_events(
+            stream::iter([Ok::<_, Infallible>(body.clone())]),
+            state.decoder,
+        ));
+        let aliases = std::sync::Arc::new(conversion.tool_aliases.clone());
+        let events: Vec<Event> =
+            pipeline::restore_tool_names(pipeline::assemble_tool_calls(events), aliases)
+                .collect()
+                .await;
+        let turn = ToolTurn::from_events(&events);
+        if !turn.only_calls(injected) {
+            return Ok((conversion, body, headers));
+        }
+        if iteration >= tools.max_iterations() {
+            tracing::warn!("内置工具执行达到轮次上限 {}，返回当前响应", iteration);
+            return Ok((conversion, body, headers));
+        }
+        if deadline.is_some_and(|d| !d.allows(progress.estimate())) {
+            tracing::warn!("剩余时间不足以完成下一轮内置工具调用，返回当前响应");
+            return Ok((conversion, body, headers));
+        }
+        iteration += 1;
+
+        round.record_success(payload, &turn).await;
+        turn.execute_into(tools, payload).await;
+        conversion = prepare_request(state, payload, agent_mode, overrides).await?;
+    }
+}
+
+/// 内置工具循环中的一轮上游调用
+struct ServerToolRound<'a> {
+    account: Option<(&'a str, &'a AccountPool)>,
+    account_name: &'a str,
+    client: &'a ClientInfo,
+    model: &'a str,
+    start: std::time::Instant,
+}
+
+impl ServerToolRound<'_> {
+    /// 记录成功的中间轮次：计入请求记录和额度账本
+    async fn record_success(&self, payload: &MessagesRequest, turn: &ToolTurn) {
+        let Some((id, pool)) = self.account else {
+            return;
+        };
+        let input_tokens = match turn.context_usage_percentage {
+            Some(percentage) => (percentage * (CONTEXT_WINDOW_SIZE as f64) / 100.0) as i32,
+            None => {
+                token::count_all_tokens(
+                    payload.model.clone(),
+                    payload.system.clone(),
+                    payload.messages.clone(),
+                    payload.tools.clone(),
+                )
+                .await as i32
+            }
+        };
+        let output_tokens = token::estimate_output_tokens(&turn.content());
+        pool.record_metering(
+            id,
+            turn.metering_usage,
+            turn.metering_unit.as_deref(),
+            input_tokens,
+            output_tokens,
+        )
+        .await;
+        pool.add_request_log(self.log(input_tokens, output_tokens, None))
+            .await;
+    }
+
+    /// 记录失败的轮次：计入账号错误和请求记录
+    async fn record_failure(&self, e: &KiroError) {
+        let Some((id, pool)) = self.account else {
+            return;
+        };
+        record_account_error(pool, id, e).await;
+        pool.add_request_log(self.log(0, 0, Some(e.to_string())))
+            .await;
+    }
+
+    /// 本轮的请求记录
+    fn log(
+        &self,
+        input_tokens: i32,
+        output_tokens: i32,
+        error: Option<String>,
+    ) -> crate::pool::RequestLog {
+        crate::pool::RequestLog {
+            id: Uuid::new_v4().to_string(),
+            account_id: self
+                .account
+                .map(|(id, _)| id.to_string())
+                .unwrap_or_default(),
+            account_name: self.account_name.to_string(),
+            model: self.model.to_string(),
+            input_tokens,
+            output_tokens,
+            success: error.is_none(),
+            error,
+            timestamp: chrono::Utc::now(),
+            duration_ms: self.start.elapsed().as_millis() as u64,
+            client: self.client.clone(),
+        }
+    }
+}
+
+/// `?continue=true`：响应因 max_tokens 截断时带上已生成的文本继续请求，并拼接各轮内容
+///
+/// 续写轮次失败或剩余时间不足以完成下一轮时返回已拼接的内容（stop_reason 仍为 max_tokens）
+#[allow(clippy::too_many_arguments)]
+async fn continue_truncated(
+    state: &AppState,
+    mut payload: MessagesRequest,
+    response: Response,
+    provider: std::sync::Arc<KiroProvider>,
+    profile_arn: Option<String>,
+    agent_mode: AgentMode,
+    overrides: &RequestOverrides,
+    account_id: Option<String>,
+    account_name: String,
+    pool: Option<std::sync::Arc<AccountPool>>,
+    client: &ClientInfo,
+    start_time: std::time::Instant,
+    deadline: Option<Deadline>,
+    progress: &Progress,
+) -> Response {
+    let (parts, mut body) = match continuation::read_json(response).await {
+        Ok(json) => json,
+        Err(response) => return response,
+    };
+    let mut last = body.clone();
+    let thinking_enabled = payload
+        .thinking
+        .as_ref()
+        .is_some_and(|t| t.thinking_type == "enabled");
+    for round in 1..=continuation::MAX_CONTINUATIONS {
+        let Some(text) = continuation::truncated_text(&last) else {
+            break;
+        };
+        if deadline.is_some_and(|d| !d.allows(progress.estimate())) {
+            tracing::warn!("剩余时间不足以完成续写，返回已生成的内容");
+            break;
+        }
+        tracing::info!("响应因 max_tokens 截断，自动续写（第 {} 轮）", round);
+        progress.stage(Stage::Continuation);
+        continuation::append_partial(&mut payload, &text);
+
+        let Ok(conversion) = prepare_request(state, &mut payload, agent_mode, overrides).await
+        else {
+            break;
+        };
+        let kiro_request = KiroRequest {
+            conversation_state: conversion.conversation_state,
+            profile_arn: profile_arn.clone(),
+        };
+        let request_body = match serde_json::to_string(&kiro_request) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("序列化续写请求失败: {}", e);
+                break;
+            }
+        };
+        let input_tokens = token::count_all_tokens(
+            payload.model.clone(),
+            payload.system.clone(),
+            payload.messages.clone(),
+            payload.tools.clone(),
+        )
+        .await as i32;
+
+        let call_start = std::time::Instant::now();
+        let response = handle_non_stream_request(
+            provider.clone(),
+            &request_body,
+            agent_mode,
+            &payload.model,
+            input_tokens,
+            thinking_enabled,
+            state.event_filter,
+            state.post_process.clone(),
+            state.chaos.clone(),
+            state.decoder,
+            std::sync::Arc::new(conversion.tool_aliases),
+            payload.disable_parallel_tool_use(),
+            account_id.clone(),
+            account_name.clone(),
+            pool.clone(),
+            client.clone(),
+            start_time,
+            None,
+        )
+        .await;
+        progress.record_call(call_start.elapsed());
+        match continuation::read_json(response).await {
+            Ok((_, next)) => {
+                continuation::stitch(&mut body, &next);
+                last = next;
+            }
+            Err(response) => {
+                tracing::warn!("续写请求失败（{}），返回已生成的内容", response.status());
+                break;
+            }
+        }
+    }
+    continuation::into_response(parts, &body)
+}
+
+/// 确定请求的 Kiro 代理模式，并去掉模型名中的模式后缀（如 `claude-sonnet-4-5:spec`）
+pub(super) fn resolve_agent_mode(
+    headers: &HeaderMap,
+    payload: &mut MessagesRequest,
+    default: AgentMode,
+) -> AgentMode {
+    let suffix_mode = match AgentMode::split_model_suffix(&payload.model) {
+        (model, Some(mode)) => {
+            payload.model = model.to_string();
+            Some(mode)
+        }
+        (_, None) => None,
+    };
+    headers
+        .get("x-kiro-agent-mode")
+        .and_then(|v| v.to_str().ok())
+        .and_then(AgentMode::parse)
+        .or(suffix_mode)
+        .unwrap_or(default)
+}
+
+/// 覆盖 agentTaskType 的请求头
+const AGENT_TASK_TYPE_HEADER: &str = "x-kiro-agent-task-type";
+
+/// 覆盖 thinking 预算的请求头，0 表示关闭 thinking
+const THINKING_BUDGET_HEADER: &str = "x-thinking-budget";
+
+/// 覆盖用户消息 origin 的请求头
+const ORIGIN_HEADER: &str = "x-kiro-origin";
+
+/// 覆盖 chatTriggerType 的请求头
+const CHAT_TRIGGER_TYPE_HEADER: &str = "x-kiro-chat-trigger-type";
+
+/// 标识会话的请求头，同一会话的请求复用上游会话 ID
+const CONVERSATION_ID_HEADER: &str = "x-kiro-conversation-id";
+
+/// 请求头指定的上游请求字段
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(super) struct RequestOverrides {
+    pub task_type: Option<String>,
+    pub origin: Option<KiroOrigin>,
+    pub chat_trigger_type: Option<ChatTriggerType>,
+    /// 会话缓存键（按调用方隔离）
+    pub conversation: Option<String>,
+}
+
+/// 流式请求合并键：请求体、代理模式、调用方和请求头覆盖的上游字段
+///
+/// 代理模式和上游字段可能来自请求头；不同调用方（托管 API Key、工作区）使用各自的账号池和限额，
+/// 即使请求完全相同也不能合并
+fn coalesce_key(
+    payload: &MessagesRequest,
+    agent_mode: AgentMode,
+    caller: &str,
+    overrides: &RequestOverrides,
+) -> Option<String> {
+    let mut body = serde_json::to_vec(payload).ok()?;
+    let parts = [
+        Some(agent_mode.as_str()),
+        Some(caller),
+        overrides.task_type.as_deref(),
+        overrides.origin.map(KiroOrigin::as_str),
+        overrides.chat_trigger_type.map(ChatTriggerType::as_str),
+        overrides.conversation.as_deref(),
+    ];
+    for part in parts {
+        body.push(0);
+        body.extend_from_slice(part.unwrap_or("-").as_bytes());
+    }
+    Some(StreamCoalescer::key_for(&body))
+}
+
+/// 会话缓存键：`x-kiro-conversation-id` 加上调用方前缀，不同租户的同名会话互不影响
+fn conversation_key(headers: &HeaderMap, caller: &str) -> Option<String> {
+    headers
+        .get(CONVERSATION_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| format!("{}/{}", caller, id))
+}
+
+/// 应用覆盖请求头：thinking 预算直接写入请求，返回其余覆盖值，请求头无效时返回错误信息
+///
+/// 只有可信密钥的请求生效，工作区密钥携带的覆盖请求头被忽略
+fn apply_header_overrides(
+    headers: &HeaderMap,
+    payload: &mut MessagesRequest,
+    trusted: bool,
+) -> Result<RequestOverrides, String> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+    };
+    let task_type = header(AGENT_TASK_TYPE_HEADER).filter(|v| !v.is_empty());
+    let budget = header(THINKING_BUDGET_HEADER);
+    let origin = header(ORIGIN_HEADER).filter(|v| !v.is_empty());
+    let trigger = header(CHAT_TRIGGER_TYPE_HEADER).filter(|v| !v.is_empty());
+    if task_type.is_none() && budget.is_none() && origin.is_none() && trigger.is_none() {
+        return Ok(RequestOverrides::default());
+    }
+    if !trusted {
+        tracing::debug!("非可信密钥，忽略覆盖请求头");
+        return Ok(RequestOverrides::default());
+    }
+
+    if let Some(budget) = budget {
+        match budget.parse::<i32>() {
+            Ok(0) => payload.thinking = None,
+            Ok(tokens) if tokens > 0 => {
+                let thinking = Thinking::enabled(tokens);
+                if thinking.budget_tokens >= payload.max_tokens {
+                    return Err(format!(
+                        "{} 必须小于 max_tokens（{}）: {}",
+                        THINKING_BUDGET_HEADER, payload.max_tokens, budget
+                    ));
+                }
+                payload.thinking = Some(thinking);
+            }
+            _ => {
+                return Err(format!(
+                    "{} 必须是非负整数: {}",
+                    THINKING_BUDGET_HEADER, budget
+                ))
+            }
+        }
+    }
+    if let Some(task_type) = task_type {
+        tracing::debug!("请求头覆盖 agentTaskType: {}", task_type);
+    }
+    let origin = origin
+        .map(|v| {
+            KiroOrigin::parse(v).ok_or_else(|| {
+                format!(
+                    "{} 无效: {}（可选值: AI_EDITOR, CLI, IDE, CONSOLE）",
+                    ORIGIN_HEADER, v
+                )
+            })
+        })
+        .transpose()?;
+    let chat_trigger_type = trigger
+        .map(|v| {
+            ChatTriggerType::parse(v).ok_or_else(|| {
+                format!(
+                    "{} 无效: {}（可选值: MANUAL, AUTO, DIAGNOSTIC, INLINE_CHAT）",
+                    CHAT_TRIGGER_TYPE_HEADER, v
+                )
+            })
+        })
+        .transpose()?;
+    Ok(RequestOverrides {
+        task_type: task_type.map(str::to_string),
+        origin,
+        chat_trigger_type,
+        conversation: None,
+    })
+}
+
+/// 转换请求，转换器 panic 时先上报遥测再继续传播 panic
+fn convert_with_telemetry(
+    state: &AppState,
+    payload: &MessagesRequest,
+) -> Result<ConversionResult, ConversionError> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| convert_request(payload)))
+        .unwrap_or_else(|panic| {
+            state.report_failure(ConverterFailure::new(
+                FailureKind::Panic,
+                "converter_panic",
+                "",
+                &payload.model,
+            ));
+            std::panic::resume_unwind(panic)
+        })
+}
+
+/// 试运行：返回转换后的 Kiro 请求和估算的输入 tokens，不选择账号、不调用上游
+///
+/// 账号池模式下每个账号的 profileArn 不同，试运行不选择账号，只以占位符表示
+async fn dry_run(
+    state: &AppState,
+    mut payload: MessagesRequest,
+    agent_mode: AgentMode,
+    overrides: &RequestOverrides,
+) -> Response {
+    tracing::info!("试运行请求转换，不调用上游");
+
+    let conversion_result = match prepare_request(state, &mut payload, agent_mode, overrides).await
+    {
+        Ok(result) => result,
+        Err(response) => return response,
+    };
+    let truncated = conversion_result.truncated;
+
+    let has_profile_arn = state.profile_arn.is_some()
+        || state.account_pool.is_some()
+        || match &state.kiro_provider {
+            Some(provider) => provider.profile_arn().await.is_some(),
+            None => false,
+        };
+    let kiro_request = KiroRequest {
+        conversation_state: conversion_result.conversation_state,
+        profile_arn: has_profile_arn.then(|| REDACTED.to_string()),
+    };
+    let request_bytes = serde_json::to_vec(&kiro_request)
+        .map(|body| body.len())
+        .unwrap_or_default();
+
+    let input_tokens = token::count_all_tokens(
+        payload.model,
+        payload.system,
+        payload.messages,
+        payload.tools,
+    )
+    .await as i32;
 
-    // 检查是否启用了thinking
-    let thinking_enabled = payload
-        .thinking
-        .as_ref()
-        .map(|t| t.thinking_type == "enabled")
-        .unwrap_or(false);
+    let mut response = Json(DryRunResponse {
+        kiro_request,
+        input_tokens,
+        request_bytes,
+    })
+    .into_response();
+    apply_truncation_headers(&mut response, truncated);
+    response
+}
 
-    if payload.stream {
-        // 流式响应
-        handle_stream_request(
-            provider,
-            &request_body,
-            &payload.model,
-            input_tokens,
-            thinking_enabled,
-            account_id,
-            account_name,
-            pool_ref,
-            start_time,
-        )
-        .await
-    } else {
-        // 非流式响应
-        handle_non_stream_request(
-            provider,
-            &request_body,
-            &payload.model,
-            input_tokens,
-            account_id,
-            account_name,
-            pool_ref,
-            start_time,
-        )
-        .await
+/// 启用 Files API 时解析消息中的 file_id 引用
+async fn resolve_file_references_if_enabled(
+    state: &AppState,
+    payload: &mut MessagesRequest,
+) -> Result<(), ConversionError> {
+    match &state.file_store {
+        Some(store) => resolve_file_references(&mut payload.messages, store).await,
+        None => Ok(()),
     }
 }
 
@@ -222,78 +1292,116 @@ pub async fn post_messages(
 struct StreamStats {
     output_tokens: i32,
     input_tokens: i32,
+    metering_usage: f64,
+    metering_unit: Option<String>,
 }
 
 /// 处理流式请求
-async fn handle_stream_request(
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn handle_stream_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
     request_body: &str,
+    agent_mode: AgentMode,
     model: &str,
     input_tokens: i32,
+    max_tokens: i32,
     thinking_enabled: bool,
+    event_filter: EventFilter,
+    post_process: std::sync::Arc<PostProcessConfig>,
+    chaos: Option<ChaosConfig>,
+    decoder: DecoderConfig,
+    sse: SseConfig,
+    tool_aliases: std::sync::Arc<ToolAliases>,
+    single_tool_call: bool,
     account_id: Option<String>,
     account_name: String,
     pool: Option<std::sync::Arc<crate::pool::AccountPool>>,
+    client: ClientInfo,
     start_time: std::time::Instant,
+    publisher: Option<Publisher>,
+    format: StreamFormat,
+    prefetched: Option<(Bytes, UpstreamHeaders)>,
+    deadline: Option<tokio::time::Instant>,
+    idle_timeout: Option<Duration>,
 ) -> Response {
-    // 调用 Kiro API
-    let response = match provider.call_api_stream(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => {
-            let error_msg = e.to_string();
-            tracing::error!("Kiro API 调用失败: {}", error_msg);
+    // 调用 Kiro API（内置工具循环已取得最后一轮响应时直接使用）
+    let (upstream, upstream_headers) = match prefetched {
+        Some((body, headers)) => (
+            stream::iter([Ok::<_, reqwest::Error>(body)]).left_stream(),
+            headers,
+        ),
+        None => match provider.call_api_stream(request_body, agent_mode).await {
+            Ok(resp) => {
+                let headers = UpstreamHeaders::capture(resp.headers());
+                (resp.bytes_stream().right_stream(), headers)
+            }
+            Err(e) => {
+                let error_msg = e.to_string();
+                tracing::error!("Kiro API 调用失败: {}", error_msg);
 
-            // 记录错误到账号池
-            if let (Some(id), Some(pool)) = (&account_id, &pool) {
-                let is_rate_limit = error_msg.contains("429") || error_msg.contains("rate");
-                let is_suspended = error_msg.contains("suspended") || error_msg.contains("403");
-
-                if is_suspended {
-                    pool.mark_invalid(id).await;
-                    tracing::warn!("账号 {} 已被标记为失效（暂停）", id);
-                } else {
-                    pool.record_error(id, is_rate_limit).await;
-                    tracing::warn!("账号 {} 记录错误，限流: {}", id, is_rate_limit);
+                // 记录错误到账号池
+                if let (Some(id), Some(pool)) = (&account_id, &pool) {
+                    record_account_error(pool, id, &e).await;
+
+                    // 记录失败的请求
+                    let log = crate::pool::RequestLog {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        account_id: id.clone(),
+                        account_name: account_name.clone(),
+                        model: model.to_string(),
+                        input_tokens,
+                        output_tokens: 0,
+                        success: false,
+                        error: Some(error_msg.clone()),
+                        timestamp: chrono::Utc::now(),
+                        duration_ms: start_time.elapsed().as_millis() as u64,
+                        client: client.clone(),
+                    };
+                    pool.add_request_log(log).await;
                 }
 
-                // 记录失败的请求
-                let log = crate::pool::RequestLog {
-                    id: uuid::Uuid::new_v4().to_string(),
-                    account_id: id.clone(),
-                    account_name: account_name.clone(),
-                    model: model.to_string(),
-                    input_tokens,
-                    output_tokens: 0,
-                    success: false,
-                    error: Some(error_msg.clone()),
-                    timestamp: chrono::Utc::now(),
-                    duration_ms: start_time.elapsed().as_millis() as u64,
-                };
-                pool.add_request_log(log).await;
+                return upstream_failure(&e, request_body);
             }
-
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
-        }
+        },
     };
 
+    // 同步 Token 刷新时自动发现的 profileArn
+    if let (Some(id), Some(pool)) = (&account_id, &pool) {
+        pool.sync_profile_arn(id).await;
+    }
+
     // 创建 channel 用于在流结束时传递统计信息
     let (stats_tx, stats_rx) = tokio::sync::oneshot::channel::<StreamStats>();
 
-    // 创建流处理上下文
-    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled);
-
-    // 生成初始事件
-    let initial_events = ctx.generate_initial_events();
-
-    // 创建 SSE 流（传入 stats_tx）
-    let stream = create_sse_stream(response, ctx, initial_events, Some(stats_tx));
+    // 组装处理管道：解码 → 文本后处理 → 还原工具名称 → 限制并行工具调用 → 映射为 Anthropic 事件（含保活） → 合并文本增量 → 序列化
+    let ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled)
+        .with_event_filter(event_filter)
+        .with_max_tokens(max_tokens);
+    let keepalive =
+        (!event_filter.suppress_ping).then_some(Duration::from_secs(PING_INTERVAL_SECS));
+    let events = pipeline::enforce_timeouts(
+        pipeline::decode_events(chaos::inject_stream(upstream, chaos), decoder),
+        deadline,
+        idle_timeout,
+    );
+    let events = pipeline::post_process(pipeline::watchdog(events), post_process);
+    let events = pipeline::map_to_anthropic(
+        pipeline::single_tool_call(
+            pipeline::restore_tool_names(events, tool_aliases),
+            single_tool_call,
+        ),
+        ctx,
+        keepalive,
+        move |ctx| send_stream_stats(ctx, stats_tx),
+    );
+    let coalesce_interval =
+        (sse.coalesce_interval_ms > 0).then(|| Duration::from_millis(sse.coalesce_interval_ms));
+    let events = pipeline::coalesce_text_deltas(events, coalesce_interval, sse.coalesce_max_bytes);
+    let stream = pipeline::with_padding(
+        pipeline::serialize(events, format),
+        format,
+        sse.padding_bytes,
+    );
 
     // 异步等待流结束并记录日志
     if let (Some(id), Some(pool)) = (account_id, pool) {
@@ -301,6 +1409,14 @@ async fn handle_stream_request(
         tokio::spawn(async move {
             match stats_rx.await {
                 Ok(stats) => {
+                    pool.record_metering(
+                        &id,
+                        stats.metering_usage,
+                        stats.metering_unit.as_deref(),
+                        stats.input_tokens,
+                        stats.output_tokens,
+                    )
+                    .await;
                     let log = crate::pool::RequestLog {
                         id: uuid::Uuid::new_v4().to_string(),
                         account_id: id,
@@ -312,6 +1428,7 @@ async fn handle_stream_request(
                         error: None,
                         timestamp: chrono::Utc::now(),
                         duration_ms: start_time.elapsed().as_millis() as u64,
+                        client: client.clone(),
                     };
                     pool.add_request_log(log).await;
                     tracing::debug!("流式请求完成，output_tokens: {}", stats.output_tokens);
@@ -329,6 +1446,7 @@ async fn handle_stream_request(
                         error: Some("客户端可能提前断开".to_string()),
                         timestamp: chrono::Utc::now(),
                         duration_ms: start_time.elapsed().as_millis() as u64,
+                        client: client.clone(),
                     };
                     pool.add_request_log(log).await;
                     tracing::warn!("流式请求统计 channel 关闭，可能客户端断开");
@@ -337,204 +1455,314 @@ async fn handle_stream_request(
         });
     }
 
-    // 返回 SSE 响应
-    Response::builder()
+    // 启用合并时，Leader 直接输出上游流并广播给被合并的请求，客户端断开时上游请求随之取消
+    // （流先装箱，避免处理管道的类型再嵌套一层导致编译期内存暴涨）
+    let body = match publisher {
+        Some(publisher) => Body::from_stream(publisher.tee(stream.boxed())),
+        None => Body::from_stream(stream),
+    };
+
+    let mut response = stream_response(body, format, &sse);
+    upstream_headers.apply(response.headers_mut());
+    response
+}
+
+/// 上游调用失败时更新账号状态：暂停的账号标记为失效，限流的账号进入冷却，其他错误计入错误次数
+///
+/// 请求格式错误和本地校验错误与账号无关，不计入错误次数
+async fn record_account_error(pool: &AccountPool, id: &str, e: &KiroError) {
+    if matches!(e, KiroError::Validation(_)) || diagnose::is_malformed_request(&e.to_string()) {
+        return;
+    }
+    // 上游过载与账号无关：整个账号池短暂退避，不让该账号进入限流冷却
+    if let KiroError::Overloaded { retry_after, .. } = e {
+        pool.record_overload(*retry_after);
+        return;
+    }
+    let is_rate_limit = e.is_throttled();
+
+    if e.is_suspended() {
+        pool.mark_invalid(id).await;
+        tracing::warn!("账号 {} 已被标记为失效（暂停）", id);
+    } else if pool.handle_unauthorized(id, e).await {
+        return;
+    } else {
+        pool.record_error(id, is_rate_limit).await;
+        tracing::warn!("账号 {} 记录错误，限流: {}", id, is_rate_limit);
+    }
+    pool.handle_refresh_error(id, e).await;
+}
+
+/// 上游调用失败的响应
+///
+/// 上游限流时返回 429，上游过载时返回 529 overloaded_error（均带上游的 `retry-after`）；上游报告请求格式错误时返回 400，并根据转换后的请求
+/// 指出最可能出错的字段；其他错误返回 502。上游返回了响应时附带采集到的 `x-upstream-*` 响应头
+fn upstream_failure(e: &KiroError, request_body: &str) -> Response {
+    let upstream_headers = e.upstream().map(|upstream| &upstream.headers);
+    if let Some(request_id) = upstream_headers.and_then(UpstreamHeaders::request_id) {
+        tracing::warn!("上游请求 ID: {}", request_id);
+    }
+    let mut response = upstream_failure_response(e, request_body);
+    if let Some(headers) = upstream_headers {
+        headers.apply(response.headers_mut());
+    }
+    response
+}
+
+fn upstream_failure_response(e: &KiroError, request_body: &str) -> Response {
+    if let KiroError::Overloaded { retry_after, .. } = e {
+        return overloaded_response(format!("上游服务过载: {}", e), *retry_after);
+    }
+    if let KiroError::Throttled { retry_after, .. } = e {
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(ErrorResponse::new(
+                "rate_limit_error",
+                format!("上游 API 限流: {}", e),
+            )),
+        )
+            .into_response();
+        if let Some(retry_after) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.as_secs().max(1).into());
+        }
+        return response;
+    }
+    if !diagnose::is_malformed_request(&e.to_string()) {
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ErrorResponse::new(
+                "api_error",
+                format!("上游 API 调用失败: {}", e),
+            )),
+        )
+            .into_response();
+    }
+
+    let suspects = diagnose::diagnose(request_body);
+    for suspect in &suspects {
+        tracing::warn!("可疑字段 {}: {}", suspect.field, suspect.reason);
+    }
+    tracing::debug!("格式错误的 Kiro 请求: {}", diagnose::redact(request_body));
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse::new(
+            "invalid_request_error",
+            diagnose::error_message(&suspects),
+        )),
+    )
+        .into_response()
+}
+
+/// 构建流式响应（SSE 或 NDJSON）
+fn stream_response(body: Body, format: StreamFormat, sse: &SseConfig) -> Response {
+    let mut builder = Response::builder()
         .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CONTENT_TYPE, format.content_type())
         .header(header::CACHE_CONTROL, "no-cache")
-        .header(header::CONNECTION, "keep-alive")
-        .body(Body::from_stream(stream))
-        .unwrap()
+        .header(header::CONNECTION, "keep-alive");
+    if sse.disable_proxy_buffering {
+        builder = builder.header("x-accel-buffering", "no");
+    }
+    builder.body(body).unwrap()
 }
 
 /// Ping 事件间隔（25秒）
 const PING_INTERVAL_SECS: u64 = 25;
 
-/// 创建 ping 事件的 SSE 字符串
-fn create_ping_sse() -> Bytes {
-    Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n")
-}
-
-/// 创建 SSE 事件流
-fn create_sse_stream(
-    response: reqwest::Response,
-    ctx: StreamContext,
-    initial_events: Vec<SseEvent>,
-    stats_tx: Option<tokio::sync::oneshot::Sender<StreamStats>>,
-) -> impl Stream<Item = Result<Bytes, Infallible>> {
-    // 先发送初始事件
-    let initial_stream = stream::iter(
-        initial_events
-            .into_iter()
-            .map(|e| Ok(Bytes::from(e.to_sse_string()))),
-    );
-
-    // 然后处理 Kiro 响应流，同时每25秒发送 ping 保活
-    let body_stream = response.bytes_stream();
-
-    let processing_stream = stream::unfold(
-        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS)), stats_tx),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval, stats_tx)| async move {
-            if finished {
-                return None;
-            }
-
-            // 使用 select! 同时等待数据和 ping 定时器
-            tokio::select! {
-                // 处理数据流
-                chunk_result = body_stream.next() => {
-                    match chunk_result {
-                        Some(Ok(chunk)) => {
-                            // 解码事件
-                            if let Err(e) = decoder.feed(&chunk) {
-                                tracing::warn!("缓冲区溢出: {}", e);
-                            }
-
-                            let mut events = Vec::new();
-                            for result in decoder.decode_iter() {
-                                match result {
-                                    Ok(frame) => {
-                                        if let Ok(event) = Event::from_frame(frame) {
-                                            let sse_events = ctx.process_kiro_event(&event);
-                                            events.extend(sse_events);
-                                        }
-                                    }
-                                    Err(e) => {
-                                        tracing::warn!("解码事件失败: {}", e);
-                                    }
-                                }
-                            }
-
-                            // 转换为 SSE 字节流
-                            let bytes: Vec<Result<Bytes, Infallible>> = events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
-
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, stats_tx)))
-                        }
-                        Some(Err(e)) => {
-                            tracing::error!("读取响应流失败: {}", e);
-                            // 发送最终事件并结束
-                            let final_events = ctx.generate_final_events();
-
-                            // 发送统计信息
-                            let final_input_tokens = ctx.context_input_tokens.unwrap_or(ctx.input_tokens);
-                            if let Some(tx) = stats_tx {
-                                let _ = tx.send(StreamStats {
-                                    output_tokens: ctx.output_tokens,
-                                    input_tokens: final_input_tokens,
-                                });
-                            }
-
-                            let bytes: Vec<Result<Bytes, Infallible>> = final_events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, None)))
-                        }
-                        None => {
-                            // 流结束，发送最终事件
-                            let final_events = ctx.generate_final_events();
-
-                            // 发送统计信息
-                            let final_input_tokens = ctx.context_input_tokens.unwrap_or(ctx.input_tokens);
-                            if let Some(tx) = stats_tx {
-                                let _ = tx.send(StreamStats {
-                                    output_tokens: ctx.output_tokens,
-                                    input_tokens: final_input_tokens,
-                                });
-                            }
-
-                            let bytes: Vec<Result<Bytes, Infallible>> = final_events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval, None)))
-                        }
-                    }
-                }
-                // 发送 ping 保活
-                _ = ping_interval.tick() => {
-                    tracing::trace!("发送 ping 保活事件");
-                    let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval, stats_tx)))
-                }
-            }
-        },
-    )
-    .flatten();
-
-    initial_stream.chain(processing_stream)
+/// 流结束时发送统计信息
+fn send_stream_stats(ctx: &StreamContext, stats_tx: tokio::sync::oneshot::Sender<StreamStats>) {
+    let _ = stats_tx.send(StreamStats {
+        output_tokens: ctx.output_tokens,
+        input_tokens: ctx.context_input_tokens.unwrap_or(ctx.input_tokens),
+        metering_usage: ctx.metering_usage,
+        metering_unit: ctx.metering_unit.clone(),
+    });
 }
 
 /// 上下文窗口大小（200k tokens）
 const CONTEXT_WINDOW_SIZE: i32 = 200_000;
 
 /// 处理非流式请求
-async fn handle_non_stream_request(
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn handle_non_stream_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
     request_body: &str,
+    agent_mode: AgentMode,
     model: &str,
     input_tokens: i32,
+    thinking_enabled: bool,
+    event_filter: EventFilter,
+    post_process: std::sync::Arc<PostProcessConfig>,
+    chaos: Option<ChaosConfig>,
+    decoder: DecoderConfig,
+    tool_aliases: std::sync::Arc<ToolAliases>,
+    single_tool_call: bool,
     account_id: Option<String>,
     account_name: String,
     pool: Option<std::sync::Arc<crate::pool::AccountPool>>,
+    client: ClientInfo,
     start_time: std::time::Instant,
+    prefetched: Option<(Bytes, UpstreamHeaders)>,
 ) -> Response {
-    // 调用 Kiro API
-    let response = match provider.call_api(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => {
-            let error_msg = e.to_string();
-            tracing::error!("Kiro API 调用失败: {}", error_msg);
+    // 写入预写日志，请求 ID 与请求记录共用
+    let request_id = uuid::Uuid::new_v4().to_string();
+    if let (Some(id), Some(pool)) = (&account_id, &pool) {
+        pool.journal_begin(&request_id, model, id).await;
+    }
 
-            // 记录错误到账号池
-            if let (Some(id), Some(pool)) = (&account_id, &pool) {
-                let is_rate_limit = error_msg.contains("429") || error_msg.contains("rate");
-                let is_suspended = error_msg.contains("suspended") || error_msg.contains("403");
-
-                if is_suspended {
-                    pool.mark_invalid(id).await;
-                    tracing::warn!("账号 {} 已被标记为失效（暂停）", id);
-                } else {
-                    pool.record_error(id, is_rate_limit).await;
-                    tracing::warn!("账号 {} 记录错误，限流: {}", id, is_rate_limit);
+    // 调用 Kiro API（内置工具循环已取得最后一轮响应时直接使用）
+    // 响应体边接收边解码，不缓存完整的原始字节；读取失败时记录错误，解码结束后返回 502
+    let read_error = std::sync::Arc::new(std::sync::Mutex::new(None::<KiroError>));
+    let (body, upstream_headers) = match prefetched {
+        Some((bytes, headers)) => (stream::iter([Ok(bytes)]).boxed(), headers),
+        None => {
+            let response = match provider.call_api(request_body, agent_mode).await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    let error_msg = e.to_string();
+                    tracing::error!("Kiro API 调用失败: {}", error_msg);
+
+                    // 记录错误到账号池
+                    if let (Some(id), Some(pool)) = (&account_id, &pool) {
+                        pool.journal_finish(&request_id, Some(&error_msg)).await;
+                        record_account_error(pool, id, &e).await;
+
+                        // 记录失败的请求
+                        let log = crate::pool::RequestLog {
+                            id: request_id.clone(),
+                            account_id: id.clone(),
+                            account_name: account_name.clone(),
+                            model: model.to_string(),
+                            input_tokens,
+                            output_tokens: 0,
+                            success: false,
+                            error: Some(error_msg.clone()),
+                            timestamp: chrono::Utc::now(),
+                            duration_ms: start_time.elapsed().as_millis() as u64,
+                            client: client.clone(),
+                        };
+                        pool.add_request_log(log).await;
+                    }
+
+                    return upstream_failure(&e, request_body);
                 }
+            };
 
-                // 记录失败的请求
-                let log = crate::pool::RequestLog {
-                    id: uuid::Uuid::new_v4().to_string(),
-                    account_id: id.clone(),
-                    account_name: account_name.clone(),
-                    model: model.to_string(),
-                    input_tokens,
-                    output_tokens: 0,
-                    success: false,
-                    error: Some(error_msg.clone()),
-                    timestamp: chrono::Utc::now(),
-                    duration_ms: start_time.elapsed().as_millis() as u64,
-                };
-                pool.add_request_log(log).await;
+            // 同步 Token 刷新时自动发现的 profileArn
+            if let (Some(id), Some(pool)) = (&account_id, &pool) {
+                pool.sync_profile_arn(id).await;
             }
 
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("上游 API 调用失败: {}", e),
-                )),
-            )
-                .into_response();
+            let headers = UpstreamHeaders::capture(response.headers());
+            let slot = read_error.clone();
+            let body = response.bytes_stream().map(move |chunk| {
+                chunk.map_err(|e| {
+                    let message = e.to_string();
+                    *slot.lock().unwrap() = Some(KiroError::from(e));
+                    message
+                })
+            });
+            (body.boxed(), headers)
         }
     };
 
-    // 读取响应体
-    let body_bytes = match response.bytes().await {
-        Ok(bytes) => bytes,
-        Err(e) => {
+    // 解析事件流，对文本做后处理，并将工具调用的参数片段拼接完整
+    let events = pipeline::decode_events(chaos::inject_stream(body, chaos), decoder);
+    let events = pipeline::post_process(pipeline::watchdog(events), post_process);
+    let events = pipeline::assemble_tool_calls(events);
+    let events = pipeline::restore_tool_names(events, tool_aliases);
+    let mut events = std::pin::pin!(pipeline::single_tool_call(events, single_tool_call));
+
+    let mut text_content = String::new();
+    let mut tool_uses: Vec<serde_json::Value> = Vec::new();
+    let mut has_tool_use = false;
+    let mut stop_reason: Option<&str> = None;
+    let mut completion_status = None;
+    // 从 contextUsageEvent 计算的实际输入 tokens
+    let mut context_input_tokens: Option<i32> = None;
+    let mut context_usage_percentage: Option<f64> = None;
+    // meteringEvent 报告的额度消耗
+    let mut metering_usage = 0.0;
+    let mut metering_unit: Option<String> = None;
+    // 上游在事件流中返回的错误或异常（收到后不再继续读取）
+    let mut upstream_error = None;
+
+    while let Some(event) = events.next().await {
+        match event {
+            Event::AssistantResponse(resp) => {
+                text_content.push_str(&resp.content);
+                completion_status = resp.completion_status().or(completion_status);
+            }
+            Event::ToolUse(tool_use) => {
+                has_tool_use = true;
+
+                let input: serde_json::Value = serde_json::from_str(&tool_use.input)
+                    .unwrap_or_else(|e| {
+                        tracing::warn!(
+                            "工具输入 JSON 解析失败: {}, tool_use_id: {}, 原始内容: {}",
+                            e,
+                            tool_use.tool_use_id,
+                            tool_use.input
+                        );
+                        serde_json::json!({})
+                    });
+
+                tool_uses.push(json!({
+                    "type": "tool_use",
+                    "id": tool_use.tool_use_id,
+                    "name": tool_use.name,
+                    "input": input
+                }));
+            }
+            Event::ContextUsage(context_usage) => {
+                // 从上下文使用百分比计算实际的 input_tokens
+                // 公式: percentage * 200000 / 100 = percentage * 2000
+                let actual_input_tokens = (context_usage.context_usage_percentage
+                    * (CONTEXT_WINDOW_SIZE as f64)
+                    / 100.0) as i32;
+                context_input_tokens = Some(actual_input_tokens);
+                context_usage_percentage = Some(context_usage.context_usage_percentage);
+                tracing::debug!(
+                    "收到 contextUsageEvent: {}%, 计算 input_tokens: {}",
+                    context_usage.context_usage_percentage,
+                    actual_input_tokens
+                );
+            }
+            Event::Metering(metering) => {
+                metering_usage += metering.usage;
+                metering_unit = metering.unit.or(metering_unit);
+            }
+            Event::Exception { exception_type, .. }
+                if exception_type == "ContentLengthExceededException" =>
+            {
+                stop_reason = Some("max_tokens");
+            }
+            Event::Exception {
+                exception_type,
+                message,
+            } => {
+                upstream_error = Some(KiroError::from_event(&exception_type, &message));
+                break;
+            }
+            Event::Error {
+                error_code,
+                error_message,
+            } => {
+                upstream_error = Some(KiroError::from_event(&error_code, &error_message));
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    // 读取响应体中途失败或上游返回错误事件：已解码的部分内容不完整，不作为成功响应返回
+    let read_error = read_error.lock().unwrap().take();
+    let failure = match (read_error, upstream_error) {
+        (Some(e), _) => {
             tracing::error!("读取响应体失败: {}", e);
-            return (
+            let response = (
                 StatusCode::BAD_GATEWAY,
                 Json(ErrorResponse::new(
                     "api_error",
@@ -542,94 +1770,46 @@ async fn handle_non_stream_request(
                 )),
             )
                 .into_response();
+            Some((e, response))
         }
+        (None, Some(e)) => {
+            tracing::error!("Kiro API 调用失败: {}", e);
+            let mut response = upstream_failure(&e, request_body);
+            upstream_headers.apply(response.headers_mut());
+            Some((e, response))
+        }
+        (None, None) => None,
     };
-
-    // 解析事件流
-    let mut decoder = EventStreamDecoder::new();
-    if let Err(e) = decoder.feed(&body_bytes) {
-        tracing::warn!("缓冲区溢出: {}", e);
-    }
-
-    let mut text_content = String::new();
-    let mut tool_uses: Vec<serde_json::Value> = Vec::new();
-    let mut has_tool_use = false;
-    let mut stop_reason = "end_turn".to_string();
-    // 从 contextUsageEvent 计算的实际输入 tokens
-    let mut context_input_tokens: Option<i32> = None;
-
-    // 收集工具调用的增量 JSON
-    let mut tool_json_buffers: std::collections::HashMap<String, String> =
-        std::collections::HashMap::new();
-
-    for result in decoder.decode_iter() {
-        match result {
-            Ok(frame) => {
-                if let Ok(event) = Event::from_frame(frame) {
-                    match event {
-                        Event::AssistantResponse(resp) => {
-                            text_content.push_str(&resp.content);
-                        }
-                        Event::ToolUse(tool_use) => {
-                            has_tool_use = true;
-
-                            // 累积工具的 JSON 输入
-                            let buffer = tool_json_buffers
-                                .entry(tool_use.tool_use_id.clone())
-                                .or_insert_with(String::new);
-                            buffer.push_str(&tool_use.input);
-
-                            // 如果是完整的工具调用，添加到列表
-                            if tool_use.stop {
-                                let input: serde_json::Value = serde_json::from_str(buffer)
-                                    .unwrap_or_else(|e| {
-                                        tracing::warn!(
-                                            "工具输入 JSON 解析失败: {}, tool_use_id: {}, 原始内容: {}",
-                                            e, tool_use.tool_use_id, buffer
-                                        );
-                                        serde_json::json!({})
-                                    });
-
-                                tool_uses.push(json!({
-                                    "type": "tool_use",
-                                    "id": tool_use.tool_use_id,
-                                    "name": tool_use.name,
-                                    "input": input
-                                }));
-                            }
-                        }
-                        Event::ContextUsage(context_usage) => {
-                            // 从上下文使用百分比计算实际的 input_tokens
-                            // 公式: percentage * 200000 / 100 = percentage * 2000
-                            let actual_input_tokens = (context_usage.context_usage_percentage
-                                * (CONTEXT_WINDOW_SIZE as f64)
-                                / 100.0)
-                                as i32;
-                            context_input_tokens = Some(actual_input_tokens);
-                            tracing::debug!(
-                                "收到 contextUsageEvent: {}%, 计算 input_tokens: {}",
-                                context_usage.context_usage_percentage,
-                                actual_input_tokens
-                            );
-                        }
-                        Event::Exception { exception_type, .. } => {
-                            if exception_type == "ContentLengthExceededException" {
-                                stop_reason = "max_tokens".to_string();
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            Err(e) => {
-                tracing::warn!("解码事件失败: {}", e);
-            }
+    if let Some((e, response)) = failure {
+        if let (Some(id), Some(pool)) = (&account_id, &pool) {
+            let error_msg = e.to_string();
+            pool.journal_finish(&request_id, Some(&error_msg)).await;
+            record_account_error(pool, id, &e).await;
+
+            // 记录失败的请求
+            let log = crate::pool::RequestLog {
+                id: request_id,
+                account_id: id.clone(),
+                account_name,
+                model: model.to_string(),
+                input_tokens,
+                output_tokens: 0,
+                success: false,
+                error: Some(error_msg),
+                timestamp: chrono::Utc::now(),
+                duration_ms: start_time.elapsed().as_millis() as u64,
+                client,
+            };
+            pool.add_request_log(log).await;
         }
+        return response;
     }
 
-    // 确定 stop_reason
-    if has_tool_use && stop_reason == "end_turn" {
-        stop_reason = "tool_use".to_string();
+    let stop_reason = resolve_stop_reason(stop_reason, completion_status, has_tool_use);
+
+    // 屏蔽 thinking 时与流式响应一致，丢弃 thinking 内容
+    if thinking_enabled && event_filter.suppress_thinking {
+        text_content = strip_thinking_block(&text_content);
     }
 
     // 构建响应内容
@@ -667,8 +1847,17 @@ async fn handle_non_stream_request(
 
     // 记录成功的请求
     if let (Some(id), Some(pool)) = (&account_id, &pool) {
+        pool.journal_finish(&request_id, None).await;
+        pool.record_metering(
+            id,
+            metering_usage,
+            metering_unit.as_deref(),
+            final_input_tokens,
+            output_tokens,
+        )
+        .await;
         let log = crate::pool::RequestLog {
-            id: uuid::Uuid::new_v4().to_string(),
+            id: request_id,
             account_id: id.clone(),
             account_name,
             model: model.to_string(),
@@ -678,33 +1867,387 @@ async fn handle_non_stream_request(
             error: None,
             timestamp: chrono::Utc::now(),
             duration_ms: start_time.elapsed().as_millis() as u64,
+            client: client.clone(),
         };
         pool.add_request_log(log).await;
     }
 
-    (StatusCode::OK, Json(response_body)).into_response()
+    let mut response = (StatusCode::OK, Json(response_body)).into_response();
+    upstream_headers.apply(response.headers_mut());
+    if let Some(percentage) = context_usage_percentage.filter(|_| event_filter.emit_context_usage) {
+        if let Ok(value) = header::HeaderValue::from_str(&format!("{:.2}", percentage)) {
+            response
+                .headers_mut()
+                .insert(super::stream::CONTEXT_USAGE_HEADER, value);
+        }
+    }
+    response
+}
+
+/// GET /v1/clients
+///
+/// 单账号模式的客户端分布统计（账号池模式使用管理 API `/api/clients`）
+pub async fn get_client_stats(State(state): State<AppState>) -> Json<crate::pool::ClientStats> {
+    Json(state.client_stats.lock().unwrap().clone())
 }
 
 /// POST /v1/messages/count_tokens
 ///
 /// 计算消息的 token 数量
 pub async fn count_tokens(
+    managed: Option<Extension<ManagedKey>>,
     JsonExtractor(payload): JsonExtractor<CountTokensRequest>,
-) -> impl IntoResponse {
+) -> Response {
     tracing::info!(
         model = %payload.model,
         message_count = %payload.messages.len(),
         "Received POST /v1/messages/count_tokens request"
     );
+    if let Some(response) = model_not_allowed(managed.as_ref(), &payload.model) {
+        return response;
+    }
 
     let total_tokens = token::count_all_tokens(
         payload.model,
         payload.system,
         payload.messages,
         payload.tools,
-    ) as i32;
+    )
+    .await as i32;
 
     Json(CountTokensResponse {
-        input_tokens: total_tokens.max(1) as i32,
+        input_tokens: total_tokens.max(1),
+    })
+    .into_response()
+}
+
+/// POST /v1/estimate
+///
+/// 估算请求的输入 tokens、预计额度消耗（按历史计费事件的每 token 平均消耗）和将处理
+/// 该请求的账号池，不调用上游
+pub async fn post_estimate(
+    State(state): State<AppState>,
+    workspace: Option<Extension<Workspace>>,
+    managed: Option<Extension<ManagedKey>>,
+    headers: HeaderMap,
+    JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
+) -> Response {
+    tracing::info!(
+        model = %payload.model,
+        max_tokens = %payload.max_tokens,
+        message_count = %payload.messages.len(),
+        "Received POST /v1/estimate request"
+    );
+    if let Some(response) = model_not_allowed(managed.as_ref(), &payload.model) {
+        return response;
+    }
+
+    let system_prompt = workspace
+        .as_ref()
+        .and_then(|Extension(ws)| ws.system_prompt.as_deref())
+        .or(state.system_prompt.as_deref());
+    if let Some(prompt) = system_prompt {
+        inject_system_prompt(&mut payload, prompt, state.system_prompt_position);
+    }
+    let max_tokens = payload.max_tokens;
+    let input_tokens = (token::count_all_tokens(
+        payload.model,
+        payload.system,
+        payload.messages,
+        payload.tools,
+    )
+    .await as i32)
+        .max(1);
+
+    let priority = request_priority(&headers, workspace.as_ref());
+    let (name, account_pool) = match &workspace {
+        Some(Extension(ws)) => (ws.name.clone(), Some(ws.pool.clone())),
+        None => match &state.account_pool {
+            Some(pool) => ("default".to_string(), Some(pool.clone())),
+            None => ("single".to_string(), None),
+        },
+    };
+    let Some(pool) = account_pool else {
+        return Json(EstimateResponse {
+            input_tokens,
+            quota: None,
+            pool: PoolEstimate {
+                name,
+                readiness: "ready",
+                retry_after_secs: None,
+                subscription_types: Vec::new(),
+            },
+            priority,
+        })
+        .into_response();
+    };
+
+    let quota = pool.metering_rate().map(|rate| {
+        let output_tokens = (rate.avg_output_tokens.round() as i32).min(max_tokens);
+        QuotaEstimate {
+            units: rate.per_token * f64::from(input_tokens + output_tokens),
+            unit: rate.unit,
+            assumed_output_tokens: output_tokens,
+            rate_per_token: rate.per_token,
+            samples: rate.requests,
+        }
+    });
+    let (readiness, retry_after_secs) = match pool.readiness().await {
+        PoolReadiness::Ready => ("ready", None),
+        PoolReadiness::Saturated { retry_after } => {
+            ("saturated", retry_after.map(|d| d.as_secs().max(1)))
+        }
+        PoolReadiness::Unavailable => ("unavailable", None),
+    };
+
+    Json(EstimateResponse {
+        input_tokens,
+        quota,
+        pool: PoolEstimate {
+            name,
+            readiness,
+            retry_after_secs,
+            subscription_types: pool.available_subscription_types().await,
+        },
+        priority,
     })
+    .into_response()
+}
+
+/// POST /v1/embeddings
+///
+/// 转发到配置的外部 embeddings 服务，未配置时返回不支持错误
+pub async fn post_embeddings(
+    State(state): State<AppState>,
+    JsonExtractor(payload): JsonExtractor<serde_json::Value>,
+) -> Response {
+    tracing::info!("Received POST /v1/embeddings request");
+
+    match &state.embeddings {
+        Some(proxy) => proxy.forward(payload).await,
+        None => embeddings_not_supported(),
+    }
+}
+
+/// POST /v1/files
+///
+/// 上传文件到本地存储，返回文件元数据
+pub async fn upload_file(State(state): State<AppState>, mut multipart: Multipart) -> Response {
+    tracing::info!("Received POST /v1/files request");
+
+    let Some(store) = &state.file_store else {
+        return files_not_configured();
+    };
+
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(
+                        "invalid_request_error",
+                        format!("解析上传内容失败: {}", e),
+                    )),
+                )
+                    .into_response();
+            }
+        };
+        if field.name() != Some("file") {
+            continue;
+        }
+
+        let filename = field.file_name().unwrap_or("upload").to_string();
+        let mime_type = field
+            .content_type()
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        let data = match field.bytes().await {
+            Ok(data) => data,
+            Err(e) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse::new(
+                        "invalid_request_error",
+                        format!("读取上传文件失败: {}", e),
+                    )),
+                )
+                    .into_response();
+            }
+        };
+
+        return match store.save(filename, mime_type, &data).await {
+            Ok(metadata) => Json(metadata).into_response(),
+            Err(e) => {
+                tracing::error!("保存文件失败: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(
+                        "internal_error",
+                        format!("保存文件失败: {}", e),
+                    )),
+                )
+                    .into_response()
+            }
+        };
+    }
+
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse::new(
+            "invalid_request_error",
+            "缺少 file 字段",
+        )),
+    )
+        .into_response()
+}
+
+/// GET /v1/files/{file_id}
+///
+/// 获取文件元数据
+pub async fn get_file(State(state): State<AppState>, Path(file_id): Path<String>) -> Response {
+    tracing::info!("Received GET /v1/files/{} request", file_id);
+
+    let Some(store) = &state.file_store else {
+        return files_not_configured();
+    };
+
+    match store.get_metadata(&file_id).await {
+        Some(metadata) => Json(metadata).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(
+                "not_found_error",
+                format!("文件不存在: {}", file_id),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+/// Files API 未启用时的错误响应
+fn files_not_configured() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(ErrorResponse::new(
+            "service_unavailable",
+            "Files API not configured",
+        )),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> MessagesRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_coalesce_key_separates_callers() {
+        let payload = request();
+        let none = RequestOverrides::default();
+        let key = |mode, caller| coalesce_key(&payload, mode, caller, &none).unwrap();
+        assert_eq!(key(AgentMode::Vibe, "-"), key(AgentMode::Vibe, "-"));
+        assert_ne!(key(AgentMode::Vibe, "-"), key(AgentMode::Vibe, "team-a"));
+        assert_ne!(key(AgentMode::Vibe, "ws-a"), key(AgentMode::Vibe, "ws-b"));
+        assert_ne!(key(AgentMode::Vibe, "-"), key(AgentMode::Spec, "-"));
+
+        // 请求头覆盖的上游字段不同时不合并
+        let cli = RequestOverrides {
+            origin: Some(KiroOrigin::Cli),
+            ..Default::default()
+        };
+        let with_origin = coalesce_key(&payload, AgentMode::Vibe, "-", &cli).unwrap();
+        assert_ne!(key(AgentMode::Vibe, "-"), with_origin);
+    }
+
+    #[test]
+    fn test_header_overrides() {
+        let overrides = headers(&[
+            (THINKING_BUDGET_HEADER, "100000"),
+            (AGENT_TASK_TYPE_HEADER, "spec"),
+            (ORIGIN_HEADER, "cli"),
+            (CHAT_TRIGGER_TYPE_HEADER, "INLINE_CHAT"),
+        ]);
+
+        let mut payload = request();
+        payload.max_tokens = 32000;
+        assert_eq!(
+            apply_header_overrides(&overrides, &mut payload, false),
+            Ok(RequestOverrides::default())
+        );
+        assert!(payload.thinking.is_none());
+
+        let applied = apply_header_overrides(&overrides, &mut payload, true).unwrap();
+        assert_eq!(applied.task_type.as_deref(), Some("spec"));
+        assert_eq!(applied.origin, Some(KiroOrigin::Cli));
+        assert_eq!(applied.chat_trigger_type, Some(ChatTriggerType::InlineChat));
+        let thinking = payload.thinking.as_ref().unwrap();
+        assert_eq!(thinking.thinking_type, "enabled");
+        assert_eq!(thinking.budget_tokens, 24576);
+
+        let disable = headers(&[(THINKING_BUDGET_HEADER, "0")]);
+        assert_eq!(
+            apply_header_overrides(&disable, &mut payload, true),
+            Ok(RequestOverrides::default())
+        );
+        assert!(payload.thinking.is_none());
+
+        let invalid = headers(&[(THINKING_BUDGET_HEADER, "-1")]);
+        assert!(apply_header_overrides(&invalid, &mut payload, true).is_err());
+        // 预算不小于 max_tokens 时拒绝
+        let invalid = headers(&[(THINKING_BUDGET_HEADER, "32000")]);
+        assert!(apply_header_overrides(&invalid, &mut payload, true).is_ok());
+        payload.max_tokens = 24576;
+        assert!(apply_header_overrides(&invalid, &mut payload, true).is_err());
+        let invalid = headers(&[(ORIGIN_HEADER, "BROWSER")]);
+        assert!(apply_header_overrides(&invalid, &mut payload, true).is_err());
+    }
+
+    #[test]
+    fn test_upstream_failure_status_by_error_kind() {
+        let throttled = KiroError::from_status(
+            "API 请求失败",
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers(&[("retry-after", "12")]),
+            "",
+        );
+        let response = upstream_failure(&throttled, "{}");
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "12");
+
+        let overloaded = KiroError::from_status(
+            "API 请求失败",
+            StatusCode::from_u16(529).unwrap(),
+            &headers(&[("retry-after", "3")]),
+            "",
+        );
+        let response = upstream_failure(&overloaded, "{}");
+        assert_eq!(response.status().as_u16(), 529);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+
+        let unauthorized =
+            KiroError::from_status("API 请求失败", StatusCode::FORBIDDEN, &headers(&[]), "");
+        assert_eq!(
+            upstream_failure(&unauthorized, "{}").status(),
+            StatusCode::BAD_GATEWAY
+        );
+    }
 }
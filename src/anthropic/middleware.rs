@@ -1,32 +1,151 @@
 //! Anthropic API 中间件
 
-use std::sync::Arc;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
 
 use axum::{
     body::Body,
     extract::State,
-    http::{header, Request, StatusCode},
+    http::{header, HeaderValue, Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
+use tower_http::catch_panic::CatchPanicLayer;
 
+use crate::kiro::parser::decoder::DecoderConfig;
 use crate::kiro::provider::KiroProvider;
-use crate::pool::AccountPool;
+use crate::model::config::{
+    AgentMode, ChaosConfig, ChatTriggerType, ClientToolPolicy, Config, KiroOrigin, RequestTimeouts,
+    SseConfig, SystemPromptPosition, UnknownBetaPolicy,
+};
+use crate::pool::{AccountPool, ClientStats, Workspace};
 
+use super::api_keys::{ApiKeyInfo, ApiKeyStore, KeyCheck};
+use super::beta::{self, AnthropicBetas};
+use super::blocks::BlockPolicy;
+use super::body::DEFAULT_MAX_BODY_BYTES;
+use super::coalesce::StreamCoalescer;
+use super::conversations::ConversationCache;
+use super::converter::ToolLimits;
+use super::embeddings::EmbeddingsProxy;
+use super::files::FileStore;
+use super::postprocess::PostProcessConfig;
+use super::scheduler::PriorityScheduler;
+use super::server_tools::ServerTools;
+use super::shadow::ShadowMirror;
+use super::stream::EventFilter;
+use super::telemetry::{ConverterFailure, Telemetry};
+use super::templates::TemplateStore;
 use super::types::ErrorResponse;
+use super::version::{self, AnthropicVersion};
 
 /// 应用共享状态
 #[derive(Clone)]
 pub struct AppState {
     /// API 密钥
     pub api_key: String,
+    /// 轮换期间仍然有效的旧 API 密钥（可选）
+    pub previous_api_key: Option<PreviousApiKey>,
     /// Kiro Provider（可选，用于实际 API 调用 - 单账号模式）
-    /// 内部使用 Mutex 管理 TokenManager 状态，已支持线程安全
+    /// 内部使用 RwLock 管理 TokenManager 状态，同一账号上的并发请求可并行处理
     pub kiro_provider: Option<Arc<KiroProvider>>,
     /// Profile ARN（可选，用于请求）
     pub profile_arn: Option<String>,
     /// 账号池（可选，用于多账号模式）
     pub account_pool: Option<Arc<AccountPool>>,
+    /// SSE 事件过滤配置
+    pub event_filter: EventFilter,
+    /// 流式请求合并器（可选）
+    pub stream_coalescer: Option<Arc<StreamCoalescer>>,
+    /// Files API 本地存储（可选）
+    pub file_store: Option<Arc<FileStore>>,
+    /// 多租户工作区（按 API Key 区分）
+    pub workspaces: Arc<Vec<Workspace>>,
+    /// 通过管理 API 签发的托管 API Key（可选）
+    pub api_keys: Option<Arc<ApiKeyStore>>,
+    /// 注入到每个请求的系统提示词（可选）
+    pub system_prompt: Option<String>,
+    /// 系统提示词注入位置
+    pub system_prompt_position: SystemPromptPosition,
+    /// 工具列表限制
+    pub tool_limits: ToolLimits,
+    /// 客户端工具定义的处理策略
+    pub client_tools: Arc<ClientToolPolicy>,
+    /// 未知内容块处理策略
+    pub block_policy: Arc<BlockPolicy>,
+    /// 助手文本后处理配置
+    pub post_process: Arc<PostProcessConfig>,
+    /// 外部 embeddings 服务转发器（可选）
+    pub embeddings: Option<Arc<EmbeddingsProxy>>,
+    /// 转换失败匿名遥测（可选）
+    pub telemetry: Option<Arc<Telemetry>>,
+    /// 代理执行的内置工具（可选）
+    pub server_tools: Option<Arc<ServerTools>>,
+    /// 上游并发限制与优先级排队（可选）
+    pub scheduler: Option<Arc<PriorityScheduler>>,
+    /// 默认 Kiro 代理模式
+    pub agent_mode: AgentMode,
+    /// 默认的用户消息来源
+    pub origin: KiroOrigin,
+    /// 默认的聊天触发类型（未设置时由转换器推断）
+    pub chat_trigger_type: Option<ChatTriggerType>,
+    /// 默认的请求时长限制（可被工作区或托管 API Key 覆盖）
+    pub timeouts: RequestTimeouts,
+    /// `/v1/messages` 请求体上限（字节）
+    pub max_body_bytes: usize,
+    /// 响应流故障注入配置（可选，仅 debug 构建生效）
+    pub chaos: Option<ChaosConfig>,
+    /// 影子流量镜像器（可选）
+    pub shadow: Option<Arc<ShadowMirror>>,
+    /// 上游事件流解码器的缓冲配置
+    pub decoder: DecoderConfig,
+    /// 流式响应的刷新与缓冲配置
+    pub sse: SseConfig,
+    /// 提示词模板
+    pub templates: Arc<TemplateStore>,
+    /// 按 `x-kiro-conversation-id` 复用上游会话 ID 的缓存
+    pub conversations: Arc<ConversationCache>,
+    /// `anthropic-beta` 中未识别的 beta 的处理方式
+    pub unknown_beta_policy: UnknownBetaPolicy,
+    /// 单账号模式的客户端分布统计（账号池模式记录在账号池中）
+    pub client_stats: Arc<Mutex<ClientStats>>,
+}
+
+/// 请求使用主 API 密钥（或轮换期间的旧密钥）认证，允许通过请求头覆盖转换参数
+///
+/// 工作区密钥属于租户，不视为可信密钥
+#[derive(Debug, Clone, Copy)]
+pub struct TrustedKey;
+
+/// 请求使用托管 API Key 认证，携带该 Key 的元数据（用于模型限制）
+#[derive(Debug, Clone)]
+pub struct ManagedKey(pub ApiKeyInfo);
+
+/// 轮换期间仍然有效的旧 API 密钥
+#[derive(Clone)]
+pub struct PreviousApiKey {
+    pub key: String,
+    /// 失效时间，之后使用该密钥的请求返回 401
+    pub expires_at: DateTime<Utc>,
+}
+
+impl PreviousApiKey {
+    /// 从配置读取，未同时设置密钥和失效时间时返回 None
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            key: config.previous_api_key.clone()?,
+            expires_at: config.previous_api_key_expires_at?,
+        })
+    }
+
+    /// 密钥匹配且未过期
+    pub fn accepts(&self, key: &str, now: DateTime<Utc>) -> bool {
+        constant_time_eq(key, &self.key) && now < self.expires_at
+    }
 }
 
 impl AppState {
@@ -34,12 +153,47 @@ impl AppState {
     pub fn new(api_key: impl Into<String>) -> Self {
         Self {
             api_key: api_key.into(),
+            previous_api_key: None,
             kiro_provider: None,
             profile_arn: None,
             account_pool: None,
+            event_filter: EventFilter::default(),
+            stream_coalescer: None,
+            file_store: None,
+            workspaces: Arc::new(Vec::new()),
+            api_keys: None,
+            system_prompt: None,
+            system_prompt_position: SystemPromptPosition::default(),
+            tool_limits: ToolLimits::default(),
+            client_tools: Arc::new(ClientToolPolicy::default()),
+            block_policy: Arc::new(BlockPolicy::default()),
+            post_process: Arc::new(PostProcessConfig::default()),
+            embeddings: None,
+            telemetry: None,
+            server_tools: None,
+            scheduler: None,
+            agent_mode: AgentMode::default(),
+            origin: KiroOrigin::default(),
+            chat_trigger_type: None,
+            timeouts: RequestTimeouts::default(),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            chaos: None,
+            shadow: None,
+            decoder: DecoderConfig::default(),
+            sse: SseConfig::default(),
+            templates: Arc::new(TemplateStore::default()),
+            conversations: Arc::new(ConversationCache::default()),
+            unknown_beta_policy: UnknownBetaPolicy::default(),
+            client_stats: Arc::new(Mutex::new(ClientStats::default())),
         }
     }
 
+    /// 设置轮换期间仍然有效的旧 API 密钥
+    pub fn with_previous_api_key(mut self, key: Option<PreviousApiKey>) -> Self {
+        self.previous_api_key = key;
+        self
+    }
+
     /// 设置 KiroProvider
     pub fn with_kiro_provider(mut self, provider: KiroProvider) -> Self {
         self.kiro_provider = Some(Arc::new(provider));
@@ -57,6 +211,169 @@ impl AppState {
         self.account_pool = Some(pool);
         self
     }
+
+    /// 设置 SSE 事件过滤配置
+    pub fn with_event_filter(mut self, filter: EventFilter) -> Self {
+        self.event_filter = filter;
+        self
+    }
+
+    /// 启用流式请求合并
+    pub fn with_stream_coalescer(mut self) -> Self {
+        self.stream_coalescer = Some(Arc::new(StreamCoalescer::new()));
+        self
+    }
+
+    /// 设置多租户工作区
+    pub fn with_workspaces(mut self, workspaces: Vec<Workspace>) -> Self {
+        self.workspaces = Arc::new(workspaces);
+        self
+    }
+
+    /// 设置托管 API Key 存储
+    pub fn with_api_keys(mut self, store: Arc<ApiKeyStore>) -> Self {
+        self.api_keys = Some(store);
+        self
+    }
+
+    /// 设置系统提示词注入
+    pub fn with_system_prompt(
+        mut self,
+        prompt: Option<String>,
+        position: SystemPromptPosition,
+    ) -> Self {
+        self.system_prompt = prompt;
+        self.system_prompt_position = position;
+        self
+    }
+
+    /// 设置默认 Kiro 代理模式
+    pub fn with_agent_mode(mut self, mode: AgentMode) -> Self {
+        self.agent_mode = mode;
+        self
+    }
+
+    /// 设置默认的 origin 和 chatTriggerType
+    pub fn with_origin(mut self, origin: KiroOrigin, trigger: Option<ChatTriggerType>) -> Self {
+        self.origin = origin;
+        self.chat_trigger_type = trigger;
+        self
+    }
+
+    /// 设置默认的请求时长限制
+    pub fn with_timeouts(mut self, timeouts: RequestTimeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// 设置 `/v1/messages` 请求体上限（字节）
+    pub fn with_max_body_bytes(mut self, limit: usize) -> Self {
+        self.max_body_bytes = limit;
+        self
+    }
+
+    /// 设置故障注入配置
+    pub fn with_chaos(mut self, config: Option<ChaosConfig>) -> Self {
+        self.chaos = config;
+        self
+    }
+
+    /// 启用影子流量镜像
+    pub fn with_shadow(mut self, mirror: ShadowMirror) -> Self {
+        self.shadow = Some(Arc::new(mirror));
+        self
+    }
+
+    /// 设置事件流解码器的缓冲配置
+    pub fn with_decoder(mut self, config: DecoderConfig) -> Self {
+        self.decoder = config;
+        self
+    }
+
+    /// 设置流式响应的刷新与缓冲配置
+    pub fn with_sse(mut self, config: SseConfig) -> Self {
+        self.sse = config;
+        self
+    }
+
+    /// 启用代理执行的内置工具
+    pub fn with_server_tools(mut self, tools: ServerTools) -> Self {
+        self.server_tools = Some(Arc::new(tools));
+        self
+    }
+
+    /// 设置提示词模板存储
+    pub fn with_templates(mut self, templates: Arc<TemplateStore>) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// 设置会话缓存
+    pub fn with_conversations(mut self, conversations: Arc<ConversationCache>) -> Self {
+        self.conversations = conversations;
+        self
+    }
+
+    /// 设置未识别 beta 的处理方式
+    pub fn with_unknown_beta_policy(mut self, policy: UnknownBetaPolicy) -> Self {
+        self.unknown_beta_policy = policy;
+        self
+    }
+
+    /// 设置未知内容块处理策略
+    pub fn with_block_policy(mut self, policy: BlockPolicy) -> Self {
+        self.block_policy = Arc::new(policy);
+        self
+    }
+
+    /// 设置工具列表限制
+    pub fn with_tool_limits(mut self, limits: ToolLimits) -> Self {
+        self.tool_limits = limits;
+        self
+    }
+
+    /// 设置客户端工具策略
+    pub fn with_client_tools(mut self, policy: ClientToolPolicy) -> Self {
+        self.client_tools = Arc::new(policy);
+        self
+    }
+
+    /// 设置助手文本后处理配置
+    pub fn with_post_process(mut self, config: PostProcessConfig) -> Self {
+        self.post_process = Arc::new(config);
+        self
+    }
+
+    /// 设置 Files API 存储
+    pub fn with_file_store(mut self, store: FileStore) -> Self {
+        self.file_store = Some(Arc::new(store));
+        self
+    }
+
+    /// 设置外部 embeddings 服务转发器
+    pub fn with_embeddings(mut self, proxy: EmbeddingsProxy) -> Self {
+        self.embeddings = Some(Arc::new(proxy));
+        self
+    }
+
+    /// 设置转换失败遥测
+    pub fn with_telemetry(mut self, telemetry: Telemetry) -> Self {
+        self.telemetry = Some(Arc::new(telemetry));
+        self
+    }
+
+    /// 设置上游并发限制与优先级排队
+    pub fn with_scheduler(mut self, scheduler: PriorityScheduler) -> Self {
+        self.scheduler = Some(Arc::new(scheduler));
+        self
+    }
+
+    /// 上报转换失败（未启用遥测时忽略）
+    pub fn report_failure(&self, failure: ConverterFailure) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.report(failure);
+        }
+    }
 }
 
 /// 从请求中提取 API Key
@@ -87,7 +404,7 @@ fn extract_api_key(request: &Request<Body>) -> Option<String> {
 ///
 /// 无论字符串内容如何，比较所需的时间都是恒定的，
 /// 这可以防止攻击者通过测量响应时间来猜测 API Key。
-fn constant_time_eq(a: &str, b: &str) -> bool {
+pub(crate) fn constant_time_eq(a: &str, b: &str) -> bool {
     let a_bytes = a.as_bytes();
     let b_bytes = b.as_bytes();
 
@@ -112,20 +429,181 @@ fn constant_time_eq(a: &str, b: &str) -> bool {
 }
 
 /// API Key 认证中间件
+///
+/// 使用主 API Key 认证时写入 [`TrustedKey`]，使用工作区 API Key 认证时，将对应的 [`Workspace`] 写入请求扩展，
+/// 使用托管 API Key 认证时写入 [`ManagedKey`]。托管 Key 超出每分钟请求数上限时返回 429
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Response {
-    match extract_api_key(&request) {
-        Some(key) if constant_time_eq(&key, &state.api_key) => next.run(request).await,
-        _ => {
+    let Some(key) = extract_api_key(&request) else {
+        let error = ErrorResponse::authentication_error();
+        return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
+    };
+
+    if constant_time_eq(&key, &state.api_key) {
+        request.extensions_mut().insert(TrustedKey);
+        return next.run(request).await;
+    }
+    if let Some(previous) = &state.previous_api_key {
+        if previous.accepts(&key, Utc::now()) {
+            tracing::warn!(
+                "请求使用了即将失效的旧 API Key: {} {}（{} 失效）",
+                request.method(),
+                request.uri().path(),
+                previous.expires_at.to_rfc3339()
+            );
+            request.extensions_mut().insert(TrustedKey);
+            return next.run(request).await;
+        }
+    }
+
+    // 遍历所有工作区，避免通过响应时间推断匹配位置
+    let workspace = state
+        .workspaces
+        .iter()
+        .fold(None, |found, ws| {
+            if constant_time_eq(&key, &ws.api_key) {
+                Some(ws)
+            } else {
+                found
+            }
+        })
+        .cloned();
+    if let Some(workspace) = workspace {
+        tracing::debug!("请求来自工作区: {}", workspace.name);
+        request.extensions_mut().insert(workspace);
+        return next.run(request).await;
+    }
+
+    let check = match &state.api_keys {
+        Some(store) => store.authenticate(&key, Utc::now()).await,
+        None => KeyCheck::Unknown,
+    };
+    match check {
+        KeyCheck::Allowed(info) => {
+            tracing::debug!("请求来自托管 API Key: {} ({})", info.prefix, info.owner);
+            request.extensions_mut().insert(ManagedKey(*info));
+            next.run(request).await
+        }
+        KeyCheck::RateLimited(retry_after) => {
+            let error = ErrorResponse::new("rate_limit_error", "API Key 超出每分钟请求数上限");
+            let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(error)).into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.max(1).into());
+            response
+        }
+        KeyCheck::Unknown => {
             let error = ErrorResponse::authentication_error();
             (StatusCode::UNAUTHORIZED, Json(error)).into_response()
         }
     }
 }
 
+/// `anthropic-version` 协商中间件
+///
+/// 拒绝不支持的版本，并将协商结果写入请求扩展
+pub async fn version_middleware(mut request: Request<Body>, next: Next) -> Response {
+    let value = request
+        .headers()
+        .get(version::HEADER)
+        .and_then(|v| v.to_str().ok());
+    match AnthropicVersion::negotiate(value) {
+        Ok(version) => {
+            request.extensions_mut().insert(version);
+            next.run(request).await
+        }
+        Err(message) => {
+            tracing::warn!("{}", message);
+            let error = ErrorResponse::new("invalid_request_error", message);
+            (StatusCode::BAD_REQUEST, Json(error)).into_response()
+        }
+    }
+}
+
+/// `anthropic-beta` 请求头检查中间件
+///
+/// 解析请求开启的 beta 功能并写入请求扩展，含不支持的 beta（或按配置拒绝未识别的 beta）时返回 400
+pub async fn beta_middleware(
+    State(state): State<AppState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let betas = AnthropicBetas::parse(
+        request
+            .headers()
+            .get_all(beta::HEADER)
+            .iter()
+            .filter_map(|v| v.to_str().ok()),
+    );
+    match betas.check(state.unknown_beta_policy) {
+        Ok(()) => {
+            request.extensions_mut().insert(betas);
+            next.run(request).await
+        }
+        Err(message) => {
+            tracing::warn!("{}", message);
+            let error = ErrorResponse::new("invalid_request_error", message);
+            (StatusCode::BAD_REQUEST, Json(error)).into_response()
+        }
+    }
+}
+
+thread_local! {
+    /// 最近一次 panic 的调用栈（panic hook 写入，同一线程上的 [`catch_panic_layer`] 取出）
+    static PANIC_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// 安装 panic hook，记录调用栈供 [`catch_panic_layer`] 写入日志
+///
+/// 保留默认 hook 的输出，请求之外（如后台任务）的 panic 仍按原方式打印
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        PANIC_BACKTRACE.with(|bt| *bt.borrow_mut() = Some(Backtrace::force_capture()));
+        default_hook(info);
+    }));
+}
+
+/// panic 捕获中间件层
+///
+/// 处理器或转换器 panic 时返回 500 `api_error` 响应（带 `request-id`），
+/// 而不是直接断开连接（客户端会报告为 "connection dropped"）
+pub fn catch_panic_layer() -> CatchPanicLayer<fn(Box<dyn Any + Send + 'static>) -> Response> {
+    CatchPanicLayer::custom(panic_response as fn(_) -> _)
+}
+
+fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let request_id = format!("req_{}", uuid::Uuid::new_v4().simple());
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("未知 panic");
+    let backtrace = PANIC_BACKTRACE
+        .with(|bt| bt.borrow_mut().take())
+        .map(|bt| bt.to_string())
+        .unwrap_or_default();
+    tracing::error!(
+        request_id = %request_id,
+        "请求处理发生 panic: {}\n{}",
+        message,
+        backtrace
+    );
+
+    let error = ErrorResponse::new(
+        "api_error",
+        format!("服务内部错误（request_id: {}）", request_id),
+    );
+    let mut response = (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response();
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert("request-id", value);
+    }
+    response
+}
+
 /// CORS 中间件层
 ///
 /// **安全说明**：当前配置允许所有来源（Any），这是为了支持公开 API 服务。
@@ -143,3 +621,46 @@ pub fn cors_layer() -> tower_http::cors::CorsLayer {
         .allow_methods(Any)
         .allow_headers(Any)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_previous_api_key_window() {
+        let now = Utc::now();
+        let previous = PreviousApiKey {
+            key: "old-key".to_string(),
+            expires_at: now + chrono::Duration::hours(1),
+        };
+        assert!(previous.accepts("old-key", now));
+        assert!(!previous.accepts("new-key", now));
+        assert!(!previous.accepts("old-key", previous.expires_at));
+
+        let config = Config {
+            previous_api_key: Some("old-key".to_string()),
+            ..Config::default()
+        };
+        assert!(PreviousApiKey::from_config(&config).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_panic_response() {
+        let response = panic_response(Box::new("boom"));
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let request_id = response.headers()["request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(request_id.starts_with("req_"));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["type"], "api_error");
+        assert!(json["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains(&request_id));
+    }
+}
@@ -4,8 +4,14 @@
 //!
 //! # 支持的端点
 //! - `GET /v1/models` - 获取可用模型列表
-//! - `POST /v1/messages` - 创建消息（对话）
+//! - `POST /v1/messages` - 创建消息（对话），`?dry_run=true` 时只返回转换结果，
+//!   `?continue=true` 时自动续写因 max_tokens 截断的非流式响应
 //! - `POST /v1/messages/count_tokens` - 计算 token 数量
+//! - `POST /v1/files` - 上传文件
+//! - `GET /v1/files/{file_id}` - 获取文件元数据
+//! - `POST /v1/embeddings` - 转发到外部 embeddings 服务（OpenAI 兼容）
+//!
+//! 配置 `pathPrefix` 时以上端点均挂载在该前缀下，见 [`prefix`]。
 //!
 //! # 使用示例
 //! ```rust,ignore
@@ -16,11 +22,45 @@
 //! axum::serve(listener, app).await?;
 //! ```
 
+mod api_keys;
+mod beta;
+mod blocks;
+mod body;
+mod coalesce;
+mod continuation;
+mod conversations;
 mod converter;
+mod deadline;
+mod diagnose;
+mod embeddings;
+mod files;
+#[cfg(test)]
+mod golden;
 mod handlers;
 mod middleware;
+mod oneshot;
+mod pipeline;
+mod postprocess;
+pub mod prefix;
+mod recent;
 mod router;
+mod scheduler;
+mod server_tools;
+mod shadow;
+mod sse_writer;
 mod stream;
+mod telemetry;
+mod templates;
+mod tool_alias;
+mod tool_policy;
 pub mod types;
+mod version;
 
+pub use api_keys::{ApiKeyStore, NewApiKey};
+pub use conversations::ConversationCache;
+pub(crate) use middleware::constant_time_eq;
+pub use middleware::{catch_panic_layer, install_panic_hook, PreviousApiKey};
+pub use oneshot::run_request;
 pub use router::{create_router_with_pool, create_router_with_provider};
+pub use server_tools::ServerTool;
+pub use templates::TemplateStore;
@@ -0,0 +1,166 @@
+//! 单次请求命令
+//!
+//! `kiro-rs request --file req.json [--stream]` 不启动 HTTP 服务，直接转换请求并调用上游，
+//! 把响应 JSON（或 SSE 事件流）写到标准输出。请求不经过认证、模板、影子流量和调度等
+//! 服务层中间件，可用于脚本调用，也可用于判断问题出在服务层还是转换器和上游调用。
+
+use std::io::Write;
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, StatusCode};
+use futures::StreamExt;
+
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::provider::KiroProvider;
+use crate::model::config::{Config, RequestTimeouts};
+use crate::pool::ClientInfo;
+use crate::token;
+
+use super::converter::inject_system_prompt;
+use super::handlers::{
+    handle_non_stream_request, handle_stream_request, prepare_request, resolve_agent_mode,
+    RequestOverrides,
+};
+use super::middleware::AppState;
+use super::router::apply_config;
+use super::stream::StreamFormat;
+use super::types::MessagesRequest;
+
+/// 执行一次 Messages 请求并把响应写到标准输出
+///
+/// `stream` 为 true 时按流式请求处理（忽略请求体中的 `stream` 字段）。
+/// 返回上游是否成功响应，错误响应体同样写到标准输出
+pub async fn run_request(
+    provider: KiroProvider,
+    profile_arn: Option<String>,
+    config: &Config,
+    body: &[u8],
+    stream: bool,
+) -> anyhow::Result<bool> {
+    let mut payload: MessagesRequest = serde_json::from_slice(body)?;
+    if stream {
+        payload.stream = true;
+    }
+
+    let state = apply_config(AppState::new(""), config);
+    if let Some(prompt) = state.system_prompt.as_deref() {
+        inject_system_prompt(&mut payload, prompt, state.system_prompt_position);
+    }
+    let agent_mode = resolve_agent_mode(&HeaderMap::new(), &mut payload, state.agent_mode);
+
+    let conversion = match prepare_request(
+        &state,
+        &mut payload,
+        agent_mode,
+        &RequestOverrides::default(),
+    )
+    .await
+    {
+        Ok(conversion) => conversion,
+        Err(response) => return write_response(response).await,
+    };
+    let profile_arn = match profile_arn {
+        Some(arn) => Some(arn),
+        None => provider.profile_arn().await,
+    };
+    let request_body = serde_json::to_string(&KiroRequest {
+        conversation_state: conversion.conversation_state,
+        profile_arn,
+    })?;
+    tracing::debug!("Kiro request body: {}", request_body);
+
+    let input_tokens = token::count_all_tokens(
+        payload.model.clone(),
+        payload.system.clone(),
+        payload.messages.clone(),
+        payload.tools.clone(),
+    )
+    .await as i32;
+    let thinking_enabled = payload
+        .thinking
+        .as_ref()
+        .is_some_and(|t| t.thinking_type == "enabled");
+    let tool_aliases = Arc::new(conversion.tool_aliases);
+    let timeouts = RequestTimeouts::from(config);
+    let start_time = std::time::Instant::now();
+
+    let response = if payload.stream {
+        let deadline = timeouts
+            .max_request()
+            .map(|max| tokio::time::Instant::from_std(start_time) + max);
+        handle_stream_request(
+            Arc::new(provider),
+            &request_body,
+            agent_mode,
+            &payload.model,
+            input_tokens,
+            payload.max_tokens,
+            thinking_enabled,
+            state.event_filter,
+            state.post_process.clone(),
+            state.chaos.clone(),
+            state.decoder,
+            state.sse.clone(),
+            tool_aliases,
+            payload.disable_parallel_tool_use(),
+            None,
+            String::new(),
+            None,
+            ClientInfo::default(),
+            start_time,
+            None,
+            StreamFormat::Sse,
+            None,
+            deadline,
+            timeouts.stream_idle(),
+        )
+        .await
+    } else {
+        handle_non_stream_request(
+            Arc::new(provider),
+            &request_body,
+            agent_mode,
+            &payload.model,
+            input_tokens,
+            thinking_enabled,
+            state.event_filter,
+            state.post_process.clone(),
+            state.chaos.clone(),
+            state.decoder,
+            tool_aliases,
+            payload.disable_parallel_tool_use(),
+            None,
+            String::new(),
+            None,
+            ClientInfo::default(),
+            start_time,
+            None,
+        )
+        .await
+    };
+    write_response(response).await
+}
+
+/// 把响应体逐块写到标准输出，返回是否为成功响应
+async fn write_response(response: axum::response::Response) -> anyhow::Result<bool> {
+    let success = response.status() == StatusCode::OK;
+    if !success {
+        tracing::error!("请求失败: {}", response.status());
+    }
+    let mut body = response.into_body().into_data_stream();
+    let mut stdout = std::io::stdout();
+    let mut ends_with_newline = true;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        stdout.write_all(&chunk)?;
+        stdout.flush()?;
+        if let Some(last) = chunk.last() {
+            ends_with_newline = *last == b'\n';
+        }
+    }
+    // JSON 响应体没有结尾换行，补上以便在终端和管道中按行处理
+    if !ends_with_newline {
+        writeln!(stdout)?;
+    }
+    Ok(success)
+}
@@ -0,0 +1,798 @@
+//! 流式响应处理管道
+//!
+//! 将上游响应的处理拆分为可组合的 `Stream` 阶段，各阶段可独立测试和复用：
+//!
+//! ```text
+//! 字节流 ─decode_events→ Kiro 事件 ─(enforce_timeouts)→ Kiro 事件 ─watchdog→ Kiro 事件
+//!        ─post_process→ Kiro 事件
+//!        ─(assemble_tool_calls)→ Kiro 事件
+//!        ─restore_tool_names→ Kiro 事件 ─single_tool_call→ Kiro 事件
+//!        ─map_to_anthropic→ SSE 事件（含保活 ping） ─serialize→ 字节流（SSE 或 NDJSON）
+//! ```
+//!
+//! 流式响应需要逐块转发工具参数（`input_json_delta`），因此不经过 `assemble_tool_calls`；
+//! 非流式响应使用该阶段拼接完整的工具调用。
+
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{future, stream, Stream, StreamExt};
+use tokio::time::Instant;
+
+use crate::kiro::model::events::{AssistantResponseEvent, Event, ToolUseEvent};
+use crate::kiro::parser::decoder::{DecoderConfig, EventStreamDecoder};
+
+use super::postprocess::{PostProcessConfig, TextPostProcessor};
+use super::sse_writer::SseWriter;
+use super::stream::{SseEvent, StreamContext, StreamFormat};
+use super::tool_alias::ToolAliases;
+
+/// 从解码器中取出所有完整帧并解析为事件
+fn drain_decoder(decoder: &mut EventStreamDecoder) -> Vec<Event> {
+    let mut events = Vec::new();
+    for result in decoder.decode_iter() {
+        match result {
+            Ok(frame) => {
+                if let Ok(event) = Event::from_frame(frame) {
+                    events.push(event);
+                }
+            }
+            Err(e) => {
+                tracing::warn!("解码事件失败: {}", e);
+            }
+        }
+    }
+    events
+}
+
+/// 解码阶段：AWS Event Stream 字节流 → Kiro 事件
+///
+/// 读取上游响应失败、缓冲区超限或解码器停止时记录错误并结束流，由下游阶段负责发送收尾事件
+pub fn decode_events<S, E>(
+    body: S,
+    config: DecoderConfig,
+) -> impl Stream<Item = Event> + Send + 'static
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Display,
+{
+    stream::unfold(
+        Some((Box::pin(body), EventStreamDecoder::from_config(config))),
+        |state| async move {
+            let (mut body, mut decoder) = state?;
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    let fed = decoder.feed(&chunk);
+                    let events = drain_decoder(&mut decoder);
+                    let next = match fed {
+                        Err(e) => {
+                            tracing::error!("缓冲区溢出，停止解析响应: {}", e);
+                            None
+                        }
+                        Ok(()) if decoder.is_stopped() => None,
+                        Ok(()) => Some((body, decoder)),
+                    };
+                    Some((stream::iter(events), next))
+                }
+                Some(Err(e)) => {
+                    tracing::error!("读取响应流失败: {}", e);
+                    None
+                }
+                None => None,
+            }
+        },
+    )
+    .flatten()
+}
+
+/// 时长限制阶段：超过请求截止时间或上游空闲超时后结束流
+///
+/// 与读取失败一样由下游阶段负责发送收尾事件；空闲时间按上游事件计算，不受保活 ping 影响
+pub fn enforce_timeouts<S>(
+    events: S,
+    deadline: Option<Instant>,
+    idle: Option<Duration>,
+) -> impl Stream<Item = Event> + Send + 'static
+where
+    S: Stream<Item = Event> + Send + 'static,
+{
+    if deadline.is_none() && idle.is_none() {
+        return events.left_stream();
+    }
+    stream::unfold(Some(Box::pin(events)), move |state| async move {
+        let mut events = state?;
+        let limit = match (deadline, idle.map(|idle| Instant::now() + idle)) {
+            (Some(deadline), Some(idle)) => deadline.min(idle),
+            (deadline, idle) => deadline.or(idle)?,
+        };
+        match tokio::time::timeout_at(limit, events.next()).await {
+            Ok(event) => event.map(|event| (event, Some(events))),
+            Err(_) => {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    tracing::warn!("请求超过最长处理时间，结束流");
+                } else {
+                    tracing::warn!("上游超过 {:?} 没有输出，结束流", idle.unwrap_or_default());
+                }
+                None
+            }
+        }
+    })
+    .right_stream()
+}
+
+/// 事件顺序检查状态
+#[derive(Default)]
+struct Watchdog {
+    /// 已收到带完成状态的助手响应
+    completed: bool,
+    /// 已结束（收到 stop）的工具调用：tool_use_id → 工具名称
+    stopped: HashMap<String, String>,
+    /// 进行中的重复 ID 工具调用：上游 tool_use_id → 重新分配的 ID
+    renamed: HashMap<String, String>,
+    /// 已分配的工具调用 ID
+    assigned: HashSet<String>,
+}
+
+impl Watchdog {
+    /// 检查一个事件，违反协议顺序时修正或丢弃（返回 None）
+    fn check(&mut self, event: Event) -> Option<Event> {
+        match event {
+            Event::AssistantResponse(resp) if self.completed => {
+                if !resp.content.is_empty() {
+                    tracing::warn!(
+                        "上游在响应完成后仍输出文本，丢弃 {} 字节",
+                        resp.content.len()
+                    );
+                }
+                None
+            }
+            Event::AssistantResponse(resp) => {
+                self.completed = resp.completion_status().is_some();
+                Some(Event::AssistantResponse(resp))
+            }
+            Event::ToolUse(mut tool_use) => {
+                if let Some(id) = self.renamed.get(&tool_use.tool_use_id) {
+                    tool_use.tool_use_id = id.clone();
+                } else if let Some(name) = self.stopped.get(&tool_use.tool_use_id) {
+                    if *name == tool_use.name {
+                        tracing::warn!(
+                            "工具调用 {} ({}) 已结束后仍收到参数片段，丢弃",
+                            tool_use.name,
+                            tool_use.tool_use_id
+                        );
+                        return None;
+                    }
+                    let id = (2..)
+                        .map(|n| format!("{}_{}", tool_use.tool_use_id, n))
+                        .find(|id| !self.assigned.contains(id))
+                        .expect("工具调用 ID 序号耗尽");
+                    tracing::warn!(
+                        "工具调用 {} 与已结束的 {} 使用了相同的 ID {}，重新分配为 {}",
+                        tool_use.name,
+                        name,
+                        tool_use.tool_use_id,
+                        id
+                    );
+                    self.assigned.insert(id.clone());
+                    self.renamed
+                        .insert(std::mem::replace(&mut tool_use.tool_use_id, id.clone()), id);
+                }
+                self.assigned.insert(tool_use.tool_use_id.clone());
+                if tool_use.stop {
+                    self.renamed.retain(|_, id| *id != tool_use.tool_use_id);
+                    self.stopped
+                        .insert(tool_use.tool_use_id.clone(), tool_use.name.clone());
+                }
+                Some(Event::ToolUse(tool_use))
+            }
+            other => Some(other),
+        }
+    }
+}
+
+/// 顺序检查阶段：修正上游违反协议顺序的事件，避免生成客户端无法解析的流
+///
+/// - 响应完成（带完成状态的助手响应）后的文本事件：丢弃
+/// - 工具调用结束（`stop`）后同一调用的参数片段：丢弃
+/// - 另一个工具复用了已结束调用的 ID：重新分配 ID（`{id}_2` 等），作为独立的调用输出
+///
+/// 每次修正都会记录警告日志
+pub fn watchdog<S>(events: S) -> impl Stream<Item = Event>
+where
+    S: Stream<Item = Event>,
+{
+    events
+        .scan(Watchdog::default(), |watchdog, event| {
+            future::ready(Some(watchdog.check(event)))
+        })
+        .filter_map(future::ready)
+}
+
+/// 构造文本事件
+fn text_event(content: String) -> Event {
+    let mut event = AssistantResponseEvent::default();
+    event.content = content;
+    Event::AssistantResponse(event)
+}
+
+/// 后处理阶段：按配置清理助手文本
+///
+/// 处理器暂存的文本在遇到其他事件或上游结束时输出，保证与工具调用等事件的相对顺序
+pub fn post_process<S>(
+    events: S,
+    config: Arc<PostProcessConfig>,
+) -> impl Stream<Item = Event> + Send + 'static
+where
+    S: Stream<Item = Event> + Send + 'static,
+{
+    stream::unfold(
+        Some((Box::pin(events), TextPostProcessor::new(config))),
+        |state| async move {
+            let (mut events, mut processor) = state?;
+            let mut output = Vec::new();
+            match events.next().await {
+                Some(Event::AssistantResponse(mut resp)) => {
+                    resp.content = processor.push_event(&resp);
+                    // 内容为空但携带完成状态的事件也要保留，用于确定 stop_reason
+                    if !resp.content.is_empty() || resp.completion_status().is_some() {
+                        output.push(Event::AssistantResponse(resp));
+                    }
+                }
+                Some(event) => {
+                    let rest = processor.finish();
+                    if !rest.is_empty() {
+                        output.push(text_event(rest));
+                    }
+                    output.push(event);
+                }
+                None => {
+                    let rest = processor.finish();
+                    if !rest.is_empty() {
+                        output.push(text_event(rest));
+                    }
+                    return Some((output, None));
+                }
+            }
+            Some((output, Some((events, processor))))
+        },
+    )
+    .flat_map(stream::iter)
+}
+
+/// 工具调用拼接阶段：按 tool_use_id 合并参数片段
+///
+/// 只在收到 `stop` 时输出一个包含完整参数的 ToolUse 事件，其他事件原样透传
+pub fn assemble_tool_calls<S>(events: S) -> impl Stream<Item = Event>
+where
+    S: Stream<Item = Event>,
+{
+    events
+        .scan(HashMap::<String, String>::new(), |buffers, event| {
+            let output = match event {
+                Event::ToolUse(tool_use) => {
+                    let buffer = buffers.entry(tool_use.tool_use_id.clone()).or_default();
+                    buffer.push_str(&tool_use.input);
+                    if tool_use.stop {
+                        let input = buffers.remove(&tool_use.tool_use_id).unwrap_or_default();
+                        Some(Event::ToolUse(ToolUseEvent { input, ..tool_use }))
+                    } else {
+                        None
+                    }
+                }
+                other => Some(other),
+            };
+            future::ready(Some(output))
+        })
+        .filter_map(future::ready)
+}
+
+/// 工具名称还原阶段：将上游别名映射回客户端请求中的原始名称
+pub fn restore_tool_names<S>(events: S, aliases: Arc<ToolAliases>) -> impl Stream<Item = Event>
+where
+    S: Stream<Item = Event>,
+{
+    events.map(move |event| match event {
+        Event::ToolUse(mut tool_use) if !aliases.is_empty() => {
+            tool_use.name = aliases.restore(&tool_use.name).to_string();
+            Event::ToolUse(tool_use)
+        }
+        other => other,
+    })
+}
+
+/// 单工具调用阶段：客户端设置 `disable_parallel_tool_use` 时每轮只保留第一个工具调用
+///
+/// 按 tool_use_id 识别第一个调用，之后出现的其他调用（含参数片段）全部丢弃；
+/// `enabled` 为 false 时原样透传
+pub fn single_tool_call<S>(events: S, enabled: bool) -> impl Stream<Item = Event>
+where
+    S: Stream<Item = Event>,
+{
+    let mut first: Option<String> = None;
+    events.filter(move |event| {
+        let keep = match event {
+            Event::ToolUse(tool_use) if enabled => {
+                let first = first.get_or_insert_with(|| tool_use.tool_use_id.clone());
+                if *first == tool_use.tool_use_id {
+                    true
+                } else {
+                    tracing::debug!(
+                        "已禁用并行工具调用，丢弃工具调用 {} ({})",
+                        tool_use.name,
+                        tool_use.tool_use_id
+                    );
+                    false
+                }
+            }
+            _ => true,
+        };
+        future::ready(keep)
+    })
+}
+
+/// 映射阶段：Kiro 事件 → Anthropic SSE 事件（含保活 ping）
+///
+/// 由 [`SseWriter`] 保证生命周期事件完整：上游正常结束、panic 或达到 max_tokens 上限时
+/// 都会输出收尾事件，并以最终的 StreamContext 调用 `on_finish`。`keepalive` 为 None 时不发送 ping
+pub fn map_to_anthropic<S, F>(
+    events: S,
+    ctx: StreamContext,
+    keepalive: Option<Duration>,
+    on_finish: F,
+) -> impl Stream<Item = SseEvent> + Send + 'static
+where
+    S: Stream<Item = Event> + Send + 'static,
+    F: FnOnce(&StreamContext) + Send + 'static,
+{
+    SseWriter::new(ctx, on_finish).into_stream(events, keepalive)
+}
+
+/// 文本增量的内容块索引和文本
+fn text_delta(event: &SseEvent) -> Option<(u64, &str)> {
+    let data = &event.data;
+    if event.event != "content_block_delta" || data["delta"]["type"] != "text_delta" {
+        return None;
+    }
+    Some((data["index"].as_u64()?, data["delta"]["text"].as_str()?))
+}
+
+/// 正在合并的文本增量
+struct PendingDelta {
+    event: SseEvent,
+    index: u64,
+    text: String,
+    /// 最迟输出时间
+    deadline: Instant,
+}
+
+impl PendingDelta {
+    fn into_event(mut self) -> SseEvent {
+        self.event.data["delta"]["text"] = self.text.into();
+        self.event
+    }
+}
+
+/// 合并阶段：在时间窗口内合并同一内容块的连续文本增量
+///
+/// 窗口到期、合并后达到 `max_bytes`（0 为不限）或遇到其他事件时立即输出已合并的增量，
+/// 事件顺序保持不变。`interval` 为 None 时原样输出
+pub fn coalesce_text_deltas<S>(
+    events: S,
+    interval: Option<Duration>,
+    max_bytes: usize,
+) -> impl Stream<Item = SseEvent> + Send + 'static
+where
+    S: Stream<Item = SseEvent> + Send + 'static,
+{
+    let Some(interval) = interval else {
+        return events.left_stream();
+    };
+
+    // (上游事件, 正在合并的增量, 合并中断后待输出的事件, 上游是否结束)
+    let state = (
+        Box::pin(events),
+        None::<PendingDelta>,
+        None::<SseEvent>,
+        false,
+    );
+    stream::unfold(
+        state,
+        move |(mut events, mut pending, mut queued, mut done)| async move {
+            loop {
+                if let Some(event) = queued.take() {
+                    return Some((event, (events, pending, None, done)));
+                }
+                if done {
+                    let event = pending.take()?.into_event();
+                    return Some((event, (events, None, None, true)));
+                }
+                let deadline = pending.as_ref().map(|p| p.deadline);
+                tokio::select! {
+                    biased;
+                    event = events.next() => {
+                        let Some(event) = event else {
+                            done = true;
+                            continue;
+                        };
+                        let Some((index, text)) = text_delta(&event) else {
+                            // 其他事件：先输出已合并的增量
+                            match pending.take() {
+                                Some(p) => return Some((p.into_event(), (events, None, Some(event), done))),
+                                None => return Some((event, (events, None, None, done))),
+                            }
+                        };
+                        match &mut pending {
+                            Some(p) if p.index == index => p.text.push_str(text),
+                            _ => {
+                                let next = PendingDelta {
+                                    index,
+                                    text: text.to_string(),
+                                    event,
+                                    deadline: Instant::now() + interval,
+                                };
+                                if let Some(previous) = pending.replace(next) {
+                                    return Some((previous.into_event(), (events, pending, None, done)));
+                                }
+                            }
+                        }
+                        if max_bytes > 0 && pending.as_ref().is_some_and(|p| p.text.len() >= max_bytes) {
+                            let event = pending.take()?.into_event();
+                            return Some((event, (events, None, None, done)));
+                        }
+                    }
+                    _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                        let event = pending.take()?.into_event();
+                        return Some((event, (events, None, None, done)));
+                    }
+                }
+            }
+        },
+    )
+    .right_stream()
+}
+
+/// 在流开头输出 SSE 注释作为填充，NDJSON 格式不支持注释，不做填充
+pub fn with_padding<S>(
+    stream: S,
+    format: StreamFormat,
+    padding_bytes: usize,
+) -> impl Stream<Item = Result<Bytes, Infallible>>
+where
+    S: Stream<Item = Result<Bytes, Infallible>>,
+{
+    let padding = (padding_bytes > 0 && format != StreamFormat::Ndjson)
+        .then(|| Ok(Bytes::from(format!(":{}\n\n", " ".repeat(padding_bytes)))));
+    stream::iter(padding).chain(stream)
+}
+
+/// 序列化阶段：SSE 事件 → 字节流（SSE 或 NDJSON）
+pub fn serialize<S>(
+    events: S,
+    format: StreamFormat,
+) -> impl Stream<Item = Result<Bytes, Infallible>>
+where
+    S: Stream<Item = SseEvent>,
+{
+    events.map(move |e| Ok(Bytes::from(format.encode(&e))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_eventstream_lite::crc::crc32;
+    use serde_json::json;
+
+    /// 构造 AWS Event Stream 帧
+    fn encode_frame(event_type: &str, payload: &str) -> Vec<u8> {
+        let mut headers = Vec::new();
+        for (name, value) in [(":message-type", "event"), (":event-type", event_type)] {
+            headers.push(name.len() as u8);
+            headers.extend_from_slice(name.as_bytes());
+            headers.push(7);
+            headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            headers.extend_from_slice(value.as_bytes());
+        }
+
+        let total_len = 12 + headers.len() + payload.len() + 4;
+        let mut frame = Vec::with_capacity(total_len);
+        frame.extend_from_slice(&(total_len as u32).to_be_bytes());
+        frame.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&crc32(&frame[..8]).to_be_bytes());
+        frame.extend_from_slice(&headers);
+        frame.extend_from_slice(payload.as_bytes());
+        frame.extend_from_slice(&crc32(&frame).to_be_bytes());
+        frame
+    }
+
+    fn text_event(content: &str) -> Event {
+        super::text_event(content.to_string())
+    }
+
+    fn tool_use(input: &str, stop: bool) -> Event {
+        Event::ToolUse(ToolUseEvent {
+            name: "read".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: input.to_string(),
+            stop,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_decode_events_across_chunks() {
+        let mut bytes = encode_frame("assistantResponseEvent", r#"{"content":"Hello"}"#);
+        bytes.extend(encode_frame(
+            "assistantResponseEvent",
+            r#"{"content":" world"}"#,
+        ));
+        let (first, second) = bytes.split_at(10);
+        let body = stream::iter([
+            Ok::<_, Infallible>(Bytes::copy_from_slice(first)),
+            Ok(Bytes::copy_from_slice(second)),
+        ]);
+
+        let contents: Vec<String> = decode_events(body, DecoderConfig::default())
+            .filter_map(|event| {
+                future::ready(match event {
+                    Event::AssistantResponse(resp) => Some(resp.content),
+                    _ => None,
+                })
+            })
+            .collect()
+            .await;
+        assert_eq!(contents, vec!["Hello", " world"]);
+    }
+
+    #[tokio::test]
+    async fn test_decode_events_stops_on_buffer_overflow() {
+        let bytes = encode_frame("assistantResponseEvent", r#"{"content":"Hello"}"#);
+        let chunks = [bytes.clone(), vec![0u8; 200], bytes];
+        let body = stream::iter(chunks.map(|c| Ok::<_, Infallible>(Bytes::from(c))));
+        let config = DecoderConfig {
+            max_buffer_size: 128,
+            ..DecoderConfig::default()
+        };
+
+        // 超限后结束流，不再读取后续数据
+        let events: Vec<Event> = decode_events(body, config).collect().await;
+        assert_eq!(events.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_assemble_tool_calls() {
+        let events = stream::iter([
+            tool_use(r#"{"pa"#, false),
+            text_event("text"),
+            tool_use(r#"th":"a"}"#, true),
+        ]);
+
+        let assembled: Vec<Event> = assemble_tool_calls(events).collect().await;
+        assert_eq!(assembled.len(), 2);
+        assert!(matches!(&assembled[0], Event::AssistantResponse(_)));
+        match &assembled[1] {
+            Event::ToolUse(tool_use) => {
+                assert_eq!(tool_use.input, r#"{"path":"a"}"#);
+                assert!(tool_use.stop);
+            }
+            other => panic!("应为 ToolUse 事件: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watchdog_repairs_out_of_order_events() {
+        let completed: AssistantResponseEvent =
+            serde_json::from_value(json!({"content": "", "messageStatus": "COMPLETED"})).unwrap();
+        let write = |input: &str, stop: bool| {
+            Event::ToolUse(ToolUseEvent {
+                name: "write".to_string(),
+                tool_use_id: "tool_1".to_string(),
+                input: input.to_string(),
+                stop,
+            })
+        };
+        let events = stream::iter([
+            tool_use(r#"{"path":"a"}"#, true),
+            // 结束后的参数片段
+            tool_use(r#"{"x":1}"#, false),
+            // 另一个工具复用 ID
+            write(r#"{"b":"#, false),
+            write("2}", true),
+            Event::AssistantResponse(completed),
+            text_event("late"),
+        ]);
+
+        let repaired: Vec<Event> = watchdog(events).collect().await;
+        assert_eq!(repaired.len(), 4);
+        let ids: Vec<&str> = repaired
+            .iter()
+            .filter_map(|event| match event {
+                Event::ToolUse(tool_use) => Some(tool_use.tool_use_id.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ids, vec!["tool_1", "tool_1_2", "tool_1_2"]);
+        assert!(matches!(&repaired[3], Event::AssistantResponse(resp) if resp.content.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_single_tool_call_drops_parallel_calls() {
+        let second = |input: &str, stop: bool| {
+            Event::ToolUse(ToolUseEvent {
+                name: "write".to_string(),
+                tool_use_id: "tool_2".to_string(),
+                input: input.to_string(),
+                stop,
+            })
+        };
+        let events = || {
+            stream::iter([
+                tool_use("{", false),
+                second("{", false),
+                tool_use("}", true),
+                second("}", true),
+                text_event("done"),
+            ])
+        };
+
+        let kept: Vec<Event> = single_tool_call(events(), true).collect().await;
+        assert_eq!(kept.len(), 3);
+        assert!(kept.iter().all(|event| match event {
+            Event::ToolUse(tool_use) => tool_use.tool_use_id == "tool_1",
+            _ => true,
+        }));
+
+        let all: Vec<Event> = single_tool_call(events(), false).collect().await;
+        assert_eq!(all.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_post_process_flushes_pending_text_before_other_events() {
+        let config = Arc::new(PostProcessConfig {
+            normalize_newlines: true,
+            ..Default::default()
+        });
+        let events = stream::iter([text_event("a\r"), tool_use("{}", true), text_event("b\r")]);
+
+        let processed: Vec<Event> = post_process(events, config).collect().await;
+        let contents: Vec<String> = processed
+            .iter()
+            .map(|event| match event {
+                Event::AssistantResponse(resp) => resp.content.clone(),
+                Event::ToolUse(_) => "<tool>".to_string(),
+                other => panic!("意外的事件: {:?}", other),
+            })
+            .collect();
+        assert_eq!(contents, vec!["a", "\n", "<tool>", "b", "\n"]);
+    }
+
+    #[tokio::test]
+    async fn test_map_to_anthropic_emits_lifecycle_and_finishes() {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let events = stream::iter([text_event("hi")]);
+        let ctx = StreamContext::new_with_thinking("test-model", 1, false);
+
+        let names: Vec<String> = map_to_anthropic(events, ctx, None, move |ctx| {
+            let _ = tx.send(ctx.output_tokens);
+        })
+        .map(|e| e.event)
+        .collect()
+        .await;
+
+        assert_eq!(names.first().map(String::as_str), Some("message_start"));
+        assert_eq!(names.last().map(String::as_str), Some("message_stop"));
+        assert!(names.iter().any(|n| n == "content_block_delta"));
+        assert!(rx.await.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_serialize_formats_share_events() {
+        let events = || {
+            stream::iter([
+                SseEvent::new("message_start", json!({"type": "message_start"})),
+                SseEvent::new("ping", json!({"type": "ping"})),
+            ])
+        };
+        let collect = |format| async move {
+            serialize(events(), format)
+                .map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap())
+                .collect::<String>()
+                .await
+        };
+
+        assert_eq!(
+            collect(StreamFormat::Sse).await,
+            "event: message_start\ndata: {\"type\":\"message_start\"}\n\n\
+             event: ping\ndata: {\"type\":\"ping\"}\n\n"
+        );
+        assert_eq!(
+            collect(StreamFormat::Ndjson).await,
+            "{\"type\":\"message_start\"}\n{\"type\":\"ping\"}\n"
+        );
+    }
+
+    fn delta(index: u64, text: &str) -> SseEvent {
+        SseEvent::new(
+            "content_block_delta",
+            json!({"type": "content_block_delta", "index": index, "delta": {"type": "text_delta", "text": text}}),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_text_deltas() {
+        let events = stream::iter([
+            delta(0, "a"),
+            delta(0, "b"),
+            delta(0, "c"),
+            delta(1, "d"),
+            SseEvent::new("content_block_stop", json!({"index": 1})),
+            delta(2, "e"),
+        ]);
+        let output: Vec<_> = coalesce_text_deltas(events, Some(Duration::from_secs(60)), 2)
+            .map(|e| (e.event, e.data["delta"]["text"].clone()))
+            .collect()
+            .await;
+        assert_eq!(
+            output,
+            vec![
+                ("content_block_delta".to_string(), json!("ab")),
+                ("content_block_delta".to_string(), json!("c")),
+                ("content_block_delta".to_string(), json!("d")),
+                ("content_block_stop".to_string(), serde_json::Value::Null),
+                ("content_block_delta".to_string(), json!("e")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_flushes_after_interval() {
+        let events = stream::iter([delta(0, "a"), delta(0, "b")]).chain(stream::pending());
+        let mut stream = Box::pin(coalesce_text_deltas(
+            events,
+            Some(Duration::from_millis(10)),
+            0,
+        ));
+        let event = tokio::time::timeout(Duration::from_secs(1), stream.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.data["delta"]["text"], "ab");
+    }
+
+    #[tokio::test]
+    async fn test_enforce_timeouts_ends_idle_and_expired_streams() {
+        // 上游输出一个事件后不再有数据
+        let idle_events = stream::iter([text_event("a")]).chain(stream::pending());
+        let events = enforce_timeouts(idle_events, None, Some(Duration::from_millis(20)));
+        let collected = tokio::time::timeout(Duration::from_secs(1), events.collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert_eq!(collected.len(), 1);
+
+        // 上游持续输出，但超过截止时间
+        let busy_events = stream::repeat_with(|| text_event("b")).then(|event| async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            event
+        });
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let events = enforce_timeouts(busy_events, Some(deadline), Some(Duration::from_secs(1)));
+        let collected = tokio::time::timeout(Duration::from_secs(1), events.collect::<Vec<_>>())
+            .await
+            .unwrap();
+        assert!(!collected.is_empty());
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[tokio::test]
+    async fn test_padding_skips_ndjson() {
+        let collect = |format| async move {
+            let stream = with_padding(stream::iter([Ok(Bytes::from("x"))]), format, 4);
+            let chunks: Vec<_> = stream.map(|b| b.unwrap()).collect().await;
+            chunks.concat()
+        };
+        assert_eq!(collect(StreamFormat::Sse).await, b":    \n\nx".to_vec());
+        assert_eq!(collect(StreamFormat::Ndjson).await, b"x".to_vec());
+    }
+}
@@ -0,0 +1,301 @@
+//! 助手文本后处理
+//!
+//! 按配置对上游返回的助手文本做统一清理：去除 Kiro 特有的残留内容（追问提示回显、
+//! 回显的 thinking 控制标签）、统一换行符、改写代码块语言标记。
+//! 流式与非流式响应共用同一处理器，跨分片的标签、`\r` 和代码块行首会暂存到后续分片再处理。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::kiro::model::events::AssistantResponseEvent;
+use crate::model::config::Config;
+
+/// 需要整体移除的残留标签（请求中注入的 thinking 控制标签可能被模型回显）
+const ARTIFACT_TAGS: &[&str] = &["thinking_mode", "max_thinking_length"];
+
+/// 代码块围栏
+const FENCE: &str = "```";
+
+/// 后处理配置
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PostProcessConfig {
+    /// 去除 Kiro 残留内容
+    pub strip_artifacts: bool,
+    /// 去除正文末尾回显的追问提示（`strip_artifacts` 已包含）
+    pub strip_followup: bool,
+    /// 将 `\r\n` 和 `\r` 统一为 `\n`
+    pub normalize_newlines: bool,
+    /// 代码块语言标记改写（如 `jsx` → `javascript`）
+    pub code_fence_languages: HashMap<String, String>,
+}
+
+impl From<&Config> for PostProcessConfig {
+    fn from(config: &Config) -> Self {
+        Self {
+            strip_artifacts: config.strip_artifacts,
+            strip_followup: false,
+            normalize_newlines: config.normalize_newlines,
+            code_fence_languages: config.code_fence_languages.clone(),
+        }
+    }
+}
+
+impl PostProcessConfig {
+    /// 设置是否去除追问提示（`disabledEvents` 包含 `followup` 时）
+    pub fn with_strip_followup(mut self, enabled: bool) -> Self {
+        self.strip_followup = enabled;
+        self
+    }
+
+    /// 是否启用了任一处理规则
+    pub fn is_enabled(&self) -> bool {
+        self.strip_artifacts || self.normalize_newlines || !self.code_fence_languages.is_empty()
+    }
+}
+
+/// 流式文本后处理器
+pub struct TextPostProcessor {
+    config: Arc<PostProcessConfig>,
+    /// 暂存的未决文本
+    pending: String,
+    /// 已输出的文本是否以换行结尾（用于判断分片开头是否为行首）
+    at_line_start: bool,
+}
+
+impl TextPostProcessor {
+    pub fn new(config: Arc<PostProcessConfig>) -> Self {
+        Self {
+            config,
+            pending: String::new(),
+            at_line_start: true,
+        }
+    }
+
+    /// 处理一个助手响应事件，返回可以立即输出的文本
+    pub fn push_event(&mut self, event: &AssistantResponseEvent) -> String {
+        match event.followup_prompt() {
+            Some(followup) if self.config.strip_artifacts || self.config.strip_followup => {
+                self.push(strip_followup_echo(&event.content, followup))
+            }
+            _ => self.push(&event.content),
+        }
+    }
+
+    /// 处理一个文本分片，返回可以立即输出的文本
+    pub fn push(&mut self, chunk: &str) -> String {
+        if !self.config.is_enabled() {
+            return chunk.to_string();
+        }
+        self.pending.push_str(chunk);
+        let split = self.safe_split();
+        let ready: String = self.pending.drain(..split).collect();
+        self.transform(ready)
+    }
+
+    /// 输出所有暂存文本
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        self.transform(rest)
+    }
+
+    /// 计算可以安全处理的前缀长度，其余部分需要等待后续分片
+    fn safe_split(&self) -> usize {
+        let text = &self.pending;
+        let mut cut = text.len();
+
+        if self.config.normalize_newlines && text.ends_with('\r') {
+            cut -= 1;
+        }
+
+        if self.config.strip_artifacts {
+            for tag in ARTIFACT_TAGS {
+                let open = format!("<{}>", tag);
+                let close = format!("</{}>", tag);
+                if let Some(pos) = text[..cut].rfind(&open) {
+                    if !text[pos..cut].contains(&close) {
+                        cut = pos;
+                    }
+                }
+            }
+            if let Some(pos) = text[..cut].rfind('<') {
+                let fragment = &text[pos..cut];
+                if ARTIFACT_TAGS.iter().any(|tag| is_tag_prefix(fragment, tag)) {
+                    cut = pos;
+                }
+            }
+        }
+
+        if !self.config.code_fence_languages.is_empty() {
+            let line_start = match text[..cut].rfind('\n') {
+                Some(pos) => Some(pos + 1),
+                None => self.at_line_start.then_some(0),
+            };
+            if let Some(start) = line_start {
+                let line = &text[start..cut];
+                if FENCE.starts_with(line) || line.starts_with(FENCE) {
+                    cut = start;
+                }
+            }
+        }
+
+        cut
+    }
+
+    /// 对可输出的文本应用处理规则
+    fn transform(&mut self, text: String) -> String {
+        if text.is_empty() {
+            return text;
+        }
+        let at_line_start = self.at_line_start;
+        self.at_line_start = text.ends_with('\n');
+
+        let mut text = text;
+        if self.config.normalize_newlines {
+            text = text.replace("\r\n", "\n").replace('\r', "\n");
+        }
+        if self.config.strip_artifacts {
+            text = strip_artifact_tags(&text);
+        }
+        if !self.config.code_fence_languages.is_empty() {
+            text = rewrite_code_fences(&text, at_line_start, &self.config.code_fence_languages);
+        }
+        text
+    }
+}
+
+/// 判断片段是否可能是残留标签的开头（尚未收到 `>`）
+fn is_tag_prefix(fragment: &str, tag: &str) -> bool {
+    !fragment.contains('>')
+        && (format!("<{}>", tag).starts_with(fragment)
+            || format!("</{}>", tag).starts_with(fragment))
+}
+
+/// 移除残留标签及其内容，以及未配对的标签
+fn strip_artifact_tags(text: &str) -> String {
+    let mut text = text.to_string();
+    for tag in ARTIFACT_TAGS {
+        let open = format!("<{}>", tag);
+        let close = format!("</{}>", tag);
+        while let Some(start) = text.find(&open) {
+            let end = match text[start..].find(&close) {
+                Some(pos) => start + pos + close.len(),
+                None => start + open.len(),
+            };
+            text.replace_range(start..end, "");
+        }
+        text = text.replace(&close, "");
+    }
+    text
+}
+
+/// 去除正文末尾回显的追问提示
+fn strip_followup_echo<'a>(content: &'a str, followup: &str) -> &'a str {
+    let followup = followup.trim();
+    if followup.is_empty() {
+        return content;
+    }
+    match content.trim_end().strip_suffix(followup) {
+        Some(rest) => rest,
+        None => content,
+    }
+}
+
+/// 按映射改写行首代码块围栏的语言标记
+fn rewrite_code_fences(
+    text: &str,
+    at_line_start: bool,
+    languages: &HashMap<String, String>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let is_line_start = i > 0 || at_line_start;
+        let rewritten = line
+            .strip_prefix(FENCE)
+            .filter(|_| is_line_start)
+            .and_then(|rest| {
+                let body = rest.trim_end_matches(['\r', '\n']);
+                let ending = &rest[body.len()..];
+                languages
+                    .get(body.trim())
+                    .map(|lang| format!("{}{}{}", FENCE, lang, ending))
+            });
+        match rewritten {
+            Some(line) => output.push_str(&line),
+            None => output.push_str(line),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processor(config: PostProcessConfig) -> TextPostProcessor {
+        TextPostProcessor::new(Arc::new(config))
+    }
+
+    fn run(processor: &mut TextPostProcessor, chunks: &[&str]) -> String {
+        let mut output: String = chunks.iter().map(|c| processor.push(c)).collect();
+        output.push_str(&processor.finish());
+        output
+    }
+
+    #[test]
+    fn test_disabled_passes_through() {
+        let mut p = processor(PostProcessConfig::default());
+        assert_eq!(p.push("a\r\n<thinking_mode>"), "a\r\n<thinking_mode>");
+        assert_eq!(p.finish(), "");
+    }
+
+    #[test]
+    fn test_strip_artifacts_across_chunks() {
+        let mut p = processor(PostProcessConfig {
+            strip_artifacts: true,
+            ..Default::default()
+        });
+        let output = run(
+            &mut p,
+            &[
+                "Hi <thin",
+                "king_mode>enabled</thinking",
+                "_mode>there</max_thinking_length>",
+            ],
+        );
+        assert_eq!(output, "Hi there");
+    }
+
+    #[test]
+    fn test_normalize_newlines_across_chunks() {
+        let mut p = processor(PostProcessConfig {
+            normalize_newlines: true,
+            ..Default::default()
+        });
+        assert_eq!(p.push("a\r"), "a");
+        assert_eq!(run(&mut p, &["\nb\rc"]), "\nb\nc");
+    }
+
+    #[test]
+    fn test_rewrite_code_fence_languages() {
+        let mut p = processor(PostProcessConfig {
+            code_fence_languages: HashMap::from([("jsx".to_string(), "javascript".to_string())]),
+            ..Default::default()
+        });
+        let output = run(&mut p, &["text ```jsx\n", "`", "``js", "x\nlet a;\n```\n"]);
+        assert_eq!(output, "text ```jsx\n```javascript\nlet a;\n```\n");
+    }
+
+    #[test]
+    fn test_strip_followup_echo() {
+        let event: AssistantResponseEvent = serde_json::from_value(serde_json::json!({
+            "content": "Done. Want more?",
+            "followupPrompt": {"content": "Want more?"}
+        }))
+        .unwrap();
+        let mut p = processor(PostProcessConfig {
+            strip_artifacts: true,
+            ..Default::default()
+        });
+        assert_eq!(p.push_event(&event), "Done. ");
+    }
+}
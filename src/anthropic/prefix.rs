@@ -0,0 +1,86 @@
+//! 路径前缀（反向代理兼容）
+//!
+//! 配置 `pathPrefix` 后全部路由挂载在该前缀下（如 `/kiro/v1/messages`），网关按路径前缀转发时
+//! 无需改写路径。网关去掉前缀后再转发时，通过 `X-Forwarded-Prefix` 请求头声明外部前缀，
+//! 管理面板和 Playground 页面据此拼接自身调用的接口地址。
+
+use axum::{http::HeaderMap, response::Html, Router};
+
+/// 网关声明外部路径前缀的请求头
+const FORWARDED_PREFIX_HEADER: &str = "x-forwarded-prefix";
+
+/// 页面中表示接口前缀的占位符
+const BASE_PATH_PLACEHOLDER: &str = "__BASE_PATH__";
+
+/// 前缀是否只包含路径中安全的字符（会被写入页面脚本）
+pub fn is_valid(prefix: &str) -> bool {
+    prefix
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.' | '~'))
+}
+
+/// 规范化前缀：以 `/` 开头、不以 `/` 结尾，空字符串或 `/` 表示不使用前缀
+pub fn normalize(prefix: &str) -> String {
+    let trimmed = prefix.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+/// 将路由挂载到前缀下，前缀为空时原样返回
+pub fn mount(router: Router, prefix: &str) -> Router {
+    let prefix = normalize(prefix);
+    if prefix.is_empty() {
+        router
+    } else {
+        Router::new().nest(&prefix, router)
+    }
+}
+
+/// 客户端访问本服务使用的路径前缀：`X-Forwarded-Prefix` 加上配置的前缀
+///
+/// 请求头包含不安全字符时忽略
+pub fn public_prefix(headers: &HeaderMap, prefix: &str) -> String {
+    let forwarded = headers
+        .get(FORWARDED_PREFIX_HEADER)
+        .and_then(|v| v.to_str().ok())
+        // 经过多层代理时取第一个（最外层）
+        .and_then(|v| v.split(',').next())
+        .filter(|v| is_valid(v.trim()))
+        .map(normalize)
+        .unwrap_or_default();
+    format!("{}{}", forwarded, normalize(prefix))
+}
+
+/// 渲染页面，将接口前缀写入页面脚本
+pub fn render_page(html: &str, headers: &HeaderMap, prefix: &str) -> Html<String> {
+    Html(html.replace(BASE_PATH_PLACEHOLDER, &public_prefix(headers, prefix)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_and_forwarded_prefix() {
+        assert_eq!(normalize(""), "");
+        assert_eq!(normalize("/"), "");
+        assert_eq!(normalize("kiro/"), "/kiro");
+        assert_eq!(normalize("/gw/kiro"), "/gw/kiro");
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(public_prefix(&headers, "/kiro"), "/kiro");
+        headers.insert(FORWARDED_PREFIX_HEADER, "/edge/, /inner".parse().unwrap());
+        assert_eq!(public_prefix(&headers, "/kiro"), "/edge/kiro");
+        assert_eq!(
+            render_page("fetch('__BASE_PATH__/api')", &headers, "").0,
+            "fetch('/edge/api')"
+        );
+
+        // 不安全的请求头不会写入页面
+        headers.insert(FORWARDED_PREFIX_HEADER, "/x';alert(1)//".parse().unwrap());
+        assert_eq!(public_prefix(&headers, ""), "");
+    }
+}
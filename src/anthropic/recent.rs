@@ -0,0 +1,226 @@
+//! 托管 API Key 的最近请求记录
+//!
+//! 配置 `recentRequestsPerKey` 后，为每个托管 API Key 在内存中保留最近 N 次 `/v1/messages`
+//! 请求和响应（环形缓冲区，超出后丢弃最早的记录），运营方可以通过管理 API
+//! `GET /api/keys/{id}/recent` 查看用户反馈的异常响应。
+//!
+//! 请求体按调试日志的规则脱敏（隐藏 profileArn 和图片数据，截断过长的字符串），响应体只保留前
+//! [`MAX_RESPONSE_BYTES`] 字节。流式响应在输出结束（或客户端断开）时记录。记录不落盘，重启后清空。
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::body::read_body;
+use super::diagnose::redact;
+use super::middleware::{AppState, ManagedKey};
+
+/// 每条记录保留的响应体上限（字节）
+const MAX_RESPONSE_BYTES: usize = 64 * 1024;
+
+/// 一次请求的记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentRequest {
+    pub at: DateTime<Utc>,
+    pub model: Option<String>,
+    pub stream: bool,
+    pub status: u16,
+    /// 从收到请求到响应输出结束的耗时
+    pub duration_ms: u64,
+    /// 脱敏后的请求体（无法解析为 JSON 时为提示字符串）
+    pub request: Value,
+    /// 响应体（流式响应为原始 SSE 文本）
+    pub response: String,
+    /// 响应体是否因超出上限被截断
+    pub truncated: bool,
+}
+
+/// 按托管 Key 保存最近请求的环形缓冲区
+pub struct RecentRequests {
+    capacity: usize,
+    entries: Mutex<HashMap<String, VecDeque<RecentRequest>>>,
+}
+
+impl RecentRequests {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// 记录一次请求，超出容量时丢弃该 Key 最早的记录
+    pub fn record(&self, key_id: &str, entry: RecentRequest) {
+        let mut entries = self.entries.lock().unwrap();
+        let queue = entries.entry(key_id.to_string()).or_default();
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+        }
+        queue.push_back(entry);
+    }
+
+    /// 该 Key 的最近请求，最新的在前
+    pub fn get(&self, key_id: &str) -> Vec<RecentRequest> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(key_id)
+            .map(|queue| queue.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// 记录中的响应体，随响应流一起释放时写入记录
+struct Capture {
+    recent: Arc<RecentRequests>,
+    key_id: String,
+    entry: RecentRequest,
+    start: Instant,
+    body: Vec<u8>,
+}
+
+impl Capture {
+    fn push(&mut self, chunk: &[u8]) {
+        let room = MAX_RESPONSE_BYTES.saturating_sub(self.body.len());
+        if chunk.len() > room {
+            self.entry.truncated = true;
+        }
+        self.body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        self.entry.duration_ms = self.start.elapsed().as_millis() as u64;
+        self.entry.response = String::from_utf8_lossy(&self.body).into_owned();
+        self.recent.record(
+            &self.key_id,
+            std::mem::replace(&mut self.entry, empty_entry()),
+        );
+    }
+}
+
+fn empty_entry() -> RecentRequest {
+    RecentRequest {
+        at: Utc::now(),
+        model: None,
+        stream: false,
+        status: 0,
+        duration_ms: 0,
+        request: Value::Null,
+        response: String::new(),
+        truncated: false,
+    }
+}
+
+/// 最近请求记录中间件，仅用于 `/v1/messages`
+///
+/// 位于模板中间件之外，记录的是客户端发送的原始请求体；只记录托管 API Key 的请求
+pub async fn recent_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let recent = state.api_keys.as_ref().and_then(|store| store.recent());
+    let key_id = request
+        .extensions()
+        .get::<ManagedKey>()
+        .map(|key| key.0.id.clone());
+    let (Some(recent), Some(key_id)) = (recent, key_id) else {
+        return next.run(request).await;
+    };
+
+    let (at, start) = (Utc::now(), Instant::now());
+    let (parts, body) = request.into_parts();
+    let bytes = match read_body(&parts.headers, body, state.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(e) => return e.into_response(),
+    };
+    let parsed: Option<Value> = serde_json::from_slice(&bytes).ok();
+    let model = parsed
+        .as_ref()
+        .and_then(|v| v["model"].as_str())
+        .map(str::to_string);
+    let stream = parsed.as_ref().is_some_and(|v| v["stream"] == true);
+    let redacted = redact(&String::from_utf8_lossy(&bytes));
+    let request_value = serde_json::from_str(&redacted).unwrap_or(Value::String(redacted));
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+
+    let mut capture = Capture {
+        recent,
+        key_id,
+        entry: RecentRequest {
+            at,
+            model,
+            stream,
+            status: response.status().as_u16(),
+            request: request_value,
+            ..empty_entry()
+        },
+        start,
+        body: Vec::new(),
+    };
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            capture.push(bytes);
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(status: u16) -> RecentRequest {
+        RecentRequest {
+            status,
+            ..empty_entry()
+        }
+    }
+
+    #[test]
+    fn test_ring_buffer_and_capture() {
+        let recent = Arc::new(RecentRequests::new(2));
+        for status in [200, 429, 500] {
+            recent.record("a", entry(status));
+        }
+        let statuses: Vec<u16> = recent.get("a").iter().map(|e| e.status).collect();
+        assert_eq!(statuses, vec![500, 429]);
+        assert!(recent.get("b").is_empty());
+
+        let mut capture = Capture {
+            recent: recent.clone(),
+            key_id: "b".to_string(),
+            entry: entry(200),
+            start: Instant::now(),
+            body: Vec::new(),
+        };
+        capture.push(b"event: ping\n\n");
+        capture.push(&vec![b'x'; MAX_RESPONSE_BYTES]);
+        drop(capture);
+
+        let recorded = &recent.get("b")[0];
+        assert!(recorded.truncated);
+        assert_eq!(recorded.response.len(), MAX_RESPONSE_BYTES);
+        assert!(recorded.response.starts_with("event: ping"));
+    }
+}
@@ -1,26 +1,109 @@
 //! Anthropic API 路由配置
 
 use axum::{
+    extract::DefaultBodyLimit,
     middleware,
     routing::{get, post},
     Router,
 };
 use std::sync::Arc;
+use std::time::Duration;
 
+use crate::kiro::parser::decoder::DecoderConfig;
 use crate::kiro::provider::KiroProvider;
-use crate::pool::AccountPool;
+use crate::model::config::{Config, RequestTimeouts};
+use crate::pool::{AccountPool, Workspace};
 
 use super::{
-    handlers::{count_tokens, get_models, post_messages},
-    middleware::{auth_middleware, cors_layer, AppState},
+    api_keys::ApiKeyStore,
+    blocks::BlockPolicy,
+    conversations::ConversationCache,
+    converter::ToolLimits,
+    embeddings::EmbeddingsProxy,
+    files::FileStore,
+    handlers::{
+        count_tokens, get_client_stats, get_file, get_model, get_models, post_embeddings,
+        post_estimate, post_messages, upload_file,
+    },
+    middleware::{
+        auth_middleware, beta_middleware, cors_layer, version_middleware, AppState, PreviousApiKey,
+    },
+    postprocess::PostProcessConfig,
+    prefix,
+    recent::recent_middleware,
+    scheduler::PriorityScheduler,
+    server_tools::ServerTools,
+    shadow::{shadow_middleware, ShadowMirror},
+    stream::EventFilter,
+    telemetry::Telemetry,
+    templates::{template_middleware, TemplateStore},
 };
 
+/// 上传文件大小上限（32MB）
+const MAX_FILE_SIZE: usize = 32 * 1024 * 1024;
+
+/// 根据配置设置应用状态中的可选功能
+pub(super) fn apply_config(mut state: AppState, config: &Config) -> AppState {
+    let event_filter = EventFilter::from_names(&config.disabled_events)
+        .with_context_usage(config.emit_context_usage);
+    state = state
+        .with_previous_api_key(PreviousApiKey::from_config(config))
+        .with_event_filter(event_filter)
+        .with_file_store(FileStore::new(&config.files_dir))
+        .with_system_prompt(config.system_prompt.clone(), config.system_prompt_position)
+        .with_tool_limits(ToolLimits::from(config))
+        .with_client_tools(config.client_tools.clone())
+        .with_block_policy(BlockPolicy::from(config))
+        .with_unknown_beta_policy(config.unknown_beta_policy)
+        .with_agent_mode(config.agent_mode)
+        .with_origin(config.origin, config.chat_trigger_type)
+        .with_timeouts(RequestTimeouts::from(config))
+        .with_max_body_bytes(config.max_request_body_mb * 1024 * 1024)
+        .with_chaos(config.chaos.clone())
+        .with_decoder(DecoderConfig::from(&config.decoder))
+        .with_sse(config.sse.clone())
+        .with_templates(Arc::new(TemplateStore::new(
+            config.prompt_templates.clone(),
+        )))
+        .with_conversations(Arc::new(ConversationCache::from(config)))
+        .with_post_process(
+            PostProcessConfig::from(config).with_strip_followup(event_filter.suppress_followup),
+        );
+    if config.coalesce_streams {
+        state = state.with_stream_coalescer();
+    }
+    if let Some(proxy) = EmbeddingsProxy::from_config(config) {
+        state = state.with_embeddings(proxy);
+    }
+    if let Some(mirror) = config.shadow.as_ref().and_then(ShadowMirror::new) {
+        state = state.with_shadow(mirror);
+    }
+    if let Some(tools) = ServerTools::from_config(config) {
+        state = state.with_server_tools(tools);
+    }
+    if let Some(telemetry) = Telemetry::spawn(config) {
+        state = state.with_telemetry(telemetry);
+    }
+    if config.max_concurrent_requests > 0 {
+        state = state.with_scheduler(PriorityScheduler::new(
+            config.max_concurrent_requests,
+            Duration::from_secs(config.queue_timeout_secs),
+        ));
+    }
+    state
+}
+
 /// 创建 Anthropic API 路由
 ///
 /// # 端点
 /// - `GET /v1/models` - 获取可用模型列表
-/// - `POST /v1/messages` - 创建消息（对话）
+/// - `POST /v1/messages` - 创建消息（对话），`?dry_run=true` 时只返回转换结果
 /// - `POST /v1/messages/count_tokens` - 计算 token 数量
+/// - `POST /v1/files` - 上传文件
+/// - `GET /v1/files/{file_id}` - 获取文件元数据
+/// - `POST /v1/embeddings` - 转发到外部 embeddings 服务（OpenAI 兼容）
+///
+/// 配置 `pathPrefix` 时全部端点挂载在该前缀下（如 `/kiro/v1/messages`）
 ///
 /// # 认证
 /// 所有 `/v1` 路径需要 API Key 认证，支持：
@@ -30,14 +113,17 @@ use super::{
 /// # 参数
 /// - `api_key`: API 密钥，用于验证客户端请求
 /// - `kiro_provider`: 可选的 KiroProvider，用于调用上游 API
+/// - `config`: 应用配置，用于读取事件过滤等选项
 
 /// 创建带有 KiroProvider 的 Anthropic API 路由
+#[allow(clippy::empty_line_after_doc_comments, clippy::doc_lazy_continuation)]
 pub fn create_router_with_provider(
     api_key: impl Into<String>,
     kiro_provider: Option<KiroProvider>,
     profile_arn: Option<String>,
+    config: &Config,
 ) -> Router {
-    let mut state = AppState::new(api_key);
+    let mut state = apply_config(AppState::new(api_key), config);
     if let Some(provider) = kiro_provider {
         state = state.with_kiro_provider(provider);
     }
@@ -48,35 +134,112 @@ pub fn create_router_with_provider(
     // 需要认证的 /v1 路由
     let v1_routes = Router::new()
         .route("/models", get(get_models))
-        .route("/messages", post(post_messages))
+        .route("/models/{model_id}", get(get_model))
+        .route(
+            "/messages",
+            post(post_messages)
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    shadow_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    template_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    recent_middleware,
+                )),
+        )
         .route("/messages/count_tokens", post(count_tokens))
+        .route("/estimate", post(post_estimate))
+        .route(
+            "/files",
+            post(upload_file).layer(DefaultBodyLimit::max(MAX_FILE_SIZE)),
+        )
+        .route("/files/{file_id}", get(get_file))
+        .route("/embeddings", post(post_embeddings))
+        .route("/clients", get(get_client_stats))
+        .layer(middleware::from_fn(version_middleware))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            beta_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ));
 
-    Router::new()
+    let router = Router::new()
         .nest("/v1", v1_routes)
         .layer(cors_layer())
-        .with_state(state)
+        .with_state(state);
+    prefix::mount(router, &config.path_prefix)
 }
 
 /// 创建带有账号池的 Anthropic API 路由
-pub fn create_router_with_pool(api_key: impl Into<String>, pool: Arc<AccountPool>) -> Router {
-    let state = AppState::new(api_key).with_account_pool(pool);
+///
+/// 使用工作区 API Key 的请求将路由到该工作区独立的账号池
+pub fn create_router_with_pool(
+    api_key: impl Into<String>,
+    pool: Arc<AccountPool>,
+    workspaces: Vec<Workspace>,
+    templates: Arc<TemplateStore>,
+    api_keys: Arc<ApiKeyStore>,
+    conversations: Arc<ConversationCache>,
+    config: &Config,
+) -> Router {
+    let state = apply_config(
+        AppState::new(api_key)
+            .with_account_pool(pool)
+            .with_workspaces(workspaces)
+            .with_api_keys(api_keys),
+        config,
+    )
+    .with_templates(templates)
+    .with_conversations(conversations);
 
     // 需要认证的 /v1 路由
     let v1_routes = Router::new()
         .route("/models", get(get_models))
-        .route("/messages", post(post_messages))
+        .route("/models/{model_id}", get(get_model))
+        .route(
+            "/messages",
+            post(post_messages)
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    shadow_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    template_middleware,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    recent_middleware,
+                )),
+        )
         .route("/messages/count_tokens", post(count_tokens))
+        .route("/estimate", post(post_estimate))
+        .route(
+            "/files",
+            post(upload_file).layer(DefaultBodyLimit::max(MAX_FILE_SIZE)),
+        )
+        .route("/files/{file_id}", get(get_file))
+        .route("/embeddings", post(post_embeddings))
+        .layer(middleware::from_fn(version_middleware))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            beta_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ));
 
-    Router::new()
+    let router = Router::new()
         .nest("/v1", v1_routes)
         .layer(cors_layer())
-        .with_state(state)
+        .with_state(state);
+    prefix::mount(router, &config.path_prefix)
 }
@@ -0,0 +1,277 @@
+//! 请求优先级调度
+//!
+//! 配置 `maxConcurrentRequests` 后，同时发往上游的请求数受限，超出的请求排队等待。
+//! 交互式请求优先于批处理请求出队；批处理请求有等待者时，每连续放行
+//! `STARVATION_LIMIT` 个交互式请求就放行一个批处理请求，避免批处理请求饿死。
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use crate::model::config::RequestPriority as Priority;
+
+/// 有批处理请求等待时，最多连续放行的交互式请求数
+const STARVATION_LIMIT: u32 = 4;
+
+/// 排队超时
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueTimeout;
+
+struct Waiter {
+    id: u64,
+    tx: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    running: usize,
+    interactive: VecDeque<Waiter>,
+    batch: VecDeque<Waiter>,
+    /// 批处理请求等待期间连续放行的交互式请求数
+    interactive_streak: u32,
+    next_id: u64,
+}
+
+impl SchedulerState {
+    /// 按优先级取出下一个等待者
+    fn next_waiter(&mut self) -> Option<Waiter> {
+        if self.batch.is_empty() {
+            self.interactive_streak = 0;
+            return self.interactive.pop_front();
+        }
+        if self.interactive.is_empty() || self.interactive_streak >= STARVATION_LIMIT {
+            self.interactive_streak = 0;
+            return self.batch.pop_front();
+        }
+        self.interactive_streak += 1;
+        self.interactive.pop_front()
+    }
+
+    /// 有空闲名额时唤醒等待者，跳过已取消的等待者
+    fn dispatch(&mut self, max: usize) {
+        while self.running < max {
+            let Some(waiter) = self.next_waiter() else {
+                return;
+            };
+            if waiter.tx.send(()).is_ok() {
+                self.running += 1;
+            }
+        }
+    }
+
+    fn remove(&mut self, id: u64) -> bool {
+        for queue in [&mut self.interactive, &mut self.batch] {
+            if let Some(pos) = queue.iter().position(|w| w.id == id) {
+                queue.remove(pos);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// 请求优先级调度器
+pub struct PriorityScheduler {
+    max_concurrent: usize,
+    queue_timeout: Duration,
+    state: Mutex<SchedulerState>,
+}
+
+impl PriorityScheduler {
+    pub fn new(max_concurrent: usize, queue_timeout: Duration) -> Self {
+        Self {
+            max_concurrent: max_concurrent.max(1),
+            queue_timeout,
+            state: Mutex::new(SchedulerState::default()),
+        }
+    }
+
+    /// 获取执行名额，排队超过超时时间时返回错误
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Result<Permit, QueueTimeout> {
+        let (id, rx) = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.max_concurrent
+                && state.interactive.is_empty()
+                && state.batch.is_empty()
+            {
+                state.running += 1;
+                return Ok(self.permit());
+            }
+            let (tx, rx) = oneshot::channel();
+            let id = state.next_id;
+            state.next_id += 1;
+            match priority {
+                Priority::Interactive => state.interactive.push_back(Waiter { id, tx }),
+                Priority::Batch => state.batch.push_back(Waiter { id, tx }),
+            }
+            (id, rx)
+        };
+
+        let mut ticket = Ticket {
+            scheduler: self.clone(),
+            id,
+            rx,
+            settled: false,
+        };
+        tracing::debug!("上游并发已满，{:?} 请求排队等待", priority);
+        let woken = matches!(
+            tokio::time::timeout(self.queue_timeout, &mut ticket.rx).await,
+            Ok(Ok(()))
+        );
+        ticket.settled = true;
+        // 超时：仍在队列中则移除；已被唤醒（名额已计入）则照常执行
+        if woken || !self.state.lock().unwrap().remove(id) {
+            Ok(self.permit())
+        } else {
+            Err(QueueTimeout)
+        }
+    }
+
+    /// 当前排队的请求数（交互式, 批处理）
+    pub fn queued(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.interactive.len(), state.batch.len())
+    }
+
+    fn permit(self: &Arc<Self>) -> Permit {
+        Permit {
+            scheduler: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.running = state.running.saturating_sub(1);
+        state.dispatch(self.max_concurrent);
+    }
+}
+
+/// 排队中的请求
+///
+/// `acquire` 在等待期间被取消（如客户端断开或截止时间到达）时，仍在队列中则移出队列，
+/// 已被唤醒则归还已计入的名额
+struct Ticket {
+    scheduler: Arc<PriorityScheduler>,
+    id: u64,
+    rx: oneshot::Receiver<()>,
+    /// `acquire` 已处理排队结果
+    settled: bool,
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        let mut state = self.scheduler.state.lock().unwrap();
+        if !state.remove(self.id) && self.rx.try_recv().is_ok() {
+            state.running = state.running.saturating_sub(1);
+            state.dispatch(self.scheduler.max_concurrent);
+        }
+    }
+}
+
+/// 执行名额，释放时唤醒下一个等待者
+pub struct Permit {
+    scheduler: Arc<PriorityScheduler>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(max: usize) -> Arc<PriorityScheduler> {
+        Arc::new(PriorityScheduler::new(max, Duration::from_secs(5)))
+    }
+
+    #[tokio::test]
+    async fn test_interactive_preempts_queued_batch() {
+        let scheduler = scheduler(1);
+        let running = scheduler.acquire(Priority::Batch).await.unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (name, priority) in [
+            ("batch", Priority::Batch),
+            ("interactive", Priority::Interactive),
+        ] {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            handles.push(tokio::spawn(async move {
+                let _permit = scheduler.acquire(priority).await.unwrap();
+                order.lock().unwrap().push(name);
+            }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(scheduler.queued(), (1, 1));
+
+        drop(running);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec!["interactive", "batch"]);
+    }
+
+    #[test]
+    fn test_batch_is_not_starved() {
+        let mut state = SchedulerState::default();
+        for id in 0..10 {
+            state.interactive.push_back(Waiter {
+                id,
+                tx: oneshot::channel().0,
+            });
+        }
+        state.batch.push_back(Waiter {
+            id: 100,
+            tx: oneshot::channel().0,
+        });
+
+        let order: Vec<u64> = std::iter::from_fn(|| state.next_waiter().map(|w| w.id))
+            .take(6)
+            .collect();
+        assert_eq!(order, vec![0, 1, 2, 3, 100, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let scheduler = Arc::new(PriorityScheduler::new(1, Duration::from_millis(20)));
+        let _running = scheduler.acquire(Priority::Interactive).await.unwrap();
+
+        assert!(scheduler.acquire(Priority::Batch).await.is_err());
+        assert_eq!(scheduler.queued(), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_acquire_releases_slot() {
+        let scheduler = scheduler(1);
+        let running = scheduler.acquire(Priority::Interactive).await.unwrap();
+
+        // 排队中被取消：移出队列
+        let mut queued = Box::pin(scheduler.acquire(Priority::Batch));
+        assert!(futures::poll!(&mut queued).is_pending());
+        assert_eq!(scheduler.queued(), (0, 1));
+        drop(queued);
+        assert_eq!(scheduler.queued(), (0, 0));
+
+        // 已被唤醒但未取用名额时被取消：归还名额
+        let mut woken = Box::pin(scheduler.acquire(Priority::Interactive));
+        assert!(futures::poll!(&mut woken).is_pending());
+        drop(running);
+        drop(woken);
+
+        let next = tokio::time::timeout(
+            Duration::from_millis(100),
+            scheduler.acquire(Priority::Interactive),
+        )
+        .await;
+        assert!(matches!(next, Ok(Ok(_))));
+    }
+}
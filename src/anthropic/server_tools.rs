@@ -0,0 +1,539 @@
+//! 代理端执行的内置工具
+//!
+//! 启用后代理向请求注入白名单中的内置工具定义（客户端已定义同名工具时不注入）。
+//! 模型只请求内置工具时，代理执行工具并把结果作为新一轮对话发回 Kiro，
+//! 直到模型给出最终回答或达到轮次上限，客户端只收到最后一轮的响应。
+//!
+//! fetch_url 只访问解析到公网地址的主机，不自动跟随重定向，每一跳都重新检查，
+//! 避免模型（或对话中的提示注入）借代理访问本机管理接口、云元数据或内网服务。
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+use reqwest::{redirect, Client, Url};
+use serde_json::{json, Value};
+
+use crate::http_client::{client_builder, ProxyConfig};
+use crate::kiro::model::events::{Event, ToolUseEvent};
+use crate::model::config::Config;
+
+use super::types::{Message, MessagesRequest, Tool};
+
+/// fetch_url 请求超时（秒）
+const FETCH_TIMEOUT_SECS: u64 = 30;
+
+/// fetch_url 返回的响应体上限（字节），超出部分截断
+const FETCH_MAX_BYTES: usize = 100 * 1024;
+
+/// fetch_url 最多跟随的重定向次数
+const FETCH_MAX_REDIRECTS: usize = 5;
+
+/// 内置工具
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerTool {
+    /// 获取当前 UTC 时间
+    CurrentTime,
+    /// 抓取 http(s) URL 的内容
+    FetchUrl,
+}
+
+impl ServerTool {
+    /// 全部内置工具
+    pub const ALL: [ServerTool; 2] = [ServerTool::CurrentTime, ServerTool::FetchUrl];
+
+    /// 从工具名称解析
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.name() == name.trim())
+    }
+
+    /// 工具名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerTool::CurrentTime => "current_time",
+            ServerTool::FetchUrl => "fetch_url",
+        }
+    }
+
+    /// 注入请求的工具定义
+    fn definition(&self) -> Tool {
+        let (description, schema) = match self {
+            ServerTool::CurrentTime => (
+                "Get the current date and time in UTC (RFC 3339).",
+                json!({"type": "object", "properties": {}}),
+            ),
+            ServerTool::FetchUrl => (
+                "Fetch an http(s) URL and return the HTTP status and response body as text. \
+                 Long bodies are truncated.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "url": {"type": "string", "description": "The http or https URL to fetch"}
+                    },
+                    "required": ["url"]
+                }),
+            ),
+        };
+        let input_schema: HashMap<String, Value> =
+            serde_json::from_value(schema).unwrap_or_default();
+        Tool {
+            name: self.name().to_string(),
+            description: description.to_string(),
+            input_schema,
+        }
+    }
+}
+
+/// 内置工具执行器
+pub struct ServerTools {
+    tools: Vec<ServerTool>,
+    max_iterations: usize,
+    client: Client,
+    /// 放行的本地地址（仅测试使用）
+    exempt: Option<SocketAddr>,
+}
+
+impl ServerTools {
+    /// 从全局配置创建，未启用任何内置工具时返回 None
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let tools: Vec<ServerTool> = config
+            .server_tools
+            .iter()
+            .filter_map(|name| ServerTool::parse(name))
+            .collect();
+        if tools.is_empty() {
+            return None;
+        }
+        let proxy = ProxyConfig::from_config(config);
+        let client = match client_builder(proxy.as_ref(), FETCH_TIMEOUT_SECS)
+            .and_then(|builder| Ok(builder.redirect(redirect::Policy::none()).build()?))
+        {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("创建内置工具 HTTP 客户端失败: {}", e);
+                return None;
+            }
+        };
+        Some(Self {
+            tools,
+            max_iterations: config.server_tool_max_iterations,
+            client,
+            exempt: None,
+        })
+    }
+
+    /// 单个请求内最多执行内置工具的轮次
+    pub fn max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+
+    /// 向请求注入客户端未定义的内置工具，返回实际注入的工具
+    pub fn inject(&self, req: &mut MessagesRequest) -> Vec<ServerTool> {
+        let tools = req.tools.get_or_insert_with(Vec::new);
+        let injected: Vec<ServerTool> = self
+            .tools
+            .iter()
+            .copied()
+            .filter(|t| !tools.iter().any(|defined| defined.name == t.name()))
+            .collect();
+        tools.extend(injected.iter().map(ServerTool::definition));
+        if tools.is_empty() {
+            req.tools = None;
+        }
+        injected
+    }
+
+    /// 执行一次工具调用，返回结果文本和是否出错
+    pub async fn execute(&self, tool: ServerTool, input: &Value) -> (String, bool) {
+        match tool {
+            ServerTool::CurrentTime => (chrono::Utc::now().to_rfc3339(), false),
+            ServerTool::FetchUrl => match self.fetch(input).await {
+                Ok(output) => (output, false),
+                Err(e) => (e, true),
+            },
+        }
+    }
+
+    async fn fetch(&self, input: &Value) -> Result<String, String> {
+        let url = input
+            .get("url")
+            .and_then(|v| v.as_str())
+            .ok_or("Missing required parameter: url")?;
+        let mut url = Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+
+        let mut redirects = 0;
+        let mut response = loop {
+            self.check_url(&url).await?;
+            let response = self
+                .client
+                .get(url.clone())
+                .send()
+                .await
+                .map_err(|e| format!("Request failed: {}", e))?;
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok());
+            match location {
+                Some(location) if response.status().is_redirection() => {
+                    if redirects == FETCH_MAX_REDIRECTS {
+                        return Err(format!("Too many redirects (max {})", FETCH_MAX_REDIRECTS));
+                    }
+                    redirects += 1;
+                    url = url
+                        .join(location)
+                        .map_err(|e| format!("Invalid redirect URL: {}", e))?;
+                }
+                _ => break response,
+            }
+        };
+        let status = response.status();
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?
+        {
+            body.extend_from_slice(&chunk);
+            if body.len() > FETCH_MAX_BYTES {
+                body.truncate(FETCH_MAX_BYTES);
+                truncated = true;
+                break;
+            }
+        }
+
+        let mut output = format!("HTTP {}\n\n{}", status, String::from_utf8_lossy(&body));
+        if truncated {
+            output.push_str("\n\n[truncated]");
+        }
+        Ok(output)
+    }
+
+    /// 检查 URL 协议，并确认主机解析到的所有地址都不是本机或内网地址
+    async fn check_url(&self, url: &Url) -> Result<(), String> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("Unsupported URL scheme: {}", url.scheme()));
+        }
+        let host = url.host_str().ok_or("URL has no host")?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("Failed to resolve host {}: {}", host, e))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(format!("Failed to resolve host {}", host));
+        }
+        if let Some(addr) = addrs
+            .iter()
+            .find(|addr| Some(**addr) != self.exempt && is_internal(addr.ip()))
+        {
+            return Err(format!(
+                "Blocked URL: {} resolves to non-public address {}",
+                host,
+                addr.ip()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 是否为本机、内网、链路本地、唯一本地或未指定地址
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // 运营商级 NAT 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(v4) => is_internal(IpAddr::V4(v4)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            }
+        },
+    }
+}
+
+/// 一轮上游响应中的文本、工具调用和用量
+#[derive(Debug, Default)]
+pub struct ToolTurn {
+    pub text: String,
+    pub tool_uses: Vec<ToolUseEvent>,
+    /// meteringEvent 报告的额度消耗
+    pub metering_usage: f64,
+    pub metering_unit: Option<String>,
+    /// contextUsageEvent 报告的上下文使用百分比
+    pub context_usage_percentage: Option<f64>,
+}
+
+impl ToolTurn {
+    /// 从拼接完整的上游事件中提取
+    pub fn from_events(events: &[Event]) -> Self {
+        let mut turn = Self::default();
+        for event in events {
+            match event {
+                Event::AssistantResponse(resp) => turn.text.push_str(&resp.content),
+                Event::ToolUse(tool_use) => turn.tool_uses.push(tool_use.clone()),
+                Event::Metering(metering) => {
+                    turn.metering_usage += metering.usage;
+                    turn.metering_unit = metering.unit.clone().or(turn.metering_unit.take());
+                }
+                Event::ContextUsage(usage) => {
+                    turn.context_usage_percentage = Some(usage.context_usage_percentage);
+                }
+                _ => {}
+            }
+        }
+        turn
+    }
+
+    /// 是否请求了工具且全部是代理执行的内置工具
+    pub fn only_calls(&self, tools: &[ServerTool]) -> bool {
+        !self.tool_uses.is_empty()
+            && self
+                .tool_uses
+                .iter()
+                .all(|t| ServerTool::parse(&t.name).is_some_and(|tool| tools.contains(&tool)))
+    }
+
+    /// 本轮响应对应的 assistant 消息内容
+    pub fn content(&self) -> Vec<Value> {
+        let mut content = Vec::new();
+        if !self.text.is_empty() {
+            content.push(json!({"type": "text", "text": self.text}));
+        }
+        content.extend(self.tool_uses.iter().map(|tool_use| {
+            json!({
+                "type": "tool_use",
+                "id": tool_use.tool_use_id,
+                "name": tool_use.name,
+                "input": tool_input(tool_use)
+            })
+        }));
+        content
+    }
+
+    /// 执行全部工具调用，并把本轮响应和工具结果追加到请求消息中
+    pub async fn execute_into(self, tools: &ServerTools, req: &mut MessagesRequest) {
+        let assistant = self.content();
+        let mut results = Vec::new();
+        for tool_use in self.tool_uses {
+            let input = tool_input(&tool_use);
+            let (content, is_error) = match ServerTool::parse(&tool_use.name) {
+                Some(tool) => tools.execute(tool, &input).await,
+                None => (format!("Unknown tool: {}", tool_use.name), true),
+            };
+            tracing::info!("执行内置工具 {}，出错: {}", tool_use.name, is_error);
+            results.push(json!({
+                "type": "tool_result",
+                "tool_use_id": tool_use.tool_use_id,
+                "content": content,
+                "is_error": is_error
+            }));
+        }
+        req.messages.push(Message {
+            role: "assistant".to_string(),
+            content: Value::Array(assistant),
+        });
+        req.messages.push(Message {
+            role: "user".to_string(),
+            content: Value::Array(results),
+        });
+    }
+}
+
+/// 工具调用参数（解析失败时为空对象）
+fn tool_input(tool_use: &ToolUseEvent) -> Value {
+    serde_json::from_str(&tool_use.input).unwrap_or_else(|_| json!({}))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::events::{AssistantResponseEvent, ContextUsageEvent, MeteringEvent};
+
+    fn server_tools(names: &[&str]) -> ServerTools {
+        let config = Config {
+            server_tools: names.iter().map(|n| n.to_string()).collect(),
+            ..Default::default()
+        };
+        ServerTools::from_config(&config).unwrap()
+    }
+
+    fn request() -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "tools": [{"name": "current_time", "description": "client clock", "input_schema": {"type": "object"}}],
+            "messages": [{"role": "user", "content": "what time is it?"}]
+        }))
+        .unwrap()
+    }
+
+    fn tool_use(name: &str, input: &str) -> Event {
+        Event::ToolUse(ToolUseEvent {
+            name: name.to_string(),
+            tool_use_id: format!("tooluse_{}", name),
+            input: input.to_string(),
+            stop: true,
+        })
+    }
+
+    #[test]
+    fn test_inject_skips_client_tools() {
+        assert!(ServerTools::from_config(&Config::default()).is_none());
+
+        let tools = server_tools(&["current_time", "fetch_url", "unknown"]);
+        let mut req = request();
+        assert_eq!(tools.inject(&mut req), vec![ServerTool::FetchUrl]);
+        let names: Vec<_> = req.tools.unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["current_time", "fetch_url"]);
+    }
+
+    #[test]
+    fn test_only_calls() {
+        let injected = [ServerTool::CurrentTime];
+        let turn = ToolTurn::from_events(&[tool_use("current_time", "{}")]);
+        assert!(turn.only_calls(&injected));
+        assert!(!turn.only_calls(&[ServerTool::FetchUrl]));
+
+        let mixed =
+            ToolTurn::from_events(&[tool_use("current_time", "{}"), tool_use("read", "{}")]);
+        assert!(!mixed.only_calls(&injected));
+        assert!(!ToolTurn::default().only_calls(&injected));
+    }
+
+    #[test]
+    fn test_from_events_collects_usage() {
+        let metering = |usage: f64| {
+            Event::Metering(MeteringEvent {
+                unit: Some("credit".to_string()),
+                usage,
+                ..Default::default()
+            })
+        };
+        let turn = ToolTurn::from_events(&[
+            tool_use("fetch_url", r#"{"url": "https://example.com"}"#),
+            Event::ContextUsage(ContextUsageEvent {
+                context_usage_percentage: 1.5,
+            }),
+            metering(0.25),
+            metering(0.5),
+        ]);
+        assert_eq!(turn.metering_usage, 0.75);
+        assert_eq!(turn.metering_unit.as_deref(), Some("credit"));
+        assert_eq!(turn.context_usage_percentage, Some(1.5));
+        assert_eq!(
+            turn.content()[0]["input"],
+            json!({"url": "https://example.com"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_execute_into_appends_turn() {
+        let tools = server_tools(&["current_time", "fetch_url"]);
+        let mut req = request();
+        req.tools = None;
+        tools.inject(&mut req);
+        let mut text = AssistantResponseEvent::default();
+        text.content = "Let me check.".to_string();
+        let turn = ToolTurn::from_events(&[
+            Event::AssistantResponse(text),
+            tool_use("current_time", "{}"),
+            tool_use("fetch_url", r#"{"url": "file:///etc/passwd"}"#),
+        ]);
+        turn.execute_into(&tools, &mut req).await;
+
+        assert_eq!(req.messages.len(), 3);
+        let results = req.messages[2].content.as_array().unwrap();
+        assert_eq!(results[0]["is_error"], false);
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(results[0]["content"].as_str().unwrap()).is_ok()
+        );
+        assert_eq!(results[1]["is_error"], true);
+        assert_eq!(results[1]["content"], "Unsupported URL scheme: file");
+
+        // 追加后的对话可以正常转换
+        assert!(super::super::converter::convert_request(&req).is_ok());
+    }
+
+    #[test]
+    fn test_is_internal() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "8.8.8.8", "2606:4700::1111"] {
+            assert!(!is_internal(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_loopback() {
+        let tools = server_tools(&["fetch_url"]);
+        for url in [
+            "http://127.0.0.1:1/api/accounts",
+            "http://localhost:1/",
+            "http://[::1]:1/",
+            "http://169.254.169.254/latest/meta-data/",
+        ] {
+            let err = tools.fetch(&json!({ "url": url })).await.unwrap_err();
+            assert!(err.starts_with("Blocked URL"), "{}: {}", url, err);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_redirect_to_loopback() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new()
+            .route("/ok", axum::routing::get(|| async { "hello" }))
+            .route(
+                "/",
+                axum::routing::get(|| async {
+                    axum::response::Redirect::temporary("http://127.0.0.1:1/api/accounts")
+                }),
+            );
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        // 放行测试服务器本身，模拟一个公网地址返回指向本机的重定向
+        let mut tools = server_tools(&["fetch_url"]);
+        tools.exempt = Some(addr);
+        let ok = tools
+            .fetch(&json!({ "url": format!("http://{}/ok", addr) }))
+            .await
+            .unwrap();
+        assert_eq!(ok, "HTTP 200 OK\n\nhello");
+        let err = tools
+            .fetch(&json!({ "url": format!("http://{}/", addr) }))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Blocked URL: 127.0.0.1 resolves to non-public address 127.0.0.1"
+        );
+    }
+}
@@ -0,0 +1,276 @@
+//! 影子流量（流量镜像）
+//!
+//! 配置 `shadow` 后，按比例把 `/v1/messages` 请求体复制一份发送到另一个 Anthropic 兼容后端
+//! （如使用不同区域或账号集的 kiro-rs 实例、预发布构建），比较两边的状态码和响应延迟。
+//! 镜像请求在后台执行，客户端始终只收到主后端的响应；两边的差异按请求记录 debug 日志，
+//! 并每隔 [`SUMMARY_INTERVAL`] 次对比输出一次汇总，用于在切换配置前验证其行为。
+//!
+//! 延迟统一按收到响应头的时间计算（流式响应即首字节时间），两边口径一致。
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use reqwest::Client;
+use tokio::sync::oneshot;
+
+use crate::http_client::build_client;
+use crate::model::config::ShadowConfig;
+
+use super::body::read_body;
+use super::middleware::AppState;
+
+/// 每完成多少次对比输出一次汇总日志
+const SUMMARY_INTERVAL: u64 = 100;
+/// 原样转发给影子后端的请求头
+const FORWARDED_HEADERS: &[&str] = &["anthropic-version", "accept", "x-kiro-agent-mode"];
+
+/// 单侧请求结果：状态码（请求失败时为 None）和收到响应头的耗时
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    pub status: Option<StatusCode>,
+    pub latency: Duration,
+}
+
+impl Outcome {
+    fn is_error(&self) -> bool {
+        !self.status.is_some_and(|s| s.is_success())
+    }
+}
+
+/// 主后端与影子后端的差异统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShadowStats {
+    /// 完成对比的请求数
+    pub compared: u64,
+    /// 状态码不一致的请求数
+    pub status_mismatches: u64,
+    /// 主后端返回错误的请求数
+    pub primary_errors: u64,
+    /// 影子后端返回错误（或请求失败）的请求数
+    pub shadow_errors: u64,
+    /// 主后端累计耗时（毫秒）
+    pub primary_latency_ms: u64,
+    /// 影子后端累计耗时（毫秒）
+    pub shadow_latency_ms: u64,
+}
+
+impl ShadowStats {
+    /// 记录一次对比，返回状态码是否一致
+    fn record(&mut self, primary: Outcome, shadow: Outcome) -> bool {
+        self.compared += 1;
+        let matched = primary.status == shadow.status;
+        if !matched {
+            self.status_mismatches += 1;
+        }
+        if primary.is_error() {
+            self.primary_errors += 1;
+        }
+        if shadow.is_error() {
+            self.shadow_errors += 1;
+        }
+        self.primary_latency_ms += primary.latency.as_millis() as u64;
+        self.shadow_latency_ms += shadow.latency.as_millis() as u64;
+        matched
+    }
+
+    /// 两边平均耗时（毫秒）
+    fn mean_latency_ms(&self) -> (u64, u64) {
+        let n = self.compared.max(1);
+        (self.primary_latency_ms / n, self.shadow_latency_ms / n)
+    }
+}
+
+/// 影子流量镜像器
+pub struct ShadowMirror {
+    url: String,
+    api_key: Option<String>,
+    percent: f64,
+    client: Client,
+    stats: Mutex<ShadowStats>,
+}
+
+impl ShadowMirror {
+    /// 从配置创建，创建 HTTP 客户端失败时返回 None
+    ///
+    /// 影子后端通常是内网或本机的另一实例，不经过上游代理
+    pub fn new(config: &ShadowConfig) -> Option<Self> {
+        let client = match build_client(None, config.timeout_secs) {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("创建影子流量 HTTP 客户端失败: {}", e);
+                return None;
+            }
+        };
+        tracing::info!(
+            "已启用影子流量：{}% 的请求镜像到 {}",
+            config.percent,
+            config.url
+        );
+        Some(Self {
+            url: format!("{}/v1/messages", config.url.trim_end_matches('/')),
+            api_key: config.api_key.clone(),
+            percent: config.percent,
+            client,
+            stats: Mutex::new(ShadowStats::default()),
+        })
+    }
+
+    /// 按配置的百分比抽样
+    fn sample(&self) -> bool {
+        self.percent > 0.0 && fastrand::f64() * 100.0 < self.percent
+    }
+
+    /// 后台发送影子请求，等待主后端结果后记录差异
+    fn spawn(self: Arc<Self>, headers: &HeaderMap, body: Bytes) -> oneshot::Sender<Outcome> {
+        let (tx, rx) = oneshot::channel();
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body);
+        for name in FORWARDED_HEADERS {
+            if let Some(value) = headers.get(*name) {
+                request = request.header(*name, value.clone());
+            }
+        }
+        if let Some(key) = &self.api_key {
+            request = request.header("x-api-key", key);
+        }
+
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let result = request.send().await;
+            let latency = start.elapsed();
+            let status = match result {
+                Ok(response) => {
+                    let status = StatusCode::from_u16(response.status().as_u16()).ok();
+                    // 读完响应体，避免影子后端因连接提前关闭而中止处理
+                    let _ = response.bytes().await;
+                    status
+                }
+                Err(e) => {
+                    tracing::debug!("影子请求失败: {}", e);
+                    None
+                }
+            };
+            let shadow = Outcome { status, latency };
+            // 主请求处理被取消（如客户端断开）时不记录
+            if let Ok(primary) = rx.await {
+                self.compare(primary, shadow);
+            }
+        });
+        tx
+    }
+
+    fn compare(&self, primary: Outcome, shadow: Outcome) {
+        let stats = {
+            let mut stats = self.stats.lock().unwrap();
+            if !stats.record(primary, shadow) {
+                tracing::debug!(
+                    "影子请求状态码不一致: 主后端 {:?}，影子后端 {:?}",
+                    primary.status,
+                    shadow.status
+                );
+            }
+            stats.clone()
+        };
+        if stats.compared % SUMMARY_INTERVAL == 0 {
+            let (primary_ms, shadow_ms) = stats.mean_latency_ms();
+            tracing::info!(
+                compared = stats.compared,
+                status_mismatches = stats.status_mismatches,
+                primary_errors = stats.primary_errors,
+                shadow_errors = stats.shadow_errors,
+                primary_mean_ms = primary_ms,
+                shadow_mean_ms = shadow_ms,
+                "影子流量对比汇总"
+            );
+        }
+    }
+}
+
+/// 影子流量中间件，仅用于 `/v1/messages`
+///
+/// 位于模板中间件之后，镜像的是展开模板后的请求体；试运行请求不镜像
+pub async fn shadow_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(mirror) = state.shadow.clone().filter(|m| m.sample()) else {
+        return next.run(request).await;
+    };
+    if request
+        .uri()
+        .query()
+        .is_some_and(|q| q.contains("dry_run=true"))
+    {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let bytes = match read_body(&parts.headers, body, state.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(e) => return e.into_response(),
+    };
+    let tx = mirror.spawn(&parts.headers, bytes.clone());
+
+    let start = Instant::now();
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    let _ = tx.send(Outcome {
+        status: Some(response.status()),
+        latency: start.elapsed(),
+    });
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(status: Option<u16>, ms: u64) -> Outcome {
+        Outcome {
+            status: status.map(|s| StatusCode::from_u16(s).unwrap()),
+            latency: Duration::from_millis(ms),
+        }
+    }
+
+    #[test]
+    fn test_stats_record() {
+        let mut stats = ShadowStats::default();
+        assert!(stats.record(outcome(Some(200), 100), outcome(Some(200), 300)));
+        assert!(!stats.record(outcome(Some(200), 100), outcome(Some(429), 50)));
+        assert!(!stats.record(outcome(Some(503), 100), outcome(None, 10)));
+
+        assert_eq!(stats.compared, 3);
+        assert_eq!(stats.status_mismatches, 2);
+        assert_eq!(stats.primary_errors, 1);
+        assert_eq!(stats.shadow_errors, 2);
+        assert_eq!(stats.mean_latency_ms(), (100, 120));
+    }
+
+    #[test]
+    fn test_sample_bounds() {
+        let config = |percent| ShadowConfig {
+            url: "http://127.0.0.1:1".to_string(),
+            api_key: None,
+            percent,
+            timeout_secs: 1,
+        };
+        let none = ShadowMirror::new(&config(0.0)).unwrap();
+        let all = ShadowMirror::new(&config(100.0)).unwrap();
+        assert!((0..100).all(|_| !none.sample()));
+        assert!((0..100).all(|_| all.sample()));
+        assert_eq!(all.url, "http://127.0.0.1:1/v1/messages");
+    }
+}
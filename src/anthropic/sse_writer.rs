@@ -0,0 +1,328 @@
+//! SSE 事件写入器
+//!
+//! 持有 [`StreamContext`]，负责一次流式响应的完整生命周期：
+//!
+//! - `message_start` 恰好输出一次，且在所有其他事件（包括 ping）之前
+//! - 无论上游正常结束、读取失败、某个处理阶段 panic 还是达到 max_tokens 提前终止，
+//!   都会关闭未结束的内容块，并输出带 usage 的 `message_delta` 和最后的 `message_stop`
+//! - 收尾只执行一次，`on_finish` 只调用一次；收尾后不再输出任何事件
+//! - 保活 ping 只在 `message_start` 之后、`message_stop` 之前发送
+
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+use futures::{stream, FutureExt, Stream, StreamExt};
+use serde_json::json;
+use tokio::time::{interval_at, Instant};
+
+use crate::kiro::model::events::Event;
+
+use super::stream::{SseEvent, StreamContext};
+
+/// SSE 事件写入器
+pub struct SseWriter<F>
+where
+    F: FnOnce(&StreamContext),
+{
+    ctx: StreamContext,
+    /// 收尾回调，收尾后为 None
+    on_finish: Option<F>,
+    started: bool,
+}
+
+impl<F> SseWriter<F>
+where
+    F: FnOnce(&StreamContext),
+{
+    /// 创建写入器，收尾时以最终的 StreamContext 调用 `on_finish`
+    pub fn new(ctx: StreamContext, on_finish: F) -> Self {
+        Self {
+            ctx,
+            on_finish: Some(on_finish),
+            started: false,
+        }
+    }
+
+    /// 是否已收尾
+    pub fn is_finished(&self) -> bool {
+        self.on_finish.is_none()
+    }
+
+    /// 初始事件（message_start 等），只在第一次调用时输出
+    pub fn start(&mut self) -> Vec<SseEvent> {
+        if self.started {
+            return Vec::new();
+        }
+        self.started = true;
+        self.ctx.generate_initial_events()
+    }
+
+    /// 转换一个上游事件，达到 max_tokens 上限或转换 panic 时直接收尾
+    pub fn write(&mut self, event: &Event) -> Vec<SseEvent> {
+        if self.is_finished() {
+            return Vec::new();
+        }
+        let mut output = self.start();
+        let ctx = &mut self.ctx;
+        match std::panic::catch_unwind(AssertUnwindSafe(|| ctx.process_kiro_event(event))) {
+            Ok(events) => output.extend(events),
+            Err(_) => {
+                tracing::error!("转换上游事件时 panic，结束流");
+                output.extend(self.finish());
+                return output;
+            }
+        }
+        if self.ctx.max_tokens_reached {
+            output.extend(self.finish());
+        }
+        output
+    }
+
+    /// 收尾：关闭未结束的内容块，输出 message_delta 和 message_stop，只执行一次
+    pub fn finish(&mut self) -> Vec<SseEvent> {
+        let Some(on_finish) = self.on_finish.take() else {
+            return Vec::new();
+        };
+        let mut output = self.start();
+        let ctx = &mut self.ctx;
+        match std::panic::catch_unwind(AssertUnwindSafe(|| ctx.generate_final_events())) {
+            Ok(events) => output.extend(events),
+            Err(_) => {
+                tracing::error!("生成收尾事件时 panic，输出最简收尾事件");
+                output.extend(self.fallback_final_events());
+            }
+        }
+        on_finish(&self.ctx);
+        output
+    }
+
+    /// 状态不可用时的最简收尾事件
+    fn fallback_final_events(&self) -> Vec<SseEvent> {
+        let input_tokens = self
+            .ctx
+            .context_input_tokens
+            .unwrap_or(self.ctx.input_tokens);
+        vec![
+            SseEvent::new(
+                "message_delta",
+                json!({
+                    "type": "message_delta",
+                    "delta": {"stop_reason": "end_turn", "stop_sequence": null},
+                    "usage": {
+                        "input_tokens": input_tokens,
+                        "output_tokens": self.ctx.output_tokens.max(1)
+                    }
+                }),
+            ),
+            SseEvent::new("message_stop", json!({"type": "message_stop"})),
+        ]
+    }
+}
+
+impl<F> SseWriter<F>
+where
+    F: FnOnce(&StreamContext) + Send + 'static,
+{
+    /// 将上游事件流写为 SSE 事件流
+    ///
+    /// 上游结束或 panic 时收尾，收尾后不再拉取上游事件，以便取消上游调用。
+    /// `keepalive` 为 None 时不发送 ping；有事件可读时优先输出事件
+    pub fn into_stream<S>(
+        mut self,
+        events: S,
+        keepalive: Option<Duration>,
+    ) -> impl Stream<Item = SseEvent> + Send + 'static
+    where
+        S: Stream<Item = Event> + Send + 'static,
+    {
+        let enabled = keepalive.is_some();
+        let period = keepalive.unwrap_or(Duration::from_secs(3600));
+        let ticker = interval_at(Instant::now() + period, period);
+        let initial_events = self.start();
+
+        let body = stream::unfold(
+            (Box::pin(events), self, ticker),
+            move |(mut events, mut writer, mut ticker)| async move {
+                if writer.is_finished() {
+                    return None;
+                }
+                let output = tokio::select! {
+                    biased;
+                    event = AssertUnwindSafe(events.next()).catch_unwind() => match event {
+                        Ok(Some(event)) => writer.write(&event),
+                        Ok(None) => writer.finish(),
+                        Err(_) => {
+                            tracing::error!("上游处理阶段 panic，结束流");
+                            writer.finish()
+                        }
+                    },
+                    _ = ticker.tick(), if enabled => {
+                        tracing::trace!("发送 ping 保活事件");
+                        vec![SseEvent::new("ping", json!({"type": "ping"}))]
+                    }
+                };
+                Some((output, (events, writer, ticker)))
+            },
+        )
+        .flat_map(stream::iter);
+
+        stream::iter(initial_events).chain(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::kiro::model::events::{AssistantResponseEvent, ToolUseEvent};
+
+    fn text(content: &str) -> Event {
+        let mut event = AssistantResponseEvent::default();
+        event.content = content.to_string();
+        Event::AssistantResponse(event)
+    }
+
+    fn partial_tool_use() -> Event {
+        Event::ToolUse(ToolUseEvent {
+            name: "read".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: r#"{"pa"#.to_string(),
+            stop: false,
+        })
+    }
+
+    /// 校验事件序列的生命周期不变量，返回 message_delta 中的 stop_reason
+    fn assert_lifecycle(events: &[SseEvent]) -> String {
+        let names: Vec<&str> = events.iter().map(|e| e.event.as_str()).collect();
+        assert_eq!(names.first(), Some(&"message_start"), "{:?}", names);
+        assert_eq!(names.last(), Some(&"message_stop"), "{:?}", names);
+        let count = |name| names.iter().filter(|n| **n == name).count();
+        assert_eq!(count("message_start"), 1, "{:?}", names);
+        assert_eq!(count("message_delta"), 1, "{:?}", names);
+        assert_eq!(count("message_stop"), 1, "{:?}", names);
+        assert_eq!(names[names.len() - 2], "message_delta", "{:?}", names);
+
+        // 每个内容块都在 message_delta 之前关闭
+        let mut open = HashSet::new();
+        for event in &events[..events.len() - 2] {
+            let index = event.data["index"].as_i64();
+            match event.event.as_str() {
+                "content_block_start" => assert!(open.insert(index.unwrap())),
+                "content_block_delta" => assert!(open.contains(&index.unwrap())),
+                "content_block_stop" => assert!(open.remove(&index.unwrap())),
+                _ => {}
+            }
+        }
+        assert!(open.is_empty(), "未关闭的内容块: {:?}", open);
+
+        let delta = &events[events.len() - 2].data;
+        assert!(delta["usage"]["input_tokens"].is_i64());
+        assert!(delta["usage"]["output_tokens"].as_i64().unwrap() >= 1);
+        delta["delta"]["stop_reason"].as_str().unwrap().to_string()
+    }
+
+    /// 运行写入器，返回输出事件和 on_finish 调用次数
+    async fn run<S>(events: S, ctx: StreamContext) -> (Vec<SseEvent>, usize)
+    where
+        S: Stream<Item = Event> + Send + 'static,
+    {
+        let finished = Arc::new(AtomicUsize::new(0));
+        let counter = finished.clone();
+        let writer = SseWriter::new(ctx, move |_: &StreamContext| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let output = writer.into_stream(events, None).collect().await;
+        (output, finished.load(Ordering::SeqCst))
+    }
+
+    fn ctx() -> StreamContext {
+        StreamContext::new_with_thinking("test-model", 10, false)
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_on_success_and_empty_upstream() {
+        let (events, finished) = run(stream::iter([text("hello"), text(" world")]), ctx()).await;
+        assert_eq!(assert_lifecycle(&events), "end_turn");
+        assert_eq!(finished, 1);
+
+        let (events, finished) = run(stream::empty(), ctx()).await;
+        assert_lifecycle(&events);
+        assert_eq!(finished, 1);
+
+        let thinking = StreamContext::new_with_thinking("test-model", 10, true);
+        let (events, _) = run(stream::iter([text("<thinking>hmm")]), thinking).await;
+        assert_lifecycle(&events);
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_when_upstream_stage_panics() {
+        // 第一个事件之前、文本块中间、工具块中间分别注入 panic
+        let cases: Vec<Vec<Event>> = vec![
+            vec![],
+            vec![text("partial")],
+            vec![text("calling"), partial_tool_use()],
+        ];
+        for before_panic in cases {
+            let events = stream::iter(before_panic).chain(stream::once(async {
+                panic!("injected failure");
+            }));
+            let (events, finished) = run(events, ctx()).await;
+            assert_lifecycle(&events);
+            assert_eq!(finished, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_when_upstream_ends_mid_tool_use() {
+        let events = stream::iter([text("calling"), partial_tool_use()]);
+        let (events, _) = run(events, ctx()).await;
+        assert_eq!(assert_lifecycle(&events), "tool_use");
+    }
+
+    #[tokio::test]
+    async fn test_max_tokens_finishes_without_polling_upstream() {
+        let polled = Arc::new(AtomicUsize::new(0));
+        let counter = polled.clone();
+        let events = stream::repeat_with(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            text("a long stretch of generated text ")
+        });
+        let (events, finished) = run(events, ctx().with_max_tokens(5)).await;
+
+        assert_eq!(assert_lifecycle(&events), "max_tokens");
+        assert_eq!(finished, 1);
+        assert!(polled.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn test_finish_is_idempotent() {
+        let mut writer = SseWriter::new(ctx(), |_: &StreamContext| {});
+        let events = writer.finish();
+        assert_lifecycle(&events);
+        assert!(writer.is_finished());
+        assert!(writer.finish().is_empty());
+        assert!(writer.write(&text("late")).is_empty());
+        assert!(writer.start().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_only_inside_message() {
+        let events = stream::iter([text("a")]).chain(stream::once(async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            text("b")
+        }));
+        let writer = SseWriter::new(ctx(), |_: &StreamContext| {});
+        let events: Vec<SseEvent> = writer
+            .into_stream(events, Some(Duration::from_millis(10)))
+            .collect()
+            .await;
+
+        assert!(events.iter().any(|e| e.event == "ping"));
+        let without_pings: Vec<SseEvent> =
+            events.into_iter().filter(|e| e.event != "ping").collect();
+        assert_lifecycle(&without_pings);
+    }
+}
@@ -7,7 +7,9 @@ use std::collections::HashMap;
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::kiro::model::events::Event;
+use crate::kiro::model::events::{CompletionStatus, Event};
+
+use super::version::AnthropicVersion;
 
 /// 找到小于等于目标位置的最近有效UTF-8字符边界
 ///
@@ -34,6 +36,7 @@ fn find_char_boundary(s: &str, target: usize) -> usize {
 /// - 反引号 (`)：行内代码
 /// - 双引号 (")：字符串
 /// - 单引号 (')：字符串
+#[allow(clippy::byte_char_slices)]
 const QUOTE_CHARS: &[u8] = &[
     b'`', b'"', b'\'', b'\\', b'#', b'!', b'@', b'$', b'%', b'^', b'&', b'*', b'(', b')', b'-',
     b'_', b'=', b'+', b'[', b']', b'{', b'}', b';', b':', b'<', b'>', b',', b'.', b'?', b'/',
@@ -134,6 +137,22 @@ fn find_real_thinking_start_tag(buffer: &str) -> Option<usize> {
     None
 }
 
+/// 去除文本中的 thinking 块（非流式响应屏蔽 thinking 时使用）
+///
+/// 与流式处理一致，只识别第一个真正的 thinking 块，结束标签后的双换行一并去除；
+/// 没有结束标签时其后的内容都属于 thinking
+pub fn strip_thinking_block(text: &str) -> String {
+    let Some(start) = find_real_thinking_start_tag(text) else {
+        return text.to_string();
+    };
+    let rest = &text[start + "<thinking>".len()..];
+    let after = match find_real_thinking_end_tag(rest) {
+        Some(end) => &rest[end + "</thinking>\n\n".len()..],
+        None => "",
+    };
+    format!("{}{}", &text[..start], after)
+}
+
 /// SSE 事件
 #[derive(Debug, Clone)]
 pub struct SseEvent {
@@ -157,6 +176,159 @@ impl SseEvent {
             serde_json::to_string(&self.data).unwrap_or_default()
         )
     }
+
+    /// 格式化为 NDJSON 行（事件类型已包含在 data 的 `type` 字段中）
+    pub fn to_ndjson_string(&self) -> String {
+        let mut line = serde_json::to_string(&self.data).unwrap_or_default();
+        line.push('\n');
+        line
+    }
+}
+
+/// 流式响应格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamFormat {
+    /// Server-Sent Events（默认）
+    #[default]
+    Sse,
+    /// 换行分隔的 JSON（`Accept: application/x-ndjson`）
+    Ndjson,
+    /// 不带 `event:` 行的 SSE，以 `data: [DONE]` 结束（`anthropic-version: 2023-01-01`）
+    LegacySse,
+}
+
+impl StreamFormat {
+    /// 根据 Accept 请求头协商格式，未明确要求 NDJSON 时使用 SSE
+    pub fn from_accept(accept: Option<&str>) -> Self {
+        let wants_ndjson = accept.is_some_and(|accept| {
+            accept.split(',').any(|item| {
+                let media_type = item.split(';').next().unwrap_or("").trim();
+                media_type.eq_ignore_ascii_case("application/x-ndjson")
+                    || media_type.eq_ignore_ascii_case("application/ndjson")
+            })
+        });
+        if wants_ndjson {
+            StreamFormat::Ndjson
+        } else {
+            StreamFormat::Sse
+        }
+    }
+
+    /// 按协商的 API 版本调整 SSE 格式
+    pub fn for_version(self, version: AnthropicVersion) -> Self {
+        match self {
+            StreamFormat::Sse if !version.named_sse_events() => StreamFormat::LegacySse,
+            format => format,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StreamFormat::Sse | StreamFormat::LegacySse => "text/event-stream",
+            StreamFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// 按格式序列化单个事件
+    pub fn encode(&self, event: &SseEvent) -> String {
+        match self {
+            StreamFormat::Sse => event.to_sse_string(),
+            StreamFormat::Ndjson => event.to_ndjson_string(),
+            StreamFormat::LegacySse => {
+                let mut data = format!(
+                    "data: {}\n\n",
+                    serde_json::to_string(&event.data).unwrap_or_default()
+                );
+                if event.event == "message_stop" {
+                    data.push_str("data: [DONE]\n\n");
+                }
+                data
+            }
+        }
+    }
+}
+
+/// 事件过滤配置
+///
+/// 按配置屏蔽部分转换后的 SSE 事件，用于兼容无法处理特定块类型的客户端
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    /// 屏蔽 thinking 内容块（thinking 内容直接丢弃）
+    pub suppress_thinking: bool,
+    /// 屏蔽 ping 保活事件
+    pub suppress_ping: bool,
+    /// 屏蔽上下文使用率扩展事件和响应头（优先于 `emitContextUsage`）
+    pub suppress_context_usage: bool,
+    /// 去除正文末尾回显的追问提示
+    pub suppress_followup: bool,
+    /// 输出 `kiro_context_usage` 扩展事件
+    pub emit_context_usage: bool,
+}
+
+impl EventFilter {
+    /// 从事件名称列表构建过滤配置
+    ///
+    /// 支持的名称：`thinking`、`ping`、`context_usage`、`followup`，未知名称会被忽略并记录警告
+    pub fn from_names(names: &[String]) -> Self {
+        let mut filter = Self::default();
+        for name in names {
+            match name.trim().to_lowercase().as_str() {
+                "thinking" => filter.suppress_thinking = true,
+                "ping" => filter.suppress_ping = true,
+                "context_usage" => filter.suppress_context_usage = true,
+                "followup" => filter.suppress_followup = true,
+                "" => {}
+                other => tracing::warn!("未知的禁用事件类型: {}", other),
+            }
+        }
+        filter
+    }
+
+    /// 设置是否输出上下文使用率扩展事件（已屏蔽 `context_usage` 时始终不输出）
+    pub fn with_context_usage(mut self, enabled: bool) -> Self {
+        self.emit_context_usage = enabled && !self.suppress_context_usage;
+        self
+    }
+}
+
+/// 上下文使用率扩展事件名称
+pub const CONTEXT_USAGE_EVENT: &str = "kiro_context_usage";
+
+/// 上下文使用率响应头（非流式响应）
+pub const CONTEXT_USAGE_HEADER: &str = "x-kiro-context-usage";
+
+/// 构建上下文使用率扩展事件
+fn context_usage_event(percentage: f64, input_tokens: i32) -> SseEvent {
+    SseEvent::new(
+        CONTEXT_USAGE_EVENT,
+        json!({
+            "type": CONTEXT_USAGE_EVENT,
+            "context_usage_percentage": percentage,
+            "input_tokens": input_tokens,
+            "context_window": CONTEXT_WINDOW_SIZE
+        }),
+    )
+}
+
+/// 确定最终的 stop_reason（流式与非流式响应共用）
+///
+/// 本地判定的原因（输出上限截断、上下文超长异常）优先，其次是上游报告的截断；
+/// 有工具调用时为 `tool_use`，否则为 `end_turn`。上游报告 `TOOL_USE` 但没有收到
+/// 工具调用时不返回 `tool_use`，避免客户端等待不存在的工具调用
+pub fn resolve_stop_reason(
+    explicit: Option<&str>,
+    completion_status: Option<CompletionStatus>,
+    has_tool_use: bool,
+) -> &str {
+    if let Some(reason) = explicit {
+        reason
+    } else if completion_status == Some(CompletionStatus::MaxTokens) {
+        "max_tokens"
+    } else if has_tool_use {
+        "tool_use"
+    } else {
+        "end_turn"
+    }
 }
 
 /// 内容块状态
@@ -200,6 +372,8 @@ pub struct SseStateManager {
     stop_reason: Option<String>,
     /// 是否有工具调用
     has_tool_use: bool,
+    /// 上游报告的完成状态
+    completion_status: Option<CompletionStatus>,
 }
 
 impl Default for SseStateManager {
@@ -218,6 +392,7 @@ impl SseStateManager {
             next_block_index: 0,
             stop_reason: None,
             has_tool_use: false,
+            completion_status: None,
         }
     }
 
@@ -245,15 +420,19 @@ impl SseStateManager {
         self.stop_reason = Some(reason.into());
     }
 
+    /// 记录上游报告的完成状态
+    pub fn set_completion_status(&mut self, status: CompletionStatus) {
+        self.completion_status = Some(status);
+    }
+
     /// 获取最终的 stop_reason
     pub fn get_stop_reason(&self) -> String {
-        if let Some(ref reason) = self.stop_reason {
-            reason.clone()
-        } else if self.has_tool_use {
-            "tool_use".to_string()
-        } else {
-            "end_turn".to_string()
-        }
+        resolve_stop_reason(
+            self.stop_reason.as_deref(),
+            self.completion_status,
+            self.has_tool_use,
+        )
+        .to_string()
     }
 
     /// 处理 message_start 事件
@@ -426,6 +605,10 @@ pub struct StreamContext {
     pub context_input_tokens: Option<i32>,
     /// 输出 tokens 累计
     pub output_tokens: i32,
+    /// meteringEvent 报告的额度消耗累计
+    pub metering_usage: f64,
+    /// meteringEvent 的计量单位
+    pub metering_unit: Option<String>,
     /// 工具块索引映射 (tool_id -> block_index)
     pub tool_block_indices: HashMap<String, i32>,
     /// thinking 是否启用
@@ -440,6 +623,12 @@ pub struct StreamContext {
     pub thinking_block_index: Option<i32>,
     /// 文本块索引（thinking 启用时动态分配）
     pub text_block_index: Option<i32>,
+    /// 事件过滤配置
+    pub event_filter: EventFilter,
+    /// 输出 tokens 上限（来自请求的 max_tokens）
+    pub max_tokens: Option<i32>,
+    /// 是否已达到输出 tokens 上限
+    pub max_tokens_reached: bool,
 }
 
 impl StreamContext {
@@ -456,6 +645,8 @@ impl StreamContext {
             input_tokens,
             context_input_tokens: None,
             output_tokens: 0,
+            metering_usage: 0.0,
+            metering_unit: None,
             tool_block_indices: HashMap::new(),
             thinking_enabled,
             thinking_buffer: String::new(),
@@ -463,6 +654,30 @@ impl StreamContext {
             thinking_extracted: false,
             thinking_block_index: None,
             text_block_index: None,
+            event_filter: EventFilter::default(),
+            max_tokens: None,
+            max_tokens_reached: false,
+        }
+    }
+
+    /// 设置事件过滤配置
+    pub fn with_event_filter(mut self, filter: EventFilter) -> Self {
+        self.event_filter = filter;
+        self
+    }
+
+    /// 设置输出 tokens 上限（非正数表示不限制）
+    pub fn with_max_tokens(mut self, max_tokens: i32) -> Self {
+        self.max_tokens = (max_tokens > 0).then_some(max_tokens);
+        self
+    }
+
+    /// 标记已达到输出 tokens 上限
+    fn mark_max_tokens_reached(&mut self) {
+        if !self.max_tokens_reached {
+            tracing::info!("输出 tokens 已达到上限 {:?}，终止流", self.max_tokens);
+            self.max_tokens_reached = true;
+            self.state_manager.set_stop_reason("max_tokens");
         }
     }
 
@@ -527,8 +742,28 @@ impl StreamContext {
 
     /// 处理 Kiro 事件并转换为 Anthropic SSE 事件
     pub fn process_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
+        // 计费事件不产生输出，达到输出上限后也要累计
+        if let Event::Metering(metering) = event {
+            tracing::debug!("收到 meteringEvent: {}", metering);
+            self.metering_usage += metering.usage;
+            if metering.unit.is_some() {
+                self.metering_unit = metering.unit.clone();
+            }
+            return Vec::new();
+        }
+
+        // 达到输出上限后丢弃后续内容
+        if self.max_tokens_reached {
+            return Vec::new();
+        }
+
         match event {
-            Event::AssistantResponse(resp) => self.process_assistant_response(&resp.content),
+            Event::AssistantResponse(resp) => {
+                if let Some(status) = resp.completion_status() {
+                    self.state_manager.set_completion_status(status);
+                }
+                self.process_assistant_response(&resp.content)
+            }
             Event::ToolUse(tool_use) => self.process_tool_use(tool_use),
             Event::ContextUsage(context_usage) => {
                 // 从上下文使用百分比计算实际的 input_tokens
@@ -542,7 +777,14 @@ impl StreamContext {
                     context_usage.context_usage_percentage,
                     actual_input_tokens
                 );
-                Vec::new()
+                if self.event_filter.emit_context_usage {
+                    vec![context_usage_event(
+                        context_usage.context_usage_percentage,
+                        actual_input_tokens,
+                    )]
+                } else {
+                    Vec::new()
+                }
             }
             Event::Error {
                 error_code,
@@ -572,8 +814,26 @@ impl StreamContext {
             return Vec::new();
         }
 
+        // 按剩余输出预算截断内容
+        let content = match self.max_tokens {
+            Some(max_tokens) => {
+                let truncated = truncate_to_tokens(content, max_tokens - self.output_tokens);
+                if truncated.len() < content.len() {
+                    self.mark_max_tokens_reached();
+                }
+                if truncated.is_empty() {
+                    return Vec::new();
+                }
+                truncated
+            }
+            None => content,
+        };
+
         // 估算 tokens
         self.output_tokens += estimate_tokens(content);
+        if self.max_tokens.is_some_and(|max| self.output_tokens >= max) {
+            self.mark_max_tokens_reached();
+        }
 
         // 如果启用了thinking，需要处理thinking块
         if self.thinking_enabled {
@@ -608,21 +868,24 @@ impl StreamContext {
                         self.thinking_buffer[start_pos + "<thinking>".len()..].to_string();
 
                     // 创建 thinking 块的 content_block_start 事件
-                    let thinking_index = self.state_manager.next_block_index();
-                    self.thinking_block_index = Some(thinking_index);
-                    let start_events = self.state_manager.handle_content_block_start(
-                        thinking_index,
-                        "thinking",
-                        json!({
-                            "type": "content_block_start",
-                            "index": thinking_index,
-                            "content_block": {
-                                "type": "thinking",
-                                "thinking": ""
-                            }
-                        }),
-                    );
-                    events.extend(start_events);
+                    // 屏蔽 thinking 时不分配块索引，后续 thinking 内容均被丢弃
+                    if !self.event_filter.suppress_thinking {
+                        let thinking_index = self.state_manager.next_block_index();
+                        self.thinking_block_index = Some(thinking_index);
+                        let start_events = self.state_manager.handle_content_block_start(
+                            thinking_index,
+                            "thinking",
+                            json!({
+                                "type": "content_block_start",
+                                "index": thinking_index,
+                                "content_block": {
+                                    "type": "thinking",
+                                    "thinking": ""
+                                }
+                            }),
+                        );
+                        events.extend(start_events);
+                    }
                 } else {
                     // 没有找到 <thinking>，检查是否可能是部分标签
                     // 保留可能是部分标签的内容
@@ -916,11 +1179,37 @@ fn estimate_tokens(text: &str) -> i32 {
         }
     }
 
+    estimate_tokens_from_counts(chinese_count, other_count).max(1)
+}
+
+/// 根据中文字符数和其他字符数估算 tokens
+fn estimate_tokens_from_counts(chinese_count: i32, other_count: i32) -> i32 {
     // 中文约 1.5 字符/token，英文约 4 字符/token
     let chinese_tokens = (chinese_count * 2 + 2) / 3;
     let other_tokens = (other_count + 3) / 4;
 
-    (chinese_tokens + other_tokens).max(1)
+    chinese_tokens + other_tokens
+}
+
+/// 截断文本，使其估算 tokens 不超过 max_tokens
+fn truncate_to_tokens(text: &str, max_tokens: i32) -> &str {
+    if max_tokens <= 0 {
+        return "";
+    }
+
+    let mut chinese_count = 0;
+    let mut other_count = 0;
+    for (idx, c) in text.char_indices() {
+        if ('\u{4E00}'..='\u{9FFF}').contains(&c) {
+            chinese_count += 1;
+        } else {
+            other_count += 1;
+        }
+        if estimate_tokens_from_counts(chinese_count, other_count) > max_tokens {
+            return &text[..idx];
+        }
+    }
+    text
 }
 
 #[cfg(test)]
@@ -937,6 +1226,34 @@ mod tests {
         assert!(sse_str.ends_with("\n\n"));
     }
 
+    #[test]
+    fn test_stream_format_from_accept() {
+        assert_eq!(StreamFormat::from_accept(None), StreamFormat::Sse);
+        assert_eq!(
+            StreamFormat::from_accept(Some("text/event-stream")),
+            StreamFormat::Sse
+        );
+        assert_eq!(
+            StreamFormat::from_accept(Some("application/json, application/x-ndjson;q=0.9")),
+            StreamFormat::Ndjson
+        );
+    }
+
+    #[test]
+    fn test_legacy_sse_format() {
+        let format = StreamFormat::Sse.for_version(AnthropicVersion::V2023_01_01);
+        assert_eq!(format, StreamFormat::LegacySse);
+        assert_eq!(
+            StreamFormat::Ndjson.for_version(AnthropicVersion::V2023_01_01),
+            StreamFormat::Ndjson
+        );
+
+        let ping = SseEvent::new("ping", json!({"type": "ping"}));
+        assert_eq!(format.encode(&ping), "data: {\"type\":\"ping\"}\n\n");
+        let stop = SseEvent::new("message_stop", json!({"type": "message_stop"}));
+        assert!(format.encode(&stop).ends_with("data: [DONE]\n\n"));
+    }
+
     #[test]
     fn test_sse_state_manager_message_start() {
         let mut manager = SseStateManager::new();
@@ -1109,6 +1426,169 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_event_filter_from_names() {
+        let filter = EventFilter::from_names(&["Thinking".to_string(), "unknown".to_string()]);
+        assert!(filter.suppress_thinking);
+        assert!(!filter.suppress_ping);
+
+        let filter = EventFilter::from_names(&["ping".to_string()]);
+        assert!(filter.suppress_ping);
+        assert!(!filter.suppress_thinking);
+
+        let filter =
+            EventFilter::from_names(&["context_usage".to_string(), "followup".to_string()])
+                .with_context_usage(true);
+        assert!(filter.suppress_followup);
+        assert!(!filter.emit_context_usage);
+
+        assert_eq!(
+            strip_thinking_block("<thinking>secret</thinking>\n\nanswer"),
+            "answer"
+        );
+        assert_eq!(
+            strip_thinking_block("no `<thinking>` here"),
+            "no `<thinking>` here"
+        );
+    }
+
+    #[test]
+    fn test_suppress_thinking_drops_thinking_block() {
+        let filter = EventFilter {
+            suppress_thinking: true,
+            ..Default::default()
+        };
+        let mut ctx =
+            StreamContext::new_with_thinking("test-model", 1, true).with_event_filter(filter);
+        let mut events = ctx.generate_initial_events();
+        events.extend(ctx.process_assistant_response("<thinking>secret</thinking>\n\nanswer"));
+        events.extend(ctx.generate_final_events());
+
+        assert!(events
+            .iter()
+            .all(|e| e.data["content_block"]["type"] != "thinking"));
+        assert!(events
+            .iter()
+            .all(|e| e.data["delta"]["type"] != "thinking_delta"));
+        assert!(events.iter().any(|e| {
+            e.data["delta"]["type"] == "text_delta"
+                && e.data["delta"]["text"]
+                    .as_str()
+                    .unwrap_or("")
+                    .contains("answer")
+        }));
+        // 文本块应占用索引 0
+        assert!(events.iter().any(|e| {
+            e.event == "content_block_start"
+                && e.data["content_block"]["type"] == "text"
+                && e.data["index"] == 0
+        }));
+    }
+
+    #[test]
+    fn test_context_usage_event_gated_by_filter() {
+        let event = Event::ContextUsage(crate::kiro::model::events::ContextUsageEvent {
+            context_usage_percentage: 42.5,
+        });
+
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        assert!(ctx.process_kiro_event(&event).is_empty());
+
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_event_filter(EventFilter::default().with_context_usage(true));
+        let events = ctx.process_kiro_event(&event);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, CONTEXT_USAGE_EVENT);
+        assert_eq!(events[0].data["context_usage_percentage"], 42.5);
+        assert_eq!(events[0].data["input_tokens"], 85000);
+        assert_eq!(ctx.context_input_tokens, Some(85000));
+    }
+
+    #[test]
+    fn test_metering_accumulated_after_max_tokens() {
+        let metering = |usage| {
+            Event::Metering(crate::kiro::model::events::MeteringEvent {
+                unit: Some("credit".to_string()),
+                unit_plural: Some("credits".to_string()),
+                usage,
+            })
+        };
+
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        assert!(ctx.process_kiro_event(&metering(0.25)).is_empty());
+        ctx.max_tokens_reached = true;
+        assert!(ctx.process_kiro_event(&metering(0.5)).is_empty());
+        assert_eq!(ctx.metering_usage, 0.75);
+        assert_eq!(ctx.metering_unit.as_deref(), Some("credit"));
+    }
+
+    #[test]
+    fn test_truncate_to_tokens() {
+        assert_eq!(truncate_to_tokens("abcdefgh", 1), "abcd");
+        assert_eq!(truncate_to_tokens("abcdefgh", 2), "abcdefgh");
+        assert_eq!(truncate_to_tokens("你好世界", 2), "你好世");
+        assert_eq!(truncate_to_tokens("abc", 0), "");
+    }
+
+    #[test]
+    fn test_max_tokens_truncates_and_sets_stop_reason() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_max_tokens(2);
+        let mut events = ctx.generate_initial_events();
+        events.extend(ctx.process_assistant_response("abcdefghijkl"));
+        assert!(ctx.max_tokens_reached);
+
+        // 达到上限后的内容应被丢弃
+        let more = serde_json::from_value(json!({"content": "more"})).unwrap();
+        let dropped = ctx.process_kiro_event(&Event::AssistantResponse(more));
+        assert!(dropped.is_empty());
+        events.extend(ctx.generate_final_events());
+
+        let text: String = events
+            .iter()
+            .filter_map(|e| e.data["delta"]["text"].as_str())
+            .collect();
+        assert_eq!(text, "abcdefgh");
+        assert!(events
+            .iter()
+            .any(|e| e.event == "message_delta" && e.data["delta"]["stop_reason"] == "max_tokens"));
+    }
+
+    #[test]
+    fn test_upstream_completion_status_sets_stop_reason() {
+        let stop_reason = |status: &str| {
+            let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+            let mut events = ctx.generate_initial_events();
+            let text = serde_json::from_value(json!({"content": "partial"})).unwrap();
+            events.extend(ctx.process_kiro_event(&Event::AssistantResponse(text)));
+            // 最后的事件只携带完成状态，没有内容
+            let last = serde_json::from_value(json!({"messageStatus": status})).unwrap();
+            events.extend(ctx.process_kiro_event(&Event::AssistantResponse(last)));
+            events.extend(ctx.generate_final_events());
+            events
+                .iter()
+                .find(|e| e.event == "message_delta")
+                .map(|e| e.data["delta"]["stop_reason"].clone())
+                .unwrap()
+        };
+        assert_eq!(stop_reason("MAX_TOKENS"), "max_tokens");
+        assert_eq!(stop_reason("COMPLETED"), "end_turn");
+        // 没有实际的工具调用时不返回 tool_use
+        assert_eq!(stop_reason("TOOL_USE"), "end_turn");
+    }
+
+    #[test]
+    fn test_resolve_stop_reason() {
+        let max = Some(CompletionStatus::MaxTokens);
+        let done = Some(CompletionStatus::Completed);
+        assert_eq!(resolve_stop_reason(None, None, false), "end_turn");
+        assert_eq!(resolve_stop_reason(None, done, true), "tool_use");
+        assert_eq!(resolve_stop_reason(None, max, true), "max_tokens");
+        assert_eq!(
+            resolve_stop_reason(Some("max_tokens"), done, true),
+            "max_tokens"
+        );
+    }
+
     #[test]
     fn test_estimate_tokens() {
         assert!(estimate_tokens("Hello") > 0);
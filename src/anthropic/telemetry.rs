@@ -0,0 +1,152 @@
+//! 转换失败匿名遥测
+//!
+//! 配置 `telemetryUrl` 后，请求转换失败、转换器 panic 和 Kiro 请求序列化失败时
+//! 向该地址 POST 一条记录，帮助维护者了解哪些客户端格式尚未兼容。
+//! 记录只包含错误类型、字段路径、模型名和版本号，不包含消息内容、API Key 或账号信息。
+
+use reqwest::Client;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::http_client::{build_client, ProxyConfig};
+use crate::model::config::Config;
+
+/// 上报请求超时（秒）
+const TELEMETRY_TIMEOUT_SECS: u64 = 10;
+/// 待发送记录队列长度，队列满时直接丢弃，不影响请求处理
+const QUEUE_CAPACITY: usize = 100;
+/// 模型名最大长度，超出部分截断（模型名由客户端提供）
+const MAX_MODEL_LEN: usize = 64;
+
+/// 失败类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// 请求转换返回错误
+    Conversion,
+    /// 转换器 panic
+    Panic,
+    /// Kiro 请求序列化失败
+    Serialization,
+}
+
+/// 转换失败记录
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConverterFailure {
+    pub kind: FailureKind,
+    /// 具体错误（如 `unsupported_model`）
+    pub error: String,
+    /// 出错的请求字段路径（如 `messages[].content[].source`）
+    pub field_path: String,
+    pub model: String,
+    pub version: &'static str,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+impl ConverterFailure {
+    pub fn new(
+        kind: FailureKind,
+        error: impl Into<String>,
+        field_path: impl Into<String>,
+        model: &str,
+    ) -> Self {
+        Self {
+            kind,
+            error: error.into(),
+            field_path: field_path.into(),
+            model: model.chars().take(MAX_MODEL_LEN).collect(),
+            version: env!("CARGO_PKG_VERSION"),
+            timestamp: chrono::Utc::now(),
+        }
+    }
+}
+
+/// 转换失败遥测上报器
+///
+/// 记录通过 channel 交给后台任务逐条发送，发送失败只记录日志，不重试
+pub struct Telemetry {
+    tx: mpsc::Sender<ConverterFailure>,
+}
+
+impl Telemetry {
+    /// 启动后台发送任务，未配置上报地址时返回 None
+    pub fn spawn(config: &Config) -> Option<Self> {
+        let url = config.telemetry_url.clone()?;
+        let proxy = ProxyConfig::from_config(config);
+        let client = match build_client(proxy.as_ref(), TELEMETRY_TIMEOUT_SECS) {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("创建遥测 HTTP 客户端失败: {}", e);
+                return None;
+            }
+        };
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(run(client, url, rx));
+        tracing::info!("已启用转换失败匿名遥测");
+        Some(Self { tx })
+    }
+
+    /// 提交一条失败记录
+    pub fn report(&self, failure: ConverterFailure) {
+        if self.tx.try_send(failure).is_err() {
+            tracing::debug!("遥测队列已满或发送任务已退出，丢弃记录");
+        }
+    }
+}
+
+async fn run(client: Client, url: String, mut rx: mpsc::Receiver<ConverterFailure>) {
+    while let Some(failure) = rx.recv().await {
+        let result = client.post(&url).json(&failure).send().await;
+        match result {
+            Ok(response) if !response.status().is_success() => {
+                tracing::debug!("遥测端点返回错误状态: {}", response.status());
+            }
+            Ok(_) => {}
+            Err(e) => tracing::debug!("发送遥测记录失败: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_telemetry_is_opt_in() {
+        assert!(Config::default().telemetry_url.is_none());
+    }
+
+    #[test]
+    fn test_failure_contains_no_content() {
+        let model = "m".repeat(200);
+        let failure = ConverterFailure::new(
+            FailureKind::Conversion,
+            "unsupported_model",
+            "model",
+            &model,
+        );
+        assert_eq!(failure.model.len(), MAX_MODEL_LEN);
+
+        let value = serde_json::to_value(&failure).unwrap();
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "error",
+                "fieldPath",
+                "kind",
+                "model",
+                "timestamp",
+                "version"
+            ]
+        );
+        assert_eq!(value["kind"], "conversion");
+    }
+}
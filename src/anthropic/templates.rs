@@ -0,0 +1,241 @@
+//! 提示词模板
+//!
+//! 运营方在配置文件或管理 API 中定义命名模板（系统提示词 + 默认参数），客户端在
+//! `/v1/messages` 请求体中通过扩展字段 `"template": "<name>"` 引用。模板在请求反序列化前展开：
+//! 系统提示词插入到请求的系统消息之前，默认参数只填充请求中缺失的字段。
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::{json, Value};
+
+use crate::model::config::PromptTemplate;
+use crate::storage::Storage;
+
+use super::body::read_body;
+use super::middleware::AppState;
+use super::types::ErrorResponse;
+
+/// 请求体中引用模板的字段名
+pub const TEMPLATE_FIELD: &str = "template";
+
+/// 管理 API 保存的模板文件名
+const TEMPLATES_FILE: &str = "templates.json";
+
+/// 提示词模板存储
+///
+/// 配置文件中的模板在启动时加载，管理 API 的修改保存到存储后端并在重启后覆盖同名配置
+#[derive(Default)]
+pub struct TemplateStore {
+    templates: RwLock<HashMap<String, PromptTemplate>>,
+    /// 持久化存储（None 时修改只保存在内存中）
+    storage: Option<Arc<dyn Storage>>,
+}
+
+impl TemplateStore {
+    /// 创建只保存在内存中的模板存储
+    pub fn new(templates: HashMap<String, PromptTemplate>) -> Self {
+        Self {
+            templates: RwLock::new(templates),
+            storage: None,
+        }
+    }
+
+    /// 加载配置中的模板，并合并存储中通过管理 API 保存的模板
+    pub async fn load(
+        templates: HashMap<String, PromptTemplate>,
+        storage: Arc<dyn Storage>,
+    ) -> Self {
+        let mut templates = templates;
+        match storage
+            .get_json::<HashMap<String, PromptTemplate>>(TEMPLATES_FILE)
+            .await
+        {
+            Ok(Some(saved)) => {
+                tracing::info!("从存储加载了 {} 个提示词模板", saved.len());
+                templates.extend(saved);
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("读取提示词模板失败: {}", e),
+        }
+        Self {
+            templates: RwLock::new(templates),
+            storage: Some(storage),
+        }
+    }
+
+    /// 按名称排序的全部模板
+    pub fn list(&self) -> BTreeMap<String, PromptTemplate> {
+        let templates = self.templates.read().unwrap();
+        templates
+            .iter()
+            .map(|(name, t)| (name.clone(), t.clone()))
+            .collect()
+    }
+
+    /// 获取指定模板
+    pub fn get(&self, name: &str) -> Option<PromptTemplate> {
+        self.templates.read().unwrap().get(name).cloned()
+    }
+
+    /// 新增或替换模板
+    pub async fn upsert(&self, name: String, template: PromptTemplate) -> anyhow::Result<()> {
+        self.templates.write().unwrap().insert(name, template);
+        self.save().await
+    }
+
+    /// 删除模板，不存在时返回 false
+    pub async fn remove(&self, name: &str) -> anyhow::Result<bool> {
+        if self.templates.write().unwrap().remove(name).is_none() {
+            return Ok(false);
+        }
+        self.save().await?;
+        Ok(true)
+    }
+
+    async fn save(&self) -> anyhow::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        storage.put_json(TEMPLATES_FILE, &self.list()).await
+    }
+
+    /// 展开请求体中的模板引用，未引用模板时返回 false
+    pub fn expand(&self, body: &mut Value) -> Result<bool, String> {
+        let Some(request) = body.as_object_mut() else {
+            return Ok(false);
+        };
+        let Some(name) = request.remove(TEMPLATE_FIELD) else {
+            return Ok(false);
+        };
+        let name = name.as_str().ok_or("template 字段必须是字符串")?;
+        let template = self
+            .get(name)
+            .ok_or_else(|| format!("模板不存在: {}", name))?;
+
+        for (key, value) in template.params {
+            request.entry(key).or_insert(value);
+        }
+        if let Some(system) = template.system {
+            let mut blocks = vec![json!({"type": "text", "text": system})];
+            match request.remove("system") {
+                None | Some(Value::Null) => {}
+                Some(Value::String(text)) => blocks.push(json!({"type": "text", "text": text})),
+                Some(Value::Array(existing)) => blocks.extend(existing),
+                Some(other) => blocks.push(other),
+            }
+            request.insert("system".to_string(), Value::Array(blocks));
+        }
+        tracing::debug!("展开提示词模板: {}", name);
+        Ok(true)
+    }
+}
+
+/// 在请求反序列化前展开 `template` 字段
+///
+/// 请求体不是 JSON 或未引用模板时原样转发，由处理器返回常规的解析错误
+pub async fn template_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let bytes = match read_body(&parts.headers, body, state.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(e) => return e.into_response(),
+    };
+
+    let needle = format!("\"{}\"", TEMPLATE_FIELD);
+    let mentions_template = bytes.windows(needle.len()).any(|w| w == needle.as_bytes());
+    let parsed = mentions_template
+        .then(|| serde_json::from_slice::<Value>(&bytes).ok())
+        .flatten();
+
+    let body = match parsed {
+        Some(mut value) => match state.templates.expand(&mut value) {
+            Ok(true) => {
+                parts.headers.remove(header::CONTENT_LENGTH);
+                Body::from(serde_json::to_vec(&value).unwrap_or_default())
+            }
+            Ok(false) => Body::from(bytes),
+            Err(message) => {
+                let error = ErrorResponse::new("invalid_request_error", message);
+                return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+            }
+        },
+        None => Body::from(bytes),
+    };
+    next.run(Request::from_parts(parts, body)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStorage;
+
+    fn store() -> TemplateStore {
+        let template: PromptTemplate = serde_json::from_value(json!({
+            "system": "You are a strict code reviewer.",
+            "params": {"model": "claude-sonnet-4-5", "max_tokens": 4096}
+        }))
+        .unwrap();
+        TemplateStore::new(HashMap::from([("code-review".to_string(), template)]))
+    }
+
+    #[test]
+    fn test_expand() {
+        let mut body = json!({
+            "template": "code-review",
+            "max_tokens": 1024,
+            "system": "Be brief.",
+            "messages": [{"role": "user", "content": "diff"}]
+        });
+        assert_eq!(store().expand(&mut body), Ok(true));
+        assert!(body.get("template").is_none());
+        assert_eq!(body["model"], "claude-sonnet-4-5");
+        // 请求中已有的参数优先
+        assert_eq!(body["max_tokens"], 1024);
+        assert_eq!(body["system"][0]["text"], "You are a strict code reviewer.");
+        assert_eq!(body["system"][1]["text"], "Be brief.");
+
+        let request: super::super::types::MessagesRequest = serde_json::from_value(body).unwrap();
+        assert_eq!(request.system.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        let mut plain = json!({"model": "m", "messages": []});
+        assert_eq!(store().expand(&mut plain), Ok(false));
+
+        let mut unknown = json!({"template": "missing"});
+        assert_eq!(
+            store().expand(&mut unknown),
+            Err("模板不存在: missing".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_persistence() {
+        let dir = std::env::temp_dir().join(format!("kiro-templates-{}", uuid::Uuid::new_v4()));
+        let storage: Arc<dyn Storage> = Arc::new(FileStorage::new(dir.clone()));
+        let store = TemplateStore::load(HashMap::new(), storage.clone()).await;
+        store
+            .upsert("summary".to_string(), PromptTemplate::default())
+            .await
+            .unwrap();
+
+        let reloaded = TemplateStore::load(HashMap::new(), storage).await;
+        assert!(reloaded.get("summary").is_some());
+        assert!(reloaded.remove("summary").await.unwrap());
+        assert!(!reloaded.remove("summary").await.unwrap());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
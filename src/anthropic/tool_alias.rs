@@ -0,0 +1,255 @@
+//! 工具名称清理与别名映射
+//!
+//! Kiro 只接受 `[A-Za-z0-9_-]{1,64}` 形式的工具名称，而 Anthropic 允许点号等字符和更长的名称。
+//! 转换时将不合规的名称替换为安全的别名，响应中的 tool_use 再映射回客户端的原始名称。
+//! 清理后重名或超长的名称追加原始名称的哈希后缀，保证别名唯一。
+
+use std::collections::{HashMap, HashSet};
+
+use sha2::{Digest, Sha256};
+
+use crate::kiro::model::requests::conversation::{ConversationState, Message};
+
+use super::types::MessagesRequest;
+
+/// 上游允许的工具名称最大长度
+pub const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// 哈希后缀的十六进制位数
+const HASH_SUFFIX_LEN: usize = 8;
+
+/// 工具名称是否可以直接发送给上游
+pub fn is_valid_tool_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_TOOL_NAME_LEN
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// 将不允许的字符替换为 `_`
+fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if cleaned.is_empty() {
+        "tool".to_string()
+    } else {
+        cleaned
+    }
+}
+
+/// 截断后追加后缀，总长度不超过上限
+fn with_suffix(base: &str, suffix: &str) -> String {
+    let keep = MAX_TOOL_NAME_LEN - suffix.len() - 1;
+    format!("{}_{}", &base[..base.len().min(keep)], suffix)
+}
+
+/// 请求级别的工具名称映射
+#[derive(Debug, Clone, Default)]
+pub struct ToolAliases {
+    /// 原始名称 → 上游名称
+    forward: HashMap<String, String>,
+    /// 上游名称 → 原始名称
+    reverse: HashMap<String, String>,
+    /// 客户端工具名称的前缀（`clientTools.mode` 为 namespace 时），还原时去掉
+    namespace: String,
+}
+
+impl ToolAliases {
+    /// 为请求中的工具定义和历史 tool_use 生成别名
+    ///
+    /// 合规名称保持不变并优先占用，不合规名称按出现顺序分配别名
+    pub fn from_request(req: &MessagesRequest) -> Self {
+        let mut names: Vec<String> = Vec::new();
+        for tool in req.tools.iter().flatten() {
+            names.push(tool.name.clone());
+        }
+        for msg in req.messages.iter().filter(|m| m.role == "assistant") {
+            for block in msg.content.as_array().into_iter().flatten() {
+                if block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
+                    if let Some(name) = block.get("name").and_then(|v| v.as_str()) {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+        Self::build(names)
+    }
+
+    fn build(names: Vec<String>) -> Self {
+        let mut taken: HashSet<String> = names
+            .iter()
+            .filter(|n| is_valid_tool_name(n))
+            .cloned()
+            .collect();
+        let mut aliases = Self::default();
+        for name in names {
+            if is_valid_tool_name(&name) || aliases.forward.contains_key(&name) {
+                continue;
+            }
+            let alias = Self::allocate(&name, &taken);
+            tracing::debug!("工具名称 {} 映射为 {}", name, alias);
+            taken.insert(alias.clone());
+            aliases.reverse.insert(alias.clone(), name.clone());
+            aliases.forward.insert(name, alias);
+        }
+        aliases
+    }
+
+    /// 分配未被占用的别名
+    fn allocate(name: &str, taken: &HashSet<String>) -> String {
+        let base = sanitize(name);
+        if base.len() <= MAX_TOOL_NAME_LEN && !taken.contains(&base) {
+            return base;
+        }
+        let hash = hex::encode(Sha256::digest(name.as_bytes()));
+        let alias = with_suffix(&base, &hash[..HASH_SUFFIX_LEN]);
+        if !taken.contains(&alias) {
+            return alias;
+        }
+        (2..)
+            .map(|n| with_suffix(&base, &format!("{}_{}", &hash[..HASH_SUFFIX_LEN], n)))
+            .find(|alias| !taken.contains(alias))
+            .expect("别名序号耗尽")
+    }
+
+    /// 设置客户端工具名称的前缀
+    pub fn with_namespace(mut self, prefix: &str) -> Self {
+        self.namespace = prefix.to_string();
+        self
+    }
+
+    /// 是否没有需要映射的名称
+    pub fn is_empty(&self) -> bool {
+        self.forward.is_empty() && self.namespace.is_empty()
+    }
+
+    /// 原始名称对应的上游名称
+    pub fn upstream<'a>(&'a self, name: &'a str) -> &'a str {
+        self.forward.get(name).map(String::as_str).unwrap_or(name)
+    }
+
+    /// 上游名称对应的原始名称
+    pub fn restore<'a>(&'a self, name: &'a str) -> &'a str {
+        let name = self.reverse.get(name).map(String::as_str).unwrap_or(name);
+        name.strip_prefix(self.namespace.as_str()).unwrap_or(name)
+    }
+
+    /// 将转换后的 Kiro 请求中的工具名称替换为上游名称
+    pub fn apply(&self, state: &mut ConversationState) {
+        if self.is_empty() {
+            return;
+        }
+        let rename = |name: &mut String| *name = self.upstream(name).to_string();
+        let context = &mut state
+            .current_message
+            .user_input_message
+            .user_input_message_context;
+        for tool in &mut context.tools {
+            rename(&mut tool.tool_specification.name);
+        }
+        for message in &mut state.history {
+            match message {
+                Message::User(user) => {
+                    let context = &mut user.user_input_message.user_input_message_context;
+                    for tool in &mut context.tools {
+                        rename(&mut tool.tool_specification.name);
+                    }
+                }
+                Message::Assistant(assistant) => {
+                    let tool_uses = assistant.assistant_response_message.tool_uses.iter_mut();
+                    for tool_use in tool_uses.flatten() {
+                        rename(&mut tool_use.name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases(names: &[&str]) -> ToolAliases {
+        ToolAliases::build(names.iter().map(|n| n.to_string()).collect())
+    }
+
+    #[test]
+    fn test_sanitize_and_restore() {
+        let a = aliases(&["Read", "mcp.github.search", "web search"]);
+        assert_eq!(a.upstream("Read"), "Read");
+        assert_eq!(a.upstream("mcp.github.search"), "mcp_github_search");
+        assert_eq!(a.upstream("web search"), "web_search");
+        assert_eq!(a.restore("mcp_github_search"), "mcp.github.search");
+        assert_eq!(a.restore("Read"), "Read");
+        assert_eq!(a.restore("unknown"), "unknown");
+
+        let a = aliases(&["client_mcp.github.search"]).with_namespace("client_");
+        assert_eq!(
+            a.restore(a.upstream("client_mcp.github.search")),
+            "mcp.github.search"
+        );
+        assert_eq!(a.restore("client_Read"), "Read");
+    }
+
+    #[test]
+    fn test_collisions() {
+        // 合规名称优先保留，冲突的别名追加哈希后缀
+        let a = aliases(&["a.b", "a_b", "a-b", "a:b"]);
+        assert_eq!(a.upstream("a_b"), "a_b");
+        let dotted = a.upstream("a.b");
+        let colon = a.upstream("a:b");
+        assert!(dotted.starts_with("a_b_") && colon.starts_with("a_b_"));
+        assert_ne!(dotted, colon);
+        assert!(is_valid_tool_name(dotted) && is_valid_tool_name(colon));
+        assert_eq!(a.restore(dotted), "a.b");
+        assert_eq!(a.restore(colon), "a:b");
+    }
+
+    #[test]
+    fn test_long_names() {
+        let long = format!("server.{}", "x".repeat(80));
+        let a = aliases(&[&long, &long]);
+        let alias = a.upstream(&long);
+        assert_eq!(alias.len(), MAX_TOOL_NAME_LEN);
+        assert!(is_valid_tool_name(alias));
+        assert_eq!(a.restore(alias), long);
+    }
+
+    #[test]
+    fn test_convert_request_uses_aliases() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "tools": [{"name": "fs.read", "description": "read", "input_schema": {"type": "object"}}],
+            "messages": [
+                {"role": "user", "content": "read it"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t1", "name": "fs.read", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "ok"}
+                ]}
+            ]
+        }))
+        .unwrap();
+        let result = super::super::converter::convert_request(&req).unwrap();
+        let state = serde_json::to_value(&result.conversation_state).unwrap();
+        let tools =
+            &state["currentMessage"]["userInputMessage"]["userInputMessageContext"]["tools"];
+        assert_eq!(tools[0]["toolSpecification"]["name"], "fs_read");
+        let history = state["history"].as_array().unwrap();
+        let tool_use = &history[1]["assistantResponseMessage"]["toolUses"][0];
+        assert_eq!(tool_use["name"], "fs_read");
+        assert_eq!(result.tool_aliases.restore("fs_read"), "fs.read");
+    }
+}
@@ -0,0 +1,214 @@
+//! 客户端工具来源策略
+//!
+//! 请求中的工具定义来自客户端，属于不可信来源；代理注入的内置工具（`serverTools`）属于可信来源。
+//! 按 `clientTools` 配置（托管 API Key 可覆盖）处理客户端工具：
+//! - `passthrough`：原样发送给上游
+//! - `filter`：移除 deny 匹配或不在 allow 中的工具，并记录审计日志
+//! - `namespace`：在 filter 的基础上为名称加前缀，响应中的 tool_use 还原为原始名称
+//!
+//! 策略在注入内置工具之前执行，内置工具不受影响。
+
+use serde_json::Value;
+
+use crate::model::config::{ClientToolMode, ClientToolPolicy};
+
+use super::types::MessagesRequest;
+
+/// 工具名称是否匹配模式（不区分大小写，`*` 匹配任意字符）
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if parts.len() == 1 {
+        return name == pattern;
+    }
+    if name.len() < first.len() + last.len() || !name.starts_with(first) || !name.ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// 策略是否允许该工具
+pub fn allows(policy: &ClientToolPolicy, name: &str) -> bool {
+    if policy.deny.iter().any(|p| matches(p, name)) {
+        return false;
+    }
+    policy.allow.is_empty() || policy.allow.iter().any(|p| matches(p, name))
+}
+
+/// 对话历史中调用过的工具名称
+fn history_tool_uses(req: &MessagesRequest) -> impl Iterator<Item = &str> {
+    req.messages
+        .iter()
+        .filter(|m| m.role == "assistant")
+        .filter_map(|m| m.content.as_array())
+        .flatten()
+        .filter(|block| block["type"] == "tool_use")
+        .filter_map(|block| block["name"].as_str())
+}
+
+/// 按策略处理请求中的客户端工具
+///
+/// `key` 为审计日志中记录的调用方（托管 API Key 前缀等）。对话历史或 tool_choice
+/// 引用了被禁止的工具时返回错误信息：移除后历史中的工具调用无法对应工具定义
+pub fn apply_client_tool_policy(
+    req: &mut MessagesRequest,
+    policy: &ClientToolPolicy,
+    key: &str,
+) -> Result<(), String> {
+    if policy.mode == ClientToolMode::Passthrough {
+        return Ok(());
+    }
+
+    if let Some(name) = history_tool_uses(req).find(|name| !allows(policy, name)) {
+        return Err(format!("对话历史中调用了被禁止的工具: {}", name));
+    }
+    if let Some(name) = req
+        .tool_choice
+        .as_ref()
+        .and_then(|choice| choice["name"].as_str())
+        .filter(|name| !allows(policy, name))
+    {
+        return Err(format!("tool_choice 指定了被禁止的工具: {}", name));
+    }
+
+    if let Some(tools) = &mut req.tools {
+        let mut stripped = Vec::new();
+        tools.retain(|tool| {
+            let allowed = allows(policy, &tool.name);
+            if !allowed {
+                stripped.push(tool.name.clone());
+            }
+            allowed
+        });
+        if !stripped.is_empty() {
+            tracing::warn!(
+                target: "audit",
+                key = key,
+                tools = %stripped.join(","),
+                "按客户端工具策略移除工具"
+            );
+        }
+        if tools.is_empty() {
+            req.tools = None;
+        }
+    }
+
+    if policy.mode == ClientToolMode::Namespace {
+        namespace(req, &policy.prefix);
+    }
+    Ok(())
+}
+
+/// 为工具定义、历史中的工具调用和 tool_choice 中的名称加前缀
+fn namespace(req: &mut MessagesRequest, prefix: &str) {
+    let prefixed = |name: &str| format!("{}{}", prefix, name);
+    for tool in req.tools.iter_mut().flatten() {
+        tool.name = prefixed(&tool.name);
+    }
+    for msg in req.messages.iter_mut().filter(|m| m.role == "assistant") {
+        let Value::Array(blocks) = &mut msg.content else {
+            continue;
+        };
+        for block in blocks.iter_mut().filter(|b| b["type"] == "tool_use") {
+            if let Some(name) = block["name"].as_str() {
+                block["name"] = Value::String(prefixed(name));
+            }
+        }
+    }
+    if let Some(choice) = &mut req.tool_choice {
+        if let Some(name) = choice["name"].as_str() {
+            choice["name"] = Value::String(prefixed(name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn request() -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t1", "name": "search", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "ok"}
+                ]}
+            ],
+            "tools": [
+                {"name": "search", "description": "", "input_schema": {}},
+                {"name": "read_file", "description": "", "input_schema": {}},
+                {"name": "fsWrite", "description": "", "input_schema": {}}
+            ],
+            "tool_choice": {"type": "tool", "name": "search"}
+        }))
+        .unwrap()
+    }
+
+    fn names(req: &MessagesRequest) -> Vec<&str> {
+        req.tools
+            .iter()
+            .flatten()
+            .map(|t| t.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_and_namespace() {
+        assert!(matches("*file*", "READ_FILE"));
+        assert!(matches("fs*", "fsWrite"));
+        assert!(matches("a*b*c", "axxbyyc"));
+        assert!(!matches("a*b*c", "acb"));
+        assert!(!matches("read", "read_file"));
+
+        let mut policy = ClientToolPolicy {
+            mode: ClientToolMode::Passthrough,
+            deny: vec!["*file*".to_string(), "fs*".to_string()],
+            ..ClientToolPolicy::default()
+        };
+        let mut req = request();
+        apply_client_tool_policy(&mut req, &policy, "-").unwrap();
+        assert_eq!(names(&req).len(), 3);
+
+        policy.mode = ClientToolMode::Filter;
+        apply_client_tool_policy(&mut req, &policy, "-").unwrap();
+        assert_eq!(names(&req), vec!["search"]);
+
+        policy.mode = ClientToolMode::Namespace;
+        let mut req = request();
+        apply_client_tool_policy(&mut req, &policy, "-").unwrap();
+        assert_eq!(names(&req), vec!["client_search"]);
+        assert_eq!(req.messages[1].content[0]["name"], "client_search");
+        assert_eq!(req.tool_choice.as_ref().unwrap()["name"], "client_search");
+
+        // 历史中调用过的工具被禁止时拒绝请求
+        policy.deny.push("search".to_string());
+        let err = apply_client_tool_policy(&mut request(), &policy, "-").unwrap_err();
+        assert!(err.contains("search"));
+
+        // allow 非空时只保留匹配的工具
+        let policy = ClientToolPolicy {
+            mode: ClientToolMode::Filter,
+            allow: vec!["search".to_string(), "read_*".to_string()],
+            ..ClientToolPolicy::default()
+        };
+        let mut req = request();
+        apply_client_tool_policy(&mut req, &policy, "-").unwrap();
+        assert_eq!(names(&req), vec!["search", "read_file"]);
+    }
+}
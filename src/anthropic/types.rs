@@ -3,6 +3,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::model::config::RequestPriority;
+
 // === 错误响应 ===
 
 /// API 错误响应
@@ -51,6 +53,31 @@ pub struct Model {
     pub max_tokens: i32,
 }
 
+/// 单个模型信息（`GET /v1/models/{model_id}`，与 Anthropic 格式相同）
+#[derive(Debug, Serialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub model_type: &'static str,
+    pub display_name: String,
+    /// 发布时间（RFC 3339）
+    pub created_at: String,
+}
+
+impl From<&Model> for ModelInfo {
+    fn from(model: &Model) -> Self {
+        let created_at = chrono::DateTime::from_timestamp(model.created, 0)
+            .unwrap_or_default()
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        Self {
+            id: model.id.clone(),
+            model_type: "model",
+            display_name: model.display_name.clone(),
+            created_at,
+        }
+    }
+}
+
 /// 模型列表响应
 #[derive(Debug, Serialize)]
 pub struct ModelsResponse {
@@ -64,17 +91,28 @@ pub struct ModelsResponse {
 const MAX_BUDGET_TOKENS: i32 = 24576;
 
 /// Thinking 配置
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Thinking {
     #[serde(rename = "type")]
     pub thinking_type: String,
     #[serde(
         default = "default_budget_tokens",
-        deserialize_with = "deserialize_budget_tokens"
+        deserialize_with = "deserialize_budget_tokens",
+        alias = "budgetTokens"
     )]
     pub budget_tokens: i32,
 }
 
+impl Thinking {
+    /// 启用 thinking，预算不超过上限
+    pub fn enabled(budget_tokens: i32) -> Self {
+        Self {
+            thinking_type: "enabled".to_string(),
+            budget_tokens: budget_tokens.min(MAX_BUDGET_TOKENS),
+        }
+    }
+}
+
 fn default_budget_tokens() -> i32 {
     20000
 }
@@ -87,27 +125,137 @@ where
 }
 
 /// Messages 请求体
-#[derive(Debug, Deserialize)]
+///
+/// 部分第三方客户端以 camelCase 发送字段，通过 alias 兼容（内容块中的字段见 [`FIELD_ALIASES`]）
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MessagesRequest {
     pub model: String,
+    #[serde(alias = "maxTokens")]
     pub max_tokens: i32,
     pub messages: Vec<Message>,
     #[serde(default)]
     pub stream: bool,
     pub system: Option<Vec<SystemMessage>>,
     pub tools: Option<Vec<Tool>>,
+    #[serde(
+        default,
+        alias = "toolChoice",
+        deserialize_with = "deserialize_tool_choice"
+    )]
     pub tool_choice: Option<serde_json::Value>,
     pub thinking: Option<Thinking>,
 }
 
+/// 内容块和 tool_choice 中可能以 camelCase 发送的字段，反序列化时统一为 snake_case
+pub const FIELD_ALIASES: &[(&str, &str)] = &[
+    ("toolUseId", "tool_use_id"),
+    ("isError", "is_error"),
+    ("mediaType", "media_type"),
+    ("fileId", "file_id"),
+    ("cacheControl", "cache_control"),
+    ("disableParallelToolUse", "disable_parallel_tool_use"),
+];
+
+/// 将对象中的 camelCase 字段改为 snake_case（两者同时存在时保留 snake_case），
+/// 并递归处理 `source` 和 `content` 中嵌套的对象
+fn normalize_field_names(value: &mut serde_json::Value) {
+    use serde_json::Value;
+
+    let Value::Object(map) = value else {
+        return;
+    };
+    for (camel, snake) in FIELD_ALIASES {
+        if let Some(v) = map.remove(*camel) {
+            map.entry(*snake).or_insert(v);
+        }
+    }
+    if let Some(source) = map.get_mut("source") {
+        normalize_field_names(source);
+    }
+    if let Some(Value::Array(items)) = map.get_mut("content") {
+        items.iter_mut().for_each(normalize_field_names);
+    }
+}
+
+fn deserialize_tool_choice<'de, D>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut choice = Option::<serde_json::Value>::deserialize(deserializer)?;
+    if let Some(choice) = &mut choice {
+        normalize_field_names(choice);
+    }
+    Ok(choice)
+}
+
+impl MessagesRequest {
+    /// 客户端是否通过 `tool_choice.disable_parallel_tool_use` 要求每轮最多一个工具调用
+    pub fn disable_parallel_tool_use(&self) -> bool {
+        self.tool_choice
+            .as_ref()
+            .and_then(|choice| choice.get("disable_parallel_tool_use"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+}
+
 /// 消息
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Message {
     pub role: String,
-    /// 可以是 string 或 ContentBlock 数组
+    /// string 或 ContentBlock 数组（单个 ContentBlock 对象在反序列化时包装为数组）
+    #[serde(deserialize_with = "deserialize_content")]
     pub content: serde_json::Value,
 }
 
+fn deserialize_content<'de, D>(deserializer: D) -> Result<serde_json::Value, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let mut content = normalize_content(serde_json::Value::deserialize(deserializer)?)
+        .map_err(serde::de::Error::custom)?;
+    if let serde_json::Value::Array(blocks) = &mut content {
+        blocks.iter_mut().for_each(normalize_field_names);
+    }
+    Ok(content)
+}
+
+/// 将消息内容统一为字符串或内容块数组，形状无效时返回错误说明
+pub fn normalize_content(content: serde_json::Value) -> Result<serde_json::Value, String> {
+    use serde_json::Value;
+
+    match content {
+        Value::String(_) => Ok(content),
+        Value::Array(ref blocks) => match blocks.iter().position(|b| !b.is_object()) {
+            Some(i) => Err(format!(
+                "content[{}] 必须是内容块对象，实际为 {}",
+                i,
+                json_kind(&blocks[i])
+            )),
+            None => Ok(content),
+        },
+        Value::Object(ref block) => match block.get("type") {
+            Some(Value::String(_)) => Ok(Value::Array(vec![content])),
+            _ => Err("单个内容块对象缺少字符串类型的 type 字段".to_string()),
+        },
+        other => Err(format!(
+            "content 必须是字符串、内容块数组或单个内容块对象，实际为 {}",
+            json_kind(&other)
+        )),
+    }
+}
+
+fn json_kind(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
 /// 系统消息
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SystemMessage {
@@ -119,6 +267,7 @@ pub struct SystemMessage {
 pub struct Tool {
     pub name: String,
     pub description: String,
+    #[serde(alias = "inputSchema")]
     pub input_schema: HashMap<String, serde_json::Value>,
 }
 
@@ -174,3 +323,172 @@ pub struct CountTokensRequest {
 pub struct CountTokensResponse {
     pub input_tokens: i32,
 }
+
+/// 请求开销估算响应（`POST /v1/estimate`）
+#[derive(Debug, Serialize)]
+pub struct EstimateResponse {
+    /// 估算的输入 tokens（含注入的系统提示词）
+    pub input_tokens: i32,
+    /// 预计消耗的额度，没有历史计费记录时为 None
+    pub quota: Option<QuotaEstimate>,
+    /// 将处理该请求的账号池
+    pub pool: PoolEstimate,
+    /// 排队优先级
+    pub priority: RequestPriority,
+}
+
+/// 预计额度消耗
+#[derive(Debug, Serialize)]
+pub struct QuotaEstimate {
+    /// 预计消耗的额度单位
+    pub units: f64,
+    /// 计量单位（如 credit）
+    pub unit: Option<String>,
+    /// 估算时假设的输出 tokens（历史平均值，不超过 max_tokens）
+    pub assumed_output_tokens: i32,
+    /// 每 token 的平均额度消耗
+    pub rate_per_token: f64,
+    /// 得出速率的历史请求数
+    pub samples: u64,
+}
+
+/// 处理请求的账号池
+#[derive(Debug, Serialize)]
+pub struct PoolEstimate {
+    /// 工作区名称，全局账号池为 `default`，单账号模式为 `single`
+    pub name: String,
+    /// 就绪状态：ready / saturated / unavailable
+    pub readiness: &'static str,
+    /// 账号池饱和时预计的恢复时间（秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+    /// 当前可选账号的订阅类型
+    pub subscription_types: Vec<String>,
+}
+
+/// `POST /v1/messages` 查询参数
+#[derive(Debug, Default, Deserialize)]
+pub struct MessagesQuery {
+    /// 只转换请求并返回结果，不调用上游
+    #[serde(default)]
+    pub dry_run: bool,
+    /// 非流式响应因 max_tokens 截断时自动续写并拼接内容
+    #[serde(default, rename = "continue")]
+    pub auto_continue: bool,
+}
+
+/// 试运行响应
+#[derive(Debug, Serialize)]
+pub struct DryRunResponse {
+    /// 将发送到 Kiro 的请求（profileArn 已脱敏）
+    pub kiro_request: crate::kiro::model::requests::kiro::KiroRequest,
+    /// 估算的输入 tokens
+    pub input_tokens: i32,
+    /// 请求体字节数
+    pub request_bytes: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// 各客户端实际发送的字段写法
+    fn client_bodies() -> Vec<(&'static str, Value)> {
+        vec![
+            (
+                "anthropic-sdk",
+                json!({
+                    "model": "claude-sonnet-4-5",
+                    "max_tokens": 1024,
+                    "tool_choice": {"type": "auto", "disable_parallel_tool_use": true},
+                    "thinking": {"type": "enabled", "budget_tokens": 2048},
+                    "tools": [{"name": "read", "description": "", "input_schema": {"type": "object"}}],
+                    "messages": [
+                        {"role": "assistant", "content": [
+                            {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+                        ]},
+                        {"role": "user", "content": [
+                            {"type": "tool_result", "tool_use_id": "t1", "is_error": true, "content": [
+                                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AA=="}}
+                            ]}
+                        ]}
+                    ]
+                }),
+            ),
+            (
+                "camelCase",
+                json!({
+                    "model": "claude-sonnet-4-5",
+                    "maxTokens": 1024,
+                    "toolChoice": {"type": "auto", "disableParallelToolUse": true},
+                    "thinking": {"type": "enabled", "budgetTokens": 2048},
+                    "tools": [{"name": "read", "description": "", "inputSchema": {"type": "object"}}],
+                    "messages": [
+                        {"role": "assistant", "content": [
+                            {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+                        ]},
+                        {"role": "user", "content": [
+                            {"type": "tool_result", "toolUseId": "t1", "isError": true, "content": [
+                                {"type": "image", "source": {"type": "base64", "mediaType": "image/png", "data": "AA=="}}
+                            ]}
+                        ]}
+                    ]
+                }),
+            ),
+            (
+                "mixed",
+                json!({
+                    "model": "claude-sonnet-4-5",
+                    "max_tokens": 1024,
+                    "toolChoice": {"type": "auto", "disable_parallel_tool_use": true},
+                    "thinking": {"type": "enabled", "budgetTokens": 2048},
+                    "tools": [{"name": "read", "description": "", "input_schema": {"type": "object"}}],
+                    "messages": [
+                        {"role": "assistant", "content": [
+                            {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+                        ]},
+                        {"role": "user", "content": {
+                            "type": "tool_result", "toolUseId": "t1", "is_error": true, "content": [
+                                {"type": "image", "source": {"type": "base64", "mediaType": "image/png", "data": "AA=="}}
+                            ]
+                        }}
+                    ]
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_field_casing_compat_matrix() {
+        for (client, body) in client_bodies() {
+            let req: MessagesRequest = serde_json::from_value(body)
+                .unwrap_or_else(|e| panic!("{} 的请求解析失败: {}", client, e));
+            assert_eq!(req.max_tokens, 1024, "{}", client);
+            assert!(req.disable_parallel_tool_use(), "{}", client);
+            assert_eq!(
+                req.thinking.as_ref().unwrap().budget_tokens,
+                2048,
+                "{}",
+                client
+            );
+            assert!(
+                req.tools.as_ref().unwrap()[0]
+                    .input_schema
+                    .contains_key("type"),
+                "{}",
+                client
+            );
+
+            let result = &req.messages[1].content[0];
+            assert_eq!(result["tool_use_id"], "t1", "{}", client);
+            assert_eq!(result["is_error"], true, "{}", client);
+            assert_eq!(
+                result["content"][0]["source"]["media_type"], "image/png",
+                "{}",
+                client
+            );
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! `anthropic-version` 请求头协商
+//!
+//! Messages API 在各版本间的请求/响应结构一致，差异仅在流式响应格式：
+//! `2023-01-01` 的 SSE 不带 `event:` 行，并以 `data: [DONE]` 结束。
+//! 未携带该请求头时按最新版本处理，携带不支持的版本时返回 `invalid_request_error`。
+
+/// 请求头名称
+pub const HEADER: &str = "anthropic-version";
+
+/// 支持的 API 版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnthropicVersion {
+    /// 2023-01-01：SSE 事件不带名称，以 `[DONE]` 结束
+    V2023_01_01,
+    /// 2023-06-01：具名 SSE 事件（当前版本）
+    #[default]
+    V2023_06_01,
+}
+
+impl AnthropicVersion {
+    /// 全部支持的版本（从旧到新）
+    pub const ALL: [Self; 2] = [Self::V2023_01_01, Self::V2023_06_01];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::V2023_01_01 => "2023-01-01",
+            Self::V2023_06_01 => "2023-06-01",
+        }
+    }
+
+    /// 解析请求头的值，未携带时使用最新版本
+    pub fn negotiate(value: Option<&str>) -> Result<Self, String> {
+        let Some(value) = value.map(str::trim) else {
+            return Ok(Self::default());
+        };
+        Self::ALL
+            .into_iter()
+            .find(|v| v.as_str() == value)
+            .ok_or_else(|| {
+                let supported: Vec<&str> = Self::ALL.iter().map(|v| v.as_str()).collect();
+                format!(
+                    "不支持的 anthropic-version: {}（支持: {}）",
+                    value,
+                    supported.join(", ")
+                )
+            })
+    }
+
+    /// 流式响应是否使用具名 SSE 事件
+    pub fn named_sse_events(&self) -> bool {
+        *self != Self::V2023_01_01
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(
+            AnthropicVersion::negotiate(None),
+            Ok(AnthropicVersion::V2023_06_01)
+        );
+        assert_eq!(
+            AnthropicVersion::negotiate(Some("2023-01-01")),
+            Ok(AnthropicVersion::V2023_01_01)
+        );
+        let err = AnthropicVersion::negotiate(Some("2024-13-01")).unwrap_err();
+        assert!(err.contains("2023-01-01, 2023-06-01"));
+    }
+}
@@ -1,10 +1,77 @@
 //! HTTP Client 构建模块
 //!
-//! 提供统一的 HTTP Client 构建功能，支持代理配置
+//! 提供统一的 HTTP Client 构建功能，支持代理配置和传输层调优
 
-use reqwest::{Client, Proxy};
+use reqwest::{Client, ClientBuilder, Proxy};
+use std::sync::OnceLock;
 use std::time::Duration;
 
+use crate::model::config::{Config, HttpVersion, TlsBackend};
+
+/// 全局传输层配置，启动时设置一次
+static TRANSPORT: OnceLock<TransportConfig> = OnceLock::new();
+
+/// 传输层配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportConfig {
+    pub http_version: HttpVersion,
+    pub tcp_nodelay: bool,
+    pub pool_idle_timeout: Option<Duration>,
+    pub tls_backend: TlsBackend,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            http_version: HttpVersion::Auto,
+            tcp_nodelay: true,
+            pool_idle_timeout: None,
+            tls_backend: TlsBackend::default(),
+        }
+    }
+}
+
+impl From<&Config> for TransportConfig {
+    fn from(config: &Config) -> Self {
+        Self {
+            http_version: config.http_version,
+            tcp_nodelay: config.tcp_nodelay,
+            pool_idle_timeout: config.pool_idle_timeout_secs.map(Duration::from_secs),
+            tls_backend: config.tls_backend,
+        }
+    }
+}
+
+impl TransportConfig {
+    /// 应用到 ClientBuilder
+    fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        builder = match self.http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+        builder = match self.tls_backend {
+            #[cfg(feature = "native-tls")]
+            TlsBackend::NativeTls => builder.use_native_tls(),
+            // 未编译原生 TLS 时由配置校验提示，这里退回 rustls
+            #[cfg(not(feature = "native-tls"))]
+            TlsBackend::NativeTls => builder.use_rustls_tls(),
+            TlsBackend::Rustls => builder.use_rustls_tls(),
+        };
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        builder.tcp_nodelay(self.tcp_nodelay)
+    }
+}
+
+/// 设置全局传输层配置（需在构建任何 Client 之前调用，重复调用无效）
+pub fn configure_transport(transport: TransportConfig) {
+    if TRANSPORT.set(transport).is_err() {
+        tracing::warn!("传输层配置已设置，忽略重复配置");
+    }
+}
+
 /// 代理配置
 #[derive(Debug, Clone, Default)]
 pub struct ProxyConfig {
@@ -26,6 +93,15 @@ impl ProxyConfig {
         }
     }
 
+    /// 从全局配置读取，未配置代理时返回 None
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let mut proxy = Self::new(config.proxy_url.as_ref()?);
+        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
+            proxy = proxy.with_auth(username, password);
+        }
+        Some(proxy)
+    }
+
     /// 设置认证信息
     pub fn with_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
         self.username = Some(username.into());
@@ -43,7 +119,16 @@ impl ProxyConfig {
 /// # Returns
 /// 配置好的 reqwest::Client
 pub fn build_client(proxy: Option<&ProxyConfig>, timeout_secs: u64) -> anyhow::Result<Client> {
-    let mut builder = Client::builder().timeout(Duration::from_secs(timeout_secs));
+    Ok(client_builder(proxy, timeout_secs)?.build()?)
+}
+
+/// 构建已应用传输层和代理配置的 ClientBuilder，供需要额外设置的调用方使用
+pub fn client_builder(
+    proxy: Option<&ProxyConfig>,
+    timeout_secs: u64,
+) -> anyhow::Result<ClientBuilder> {
+    let transport = TRANSPORT.get().cloned().unwrap_or_default();
+    let mut builder = transport.apply(Client::builder().timeout(Duration::from_secs(timeout_secs)));
 
     if let Some(proxy_config) = proxy {
         let mut proxy = Proxy::all(&proxy_config.url)?;
@@ -57,7 +142,7 @@ pub fn build_client(proxy: Option<&ProxyConfig>, timeout_secs: u64) -> anyhow::R
         tracing::debug!("HTTP Client 使用代理: {}", proxy_config.url);
     }
 
-    Ok(builder.build()?)
+    Ok(builder)
 }
 
 #[cfg(test)]
@@ -92,4 +177,36 @@ mod tests {
         let client = build_client(Some(&config), 30);
         assert!(client.is_ok());
     }
+
+    #[test]
+    fn test_transport_options_build() {
+        for transport in [
+            TransportConfig {
+                http_version: HttpVersion::Http1,
+                tls_backend: TlsBackend::Rustls,
+                ..Default::default()
+            },
+            TransportConfig {
+                http_version: HttpVersion::Http2,
+                tcp_nodelay: false,
+                pool_idle_timeout: Some(Duration::from_secs(30)),
+                ..Default::default()
+            },
+        ] {
+            assert!(transport.apply(Client::builder()).build().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_transport_from_config() {
+        let config = Config {
+            http_version: HttpVersion::Http1,
+            pool_idle_timeout_secs: Some(15),
+            ..Config::default()
+        };
+        let transport = TransportConfig::from(&config);
+        assert_eq!(transport.http_version, HttpVersion::Http1);
+        assert_eq!(transport.pool_idle_timeout, Some(Duration::from_secs(15)));
+        assert!(transport.tcp_nodelay);
+    }
 }
@@ -0,0 +1,140 @@
+//! 故障注入（混沌测试）
+//!
+//! 按 `chaos` 配置的概率在上游调用前注入延迟或 429，在响应字节流中丢弃数据块或篡改
+//! 末尾字节（破坏消息 CRC），用于在集成测试中覆盖重试、故障转移和流错误处理路径。
+//! 仅在 debug 构建中生效，release 构建始终忽略该配置。
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{future, Stream, StreamExt};
+
+use crate::model::config::ChaosConfig;
+
+use super::error::KiroError;
+
+/// 当前构建是否支持故障注入
+pub const SUPPORTED: bool = cfg!(debug_assertions);
+
+/// 返回生效的故障注入配置（release 构建始终为 None）
+pub fn active(config: Option<&ChaosConfig>) -> Option<&ChaosConfig> {
+    config.filter(|_| SUPPORTED)
+}
+
+/// 按概率判定是否触发
+fn roll(probability: f64) -> bool {
+    probability > 0.0 && fastrand::f64() < probability
+}
+
+/// 上游请求发出前注入延迟或限流错误
+///
+/// 注入的 429 错误与真实上游错误格式一致，会走相同的限流记录和故障转移逻辑
+pub async fn before_request(config: Option<&ChaosConfig>) -> Result<(), KiroError> {
+    let Some(config) = active(config) else {
+        return Ok(());
+    };
+    if roll(config.delay_probability) {
+        tracing::warn!("故障注入：延迟上游响应 {}ms", config.delay_ms);
+        tokio::time::sleep(Duration::from_millis(config.delay_ms)).await;
+    }
+    if roll(config.rate_limit_probability) {
+        tracing::warn!("故障注入：返回 429");
+        return Err(KiroError::from_status(
+            "API 请求失败",
+            reqwest::StatusCode::TOO_MANY_REQUESTS,
+            &reqwest::header::HeaderMap::new(),
+            "(故障注入)",
+        ));
+    }
+    Ok(())
+}
+
+/// 对上游响应字节流注入丢块和 CRC 损坏
+pub fn inject_stream<S, E>(
+    body: S,
+    config: Option<ChaosConfig>,
+) -> impl Stream<Item = Result<Bytes, E>> + Send + 'static
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Send + 'static,
+{
+    let config = config.filter(|_| SUPPORTED);
+    body.filter_map(move |chunk| {
+        let chunk = match (&config, chunk) {
+            (Some(config), Ok(bytes)) => inject_chunk(config, bytes).map(Ok),
+            (_, chunk) => Some(chunk),
+        };
+        future::ready(chunk)
+    })
+}
+
+fn inject_chunk(config: &ChaosConfig, bytes: Bytes) -> Option<Bytes> {
+    if roll(config.drop_frame_probability) {
+        tracing::warn!("故障注入：丢弃 {} 字节数据块", bytes.len());
+        return None;
+    }
+    if !bytes.is_empty() && roll(config.corrupt_crc_probability) {
+        tracing::warn!("故障注入：篡改数据块 CRC");
+        let mut corrupted = bytes.to_vec();
+        if let Some(last) = corrupted.last_mut() {
+            *last ^= 0xff;
+        }
+        return Some(Bytes::from(corrupted));
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use std::convert::Infallible;
+
+    fn chunks() -> Vec<Result<Bytes, Infallible>> {
+        vec![
+            Ok(Bytes::from_static(b"abc")),
+            Ok(Bytes::from_static(b"def")),
+        ]
+    }
+
+    async fn collect(config: ChaosConfig) -> Vec<Bytes> {
+        inject_stream(stream::iter(chunks()), Some(config))
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_inject_stream() {
+        let passthrough = collect(ChaosConfig::default()).await;
+        assert_eq!(passthrough, vec!["abc", "def"]);
+
+        let dropped = collect(ChaosConfig {
+            drop_frame_probability: 1.0,
+            ..ChaosConfig::default()
+        })
+        .await;
+        assert!(dropped.is_empty());
+
+        let corrupted = collect(ChaosConfig {
+            corrupt_crc_probability: 1.0,
+            ..ChaosConfig::default()
+        })
+        .await;
+        assert_eq!(corrupted[0][..2], *b"ab");
+        assert_ne!(corrupted[0][2], b'c');
+    }
+
+    #[tokio::test]
+    async fn test_before_request_rate_limit() {
+        assert!(before_request(None).await.is_ok());
+        let config = ChaosConfig {
+            rate_limit_probability: 1.0,
+            ..ChaosConfig::default()
+        };
+        let err = before_request(Some(&config)).await.unwrap_err();
+        // 与真实上游限流走相同的处理逻辑
+        assert!(err.is_throttled());
+        assert!(err.to_string().contains("429"));
+    }
+}
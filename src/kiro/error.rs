@@ -0,0 +1,268 @@
+//! Kiro 调用错误
+//!
+//! [`KiroProvider`](super::provider::KiroProvider) 和 [`TokenManager`](super::token_manager::TokenManager)
+//! 返回 [`KiroError`]，调用方按类型区分限流、凭证失效、网络故障等情况，
+//! 决定账号冷却、标记失效以及返回给客户端的状态码，不再依赖错误信息中的关键字。
+
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+use super::token_manager::{RefreshCircuitOpen, RefreshError};
+use super::upstream_headers::UpstreamHeaders;
+
+/// 上游响应体中表示服务过载（而非账号限流）的标记
+const OVERLOAD_MARKERS: &[&str] = &["ServiceUnavailableException", "INSUFFICIENT_MODEL_CAPACITY"];
+
+/// 上游返回的非成功状态
+#[derive(Debug, Clone)]
+pub struct UpstreamStatus {
+    pub status: u16,
+    /// 错误描述（包含状态码和上游响应体）
+    pub message: String,
+    /// 采集到的上游响应头
+    pub headers: UpstreamHeaders,
+}
+
+/// Token 刷新失败
+#[derive(Debug)]
+pub enum RefreshFailure {
+    /// 本次刷新失败
+    Failed(RefreshError),
+    /// 连续失败后已熔断
+    CircuitOpen(RefreshCircuitOpen),
+    /// 静态 Token（没有 refreshToken）已过期，无法刷新
+    NotRefreshable(String),
+}
+
+impl std::fmt::Display for RefreshFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Failed(e) => e.fmt(f),
+            Self::CircuitOpen(e) => e.fmt(f),
+            Self::NotRefreshable(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Kiro 调用错误
+#[derive(Debug)]
+pub enum KiroError {
+    /// 上游限流（429 或 ThrottlingException），`retry_after` 取自 `Retry-After` 响应头
+    Throttled {
+        retry_after: Option<Duration>,
+        upstream: UpstreamStatus,
+    },
+    /// 上游服务过载（503/529、ServiceUnavailableException 或模型容量不足），与账号无关
+    Overloaded {
+        retry_after: Option<Duration>,
+        upstream: UpstreamStatus,
+    },
+    /// 上游拒绝凭证（401/403，包括账号被暂停）
+    Unauthorized(UpstreamStatus),
+    /// Token 已过期且刷新失败
+    Expired(RefreshFailure),
+    /// 连接失败、超时等网络错误
+    Network(reqwest::Error),
+    /// 上游返回了非预期的状态或内容
+    Protocol {
+        message: String,
+        upstream: Option<UpstreamStatus>,
+    },
+    /// 本地凭证或配置无效，请求未发出
+    Validation(String),
+}
+
+impl KiroError {
+    /// 按上游响应状态分类
+    ///
+    /// `context` 为错误描述前缀（如 `API 请求失败`），`body` 为上游响应体
+    pub fn from_status(context: &str, status: StatusCode, headers: &HeaderMap, body: &str) -> Self {
+        let upstream = UpstreamStatus {
+            status: status.as_u16(),
+            message: format!("{}: {} {}", context, status, body),
+            headers: UpstreamHeaders::capture(headers),
+        };
+        let retry_after = headers
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        // 容量不足可能以 ThrottlingException 的形式返回，先于限流判断
+        if matches!(status.as_u16(), 503 | 529)
+            || OVERLOAD_MARKERS.iter().any(|marker| body.contains(marker))
+        {
+            return Self::Overloaded {
+                retry_after,
+                upstream,
+            };
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS || body.contains("ThrottlingException") {
+            return Self::Throttled {
+                retry_after,
+                upstream,
+            };
+        }
+        if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+            return Self::Unauthorized(upstream);
+        }
+        Self::Protocol {
+            message: upstream.message.clone(),
+            upstream: Some(upstream),
+        }
+    }
+
+    /// 按事件流中的错误或异常事件分类（HTTP 状态为 200，事件类型作为响应体参与分类）
+    pub fn from_event(kind: &str, message: &str) -> Self {
+        Self::from_status(
+            "上游返回错误事件",
+            StatusCode::OK,
+            &HeaderMap::new(),
+            &format!("{}: {}", kind, message),
+        )
+    }
+
+    /// 上游返回的状态（请求未得到上游响应时为 None）
+    pub fn upstream(&self) -> Option<&UpstreamStatus> {
+        match self {
+            Self::Throttled { upstream, .. }
+            | Self::Overloaded { upstream, .. }
+            | Self::Unauthorized(upstream) => Some(upstream),
+            Self::Protocol { upstream, .. } => upstream.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// 是否为限流
+    pub fn is_throttled(&self) -> bool {
+        matches!(self, Self::Throttled { .. })
+    }
+
+    /// 账号是否已被暂停（403 或上游响应中带有 suspended）
+    pub fn is_suspended(&self) -> bool {
+        if let Self::Unauthorized(upstream) = self {
+            if upstream.status == StatusCode::FORBIDDEN.as_u16() {
+                return true;
+            }
+        }
+        self.upstream()
+            .is_some_and(|u| u.message.to_ascii_lowercase().contains("suspended"))
+    }
+
+    /// 本次刷新失败（未熔断）
+    pub fn refresh_error(&self) -> Option<&RefreshError> {
+        match self {
+            Self::Expired(RefreshFailure::Failed(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// 刷新熔断
+    pub fn circuit_open(&self) -> Option<&RefreshCircuitOpen> {
+        match self {
+            Self::Expired(RefreshFailure::CircuitOpen(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// 静态 Token 已过期（无法刷新，账号需要更换凭证）
+    pub fn is_not_refreshable(&self) -> bool {
+        matches!(self, Self::Expired(RefreshFailure::NotRefreshable(_)))
+    }
+}
+
+impl std::fmt::Display for KiroError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Throttled { upstream, .. }
+            | Self::Overloaded { upstream, .. }
+            | Self::Unauthorized(upstream) => f.write_str(&upstream.message),
+            Self::Expired(failure) => failure.fmt(f),
+            Self::Network(e) => write!(f, "网络错误: {}", e),
+            Self::Protocol { message, .. } => f.write_str(message),
+            Self::Validation(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for KiroError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Network(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for KiroError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Network(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status_classification() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "30".parse().unwrap());
+        let err = KiroError::from_status(
+            "API 请求失败",
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            "slow down",
+        );
+        let KiroError::Throttled { retry_after, .. } = &err else {
+            panic!("应为限流: {:?}", err);
+        };
+        assert_eq!(*retry_after, Some(Duration::from_secs(30)));
+        assert_eq!(
+            err.to_string(),
+            "API 请求失败: 429 Too Many Requests slow down"
+        );
+
+        let headers = HeaderMap::new();
+        let err = KiroError::from_status(
+            "API 请求失败",
+            StatusCode::BAD_REQUEST,
+            &headers,
+            r#"{"__type":"ThrottlingException"}"#,
+        );
+        assert!(err.is_throttled());
+
+        let err = KiroError::from_status(
+            "API 请求失败",
+            StatusCode::BAD_REQUEST,
+            &headers,
+            r#"{"__type":"ThrottlingException","reason":"INSUFFICIENT_MODEL_CAPACITY"}"#,
+        );
+        assert!(matches!(err, KiroError::Overloaded { .. }));
+        let err = KiroError::from_status(
+            "API 请求失败",
+            StatusCode::SERVICE_UNAVAILABLE,
+            &headers,
+            "",
+        );
+        assert!(matches!(err, KiroError::Overloaded { .. }));
+
+        let err = KiroError::from_status("API 请求失败", StatusCode::FORBIDDEN, &headers, "");
+        assert!(matches!(err, KiroError::Unauthorized(_)));
+        assert!(err.is_suspended());
+
+        let err = KiroError::from_status(
+            "API 请求失败",
+            StatusCode::BAD_REQUEST,
+            &headers,
+            "Your account is SUSPENDED",
+        );
+        assert!(matches!(err, KiroError::Protocol { .. }));
+        assert!(err.is_suspended());
+
+        let err = KiroError::from_status("API 请求失败", StatusCode::UNAUTHORIZED, &headers, "");
+        assert!(!err.is_suspended());
+        assert_eq!(err.upstream().map(|u| u.status), Some(401));
+    }
+}
@@ -0,0 +1,204 @@
+//! Kiro IDE 凭证同步
+//!
+//! 开启 `ideSync` 后定期检查 Kiro IDE 的 SSO 缓存文件（默认 `~/.aws/sso/cache/kiro-auth-token.json`），
+//! 修改时间变化时读取其中的 Token，比当前 Token 晚过期则替换单账号模式下的凭证。IDE 保持运行时会在
+//! Token 过期前自行刷新（并可能轮换 refreshToken），代理随之使用 IDE 写入的新 Token，不会因为
+//! refreshToken 被 IDE 轮换而刷新失败。
+//!
+//! IdC 登录时缓存文件只记录 `clientIdHash`，clientId/clientSecret 从同目录的 `{clientIdHash}.json` 读取。
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::token_manager::TokenManager;
+use crate::model::config::Config;
+
+/// 相对用户主目录的默认缓存文件路径
+const DEFAULT_TOKEN_FILE: &str = ".aws/sso/cache/kiro-auth-token.json";
+
+/// IDE 缓存文件
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IdeToken {
+    #[serde(default)]
+    client_id_hash: Option<String>,
+    #[serde(flatten)]
+    credentials: KiroCredentials,
+}
+
+/// IdC 客户端注册文件
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ClientRegistration {
+    client_id: String,
+    client_secret: String,
+}
+
+/// 要同步的缓存文件：`ideTokenFile`，未配置时为用户主目录下的默认路径
+pub fn token_file(config: &Config) -> Option<PathBuf> {
+    if let Some(path) = &config.ide_token_file {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(DEFAULT_TOKEN_FILE))
+}
+
+/// 读取缓存文件中的凭证，IdC 登录时补充客户端注册信息
+pub async fn read_token_file(path: &Path) -> anyhow::Result<KiroCredentials> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let token: IdeToken = serde_json::from_str(&content)?;
+    let mut credentials = token.credentials;
+    if let Some(hash) = token
+        .client_id_hash
+        .filter(|_| credentials.client_id.is_none())
+    {
+        let registration = path.with_file_name(format!("{}.json", hash));
+        match tokio::fs::read_to_string(&registration).await {
+            Ok(content) => {
+                let registration: ClientRegistration = serde_json::from_str(&content)?;
+                credentials.client_id = Some(registration.client_id);
+                credentials.client_secret = Some(registration.client_secret);
+            }
+            Err(e) => tracing::warn!("读取 IdC 客户端注册文件 {:?} 失败: {}", registration, e),
+        }
+    }
+    Ok(credentials)
+}
+
+/// 缓存文件同步状态
+pub struct IdeSync {
+    token_manager: Arc<RwLock<TokenManager>>,
+    path: PathBuf,
+    /// 上次读取时的文件修改时间
+    modified: Option<SystemTime>,
+}
+
+impl IdeSync {
+    pub fn new(token_manager: Arc<RwLock<TokenManager>>, path: PathBuf) -> Self {
+        Self {
+            token_manager,
+            path,
+            modified: None,
+        }
+    }
+
+    /// 文件修改时间变化时读取并同步，返回是否采用了文件中的 Token
+    pub async fn check(&mut self) -> bool {
+        let modified = match tokio::fs::metadata(&self.path).await {
+            Ok(metadata) => metadata.modified().ok(),
+            Err(e) => {
+                tracing::debug!("无法读取 Kiro IDE Token 文件 {:?}: {}", self.path, e);
+                return false;
+            }
+        };
+        if modified.is_some() && modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+
+        let credentials = match read_token_file(&self.path).await {
+            Ok(credentials) => credentials,
+            Err(e) => {
+                tracing::warn!("解析 Kiro IDE Token 文件 {:?} 失败: {}", self.path, e);
+                return false;
+            }
+        };
+        let expires_at = credentials.expires_at.clone();
+        let adopted = self.token_manager.write().await.adopt_external(credentials);
+        if adopted {
+            tracing::info!(
+                "已同步 Kiro IDE 刷新的 Token，过期时间: {}",
+                expires_at.as_deref().unwrap_or("-")
+            );
+        }
+        adopted
+    }
+}
+
+/// 启动后台同步任务，启动时立即检查一次
+pub fn spawn(token_manager: Arc<RwLock<TokenManager>>, path: PathBuf, interval: Duration) {
+    tracing::info!(
+        "同步 Kiro IDE Token: {:?}（每 {:?} 检查一次）",
+        path,
+        interval
+    );
+    let mut sync = IdeSync::new(token_manager, path);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            sync.check().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_sync_newer_ide_token() {
+        let dir = std::env::temp_dir().join(format!("kiro-ide-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kiro-auth-token.json");
+        let write = |token: &str, expires_at: chrono::DateTime<chrono::Utc>| {
+            let content = json!({
+                "accessToken": token,
+                "refreshToken": format!("{}-refresh", token),
+                "expiresAt": expires_at.to_rfc3339(),
+                "authMethod": "IdC",
+                "provider": "BuilderId",
+                "clientIdHash": "abc123",
+                "region": "us-east-1"
+            });
+            std::fs::write(&path, content.to_string()).unwrap();
+        };
+        std::fs::write(
+            dir.join("abc123.json"),
+            json!({"clientId": "cid", "clientSecret": "secret"}).to_string(),
+        )
+        .unwrap();
+
+        let credentials = crate::testing::test_credentials();
+        let tm = Arc::new(RwLock::new(TokenManager::new(
+            Config::default(),
+            credentials,
+            None,
+        )));
+        let mut sync = IdeSync::new(tm.clone(), path.clone());
+        assert!(!sync.check().await, "文件不存在时不同步");
+
+        // 比当前 Token 早过期，不采用
+        write("older", chrono::Utc::now() + chrono::Duration::minutes(5));
+        assert!(!sync.check().await);
+        assert_eq!(
+            tm.read().await.credentials().access_token.as_deref(),
+            Some("test-access-token")
+        );
+
+        write("ide", chrono::Utc::now() + chrono::Duration::hours(2));
+        sync.modified = None;
+        assert!(sync.check().await);
+        {
+            let tm = tm.read().await;
+            let credentials = tm.credentials();
+            assert_eq!(credentials.access_token.as_deref(), Some("ide"));
+            assert_eq!(credentials.refresh_token.as_deref(), Some("ide-refresh"));
+            assert_eq!(credentials.client_id.as_deref(), Some("cid"));
+            assert_eq!(credentials.client_secret.as_deref(), Some("secret"));
+            // IDE 文件中的 region 是 SSO 区域，不覆盖 API 区域
+            assert_eq!(credentials.region, None);
+        }
+
+        // 文件未变化时不重复读取
+        assert!(!sync.check().await);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -8,7 +8,8 @@ use crate::model::config::Config;
 
 /// 根据凭证信息生成唯一的 Machine ID
 ///
-/// 优先使用自定义配置，然后使用 profileArn 生成，否则使用 refreshToken 生成
+/// 优先使用自定义配置，其次是账号池为该账号固定的 ID，然后使用 profileArn 生成，
+/// 否则使用 refreshToken（静态 Token 使用 accessToken）生成
 pub fn generate_from_credentials(credentials: &KiroCredentials, config: &Config) -> Option<String> {
     // 如果配置了自定义 machineId 且长度为 64，优先使用
     if let Some(ref machine_id) = config.machine_id {
@@ -17,6 +18,15 @@ pub fn generate_from_credentials(credentials: &KiroCredentials, config: &Config)
         }
     }
 
+    if let Some(ref machine_id) = credentials.machine_id {
+        return Some(machine_id.clone());
+    }
+
+    derive_from_credentials(credentials)
+}
+
+/// 由 profileArn、refreshToken 或静态 accessToken 派生 Machine ID（不考虑配置和已固定的 ID）
+pub fn derive_from_credentials(credentials: &KiroCredentials) -> Option<String> {
     // 如果有有效的 profileArn 则使用 profileArn 固定指纹
     if let Some(ref profile_arn) = credentials.profile_arn {
         if is_valid_profile_arn(profile_arn) {
@@ -31,6 +41,14 @@ pub fn generate_from_credentials(credentials: &KiroCredentials, config: &Config)
         }
     }
 
+    // 静态 Token 没有 refreshToken，使用 accessToken 生成
+    if credentials.is_static() {
+        return credentials
+            .access_token
+            .as_ref()
+            .map(|access_token| sha256_hex(&format!("KotlinNativeAPI/{}", access_token)));
+    }
+
     // 没有有效的凭证
     None
 }
@@ -51,6 +69,7 @@ fn sha256_hex(input: &str) -> String {
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
 
@@ -104,6 +123,25 @@ mod tests {
         assert_eq!(result.as_ref().unwrap().len(), 64);
     }
 
+    #[test]
+    fn test_generate_prefers_pinned_id() {
+        let credentials = KiroCredentials {
+            refresh_token: Some("test_refresh_token".to_string()),
+            machine_id: Some("b".repeat(64)),
+            ..Default::default()
+        };
+
+        let result = generate_from_credentials(&credentials, &Config::default());
+        assert_eq!(result, Some("b".repeat(64)));
+
+        let config = Config {
+            machine_id: Some("a".repeat(64)),
+            ..Config::default()
+        };
+        let result = generate_from_credentials(&credentials, &config);
+        assert_eq!(result, Some("a".repeat(64)));
+    }
+
     #[test]
     fn test_generate_without_credentials() {
         let credentials = KiroCredentials::default();
@@ -1,7 +1,12 @@
 //! Kiro API 客户端模块
 
+pub mod chaos;
+pub mod doctor;
+pub mod error;
+pub mod ide_sync;
 pub mod machine_id;
 pub mod model;
 pub mod parser;
 pub mod provider;
 pub mod token_manager;
+pub mod upstream_headers;
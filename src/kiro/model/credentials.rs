@@ -2,11 +2,14 @@
 //!
 //! 支持从 Kiro IDE 的凭证文件加载，使用 Social 认证方式
 
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::env;
 use std::fs;
 use std::path::Path;
 
+use crate::model::config::Config;
+
 /// Kiro OAuth 凭证
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -23,8 +26,12 @@ pub struct KiroCredentials {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub profile_arn: Option<String>,
 
-    /// 过期时间 (RFC3339 格式)
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// 过期时间 (RFC3339 格式，加载时兼容 Unix 秒/毫秒时间戳并转换为 RFC3339)
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_expires_at"
+    )]
     pub expires_at: Option<String>,
 
     /// 认证方式 (social / idc / builder-id)
@@ -38,6 +45,22 @@ pub struct KiroCredentials {
     /// OIDC Client Secret (IdC 认证需要)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub client_secret: Option<String>,
+
+    /// 自定义 Token 刷新地址（覆盖全局配置）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_url: Option<String>,
+
+    /// IdC 刷新使用的 OIDC 区域（覆盖全局配置）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oidc_region: Option<String>,
+
+    /// Kiro API 区域（覆盖全局配置）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+
+    /// 账号池为该账号固定的 Machine ID（运行时设置，保存在 `machine_ids.json` 而非凭证中）
+    #[serde(skip)]
+    pub machine_id: Option<String>,
 }
 
 impl KiroCredentials {
@@ -47,28 +70,51 @@ impl KiroCredentials {
     }
 
     /// 从环境变量加载凭证
+    ///
+    /// 需要 `REFRESH_TOKEN` 和 `AUTH_METHOD`；只提供 `ACCESS_TOKEN` 时作为静态 Token 加载
     pub fn from_env() -> Option<Self> {
+        let access_token = env::var("ACCESS_TOKEN").ok();
         let refresh_token = env::var("REFRESH_TOKEN").ok();
         let auth_method = env::var("AUTH_METHOD").ok();
 
-        // 至少需要 refresh_token 和 auth_method
-        if refresh_token.is_none() || auth_method.is_none() {
+        let is_static = refresh_token.is_none() && access_token.is_some();
+        if !is_static && (refresh_token.is_none() || auth_method.is_none()) {
             return None;
         }
 
+        // 可刷新的凭证缺少过期时间时视为已过期，启动后立即刷新；
+        // 静态 Token 缺少过期时间时一直使用到上游拒绝为止
+        let expires_at = env::var("EXPIRES_AT")
+            .ok()
+            .map(|value| normalize_expires_at(&value))
+            .or_else(|| (!is_static).then(|| "2000-01-01T00:00:00Z".to_string()));
+
         Some(Self {
-            access_token: env::var("ACCESS_TOKEN").ok(),
+            access_token,
             refresh_token,
             profile_arn: env::var("PROFILE_ARN").ok(),
-            expires_at: env::var("EXPIRES_AT")
-                .ok()
-                .or_else(|| Some("2000-01-01T00:00:00Z".to_string())),
+            expires_at,
             auth_method,
             client_id: env::var("CLIENT_ID").ok(),
             client_secret: env::var("CLIENT_SECRET").ok(),
+            refresh_url: None,
+            oidc_region: None,
+            region: None,
+            machine_id: None,
         })
     }
 
+    /// 是否为静态 Token：只有 accessToken，没有 refreshToken，无法刷新
+    pub fn is_static(&self) -> bool {
+        self.access_token.as_deref().is_some_and(|t| !t.is_empty())
+            && self.refresh_token.as_deref().is_none_or(str::is_empty)
+    }
+
+    /// 账号使用的 Kiro API 区域（账号配置优先于全局配置）
+    pub fn api_region<'a>(&'a self, config: &'a Config) -> &'a str {
+        self.region.as_deref().unwrap_or(&config.region)
+    }
+
     /// 从 JSON 字符串解析凭证
     pub fn from_json(json_string: &str) -> Result<Self, serde_json::Error> {
         serde_json::from_str(json_string)
@@ -97,11 +143,65 @@ impl KiroCredentials {
     }
 
     /// 序列化为格式化的 JSON 字符串
+    #[allow(dead_code)]
     pub fn to_pretty_json(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string_pretty(self)
     }
 }
 
+/// 大于该值的时间戳按毫秒解析（按秒解析时已是 5138 年之后）
+const MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// 解析过期时间，支持 RFC3339、Unix 毫秒时间戳和 Unix 秒时间戳
+pub fn parse_expires_at(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return from_timestamp(timestamp);
+    }
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// 按数值大小区分秒和毫秒
+fn from_timestamp(timestamp: i64) -> Option<DateTime<Utc>> {
+    if timestamp.abs() >= MILLIS_THRESHOLD {
+        Utc.timestamp_millis_opt(timestamp).single()
+    } else {
+        Utc.timestamp_opt(timestamp, 0).single()
+    }
+}
+
+/// 时间戳转换为 RFC3339，已是 RFC3339 或无法解析的值保持原样
+fn normalize_expires_at(value: &str) -> String {
+    match value.trim().parse::<i64>().ok().and_then(from_timestamp) {
+        Some(dt) => dt.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        None => value.to_string(),
+    }
+}
+
+/// 反序列化过期时间，兼容字符串和数值形式的时间戳
+fn deserialize_expires_at<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(match value {
+        None | Some(serde_json::Value::Null) => None,
+        Some(serde_json::Value::String(s)) => Some(normalize_expires_at(&s)),
+        Some(serde_json::Value::Number(n)) => {
+            let timestamp = n.as_i64().or_else(|| n.as_f64().map(|f| f as i64));
+            timestamp
+                .and_then(from_timestamp)
+                .map(|dt| dt.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+        }
+        Some(other) => {
+            tracing::warn!("无法识别的 expiresAt: {}", other);
+            None
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,12 +245,17 @@ mod tests {
             auth_method: Some("social".to_string()),
             client_id: None,
             client_secret: None,
+            refresh_url: None,
+            oidc_region: None,
+            region: None,
+            machine_id: Some("a".repeat(64)),
         };
 
         let json = creds.to_pretty_json().unwrap();
         assert!(json.contains("accessToken"));
         assert!(json.contains("authMethod"));
         assert!(!json.contains("refreshToken"));
+        assert!(!json.contains("machineId"));
     }
 
     #[test]
@@ -160,4 +265,37 @@ mod tests {
             "credentials.json"
         );
     }
+
+    #[test]
+    fn test_parse_expires_at_formats() {
+        let expected = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+        assert_eq!(parse_expires_at("2025-06-01T12:00:00Z"), Some(expected));
+        assert_eq!(
+            parse_expires_at("2025-06-01T20:00:00+08:00"),
+            Some(expected)
+        );
+        assert_eq!(parse_expires_at("1748779200000"), Some(expected));
+        assert_eq!(parse_expires_at("1748779200"), Some(expected));
+        assert_eq!(parse_expires_at("tomorrow"), None);
+    }
+
+    #[test]
+    fn test_expires_at_normalized_on_load() {
+        let expires_at = |value: &str| {
+            let json = format!(r#"{{"refreshToken": "r", "expiresAt": {}}}"#, value);
+            KiroCredentials::from_json(&json).unwrap().expires_at
+        };
+        let expected = Some("2025-06-01T12:00:00Z".to_string());
+        assert_eq!(expires_at("1748779200000"), expected);
+        assert_eq!(expires_at("1748779200"), expected);
+        assert_eq!(expires_at(r#""1748779200000""#), expected);
+        assert_eq!(expires_at("1748779200000.0"), expected);
+        assert_eq!(expires_at(r#""2025-06-01T12:00:00Z""#), expected);
+        assert_eq!(expires_at("null"), None);
+
+        // 保存时写出 RFC3339
+        let creds = KiroCredentials::from_json(r#"{"expiresAt": 1748779200}"#).unwrap();
+        let saved = serde_json::to_value(&creds).unwrap();
+        assert_eq!(saved["expiresAt"], "2025-06-01T12:00:00Z");
+    }
 }
@@ -9,6 +9,31 @@ use crate::kiro::parser::frame::Frame;
 
 use super::base::EventPayload;
 
+/// 上游报告的消息完成状态（`messageStatus`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionStatus {
+    /// 正常结束
+    Completed,
+    /// 因调用工具而结束
+    ToolUse,
+    /// 因输出长度上限被截断
+    MaxTokens,
+}
+
+impl CompletionStatus {
+    /// 解析 `messageStatus`，进行中或未知的状态返回 None
+    pub fn parse(status: &str) -> Option<Self> {
+        match status.trim().to_ascii_uppercase().as_str() {
+            "COMPLETED" | "COMPLETE" | "END_TURN" => Some(Self::Completed),
+            "TOOL_USE" | "TOOL_USE_REQUESTED" => Some(Self::ToolUse),
+            "MAX_TOKENS" | "LENGTH" | "TRUNCATED" | "INCOMPLETE" | "CONTENT_LENGTH_EXCEEDED" => {
+                Some(Self::MaxTokens)
+            }
+            _ => None,
+        }
+    }
+}
+
 /// 助手响应事件
 ///
 /// 包含 AI 助手的流式响应内容
@@ -37,10 +62,21 @@ pub struct AssistantResponseEvent {
     /// 捕获其他未使用的字段，确保反序列化兼容性
     #[serde(flatten)]
     #[serde(skip_serializing)]
-    #[allow(dead_code)]
     extra: serde_json::Value,
 }
 
+impl AssistantResponseEvent {
+    /// 追问提示内容（`followupPrompt.content`）
+    pub fn followup_prompt(&self) -> Option<&str> {
+        self.extra.get("followupPrompt")?.get("content")?.as_str()
+    }
+
+    /// 消息完成状态（`messageStatus`），仅在最后的事件中出现
+    pub fn completion_status(&self) -> Option<CompletionStatus> {
+        CompletionStatus::parse(self.extra.get("messageStatus")?.as_str()?)
+    }
+}
+
 impl EventPayload for AssistantResponseEvent {
     fn from_frame(frame: &Frame) -> ParseResult<Self> {
         frame.payload_as_json()
@@ -88,6 +124,26 @@ mod tests {
         }"#;
         let event: AssistantResponseEvent = serde_json::from_str(json).unwrap();
         assert_eq!(event.content, "Done");
+        assert_eq!(event.completion_status(), Some(CompletionStatus::Completed));
+    }
+
+    #[test]
+    fn test_completion_status() {
+        let status = |json: &str| {
+            serde_json::from_str::<AssistantResponseEvent>(json)
+                .unwrap()
+                .completion_status()
+        };
+        assert_eq!(status(r#"{"content":"a"}"#), None);
+        assert_eq!(status(r#"{"messageStatus":"IN_PROGRESS"}"#), None);
+        assert_eq!(
+            status(r#"{"messageStatus":"max_tokens"}"#),
+            Some(CompletionStatus::MaxTokens)
+        );
+        assert_eq!(
+            status(r#"{"messageStatus":"TOOL_USE"}"#),
+            Some(CompletionStatus::ToolUse)
+        );
     }
 
     #[test]
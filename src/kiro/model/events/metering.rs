@@ -0,0 +1,62 @@
+//! 计费事件
+//!
+//! 处理 meteringEvent 类型的事件
+
+use serde::Deserialize;
+
+use crate::kiro::parser::error::ParseResult;
+use crate::kiro::parser::frame::Frame;
+
+use super::base::EventPayload;
+
+/// 计费事件
+///
+/// 上游在响应结束前报告本次请求消耗的额度
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeteringEvent {
+    /// 计量单位 (如 credit)
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// 计量单位复数形式
+    #[serde(default)]
+    pub unit_plural: Option<String>,
+    /// 本次消耗的额度
+    #[serde(default)]
+    pub usage: f64,
+}
+
+impl EventPayload for MeteringEvent {
+    fn from_frame(frame: &Frame) -> ParseResult<Self> {
+        frame.payload_as_json()
+    }
+}
+
+impl std::fmt::Display for MeteringEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = if self.usage == 1.0 {
+            self.unit.as_deref()
+        } else {
+            self.unit_plural.as_deref().or(self.unit.as_deref())
+        };
+        write!(f, "{} {}", self.usage, unit.unwrap_or("credits"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_metering_event() {
+        let event: MeteringEvent =
+            serde_json::from_str(r#"{"unit":"credit","unitPlural":"credits","usage":0.25}"#)
+                .unwrap();
+        assert_eq!(event.usage, 0.25);
+        assert_eq!(event.to_string(), "0.25 credits");
+
+        // 缺少字段时按 0 处理
+        let event: MeteringEvent = serde_json::from_str("{}").unwrap();
+        assert_eq!(event.usage, 0.0);
+    }
+}
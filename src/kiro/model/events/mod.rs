@@ -5,9 +5,11 @@
 mod assistant;
 mod base;
 mod context_usage;
+mod metering;
 mod tool_use;
 
-pub use assistant::AssistantResponseEvent;
+pub use assistant::{AssistantResponseEvent, CompletionStatus};
 pub use base::Event;
 pub use context_usage::ContextUsageEvent;
+pub use metering::MeteringEvent;
 pub use tool_use::ToolUseEvent;
@@ -5,10 +5,12 @@
 //! - `events`: 响应事件类型
 //! - `requests`: 请求类型
 //! - `credentials`: OAuth 凭证
+//! - `profiles`: Profile 查询
 //! - `token_refresh`: Token 刷新
 
 pub mod common;
 pub mod credentials;
 pub mod events;
+pub mod profiles;
 pub mod requests;
 pub mod token_refresh;
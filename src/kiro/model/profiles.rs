@@ -0,0 +1,66 @@
+//! Profile 查询数据模型
+//!
+//! 对应 ListAvailableProfiles 接口，用于自动发现 profileArn
+
+use serde::{Deserialize, Serialize};
+
+/// ListAvailableProfiles 请求体
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListAvailableProfilesRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_token: Option<String>,
+}
+
+/// ListAvailableProfiles 响应体
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListAvailableProfilesResponse {
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+}
+
+/// 可用 Profile
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub arn: String,
+}
+
+impl ListAvailableProfilesResponse {
+    /// 选取第一个有效的 profileArn
+    pub fn first_arn(&self) -> Option<String> {
+        self.profiles
+            .iter()
+            .map(|p| p.arn.as_str())
+            .find(|arn| !arn.is_empty())
+            .map(|arn| arn.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_profiles() {
+        let json = r#"{
+            "profiles": [
+                {"arn": "arn:aws:codewhisperer:us-east-1:123456789:profile/ABC", "profileName": "default"}
+            ]
+        }"#;
+        let resp: ListAvailableProfilesResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            resp.first_arn(),
+            Some("arn:aws:codewhisperer:us-east-1:123456789:profile/ABC".to_string())
+        );
+    }
+
+    #[test]
+    fn test_first_arn_empty() {
+        let resp: ListAvailableProfilesResponse = serde_json::from_str("{}").unwrap();
+        assert!(resp.first_arn().is_none());
+    }
+}
@@ -13,17 +13,22 @@ use super::tool::{Tool, ToolResult, ToolUseEntry};
 #[serde(rename_all = "camelCase")]
 pub struct ConversationState {
     /// 代理延续 ID
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        alias = "agent_continuation_id"
+    )]
     pub agent_continuation_id: Option<String>,
     /// 代理任务类型（通常为 "vibe"）
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "agent_task_type")]
     pub agent_task_type: Option<String>,
     /// 聊天触发类型（"MANUAL" 或 "AUTO"）
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "chat_trigger_type")]
     pub chat_trigger_type: Option<String>,
     /// 当前消息
+    #[serde(alias = "current_message")]
     pub current_message: CurrentMessage,
     /// 会话 ID
+    #[serde(alias = "conversation_id")]
     pub conversation_id: String,
     /// 历史消息列表
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -79,6 +84,7 @@ impl ConversationState {
 #[serde(rename_all = "camelCase")]
 pub struct CurrentMessage {
     /// 用户输入消息
+    #[serde(alias = "user_input_message")]
     pub user_input_message: UserInputMessage,
 }
 
@@ -94,10 +100,12 @@ impl CurrentMessage {
 #[serde(rename_all = "camelCase")]
 pub struct UserInputMessage {
     /// 用户输入消息上下文
+    #[serde(alias = "user_input_message_context")]
     pub user_input_message_context: UserInputMessageContext,
     /// 消息内容
     pub content: String,
     /// 模型 ID
+    #[serde(alias = "model_id")]
     pub model_id: String,
     /// 图片列表
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -145,7 +153,7 @@ impl UserInputMessage {
 #[serde(rename_all = "camelCase")]
 pub struct UserInputMessageContext {
     /// 工具执行结果列表
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty", alias = "tool_results")]
     pub tool_results: Vec<ToolResult>,
     /// 可用工具列表
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
@@ -240,6 +248,7 @@ impl Message {
 #[serde(rename_all = "camelCase")]
 pub struct HistoryUserMessage {
     /// 用户输入消息
+    #[serde(alias = "user_input_message")]
     pub user_input_message: UserMessage,
 }
 
@@ -259,6 +268,7 @@ pub struct UserMessage {
     /// 消息内容
     pub content: String,
     /// 模型 ID
+    #[serde(alias = "model_id")]
     pub model_id: String,
     /// 消息来源
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -267,7 +277,11 @@ pub struct UserMessage {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub images: Vec<KiroImage>,
     /// 用户输入消息上下文
-    #[serde(default, skip_serializing_if = "is_default_context")]
+    #[serde(
+        default,
+        skip_serializing_if = "is_default_context",
+        alias = "user_input_message_context"
+    )]
     pub user_input_message_context: UserInputMessageContext,
 }
 
@@ -305,6 +319,7 @@ impl UserMessage {
 #[serde(rename_all = "camelCase")]
 pub struct HistoryAssistantMessage {
     /// 助手响应消息
+    #[serde(alias = "assistant_response_message")]
     pub assistant_response_message: AssistantMessage,
 }
 
@@ -324,7 +339,7 @@ pub struct AssistantMessage {
     /// 响应内容
     pub content: String,
     /// 工具使用列表
-    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none", alias = "tool_uses")]
     pub tool_uses: Option<Vec<ToolUseEntry>>,
 }
 
@@ -31,9 +31,10 @@ use super::conversation::ConversationState;
 #[serde(rename_all = "camelCase")]
 pub struct KiroRequest {
     /// 对话状态
+    #[serde(alias = "conversation_state")]
     pub conversation_state: ConversationState,
     /// Profile ARN（可选）
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "profile_arn")]
     pub profile_arn: Option<String>,
 }
 #[cfg(test)]
@@ -65,4 +66,51 @@ mod tests {
             "Test message"
         );
     }
+
+    #[test]
+    fn test_kiro_request_accepts_snake_case() {
+        let json = r#"{
+            "conversation_state": {
+                "conversation_id": "conv-789",
+                "agent_task_type": "vibe",
+                "current_message": {
+                    "user_input_message": {
+                        "content": "Test",
+                        "model_id": "claude-sonnet-4.5",
+                        "user_input_message_context": {
+                            "tools": [{"tool_specification": {
+                                "name": "read", "description": "", "input_schema": {"json": {}}
+                            }}],
+                            "toolResults": [{"tool_use_id": "t1", "content": [], "is_error": true}]
+                        }
+                    }
+                },
+                "history": [
+                    {"userInputMessage": {"content": "hi", "model_id": "claude-sonnet-4.5"}},
+                    {"assistant_response_message": {"content": "ok", "tool_uses": [
+                        {"tool_use_id": "t1", "name": "read", "input": {}}
+                    ]}}
+                ]
+            },
+            "profile_arn": "arn:test"
+        }"#;
+
+        let request: KiroRequest = serde_json::from_str(json).unwrap();
+        let state = &request.conversation_state;
+        assert_eq!(state.conversation_id, "conv-789");
+        assert_eq!(state.agent_task_type.as_deref(), Some("vibe"));
+        let context = &state
+            .current_message
+            .user_input_message
+            .user_input_message_context;
+        assert_eq!(context.tools[0].tool_specification.name, "read");
+        assert!(context.tool_results[0].is_error);
+        assert!(state.history[0].is_user());
+        assert!(state.history[1].is_assistant());
+        assert_eq!(request.profile_arn.as_deref(), Some("arn:test"));
+        // 序列化仍使用 camelCase
+        assert!(serde_json::to_string(&request)
+            .unwrap()
+            .contains("\"conversationState\""));
+    }
 }
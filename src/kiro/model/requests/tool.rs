@@ -11,6 +11,7 @@ use serde::{Deserialize, Serialize};
 #[serde(rename_all = "camelCase")]
 pub struct Tool {
     /// 工具规范
+    #[serde(alias = "tool_specification")]
     pub tool_specification: ToolSpecification,
 }
 
@@ -25,6 +26,7 @@ pub struct ToolSpecification {
     /// 工具描述
     pub description: String,
     /// 输入模式（JSON Schema）
+    #[serde(alias = "input_schema")]
     pub input_schema: InputSchema,
 }
 
@@ -62,6 +64,7 @@ impl InputSchema {
 #[serde(rename_all = "camelCase")]
 pub struct ToolResult {
     /// 工具使用 ID（与请求中的 tool_use_id 对应）
+    #[serde(alias = "tool_use_id")]
     pub tool_use_id: String,
     /// 结果内容（数组格式）
     pub content: Vec<serde_json::Map<String, serde_json::Value>>,
@@ -69,7 +72,7 @@ pub struct ToolResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub status: Option<String>,
     /// 是否为错误
-    #[serde(default, skip_serializing_if = "is_false")]
+    #[serde(default, skip_serializing_if = "is_false", alias = "is_error")]
     pub is_error: bool,
 }
 
@@ -118,6 +121,7 @@ impl ToolResult {
 #[serde(rename_all = "camelCase")]
 pub struct ToolUseEntry {
     /// 工具使用 ID
+    #[serde(alias = "tool_use_id")]
     pub tool_use_id: String,
     /// 工具名称
     pub name: String,
@@ -1,10 +1,22 @@
 //! AWS Event Stream 解析器
 //!
-//! 提供对 AWS Event Stream 协议的解析支持，
-//! 用于处理 generateAssistantResponse 端点的流式响应
+//! 解析实现位于独立的 `aws-eventstream-lite` 子 crate，
+//! 此处重新导出以保持原有模块路径
 
-pub mod crc;
-pub mod decoder;
-pub mod error;
-pub mod frame;
-pub mod header;
+pub use aws_eventstream_lite::{decoder, error, frame};
+
+use crate::model::config::{DecoderBufferConfig, DecoderOverflow};
+
+impl From<&DecoderBufferConfig> for decoder::DecoderConfig {
+    fn from(config: &DecoderBufferConfig) -> Self {
+        Self {
+            initial_capacity: config.initial_capacity,
+            max_buffer_size: config.max_buffer_bytes,
+            overflow: match config.overflow {
+                DecoderOverflow::Error => decoder::OverflowStrategy::Error,
+                DecoderOverflow::Truncate => decoder::OverflowStrategy::Truncate,
+            },
+            ..Self::default()
+        }
+    }
+}
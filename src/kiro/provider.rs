@@ -6,20 +6,24 @@
 use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST};
 use reqwest::Client;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::RwLock;
 use uuid::Uuid;
 
 use crate::http_client::{build_client, ProxyConfig};
-use crate::kiro::machine_id;
+use crate::kiro::error::KiroError;
 use crate::kiro::model::credentials::KiroCredentials;
 use crate::kiro::token_manager::TokenManager;
+use crate::kiro::upstream_headers::UpstreamHeaders;
+use crate::kiro::{chaos, machine_id};
+use crate::model::config::AgentMode;
 
 /// Kiro API Provider
 ///
 /// 核心组件，负责与 Kiro API 通信
-/// 内部使用 Arc<Mutex<_>> 管理 TokenManager 状态，支持线程安全的并发访问
+/// 内部使用 Arc<RwLock<_>> 管理 TokenManager 状态：Token 有效时并发请求只需读锁，
+/// 仅在需要刷新时获取写锁，同一账号上的请求可以并行发送
 pub struct KiroProvider {
-    token_manager: Arc<Mutex<TokenManager>>,
+    token_manager: Arc<RwLock<TokenManager>>,
     client: Client,
 }
 
@@ -36,14 +40,14 @@ impl KiroProvider {
             .expect("创建 HTTP 客户端失败");
 
         Self {
-            token_manager: Arc::new(Mutex::new(token_manager)),
+            token_manager: Arc::new(RwLock::new(token_manager)),
             client,
         }
     }
 
     /// 使用共享的 TokenManager 创建 Provider（适用于账号池模式）
     pub fn with_shared_token_manager(
-        token_manager: Arc<Mutex<TokenManager>>,
+        token_manager: Arc<RwLock<TokenManager>>,
         proxy: Option<ProxyConfig>,
     ) -> Self {
         let client = build_client(proxy.as_ref(), 720) // 12 分钟超时
@@ -55,27 +59,43 @@ impl KiroProvider {
         }
     }
 
-    /// 获取 API 基础 URL
+    /// 共享的 TokenManager
+    pub fn token_manager(&self) -> Arc<RwLock<TokenManager>> {
+        self.token_manager.clone()
+    }
+
+    /// 获取 API 基础 URL（使用账号区域，未配置时使用全局区域）
     #[allow(dead_code)]
     pub async fn base_url(&self) -> String {
-        let region = {
-            let tm = self.token_manager.lock().await;
-            tm.config().region.clone()
-        };
-        format!(
-            "https://q.{}.amazonaws.com/generateAssistantResponse",
-            region
-        )
+        let tm = self.token_manager.read().await;
+        Self::api_url(tm.credentials(), tm.config())
     }
 
-    /// 获取 API 基础域名
+    /// 获取 API 基础域名（使用账号区域，未配置时使用全局区域）
     #[allow(dead_code)]
     pub async fn base_domain(&self) -> String {
-        let region = {
-            let tm = self.token_manager.lock().await;
-            tm.config().region.clone()
-        };
-        format!("q.{}.amazonaws.com", region)
+        let tm = self.token_manager.read().await;
+        Self::api_domain(tm.credentials(), tm.config())
+    }
+
+    fn api_url(credentials: &KiroCredentials, config: &crate::model::config::Config) -> String {
+        if let Some(url) = &config.kiro_api_url {
+            return format!("{}/generateAssistantResponse", url.trim_end_matches('/'));
+        }
+        format!(
+            "https://{}/generateAssistantResponse",
+            Self::api_domain(credentials, config)
+        )
+    }
+
+    fn api_domain(credentials: &KiroCredentials, config: &crate::model::config::Config) -> String {
+        format!("q.{}.amazonaws.com", credentials.api_region(config))
+    }
+
+    /// 获取当前凭证中的 profileArn（可能由 Token 刷新后自动发现）
+    pub async fn profile_arn(&self) -> Option<String> {
+        let tm = self.token_manager.read().await;
+        tm.credentials().profile_arn.clone()
     }
 
     /// 构建请求头
@@ -83,14 +103,17 @@ impl KiroProvider {
         token: &str,
         credentials: &KiroCredentials,
         config: &crate::model::config::Config,
-    ) -> anyhow::Result<HeaderMap> {
-        let machine_id = machine_id::generate_from_credentials(credentials, config)
-            .ok_or_else(|| anyhow::anyhow!("无法生成 machine_id，请检查凭证配置"))?;
+        agent_mode: AgentMode,
+    ) -> Result<HeaderMap, KiroError> {
+        let machine_id =
+            machine_id::generate_from_credentials(credentials, config).ok_or_else(|| {
+                KiroError::Validation("无法生成 machine_id，请检查凭证配置".to_string())
+            })?;
 
         let kiro_version = config.kiro_version.clone();
         let os_name = config.system_version.clone();
         let node_version = config.node_version.clone();
-        let base_domain = format!("q.{}.amazonaws.com", config.region);
+        let base_domain = Self::api_domain(credentials, config);
 
         let x_amz_user_agent = format!("aws-sdk-js/1.0.27 KiroIDE-{}-{}", kiro_version, machine_id);
 
@@ -106,7 +129,10 @@ impl KiroProvider {
             "x-amzn-codewhisperer-optout",
             HeaderValue::from_static("true"),
         );
-        headers.insert("x-amzn-kiro-agent-mode", HeaderValue::from_static("vibe"));
+        headers.insert(
+            "x-amzn-kiro-agent-mode",
+            HeaderValue::from_static(agent_mode.as_str()),
+        );
         headers.insert(
             "x-amz-user-agent",
             HeaderValue::from_str(&x_amz_user_agent).unwrap(),
@@ -115,7 +141,10 @@ impl KiroProvider {
             reqwest::header::USER_AGENT,
             HeaderValue::from_str(&user_agent).unwrap(),
         );
-        headers.insert(HOST, HeaderValue::from_str(&base_domain).unwrap());
+        // 自定义 API 地址时由 HTTP 客户端按地址填写 Host
+        if config.kiro_api_url.is_none() {
+            headers.insert(HOST, HeaderValue::from_str(&base_domain).unwrap());
+        }
         headers.insert(
             "amz-sdk-invocation-id",
             HeaderValue::from_str(&Uuid::new_v4().to_string()).unwrap(),
@@ -133,10 +162,31 @@ impl KiroProvider {
         Ok(headers)
     }
 
+    /// 按当前凭证构建请求地址和请求头，不发送请求（诊断用）
+    pub async fn preview_request(
+        &self,
+        agent_mode: AgentMode,
+    ) -> Result<(String, HeaderMap), KiroError> {
+        let (token, config, credentials) = self.acquire_token_snapshot().await?;
+        let url = Self::api_url(&credentials, &config);
+        let headers = Self::build_headers(&token, &credentials, &config, agent_mode)?;
+        Ok((url, headers))
+    }
+
+    /// 获取有效 Token 及对应的配置和凭证快照
+    ///
+    /// 无需刷新时只持有读锁，需要刷新时获取写锁（写锁内会再次检查，避免重复刷新）
     async fn acquire_token_snapshot(
         &self,
-    ) -> anyhow::Result<(String, crate::model::config::Config, KiroCredentials)> {
-        let mut tm = self.token_manager.lock().await;
+    ) -> Result<(String, crate::model::config::Config, KiroCredentials), KiroError> {
+        {
+            let tm = self.token_manager.read().await;
+            if let Some(token) = tm.cached_token() {
+                return Ok((token, tm.config().clone(), tm.credentials().clone()));
+            }
+        }
+
+        let mut tm = self.token_manager.write().await;
         let token = tm.ensure_valid_token().await?;
         let config = tm.config().clone();
         let credentials = tm.credentials().clone();
@@ -147,16 +197,19 @@ impl KiroProvider {
     ///
     /// # Arguments
     /// * `request_body` - JSON 格式的请求体字符串
+    /// * `agent_mode` - Kiro 代理模式，需与请求体中的 `agentTaskType` 一致
     ///
     /// # Returns
-    /// 返回原始的 HTTP Response，不做解析
-    pub async fn call_api(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
+    /// 返回原始的 HTTP Response，不做解析；上游返回非成功状态时按状态分类为 [`KiroError`]
+    pub async fn call_api(
+        &self,
+        request_body: &str,
+        agent_mode: AgentMode,
+    ) -> Result<reqwest::Response, KiroError> {
         let (token, config, credentials) = self.acquire_token_snapshot().await?;
-        let url = format!(
-            "https://q.{}.amazonaws.com/generateAssistantResponse",
-            config.region
-        );
-        let headers = Self::build_headers(&token, &credentials, &config)?;
+        chaos::before_request(config.chaos.as_ref()).await?;
+        let url = Self::api_url(&credentials, &config);
+        let headers = Self::build_headers(&token, &credentials, &config, agent_mode)?;
 
         let response = self
             .client
@@ -168,8 +221,18 @@ impl KiroProvider {
 
         if !response.status().is_success() {
             let status = response.status();
+            let headers = response.headers().clone();
             let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("API 请求失败: {} {}", status, body);
+            return Err(KiroError::from_status(
+                "API 请求失败",
+                status,
+                &headers,
+                &body,
+            ));
+        }
+        let upstream_headers = UpstreamHeaders::capture(response.headers());
+        if !upstream_headers.is_empty() {
+            tracing::debug!("上游响应头: {}", upstream_headers);
         }
 
         Ok(response)
@@ -179,16 +242,19 @@ impl KiroProvider {
     ///
     /// # Arguments
     /// * `request_body` - JSON 格式的请求体字符串
+    /// * `agent_mode` - Kiro 代理模式，需与请求体中的 `agentTaskType` 一致
     ///
     /// # Returns
     /// 返回原始的 HTTP Response，调用方负责处理流式数据
-    pub async fn call_api_stream(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
+    pub async fn call_api_stream(
+        &self,
+        request_body: &str,
+        agent_mode: AgentMode,
+    ) -> Result<reqwest::Response, KiroError> {
         let (token, config, credentials) = self.acquire_token_snapshot().await?;
-        let url = format!(
-            "https://q.{}.amazonaws.com/generateAssistantResponse",
-            config.region
-        );
-        let headers = Self::build_headers(&token, &credentials, &config)?;
+        chaos::before_request(config.chaos.as_ref()).await?;
+        let url = Self::api_url(&credentials, &config);
+        let headers = Self::build_headers(&token, &credentials, &config, agent_mode)?;
 
         let response = self
             .client
@@ -200,8 +266,18 @@ impl KiroProvider {
 
         if !response.status().is_success() {
             let status = response.status();
+            let headers = response.headers().clone();
             let body = response.text().await.unwrap_or_default();
-            anyhow::bail!("流式 API 请求失败: {} {}", status, body);
+            return Err(KiroError::from_status(
+                "流式 API 请求失败",
+                status,
+                &headers,
+                &body,
+            ));
+        }
+        let upstream_headers = UpstreamHeaders::capture(response.headers());
+        if !upstream_headers.is_empty() {
+            tracing::debug!("上游响应头: {}", upstream_headers);
         }
 
         Ok(response)
@@ -209,6 +285,7 @@ impl KiroProvider {
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
     use crate::kiro::model::credentials::KiroCredentials;
@@ -235,6 +312,47 @@ mod tests {
         assert_eq!(provider.base_domain().await, "q.us-east-1.amazonaws.com");
     }
 
+    #[tokio::test]
+    async fn test_account_region_overrides_global() {
+        let config = Config {
+            region: "us-east-1".to_string(),
+            ..Config::default()
+        };
+        let credentials = KiroCredentials {
+            region: Some("eu-west-1".to_string()),
+            ..Default::default()
+        };
+        let tm = TokenManager::new(config, credentials, None);
+        let provider = KiroProvider::new(tm);
+        assert_eq!(
+            provider.base_url().await,
+            "https://q.eu-west-1.amazonaws.com/generateAssistantResponse"
+        );
+        assert_eq!(provider.base_domain().await, "q.eu-west-1.amazonaws.com");
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_does_not_block_concurrent_requests() {
+        let credentials = KiroCredentials {
+            access_token: Some("token".to_string()),
+            expires_at: Some((chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let tm = TokenManager::new(Config::default(), credentials, None);
+        let provider = KiroProvider::new(tm);
+
+        // 其他请求持有读锁时仍可获取 Token
+        let _guard = provider.token_manager.read().await;
+        let (token, _, _) = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            provider.acquire_token_snapshot(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(token, "token");
+    }
+
     #[tokio::test]
     async fn test_build_headers() {
         let mut config = Config::default();
@@ -245,7 +363,9 @@ mod tests {
         credentials.profile_arn = Some("arn:aws:sso::123456789:profile/test".to_string());
         credentials.refresh_token = Some("a".repeat(150));
 
-        let headers = KiroProvider::build_headers("test_token", &credentials, &config).unwrap();
+        let headers =
+            KiroProvider::build_headers("test_token", &credentials, &config, AgentMode::Vibe)
+                .unwrap();
 
         assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "application/json");
         assert_eq!(headers.get("x-amzn-codewhisperer-optout").unwrap(), "true");
@@ -257,5 +377,10 @@ mod tests {
             .unwrap()
             .starts_with("Bearer "));
         assert_eq!(headers.get(CONNECTION).unwrap(), "close");
+
+        let headers =
+            KiroProvider::build_headers("test_token", &credentials, &config, AgentMode::Spec)
+                .unwrap();
+        assert_eq!(headers.get("x-amzn-kiro-agent-mode").unwrap(), "spec");
     }
 }
@@ -1,18 +1,118 @@
 //! Token 管理模块
 //!
-//! 负责 Token 过期检测和刷新，支持 Social 和 IdC 认证方式
+//! 负责 Token 过期检测和刷新，支持 Social 和 IdC 认证方式。
+//! 只提供 accessToken 的静态凭证（由外部系统签发）不会刷新，过期后返回
+//! [`RefreshFailure::NotRefreshable`]
 
 use anyhow::bail;
 use chrono::{DateTime, Duration, Utc};
+use tokio::task::JoinHandle;
 
 use crate::http_client::{build_client, ProxyConfig};
+use crate::kiro::error::{KiroError, RefreshFailure};
 use crate::kiro::machine_id;
-use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::credentials::{parse_expires_at, KiroCredentials};
+use crate::kiro::model::profiles::{ListAvailableProfilesRequest, ListAvailableProfilesResponse};
 use crate::kiro::model::token_refresh::{
     IdcRefreshRequest, IdcRefreshResponse, RefreshRequest, RefreshResponse,
 };
 use crate::model::config::Config;
 
+/// Token 刷新失败
+///
+/// 保留原始错误信息，便于调用方区分刷新失败与 API 调用失败
+#[derive(Debug)]
+pub struct RefreshError(pub String);
+
+impl std::fmt::Display for RefreshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for RefreshError {}
+
+/// Token 刷新熔断
+///
+/// 连续刷新失败达到阈值后，在 `retry_at` 之前不再请求刷新端点
+#[derive(Debug)]
+pub struct RefreshCircuitOpen {
+    /// 连续失败次数
+    pub failures: u32,
+    /// 允许再次尝试刷新的时间
+    pub retry_at: DateTime<Utc>,
+    /// 最近一次刷新错误
+    pub last_error: String,
+    /// 是否由本次失败触发熔断（用于只在首次熔断时告警）
+    pub just_opened: bool,
+}
+
+impl std::fmt::Display for RefreshCircuitOpen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Token 刷新已熔断（连续失败 {} 次，{} 后重试）: {}",
+            self.failures,
+            self.retry_at.to_rfc3339(),
+            self.last_error
+        )
+    }
+}
+
+impl std::error::Error for RefreshCircuitOpen {}
+
+/// 刷新端点返回的非成功状态
+#[derive(Debug)]
+struct RefreshStatusError {
+    status: u16,
+    message: String,
+}
+
+impl std::fmt::Display for RefreshStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RefreshStatusError {}
+
+/// 后台刷新的最大尝试次数
+const BACKGROUND_REFRESH_ATTEMPTS: u32 = 3;
+/// 后台刷新首次重试等待时间（秒），之后每次翻倍
+const BACKGROUND_REFRESH_RETRY_SECS: u64 = 2;
+
+/// 刷新失败是否为临时错误（网络错误、超时、限流、服务端错误），临时错误不计入熔断
+fn is_transient_refresh_error(e: &anyhow::Error) -> bool {
+    if let Some(status_error) = e.downcast_ref::<RefreshStatusError>() {
+        return status_error.status == 429 || status_error.status >= 500;
+    }
+    e.downcast_ref::<reqwest::Error>().is_some()
+        || e.downcast_ref::<tokio::time::error::Elapsed>().is_some()
+}
+
+/// Token 刷新熔断状态
+#[derive(Debug, Default)]
+struct RefreshBreaker {
+    failures: u32,
+    open_until: Option<DateTime<Utc>>,
+    last_error: String,
+}
+
+/// Token 刷新结果计数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RefreshStats {
+    pub successes: u64,
+    pub failures: u64,
+}
+
+impl RefreshStats {
+    /// 刷新成功率（尚未刷新过时返回 None）
+    pub fn success_rate(&self) -> Option<f64> {
+        let total = self.successes + self.failures;
+        (total > 0).then(|| self.successes as f64 / total as f64)
+    }
+}
+
 /// Token 管理器
 ///
 /// 负责管理凭据和 Token 的自动刷新
@@ -20,15 +120,41 @@ pub struct TokenManager {
     config: Config,
     credentials: KiroCredentials,
     proxy: Option<ProxyConfig>,
+    breaker: RefreshBreaker,
+    refresh_stats: RefreshStats,
+    /// 进行中的后台刷新任务
+    pending_refresh: Option<JoinHandle<anyhow::Result<KiroCredentials>>>,
+}
+
+impl Drop for TokenManager {
+    fn drop(&mut self) {
+        if let Some(handle) = self.pending_refresh.take() {
+            handle.abort();
+        }
+    }
 }
 
 impl TokenManager {
     /// 创建新的 TokenManager 实例
     pub fn new(config: Config, credentials: KiroCredentials, proxy: Option<ProxyConfig>) -> Self {
+        if credentials.is_static() {
+            match &credentials.expires_at {
+                Some(expires_at) => tracing::info!(
+                    "使用静态 accessToken（没有 refreshToken，不会刷新），过期时间: {}",
+                    expires_at
+                ),
+                None => tracing::info!(
+                    "使用静态 accessToken（没有 refreshToken，不会刷新），未提供过期时间，上游拒绝前一直使用"
+                ),
+            }
+        }
         Self {
             config,
             credentials,
             proxy,
+            breaker: RefreshBreaker::default(),
+            refresh_stats: RefreshStats::default(),
+            pending_refresh: None,
         }
     }
 
@@ -42,25 +168,342 @@ impl TokenManager {
         &self.config
     }
 
+    /// 获取刷新结果计数
+    pub fn refresh_stats(&self) -> RefreshStats {
+        self.refresh_stats
+    }
+
+    /// 无需刷新时返回当前 Token（只读，可在读锁下并发调用）
+    ///
+    /// 返回 None 时需调用 [`Self::ensure_valid_token`] 刷新或收集后台刷新结果
+    pub fn cached_token(&self) -> Option<String> {
+        if self.credentials.is_static() {
+            return self.static_token().ok();
+        }
+        if is_token_expired(&self.credentials) {
+            return None;
+        }
+        match &self.pending_refresh {
+            // 后台刷新已完成，需要写锁收集结果
+            Some(handle) if handle.is_finished() => return None,
+            // 后台刷新进行中，继续使用当前 Token
+            Some(_) => {}
+            // 即将过期，需要发起后台刷新
+            None if is_token_expiring_soon(&self.credentials) => return None,
+            None => {}
+        }
+        self.credentials.access_token.clone()
+    }
+
     /// 确保获取有效的访问 Token
     ///
-    /// 如果 Token 过期或即将过期，会自动刷新
-    pub async fn ensure_valid_token(&mut self) -> anyhow::Result<String> {
-        if is_token_expired(&self.credentials) || is_token_expiring_soon(&self.credentials) {
-            self.credentials =
-                refresh_token(&self.credentials, &self.config, self.proxy.as_ref()).await?;
-
-            // 刷新后再次检查 token 时间有效性
-            if is_token_expired(&self.credentials) {
-                anyhow::bail!("刷新后的 Token 仍然无效或已过期");
-            }
+    /// Token 已过期时同步刷新，单次刷新受 `refreshTimeoutSecs` 限制，调用方取消时刷新随之取消；
+    /// Token 即将过期但仍可用时直接返回当前 Token，刷新在后台进行并对临时错误重试。
+    /// 刷新后若凭证缺少 profileArn，会尝试自动发现。
+    /// 连续刷新失败达到阈值后熔断，熔断期间直接返回 [`RefreshCircuitOpen`]。
+    /// 静态 Token 不刷新，过期后返回 [`RefreshFailure::NotRefreshable`]
+    pub async fn ensure_valid_token(&mut self) -> Result<String, KiroError> {
+        if self.credentials.is_static() {
+            return self.static_token();
+        }
+        self.collect_background_refresh().await;
+
+        if is_token_expired(&self.credentials) {
+            self.refresh_now().await?;
+        } else if is_token_expiring_soon(&self.credentials) {
+            self.spawn_background_refresh();
+        }
+
+        self.credentials
+            .access_token
+            .clone()
+            .ok_or_else(|| KiroError::Validation("没有可用的 accessToken".to_string()))
+    }
+
+    /// 确保 Token 有效，凭证缺少 profileArn 时自动发现
+    ///
+    /// 与 [`Self::ensure_valid_token`] 不同，Token 仍然有效、无需刷新时也会查询 profileArn，
+    /// 用于导入或添加账号后立即补全
+    pub async fn ensure_profile_arn(&mut self) -> Result<(), KiroError> {
+        self.ensure_valid_token().await?;
+        if self.credentials.profile_arn.is_none() {
+            self.discover_profile_arn().await;
+        }
+        Ok(())
+    }
+
+    /// 采用外部（Kiro IDE）刷新后写入的凭证，返回是否采用
+    ///
+    /// 只在外部 Token 比当前 Token 晚过期时替换 accessToken、refreshToken 和过期时间，外部凭证
+    /// 缺少的字段保留当前值；IDE 刷新时可能已轮换 refreshToken，因此同时取消进行中的后台刷新
+    /// 并清除熔断状态
+    pub fn adopt_external(&mut self, external: KiroCredentials) -> bool {
+        let Some(expires) = external.expires_at.as_deref().and_then(parse_expires_at) else {
+            return false;
+        };
+        if external.access_token.as_deref().is_none_or(str::is_empty) {
+            return false;
         }
+        let current = self
+            .credentials
+            .expires_at
+            .as_deref()
+            .and_then(parse_expires_at);
+        if current.is_some_and(|current| expires <= current) {
+            return false;
+        }
+
+        if let Some(handle) = self.pending_refresh.take() {
+            handle.abort();
+        }
+        self.breaker = RefreshBreaker::default();
+        let credentials = &mut self.credentials;
+        credentials.access_token = external.access_token;
+        credentials.expires_at = external.expires_at;
+        credentials.refresh_token = external.refresh_token.or(credentials.refresh_token.take());
+        credentials.profile_arn = external.profile_arn.or(credentials.profile_arn.take());
+        credentials.auth_method = external.auth_method.or(credentials.auth_method.take());
+        credentials.client_id = external.client_id.or(credentials.client_id.take());
+        credentials.client_secret = external.client_secret.or(credentials.client_secret.take());
+        true
+    }
+
+    /// 立即刷新 Token（管理 API 手动触发）
+    ///
+    /// 无论 Token 是否过期都向刷新端点发起请求：取消进行中的后台刷新，并清除熔断状态，
+    /// 便于运营方修复凭证后立即验证。静态 Token 无法刷新，直接返回错误
+    pub async fn force_refresh(&mut self) -> Result<(), KiroError> {
+        if self.credentials.is_static() {
+            return Err(not_refreshable(&self.credentials));
+        }
+        if let Some(handle) = self.pending_refresh.take() {
+            handle.abort();
+        }
+        self.breaker = RefreshBreaker::default();
+        let result =
+            refresh_with_timeout(&self.credentials, &self.config, self.proxy.as_ref()).await;
+        self.apply_refresh_result(result).await
+    }
 
+    /// 静态 Token：过期前（不留提前刷新的余量）原样返回，缺少过期时间时视为有效
+    fn static_token(&self) -> Result<String, KiroError> {
+        if is_token_expiring_within(&self.credentials, 0).unwrap_or(false) {
+            return Err(not_refreshable(&self.credentials));
+        }
         self.credentials
             .access_token
             .clone()
-            .ok_or_else(|| anyhow::anyhow!("没有可用的 accessToken"))
+            .ok_or_else(|| KiroError::Validation("没有可用的 accessToken".to_string()))
+    }
+
+    /// 同步刷新 Token；已有后台刷新时等待其结果而不是重复发起
+    async fn refresh_now(&mut self) -> Result<(), KiroError> {
+        let timeout = std::time::Duration::from_secs(self.config.refresh_timeout_secs);
+        let result = match self.pending_refresh.as_mut() {
+            // 等待期间调用方取消时任务句柄仍保留，后续请求可继续使用其结果
+            Some(handle) => match tokio::time::timeout(timeout, handle).await {
+                Ok(joined) => {
+                    self.pending_refresh = None;
+                    joined.unwrap_or_else(|e| Err(anyhow::anyhow!("后台刷新任务异常退出: {}", e)))
+                }
+                Err(elapsed) => Err(elapsed.into()),
+            },
+            None => {
+                self.check_refresh_circuit()?;
+                refresh_with_timeout(&self.credentials, &self.config, self.proxy.as_ref()).await
+            }
+        };
+        self.apply_refresh_result(result).await
+    }
+
+    /// 在后台刷新 Token（已有后台刷新或熔断期间不重复发起）
+    fn spawn_background_refresh(&mut self) {
+        if self.pending_refresh.is_some() || self.check_refresh_circuit().is_err() {
+            return;
+        }
+        tracing::debug!("Token 即将过期，在后台刷新");
+        let credentials = self.credentials.clone();
+        let config = self.config.clone();
+        let proxy = self.proxy.clone();
+        self.pending_refresh = Some(tokio::spawn(async move {
+            refresh_with_retry(&credentials, &config, proxy.as_ref()).await
+        }));
+    }
+
+    /// 应用已完成的后台刷新结果
+    async fn collect_background_refresh(&mut self) {
+        if !self
+            .pending_refresh
+            .as_ref()
+            .is_some_and(|handle| handle.is_finished())
+        {
+            return;
+        }
+        let Some(handle) = self.pending_refresh.take() else {
+            return;
+        };
+        let result = handle
+            .await
+            .unwrap_or_else(|e| Err(anyhow::anyhow!("后台刷新任务异常退出: {}", e)));
+        if let Err(e) = self.apply_refresh_result(result).await {
+            tracing::warn!("后台 Token 刷新失败: {}", e);
+        }
+    }
+
+    /// 记录刷新结果并更新凭证
+    async fn apply_refresh_result(
+        &mut self,
+        result: anyhow::Result<KiroCredentials>,
+    ) -> Result<(), KiroError> {
+        match result {
+            Ok(credentials) => {
+                self.credentials = credentials;
+                self.breaker = RefreshBreaker::default();
+                self.refresh_stats.successes += 1;
+            }
+            Err(e) => {
+                self.refresh_stats.failures += 1;
+                return Err(self.record_refresh_failure(e));
+            }
+        }
+
+        // 刷新后再次检查 token 时间有效性
+        if is_token_expired(&self.credentials) {
+            return Err(KiroError::Protocol {
+                message: "刷新后的 Token 仍然无效或已过期".to_string(),
+                upstream: None,
+            });
+        }
+
+        if self.credentials.profile_arn.is_none() {
+            self.discover_profile_arn().await;
+        }
+        Ok(())
     }
+
+    /// 熔断期间拒绝刷新
+    fn check_refresh_circuit(&self) -> Result<(), KiroError> {
+        match self.breaker.open_until {
+            Some(until) if Utc::now() < until => Err(KiroError::Expired(
+                RefreshFailure::CircuitOpen(RefreshCircuitOpen {
+                    failures: self.breaker.failures,
+                    retry_at: until,
+                    last_error: self.breaker.last_error.clone(),
+                    just_opened: false,
+                }),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// 记录刷新失败，达到阈值时打开熔断
+    fn record_refresh_failure(&mut self, e: anyhow::Error) -> KiroError {
+        let error_msg = e.to_string();
+        let threshold = self.config.refresh_failure_threshold;
+        if threshold == 0 || is_transient_refresh_error(&e) {
+            return KiroError::Expired(RefreshFailure::Failed(RefreshError(error_msg)));
+        }
+
+        self.breaker.failures += 1;
+        self.breaker.last_error = error_msg.clone();
+        if self.breaker.failures < threshold {
+            return KiroError::Expired(RefreshFailure::Failed(RefreshError(error_msg)));
+        }
+
+        let retry_at = Utc::now() + Duration::seconds(self.config.refresh_backoff_secs as i64);
+        self.breaker.open_until = Some(retry_at);
+        tracing::warn!(
+            "Token 连续刷新失败 {} 次，暂停刷新至 {}",
+            self.breaker.failures,
+            retry_at.to_rfc3339()
+        );
+        KiroError::Expired(RefreshFailure::CircuitOpen(RefreshCircuitOpen {
+            failures: self.breaker.failures,
+            retry_at,
+            last_error: error_msg,
+            just_opened: true,
+        }))
+    }
+
+    /// 自动发现 profileArn
+    ///
+    /// 发现失败不影响 Token 使用，仅记录警告
+    async fn discover_profile_arn(&mut self) {
+        let Some(token) = self.credentials.access_token.clone() else {
+            return;
+        };
+
+        match list_available_profiles(&token, &self.credentials, &self.config, self.proxy.as_ref())
+            .await
+        {
+            Ok(Some(arn)) => {
+                tracing::info!("已自动发现 profileArn: {}", arn);
+                self.credentials.profile_arn = Some(arn);
+            }
+            Ok(None) => tracing::warn!("ListAvailableProfiles 未返回可用的 profileArn"),
+            Err(e) => tracing::warn!("自动发现 profileArn 失败: {}", e),
+        }
+    }
+}
+
+/// 静态 Token 无法刷新的错误
+fn not_refreshable(credentials: &KiroCredentials) -> KiroError {
+    let message = match &credentials.expires_at {
+        Some(expires_at) if is_token_expiring_within(credentials, 0).unwrap_or(false) => {
+            format!(
+                "静态 accessToken 已于 {} 过期，没有 refreshToken 无法刷新",
+                expires_at
+            )
+        }
+        _ => "静态 accessToken 没有 refreshToken，无法刷新".to_string(),
+    };
+    KiroError::Expired(RefreshFailure::NotRefreshable(message))
+}
+
+/// 查询账号可用的 Profile，返回第一个 profileArn
+async fn list_available_profiles(
+    token: &str,
+    credentials: &KiroCredentials,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<Option<String>> {
+    let machine_id = machine_id::generate_from_credentials(credentials, config)
+        .ok_or_else(|| anyhow::anyhow!("无法生成 machineId"))?;
+    let url = format!(
+        "https://q.{}.amazonaws.com/ListAvailableProfiles",
+        credentials.api_region(config)
+    );
+
+    let client = build_client(proxy, 60)?;
+    let body = ListAvailableProfilesRequest {
+        max_results: Some(10),
+        next_token: None,
+    };
+
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", token))
+        .header(
+            "x-amz-user-agent",
+            format!(
+                "aws-sdk-js/1.0.27 KiroIDE-{}-{}",
+                config.kiro_version, machine_id
+            ),
+        )
+        .header("Connection", "close")
+        .json(&body)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body_text = response.text().await.unwrap_or_default();
+        bail!("ListAvailableProfiles 请求失败: {} {}", status, body_text);
+    }
+
+    let data: ListAvailableProfilesResponse = response.json().await?;
+    Ok(data.first_arn())
 }
 
 /// 检查 Token 是否在指定时间内过期
@@ -68,7 +511,7 @@ fn is_token_expiring_within(credentials: &KiroCredentials, minutes: i64) -> Opti
     credentials
         .expires_at
         .as_ref()
-        .and_then(|expires_at| DateTime::parse_from_rfc3339(expires_at).ok())
+        .and_then(|expires_at| parse_expires_at(expires_at))
         .map(|expires| expires <= Utc::now() + Duration::minutes(minutes))
 }
 
@@ -105,6 +548,43 @@ fn validate_refresh_token(credentials: &KiroCredentials) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// 带超时的 Token 刷新
+async fn refresh_with_timeout(
+    credentials: &KiroCredentials,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<KiroCredentials> {
+    let timeout = std::time::Duration::from_secs(config.refresh_timeout_secs);
+    match tokio::time::timeout(timeout, refresh_token(credentials, config, proxy)).await {
+        Ok(result) => result,
+        Err(elapsed) => {
+            tracing::warn!("Token 刷新超时（{} 秒）", config.refresh_timeout_secs);
+            Err(elapsed.into())
+        }
+    }
+}
+
+/// 后台刷新，临时错误按指数退避重试
+async fn refresh_with_retry(
+    credentials: &KiroCredentials,
+    config: &Config,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<KiroCredentials> {
+    let mut delay = std::time::Duration::from_secs(BACKGROUND_REFRESH_RETRY_SECS);
+    let mut attempt = 1;
+    loop {
+        match refresh_with_timeout(credentials, config, proxy).await {
+            Err(e) if attempt < BACKGROUND_REFRESH_ATTEMPTS && is_transient_refresh_error(&e) => {
+                tracing::debug!("后台 Token 刷新失败（第 {} 次）: {}", attempt, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 /// 刷新 Token
 async fn refresh_token(
     credentials: &KiroCredentials,
@@ -122,6 +602,49 @@ async fn refresh_token(
     }
 }
 
+/// 解析 Social Token 刷新地址（账号配置 > 全局配置 > 按账号区域拼接）
+fn social_refresh_url(credentials: &KiroCredentials, config: &Config) -> String {
+    credentials
+        .refresh_url
+        .clone()
+        .or_else(|| config.social_refresh_url.clone())
+        .unwrap_or_else(|| {
+            format!(
+                "https://prod.{}.auth.desktop.kiro.dev/refreshToken",
+                credentials.api_region(config)
+            )
+        })
+}
+
+/// 解析 IdC Token 刷新地址（账号配置 > 全局配置 > 按 OIDC 区域或账号区域拼接）
+fn idc_refresh_url(credentials: &KiroCredentials, config: &Config) -> String {
+    if let Some(url) = credentials
+        .refresh_url
+        .as_ref()
+        .or(config.idc_refresh_url.as_ref())
+    {
+        return url.clone();
+    }
+    let region = credentials
+        .oidc_region
+        .as_deref()
+        .or(config.oidc_region.as_deref())
+        .unwrap_or(credentials.api_region(config));
+    format!("https://oidc.{}.amazonaws.com/token", region)
+}
+
+/// 从刷新地址中提取 Host header
+fn host_header(url: &str) -> anyhow::Result<String> {
+    let parsed = reqwest::Url::parse(url)?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("刷新地址缺少主机名: {}", url))?;
+    Ok(match parsed.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
 /// 刷新 Social Token
 async fn refresh_social_token(
     credentials: &KiroCredentials,
@@ -131,15 +654,13 @@ async fn refresh_social_token(
     tracing::info!("正在刷新 Social Token...");
 
     let refresh_token = credentials.refresh_token.as_ref().unwrap();
-    let region = &config.region;
-
-    let refresh_url = format!("https://prod.{}.auth.desktop.kiro.dev/refreshToken", region);
-    let refresh_domain = format!("prod.{}.auth.desktop.kiro.dev", region);
+    let refresh_url = social_refresh_url(credentials, config);
+    let refresh_domain = host_header(&refresh_url)?;
     let machine_id = machine_id::generate_from_credentials(credentials, config)
         .ok_or_else(|| anyhow::anyhow!("无法生成 machineId"))?;
     let kiro_version = &config.kiro_version;
 
-    let client = build_client(proxy, 60)?;
+    let client = build_client(proxy, config.refresh_timeout_secs)?;
     let body = RefreshRequest {
         refresh_token: refresh_token.to_string(),
     };
@@ -169,7 +690,11 @@ async fn refresh_social_token(
             500..=599 => "服务器错误，AWS OAuth 服务暂时不可用",
             _ => "Token 刷新失败",
         };
-        bail!("{}: {} {}", error_msg, status, body_text);
+        return Err(RefreshStatusError {
+            status: status.as_u16(),
+            message: format!("{}: {} {}", error_msg, status, body_text),
+        }
+        .into());
     }
 
     let data: RefreshResponse = response.json().await?;
@@ -215,10 +740,10 @@ async fn refresh_idc_token(
         .as_ref()
         .ok_or_else(|| anyhow::anyhow!("IdC 刷新需要 clientSecret"))?;
 
-    let region = &config.region;
-    let refresh_url = format!("https://oidc.{}.amazonaws.com/token", region);
+    let refresh_url = idc_refresh_url(credentials, config);
+    let refresh_domain = host_header(&refresh_url)?;
 
-    let client = build_client(proxy, 60)?;
+    let client = build_client(proxy, config.refresh_timeout_secs)?;
     let body = IdcRefreshRequest {
         client_id: client_id.to_string(),
         client_secret: client_secret.to_string(),
@@ -229,7 +754,7 @@ async fn refresh_idc_token(
     let response = client
         .post(&refresh_url)
         .header("Content-Type", "application/json")
-        .header("Host", format!("oidc.{}.amazonaws.com", region))
+        .header("Host", &refresh_domain)
         .header("Connection", "keep-alive")
         .header("x-amz-user-agent", IDC_AMZ_USER_AGENT)
         .header("Accept", "*/*")
@@ -251,7 +776,11 @@ async fn refresh_idc_token(
             500..=599 => "服务器错误，AWS OIDC 服务暂时不可用",
             _ => "IdC Token 刷新失败",
         };
-        bail!("{}: {} {}", error_msg, status, body_text);
+        return Err(RefreshStatusError {
+            status: status.as_u16(),
+            message: format!("{}: {} {}", error_msg, status, body_text),
+        }
+        .into());
     }
 
     let data: IdcRefreshResponse = response.json().await?;
@@ -285,27 +814,46 @@ mod tests {
 
     #[test]
     fn test_is_token_expired_with_expired_token() {
-        let mut credentials = KiroCredentials::default();
-        credentials.expires_at = Some("2020-01-01T00:00:00Z".to_string());
+        let credentials = KiroCredentials {
+            expires_at: Some("2020-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
         assert!(is_token_expired(&credentials));
     }
 
     #[test]
     fn test_is_token_expired_with_valid_token() {
-        let mut credentials = KiroCredentials::default();
         let future = Utc::now() + Duration::hours(1);
-        credentials.expires_at = Some(future.to_rfc3339());
+        let credentials = KiroCredentials {
+            expires_at: Some(future.to_rfc3339()),
+            ..Default::default()
+        };
         assert!(!is_token_expired(&credentials));
     }
 
     #[test]
     fn test_is_token_expired_within_5_minutes() {
-        let mut credentials = KiroCredentials::default();
         let expires = Utc::now() + Duration::minutes(3);
-        credentials.expires_at = Some(expires.to_rfc3339());
+        let credentials = KiroCredentials {
+            expires_at: Some(expires.to_rfc3339()),
+            ..Default::default()
+        };
         assert!(is_token_expired(&credentials));
     }
 
+    #[test]
+    fn test_is_token_expired_epoch_formats() {
+        // 环境变量或手工构造的凭证可能直接使用时间戳
+        let future = Utc::now() + Duration::hours(1);
+        for expires_at in [future.timestamp_millis(), future.timestamp()] {
+            let credentials = KiroCredentials {
+                expires_at: Some(expires_at.to_string()),
+                ..Default::default()
+            };
+            assert!(!is_token_expired(&credentials));
+        }
+    }
+
     #[test]
     fn test_is_token_expired_no_expires_at() {
         let credentials = KiroCredentials::default();
@@ -314,17 +862,21 @@ mod tests {
 
     #[test]
     fn test_is_token_expiring_soon_within_10_minutes() {
-        let mut credentials = KiroCredentials::default();
         let expires = Utc::now() + Duration::minutes(8);
-        credentials.expires_at = Some(expires.to_rfc3339());
+        let credentials = KiroCredentials {
+            expires_at: Some(expires.to_rfc3339()),
+            ..Default::default()
+        };
         assert!(is_token_expiring_soon(&credentials));
     }
 
     #[test]
     fn test_is_token_expiring_soon_beyond_10_minutes() {
-        let mut credentials = KiroCredentials::default();
         let expires = Utc::now() + Duration::minutes(15);
-        credentials.expires_at = Some(expires.to_rfc3339());
+        let credentials = KiroCredentials {
+            expires_at: Some(expires.to_rfc3339()),
+            ..Default::default()
+        };
         assert!(!is_token_expiring_soon(&credentials));
     }
 
@@ -337,9 +889,232 @@ mod tests {
 
     #[test]
     fn test_validate_refresh_token_valid() {
-        let mut credentials = KiroCredentials::default();
-        credentials.refresh_token = Some("a".repeat(150));
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..Default::default()
+        };
         let result = validate_refresh_token(&credentials);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_refresh_urls_default_to_region() {
+        let config = Config {
+            region: "eu-west-1".to_string(),
+            ..Config::default()
+        };
+        let credentials = KiroCredentials::default();
+        assert_eq!(
+            social_refresh_url(&credentials, &config),
+            "https://prod.eu-west-1.auth.desktop.kiro.dev/refreshToken"
+        );
+        assert_eq!(
+            idc_refresh_url(&credentials, &config),
+            "https://oidc.eu-west-1.amazonaws.com/token"
+        );
+    }
+
+    #[test]
+    fn test_refresh_url_overrides() {
+        let config = Config {
+            oidc_region: Some("ap-southeast-2".to_string()),
+            social_refresh_url: Some("https://mirror.example.com/refresh".to_string()),
+            ..Config::default()
+        };
+        let credentials = KiroCredentials::default();
+        assert_eq!(
+            social_refresh_url(&credentials, &config),
+            "https://mirror.example.com/refresh"
+        );
+        assert_eq!(
+            idc_refresh_url(&credentials, &config),
+            "https://oidc.ap-southeast-2.amazonaws.com/token"
+        );
+
+        // 账号级配置优先于全局配置
+        let credentials = KiroCredentials {
+            oidc_region: Some("eu-central-1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            idc_refresh_url(&credentials, &config),
+            "https://oidc.eu-central-1.amazonaws.com/token"
+        );
+        let credentials = KiroCredentials {
+            refresh_url: Some("http://127.0.0.1:9000/token".to_string()),
+            ..credentials
+        };
+        assert_eq!(
+            idc_refresh_url(&credentials, &config),
+            "http://127.0.0.1:9000/token"
+        );
+        assert_eq!(
+            host_header("http://127.0.0.1:9000/token").unwrap(),
+            "127.0.0.1:9000"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_circuit_opens_after_threshold() {
+        let config = Config {
+            refresh_failure_threshold: 2,
+            ..Config::default()
+        };
+        // 缺少 refreshToken，刷新必定失败且不会发起网络请求
+        let mut tm = TokenManager::new(config, KiroCredentials::default(), None);
+
+        let err = tm.ensure_valid_token().await.unwrap_err();
+        assert!(err.refresh_error().is_some());
+
+        let err = tm.ensure_valid_token().await.unwrap_err();
+        let circuit = err.circuit_open().unwrap();
+        assert!(circuit.just_opened);
+        assert_eq!(circuit.failures, 2);
+
+        let err = tm.ensure_valid_token().await.unwrap_err();
+        let circuit = err.circuit_open().unwrap();
+        assert!(!circuit.just_opened);
+    }
+
+    #[tokio::test]
+    async fn test_force_refresh_bypasses_open_circuit() {
+        let config = Config {
+            refresh_failure_threshold: 1,
+            ..Config::default()
+        };
+        let mut tm = TokenManager::new(config, KiroCredentials::default(), None);
+        let err = tm.ensure_valid_token().await.unwrap_err();
+        assert!(err.circuit_open().is_some());
+
+        // 手动刷新仍会实际尝试刷新，并返回本次的失败原因
+        let err = tm.force_refresh().await.unwrap_err();
+        let circuit = err.circuit_open().unwrap();
+        assert!(circuit.just_opened);
+        assert!(circuit.last_error.contains("refreshToken"));
+        assert_eq!(tm.refresh_stats().failures, 2);
+    }
+
+    #[test]
+    fn test_cached_token_requires_fresh_token() {
+        let mut credentials = KiroCredentials {
+            access_token: Some("token".to_string()),
+            refresh_token: Some("refresh".to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let tm = TokenManager::new(Config::default(), credentials.clone(), None);
+        assert_eq!(tm.cached_token().as_deref(), Some("token"));
+
+        credentials.expires_at = Some((Utc::now() + Duration::minutes(8)).to_rfc3339());
+        let tm = TokenManager::new(Config::default(), credentials, None);
+        assert!(tm.cached_token().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expiring_token_refreshes_in_background() {
+        let credentials = KiroCredentials {
+            access_token: Some("current".to_string()),
+            refresh_token: Some("truncated...".to_string()),
+            expires_at: Some((Utc::now() + Duration::minutes(8)).to_rfc3339()),
+            ..Default::default()
+        };
+        // refreshToken 已被截断，后台刷新必定失败且不会重试
+        let mut tm = TokenManager::new(Config::default(), credentials, None);
+
+        assert_eq!(tm.ensure_valid_token().await.unwrap(), "current");
+        assert!(tm.pending_refresh.is_some());
+
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(tm.ensure_valid_token().await.unwrap(), "current");
+        assert_eq!(tm.refresh_stats().failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_hung_refresh_times_out() {
+        // 只接受连接、从不响应的刷新端点
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut sockets = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let config = Config {
+            refresh_timeout_secs: 1,
+            refresh_failure_threshold: 1,
+            ..Config::default()
+        };
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            refresh_url: Some(format!("http://{}/refreshToken", addr)),
+            ..Default::default()
+        };
+        let mut tm = TokenManager::new(config, credentials, None);
+
+        let started = std::time::Instant::now();
+        let err = tm.ensure_valid_token().await.unwrap_err();
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        assert!(err.refresh_error().is_some());
+        // 超时属于临时错误，不计入熔断
+        assert!(tm.check_refresh_circuit().is_ok());
+    }
+
+    #[test]
+    fn test_transient_refresh_errors_do_not_trip_circuit() {
+        let config = Config {
+            refresh_failure_threshold: 1,
+            ..Config::default()
+        };
+        let mut tm = TokenManager::new(config, KiroCredentials::default(), None);
+
+        for status in [429, 503] {
+            let err = tm.record_refresh_failure(
+                RefreshStatusError {
+                    status,
+                    message: "error".to_string(),
+                }
+                .into(),
+            );
+            assert!(err.refresh_error().is_some());
+        }
+        assert!(tm.check_refresh_circuit().is_ok());
+
+        let err = tm.record_refresh_failure(
+            RefreshStatusError {
+                status: 400,
+                message: "invalid_grant".to_string(),
+            }
+            .into(),
+        );
+        assert!(err.circuit_open().is_some());
+        assert!(tm.check_refresh_circuit().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_static_token_is_not_refreshed() {
+        let credentials = KiroCredentials {
+            access_token: Some("static-token".to_string()),
+            ..Default::default()
+        };
+        assert!(credentials.is_static());
+        let mut tm = TokenManager::new(Config::default(), credentials, None);
+
+        // 缺少过期时间时一直可用，即将过期也不发起刷新
+        assert_eq!(tm.cached_token().as_deref(), Some("static-token"));
+        tm.credentials.expires_at = Some((Utc::now() + Duration::minutes(3)).to_rfc3339());
+        assert_eq!(tm.ensure_valid_token().await.unwrap(), "static-token");
+        assert!(tm.pending_refresh.is_none());
+
+        let err = tm.force_refresh().await.unwrap_err();
+        assert!(err.is_not_refreshable());
+
+        tm.credentials.expires_at = Some("2020-01-01T00:00:00Z".to_string());
+        assert!(tm.cached_token().is_none());
+        let err = tm.ensure_valid_token().await.unwrap_err();
+        assert!(err.is_not_refreshable());
+        assert!(err.to_string().contains("2020-01-01T00:00:00Z"));
+        assert_eq!(tm.refresh_stats(), RefreshStats::default());
+    }
 }
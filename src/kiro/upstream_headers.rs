@@ -0,0 +1,97 @@
+//! 上游响应头采集
+//!
+//! 从 Kiro 响应中提取排查问题有用的响应头（请求 ID、限流提示、服务端耗时），
+//! 写入调试日志，并以 `x-upstream-*` 响应头返回给客户端，便于向上游反馈问题时附上 AWS 请求 ID。
+
+use std::fmt;
+
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// 采集的上游响应头及对应的客户端响应头，同一客户端响应头只取第一个出现的值
+const CAPTURED: &[(&str, &str)] = &[
+    ("x-amzn-requestid", "x-upstream-request-id"),
+    ("x-amz-request-id", "x-upstream-request-id"),
+    ("x-amzn-trace-id", "x-upstream-trace-id"),
+    ("x-amz-cf-id", "x-upstream-cf-id"),
+    ("x-amzn-errortype", "x-upstream-error-type"),
+    ("retry-after", "x-upstream-retry-after"),
+    ("server-timing", "x-upstream-server-timing"),
+];
+
+/// 采集到的上游响应头（客户端响应头名称, 值）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpstreamHeaders(Vec<(&'static str, HeaderValue)>);
+
+impl UpstreamHeaders {
+    /// 从上游响应头中采集
+    pub fn capture(headers: &HeaderMap) -> Self {
+        let mut captured: Vec<(&'static str, HeaderValue)> = Vec::new();
+        for (upstream, name) in CAPTURED {
+            if captured.iter().any(|(n, _)| n == name) {
+                continue;
+            }
+            if let Some(value) = headers.get(*upstream) {
+                captured.push((name, value.clone()));
+            }
+        }
+        Self(captured)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 上游请求 ID
+    pub fn request_id(&self) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(name, _)| *name == "x-upstream-request-id")
+            .and_then(|(_, value)| value.to_str().ok())
+    }
+
+    /// 写入客户端响应头
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        for (name, value) in &self.0 {
+            headers.insert(HeaderName::from_static(name), value.clone());
+        }
+    }
+}
+
+impl fmt::Display for UpstreamHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, value)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            let name = name.trim_start_matches("x-upstream-");
+            write!(f, "{}={}", name, value.to_str().unwrap_or("<binary>"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_and_apply() {
+        let mut upstream = HeaderMap::new();
+        upstream.insert("x-amzn-requestid", HeaderValue::from_static("req-1"));
+        upstream.insert("x-amz-request-id", HeaderValue::from_static("req-2"));
+        upstream.insert("retry-after", HeaderValue::from_static("3"));
+        upstream.insert("content-type", HeaderValue::from_static("text/plain"));
+
+        let captured = UpstreamHeaders::capture(&upstream);
+        assert_eq!(captured.request_id(), Some("req-1"));
+        assert_eq!(captured.to_string(), "request-id=req-1, retry-after=3");
+
+        let mut response = HeaderMap::new();
+        captured.apply(&mut response);
+        assert_eq!(response.len(), 2);
+        assert_eq!(response["x-upstream-request-id"], "req-1");
+        assert_eq!(response["x-upstream-retry-after"], "3");
+
+        assert!(UpstreamHeaders::capture(&HeaderMap::new()).is_empty());
+    }
+}
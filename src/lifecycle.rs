@@ -0,0 +1,127 @@
+//! 进程生命周期管理
+//!
+//! 各子系统在启动时注册异步退出钩子（如账号池持久化），收到退出信号并停止接受新连接后
+//! 按注册顺序依次执行。单个钩子超时或出错不影响后续钩子。
+
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+
+/// 单个退出钩子的最长执行时间
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Hook = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// 退出钩子注册表
+pub struct Lifecycle {
+    hooks: Mutex<Vec<(String, Hook)>>,
+    timeout: Duration,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self {
+            hooks: Mutex::new(Vec::new()),
+            timeout: HOOK_TIMEOUT,
+        }
+    }
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册退出钩子，`name` 用于日志
+    pub fn on_shutdown<F, Fut>(&self, name: impl Into<String>, hook: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let hook: Hook = Box::new(move || Box::pin(hook()));
+        self.hooks.lock().unwrap().push((name.into(), hook));
+    }
+
+    /// 按注册顺序执行全部退出钩子，每个钩子只执行一次
+    pub async fn shutdown(&self) {
+        let hooks = std::mem::take(&mut *self.hooks.lock().unwrap());
+        for (name, hook) in hooks {
+            tracing::debug!("执行退出钩子: {}", name);
+            if tokio::time::timeout(self.timeout, hook()).await.is_err() {
+                tracing::warn!("退出钩子 {} 超时（{:?}），已跳过", name, self.timeout);
+            }
+        }
+    }
+}
+
+/// 等待退出信号（Ctrl+C，Unix 下还包括 SIGTERM）
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("监听 Ctrl+C 信号失败: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("监听 SIGTERM 信号失败: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_hooks_run_in_order_once() {
+        let lifecycle = Lifecycle::new();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        for name in ["pool", "usage", "metrics"] {
+            let order = order.clone();
+            lifecycle.on_shutdown(name, move || async move {
+                order.lock().unwrap().push(name);
+            });
+        }
+
+        lifecycle.shutdown().await;
+        lifecycle.shutdown().await;
+        assert_eq!(*order.lock().unwrap(), vec!["pool", "usage", "metrics"]);
+    }
+
+    #[tokio::test]
+    async fn test_slow_hook_does_not_block_others() {
+        let lifecycle = Lifecycle {
+            timeout: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let done = Arc::new(Mutex::new(false));
+        lifecycle.on_shutdown("slow", std::future::pending::<()>);
+        let flag = done.clone();
+        lifecycle.on_shutdown("fast", move || async move {
+            *flag.lock().unwrap() = true;
+        });
+
+        lifecycle.shutdown().await;
+        assert!(*done.lock().unwrap());
+    }
+}
@@ -1,63 +1,119 @@
+// 不编译管理面板时，账号池和模板中仅供管理 API 使用的方法未被引用
+#![cfg_attr(not(feature = "admin-ui"), allow(dead_code, unused_imports))]
+
 mod anthropic;
 mod http_client;
 mod kiro;
+mod lifecycle;
 mod model;
 mod pool;
+mod storage;
+#[cfg(test)]
+mod testing;
 pub mod token;
+#[cfg(feature = "admin-ui")]
 mod ui;
 
 use std::sync::Arc;
 use std::time::Instant;
 
+use axum::serve::ListenerExt;
 use axum::Router;
 use clap::Parser;
 use kiro::model::credentials::KiroCredentials;
 use kiro::provider::KiroProvider;
 use kiro::token_manager::TokenManager;
-use model::arg::Args;
+use lifecycle::Lifecycle;
+use model::arg::{Args, Command, SnapshotAction};
 use model::config::Config;
-use pool::{Account, AccountPool};
+use pool::{Account, AccountPool, Workspace};
 
 #[tokio::main]
 async fn main() {
     // 解析命令行参数
     let args = Args::parse();
 
-    // 初始化日志
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive(tracing::Level::INFO.into()),
-        )
-        .init();
+    // 初始化日志（request 和 doctor 子命令的标准输出用于输出结果，日志写到标准错误）
+    let subscriber = tracing_subscriber::fmt().with_env_filter(
+        tracing_subscriber::EnvFilter::from_default_env()
+            .add_directive(tracing::Level::INFO.into()),
+    );
+    if matches!(
+        args.command,
+        Some(Command::Request { .. } | Command::Doctor)
+    ) {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
+    anthropic::install_panic_hook();
 
     // 加载配置
     let config_path = args
         .config
         .clone()
         .unwrap_or_else(|| Config::default_config_path().to_string());
-    let mut config = Config::load(&config_path).unwrap_or_else(|e| {
-        tracing::warn!("加载配置文件失败: {}, 使用默认配置", e);
-        Config::default()
-    });
+    let profiles = args.profiles();
+    let mut config = match Config::load(&config_path, &profiles) {
+        Ok(config) => config,
+        // 显式指定 profile 时不能退回默认配置，否则会以错误的配置部署
+        Err(e) if !profiles.is_empty() => {
+            tracing::error!("加载配置 profile {:?} 失败: {}", profiles, e);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            tracing::warn!("加载配置文件失败: {}, 使用默认配置", e);
+            Config::default()
+        }
+    };
+    if !profiles.is_empty() {
+        tracing::info!("已应用配置 profile: {}", profiles.join(", "));
+    }
 
     // 从环境变量覆盖配置
     config.override_from_env();
 
+    // 校验配置，一次性列出所有问题（request 和 doctor 子命令不启动服务，不需要 apiKey）
+    let mut issues = config.validate();
+    if matches!(
+        args.command,
+        Some(Command::Request { .. } | Command::Doctor)
+    ) {
+        issues.retain(|issue| issue.field != "apiKey");
+    }
+    if !model::validation::report_issues("配置", &issues) {
+        std::process::exit(1);
+    }
+
+    if config.chaos.is_some() {
+        if kiro::chaos::SUPPORTED {
+            tracing::warn!("已启用故障注入（chaos），上游请求将按配置随机失败，请勿用于生产环境");
+        } else {
+            tracing::warn!("故障注入仅在 debug 构建中生效，已忽略 chaos 配置");
+        }
+    }
+
+    if let Some(Command::Snapshot { action }) = &args.command {
+        std::process::exit(run_snapshot_command(action, &config).await);
+    }
+    if let Some(Command::Request { file, stream }) = &args.command {
+        std::process::exit(run_request_command(&args, &config, file.as_deref(), *stream).await);
+    }
+    if let Some(Command::Doctor) = &args.command {
+        std::process::exit(run_doctor_command(&args, &config).await);
+    }
+
     // 获取 API Key
     let api_key = config.api_key.clone().unwrap_or_else(|| {
         tracing::error!("配置文件中未设置 apiKey");
         std::process::exit(1);
     });
 
+    // 传输层配置需在构建任何 HTTP Client 之前设置
+    http_client::configure_transport(http_client::TransportConfig::from(&config));
+
     // 构建代理配置
-    let proxy_config = config.proxy_url.as_ref().map(|url| {
-        let mut proxy = http_client::ProxyConfig::new(url);
-        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
-            proxy = proxy.with_auth(username, password);
-        }
-        proxy
-    });
+    let proxy_config = http_client::ProxyConfig::from_config(&config);
 
     if proxy_config.is_some() {
         tracing::info!("已配置 HTTP 代理: {}", config.proxy_url.as_ref().unwrap());
@@ -68,38 +124,159 @@ async fn main() {
         .map(|v| v == "true" || v == "1")
         .unwrap_or(false);
 
-    let app = if pool_mode {
+    // 子系统在创建时注册退出钩子
+    let lifecycle = Lifecycle::new();
+
+    let routers = if pool_mode {
         tracing::info!("启用账号池模式");
-        create_pool_mode_app(&config, &api_key, proxy_config).await
+        create_pool_mode_app(&config, &api_key, proxy_config, &lifecycle).await
     } else {
         tracing::info!("启用单账号模式");
         create_single_mode_app(&args, &config, &api_key, proxy_config).await
     };
 
     // 启动服务器
-    let addr = format!("{}:{}", config.host, config.port);
-    tracing::info!("启动 Anthropic API 端点: {}", addr);
     tracing::info!("API Key: {}***", &api_key[..(api_key.len() / 2).min(10)]);
+    if let (Some(_), Some(expires_at)) = (
+        &config.previous_api_key,
+        config.previous_api_key_expires_at,
+    ) {
+        tracing::info!("旧 API Key 在 {} 之前仍然有效", expires_at.to_rfc3339());
+    }
+    let prefix = anthropic::prefix::normalize(&config.path_prefix);
     tracing::info!("可用 API:");
-    tracing::info!("  GET  /v1/models");
-    tracing::info!("  POST /v1/messages");
-    tracing::info!("  POST /v1/messages/count_tokens");
-    if pool_mode {
-        tracing::info!("管理面板: http://{}/", addr);
+    tracing::info!("  GET  {}/v1/models", prefix);
+    tracing::info!("  POST {}/v1/messages", prefix);
+    tracing::info!("  POST {}/v1/messages/count_tokens", prefix);
+
+    let mut servers = tokio::task::JoinSet::new();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    for listener_config in config.effective_listeners() {
+        let addr = listener_config.addr();
+        let routes = listener_config.routes;
+
+        let mut app = Router::new();
+        if routes.includes_api() {
+            tracing::info!("启动 Anthropic API 端点: {}", addr);
+            app = app.merge(routers.api.clone());
+            #[cfg(feature = "admin-ui")]
+            if config.playground {
+                tracing::info!("Playground: http://{}{}/playground", addr, prefix);
+                app = app.merge(ui::create_playground_router(&prefix));
+            }
+        }
+        if routes.includes_admin() {
+            match &routers.admin {
+                Some(admin) => {
+                    tracing::info!("管理面板: http://{}{}/", addr, prefix);
+                    app = app.merge(admin.clone());
+                }
+                None if !routes.includes_api() => {
+                    tracing::warn!("单账号模式或未编译管理面板，跳过监听器: {}", addr);
+                    continue;
+                }
+                None => {}
+            }
+        }
+
+        let app = app.layer(anthropic::catch_panic_layer());
+
+        let listener = tokio::net::TcpListener::bind(&addr)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("绑定监听地址 {} 失败: {}", addr, e);
+                std::process::exit(1);
+            });
+        let tcp_nodelay = config.sse.tcp_nodelay;
+        let mut shutdown_rx = shutdown_rx.clone();
+        servers.spawn(async move {
+            let shutdown = async move {
+                let _ = shutdown_rx.changed().await;
+            };
+            let result = if tcp_nodelay {
+                let listener = listener.tap_io(|tcp| {
+                    if let Err(e) = tcp.set_nodelay(true) {
+                        tracing::warn!("设置 TCP_NODELAY 失败: {}", e);
+                    }
+                });
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            } else {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            };
+            if let Err(e) = result {
+                tracing::error!("监听器 {} 异常退出: {}", addr, e);
+            }
+        });
     }
 
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    if servers.is_empty() {
+        tracing::error!("没有可用的监听器");
+        std::process::exit(1);
+    }
+
+    // 任一监听器退出即结束进程；收到退出信号时停止接受新连接，等待进行中的请求完成
+    tokio::select! {
+        _ = servers.join_next() => {}
+        _ = lifecycle::shutdown_signal() => {
+            tracing::info!("收到退出信号，停止接受新连接");
+            let _ = shutdown_tx.send(true);
+            let drain = async { while servers.join_next().await.is_some() {} };
+            if tokio::time::timeout(SHUTDOWN_GRACE_PERIOD, drain).await.is_err() {
+                tracing::warn!("等待进行中的请求超时，强制退出");
+            }
+        }
+    }
+
+    lifecycle.shutdown().await;
+    tracing::info!("已退出");
 }
 
-/// 创建单账号模式应用
-async fn create_single_mode_app(
+/// 收到退出信号后等待进行中请求的最长时间
+const SHUTDOWN_GRACE_PERIOD: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// 应用路由集合
+struct AppRouters {
+    /// Anthropic API 路由
+    api: Router,
+    /// 管理面板路由（仅账号池模式，且启用 `admin-ui` feature）
+    admin: Option<Router>,
+}
+
+/// 加载单账号凭证并创建 KiroProvider，同时初始化 count_tokens 配置
+fn create_single_provider(
     args: &Args,
     config: &Config,
-    api_key: &str,
     proxy_config: Option<http_client::ProxyConfig>,
-) -> Router {
-    // 加载凭证（优先环境变量）
+) -> (KiroProvider, KiroCredentials) {
+    let credentials = load_single_credentials(args);
+
+    if !model::validation::report_issues("凭证", &credentials.validate()) {
+        std::process::exit(1);
+    }
+
+    // 创建 KiroProvider
+    let token_manager =
+        TokenManager::new(config.clone(), credentials.clone(), proxy_config.clone());
+    let kiro_provider = KiroProvider::with_proxy(token_manager, proxy_config.clone());
+
+    // 初始化 count_tokens 配置
+    token::init_config(token::CountTokensConfig {
+        api_url: config.count_tokens_api_url.clone(),
+        api_key: config.count_tokens_api_key.clone(),
+        auth_type: config.count_tokens_auth_type.clone(),
+        cache_size: config.count_tokens_cache_size,
+        proxy: proxy_config,
+    });
+
+    (kiro_provider, credentials)
+}
+
+/// 加载单账号凭证（优先环境变量），失败时退出
+fn load_single_credentials(args: &Args) -> KiroCredentials {
     let credentials_path = args
         .credentials
         .clone()
@@ -108,28 +285,161 @@ async fn create_single_mode_app(
         KiroCredentials::load_with_env_fallback(&credentials_path).unwrap_or_else(|e| {
             tracing::error!("加载凭证失败: {}", e);
             tracing::error!(
-                "请设置环境变量 (REFRESH_TOKEN, AUTH_METHOD) 或提供 credentials.json 文件"
+                "请设置环境变量 (REFRESH_TOKEN, AUTH_METHOD 或仅 ACCESS_TOKEN) 或提供 credentials.json 文件"
             );
             std::process::exit(1);
         });
 
     tracing::debug!("凭证已加载: {:?}", credentials);
+    credentials
+}
 
-    // 创建 KiroProvider
-    let token_manager =
-        TokenManager::new(config.clone(), credentials.clone(), proxy_config.clone());
-    let kiro_provider = KiroProvider::with_proxy(token_manager, proxy_config.clone());
+/// 创建单账号模式应用
+async fn create_single_mode_app(
+    args: &Args,
+    config: &Config,
+    api_key: &str,
+    proxy_config: Option<http_client::ProxyConfig>,
+) -> AppRouters {
+    let (kiro_provider, credentials) = create_single_provider(args, config, proxy_config);
 
-    // 初始化 count_tokens 配置
-    token::init_config(token::CountTokensConfig {
-        api_url: config.count_tokens_api_url.clone(),
-        api_key: config.count_tokens_api_key.clone(),
-        auth_type: config.count_tokens_auth_type.clone(),
-        proxy: proxy_config,
-    });
+    if !config.workspaces.is_empty() {
+        tracing::warn!("单账号模式不支持工作区，已忽略 workspaces 配置");
+    }
+
+    // 同步 Kiro IDE 刷新后写入 SSO 缓存的 Token
+    if config.ide_sync {
+        match kiro::ide_sync::token_file(config) {
+            Some(path) => kiro::ide_sync::spawn(
+                kiro_provider.token_manager(),
+                path,
+                std::time::Duration::from_secs(config.ide_sync_interval_secs.max(1)),
+            ),
+            None => {
+                tracing::warn!("无法确定用户主目录，请通过 ideTokenFile 指定 Kiro IDE Token 文件")
+            }
+        }
+    }
 
     // 构建路由
-    anthropic::create_router_with_provider(api_key, Some(kiro_provider), credentials.profile_arn)
+    let api = anthropic::create_router_with_provider(
+        api_key,
+        Some(kiro_provider),
+        credentials.profile_arn,
+        config,
+    );
+
+    AppRouters { api, admin: None }
+}
+
+/// 执行单次请求命令，返回进程退出码
+async fn run_request_command(
+    args: &Args,
+    config: &Config,
+    file: Option<&std::path::Path>,
+    stream: bool,
+) -> i32 {
+    let body = match file.filter(|f| f.as_os_str() != "-") {
+        Some(file) => std::fs::read(file),
+        None => {
+            let mut body = Vec::new();
+            std::io::Read::read_to_end(&mut std::io::stdin(), &mut body).map(|_| body)
+        }
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("读取请求文件失败: {}", e);
+            return 1;
+        }
+    };
+
+    http_client::configure_transport(http_client::TransportConfig::from(config));
+    let proxy_config = http_client::ProxyConfig::from_config(config);
+    let (provider, credentials) = create_single_provider(args, config, proxy_config);
+
+    match anthropic::run_request(provider, credentials.profile_arn, config, &body, stream).await {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            tracing::error!("请求失败: {}", e);
+            1
+        }
+    }
+}
+
+/// 执行出站链路诊断，将报告写到标准输出，返回进程退出码
+///
+/// 凭证校验问题作为报告中的一项检查，不会中止诊断
+async fn run_doctor_command(args: &Args, config: &Config) -> i32 {
+    http_client::configure_transport(http_client::TransportConfig::from(config));
+    let proxy_config = http_client::ProxyConfig::from_config(config);
+    let credentials = load_single_credentials(args);
+
+    let report = kiro::doctor::run(config, credentials, proxy_config).await;
+    match serde_json::to_string_pretty(&report) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            tracing::error!("序列化诊断报告失败: {}", e);
+            return 1;
+        }
+    }
+    if report.success {
+        0
+    } else {
+        1
+    }
+}
+
+/// 获取数据目录（默认 ./data）
+fn data_dir() -> std::path::PathBuf {
+    std::env::var("DATA_DIR")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|_| std::path::PathBuf::from("./data"))
+}
+
+/// 执行快照命令，返回进程退出码
+async fn run_snapshot_command(action: &SnapshotAction, config: &Config) -> i32 {
+    // 离线操作数据目录：不启用预写日志、使用记录采集和 Webhook，避免影响正在运行的服务
+    let config = Config {
+        request_journal: false,
+        usage_collector_url: None,
+        webhook_urls: Vec::new(),
+        ..config.clone()
+    };
+    let pool = AccountPool::with_data_dir(config, None, data_dir());
+
+    let result = match action {
+        SnapshotAction::Save { file } => save_snapshot(&pool, file).await,
+        SnapshotAction::Restore { file } => restore_snapshot(&pool, file).await,
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            tracing::error!("快照操作失败: {}", e);
+            1
+        }
+    }
+}
+
+async fn save_snapshot(pool: &AccountPool, file: &std::path::Path) -> anyhow::Result<()> {
+    pool.load_persisted().await;
+    let snapshot = pool.snapshot().await;
+    tokio::fs::write(file, serde_json::to_string_pretty(&snapshot)?).await?;
+    tracing::info!(
+        "已保存 {} 个账号的快照到 {:?}（包含账号凭证，请妥善保管）",
+        snapshot.account_count(),
+        file
+    );
+    Ok(())
+}
+
+async fn restore_snapshot(pool: &AccountPool, file: &std::path::Path) -> anyhow::Result<()> {
+    let content = tokio::fs::read_to_string(file).await?;
+    let snapshot: pool::snapshot::PoolSnapshot = serde_json::from_str(&content)?;
+    let count = pool.restore(snapshot).await?;
+    tracing::info!("已从 {:?} 恢复 {} 个账号", file, count);
+    Ok(())
 }
 
 /// 创建账号池模式应用
@@ -137,34 +447,39 @@ async fn create_pool_mode_app(
     config: &Config,
     api_key: &str,
     proxy_config: Option<http_client::ProxyConfig>,
-) -> Router {
-    // 获取数据目录（默认 ./data）
-    let data_dir = std::env::var("DATA_DIR")
-        .map(std::path::PathBuf::from)
-        .unwrap_or_else(|_| std::path::PathBuf::from("./data"));
-
+    lifecycle: &Lifecycle,
+) -> AppRouters {
+    let data_dir = data_dir();
     tracing::info!("数据存储目录: {:?}", data_dir);
+    if config.ide_sync {
+        tracing::warn!("账号池模式不支持 ideSync，已忽略");
+    }
 
     // 创建账号池（带持久化）
     let pool = Arc::new(AccountPool::with_data_dir(
         config.clone(),
         proxy_config.clone(),
-        data_dir,
+        data_dir.clone(),
     ));
 
-    // 从文件加载已保存的账号
-    if let Err(e) = pool.load_from_file().await {
-        tracing::warn!("加载账号文件失败: {}", e);
-    }
-
-    // 从文件加载请求记录
-    if let Err(e) = pool.load_logs_from_file().await {
-        tracing::warn!("加载请求记录失败: {}", e);
-    }
+    // 从文件加载已保存的账号、请求记录和配额缓存
+    pool.load_persisted().await;
+    pool.spawn_state_sync();
+    pool.spawn_log_flush();
 
-    // 从文件加载配额缓存
-    if let Err(e) = pool.load_usage_cache().await {
-        tracing::warn!("加载配额缓存失败: {}", e);
+    // 从 ACCOUNTS_JSON 导入账号：只添加池中尚不存在的账号，已持久化的账号状态优先
+    if let Ok(value) = std::env::var(pool::import::ACCOUNTS_JSON_ENV) {
+        let imported = match pool::import::parse_accounts_json(&value) {
+            Ok(accounts) => pool.add_missing_accounts(accounts).await,
+            Err(e) => Err(e),
+        };
+        match imported {
+            Ok(count) => tracing::info!("已从 ACCOUNTS_JSON 导入 {} 个新账号", count),
+            Err(e) => {
+                tracing::error!("导入 ACCOUNTS_JSON 失败: {}", e);
+                std::process::exit(1);
+            }
+        }
     }
 
     // 尝试从环境变量加载初始账号（如果池中没有账号）
@@ -183,26 +498,83 @@ async fn create_pool_mode_app(
         }
     }
 
+    // 加载多租户工作区（每个工作区独立的账号池）
+    let mut workspaces = Vec::new();
+    for workspace_config in &config.workspaces {
+        workspaces
+            .push(Workspace::load(workspace_config, config, proxy_config.clone(), &data_dir).await);
+    }
+    if !workspaces.is_empty() {
+        tracing::info!("已加载 {} 个工作区", workspaces.len());
+    }
+
+    // 退出前保存账号池状态
+    let pools = std::iter::once(("默认", pool.clone()))
+        .chain(workspaces.iter().map(|ws| (ws.name.as_str(), ws.pool.clone())));
+    for (name, pool) in pools {
+        lifecycle.on_shutdown(format!("账号池持久化 ({})", name), move || async move {
+            pool.persist().await;
+        });
+    }
+
     // 初始化 count_tokens 配置
     token::init_config(token::CountTokensConfig {
         api_url: config.count_tokens_api_url.clone(),
         api_key: config.count_tokens_api_key.clone(),
         auth_type: config.count_tokens_auth_type.clone(),
+        cache_size: config.count_tokens_cache_size,
         proxy: proxy_config,
     });
 
-    // 创建 UI 状态
-    let ui_state = ui::UiState {
+    // 提示词模板（配置文件 + 管理 API 保存的模板）
+    let templates = Arc::new(
+        anthropic::TemplateStore::load(config.prompt_templates.clone(), pool.storage()).await,
+    );
+
+    // 托管 API Key（管理 API 签发），退出前保存最近使用时间
+    let api_keys = Arc::new(
+        anthropic::ApiKeyStore::load(pool.storage())
+            .await
+            .with_rate_limiter(storage::rate_limiter(config))
+            .with_recent_requests(config.recent_requests_per_key),
+    );
+    api_keys.spawn_sync(config);
+    let store = api_keys.clone();
+    lifecycle.on_shutdown("托管 API Key 持久化", move || async move {
+        store.persist().await;
+    });
+
+    // 会话缓存（管理 API 可查看和清除）
+    let conversations = Arc::new(anthropic::ConversationCache::from(config));
+
+    // 创建管理面板路由
+    #[cfg(feature = "admin-ui")]
+    let admin = Some(ui::create_ui_router(ui::UiState {
         pool: pool.clone(),
         start_time: Instant::now(),
         version: env!("CARGO_PKG_VERSION").to_string(),
         api_key: api_key.to_string(),
-    };
+        previous_api_key: anthropic::PreviousApiKey::from_config(config),
+        workspaces: workspaces.clone(),
+        templates: templates.clone(),
+        api_keys: api_keys.clone(),
+        conversations: conversations.clone(),
+        path_prefix: config.path_prefix.clone(),
+    }));
+    #[cfg(not(feature = "admin-ui"))]
+    let admin = None;
 
-    // 构建路由：API + UI
-    let api_router = anthropic::create_router_with_pool(api_key, pool);
-    let ui_router = ui::create_ui_router(ui_state);
-
-    // 合并路由
-    Router::new().merge(api_router).merge(ui_router)
+    // 构建路由：API + UI（由监听器配置决定挂载位置）
+    AppRouters {
+        api: anthropic::create_router_with_pool(
+            api_key,
+            pool,
+            workspaces,
+            templates,
+            api_keys,
+            conversations,
+            config,
+        ),
+        admin,
+    }
 }
@@ -1,4 +1,6 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
 
 /// Anthropic <-> Kiro API 客户端
 #[derive(Parser, Debug)]
@@ -11,4 +13,64 @@ pub struct Args {
     /// 凭证文件路径
     #[arg(long)]
     pub credentials: Option<String>,
+
+    /// 部署 profile，多个以逗号分隔，按顺序叠加（也可通过 CONFIG_PROFILE 环境变量指定）
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// 选中的部署 profile（命令行优先于 CONFIG_PROFILE 环境变量）
+    pub fn profiles(&self) -> Vec<String> {
+        self.profile
+            .clone()
+            .or_else(|| std::env::var("CONFIG_PROFILE").ok())
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// 子命令（不指定时启动服务）
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 账号池状态快照（离线读写数据目录，恢复前请先停止服务）
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    /// 不启动服务，直接转换并发送一次 Messages 请求，将响应写到标准输出（使用单账号凭证）
+    Request {
+        /// Anthropic MessagesRequest JSON 文件，省略或为 `-` 时从标准输入读取
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+        /// 按流式请求处理，输出 SSE 事件
+        #[arg(long)]
+        stream: bool,
+    },
+    /// 检查出站链路（DNS、代理、TLS、Token、请求头、生成请求），输出可附在问题反馈中的 JSON 报告
+    Doctor,
+}
+
+/// 快照操作
+#[derive(Subcommand, Debug)]
+pub enum SnapshotAction {
+    /// 将数据目录中的账号池状态保存为快照文件
+    Save {
+        /// 快照文件路径
+        file: PathBuf,
+    },
+    /// 从快照文件恢复账号池状态（替换数据目录中的全部账号）
+    Restore {
+        /// 快照文件路径
+        file: PathBuf,
+    },
 }
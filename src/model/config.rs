@@ -1,4 +1,6 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::path::Path;
@@ -16,6 +18,22 @@ pub struct Config {
     #[serde(default = "default_region")]
     pub region: String,
 
+    /// IdC 刷新使用的 OIDC 区域（可选，默认与 region 相同）
+    #[serde(default)]
+    pub oidc_region: Option<String>,
+
+    /// Social Token 刷新地址（可选，用于企业镜像）
+    #[serde(default)]
+    pub social_refresh_url: Option<String>,
+
+    /// IdC Token 刷新地址（可选，设置后忽略 oidcRegion）
+    #[serde(default)]
+    pub idc_refresh_url: Option<String>,
+
+    /// Kiro API 地址（可选，用于企业镜像或本地模拟服务，设置后忽略 region）
+    #[serde(default)]
+    pub kiro_api_url: Option<String>,
+
     #[serde(default = "default_kiro_version")]
     pub kiro_version: String,
 
@@ -25,6 +43,14 @@ pub struct Config {
     #[serde(default)]
     pub api_key: Option<String>,
 
+    /// 轮换前的旧 API Key（可选，在失效时间之前与 apiKey 同时有效）
+    #[serde(default)]
+    pub previous_api_key: Option<String>,
+
+    /// 旧 API Key 的失效时间（RFC 3339）
+    #[serde(default)]
+    pub previous_api_key_expires_at: Option<DateTime<Utc>>,
+
     #[serde(default = "default_system_version")]
     pub system_version: String,
 
@@ -43,6 +69,10 @@ pub struct Config {
     #[serde(default = "default_count_tokens_auth_type")]
     pub count_tokens_auth_type: String,
 
+    /// count_tokens 结果缓存条数（按请求内容哈希的 LRU 缓存，0 表示禁用）
+    #[serde(default = "default_count_tokens_cache_size")]
+    pub count_tokens_cache_size: usize,
+
     /// HTTP 代理地址（可选）
     /// 支持格式: http://host:port, https://host:port, socks5://host:port
     #[serde(default)]
@@ -55,6 +85,956 @@ pub struct Config {
     /// 代理认证密码（可选）
     #[serde(default)]
     pub proxy_password: Option<String>,
+
+    /// 上游 HTTP 协议版本（"auto"、"http1"、"http2"）
+    #[serde(default)]
+    pub http_version: HttpVersion,
+
+    /// 是否启用 TCP_NODELAY
+    #[serde(default = "default_tcp_nodelay")]
+    pub tcp_nodelay: bool,
+
+    /// 连接池空闲连接超时（秒，可选）
+    #[serde(default)]
+    pub pool_idle_timeout_secs: Option<u64>,
+
+    /// TLS 后端（"native-tls" 或 "rustls"）
+    #[serde(default)]
+    pub tls_backend: TlsBackend,
+
+    /// 禁止转发的事件类型（可选，支持 "thinking"、"ping"）
+    #[serde(default)]
+    pub disabled_events: Vec<String>,
+
+    /// 注入到每个请求的系统提示词（可选，工作区可单独配置）
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+
+    /// 系统提示词注入位置（"prepend" 或 "append"）
+    #[serde(default)]
+    pub system_prompt_position: SystemPromptPosition,
+
+    /// 单个请求允许的最大工具数量（可选）
+    #[serde(default)]
+    pub max_tools: Option<usize>,
+
+    /// 单个请求工具定义序列化后的最大字节数（可选）
+    #[serde(default)]
+    pub max_tools_bytes: Option<usize>,
+
+    /// 工具列表超出限制时按相关性自动裁剪，而不是直接拒绝请求
+    #[serde(default)]
+    pub prune_tools: bool,
+
+    /// 单个 tool_result 内容的最大字节数（可选），超出时保留开头和结尾，中间替换为截断说明
+    #[serde(default)]
+    pub max_tool_result_bytes: Option<usize>,
+
+    /// 截断 tool_result 时保留的结尾字节数（不超过上限的一半）
+    #[serde(default = "default_tool_result_tail_bytes")]
+    pub tool_result_tail_bytes: usize,
+
+    /// 未知内容块类型的默认处理方式（drop/text/reject）
+    #[serde(default)]
+    pub unknown_block_policy: UnknownBlockPolicy,
+
+    /// 按内容块类型覆盖处理方式（如 `{"server_tool_use": "text"}`）
+    #[serde(default)]
+    pub unknown_block_policies: HashMap<String, UnknownBlockPolicy>,
+
+    /// `anthropic-beta` 请求头中未识别的 beta 的处理方式（ignore/reject）
+    #[serde(default)]
+    pub unknown_beta_policy: UnknownBetaPolicy,
+
+    /// 去除助手文本中的 Kiro 残留内容（追问提示回显、回显的 thinking 控制标签）
+    #[serde(default)]
+    pub strip_artifacts: bool,
+
+    /// 将助手文本中的 `\r\n` 和 `\r` 统一为 `\n`
+    #[serde(default)]
+    pub normalize_newlines: bool,
+
+    /// 代码块语言标记改写（如 `{"jsx": "javascript"}`）
+    #[serde(default)]
+    pub code_fence_languages: HashMap<String, String>,
+
+    /// 是否输出上下文使用率（流式为 `kiro_context_usage` 事件，非流式为响应头）
+    #[serde(default)]
+    pub emit_context_usage: bool,
+
+    /// 是否合并完全相同的并发流式请求
+    #[serde(default)]
+    pub coalesce_streams: bool,
+
+    /// 在 API 监听器上提供 `/playground` 调试页面
+    #[serde(default)]
+    pub playground: bool,
+
+    /// Files API 上传文件的存储目录
+    #[serde(default = "default_files_dir")]
+    pub files_dir: String,
+
+    /// 账号池事件 Webhook 地址列表（冷却、失效、刷新失败、配额用尽、账号池不可用）
+    #[serde(default)]
+    pub webhook_urls: Vec<String>,
+
+    /// 配额使用率告警阈值（百分比），刷新配额时跨过阈值发送 Webhook 通知
+    #[serde(default = "default_quota_warning_thresholds")]
+    pub quota_warning_thresholds: Vec<u8>,
+
+    /// 外部 embeddings 服务地址（OpenAI 兼容，如 `https://api.openai.com/v1/embeddings`）
+    ///
+    /// 未配置时 `POST /v1/embeddings` 返回明确的不支持错误
+    #[serde(default)]
+    pub embeddings_url: Option<String>,
+
+    /// 外部 embeddings 服务的 API Key（可选，以 Bearer Token 发送）
+    #[serde(default)]
+    pub embeddings_api_key: Option<String>,
+
+    /// 转发时覆盖请求中的模型名（可选）
+    #[serde(default)]
+    pub embeddings_model: Option<String>,
+
+    /// 由代理执行的内置工具白名单（支持 "current_time"、"fetch_url"，为空时不启用）
+    #[serde(default)]
+    pub server_tools: Vec<String>,
+
+    /// 单个请求内最多执行内置工具的轮次，超出后将最后一轮响应原样返回客户端
+    #[serde(default = "default_server_tool_max_iterations")]
+    pub server_tool_max_iterations: usize,
+
+    /// 客户端工具定义的处理策略（可被托管 API Key 的 `clientTools` 覆盖）
+    #[serde(default)]
+    pub client_tools: ClientToolPolicy,
+
+    /// 是否为非流式请求写入预写日志，崩溃重启后报告中断的请求（仅账号池模式）
+    #[serde(default)]
+    pub request_journal: bool,
+
+    /// 是否以上游计费事件累计的本月额度判断账号配额是否用尽（仅账号池模式）
+    ///
+    /// 关闭时以定期查询的配额缓存为准
+    #[serde(default)]
+    pub metering_quota_source: bool,
+
+    /// 持久化存储后端（仅账号池模式）
+    #[serde(default)]
+    pub storage_backend: StorageBackend,
+
+    /// 使用记录外部采集端点（可选，按批 POST 请求记录）
+    #[serde(default)]
+    pub usage_collector_url: Option<String>,
+
+    /// 采集端点的 Bearer Token（可选）
+    #[serde(default)]
+    pub usage_collector_token: Option<String>,
+
+    /// 每批发送的最大记录数
+    #[serde(default = "default_usage_collector_batch_size")]
+    pub usage_collector_batch_size: usize,
+
+    /// 定时发送间隔（秒）
+    #[serde(default = "default_usage_collector_flush_secs")]
+    pub usage_collector_flush_secs: u64,
+
+    /// Token 连续刷新失败多少次后熔断并标记账号失效（0 表示不熔断）
+    #[serde(default = "default_refresh_failure_threshold")]
+    pub refresh_failure_threshold: u32,
+
+    /// 熔断后暂停刷新的时长（秒）
+    #[serde(default = "default_refresh_backoff_secs")]
+    pub refresh_backoff_secs: u64,
+
+    /// 上游过载（529/503）后整个账号池暂停分配请求的时长（秒），上游返回 `Retry-After` 时以其为准；
+    /// 过载与账号无关，不让单个账号进入 5 分钟的限流冷却
+    #[serde(default = "default_overload_backoff_secs")]
+    pub overload_backoff_secs: u64,
+
+    /// 错误预算：账号在时间窗口内的错误率超过阈值时自动禁用（可选，仅账号池模式）
+    #[serde(default)]
+    pub error_budget: Option<ErrorBudgetConfig>,
+
+    /// 单次 Token 刷新请求的超时时间（秒）
+    #[serde(default = "default_refresh_timeout_secs")]
+    pub refresh_timeout_secs: u64,
+
+    /// 同步 Kiro IDE 刷新后写入 SSO 缓存的 Token（仅单账号模式）
+    #[serde(default)]
+    pub ide_sync: bool,
+
+    /// Kiro IDE 的 Token 缓存文件（可选，默认 `~/.aws/sso/cache/kiro-auth-token.json`）
+    #[serde(default)]
+    pub ide_token_file: Option<String>,
+
+    /// 检查 Token 缓存文件是否变化的间隔（秒）
+    #[serde(default = "default_ide_sync_interval_secs")]
+    pub ide_sync_interval_secs: u64,
+
+    /// 转换失败匿名遥测上报地址（可选，默认关闭）
+    ///
+    /// 仅上报错误类型、字段路径和模型名，不包含任何请求内容
+    #[serde(default)]
+    pub telemetry_url: Option<String>,
+
+    /// 同时发往上游的最大请求数（0 表示不限制）
+    ///
+    /// 超出的请求按优先级排队，交互式请求优先于批处理请求
+    #[serde(default)]
+    pub max_concurrent_requests: usize,
+
+    /// 请求排队的最长等待时间（秒），超时返回 529
+    #[serde(default = "default_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+
+    /// 单个请求的最长处理时间（秒，0 表示不限制），流式请求包括整个输出过程
+    ///
+    /// 可被工作区或托管 API Key 的 `maxRequestSecs` 覆盖
+    #[serde(default)]
+    pub max_request_secs: u64,
+
+    /// 流式响应中上游两次输出之间的最长间隔（秒，0 表示不限制）
+    ///
+    /// 可被工作区或托管 API Key 的 `streamIdleTimeoutSecs` 覆盖
+    #[serde(default)]
+    pub stream_idle_timeout_secs: u64,
+
+    /// `/v1/messages` 请求体上限（MB），边读取边计数，超出后立即拒绝
+    #[serde(default = "default_max_request_body_mb")]
+    pub max_request_body_mb: usize,
+
+    /// 默认的 Kiro 代理模式（可被 `x-kiro-agent-mode` 请求头或模型名后缀覆盖）
+    #[serde(default)]
+    pub agent_mode: AgentMode,
+
+    /// 发往 Kiro 的用户消息来源（`origin`，可被可信密钥的 `x-kiro-origin` 请求头覆盖）
+    #[serde(default)]
+    pub origin: KiroOrigin,
+
+    /// 发往 Kiro 的 `chatTriggerType`（可被可信密钥的 `x-kiro-chat-trigger-type` 请求头覆盖），
+    /// 未设置时按请求推断：tool_choice 为 any/tool 时为 AUTO，否则为 MANUAL
+    #[serde(default)]
+    pub chat_trigger_type: Option<ChatTriggerType>,
+
+    /// 故障注入配置（可选，仅 debug 构建生效）
+    #[serde(default)]
+    pub chaos: Option<ChaosConfig>,
+
+    /// 影子流量配置（可选，将部分请求镜像到另一个后端比较延迟和错误率）
+    #[serde(default)]
+    pub shadow: Option<ShadowConfig>,
+
+    /// 会话缓存条数（按 `x-kiro-conversation-id` 复用上游会话 ID 的 LRU 缓存，0 表示禁用）
+    #[serde(default = "default_conversation_cache_size")]
+    pub conversation_cache_size: usize,
+
+    /// 会话缓存条目的过期时间（秒，按最近访问计算）
+    #[serde(default = "default_conversation_ttl_secs")]
+    pub conversation_ttl_secs: u64,
+
+    /// 每个托管 API Key 在内存中保留的最近请求数（0 表示不记录），
+    /// 可通过管理 API `GET /api/keys/{id}/recent` 查看
+    #[serde(default)]
+    pub recent_requests_per_key: usize,
+
+    /// Redis 配置（可选，多个实例共享账号状态和托管 Key 限流计数）
+    #[serde(default)]
+    pub redis: Option<RedisConfig>,
+
+    /// 上游事件流解码器的缓冲配置
+    #[serde(default)]
+    pub decoder: DecoderBufferConfig,
+
+    /// 流式响应的刷新与缓冲配置
+    #[serde(default)]
+    pub sse: SseConfig,
+
+    /// 命名的提示词模板（客户端通过请求体的 `template` 字段引用）
+    #[serde(default)]
+    pub prompt_templates: HashMap<String, PromptTemplate>,
+
+    /// 监听地址列表（可选，为空时使用 host:port 挂载全部路由）
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+
+    /// 路由路径前缀（可选，如 `/kiro`），API、管理面板和 Playground 均挂载在该前缀下
+    #[serde(default)]
+    pub path_prefix: String,
+
+    /// 多租户工作区（仅账号池模式）
+    #[serde(default)]
+    pub workspaces: Vec<WorkspaceConfig>,
+}
+
+/// 工作区配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceConfig {
+    /// 工作区名称（用于日志和管理路径）
+    pub name: String,
+    /// 访问该工作区的 API Key
+    pub api_key: String,
+    /// 数据目录（可选，默认 `{DATA_DIR}/workspaces/{name}`）
+    #[serde(default)]
+    pub data_dir: Option<String>,
+    /// 工作区的系统提示词（可选，覆盖全局 systemPrompt）
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// 工作区请求的默认优先级（可被 `x-priority` 请求头覆盖）
+    #[serde(default)]
+    pub priority: RequestPriority,
+    /// 工作区请求的时长限制（覆盖全局配置）
+    #[serde(flatten)]
+    pub timeouts: RequestTimeouts,
+}
+
+/// 故障注入配置
+///
+/// 按概率在上游调用中注入延迟、429 或流错误，用于验证重试、故障转移和流错误处理路径。
+/// 各概率取值 0.0 - 1.0，仅在 debug 构建中生效
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChaosConfig {
+    /// 上游响应前注入延迟的概率
+    pub delay_probability: f64,
+    /// 注入的延迟时长（毫秒）
+    pub delay_ms: u64,
+    /// 直接返回 429 的概率
+    pub rate_limit_probability: f64,
+    /// 丢弃响应流数据块的概率
+    pub drop_frame_probability: f64,
+    /// 篡改数据块末尾字节（破坏 CRC 校验）的概率
+    pub corrupt_crc_probability: f64,
+}
+
+/// 影子流量配置
+///
+/// 按比例将 `/v1/messages` 请求镜像到另一个 Anthropic 兼容后端（如使用不同区域或账号的
+/// kiro-rs 实例、预发布构建），记录两边的状态码和延迟差异。镜像请求在后台执行，
+/// 其结果不影响返回给客户端的响应
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowConfig {
+    /// 影子后端地址（不含 `/v1/messages`，如 `http://127.0.0.1:8081`）
+    pub url: String,
+    /// 影子后端的 API Key（可选，以 `x-api-key` 发送）
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// 镜像的请求百分比（0 - 100）
+    #[serde(default = "default_shadow_percent")]
+    pub percent: f64,
+    /// 影子请求超时（秒）
+    #[serde(default = "default_shadow_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_shadow_percent() -> f64 {
+    10.0
+}
+
+fn default_shadow_timeout_secs() -> u64 {
+    300
+}
+
+/// Redis 配置
+///
+/// 配置后托管 API Key 的每分钟请求数在所有实例间共享；`storageBackend` 设为 `redis` 时
+/// 账号池数据也保存到 Redis，各实例定期同步账号冷却和配额缓存。Redis 不可达时回退到本地状态
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedisConfig {
+    /// 连接地址（如 `redis://127.0.0.1:6379/0`）
+    pub url: String,
+    /// 键前缀，多个部署共用同一个 Redis 时用于区分
+    #[serde(default = "default_redis_key_prefix")]
+    pub key_prefix: String,
+    /// 单次操作超时（毫秒），超时按 Redis 不可达处理
+    #[serde(default = "default_redis_timeout_ms")]
+    pub timeout_ms: u64,
+    /// 从 Redis 同步其他实例账号状态的间隔（秒）
+    #[serde(default = "default_redis_sync_secs")]
+    pub sync_secs: u64,
+}
+
+fn default_redis_key_prefix() -> String {
+    "kiro:".to_string()
+}
+
+fn default_redis_timeout_ms() -> u64 {
+    500
+}
+
+fn default_redis_sync_secs() -> u64 {
+    10
+}
+
+/// 错误预算配置
+///
+/// 限流、上游过载和请求格式错误不计入错误；被自动禁用的账号需要在管理面板中手动启用
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorBudgetConfig {
+    /// 错误率阈值（0.0 - 1.0），超过时禁用账号
+    #[serde(default = "default_error_budget_threshold")]
+    pub threshold: f64,
+    /// 统计窗口（秒）
+    #[serde(default = "default_error_budget_window_secs")]
+    pub window_secs: u64,
+    /// 窗口内请求数达到该值后才判断错误率，避免少量请求误判
+    #[serde(default = "default_error_budget_min_requests")]
+    pub min_requests: usize,
+}
+
+impl Default for ErrorBudgetConfig {
+    fn default() -> Self {
+        Self {
+            threshold: default_error_budget_threshold(),
+            window_secs: default_error_budget_window_secs(),
+            min_requests: default_error_budget_min_requests(),
+        }
+    }
+}
+
+fn default_error_budget_threshold() -> f64 {
+    0.5
+}
+
+fn default_error_budget_window_secs() -> u64 {
+    600
+}
+
+fn default_error_budget_min_requests() -> usize {
+    20
+}
+
+/// 提示词模板
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PromptTemplate {
+    /// 插入到请求系统消息之前的系统提示词
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    /// 请求未指定时使用的默认参数（如 `model`、`max_tokens`）
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub params: serde_json::Map<String, serde_json::Value>,
+}
+
+/// 上游事件流解码器的缓冲配置
+///
+/// 限制单个响应可缓冲的字节数，避免损坏或恶意的上游声明超大帧时按帧长度持续占用内存
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DecoderBufferConfig {
+    /// 最大缓冲字节数（同时也是允许的最大帧长度）
+    pub max_buffer_bytes: usize,
+    /// 解码器初始缓冲区容量（字节）
+    pub initial_capacity: usize,
+    /// 超出上限时的处理方式
+    pub overflow: DecoderOverflow,
+}
+
+impl Default for DecoderBufferConfig {
+    fn default() -> Self {
+        Self {
+            max_buffer_bytes: 16 * 1024 * 1024,
+            initial_capacity: 8192,
+            overflow: DecoderOverflow::default(),
+        }
+    }
+}
+
+/// 流式响应的刷新与缓冲配置
+///
+/// 部分反向代理会缓冲较小的 SSE 写入，导致客户端长时间收不到事件；
+/// 高吞吐场景下大量细碎的文本增量又会带来额外的系统调用开销
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SseConfig {
+    /// 添加 `X-Accel-Buffering: no` 响应头（关闭 nginx 等代理的响应缓冲）
+    pub disable_proxy_buffering: bool,
+    /// 流开头发送的 SSE 注释填充字节数，用于填满代理的首个缓冲区（0 表示不填充）
+    pub padding_bytes: usize,
+    /// 合并文本增量的时间窗口（毫秒，0 表示不合并）
+    pub coalesce_interval_ms: u64,
+    /// 合并后单个文本增量的最大字节数（0 表示只受时间窗口限制）
+    pub coalesce_max_bytes: usize,
+    /// 客户端连接禁用 Nagle 算法
+    pub tcp_nodelay: bool,
+}
+
+/// 客户端工具定义的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientToolMode {
+    /// 原样发送给上游
+    #[default]
+    Passthrough,
+    /// 移除 allow/deny 不允许的工具
+    Filter,
+    /// 在 filter 的基础上为工具名称加前缀，与代理注入的内置工具区分
+    Namespace,
+}
+
+impl ClientToolMode {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "passthrough" => Some(Self::Passthrough),
+            "filter" => Some(Self::Filter),
+            "namespace" => Some(Self::Namespace),
+            _ => None,
+        }
+    }
+}
+
+/// 客户端工具定义的处理策略
+///
+/// 客户端在请求中定义的工具视为不可信来源，代理注入的内置工具不受此策略影响
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ClientToolPolicy {
+    pub mode: ClientToolMode,
+    /// 允许的工具名称（不区分大小写，`*` 匹配任意字符），为空表示全部允许
+    pub allow: Vec<String>,
+    /// 禁止的工具名称，优先于 allow
+    pub deny: Vec<String>,
+    /// namespace 模式下的名称前缀
+    pub prefix: String,
+}
+
+impl Default for ClientToolPolicy {
+    fn default() -> Self {
+        Self {
+            mode: ClientToolMode::default(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+            prefix: "client_".to_string(),
+        }
+    }
+}
+
+/// 解码器缓冲区超限时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DecoderOverflow {
+    /// 终止解析，响应按上游错误结束
+    #[default]
+    Error,
+    /// 丢弃超出部分和超限帧，继续解析后续帧
+    Truncate,
+}
+
+impl DecoderOverflow {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "error" => Some(Self::Error),
+            "truncate" => Some(Self::Truncate),
+            _ => None,
+        }
+    }
+}
+
+/// 持久化存储后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// 数据目录中的 JSON 文件
+    #[default]
+    File,
+    /// 只保存在内存中，重启后丢失
+    Memory,
+    /// 数据目录中的 SQLite 数据库
+    Sqlite,
+    /// Redis（需要配置 `redis`），多个实例共享
+    Redis,
+}
+
+impl StorageBackend {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "file" => Some(Self::File),
+            "memory" => Some(Self::Memory),
+            "sqlite" => Some(Self::Sqlite),
+            "redis" => Some(Self::Redis),
+            _ => None,
+        }
+    }
+}
+
+/// 请求优先级（启用并发限制时决定排队顺序）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestPriority {
+    /// 交互式对话请求，优先出队
+    #[default]
+    Interactive,
+    /// 批处理/后台请求
+    Batch,
+}
+
+impl RequestPriority {
+    /// 解析 `x-priority` 请求头
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "interactive" | "high" => Some(RequestPriority::Interactive),
+            "batch" | "background" | "low" => Some(RequestPriority::Batch),
+            _ => None,
+        }
+    }
+}
+
+/// 请求时长限制
+///
+/// 未设置的字段沿用上一级配置（托管 API Key → 工作区 → 全局配置），设置为 0 表示不限制
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestTimeouts {
+    /// 单个请求的最长处理时间（秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_request_secs: Option<u64>,
+    /// 流式响应中上游两次输出之间的最长间隔（秒）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_idle_timeout_secs: Option<u64>,
+}
+
+impl RequestTimeouts {
+    /// 未设置的字段使用 `fallback` 的值
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            max_request_secs: self.max_request_secs.or(fallback.max_request_secs),
+            stream_idle_timeout_secs: self
+                .stream_idle_timeout_secs
+                .or(fallback.stream_idle_timeout_secs),
+        }
+    }
+
+    /// 最长处理时间（不限制时为 None）
+    pub fn max_request(&self) -> Option<std::time::Duration> {
+        nonzero_secs(self.max_request_secs)
+    }
+
+    /// 流式响应空闲超时（不限制时为 None）
+    pub fn stream_idle(&self) -> Option<std::time::Duration> {
+        nonzero_secs(self.stream_idle_timeout_secs)
+    }
+}
+
+impl From<&Config> for RequestTimeouts {
+    fn from(config: &Config) -> Self {
+        Self {
+            max_request_secs: Some(config.max_request_secs),
+            stream_idle_timeout_secs: Some(config.stream_idle_timeout_secs),
+        }
+    }
+}
+
+fn nonzero_secs(secs: Option<u64>) -> Option<std::time::Duration> {
+    secs.filter(|&s| s > 0).map(std::time::Duration::from_secs)
+}
+
+/// Kiro 代理模式（对应 `x-amzn-kiro-agent-mode` 请求头和 `agentTaskType`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentMode {
+    /// 对话式编码（Kiro IDE 默认模式）
+    #[default]
+    Vibe,
+    /// 规格驱动开发
+    Spec,
+}
+
+impl AgentMode {
+    /// 解析配置值或 `x-kiro-agent-mode` 请求头
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "vibe" => Some(AgentMode::Vibe),
+            "spec" => Some(AgentMode::Spec),
+            _ => None,
+        }
+    }
+
+    /// 发送给上游的模式名
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AgentMode::Vibe => "vibe",
+            AgentMode::Spec => "spec",
+        }
+    }
+
+    /// 拆分模型名中的代理模式后缀（如 `claude-sonnet-4-5:spec`）
+    ///
+    /// 后缀不是已知模式时原样返回模型名
+    pub fn split_model_suffix(model: &str) -> (&str, Option<Self>) {
+        match model.rsplit_once(':') {
+            Some((base, suffix)) => match Self::parse(suffix) {
+                Some(mode) => (base, Some(mode)),
+                None => (model, None),
+            },
+            None => (model, None),
+        }
+    }
+}
+
+/// Kiro 用户消息来源（`userInputMessage.origin`），不同来源的上游行为不同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum KiroOrigin {
+    /// Kiro IDE
+    #[default]
+    AiEditor,
+    /// Kiro CLI
+    Cli,
+    /// IDE 插件
+    Ide,
+    /// 网页控制台
+    Console,
+}
+
+impl KiroOrigin {
+    /// 解析配置值或 `x-kiro-origin` 请求头（不区分大小写）
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "AI_EDITOR" => Some(Self::AiEditor),
+            "CLI" => Some(Self::Cli),
+            "IDE" => Some(Self::Ide),
+            "CONSOLE" => Some(Self::Console),
+            _ => None,
+        }
+    }
+
+    /// 发送给上游的值
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::AiEditor => "AI_EDITOR",
+            Self::Cli => "CLI",
+            Self::Ide => "IDE",
+            Self::Console => "CONSOLE",
+        }
+    }
+}
+
+/// Kiro 聊天触发类型（`conversationState.chatTriggerType`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ChatTriggerType {
+    /// 用户主动发起
+    Manual,
+    /// 要求模型调用工具
+    Auto,
+    /// 由诊断信息触发
+    Diagnostic,
+    /// 编辑器内联对话
+    InlineChat,
+}
+
+impl ChatTriggerType {
+    /// 解析配置值或 `x-kiro-chat-trigger-type` 请求头（不区分大小写）
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "MANUAL" => Some(Self::Manual),
+            "AUTO" => Some(Self::Auto),
+            "DIAGNOSTIC" => Some(Self::Diagnostic),
+            "INLINE_CHAT" => Some(Self::InlineChat),
+            _ => None,
+        }
+    }
+
+    /// 发送给上游的值
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Manual => "MANUAL",
+            Self::Auto => "AUTO",
+            Self::Diagnostic => "DIAGNOSTIC",
+            Self::InlineChat => "INLINE_CHAT",
+        }
+    }
+}
+
+/// 系统提示词注入位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemPromptPosition {
+    /// 插入到客户端系统消息之前
+    #[default]
+    Prepend,
+    /// 追加到客户端系统消息之后
+    Append,
+}
+
+impl SystemPromptPosition {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "prepend" => Some(Self::Prepend),
+            "append" => Some(Self::Append),
+            _ => None,
+        }
+    }
+}
+
+/// 未知内容块类型的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownBlockPolicy {
+    /// 丢弃内容块（记录警告日志）
+    #[default]
+    Drop,
+    /// 将原始 JSON 作为文本传给模型
+    Text,
+    /// 拒绝请求，返回 invalid_request_error
+    Reject,
+}
+
+impl UnknownBlockPolicy {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "drop" => Some(Self::Drop),
+            "text" => Some(Self::Text),
+            "reject" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+}
+
+/// `anthropic-beta` 中未识别的 beta 的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownBetaPolicy {
+    /// 忽略（记录警告日志）
+    #[default]
+    Ignore,
+    /// 拒绝请求，返回 invalid_request_error
+    Reject,
+}
+
+impl UnknownBetaPolicy {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "ignore" => Some(Self::Ignore),
+            "reject" => Some(Self::Reject),
+            _ => None,
+        }
+    }
+}
+
+/// 上游 HTTP 协议版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpVersion {
+    /// 通过 ALPN 协商
+    #[default]
+    Auto,
+    /// 强制 HTTP/1.1
+    Http1,
+    /// 强制 HTTP/2（prior knowledge）
+    Http2,
+}
+
+impl HttpVersion {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "http1" | "http1.1" => Some(Self::Http1),
+            "http2" => Some(Self::Http2),
+            _ => None,
+        }
+    }
+}
+
+/// TLS 后端
+///
+/// 未启用 `native-tls` feature 时默认使用 rustls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TlsBackend {
+    /// 系统原生 TLS
+    #[cfg_attr(feature = "native-tls", default)]
+    NativeTls,
+    /// rustls
+    #[cfg_attr(not(feature = "native-tls"), default)]
+    Rustls,
+}
+
+impl TlsBackend {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "native-tls" | "native" => Some(Self::NativeTls),
+            "rustls" => Some(Self::Rustls),
+            _ => None,
+        }
+    }
+}
+
+/// 监听器挂载的路由范围
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerRoutes {
+    /// API 与管理面板
+    #[default]
+    All,
+    /// 仅 Anthropic API
+    Api,
+    /// 仅管理面板
+    Admin,
+}
+
+impl ListenerRoutes {
+    /// 从字符串解析
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "all" => Some(Self::All),
+            "api" => Some(Self::Api),
+            "admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+
+    /// 是否挂载 API 路由
+    pub fn includes_api(&self) -> bool {
+        matches!(self, Self::All | Self::Api)
+    }
+
+    /// 是否挂载管理面板路由
+    pub fn includes_admin(&self) -> bool {
+        matches!(self, Self::All | Self::Admin)
+    }
+}
+
+/// 单个监听器配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerConfig {
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub routes: ListenerRoutes,
+}
+
+impl ListenerConfig {
+    /// 从 `host:port[=routes]` 格式解析
+    pub fn parse(s: &str) -> Option<Self> {
+        let (addr, routes) = match s.trim().split_once('=') {
+            Some((addr, routes)) => (addr, ListenerRoutes::parse(routes)?),
+            None => (s.trim(), ListenerRoutes::All),
+        };
+        let (host, port) = addr.rsplit_once(':')?;
+        Some(Self {
+            host: host.to_string(),
+            port: port.parse().ok()?,
+            routes,
+        })
+    }
+
+    /// 监听地址
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
 }
 
 impl Config {
@@ -71,9 +1051,30 @@ impl Config {
         if let Ok(region) = env::var("REGION") {
             self.region = region;
         }
+        if let Ok(region) = env::var("OIDC_REGION") {
+            self.oidc_region = Some(region);
+        }
+        if let Ok(url) = env::var("SOCIAL_REFRESH_URL") {
+            self.social_refresh_url = Some(url);
+        }
+        if let Ok(url) = env::var("IDC_REFRESH_URL") {
+            self.idc_refresh_url = Some(url);
+        }
+        if let Ok(url) = env::var("KIRO_API_URL") {
+            self.kiro_api_url = Some(url);
+        }
         if let Ok(api_key) = env::var("API_KEY") {
             self.api_key = Some(api_key);
         }
+        if let Ok(api_key) = env::var("PREVIOUS_API_KEY") {
+            self.previous_api_key = Some(api_key);
+        }
+        if let Ok(expires_at) = env::var("PREVIOUS_API_KEY_EXPIRES_AT") {
+            match DateTime::parse_from_rfc3339(&expires_at) {
+                Ok(t) => self.previous_api_key_expires_at = Some(t.with_timezone(&Utc)),
+                Err(_) => tracing::warn!("无效的 PREVIOUS_API_KEY_EXPIRES_AT: {}", expires_at),
+            }
+        }
         if let Ok(kiro_version) = env::var("KIRO_VERSION") {
             self.kiro_version = kiro_version;
         }
@@ -95,6 +1096,11 @@ impl Config {
         if let Ok(auth_type) = env::var("COUNT_TOKENS_AUTH_TYPE") {
             self.count_tokens_auth_type = auth_type;
         }
+        if let Ok(size) = env::var("COUNT_TOKENS_CACHE_SIZE") {
+            if let Ok(s) = size.parse() {
+                self.count_tokens_cache_size = s;
+            }
+        }
         if let Ok(proxy) = env::var("PROXY_URL") {
             self.proxy_url = Some(proxy);
         }
@@ -104,6 +1110,392 @@ impl Config {
         if let Ok(password) = env::var("PROXY_PASSWORD") {
             self.proxy_password = Some(password);
         }
+        if let Ok(version) = env::var("HTTP_VERSION") {
+            match HttpVersion::parse(&version) {
+                Some(v) => self.http_version = v,
+                None => tracing::warn!("无效的 HTTP_VERSION: {}", version),
+            }
+        }
+        if let Ok(nodelay) = env::var("TCP_NODELAY") {
+            self.tcp_nodelay = nodelay == "true" || nodelay == "1";
+        }
+        if let Ok(timeout) = env::var("POOL_IDLE_TIMEOUT_SECS") {
+            if let Ok(t) = timeout.parse() {
+                self.pool_idle_timeout_secs = Some(t);
+            }
+        }
+        if let Ok(backend) = env::var("TLS_BACKEND") {
+            match TlsBackend::parse(&backend) {
+                Some(b) => self.tls_backend = b,
+                None => tracing::warn!("无效的 TLS_BACKEND: {}", backend),
+            }
+        }
+        if let Ok(prefix) = env::var("PATH_PREFIX") {
+            self.path_prefix = prefix;
+        }
+        if let Ok(listeners) = env::var("LISTENERS") {
+            self.listeners = listeners
+                .split(',')
+                .filter(|s| !s.trim().is_empty())
+                .filter_map(|s| {
+                    let parsed = ListenerConfig::parse(s);
+                    if parsed.is_none() {
+                        tracing::warn!("无效的监听地址: {}", s);
+                    }
+                    parsed
+                })
+                .collect();
+        }
+        if let Ok(urls) = env::var("WEBHOOK_URLS") {
+            self.webhook_urls = urls
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Ok(thresholds) = env::var("QUOTA_WARNING_THRESHOLDS") {
+            self.quota_warning_thresholds = thresholds
+                .split(',')
+                .filter_map(|s| s.trim().parse().ok())
+                .collect();
+        }
+        if let Ok(url) = env::var("SHADOW_URL") {
+            let shadow = self.shadow.get_or_insert_with(|| ShadowConfig {
+                url: String::new(),
+                api_key: None,
+                percent: default_shadow_percent(),
+                timeout_secs: default_shadow_timeout_secs(),
+            });
+            shadow.url = url;
+        }
+        if let Some(shadow) = &mut self.shadow {
+            if let Ok(key) = env::var("SHADOW_API_KEY") {
+                shadow.api_key = Some(key);
+            }
+            if let Ok(percent) = env::var("SHADOW_PERCENT") {
+                if let Ok(p) = percent.parse() {
+                    shadow.percent = p;
+                }
+            }
+        }
+        if let Ok(threshold) = env::var("ERROR_BUDGET_THRESHOLD") {
+            if let Ok(t) = threshold.parse() {
+                self.error_budget
+                    .get_or_insert_with(Default::default)
+                    .threshold = t;
+            }
+        }
+        if let Some(budget) = &mut self.error_budget {
+            if let Ok(secs) = env::var("ERROR_BUDGET_WINDOW_SECS") {
+                if let Ok(s) = secs.parse() {
+                    budget.window_secs = s;
+                }
+            }
+            if let Ok(min) = env::var("ERROR_BUDGET_MIN_REQUESTS") {
+                if let Ok(m) = min.parse() {
+                    budget.min_requests = m;
+                }
+            }
+        }
+        if let Ok(url) = env::var("REDIS_URL") {
+            let redis = self.redis.get_or_insert_with(|| RedisConfig {
+                url: String::new(),
+                key_prefix: default_redis_key_prefix(),
+                timeout_ms: default_redis_timeout_ms(),
+                sync_secs: default_redis_sync_secs(),
+            });
+            redis.url = url;
+        }
+        if let Some(redis) = &mut self.redis {
+            if let Ok(prefix) = env::var("REDIS_KEY_PREFIX") {
+                redis.key_prefix = prefix;
+            }
+            if let Ok(secs) = env::var("REDIS_SYNC_SECS") {
+                if let Ok(s) = secs.parse() {
+                    redis.sync_secs = s;
+                }
+            }
+        }
+        if let Ok(url) = env::var("EMBEDDINGS_URL") {
+            self.embeddings_url = Some(url);
+        }
+        if let Ok(key) = env::var("EMBEDDINGS_API_KEY") {
+            self.embeddings_api_key = Some(key);
+        }
+        if let Ok(model) = env::var("EMBEDDINGS_MODEL") {
+            self.embeddings_model = Some(model);
+        }
+        if let Ok(tools) = env::var("SERVER_TOOLS") {
+            self.server_tools = tools
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Ok(max) = env::var("SERVER_TOOL_MAX_ITERATIONS") {
+            if let Ok(m) = max.parse() {
+                self.server_tool_max_iterations = m;
+            }
+        }
+        if let Ok(mode) = env::var("CLIENT_TOOL_MODE") {
+            match ClientToolMode::parse(&mode) {
+                Some(m) => self.client_tools.mode = m,
+                None => tracing::warn!("无效的 CLIENT_TOOL_MODE: {}", mode),
+            }
+        }
+        if let Ok(allow) = env::var("CLIENT_TOOL_ALLOW") {
+            self.client_tools.allow = allow
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Ok(deny) = env::var("CLIENT_TOOL_DENY") {
+            self.client_tools.deny = deny
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
+        if let Ok(prefix) = env::var("CLIENT_TOOL_PREFIX") {
+            self.client_tools.prefix = prefix;
+        }
+        if let Ok(journal) = env::var("REQUEST_JOURNAL") {
+            self.request_journal = journal == "true" || journal == "1";
+        }
+        if let Ok(source) = env::var("METERING_QUOTA_SOURCE") {
+            self.metering_quota_source = source == "true" || source == "1";
+        }
+        if let Ok(backend) = env::var("STORAGE_BACKEND") {
+            match StorageBackend::parse(&backend) {
+                Some(b) => self.storage_backend = b,
+                None => tracing::warn!("无效的 STORAGE_BACKEND: {}", backend),
+            }
+        }
+        if let Ok(url) = env::var("USAGE_COLLECTOR_URL") {
+            self.usage_collector_url = Some(url);
+        }
+        if let Ok(token) = env::var("USAGE_COLLECTOR_TOKEN") {
+            self.usage_collector_token = Some(token);
+        }
+        if let Ok(size) = env::var("USAGE_COLLECTOR_BATCH_SIZE") {
+            if let Ok(s) = size.parse() {
+                self.usage_collector_batch_size = s;
+            }
+        }
+        if let Ok(secs) = env::var("USAGE_COLLECTOR_FLUSH_SECS") {
+            if let Ok(s) = secs.parse() {
+                self.usage_collector_flush_secs = s;
+            }
+        }
+        if let Ok(threshold) = env::var("REFRESH_FAILURE_THRESHOLD") {
+            if let Ok(t) = threshold.parse() {
+                self.refresh_failure_threshold = t;
+            }
+        }
+        if let Ok(backoff) = env::var("REFRESH_BACKOFF_SECS") {
+            if let Ok(b) = backoff.parse() {
+                self.refresh_backoff_secs = b;
+            }
+        }
+        if let Ok(backoff) = env::var("OVERLOAD_BACKOFF_SECS") {
+            if let Ok(b) = backoff.parse() {
+                self.overload_backoff_secs = b;
+            }
+        }
+        if let Ok(timeout) = env::var("REFRESH_TIMEOUT_SECS") {
+            if let Ok(t) = timeout.parse() {
+                self.refresh_timeout_secs = t;
+            }
+        }
+        if let Ok(sync) = env::var("IDE_SYNC") {
+            self.ide_sync = sync == "true" || sync == "1";
+        }
+        if let Ok(path) = env::var("IDE_TOKEN_FILE") {
+            self.ide_token_file = Some(path);
+        }
+        if let Ok(interval) = env::var("IDE_SYNC_INTERVAL_SECS") {
+            if let Ok(i) = interval.parse() {
+                self.ide_sync_interval_secs = i;
+            }
+        }
+        if let Ok(url) = env::var("TELEMETRY_URL") {
+            self.telemetry_url = Some(url);
+        }
+        if let Ok(max) = env::var("MAX_CONCURRENT_REQUESTS") {
+            if let Ok(m) = max.parse() {
+                self.max_concurrent_requests = m;
+            }
+        }
+        if let Ok(timeout) = env::var("QUEUE_TIMEOUT_SECS") {
+            if let Ok(t) = timeout.parse() {
+                self.queue_timeout_secs = t;
+            }
+        }
+        if let Ok(timeout) = env::var("MAX_REQUEST_SECS") {
+            if let Ok(t) = timeout.parse() {
+                self.max_request_secs = t;
+            }
+        }
+        if let Ok(timeout) = env::var("STREAM_IDLE_TIMEOUT_SECS") {
+            if let Ok(t) = timeout.parse() {
+                self.stream_idle_timeout_secs = t;
+            }
+        }
+        if let Ok(limit) = env::var("MAX_REQUEST_BODY_MB") {
+            if let Ok(l) = limit.parse() {
+                self.max_request_body_mb = l;
+            }
+        }
+        if let Ok(size) = env::var("CONVERSATION_CACHE_SIZE") {
+            if let Ok(s) = size.parse() {
+                self.conversation_cache_size = s;
+            }
+        }
+        if let Ok(ttl) = env::var("CONVERSATION_TTL_SECS") {
+            if let Ok(t) = ttl.parse() {
+                self.conversation_ttl_secs = t;
+            }
+        }
+        if let Ok(count) = env::var("RECENT_REQUESTS_PER_KEY") {
+            if let Ok(c) = count.parse() {
+                self.recent_requests_per_key = c;
+            }
+        }
+        if let Ok(mode) = env::var("AGENT_MODE") {
+            match AgentMode::parse(&mode) {
+                Some(m) => self.agent_mode = m,
+                None => tracing::warn!("无效的 AGENT_MODE: {}", mode),
+            }
+        }
+        if let Ok(origin) = env::var("KIRO_ORIGIN") {
+            match KiroOrigin::parse(&origin) {
+                Some(o) => self.origin = o,
+                None => tracing::warn!("无效的 KIRO_ORIGIN: {}", origin),
+            }
+        }
+        if let Ok(trigger) = env::var("CHAT_TRIGGER_TYPE") {
+            match ChatTriggerType::parse(&trigger) {
+                Some(t) => self.chat_trigger_type = Some(t),
+                None => tracing::warn!("无效的 CHAT_TRIGGER_TYPE: {}", trigger),
+            }
+        }
+        if let Ok(max) = env::var("DECODER_MAX_BUFFER_BYTES") {
+            if let Ok(m) = max.parse() {
+                self.decoder.max_buffer_bytes = m;
+            }
+        }
+        if let Ok(capacity) = env::var("DECODER_INITIAL_CAPACITY") {
+            if let Ok(c) = capacity.parse() {
+                self.decoder.initial_capacity = c;
+            }
+        }
+        if let Ok(overflow) = env::var("DECODER_OVERFLOW") {
+            match DecoderOverflow::parse(&overflow) {
+                Some(o) => self.decoder.overflow = o,
+                None => tracing::warn!("无效的 DECODER_OVERFLOW: {}", overflow),
+            }
+        }
+        if let Ok(disable) = env::var("SSE_DISABLE_PROXY_BUFFERING") {
+            self.sse.disable_proxy_buffering = disable == "true" || disable == "1";
+        }
+        if let Ok(padding) = env::var("SSE_PADDING_BYTES") {
+            if let Ok(p) = padding.parse() {
+                self.sse.padding_bytes = p;
+            }
+        }
+        if let Ok(interval) = env::var("SSE_COALESCE_INTERVAL_MS") {
+            if let Ok(i) = interval.parse() {
+                self.sse.coalesce_interval_ms = i;
+            }
+        }
+        if let Ok(max) = env::var("SSE_COALESCE_MAX_BYTES") {
+            if let Ok(m) = max.parse() {
+                self.sse.coalesce_max_bytes = m;
+            }
+        }
+        if let Ok(nodelay) = env::var("SSE_TCP_NODELAY") {
+            self.sse.tcp_nodelay = nodelay == "true" || nodelay == "1";
+        }
+        if let Ok(policy) = env::var("UNKNOWN_BLOCK_POLICY") {
+            match UnknownBlockPolicy::parse(&policy) {
+                Some(p) => self.unknown_block_policy = p,
+                None => tracing::warn!("无效的 UNKNOWN_BLOCK_POLICY: {}", policy),
+            }
+        }
+        if let Ok(policy) = env::var("UNKNOWN_BETA_POLICY") {
+            match UnknownBetaPolicy::parse(&policy) {
+                Some(p) => self.unknown_beta_policy = p,
+                None => tracing::warn!("无效的 UNKNOWN_BETA_POLICY: {}", policy),
+            }
+        }
+        if let Ok(max) = env::var("MAX_TOOLS") {
+            if let Ok(m) = max.parse() {
+                self.max_tools = Some(m);
+            }
+        }
+        if let Ok(max) = env::var("MAX_TOOLS_BYTES") {
+            if let Ok(m) = max.parse() {
+                self.max_tools_bytes = Some(m);
+            }
+        }
+        if let Ok(prune) = env::var("PRUNE_TOOLS") {
+            self.prune_tools = prune == "true" || prune == "1";
+        }
+        if let Ok(max) = env::var("MAX_TOOL_RESULT_BYTES") {
+            if let Ok(m) = max.parse() {
+                self.max_tool_result_bytes = Some(m);
+            }
+        }
+        if let Ok(tail) = env::var("TOOL_RESULT_TAIL_BYTES") {
+            if let Ok(t) = tail.parse() {
+                self.tool_result_tail_bytes = t;
+            }
+        }
+        if let Ok(strip) = env::var("STRIP_ARTIFACTS") {
+            self.strip_artifacts = strip == "true" || strip == "1";
+        }
+        if let Ok(normalize) = env::var("NORMALIZE_NEWLINES") {
+            self.normalize_newlines = normalize == "true" || normalize == "1";
+        }
+        if let Ok(languages) = env::var("CODE_FENCE_LANGUAGES") {
+            self.code_fence_languages = languages
+                .split(',')
+                .filter_map(|pair| {
+                    let (from, to) = pair.split_once('=')?;
+                    Some((from.trim().to_string(), to.trim().to_string()))
+                })
+                .filter(|(from, to)| !from.is_empty() && !to.is_empty())
+                .collect();
+        }
+        if let Ok(dir) = env::var("FILES_DIR") {
+            self.files_dir = dir;
+        }
+        if let Ok(prompt) = env::var("SYSTEM_PROMPT") {
+            self.system_prompt = Some(prompt);
+        }
+        if let Ok(position) = env::var("SYSTEM_PROMPT_POSITION") {
+            match SystemPromptPosition::parse(&position) {
+                Some(p) => self.system_prompt_position = p,
+                None => tracing::warn!("无效的 SYSTEM_PROMPT_POSITION: {}", position),
+            }
+        }
+        if let Ok(emit) = env::var("EMIT_CONTEXT_USAGE") {
+            self.emit_context_usage = emit == "true" || emit == "1";
+        }
+        if let Ok(coalesce) = env::var("COALESCE_STREAMS") {
+            self.coalesce_streams = coalesce == "true" || coalesce == "1";
+        }
+        if let Ok(playground) = env::var("PLAYGROUND") {
+            self.playground = playground == "true" || playground == "1";
+        }
+        if let Ok(events) = env::var("DISABLED_EVENTS") {
+            self.disabled_events = events
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
     }
 }
 
@@ -135,27 +1527,168 @@ fn default_node_version() -> String {
     "22.21.1".to_string()
 }
 
+fn default_count_tokens_cache_size() -> usize {
+    1024
+}
+
 fn default_count_tokens_auth_type() -> String {
     "x-api-key".to_string()
 }
 
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+fn default_server_tool_max_iterations() -> usize {
+    5
+}
+
+fn default_max_request_body_mb() -> usize {
+    32
+}
+
+fn default_quota_warning_thresholds() -> Vec<u8> {
+    vec![80]
+}
+
+fn default_usage_collector_batch_size() -> usize {
+    100
+}
+
+fn default_usage_collector_flush_secs() -> u64 {
+    30
+}
+
+fn default_refresh_failure_threshold() -> u32 {
+    3
+}
+
+fn default_refresh_backoff_secs() -> u64 {
+    600
+}
+
+fn default_tool_result_tail_bytes() -> usize {
+    4096
+}
+
+fn default_overload_backoff_secs() -> u64 {
+    10
+}
+
+fn default_conversation_cache_size() -> usize {
+    10000
+}
+
+fn default_conversation_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_queue_timeout_secs() -> u64 {
+    60
+}
+
+fn default_refresh_timeout_secs() -> u64 {
+    15
+}
+
+fn default_ide_sync_interval_secs() -> u64 {
+    10
+}
+
+fn default_files_dir() -> String {
+    "./data/files".to_string()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             host: default_host(),
             port: default_port(),
             region: default_region(),
+            oidc_region: None,
+            social_refresh_url: None,
+            idc_refresh_url: None,
+            kiro_api_url: None,
             kiro_version: default_kiro_version(),
             machine_id: None,
             api_key: None,
+            previous_api_key: None,
+            previous_api_key_expires_at: None,
             system_version: default_system_version(),
             node_version: default_node_version(),
             count_tokens_api_url: None,
             count_tokens_api_key: None,
             count_tokens_auth_type: default_count_tokens_auth_type(),
+            count_tokens_cache_size: default_count_tokens_cache_size(),
             proxy_url: None,
             proxy_username: None,
             proxy_password: None,
+            http_version: HttpVersion::default(),
+            tcp_nodelay: default_tcp_nodelay(),
+            pool_idle_timeout_secs: None,
+            tls_backend: TlsBackend::default(),
+            disabled_events: Vec::new(),
+            system_prompt: None,
+            system_prompt_position: SystemPromptPosition::default(),
+            max_tools: None,
+            max_tools_bytes: None,
+            prune_tools: false,
+            max_tool_result_bytes: None,
+            tool_result_tail_bytes: default_tool_result_tail_bytes(),
+            unknown_block_policy: UnknownBlockPolicy::default(),
+            unknown_block_policies: HashMap::new(),
+            unknown_beta_policy: UnknownBetaPolicy::default(),
+            strip_artifacts: false,
+            normalize_newlines: false,
+            code_fence_languages: HashMap::new(),
+            emit_context_usage: false,
+            coalesce_streams: false,
+            playground: false,
+            files_dir: default_files_dir(),
+            webhook_urls: Vec::new(),
+            quota_warning_thresholds: default_quota_warning_thresholds(),
+            embeddings_url: None,
+            embeddings_api_key: None,
+            embeddings_model: None,
+            server_tools: Vec::new(),
+            server_tool_max_iterations: default_server_tool_max_iterations(),
+            client_tools: ClientToolPolicy::default(),
+            request_journal: false,
+            metering_quota_source: false,
+            storage_backend: StorageBackend::default(),
+            usage_collector_url: None,
+            usage_collector_token: None,
+            usage_collector_batch_size: default_usage_collector_batch_size(),
+            usage_collector_flush_secs: default_usage_collector_flush_secs(),
+            refresh_failure_threshold: default_refresh_failure_threshold(),
+            refresh_backoff_secs: default_refresh_backoff_secs(),
+            overload_backoff_secs: default_overload_backoff_secs(),
+            error_budget: None,
+            refresh_timeout_secs: default_refresh_timeout_secs(),
+            ide_sync: false,
+            ide_token_file: None,
+            ide_sync_interval_secs: default_ide_sync_interval_secs(),
+            telemetry_url: None,
+            max_concurrent_requests: 0,
+            queue_timeout_secs: default_queue_timeout_secs(),
+            max_request_secs: 0,
+            max_request_body_mb: default_max_request_body_mb(),
+            stream_idle_timeout_secs: 0,
+            agent_mode: AgentMode::default(),
+            origin: KiroOrigin::default(),
+            chat_trigger_type: None,
+            chaos: None,
+            shadow: None,
+            conversation_cache_size: default_conversation_cache_size(),
+            conversation_ttl_secs: default_conversation_ttl_secs(),
+            recent_requests_per_key: 0,
+            redis: None,
+            decoder: DecoderBufferConfig::default(),
+            sse: SseConfig::default(),
+            prompt_templates: HashMap::new(),
+            listeners: Vec::new(),
+            path_prefix: String::new(),
+            workspaces: Vec::new(),
         }
     }
 }
@@ -166,16 +1699,197 @@ impl Config {
         "config.json"
     }
 
-    /// 从文件加载配置
-    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+    /// 获取实际生效的监听器列表
+    ///
+    /// 未配置 listeners 时，使用 host:port 挂载全部路由
+    pub fn effective_listeners(&self) -> Vec<ListenerConfig> {
+        if self.listeners.is_empty() {
+            vec![ListenerConfig {
+                host: self.host.clone(),
+                port: self.port,
+                routes: ListenerRoutes::All,
+            }]
+        } else {
+            self.listeners.clone()
+        }
+    }
+
+    /// 从文件加载配置，并依次叠加指定的部署 profile
+    ///
+    /// 顶层字段为基础配置，`profiles` 中的 profile 按顺序深度合并到基础配置上
+    /// （对象逐字段合并，数组等其它值整体替换）。未指定 profile 时使用 `default` profile（如果存在）
+    pub fn load<P: AsRef<Path>>(path: P, profiles: &[String]) -> anyhow::Result<Self> {
         let path = path.as_ref();
         if !path.exists() {
+            if !profiles.is_empty() {
+                anyhow::bail!("配置文件 {:?} 不存在，无法使用 profile", path);
+            }
             // 配置文件不存在，返回默认配置
             return Ok(Self::default());
         }
 
         let content = fs::read_to_string(path)?;
-        let config: Config = serde_json::from_str(&content)?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        let config: Config = serde_json::from_value(apply_profiles(value, profiles)?)?;
         Ok(config)
     }
 }
+
+/// 配置文件中定义部署 profile 的字段
+const PROFILES_KEY: &str = "profiles";
+
+/// 将选中的 profile 依次叠加到基础配置上
+fn apply_profiles(
+    mut base: serde_json::Value,
+    profiles: &[String],
+) -> anyhow::Result<serde_json::Value> {
+    let defined = base
+        .as_object_mut()
+        .and_then(|obj| obj.remove(PROFILES_KEY))
+        .unwrap_or_default();
+    let selected: Vec<&str> = if profiles.is_empty() {
+        defined
+            .get("default")
+            .map(|_| vec!["default"])
+            .unwrap_or_default()
+    } else {
+        profiles.iter().map(String::as_str).collect()
+    };
+
+    for name in selected {
+        let overlay = defined
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("配置文件中未定义 profile: {}", name))?;
+        merge_json(&mut base, overlay);
+    }
+    Ok(base)
+}
+
+/// 深度合并 JSON：对象逐字段合并，其它值直接替换
+fn merge_json(base: &mut serde_json::Value, overlay: &serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_json(
+                    base.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    value,
+                );
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listener_parse() {
+        let l = ListenerConfig::parse("127.0.0.1:8081=admin").unwrap();
+        assert_eq!(l.host, "127.0.0.1");
+        assert_eq!(l.port, 8081);
+        assert_eq!(l.routes, ListenerRoutes::Admin);
+
+        let l = ListenerConfig::parse("0.0.0.0:8443").unwrap();
+        assert_eq!(l.routes, ListenerRoutes::All);
+
+        assert!(ListenerConfig::parse("0.0.0.0").is_none());
+        assert!(ListenerConfig::parse("0.0.0.0:80=unknown").is_none());
+    }
+
+    #[test]
+    fn test_effective_listeners_default() {
+        let config = Config {
+            host: "127.0.0.1".to_string(),
+            port: 9000,
+            ..Config::default()
+        };
+        let listeners = config.effective_listeners();
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].addr(), "127.0.0.1:9000");
+        assert_eq!(listeners[0].routes, ListenerRoutes::All);
+    }
+
+    #[test]
+    fn test_listeners_deserialize() {
+        let json = r#"{"listeners": [{"host": "127.0.0.1", "port": 8081, "routes": "admin"}]}"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.listeners.len(), 1);
+        assert_eq!(config.listeners[0].routes, ListenerRoutes::Admin);
+    }
+
+    #[test]
+    fn test_apply_profiles() {
+        let base = serde_json::json!({
+            "port": 8080,
+            "apiKey": "local-key",
+            "listeners": [{"host": "127.0.0.1", "port": 8081}],
+            "profiles": {
+                "default": {"region": "us-east-1"},
+                "prod": {"host": "0.0.0.0", "apiKey": "prod-key", "listeners": []},
+                "eu": {"region": "eu-central-1"}
+            }
+        });
+        let load = |profiles: &[&str]| -> anyhow::Result<Config> {
+            let profiles: Vec<String> = profiles.iter().map(|p| p.to_string()).collect();
+            Ok(serde_json::from_value(apply_profiles(
+                base.clone(),
+                &profiles,
+            )?)?)
+        };
+
+        // 后面的 profile 覆盖前面的设置，未覆盖的字段保留基础配置
+        let config = load(&["prod", "eu"]).unwrap();
+        assert_eq!(config.host, "0.0.0.0");
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.api_key.as_deref(), Some("prod-key"));
+        assert_eq!(config.region, "eu-central-1");
+        assert!(config.listeners.is_empty());
+
+        // 未指定 profile 时使用 default
+        let config = load(&[]).unwrap();
+        assert_eq!(config.api_key.as_deref(), Some("local-key"));
+        assert_eq!(config.listeners.len(), 1);
+
+        assert!(load(&["staging"]).is_err());
+    }
+
+    #[test]
+    fn test_agent_mode_model_suffix() {
+        assert_eq!(
+            AgentMode::split_model_suffix("claude-sonnet-4-5:spec"),
+            ("claude-sonnet-4-5", Some(AgentMode::Spec))
+        );
+        assert_eq!(
+            AgentMode::split_model_suffix("claude-sonnet-4-5:VIBE"),
+            ("claude-sonnet-4-5", Some(AgentMode::Vibe))
+        );
+        // 未知后缀不是代理模式，模型名保持不变
+        assert_eq!(
+            AgentMode::split_model_suffix("claude-sonnet-4-5:beta"),
+            ("claude-sonnet-4-5:beta", None)
+        );
+
+        let config: Config = serde_json::from_str(r#"{"agentMode": "spec"}"#).unwrap();
+        assert_eq!(config.agent_mode, AgentMode::Spec);
+        assert_eq!(Config::default().agent_mode, AgentMode::Vibe);
+    }
+
+    #[test]
+    fn test_origin_and_chat_trigger_type() {
+        let config: Config =
+            serde_json::from_str(r#"{"origin": "CLI", "chatTriggerType": "INLINE_CHAT"}"#).unwrap();
+        assert_eq!(config.origin, KiroOrigin::Cli);
+        assert_eq!(config.chat_trigger_type, Some(ChatTriggerType::InlineChat));
+        assert_eq!(Config::default().origin.as_str(), "AI_EDITOR");
+        assert!(Config::default().chat_trigger_type.is_none());
+
+        // 未知取值在加载配置时报错
+        assert!(serde_json::from_str::<Config>(r#"{"origin": "BROWSER"}"#).is_err());
+        assert_eq!(
+            ChatTriggerType::parse(" auto "),
+            Some(ChatTriggerType::Auto)
+        );
+    }
+}
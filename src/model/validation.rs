@@ -0,0 +1,685 @@
+//! 配置校验
+//!
+//! 在启动时集中检查配置与凭证，一次性列出所有问题及修复建议，
+//! 避免运行时才出现难以定位的错误。
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::anthropic::{prefix, ServerTool};
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::parser::frame::MIN_MESSAGE_SIZE;
+
+use super::config::{Config, StorageBackend, TlsBackend};
+
+/// 支持的代理协议
+const PROXY_SCHEMES: &[&str] = &["http", "https", "socks5", "socks5h"];
+
+/// 支持的 count_tokens 认证类型
+const COUNT_TOKENS_AUTH_TYPES: &[&str] = &["x-api-key", "bearer"];
+
+/// 支持屏蔽的事件类型
+const DISABLEABLE_EVENTS: &[&str] = &["thinking", "ping", "context_usage", "followup"];
+
+/// 流开头 SSE 填充的最大字节数
+const MAX_SSE_PADDING_BYTES: usize = 64 * 1024;
+
+/// 配置问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// 出错的字段
+    pub field: String,
+    /// 问题描述
+    pub message: String,
+    /// 修复建议
+    pub hint: String,
+}
+
+impl ConfigIssue {
+    fn new(field: impl Into<String>, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+            hint: hint.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}（{}）", self.field, self.message, self.hint)
+    }
+}
+
+/// 检查 URL 语法及协议
+fn check_url(field: &str, url: &str, schemes: &[&str], issues: &mut Vec<ConfigIssue>) {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if schemes.contains(&parsed.scheme()) => {
+            if parsed.host_str().is_none() {
+                issues.push(ConfigIssue::new(
+                    field,
+                    format!("缺少主机名: {}", url),
+                    "请填写完整地址，例如 http://127.0.0.1:7890",
+                ));
+            }
+        }
+        Ok(parsed) => issues.push(ConfigIssue::new(
+            field,
+            format!("不支持的协议: {}", parsed.scheme()),
+            format!("支持的协议: {}", schemes.join(", ")),
+        )),
+        Err(e) => issues.push(ConfigIssue::new(
+            field,
+            format!("地址格式无效: {} ({})", url, e),
+            "请填写完整地址，例如 http://127.0.0.1:7890",
+        )),
+    }
+}
+
+/// 检查 AWS 区域格式（如 us-east-1、ap-southeast-2）
+fn is_valid_region(region: &str) -> bool {
+    let parts: Vec<&str> = region.split('-').collect();
+    if parts.len() < 3 {
+        return false;
+    }
+    let (first, rest) = parts.split_first().unwrap();
+    let (last, middle) = rest.split_last().unwrap();
+    first.len() == 2
+        && first.chars().all(|c| c.is_ascii_lowercase())
+        && middle
+            .iter()
+            .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_lowercase()))
+        && !last.is_empty()
+        && last.chars().all(|c| c.is_ascii_digit())
+}
+
+impl Config {
+    /// 校验配置，返回所有发现的问题
+    pub fn validate(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+
+        if self.api_key.as_deref().is_none_or(|k| k.trim().is_empty()) {
+            issues.push(ConfigIssue::new(
+                "apiKey",
+                "未设置 API Key",
+                "在 config.json 中设置 apiKey 或设置环境变量 API_KEY",
+            ));
+        }
+
+        if let Some(previous) = &self.previous_api_key {
+            if self.previous_api_key_expires_at.is_none() {
+                issues.push(ConfigIssue::new(
+                    "previousApiKeyExpiresAt",
+                    "设置了旧 API Key 但未指定失效时间",
+                    "请设置 RFC 3339 格式的失效时间，如 2026-01-01T00:00:00Z",
+                ));
+            }
+            if self.api_key.as_deref() == Some(previous.as_str()) {
+                issues.push(ConfigIssue::new(
+                    "previousApiKey",
+                    "旧 API Key 与 apiKey 相同",
+                    "轮换时请将 apiKey 设置为新的密钥",
+                ));
+            }
+        }
+
+        if self.port == 0 {
+            issues.push(ConfigIssue::new(
+                "port",
+                "端口不能为 0",
+                "请使用 1-65535 之间的端口",
+            ));
+        }
+
+        if !is_valid_region(&self.region) {
+            issues.push(ConfigIssue::new(
+                "region",
+                format!("区域格式无效: {}", self.region),
+                "请使用 AWS 区域格式，例如 us-east-1",
+            ));
+        }
+
+        if let Some(region) = &self.oidc_region {
+            if !is_valid_region(region) {
+                issues.push(ConfigIssue::new(
+                    "oidcRegion",
+                    format!("区域格式无效: {}", region),
+                    "请使用 AWS 区域格式，例如 us-east-1",
+                ));
+            }
+        }
+        if let Some(url) = &self.social_refresh_url {
+            check_url("socialRefreshUrl", url, &["http", "https"], &mut issues);
+        }
+        if let Some(url) = &self.idc_refresh_url {
+            check_url("idcRefreshUrl", url, &["http", "https"], &mut issues);
+        }
+        if let Some(url) = &self.kiro_api_url {
+            check_url("kiroApiUrl", url, &["http", "https"], &mut issues);
+        }
+
+        if let Some(url) = &self.proxy_url {
+            check_url("proxyUrl", url, PROXY_SCHEMES, &mut issues);
+        }
+        match (&self.proxy_username, &self.proxy_password) {
+            (Some(_), None) | (None, Some(_)) => issues.push(ConfigIssue::new(
+                "proxyUsername/proxyPassword",
+                "代理用户名和密码需同时设置",
+                "请同时设置 proxyUsername 和 proxyPassword，或都不设置",
+            )),
+            (Some(_), Some(_)) if self.proxy_url.is_none() => issues.push(ConfigIssue::new(
+                "proxyUrl",
+                "设置了代理认证但未设置代理地址",
+                "请设置 proxyUrl 或移除 proxyUsername/proxyPassword",
+            )),
+            _ => {}
+        }
+
+        match &self.count_tokens_api_url {
+            Some(url) => check_url("countTokensApiUrl", url, &["http", "https"], &mut issues),
+            None if self.count_tokens_api_key.is_some() => issues.push(ConfigIssue::new(
+                "countTokensApiKey",
+                "设置了 countTokensApiKey 但未设置 countTokensApiUrl",
+                "请设置 countTokensApiUrl，或移除 countTokensApiKey",
+            )),
+            None => {}
+        }
+        if !COUNT_TOKENS_AUTH_TYPES.contains(&self.count_tokens_auth_type.as_str()) {
+            issues.push(ConfigIssue::new(
+                "countTokensAuthType",
+                format!("不支持的认证类型: {}", self.count_tokens_auth_type),
+                format!("支持的类型: {}", COUNT_TOKENS_AUTH_TYPES.join(", ")),
+            ));
+        }
+
+        if self.max_request_body_mb == 0 {
+            issues.push(ConfigIssue::new(
+                "maxRequestBodyMb",
+                "请求体上限不能为 0",
+                "请设置正整数（单位 MB），默认 32",
+            ));
+        }
+
+        for name in &self.disabled_events {
+            if !DISABLEABLE_EVENTS.contains(&name.trim().to_lowercase().as_str()) {
+                issues.push(ConfigIssue::new(
+                    "disabledEvents",
+                    format!("未知的事件类型: {}", name),
+                    format!("支持的类型: {}", DISABLEABLE_EVENTS.join(", ")),
+                ));
+            }
+        }
+
+        for threshold in &self.quota_warning_thresholds {
+            if !(1..100).contains(threshold) {
+                issues.push(ConfigIssue::new(
+                    "quotaWarningThresholds",
+                    format!("告警阈值超出范围: {}", threshold),
+                    "阈值为 1-99 的百分比，配额用尽另有通知",
+                ));
+            }
+        }
+
+        if cfg!(not(feature = "native-tls")) && self.tls_backend == TlsBackend::NativeTls {
+            issues.push(ConfigIssue::new(
+                "tlsBackend",
+                "当前构建未包含 native-tls",
+                "改用 rustls，或启用 native-tls feature 重新编译",
+            ));
+        }
+        if cfg!(not(feature = "sqlite")) && self.storage_backend == StorageBackend::Sqlite {
+            issues.push(ConfigIssue::new(
+                "storageBackend",
+                "当前构建未包含 sqlite",
+                "改用 file，或启用 sqlite feature 重新编译",
+            ));
+        }
+        if cfg!(not(feature = "redis"))
+            && (self.storage_backend == StorageBackend::Redis || self.redis.is_some())
+        {
+            issues.push(ConfigIssue::new(
+                "redis",
+                "当前构建未包含 redis",
+                "移除 redis 配置并改用其他存储后端，或启用 redis feature 重新编译",
+            ));
+        }
+
+        for name in &self.server_tools {
+            if ServerTool::parse(name).is_none() {
+                let supported: Vec<&str> = ServerTool::ALL.iter().map(|t| t.name()).collect();
+                issues.push(ConfigIssue::new(
+                    "serverTools",
+                    format!("未知的内置工具: {}", name),
+                    format!("支持的工具: {}", supported.join(", ")),
+                ));
+            }
+        }
+
+        for url in &self.webhook_urls {
+            check_url("webhookUrls", url, &["http", "https"], &mut issues);
+        }
+        if let Some(url) = &self.embeddings_url {
+            check_url("embeddingsUrl", url, &["http", "https"], &mut issues);
+        }
+        if let Some(url) = &self.usage_collector_url {
+            check_url("usageCollectorUrl", url, &["http", "https"], &mut issues);
+        }
+        if let Some(url) = &self.telemetry_url {
+            check_url("telemetryUrl", url, &["http", "https"], &mut issues);
+        }
+
+        let mut seen = HashSet::new();
+        for listener in &self.listeners {
+            if listener.port == 0 {
+                issues.push(ConfigIssue::new(
+                    "listeners",
+                    format!("监听端口不能为 0: {}", listener.addr()),
+                    "请使用 1-65535 之间的端口",
+                ));
+            }
+            if !seen.insert(listener.addr()) {
+                issues.push(ConfigIssue::new(
+                    "listeners",
+                    format!("监听地址重复: {}", listener.addr()),
+                    "每个 host:port 只能配置一次，可使用 routes: \"all\" 挂载全部路由",
+                ));
+            }
+        }
+        if !prefix::is_valid(&self.path_prefix) {
+            issues.push(ConfigIssue::new(
+                "pathPrefix",
+                format!("路径前缀包含不支持的字符: {}", self.path_prefix),
+                "只能包含字母、数字、/、-、_、. 和 ~，例如 /kiro",
+            ));
+        }
+
+        let mut names = HashSet::new();
+        let mut keys = HashSet::new();
+        for workspace in &self.workspaces {
+            let valid_name = !workspace.name.is_empty()
+                && workspace
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name {
+                issues.push(ConfigIssue::new(
+                    "workspaces",
+                    format!("工作区名称无效: \"{}\"", workspace.name),
+                    "名称只能包含字母、数字、- 和 _",
+                ));
+            }
+            if !names.insert(workspace.name.as_str()) {
+                issues.push(ConfigIssue::new(
+                    "workspaces",
+                    format!("工作区名称重复: {}", workspace.name),
+                    "每个工作区需要唯一的名称",
+                ));
+            }
+            if workspace.api_key.trim().is_empty() {
+                issues.push(ConfigIssue::new(
+                    "workspaces",
+                    format!("工作区 {} 未设置 apiKey", workspace.name),
+                    "为每个工作区设置独立的 apiKey",
+                ));
+            } else if !keys.insert(workspace.api_key.as_str())
+                || self.api_key.as_deref() == Some(workspace.api_key.as_str())
+            {
+                issues.push(ConfigIssue::new(
+                    "workspaces",
+                    format!("工作区 {} 的 apiKey 与其他 Key 重复", workspace.name),
+                    "每个工作区的 apiKey 必须唯一，且不能与全局 apiKey 相同",
+                ));
+            }
+        }
+
+        if let Some(chaos) = &self.chaos {
+            let probabilities = [
+                ("delayProbability", chaos.delay_probability),
+                ("rateLimitProbability", chaos.rate_limit_probability),
+                ("dropFrameProbability", chaos.drop_frame_probability),
+                ("corruptCrcProbability", chaos.corrupt_crc_probability),
+            ];
+            for (name, value) in probabilities {
+                if !(0.0..=1.0).contains(&value) {
+                    issues.push(ConfigIssue::new(
+                        format!("chaos.{}", name),
+                        format!("概率超出范围: {}", value),
+                        "请使用 0.0 - 1.0 之间的值",
+                    ));
+                }
+            }
+        }
+
+        if let Some(budget) = &self.error_budget {
+            if !(budget.threshold > 0.0 && budget.threshold <= 1.0) {
+                issues.push(ConfigIssue::new(
+                    "errorBudget.threshold",
+                    format!("错误率阈值超出范围: {}", budget.threshold),
+                    "请使用大于 0、不超过 1.0 的值",
+                ));
+            }
+            if budget.window_secs == 0 {
+                issues.push(ConfigIssue::new(
+                    "errorBudget.windowSecs",
+                    "统计窗口不能为 0",
+                    "请设置统计窗口的秒数，如 600",
+                ));
+            }
+        }
+
+        if let Some(shadow) = &self.shadow {
+            check_url("shadow.url", &shadow.url, &["http", "https"], &mut issues);
+            if !(0.0..=100.0).contains(&shadow.percent) {
+                issues.push(ConfigIssue::new(
+                    "shadow.percent",
+                    format!("百分比超出范围: {}", shadow.percent),
+                    "请使用 0 - 100 之间的值",
+                ));
+            }
+        }
+
+        if let Some(redis) = &self.redis {
+            check_url("redis.url", &redis.url, &["redis", "rediss"], &mut issues);
+            if redis.timeout_ms == 0 || redis.sync_secs == 0 {
+                issues.push(ConfigIssue::new(
+                    "redis",
+                    "timeoutMs 和 syncSecs 不能为 0",
+                    "请使用大于 0 的值，或省略以使用默认值",
+                ));
+            }
+        } else if self.storage_backend == StorageBackend::Redis {
+            issues.push(ConfigIssue::new(
+                "storageBackend",
+                "使用 redis 存储后端但未配置 redis",
+                "请配置 redis.url（或设置 REDIS_URL 环境变量）",
+            ));
+        }
+
+        if self.decoder.max_buffer_bytes < MIN_MESSAGE_SIZE {
+            issues.push(ConfigIssue::new(
+                "decoder.maxBufferBytes",
+                format!("缓冲区上限过小: {}", self.decoder.max_buffer_bytes),
+                format!("请设置不小于 {} 的值", MIN_MESSAGE_SIZE),
+            ));
+        } else if self.decoder.initial_capacity > self.decoder.max_buffer_bytes {
+            issues.push(ConfigIssue::new(
+                "decoder.initialCapacity",
+                "初始容量超过缓冲区上限",
+                "请设置不超过 maxBufferBytes 的值",
+            ));
+        }
+
+        if self.sse.padding_bytes > MAX_SSE_PADDING_BYTES {
+            issues.push(ConfigIssue::new(
+                "sse.paddingBytes",
+                format!("填充字节数过大: {}", self.sse.padding_bytes),
+                format!("请设置不超过 {} 的值", MAX_SSE_PADDING_BYTES),
+            ));
+        }
+
+        issues
+    }
+}
+
+impl KiroCredentials {
+    /// 校验凭证，返回所有发现的问题
+    ///
+    /// 静态 Token（只有 accessToken）不刷新，不检查刷新所需的字段
+    pub fn validate(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+        let refreshable = !self.is_static();
+
+        if refreshable
+            && self
+                .refresh_token
+                .as_deref()
+                .is_none_or(|t| t.trim().is_empty())
+        {
+            issues.push(ConfigIssue::new(
+                "refreshToken",
+                "未设置 refreshToken",
+                "在 credentials.json 中设置 refreshToken 或设置环境变量 REFRESH_TOKEN，\
+                 或只设置 accessToken 作为静态 Token 使用",
+            ));
+        }
+
+        let auth_method = self
+            .auth_method
+            .as_deref()
+            .unwrap_or("social")
+            .to_lowercase();
+        match auth_method.as_str() {
+            "idc" | "builder-id" => {
+                if refreshable && self.client_id.is_none() {
+                    issues.push(ConfigIssue::new(
+                        "clientId",
+                        "IdC 认证缺少 clientId",
+                        "设置 clientId（环境变量 CLIENT_ID），或将 authMethod 改为 social",
+                    ));
+                }
+                if refreshable && self.client_secret.is_none() {
+                    issues.push(ConfigIssue::new(
+                        "clientSecret",
+                        "IdC 认证缺少 clientSecret",
+                        "设置 clientSecret（环境变量 CLIENT_SECRET），或将 authMethod 改为 social",
+                    ));
+                }
+            }
+            "social" => {}
+            other => issues.push(ConfigIssue::new(
+                "authMethod",
+                format!("未知的认证方式: {}", other),
+                "支持的认证方式: social, idc",
+            )),
+        }
+
+        if let Some(url) = &self.refresh_url {
+            check_url("refreshUrl", url, &["http", "https"], &mut issues);
+        }
+        for (field, region) in [("oidcRegion", &self.oidc_region), ("region", &self.region)] {
+            if let Some(region) = region {
+                if !is_valid_region(region) {
+                    issues.push(ConfigIssue::new(
+                        field,
+                        format!("区域格式无效: {}", region),
+                        "请使用 AWS 区域格式，例如 us-east-1",
+                    ));
+                }
+            }
+        }
+
+        issues
+    }
+}
+
+/// 输出问题列表，存在问题时返回 false
+pub fn report_issues(source: &str, issues: &[ConfigIssue]) -> bool {
+    if issues.is_empty() {
+        return true;
+    }
+    tracing::error!("{}存在 {} 个问题:", source, issues.len());
+    for (i, issue) in issues.iter().enumerate() {
+        tracing::error!("  {}. {}", i + 1, issue);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::ListenerConfig;
+
+    fn valid_config() -> Config {
+        Config {
+            api_key: Some("sk-test".to_string()),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_default_config_with_api_key_is_valid() {
+        assert!(valid_config().validate().is_empty());
+    }
+
+    #[test]
+    fn test_region_format() {
+        assert!(is_valid_region("us-east-1"));
+        assert!(is_valid_region("ap-southeast-2"));
+        assert!(is_valid_region("us-gov-west-1"));
+        assert!(!is_valid_region("useast1"));
+        assert!(!is_valid_region("US-EAST-1"));
+        assert!(!is_valid_region("us-east-"));
+    }
+
+    #[test]
+    fn test_collects_multiple_issues() {
+        let config = Config {
+            api_key: None,
+            region: "bad".to_string(),
+            proxy_url: Some("ftp://proxy".to_string()),
+            proxy_username: Some("user".to_string()),
+            count_tokens_api_key: Some("key".to_string()),
+            listeners: vec![
+                ListenerConfig::parse("0.0.0.0:8080").unwrap(),
+                ListenerConfig::parse("0.0.0.0:8080=api").unwrap(),
+            ],
+            path_prefix: "/kiro api".to_string(),
+            ..Config::default()
+        };
+        let fields: Vec<String> = config.validate().into_iter().map(|i| i.field).collect();
+
+        assert_eq!(
+            fields,
+            vec![
+                "apiKey",
+                "region",
+                "proxyUrl",
+                "proxyUsername/proxyPassword",
+                "countTokensApiKey",
+                "listeners",
+                "pathPrefix",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chaos_probability_range() {
+        let config = Config {
+            chaos: Some(crate::model::config::ChaosConfig {
+                delay_probability: 0.5,
+                rate_limit_probability: 1.5,
+                ..Default::default()
+            }),
+            ..valid_config()
+        };
+        let fields: Vec<String> = config.validate().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["chaos.rateLimitProbability"]);
+    }
+
+    #[test]
+    fn test_redis_storage_requires_redis_config() {
+        let config = Config {
+            storage_backend: StorageBackend::Redis,
+            ..valid_config()
+        };
+        let fields: Vec<String> = config.validate().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["storageBackend"]);
+
+        let config = Config {
+            storage_backend: StorageBackend::Redis,
+            redis: Some(crate::model::config::RedisConfig {
+                url: "http://127.0.0.1:6379".to_string(),
+                key_prefix: "kiro:".to_string(),
+                timeout_ms: 500,
+                sync_secs: 10,
+            }),
+            ..valid_config()
+        };
+        let fields: Vec<String> = config.validate().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["redis.url"]);
+    }
+
+    #[test]
+    fn test_decoder_limits() {
+        let config = Config {
+            decoder: crate::model::config::DecoderBufferConfig {
+                max_buffer_bytes: 4096,
+                initial_capacity: 8192,
+                ..Default::default()
+            },
+            ..valid_config()
+        };
+        let fields: Vec<String> = config.validate().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["decoder.initialCapacity"]);
+    }
+
+    #[test]
+    fn test_quota_warning_threshold_range() {
+        let config = Config {
+            quota_warning_thresholds: vec![80, 0, 100],
+            ..valid_config()
+        };
+        let fields: Vec<String> = config.validate().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["quotaWarningThresholds"; 2]);
+    }
+
+    #[test]
+    fn test_unknown_server_tool() {
+        let config = Config {
+            server_tools: vec!["current_time".to_string(), "shell".to_string()],
+            ..valid_config()
+        };
+        let fields: Vec<String> = config.validate().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["serverTools"]);
+    }
+
+    #[test]
+    fn test_sse_padding_limit() {
+        let config = Config {
+            sse: crate::model::config::SseConfig {
+                padding_bytes: 1024 * 1024,
+                ..Default::default()
+            },
+            ..valid_config()
+        };
+        let fields: Vec<String> = config.validate().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["sse.paddingBytes"]);
+    }
+
+    #[test]
+    fn test_workspace_issues() {
+        let workspace = |name: &str, key: &str| crate::model::config::WorkspaceConfig {
+            name: name.to_string(),
+            api_key: key.to_string(),
+            data_dir: None,
+            system_prompt: None,
+            priority: Default::default(),
+            timeouts: Default::default(),
+        };
+        let config = Config {
+            workspaces: vec![
+                workspace("team-a", "key-a"),
+                workspace("team-a", "key-b"),
+                workspace("team/b", "sk-test"),
+            ],
+            ..valid_config()
+        };
+        let messages: Vec<String> = config.validate().into_iter().map(|i| i.message).collect();
+
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].contains("名称重复"));
+        assert!(messages[1].contains("名称无效"));
+        assert!(messages[2].contains("apiKey"));
+    }
+
+    #[test]
+    fn test_idc_credentials_require_client() {
+        let creds = KiroCredentials {
+            refresh_token: Some("token".to_string()),
+            auth_method: Some("idc".to_string()),
+            ..Default::default()
+        };
+        let fields: Vec<String> = creds.validate().into_iter().map(|i| i.field).collect();
+        assert_eq!(fields, vec!["clientId", "clientSecret"]);
+    }
+}
@@ -4,6 +4,8 @@ use crate::kiro::model::credentials::KiroCredentials;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::schedule::{self, UsageWindow};
+
 /// 账号状态
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -16,6 +18,8 @@ pub enum AccountStatus {
     Invalid,
     /// 已禁用
     Disabled,
+    /// 排空中：不再接受新请求，进行中的请求结束后转为禁用
+    Draining,
 }
 
 /// 账号信息
@@ -40,6 +44,9 @@ pub struct Account {
     pub cooldown_until: Option<DateTime<Utc>>,
     /// 创建时间
     pub created_at: DateTime<Utc>,
+    /// 每日使用时间窗口（UTC，为空时全天可用）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage_windows: Vec<UsageWindow>,
 }
 
 impl Account {
@@ -59,11 +66,15 @@ impl Account {
             last_used_at: None,
             cooldown_until: None,
             created_at: Utc::now(),
+            usage_windows: Vec::new(),
         }
     }
 
-    /// 检查是否可用
+    /// 检查是否可用（处于使用时间窗口之外时不可用）
     pub fn is_available(&self) -> bool {
+        if !schedule::within_windows(&self.usage_windows, Utc::now()) {
+            return false;
+        }
         match self.status {
             AccountStatus::Active => true,
             AccountStatus::Cooldown => {
@@ -76,6 +87,20 @@ impl Account {
         }
     }
 
+    /// 冷却剩余时间（未处于冷却或冷却已结束时返回 None）
+    pub fn cooldown_remaining(&self) -> Option<chrono::Duration> {
+        if self.status != AccountStatus::Cooldown {
+            return None;
+        }
+        let remaining = self.cooldown_until? - Utc::now();
+        (remaining > chrono::Duration::zero()).then_some(remaining)
+    }
+
+    /// 距离下一个使用时间窗口开始的时长（当前处于窗口内时返回 None）
+    pub fn window_opens_in(&self) -> Option<chrono::Duration> {
+        schedule::next_window_in(&self.usage_windows, Utc::now())
+    }
+
     /// 记录使用
     pub fn record_use(&mut self) {
         self.request_count += 1;
@@ -90,7 +115,7 @@ impl Account {
     /// 记录错误
     pub fn record_error(&mut self, is_rate_limit: bool) {
         self.error_count += 1;
-        if is_rate_limit {
+        if is_rate_limit && self.status != AccountStatus::Draining {
             // 限流，进入冷却
             self.status = AccountStatus::Cooldown;
             self.cooldown_until = Some(Utc::now() + chrono::Duration::minutes(5));
@@ -102,15 +127,81 @@ impl Account {
         self.status = AccountStatus::Invalid;
     }
 
-    /// 启用账号
+    /// 启用账号（也用于取消排空）
     pub fn enable(&mut self) {
-        if self.status == AccountStatus::Disabled {
+        if matches!(
+            self.status,
+            AccountStatus::Disabled | AccountStatus::Draining
+        ) {
             self.status = AccountStatus::Active;
         }
     }
 
+    /// 开始排空，只有可用或冷却中的账号可以排空（已在排空中时返回 true）
+    pub fn drain(&mut self) -> bool {
+        match self.status {
+            AccountStatus::Active | AccountStatus::Cooldown => {
+                self.status = AccountStatus::Draining;
+                self.cooldown_until = None;
+                true
+            }
+            AccountStatus::Draining => true,
+            AccountStatus::Invalid | AccountStatus::Disabled => false,
+        }
+    }
+
     /// 禁用账号
     pub fn disable(&mut self) {
         self.status = AccountStatus::Disabled;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cooldown_remaining() {
+        let mut account = Account::new("id", "name", KiroCredentials::default());
+        assert!(account.cooldown_remaining().is_none());
+
+        account.record_error(true);
+        let remaining = account.cooldown_remaining().unwrap();
+        assert!(remaining > chrono::Duration::minutes(4));
+        assert!(!account.is_available());
+
+        account.cooldown_until = Some(Utc::now() - chrono::Duration::seconds(1));
+        assert!(account.cooldown_remaining().is_none());
+        assert!(account.is_available());
+    }
+
+    #[test]
+    fn test_usage_window_availability() {
+        let mut account = Account::new("id", "name", KiroCredentials::default());
+        let now = Utc::now().time();
+        let window = |from: chrono::TimeDelta, to: chrono::TimeDelta| {
+            UsageWindow::parse(&format!(
+                "{}-{}",
+                (now + from).format("%H:%M"),
+                (now + to).format("%H:%M")
+            ))
+            .unwrap()
+        };
+
+        account.usage_windows = vec![window(
+            chrono::Duration::hours(2),
+            chrono::Duration::hours(3),
+        )];
+        assert!(!account.is_available());
+        let opens_in = account.window_opens_in().unwrap();
+        assert!(opens_in <= chrono::Duration::hours(2));
+        assert!(opens_in > chrono::Duration::minutes(118));
+
+        account.usage_windows = vec![window(
+            chrono::Duration::hours(-1),
+            chrono::Duration::hours(1),
+        )];
+        assert!(account.is_available());
+        assert!(account.window_opens_in().is_none());
+    }
+}
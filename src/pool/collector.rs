@@ -0,0 +1,306 @@
+//! 使用记录外部采集
+//!
+//! 将请求记录按批 POST 到外部 HTTP 采集端点，供外部计费系统使用。
+//! 发送失败时按指数退避重试，仍失败则追加到本地暂存文件（JSON Lines），下次发送时优先补发。
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use reqwest::Client;
+use serde_json::json;
+use tokio::sync::mpsc;
+
+use crate::http_client::{build_client, ProxyConfig};
+use crate::model::config::Config;
+
+use super::usage::RequestLog;
+
+/// 采集端点请求超时（秒）
+const COLLECTOR_TIMEOUT_SECS: u64 = 30;
+/// 单批最大重试次数
+const MAX_ATTEMPTS: u32 = 3;
+/// 首次重试等待时间
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+/// 本地暂存的最大记录数，超出时丢弃最早的记录
+const MAX_SPOOLED_RECORDS: usize = 10_000;
+
+/// 采集配置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectorConfig {
+    pub url: String,
+    pub token: Option<String>,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+}
+
+impl CollectorConfig {
+    /// 从全局配置读取，未配置采集地址时返回 None
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let url = config.usage_collector_url.clone()?;
+        Some(Self {
+            url,
+            token: config.usage_collector_token.clone(),
+            batch_size: config.usage_collector_batch_size.max(1),
+            flush_interval: Duration::from_secs(config.usage_collector_flush_secs.max(1)),
+        })
+    }
+}
+
+/// 使用记录采集器
+///
+/// 记录通过 channel 交给后台任务，`record` 不会阻塞请求处理
+pub struct UsageCollector {
+    tx: mpsc::UnboundedSender<RequestLog>,
+}
+
+impl UsageCollector {
+    /// 启动后台发送任务，未配置采集地址时返回 None
+    pub fn spawn(
+        config: &Config,
+        proxy: Option<&ProxyConfig>,
+        spool_path: Option<PathBuf>,
+    ) -> Option<Self> {
+        let config = CollectorConfig::from_config(config)?;
+        let client = match build_client(proxy, COLLECTOR_TIMEOUT_SECS) {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("创建使用记录采集 HTTP 客户端失败: {}", e);
+                return None;
+            }
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        let sender = BatchSender {
+            client,
+            config,
+            spool_path,
+            retry_delay: RETRY_BASE_DELAY,
+        };
+        tokio::spawn(sender.run(rx));
+        Some(Self { tx })
+    }
+
+    /// 提交一条请求记录
+    pub fn record(&self, log: &RequestLog) {
+        if self.tx.send(log.clone()).is_err() {
+            tracing::warn!("使用记录采集任务已退出，丢弃记录 {}", log.id);
+        }
+    }
+}
+
+/// 后台批量发送任务
+struct BatchSender {
+    client: Client,
+    config: CollectorConfig,
+    spool_path: Option<PathBuf>,
+    /// 首次重试等待时间，之后每次翻倍
+    retry_delay: Duration,
+}
+
+impl BatchSender {
+    async fn run(self, mut rx: mpsc::UnboundedReceiver<RequestLog>) {
+        let mut batch = Vec::with_capacity(self.config.batch_size);
+        let mut ticker = tokio::time::interval(self.config.flush_interval);
+        ticker.tick().await;
+
+        loop {
+            tokio::select! {
+                log = rx.recv() => match log {
+                    Some(log) => {
+                        batch.push(log);
+                        if batch.len() >= self.config.batch_size {
+                            self.flush(std::mem::take(&mut batch)).await;
+                        }
+                    }
+                    None => {
+                        self.flush(batch).await;
+                        return;
+                    }
+                },
+                _ = ticker.tick() => {
+                    if !batch.is_empty() || self.has_spool().await {
+                        self.flush(std::mem::take(&mut batch)).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 发送暂存记录和本批记录，失败时写回暂存文件
+    async fn flush(&self, batch: Vec<RequestLog>) {
+        let mut records = self.load_spool().await;
+        records.extend(batch);
+        if records.is_empty() {
+            return;
+        }
+
+        match self.send_with_retry(&records).await {
+            Ok(()) => {
+                tracing::debug!("已发送 {} 条使用记录到采集端点", records.len());
+                self.clear_spool().await;
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "发送使用记录失败，{} 条记录已暂存到本地: {}",
+                    records.len(),
+                    e
+                );
+                self.save_spool(records).await;
+            }
+        }
+    }
+
+    async fn send_with_retry(&self, records: &[RequestLog]) -> anyhow::Result<()> {
+        let mut delay = self.retry_delay;
+        let mut attempt = 1;
+        loop {
+            match self.send(records).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= MAX_ATTEMPTS => return Err(e),
+                Err(e) => {
+                    tracing::debug!("发送使用记录失败（第 {} 次）: {}", attempt, e);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn send(&self, records: &[RequestLog]) -> anyhow::Result<()> {
+        let mut request = self.client.post(&self.config.url).json(&json!({
+            "records": records,
+            "sentAt": chrono::Utc::now().to_rfc3339(),
+        }));
+        if let Some(token) = &self.config.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            anyhow::bail!("采集端点返回错误状态: {}", response.status());
+        }
+        Ok(())
+    }
+
+    async fn has_spool(&self) -> bool {
+        match &self.spool_path {
+            Some(path) => tokio::fs::try_exists(path).await.unwrap_or(false),
+            None => false,
+        }
+    }
+
+    async fn load_spool(&self) -> Vec<RequestLog> {
+        let Some(path) = &self.spool_path else {
+            return Vec::new();
+        };
+        match tokio::fs::read_to_string(path).await {
+            Ok(content) => parse_spool(&content),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    async fn save_spool(&self, records: Vec<RequestLog>) {
+        let Some(path) = &self.spool_path else {
+            tracing::warn!("未配置数据目录，丢弃 {} 条未发送的使用记录", records.len());
+            return;
+        };
+        if let Err(e) = tokio::fs::write(path, format_spool(records)).await {
+            tracing::error!("写入使用记录暂存文件失败: {}", e);
+        }
+    }
+
+    async fn clear_spool(&self) {
+        if let Some(path) = &self.spool_path {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+}
+
+/// 解析暂存文件，跳过损坏的行
+fn parse_spool(content: &str) -> Vec<RequestLog> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// 序列化暂存记录，只保留最近的 MAX_SPOOLED_RECORDS 条
+fn format_spool(records: Vec<RequestLog>) -> String {
+    let skip = records.len().saturating_sub(MAX_SPOOLED_RECORDS);
+    if skip > 0 {
+        tracing::warn!("使用记录暂存已满，丢弃最早的 {} 条记录", skip);
+    }
+    records
+        .iter()
+        .skip(skip)
+        .filter_map(|record| serde_json::to_string(record).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(id: &str) -> RequestLog {
+        RequestLog {
+            id: id.to_string(),
+            account_id: "acc".to_string(),
+            account_name: "work".to_string(),
+            model: "claude-sonnet-4".to_string(),
+            input_tokens: 10,
+            output_tokens: 5,
+            success: true,
+            error: None,
+            timestamp: chrono::Utc::now(),
+            duration_ms: 100,
+            client: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_collector_requires_url() {
+        assert!(CollectorConfig::from_config(&Config::default()).is_none());
+
+        let config = Config {
+            usage_collector_url: Some("https://billing.example.com/usage".to_string()),
+            usage_collector_batch_size: 0,
+            ..Config::default()
+        };
+        let collector = CollectorConfig::from_config(&config).unwrap();
+        assert_eq!(collector.batch_size, 1);
+    }
+
+    #[test]
+    fn test_spool_roundtrip_skips_corrupt_lines() {
+        let mut content = format_spool(vec![log("a"), log("b")]);
+        content.push_str("not json\n");
+
+        let ids: Vec<String> = parse_spool(&content).into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_spools_records() {
+        let dir = std::env::temp_dir().join(format!("kiro-collector-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let sender = BatchSender {
+            client: Client::new(),
+            config: CollectorConfig {
+                // 端口 9 (discard) 通常无服务监听，请求会立即失败
+                url: "http://127.0.0.1:9/usage".to_string(),
+                token: None,
+                batch_size: 10,
+                flush_interval: Duration::from_secs(60),
+            },
+            spool_path: Some(dir.join("usage_spool.jsonl")),
+            retry_delay: Duration::from_millis(1),
+        };
+
+        sender.flush(vec![log("a")]).await;
+        assert!(sender.has_spool().await);
+        assert_eq!(sender.load_spool().await.len(), 1);
+
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+    }
+}
@@ -0,0 +1,105 @@
+//! 账号错误预算
+//!
+//! 按账号统计 `errorBudget.windowSecs` 内的请求结果，请求数达到 `minRequests` 且错误率超过
+//! `threshold` 时判定超出预算，由账号池自动禁用账号并发送 `error_budget_exceeded` 通知。
+//! 用于发现 profileArn 配置错误等不会触发失效或限流、但持续返回错误的账号。
+//! 限流、上游过载和请求格式错误不计入错误。
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+
+use crate::model::config::ErrorBudgetConfig;
+
+/// 超出预算时的统计
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BudgetExceeded {
+    /// 窗口内的错误率（0.0 - 1.0）
+    pub error_rate: f64,
+    /// 窗口内的请求数
+    pub requests: usize,
+}
+
+/// 按账号的滑动窗口错误率
+pub struct ErrorBudget {
+    config: ErrorBudgetConfig,
+    /// 账号 ID -> (时间, 是否成功)
+    outcomes: DashMap<String, VecDeque<(Instant, bool)>>,
+}
+
+impl ErrorBudget {
+    pub fn new(config: ErrorBudgetConfig) -> Self {
+        Self {
+            config,
+            outcomes: DashMap::new(),
+        }
+    }
+
+    pub fn window_secs(&self) -> u64 {
+        self.config.window_secs
+    }
+
+    /// 记录一次请求结果，记录的是失败且超出预算时返回窗口内的统计
+    pub fn record(&self, id: &str, success: bool) -> Option<BudgetExceeded> {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.window_secs);
+        let mut outcomes = self.outcomes.entry(id.to_string()).or_default();
+        outcomes.push_back((now, success));
+        while outcomes
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > window)
+        {
+            outcomes.pop_front();
+        }
+
+        let requests = outcomes.len();
+        if success || requests < self.config.min_requests {
+            return None;
+        }
+        let errors = outcomes.iter().filter(|(_, success)| !success).count();
+        let error_rate = errors as f64 / requests as f64;
+        (error_rate > self.config.threshold).then_some(BudgetExceeded {
+            error_rate,
+            requests,
+        })
+    }
+
+    /// 清空账号的统计（重新启用或移除账号时）
+    pub fn reset(&self, id: &str) {
+        self.outcomes.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_rate_over_window() {
+        let budget = ErrorBudget::new(ErrorBudgetConfig {
+            threshold: 0.5,
+            window_secs: 60,
+            min_requests: 4,
+        });
+
+        // 请求数不足时不判断
+        assert_eq!(budget.record("a", false), None);
+        assert_eq!(budget.record("a", false), None);
+        assert_eq!(budget.record("a", true), None);
+
+        let exceeded = budget.record("a", false).unwrap();
+        assert_eq!(exceeded.requests, 4);
+        assert_eq!(exceeded.error_rate, 0.75);
+
+        // 其他账号互不影响，错误率等于阈值时不超出
+        for success in [true, false, true, false] {
+            assert_eq!(budget.record("b", success), None);
+        }
+        // 成功请求不触发判断
+        assert_eq!(budget.record("a", true), None);
+
+        budget.reset("a");
+        assert_eq!(budget.record("a", false), None);
+    }
+}
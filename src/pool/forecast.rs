@@ -0,0 +1,169 @@
+//! 账号配额消耗预测
+//!
+//! 每次刷新配额时记录一次使用量采样，按最近采样的消耗速率估算配额在下次重置前何时用尽，
+//! 用于管理面板展示和配额告警，便于在硬性失败前补充账号。
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::usage::UsageLimits;
+
+/// 每个账号保留的采样数
+const MAX_SAMPLES: usize = 100;
+
+/// 计算速率所需的最短采样跨度（秒），避免相邻两次刷新放大误差
+const MIN_SPAN_SECS: i64 = 60;
+
+/// 配额使用量采样
+#[derive(Debug, Default)]
+pub struct UsageHistory {
+    /// (采样时间, 当前使用量)
+    samples: VecDeque<(DateTime<Utc>, f64)>,
+}
+
+impl UsageHistory {
+    /// 记录一次采样，使用量下降（配额已重置）时丢弃之前的采样
+    pub fn record(&mut self, at: DateTime<Utc>, current_usage: f64) {
+        if self
+            .samples
+            .back()
+            .is_some_and(|(_, last)| current_usage < *last)
+        {
+            self.samples.clear();
+        }
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, current_usage));
+    }
+
+    /// 每小时消耗量，采样不足时返回 None
+    pub fn rate_per_hour(&self) -> Option<f64> {
+        let (first_at, first) = self.samples.front()?;
+        let (last_at, last) = self.samples.back()?;
+        let span = (*last_at - *first_at).num_seconds();
+        if span < MIN_SPAN_SECS {
+            return None;
+        }
+        Some((last - first) / span as f64 * 3600.0)
+    }
+}
+
+/// 账号配额预测
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageForecast {
+    pub id: String,
+    pub name: String,
+    /// 已用配额百分比（0-100）
+    pub usage_percent: f64,
+    /// 每小时消耗量（采样不足时为 None）
+    pub rate_per_hour: Option<f64>,
+    /// 按当前速率预计用尽时间（下次重置前不会用尽时为 None）
+    pub exhausts_at: Option<DateTime<Utc>>,
+    pub next_reset: Option<DateTime<Utc>>,
+}
+
+impl UsageForecast {
+    pub fn new(
+        id: &str,
+        name: &str,
+        usage: &UsageLimits,
+        history: Option<&UsageHistory>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let rate_per_hour = history.and_then(UsageHistory::rate_per_hour);
+        let exhausts_at = rate_per_hour
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| {
+                let hours = usage.available.max(0.0) / rate;
+                now + chrono::Duration::seconds((hours * 3600.0) as i64)
+            })
+            .filter(|at| usage.next_reset.is_none_or(|reset| *at < reset));
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            usage_percent: usage_percent(usage).unwrap_or(0.0),
+            rate_per_hour,
+            exhausts_at,
+            next_reset: usage.next_reset,
+        }
+    }
+}
+
+/// 已用配额百分比，限额未知时返回 None
+pub fn usage_percent(usage: &UsageLimits) -> Option<f64> {
+    (usage.usage_limit > 0.0).then(|| usage.current_usage / usage.usage_limit * 100.0)
+}
+
+/// 使用率从 `before` 变为 `after` 时跨过的最高告警阈值
+pub fn crossed_threshold(thresholds: &[u8], before: Option<f64>, after: f64) -> Option<u8> {
+    thresholds
+        .iter()
+        .copied()
+        .filter(|t| {
+            let t = f64::from(*t);
+            after >= t && before.is_none_or(|before| before < t)
+        })
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(current: f64, limit: f64, next_reset: Option<DateTime<Utc>>) -> UsageLimits {
+        UsageLimits {
+            resource_type: "CREDIT".to_string(),
+            usage_limit: limit,
+            current_usage: current,
+            available: limit - current,
+            next_reset,
+            free_trial: None,
+            user_email: None,
+            subscription_type: None,
+        }
+    }
+
+    #[test]
+    fn test_forecast_exhaustion() {
+        let now = Utc::now();
+        let mut history = UsageHistory::default();
+        history.record(now - chrono::Duration::hours(2), 40.0);
+        history.record(now, 60.0);
+        assert_eq!(history.rate_per_hour(), Some(10.0));
+
+        // 剩余 40，每小时 10，4 小时后用尽
+        let forecast =
+            UsageForecast::new("a", "work", &usage(60.0, 100.0, None), Some(&history), now);
+        assert_eq!(forecast.usage_percent, 60.0);
+        assert_eq!(forecast.exhausts_at, Some(now + chrono::Duration::hours(4)));
+
+        // 重置前不会用尽
+        let reset = Some(now + chrono::Duration::hours(1));
+        let forecast =
+            UsageForecast::new("a", "work", &usage(60.0, 100.0, reset), Some(&history), now);
+        assert!(forecast.exhausts_at.is_none());
+    }
+
+    #[test]
+    fn test_history_resets_and_needs_span() {
+        let now = Utc::now();
+        let mut history = UsageHistory::default();
+        history.record(now - chrono::Duration::hours(1), 90.0);
+        history.record(now - chrono::Duration::seconds(30), 5.0);
+        history.record(now, 6.0);
+        assert_eq!(history.rate_per_hour(), None);
+        assert_eq!(history.samples.len(), 2);
+    }
+
+    #[test]
+    fn test_crossed_threshold() {
+        assert_eq!(crossed_threshold(&[80, 95], Some(70.0), 85.0), Some(80));
+        assert_eq!(crossed_threshold(&[80, 95], Some(70.0), 96.0), Some(95));
+        assert_eq!(crossed_threshold(&[80, 95], Some(85.0), 90.0), None);
+        assert_eq!(crossed_threshold(&[80], None, 80.0), Some(80));
+        assert_eq!(crossed_threshold(&[], None, 99.0), None);
+    }
+}
@@ -0,0 +1,174 @@
+//! 账号健康度评分
+//!
+//! 综合 Token 刷新成功率、最近请求错误率、最近请求延迟和剩余配额计算 0-100 的健康分，
+//! 用于管理面板展示和 `health-weighted` 选择策略。尚无数据的指标按满分计算，新账号不会被冷落。
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+/// 每个账号保留的最近请求数
+const WINDOW_SIZE: usize = 50;
+
+/// 平均延迟不超过该值时延迟得分为满分（毫秒）
+const GOOD_LATENCY_MS: f64 = 2_000.0;
+/// 平均延迟达到该值时延迟得分为零（毫秒）
+const BAD_LATENCY_MS: f64 = 30_000.0;
+
+/// 各指标权重（合计为 1）
+const REFRESH_WEIGHT: f64 = 0.3;
+const ERROR_WEIGHT: f64 = 0.3;
+const LATENCY_WEIGHT: f64 = 0.2;
+const QUOTA_WEIGHT: f64 = 0.2;
+
+/// 最近请求结果窗口
+#[derive(Debug, Default)]
+pub struct HealthTracker {
+    /// (是否成功, 耗时毫秒)
+    outcomes: VecDeque<(bool, u64)>,
+}
+
+impl HealthTracker {
+    /// 记录一次请求结果
+    pub fn record(&mut self, success: bool, duration_ms: u64) {
+        if self.outcomes.len() == WINDOW_SIZE {
+            self.outcomes.pop_front();
+        }
+        self.outcomes.push_back((success, duration_ms));
+    }
+
+    /// 最近请求的错误率
+    pub fn error_ratio(&self) -> Option<f64> {
+        if self.outcomes.is_empty() {
+            return None;
+        }
+        let errors = self.outcomes.iter().filter(|(success, _)| !success).count();
+        Some(errors as f64 / self.outcomes.len() as f64)
+    }
+
+    /// 最近成功请求的平均延迟（失败请求的耗时不代表上游延迟，不计入）
+    pub fn avg_latency_ms(&self) -> Option<f64> {
+        let latencies: Vec<u64> = self
+            .outcomes
+            .iter()
+            .filter(|(success, _)| *success)
+            .map(|(_, ms)| *ms)
+            .collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        Some(latencies.iter().sum::<u64>() as f64 / latencies.len() as f64)
+    }
+}
+
+/// 账号健康度
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AccountHealth {
+    pub account_id: String,
+    pub account_name: String,
+    /// 健康分（0-100）
+    pub score: f64,
+    /// Token 刷新成功率（0-1）
+    pub refresh_success_rate: Option<f64>,
+    /// 最近请求错误率（0-1）
+    pub error_ratio: Option<f64>,
+    /// 最近成功请求平均延迟（毫秒）
+    pub avg_latency_ms: Option<f64>,
+    /// 剩余配额比例（0-1）
+    pub quota_remaining: Option<f64>,
+}
+
+impl AccountHealth {
+    /// 根据各项指标计算健康分
+    pub fn new(
+        account_id: impl Into<String>,
+        account_name: impl Into<String>,
+        refresh_success_rate: Option<f64>,
+        tracker: Option<&HealthTracker>,
+        quota_remaining: Option<f64>,
+    ) -> Self {
+        let error_ratio = tracker.and_then(HealthTracker::error_ratio);
+        let avg_latency_ms = tracker.and_then(HealthTracker::avg_latency_ms);
+
+        let latency_score = avg_latency_ms.map(|ms| {
+            1.0 - ((ms - GOOD_LATENCY_MS) / (BAD_LATENCY_MS - GOOD_LATENCY_MS)).clamp(0.0, 1.0)
+        });
+        let score = REFRESH_WEIGHT * refresh_success_rate.unwrap_or(1.0)
+            + ERROR_WEIGHT * (1.0 - error_ratio.unwrap_or(0.0))
+            + LATENCY_WEIGHT * latency_score.unwrap_or(1.0)
+            + QUOTA_WEIGHT * quota_remaining.unwrap_or(1.0).clamp(0.0, 1.0);
+
+        Self {
+            account_id: account_id.into(),
+            account_name: account_name.into(),
+            score: (score * 100.0).round(),
+            refresh_success_rate,
+            error_ratio,
+            avg_latency_ms,
+            quota_remaining,
+        }
+    }
+}
+
+/// 按健康分加权选择，返回下标
+///
+/// 权重为健康分的平方（至少为 1），`r` 为 [0, 1) 内的随机数
+pub fn weighted_index(scores: &[f64], r: f64) -> usize {
+    let weights: Vec<f64> = scores.iter().map(|s| s.max(1.0).powi(2)).collect();
+    let mut target = r * weights.iter().sum::<f64>();
+    for (i, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return i;
+        }
+        target -= weight;
+    }
+    scores.len().saturating_sub(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_account_is_fully_healthy() {
+        let health = AccountHealth::new("id", "name", None, None, None);
+        assert_eq!(health.score, 100.0);
+    }
+
+    #[test]
+    fn test_score_combines_metrics() {
+        let mut tracker = HealthTracker::default();
+        for i in 0..5 {
+            tracker.record(i != 0, 1_000);
+        }
+        let health = AccountHealth::new("id", "name", Some(0.5), Some(&tracker), Some(0.0));
+
+        assert_eq!(health.error_ratio, Some(0.2));
+        assert_eq!(health.avg_latency_ms, Some(1_000.0));
+        // 0.3 * 0.5 + 0.3 * 0.8 + 0.2 * 1.0 + 0.2 * 0.0
+        assert_eq!(health.score, 59.0);
+    }
+
+    #[test]
+    fn test_tracker_keeps_recent_window() {
+        let mut tracker = HealthTracker::default();
+        tracker.record(false, 0);
+        for _ in 0..WINDOW_SIZE {
+            tracker.record(true, 40_000);
+        }
+        assert_eq!(tracker.error_ratio(), Some(0.0));
+
+        let health = AccountHealth::new("id", "name", None, Some(&tracker), None);
+        assert_eq!(health.score, 80.0);
+    }
+
+    #[test]
+    fn test_weighted_index_prefers_healthy_accounts() {
+        // 权重 100 和 10000
+        let scores = [10.0, 100.0];
+        assert_eq!(weighted_index(&scores, 0.0), 0);
+        assert_eq!(weighted_index(&scores, 0.005), 0);
+        assert_eq!(weighted_index(&scores, 0.01), 1);
+        assert_eq!(weighted_index(&scores, 0.999), 1);
+    }
+}
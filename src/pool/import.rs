@@ -0,0 +1,159 @@
+//! 从环境变量导入账号池
+//!
+//! `ACCOUNTS_JSON` 为账号数组（原始 JSON 或 base64 编码），适用于 Railway、Fly.io 等
+//! 不便挂载文件的容器平台。每项包含与 credentials.json 相同的凭证字段，以及可选的
+//! `id` 和 `name`。未指定 `id` 时按 refreshToken（只有 accessToken 的静态 Token 按 accessToken）
+//! 生成固定 ID，重启后不会重复导入。
+
+use base64::Engine;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::kiro::model::credentials::KiroCredentials;
+
+use super::account::Account;
+
+/// 账号池 JSON 环境变量名
+pub const ACCOUNTS_JSON_ENV: &str = "ACCOUNTS_JSON";
+
+/// 环境变量中的单个账号
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EnvAccount {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(flatten)]
+    credentials: KiroCredentials,
+}
+
+/// 解析 `ACCOUNTS_JSON` 的值
+///
+/// 以 `[` 开头时按原始 JSON 解析，否则先按 base64（标准或 URL 安全字母表）解码
+pub fn parse_accounts_json(value: &str) -> anyhow::Result<Vec<Account>> {
+    let value = value.trim();
+    let json = if value.starts_with('[') {
+        value.to_string()
+    } else {
+        let engines = [
+            base64::engine::general_purpose::STANDARD,
+            base64::engine::general_purpose::URL_SAFE,
+        ];
+        let bytes = engines
+            .iter()
+            .find_map(|engine| engine.decode(value).ok())
+            .ok_or_else(|| anyhow::anyhow!("既不是 JSON 数组，也不是有效的 base64"))?;
+        String::from_utf8(bytes)?
+    };
+
+    let entries: Vec<EnvAccount> = serde_json::from_str(&json)?;
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| into_account(index, entry))
+        .collect()
+}
+
+fn into_account(index: usize, entry: EnvAccount) -> anyhow::Result<Account> {
+    let mut credentials = entry.credentials;
+    let is_static = credentials.is_static();
+    let token = if is_static {
+        credentials.access_token.as_deref()
+    } else {
+        credentials
+            .refresh_token
+            .as_deref()
+            .filter(|t| !t.is_empty())
+    }
+    .ok_or_else(|| {
+        anyhow::anyhow!(
+            "第 {} 个账号缺少 refreshToken（或用作静态 Token 的 accessToken）",
+            index + 1
+        )
+    })?;
+
+    let id = entry.id.unwrap_or_else(|| {
+        let hash = hex::encode(Sha256::digest(token.as_bytes()));
+        format!("env-{}", &hash[..16])
+    });
+    let name = entry
+        .name
+        .unwrap_or_else(|| format!("环境变量账号 {}", index + 1));
+
+    // 与 UI 导入一致：未指定认证方式时按是否有 clientId/clientSecret 推断
+    if credentials.auth_method.is_none() {
+        let idc = credentials.client_id.is_some() && credentials.client_secret.is_some();
+        credentials.auth_method = Some(if idc { "idc" } else { "social" }.to_string());
+    }
+    // 未提供过期时间时视为已过期，首次使用时刷新 Token；静态 Token 无法刷新，保持未设置
+    if credentials.expires_at.is_none() && !is_static {
+        credentials.expires_at = Some("2000-01-01T00:00:00Z".to_string());
+    }
+
+    Ok(Account::new(id, name, credentials))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::Config;
+    use crate::pool::AccountPool;
+
+    const ACCOUNTS: &str = r#"[
+        {"refreshToken": "token-a"},
+        {"id": "b", "name": "work", "refreshToken": "token-b",
+         "clientId": "cid", "clientSecret": "secret", "region": "eu-central-1"}
+    ]"#;
+
+    #[test]
+    fn test_parse_raw_and_base64() {
+        let raw = parse_accounts_json(ACCOUNTS).unwrap();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(ACCOUNTS);
+        let decoded = parse_accounts_json(&encoded).unwrap();
+
+        for accounts in [&raw, &decoded] {
+            assert_eq!(accounts.len(), 2);
+            assert!(accounts[0].id.starts_with("env-"));
+            assert_eq!(
+                accounts[0].credentials.auth_method.as_deref(),
+                Some("social")
+            );
+            assert_eq!(accounts[1].id, "b");
+            assert_eq!(accounts[1].name, "work");
+            assert_eq!(accounts[1].credentials.auth_method.as_deref(), Some("idc"));
+            assert_eq!(
+                accounts[1].credentials.region.as_deref(),
+                Some("eu-central-1")
+            );
+        }
+        // 未指定 id 时按 refreshToken 生成固定 ID
+        assert_eq!(raw[0].id, decoded[0].id);
+
+        // 只有 accessToken 时作为静态 Token 导入，不设置过期时间
+        let accounts = parse_accounts_json(r#"[{"accessToken": "static"}]"#).unwrap();
+        assert!(accounts[0].id.starts_with("env-"));
+        assert!(accounts[0].credentials.is_static());
+        assert!(accounts[0].credentials.expires_at.is_none());
+    }
+
+    #[test]
+    fn test_rejects_invalid_input() {
+        assert!(parse_accounts_json("not json or base64!").is_err());
+        let err = parse_accounts_json(r#"[{"refreshToken": "a"}, {"name": "x"}]"#).unwrap_err();
+        assert!(err.to_string().contains("第 2 个账号"));
+    }
+
+    #[tokio::test]
+    async fn test_import_skips_existing_accounts() {
+        let pool = AccountPool::new(Config::default(), None);
+        let accounts = parse_accounts_json(ACCOUNTS).unwrap();
+        assert_eq!(pool.add_missing_accounts(accounts).await.unwrap(), 2);
+        pool.record_error("b", false).await;
+
+        // 再次导入（如容器重启）不会覆盖已有账号的运行状态
+        let accounts = parse_accounts_json(ACCOUNTS).unwrap();
+        assert_eq!(pool.add_missing_accounts(accounts).await.unwrap(), 0);
+        assert_eq!(pool.get_stats().await.total_errors, 1);
+    }
+}
@@ -0,0 +1,85 @@
+//! 账号进行中请求计数
+//!
+//! 选中账号时取得 [`InFlightGuard`]，守卫随响应体一起释放（流式响应在流结束或客户端断开时释放）。
+//! 排空账号时等待计数归零后再禁用，避免中断进行中的对话。
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+/// 单个账号的进行中请求计数
+#[derive(Default)]
+pub struct InFlight {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+impl InFlight {
+    /// 当前进行中的请求数
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    /// 记录一个新请求
+    pub fn acquire(self: &Arc<Self>) -> InFlightGuard {
+        self.count.fetch_add(1, Ordering::AcqRel);
+        InFlightGuard(self.clone())
+    }
+
+    /// 等待进行中的请求全部结束
+    pub async fn wait_idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            // 先注册等待再检查计数，避免错过检查与等待之间的通知
+            notified.as_mut().enable();
+            if self.count() == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// 进行中请求的守卫，释放时计数减一
+pub struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_wait_idle_after_last_guard_dropped() {
+        let in_flight = Arc::new(InFlight::default());
+        in_flight.wait_idle().await;
+
+        let first = in_flight.acquire();
+        let second = in_flight.acquire();
+        assert_eq!(in_flight.count(), 2);
+
+        let waiter = tokio::spawn({
+            let in_flight = in_flight.clone();
+            async move { in_flight.wait_idle().await }
+        });
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        drop(second);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(in_flight.count(), 0);
+    }
+}
@@ -0,0 +1,313 @@
+//! 请求预写日志
+//!
+//! 非流式请求在调用上游前写入 `started` 记录，结束时写入 `completed` 或 `failed` 记录。
+//! 服务崩溃重启后，只有 `started` 而没有结束记录的请求会被确定性地标记为失败，
+//! 并通过管理 API 报告，避免请求状态未知。
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// 追加多少条记录后压缩日志文件
+const COMPACT_THRESHOLD: usize = 1000;
+
+/// 服务重启导致请求中断时记录的错误信息
+pub const INTERRUPTED_ERROR: &str = "服务重启，请求中断";
+
+/// 日志记录
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JournalRecord {
+    Started {
+        id: String,
+        model: String,
+        account_id: String,
+        at: DateTime<Utc>,
+    },
+    Completed {
+        id: String,
+        at: DateTime<Utc>,
+    },
+    Failed {
+        id: String,
+        error: String,
+        at: DateTime<Utc>,
+    },
+}
+
+/// 日志中的请求
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    pub id: String,
+    pub model: String,
+    pub account_id: String,
+    pub started_at: DateTime<Utc>,
+}
+
+/// 日志状态快照
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalSnapshot {
+    /// 正在处理中的请求
+    pub in_flight: Vec<JournalEntry>,
+    /// 上次启动时恢复出的中断请求（已标记为失败）
+    pub interrupted: Vec<JournalEntry>,
+}
+
+struct JournalState {
+    file: tokio::fs::File,
+    in_flight: HashMap<String, JournalEntry>,
+    appended: usize,
+}
+
+/// 请求预写日志
+pub struct RequestJournal {
+    path: PathBuf,
+    state: Mutex<JournalState>,
+    interrupted: Vec<JournalEntry>,
+}
+
+impl RequestJournal {
+    /// 打开日志文件并恢复上次未结束的请求
+    ///
+    /// 未结束的请求会被标记为失败，随后日志文件被压缩为空
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let interrupted = match std::fs::read_to_string(&path) {
+            Ok(content) => replay(&content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        for entry in &interrupted {
+            tracing::warn!(
+                "请求 {} (模型 {}, 账号 {}) 在上次运行中未完成，已标记为失败",
+                entry.id,
+                entry.model,
+                entry.account_id
+            );
+        }
+
+        let file = rewrite(&path, &[])?;
+        Ok(Self {
+            path,
+            state: Mutex::new(JournalState {
+                file: tokio::fs::File::from_std(file),
+                in_flight: HashMap::new(),
+                appended: 0,
+            }),
+            interrupted,
+        })
+    }
+
+    /// 记录请求开始
+    pub async fn begin(&self, id: &str, model: &str, account_id: &str) {
+        let entry = JournalEntry {
+            id: id.to_string(),
+            model: model.to_string(),
+            account_id: account_id.to_string(),
+            started_at: Utc::now(),
+        };
+        let record = JournalRecord::Started {
+            id: entry.id.clone(),
+            model: entry.model.clone(),
+            account_id: entry.account_id.clone(),
+            at: entry.started_at,
+        };
+        let mut state = self.state.lock().await;
+        state.in_flight.insert(entry.id.clone(), entry);
+        self.append(&mut state, &record).await;
+    }
+
+    /// 记录请求成功
+    pub async fn complete(&self, id: &str) {
+        self.finish(JournalRecord::Completed {
+            id: id.to_string(),
+            at: Utc::now(),
+        })
+        .await;
+    }
+
+    /// 记录请求失败
+    pub async fn fail(&self, id: &str, error: &str) {
+        self.finish(JournalRecord::Failed {
+            id: id.to_string(),
+            error: error.to_string(),
+            at: Utc::now(),
+        })
+        .await;
+    }
+
+    /// 获取日志状态快照
+    pub async fn snapshot(&self) -> JournalSnapshot {
+        let state = self.state.lock().await;
+        let mut in_flight: Vec<JournalEntry> = state.in_flight.values().cloned().collect();
+        in_flight.sort_by_key(|entry| entry.started_at);
+        JournalSnapshot {
+            in_flight,
+            interrupted: self.interrupted.clone(),
+        }
+    }
+
+    async fn finish(&self, record: JournalRecord) {
+        let mut state = self.state.lock().await;
+        if let JournalRecord::Completed { id, .. } | JournalRecord::Failed { id, .. } = &record {
+            state.in_flight.remove(id);
+        }
+        self.append(&mut state, &record).await;
+
+        if state.appended >= COMPACT_THRESHOLD {
+            self.compact(&mut state);
+        }
+    }
+
+    async fn append(&self, state: &mut JournalState, record: &JournalRecord) {
+        let Ok(mut line) = serde_json::to_string(record) else {
+            return;
+        };
+        line.push('\n');
+        let result = match state.file.write_all(line.as_bytes()).await {
+            Ok(()) => state.file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::error!("写入请求日志失败: {}", e);
+        }
+        state.appended += 1;
+    }
+
+    /// 只保留处理中请求的 started 记录
+    fn compact(&self, state: &mut JournalState) {
+        let records: Vec<JournalRecord> = state
+            .in_flight
+            .values()
+            .map(|entry| JournalRecord::Started {
+                id: entry.id.clone(),
+                model: entry.model.clone(),
+                account_id: entry.account_id.clone(),
+                at: entry.started_at,
+            })
+            .collect();
+        match rewrite(&self.path, &records) {
+            Ok(file) => {
+                state.file = tokio::fs::File::from_std(file);
+                state.appended = records.len();
+            }
+            Err(e) => tracing::error!("压缩请求日志失败: {}", e),
+        }
+    }
+}
+
+/// 重放日志，返回没有结束记录的请求
+fn replay(content: &str) -> Vec<JournalEntry> {
+    let mut pending: HashMap<String, JournalEntry> = HashMap::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str::<JournalRecord>(line) {
+            Ok(JournalRecord::Started {
+                id,
+                model,
+                account_id,
+                at,
+            }) => {
+                pending.insert(
+                    id.clone(),
+                    JournalEntry {
+                        id,
+                        model,
+                        account_id,
+                        started_at: at,
+                    },
+                );
+            }
+            Ok(JournalRecord::Completed { id, .. }) | Ok(JournalRecord::Failed { id, .. }) => {
+                pending.remove(&id);
+            }
+            // 崩溃时最后一行可能只写了一半
+            Err(e) => tracing::warn!("跳过无法解析的请求日志记录: {}", e),
+        }
+    }
+    let mut entries: Vec<JournalEntry> = pending.into_values().collect();
+    entries.sort_by_key(|entry| entry.started_at);
+    entries
+}
+
+/// 用给定记录重写日志文件，返回追加模式的文件句柄
+fn rewrite(path: &Path, records: &[JournalRecord]) -> std::io::Result<std::fs::File> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = std::fs::File::create(&tmp)?;
+        for record in records {
+            serde_json::to_writer(&mut file, record)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)?;
+    std::fs::OpenOptions::new().append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("kiro-journal-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_replay_reports_unfinished_requests() {
+        let started = |id: &str| {
+            serde_json::to_string(&JournalRecord::Started {
+                id: id.to_string(),
+                model: "claude-sonnet-4".to_string(),
+                account_id: "acc".to_string(),
+                at: Utc::now(),
+            })
+            .unwrap()
+        };
+        let completed = serde_json::to_string(&JournalRecord::Completed {
+            id: "a".to_string(),
+            at: Utc::now(),
+        })
+        .unwrap();
+        let content = [
+            started("a"),
+            started("b"),
+            completed,
+            "{\"state\":\"sta".to_string(),
+        ]
+        .join("\n");
+
+        let ids: Vec<String> = replay(&content).into_iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec!["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_journal_recovers_after_restart() {
+        let path = temp_path();
+        let journal = RequestJournal::open(&path).unwrap();
+        journal.begin("done", "m", "acc").await;
+        journal.complete("done").await;
+        journal.begin("crashed", "m", "acc").await;
+        assert_eq!(journal.snapshot().await.in_flight.len(), 1);
+        drop(journal);
+
+        let journal = RequestJournal::open(&path).unwrap();
+        let snapshot = journal.snapshot().await;
+        assert!(snapshot.in_flight.is_empty());
+        assert_eq!(snapshot.interrupted.len(), 1);
+        assert_eq!(snapshot.interrupted[0].id, "crashed");
+        drop(journal);
+
+        // 中断的请求只报告一次
+        let journal = RequestJournal::open(&path).unwrap();
+        assert!(journal.snapshot().await.interrupted.is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,276 @@
+//! 配额账本
+//!
+//! 按账号累计上游 meteringEvent 报告的额度消耗，分别统计当日与当月（UTC）用量，
+//! 持久化到 `quota_ledger.json`。启用 `meteringQuotaSource` 时账号池以本月累计值
+//! 对比配额上限判断账号是否用尽，不必等待下一次配额查询。
+//!
+//! 同时记录计费请求的 token 用量，得出每 token 的平均额度消耗，供 `POST /v1/estimate`
+//! 估算请求的额度开销。
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+
+/// 存储键
+const LEDGER_KEY: &str = "quota_ledger.json";
+
+/// 单个账号的额度消耗
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerEntry {
+    /// 计量单位（取最近一次计费事件）
+    pub unit: Option<String>,
+    /// 当日日期（UTC）
+    pub day: NaiveDate,
+    /// 当日消耗
+    pub today: f64,
+    /// 当月第一天（UTC）
+    pub month: NaiveDate,
+    /// 当月消耗
+    pub this_month: f64,
+    /// 累计消耗
+    pub total: f64,
+    /// 计费事件数
+    pub events: u64,
+    /// 最近一次记录时间
+    pub updated_at: DateTime<Utc>,
+    /// 计费请求的 token 用量
+    #[serde(default)]
+    pub sample: MeteringSample,
+}
+
+/// 计费请求的 token 用量累计
+///
+/// 旧版本账本没有记录 token，只统计记录了 token 的事件，避免低估每 token 的消耗
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeteringSample {
+    /// 请求数
+    pub requests: u64,
+    /// 额度消耗
+    pub usage: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// 从历史计费事件得出的额度消耗速率
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeteringRate {
+    /// 计量单位（取最近一次计费事件）
+    pub unit: Option<String>,
+    /// 每 token（输入加输出）的平均额度消耗
+    pub per_token: f64,
+    /// 每个请求的平均输出 tokens
+    pub avg_output_tokens: f64,
+    /// 参与统计的请求数
+    pub requests: u64,
+}
+
+impl LedgerEntry {
+    fn new(now: DateTime<Utc>) -> Self {
+        let day = now.date_naive();
+        Self {
+            unit: None,
+            day,
+            today: 0.0,
+            month: month_start(day),
+            this_month: 0.0,
+            total: 0.0,
+            events: 0,
+            updated_at: now,
+            sample: MeteringSample::default(),
+        }
+    }
+
+    /// 跨日/跨月后清零对应的统计
+    fn roll_over(&mut self, now: DateTime<Utc>) {
+        let day = now.date_naive();
+        if self.day != day {
+            self.day = day;
+            self.today = 0.0;
+        }
+        let month = month_start(day);
+        if self.month != month {
+            self.month = month;
+            self.this_month = 0.0;
+        }
+    }
+}
+
+fn month_start(day: NaiveDate) -> NaiveDate {
+    day.with_day(1).unwrap_or(day)
+}
+
+/// 账号 ID → 额度消耗
+pub struct QuotaLedger {
+    storage: Arc<dyn Storage>,
+    entries: Mutex<BTreeMap<String, LedgerEntry>>,
+}
+
+impl QuotaLedger {
+    /// 创建空账本，调用 [`QuotaLedger::load`] 加载已保存的记录
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 从存储加载，不存在或解析失败时从空账本开始
+    pub async fn load(&self) {
+        let entries = match self.storage.get_json(LEDGER_KEY).await {
+            Ok(entries) => entries.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("加载配额账本失败，将重新累计: {}", e);
+                BTreeMap::new()
+            }
+        };
+        *self.entries.lock().unwrap() = entries;
+    }
+
+    /// 记录一次计费事件，`input_tokens`/`output_tokens` 为该请求的 token 用量
+    pub async fn record(
+        &self,
+        account_id: &str,
+        usage: f64,
+        unit: Option<&str>,
+        input_tokens: u64,
+        output_tokens: u64,
+        now: DateTime<Utc>,
+    ) {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries
+                .entry(account_id.to_string())
+                .or_insert_with(|| LedgerEntry::new(now));
+            entry.roll_over(now);
+            entry.today += usage;
+            entry.this_month += usage;
+            entry.total += usage;
+            entry.events += 1;
+            entry.updated_at = now;
+            if input_tokens + output_tokens > 0 {
+                entry.sample.requests += 1;
+                entry.sample.usage += usage;
+                entry.sample.input_tokens += input_tokens;
+                entry.sample.output_tokens += output_tokens;
+            }
+            if let Some(unit) = unit {
+                entry.unit = Some(unit.to_string());
+            }
+        }
+        self.save().await;
+    }
+
+    /// 账号本月消耗，没有记录时返回 None
+    pub fn month_usage(&self, account_id: &str, now: DateTime<Utc>) -> Option<f64> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(account_id)?;
+        (entry.month == month_start(now.date_naive())).then_some(entry.this_month)
+    }
+
+    /// 所有账号合计的额度消耗速率，没有记录过 token 用量时返回 None
+    pub fn rate(&self) -> Option<MeteringRate> {
+        let entries = self.entries.lock().unwrap();
+        let mut total = MeteringSample::default();
+        for entry in entries.values() {
+            total.requests += entry.sample.requests;
+            total.usage += entry.sample.usage;
+            total.input_tokens += entry.sample.input_tokens;
+            total.output_tokens += entry.sample.output_tokens;
+        }
+        let tokens = total.input_tokens + total.output_tokens;
+        if total.requests == 0 || tokens == 0 {
+            return None;
+        }
+        let unit = entries
+            .values()
+            .filter(|entry| entry.unit.is_some())
+            .max_by_key(|entry| entry.updated_at)
+            .and_then(|entry| entry.unit.clone());
+        Some(MeteringRate {
+            unit,
+            per_token: total.usage / tokens as f64,
+            avg_output_tokens: total.output_tokens as f64 / total.requests as f64,
+            requests: total.requests,
+        })
+    }
+
+    /// 移除账号的记录
+    pub async fn remove(&self, account_id: &str) {
+        let removed = self.entries.lock().unwrap().remove(account_id).is_some();
+        if removed {
+            self.save().await;
+        }
+    }
+
+    /// 所有账号的消耗（已按 `now` 清零过期的当日/当月统计）
+    pub fn snapshot(&self, now: DateTime<Utc>) -> BTreeMap<String, LedgerEntry> {
+        let mut entries = self.entries.lock().unwrap().clone();
+        for entry in entries.values_mut() {
+            entry.roll_over(now);
+        }
+        entries
+    }
+
+    async fn save(&self) {
+        let entries = self.entries.lock().unwrap().clone();
+        if let Err(e) = self.storage.put_json(LEDGER_KEY, &entries).await {
+            tracing::warn!("保存配额账本失败: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_ledger_rolls_over_by_day_and_month() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        let at = |d: u32, m: u32| Utc.with_ymd_and_hms(2025, m, d, 12, 0, 0).unwrap();
+
+        let ledger = QuotaLedger::new(storage.clone());
+        ledger
+            .record("a", 1.5, Some("credit"), 0, 0, at(30, 6))
+            .await;
+        ledger.record("a", 0.5, None, 0, 0, at(30, 6)).await;
+        assert_eq!(ledger.rate(), None);
+        ledger.record("a", 2.0, None, 3000, 1000, at(1, 7)).await;
+
+        let entry = ledger.snapshot(at(1, 7))["a"].clone();
+        assert_eq!(entry.today, 2.0);
+        assert_eq!(entry.this_month, 2.0);
+        assert_eq!(entry.total, 4.0);
+        assert_eq!(entry.events, 3);
+        assert_eq!(entry.unit.as_deref(), Some("credit"));
+
+        // 重新加载后保留累计值，跨日只清零当日统计
+        let reloaded = QuotaLedger::new(storage);
+        reloaded.load().await;
+        let entry = reloaded.snapshot(at(2, 7))["a"].clone();
+        assert_eq!(entry.today, 0.0);
+        assert_eq!(entry.this_month, 2.0);
+        assert_eq!(reloaded.month_usage("a", at(2, 7)), Some(2.0));
+        assert_eq!(reloaded.month_usage("a", at(1, 8)), None);
+        assert_eq!(reloaded.month_usage("b", at(2, 7)), None);
+
+        // 只按记录了 token 的事件计算速率
+        reloaded.record("b", 1.0, None, 1500, 500, at(2, 7)).await;
+        let rate = reloaded.rate().unwrap();
+        assert_eq!(rate.requests, 2);
+        assert_eq!(rate.per_token, 3.0 / 6000.0);
+        assert_eq!(rate.avg_output_tokens, 750.0);
+        assert_eq!(rate.unit.as_deref(), Some("credit"));
+
+        reloaded.remove("a").await;
+        reloaded.remove("b").await;
+        assert!(reloaded.snapshot(at(2, 7)).is_empty());
+    }
+}
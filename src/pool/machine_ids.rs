@@ -0,0 +1,120 @@
+//! 按账号固定的 Machine ID
+//!
+//! Machine ID 默认由 profileArn 或 refreshToken 派生，Token 刷新或自动发现 profileArn 后
+//! 派生结果会变化，同一账号在上游看来像是换了设备。账号首次加载时把派生出的 ID 写入
+//! `machine_ids.json`，之后始终使用该值，直到账号被移除。
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::kiro::machine_id;
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::storage::Storage;
+
+/// 存储键
+const MACHINE_IDS_KEY: &str = "machine_ids.json";
+
+/// 账号 ID → Machine ID 存储
+pub struct MachineIdStore {
+    storage: Arc<dyn Storage>,
+    ids: Mutex<BTreeMap<String, String>>,
+}
+
+impl MachineIdStore {
+    /// 创建空存储，调用 [`MachineIdStore::load`] 加载已固定的 ID
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            ids: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 从存储加载，不存在或解析失败时从空存储开始
+    pub async fn load(&self) {
+        let ids = match self.storage.get_json(MACHINE_IDS_KEY).await {
+            Ok(ids) => ids.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("加载 Machine ID 失败，将重新生成: {}", e);
+                BTreeMap::new()
+            }
+        };
+        *self.ids.lock().unwrap() = ids;
+    }
+
+    /// 返回账号固定的 Machine ID，首次出现的账号由凭证派生并保存
+    ///
+    /// 凭证无法派生 ID（缺少 profileArn 和 refreshToken）时返回 None，不写入存储
+    pub async fn pin(&self, account_id: &str, credentials: &KiroCredentials) -> Option<String> {
+        let derived = {
+            let mut ids = self.ids.lock().unwrap();
+            if let Some(id) = ids.get(account_id) {
+                return Some(id.clone());
+            }
+            let derived = machine_id::derive_from_credentials(credentials)?;
+            ids.insert(account_id.to_string(), derived.clone());
+            derived
+        };
+        self.save().await;
+        Some(derived)
+    }
+
+    /// 移除账号的 Machine ID
+    pub async fn remove(&self, account_id: &str) {
+        let removed = self.ids.lock().unwrap().remove(account_id).is_some();
+        if removed {
+            self.save().await;
+        }
+    }
+
+    /// 所有已固定的 Machine ID
+    pub fn snapshot(&self) -> BTreeMap<String, String> {
+        self.ids.lock().unwrap().clone()
+    }
+
+    async fn save(&self) {
+        let ids = self.snapshot();
+        if let Err(e) = self.storage.put_json(MACHINE_IDS_KEY, &ids).await {
+            tracing::warn!("保存 Machine ID 失败: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::FileStorage;
+
+    fn credentials(refresh_token: &str) -> KiroCredentials {
+        KiroCredentials {
+            refresh_token: Some(refresh_token.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pinned_id_survives_credential_changes() {
+        let dir = std::env::temp_dir().join(format!("kiro-machine-ids-{}", uuid::Uuid::new_v4()));
+        let storage: Arc<dyn Storage> = Arc::new(FileStorage::new(dir.clone()));
+
+        let store = MachineIdStore::new(storage.clone());
+        let pinned = store.pin("a", &credentials("old")).await.unwrap();
+        assert_eq!(
+            Some(pinned.clone()),
+            machine_id::derive_from_credentials(&credentials("old"))
+        );
+        assert_eq!(
+            store.pin("a", &credentials("new")).await,
+            Some(pinned.clone())
+        );
+        assert!(store.pin("b", &KiroCredentials::default()).await.is_none());
+
+        // 重新加载后仍然使用首次派生的 ID
+        let reloaded = MachineIdStore::new(storage);
+        reloaded.load().await;
+        assert_eq!(reloaded.pin("a", &credentials("new")).await, Some(pinned));
+        reloaded.remove("a").await;
+        assert!(reloaded.snapshot().is_empty());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -2,47 +2,124 @@
 
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use dashmap::DashMap;
 use tokio::sync::RwLock;
 
 use crate::http_client::ProxyConfig;
+use crate::kiro::error::KiroError;
+use crate::kiro::machine_id;
+use crate::kiro::parser::decoder::DecoderConfig;
 use crate::kiro::provider::KiroProvider;
 use crate::kiro::token_manager::TokenManager;
 use crate::model::config::Config;
+use crate::storage::{self, MemoryStorage, Storage};
 
 use super::account::{Account, AccountStatus};
+use super::collector::UsageCollector;
+use super::error_budget::ErrorBudget;
+use super::forecast::{crossed_threshold, usage_percent, UsageForecast, UsageHistory};
+use super::health::{weighted_index, AccountHealth, HealthTracker};
+use super::in_flight::{InFlight, InFlightGuard};
+use super::journal::{JournalSnapshot, RequestJournal, INTERRUPTED_ERROR};
+use super::ledger::{LedgerEntry, MeteringRate, QuotaLedger};
+use super::machine_ids::MachineIdStore;
+use super::probe::{probe_generation, AccountTestReport, ProbeStep};
+use super::snapshot::{PoolSnapshot, SNAPSHOT_FORMAT_VERSION};
 use super::strategy::SelectionStrategy;
-use super::usage::{RequestLog, RequestLogger, RequestStats, UsageLimits};
+use super::tombstone::{RemovalReason, Tombstone, TombstoneStore};
+use super::usage::{ClientInfo, ClientStats, RequestLog, RequestLogger, RequestStats, UsageLimits};
+use super::webhook::{PoolEvent, WebhookNotifier};
 
-/// 账号存储文件名
+/// 账号存储键
 const ACCOUNTS_FILE: &str = "accounts.json";
-/// 请求记录存储文件名
+/// 请求记录存储键
 const LOGS_FILE: &str = "request_logs.json";
-/// 配额缓存存储文件名
+/// 请求记录的保存间隔（有新记录时才写入存储）
+const LOGS_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+/// 配额缓存存储键
 const USAGE_CACHE_FILE: &str = "usage_cache.json";
+/// 未发送到采集端点的使用记录暂存文件名
+const USAGE_SPOOL_FILE: &str = "usage_spool.jsonl";
+/// 请求预写日志文件名
+const JOURNAL_FILE: &str = "request_journal.jsonl";
+/// 选择策略存储键
+const STRATEGY_FILE: &str = "strategy.json";
+/// 实例间共享的账号冷却结束时间的键前缀（后接账号 ID）
+const SHARED_COOLDOWN_PREFIX: &str = "cooldown:";
+/// 实例间共享的账号配额的键前缀（后接账号 ID）
+const SHARED_USAGE_PREFIX: &str = "usage:";
 
 /// 账号池管理器
+///
+/// 每个请求都会更新账号计数和健康度，这些状态放在分片的 `DashMap` 中，
+/// 选择账号和记录结果只锁定对应账号所在的分片，不再争用整个账号池的锁。
+/// 不要在持有 `DashMap` 引用时 await，也不要在遍历时修改同一个 map。
 pub struct AccountPool {
     /// 账号列表
-    accounts: RwLock<HashMap<String, Account>>,
+    accounts: DashMap<String, Account>,
     /// Token 管理器缓存
-    token_managers: RwLock<HashMap<String, Arc<tokio::sync::Mutex<TokenManager>>>>,
+    token_managers: DashMap<String, Arc<tokio::sync::RwLock<TokenManager>>>,
     /// Provider 缓存（每账号一个，避免每请求创建 Client）
-    providers: RwLock<HashMap<String, Arc<KiroProvider>>>,
+    providers: DashMap<String, Arc<KiroProvider>>,
     /// 选择策略
     strategy: RwLock<SelectionStrategy>,
     /// 轮询索引
-    round_robin_index: RwLock<usize>,
+    round_robin_index: AtomicUsize,
     /// 全局配置
     config: Config,
     /// 代理配置
     proxy: Option<ProxyConfig>,
-    /// 数据存储目录
-    data_dir: Option<PathBuf>,
-    /// 请求记录器
-    request_logger: RwLock<RequestLogger>,
+    /// 持久化存储后端
+    storage: Arc<dyn Storage>,
+    /// 请求记录器（临界区很短，使用同步锁避免异步锁排队）
+    request_logger: Mutex<RequestLogger>,
+    /// 请求记录有未保存的修改
+    logs_dirty: AtomicBool,
+    /// 客户端分布统计
+    client_stats: Mutex<ClientStats>,
     /// 账号配额缓存
     usage_cache: RwLock<HashMap<String, UsageLimits>>,
+    /// 账号最近请求结果（用于健康度评分）
+    health: DashMap<String, HealthTracker>,
+    /// 账号错误预算（未配置时为 None）
+    error_budget: Option<ErrorBudget>,
+    /// 各账号进行中的请求数（用于排空账号）
+    in_flight: DashMap<String, Arc<InFlight>>,
+    /// 账号配额使用量采样（用于消耗预测）
+    usage_history: DashMap<String, UsageHistory>,
+    /// Webhook 通知器（未配置时为 None）
+    notifier: Option<WebhookNotifier>,
+    /// 使用记录外部采集器（未配置时为 None）
+    collector: Option<UsageCollector>,
+    /// 非流式请求预写日志（未启用时为 None）
+    journal: Option<RequestJournal>,
+    /// 按账号固定的 Machine ID
+    machine_ids: MachineIdStore,
+    /// 按账号累计的上游计费事件
+    ledger: QuotaLedger,
+    /// 已移除账号的记录
+    tombstones: TombstoneStore,
+    /// 是否已发送账号池不可用通知（恢复后重置）
+    degraded: AtomicBool,
+    /// 上游过载时整个账号池暂停分配请求的截止时间
+    overloaded_until: Mutex<Option<Instant>>,
+}
+
+/// 账号池就绪状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolReadiness {
+    /// 有可用账号
+    Ready,
+    /// 所有账号都在冷却或配额已用尽，retry_after 为最近的恢复时间
+    Saturated {
+        retry_after: Option<std::time::Duration>,
+    },
+    /// 没有可恢复的账号（为空、已失效或已禁用）
+    Unavailable,
 }
 
 /// 账号池选择结果
@@ -50,55 +127,272 @@ pub struct SelectedAccount {
     pub id: String,
     pub name: String,
     pub provider: Arc<KiroProvider>,
+    /// 进行中请求守卫，需要持有到响应结束（流式响应到流结束）
+    pub in_flight: InFlightGuard,
 }
 
 impl AccountPool {
     /// 创建新的账号池
     #[allow(dead_code)]
     pub fn new(config: Config, proxy: Option<ProxyConfig>) -> Self {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        let notifier = WebhookNotifier::new(&config.webhook_urls, proxy.as_ref());
+        let collector = UsageCollector::spawn(&config, proxy.as_ref(), None);
+        let error_budget = config.error_budget.clone().map(ErrorBudget::new);
         Self {
-            accounts: RwLock::new(HashMap::new()),
-            token_managers: RwLock::new(HashMap::new()),
-            providers: RwLock::new(HashMap::new()),
+            accounts: DashMap::new(),
+            token_managers: DashMap::new(),
+            providers: DashMap::new(),
             strategy: RwLock::new(SelectionStrategy::default()),
-            round_robin_index: RwLock::new(0),
+            round_robin_index: AtomicUsize::new(0),
             config,
             proxy,
-            data_dir: None,
-            request_logger: RwLock::new(RequestLogger::default()),
+            machine_ids: MachineIdStore::new(storage.clone()),
+            ledger: QuotaLedger::new(storage.clone()),
+            tombstones: TombstoneStore::new(storage.clone()),
+            storage,
+            journal: None,
+            request_logger: Mutex::new(RequestLogger::default()),
+            logs_dirty: AtomicBool::new(false),
+            client_stats: Mutex::new(ClientStats::default()),
             usage_cache: RwLock::new(HashMap::new()),
+            health: DashMap::new(),
+            error_budget,
+            in_flight: DashMap::new(),
+            usage_history: DashMap::new(),
+            notifier,
+            collector,
+            degraded: AtomicBool::new(false),
+            overloaded_until: Mutex::new(None),
         }
     }
 
     /// 创建带持久化存储的账号池
+    ///
+    /// 存储后端由 `storageBackend` 配置决定，预写日志和使用记录暂存文件始终写入数据目录
     pub fn with_data_dir(config: Config, proxy: Option<ProxyConfig>, data_dir: PathBuf) -> Self {
+        let storage = storage::open(&config, &data_dir, "");
+        Self::with_storage(config, proxy, data_dir, storage)
+    }
+
+    /// 使用指定存储后端创建账号池
+    pub fn with_storage(
+        config: Config,
+        proxy: Option<ProxyConfig>,
+        data_dir: PathBuf,
+        storage: Arc<dyn Storage>,
+    ) -> Self {
+        tracing::debug!("数据目录 {:?} 使用 {} 存储", data_dir, storage.backend());
+        let notifier = WebhookNotifier::new(&config.webhook_urls, proxy.as_ref());
+        let collector = UsageCollector::spawn(
+            &config,
+            proxy.as_ref(),
+            Some(data_dir.join(USAGE_SPOOL_FILE)),
+        );
+        let error_budget = config.error_budget.clone().map(ErrorBudget::new);
+        let journal = if config.request_journal {
+            RequestJournal::open(data_dir.join(JOURNAL_FILE))
+                .map_err(|e| tracing::error!("打开请求预写日志失败: {}", e))
+                .ok()
+        } else {
+            None
+        };
         Self {
-            accounts: RwLock::new(HashMap::new()),
-            token_managers: RwLock::new(HashMap::new()),
-            providers: RwLock::new(HashMap::new()),
+            accounts: DashMap::new(),
+            token_managers: DashMap::new(),
+            providers: DashMap::new(),
             strategy: RwLock::new(SelectionStrategy::default()),
-            round_robin_index: RwLock::new(0),
+            round_robin_index: AtomicUsize::new(0),
             config,
             proxy,
-            data_dir: Some(data_dir),
-            request_logger: RwLock::new(RequestLogger::default()),
+            machine_ids: MachineIdStore::new(storage.clone()),
+            ledger: QuotaLedger::new(storage.clone()),
+            tombstones: TombstoneStore::new(storage.clone()),
+            storage,
+            journal,
+            request_logger: Mutex::new(RequestLogger::default()),
+            logs_dirty: AtomicBool::new(false),
+            client_stats: Mutex::new(ClientStats::default()),
             usage_cache: RwLock::new(HashMap::new()),
+            health: DashMap::new(),
+            error_budget,
+            in_flight: DashMap::new(),
+            usage_history: DashMap::new(),
+            notifier,
+            collector,
+            degraded: AtomicBool::new(false),
+            overloaded_until: Mutex::new(None),
         }
     }
 
-    /// 从文件加载账号
-    pub async fn load_from_file(&self) -> anyhow::Result<usize> {
-        let Some(data_dir) = &self.data_dir else {
-            return Ok(0);
+    /// 存储后端（托管 API Key 和提示词模板与默认账号池共用）
+    pub fn storage(&self) -> Arc<dyn Storage> {
+        self.storage.clone()
+    }
+
+    /// 加载所有持久化数据（Machine ID、配额账本、已移除账号、账号、请求记录、配额缓存、选择策略）
+    pub async fn load_persisted(&self) {
+        // 加载账号时会固定 Machine ID，需要先加载已保存的值
+        self.machine_ids.load().await;
+        self.ledger.load().await;
+        self.tombstones.load().await;
+        if let Err(e) = self.load_from_file().await {
+            tracing::warn!("加载账号文件失败: {}", e);
+        }
+        if let Err(e) = self.load_logs_from_file().await {
+            tracing::warn!("加载请求记录失败: {}", e);
+        }
+        if let Err(e) = self.load_usage_cache().await {
+            tracing::warn!("加载配额缓存失败: {}", e);
+        }
+        match self
+            .storage
+            .get_json::<SelectionStrategy>(STRATEGY_FILE)
+            .await
+        {
+            Ok(Some(strategy)) => *self.strategy.write().await = strategy,
+            Ok(None) => {}
+            Err(e) => tracing::warn!("加载选择策略失败: {}", e),
+        }
+        self.record_interrupted_requests().await;
+    }
+
+    /// 定期保存有新记录的请求记录
+    pub fn spawn_log_flush(self: &Arc<Self>) {
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(LOGS_FLUSH_INTERVAL);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                pool.flush_logs().await;
+            }
+        });
+    }
+
+    /// 存储后端与其他实例共享时，定期同步其他实例写入的账号冷却和配额缓存
+    pub fn spawn_state_sync(self: &Arc<Self>) {
+        let Some(redis) = self.config.redis.as_ref().filter(|_| self.storage.shared()) else {
+            return;
+        };
+        let period = std::time::Duration::from_secs(redis.sync_secs.max(1));
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                if let Err(e) = pool.sync_shared_state().await {
+                    tracing::warn!("同步共享账号状态失败: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 从存储读取其他实例写入的状态并合并到本实例
+    ///
+    /// 冷却和配额按账号保存在独立的键中，不受其他实例保存整个账号列表的影响。
+    /// 采用更晚结束的冷却；已失效、已禁用或排空中的账号保持本地状态。
+    /// 配额缓存以存储中的值为准（各实例刷新配额后立即写入）
+    pub async fn sync_shared_state(&self) -> anyhow::Result<()> {
+        let now = chrono::Utc::now();
+        let ids: Vec<String> = self.accounts.iter().map(|a| a.key().clone()).collect();
+        for id in ids {
+            let cooldown = self
+                .storage
+                .get_shared(&format!("{}{}", SHARED_COOLDOWN_PREFIX, id))
+                .await?
+                .and_then(|bytes| {
+                    serde_json::from_slice::<chrono::DateTime<chrono::Utc>>(&bytes).ok()
+                })
+                .filter(|until| *until > now);
+            if let Some(until) = cooldown {
+                if let Some(mut account) = self.accounts.get_mut(&id) {
+                    let adopt = matches!(
+                        account.status,
+                        AccountStatus::Active | AccountStatus::Cooldown
+                    ) && account.cooldown_until.is_none_or(|local| local < until);
+                    if adopt {
+                        tracing::info!("账号 {} 在其他实例进入冷却，同步至 {}", id, until);
+                        account.status = AccountStatus::Cooldown;
+                        account.cooldown_until = Some(until);
+                    }
+                }
+            }
+
+            let usage = self
+                .storage
+                .get_shared(&format!("{}{}", SHARED_USAGE_PREFIX, id))
+                .await?
+                .and_then(|bytes| serde_json::from_slice::<UsageLimits>(&bytes).ok());
+            if let Some(usage) = usage {
+                self.usage_cache.write().await.insert(id, usage);
+            }
+        }
+        Ok(())
+    }
+
+    /// 写入账号的冷却结束时间供其他实例同步（冷却结束后自动过期）
+    async fn share_cooldown(&self, id: &str, until: chrono::DateTime<chrono::Utc>) {
+        let Ok(ttl) = (until - chrono::Utc::now()).to_std() else {
+            return;
         };
+        if let Ok(value) = serde_json::to_vec(&until) {
+            let key = format!("{}{}", SHARED_COOLDOWN_PREFIX, id);
+            let _ = self.storage.put_shared(&key, value, Some(ttl)).await;
+        }
+    }
 
-        let file_path = data_dir.join(ACCOUNTS_FILE);
-        if !file_path.exists() {
-            return Ok(0);
+    /// 写入账号的配额供其他实例同步
+    async fn share_usage(&self, id: &str, usage: &UsageLimits) {
+        if let Ok(value) = serde_json::to_vec(usage) {
+            let key = format!("{}{}", SHARED_USAGE_PREFIX, id);
+            let _ = self.storage.put_shared(&key, value, None).await;
         }
+    }
 
-        let content = tokio::fs::read_to_string(&file_path).await?;
-        let stored: Vec<StoredAccount> = serde_json::from_str(&content)?;
+    /// 将上次运行中断的请求记录为失败请求
+    async fn record_interrupted_requests(&self) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        for entry in journal.snapshot().await.interrupted {
+            let account_name = self
+                .account_name(&entry.account_id)
+                .await
+                .unwrap_or_default();
+            self.add_request_log(RequestLog {
+                id: entry.id,
+                account_id: entry.account_id,
+                account_name,
+                model: entry.model,
+                input_tokens: 0,
+                output_tokens: 0,
+                success: false,
+                error: Some(INTERRUPTED_ERROR.to_string()),
+                timestamp: entry.started_at,
+                duration_ms: 0,
+                client: ClientInfo::default(),
+            })
+            .await;
+        }
+    }
+
+    /// 从存储加载账号
+    pub async fn load_from_file(&self) -> anyhow::Result<usize> {
+        let Some(stored) = self
+            .storage
+            .get_json::<Vec<StoredAccount>>(ACCOUNTS_FILE)
+            .await?
+        else {
+            return Ok(0);
+        };
 
         let mut count = 0;
         for stored_account in stored {
@@ -110,52 +404,43 @@ impl AccountPool {
             }
         }
 
-        tracing::info!("从文件加载了 {} 个账号", count);
+        tracing::info!("从存储加载了 {} 个账号", count);
         Ok(count)
     }
 
-    /// 保存账号到文件
+    /// 保存账号到存储
     pub async fn save_to_file(&self) -> anyhow::Result<()> {
-        let Some(data_dir) = &self.data_dir else {
-            return Ok(());
-        };
+        let stored: Vec<StoredAccount> = self
+            .accounts
+            .iter()
+            .map(|account| StoredAccount::from_account(account.value()))
+            .collect();
 
-        // 确保目录存在
-        tokio::fs::create_dir_all(data_dir).await?;
+        self.storage.put_json(ACCOUNTS_FILE, &stored).await?;
 
-        let accounts = self.accounts.read().await;
-        let stored: Vec<StoredAccount> =
-            accounts.values().map(StoredAccount::from_account).collect();
-
-        let content = serde_json::to_string_pretty(&stored)?;
-        let file_path = data_dir.join(ACCOUNTS_FILE);
-        tokio::fs::write(&file_path, content).await?;
-
-        tracing::debug!("已保存 {} 个账号到文件", stored.len());
+        tracing::debug!("已保存 {} 个账号", stored.len());
         Ok(())
     }
 
     /// 内部添加账号（不保存文件）
     async fn add_account_internal(&self, account: Account) -> anyhow::Result<()> {
         let id = account.id.clone();
-        let credentials = account.credentials.clone();
+        let mut credentials = account.credentials.clone();
+        credentials.machine_id = self.machine_ids.pin(&id, &credentials).await;
 
         // 创建 TokenManager
         let token_manager = TokenManager::new(self.config.clone(), credentials, self.proxy.clone());
 
-        let tm = Arc::new(tokio::sync::Mutex::new(token_manager));
+        let tm = Arc::new(tokio::sync::RwLock::new(token_manager));
         let provider = Arc::new(KiroProvider::with_shared_token_manager(
             tm.clone(),
             self.proxy.clone(),
         ));
 
-        let mut accounts = self.accounts.write().await;
-        let mut managers = self.token_managers.write().await;
-        let mut providers = self.providers.write().await;
-
-        accounts.insert(id.clone(), account);
-        managers.insert(id.clone(), tm);
-        providers.insert(id, provider);
+        // 最后插入账号，保证选中的账号一定能找到对应的 Provider
+        self.token_managers.insert(id.clone(), tm);
+        self.providers.insert(id.clone(), provider);
+        self.accounts.insert(id, account);
 
         Ok(())
     }
@@ -167,20 +452,63 @@ impl AccountPool {
         Ok(())
     }
 
-    /// 移除账号
-    pub async fn remove_account(&self, id: &str) -> Option<Account> {
-        let mut accounts = self.accounts.write().await;
-        let mut managers = self.token_managers.write().await;
-        let mut providers = self.providers.write().await;
+    /// 添加池中尚不存在的账号（按 ID 判断），返回新增的账号数
+    pub async fn add_missing_accounts(&self, accounts: Vec<Account>) -> anyhow::Result<usize> {
+        let mut added = 0;
+        for account in accounts {
+            if self.accounts.contains_key(&account.id) {
+                continue;
+            }
+            self.add_account_internal(account).await?;
+            added += 1;
+        }
+        if added > 0 {
+            self.save_to_file().await?;
+        }
+        Ok(added)
+    }
 
-        managers.remove(id);
-        providers.remove(id);
-        let removed = accounts.remove(id);
+    /// 移除账号
+    ///
+    /// 移除前记录墓碑（原因未指定时按账号状态推断），保留历史统计供 [`AccountPool::tombstones`] 查询
+    pub async fn remove_account(
+        &self,
+        id: &str,
+        reason: Option<RemovalReason>,
+        note: Option<String>,
+    ) -> Option<Account> {
+        let removed = self.accounts.remove(id).map(|(_, account)| account);
+        if let Some(account) = &removed {
+            let now = chrono::Utc::now();
+            let tombstone = Tombstone {
+                account_id: account.id.clone(),
+                account_name: account.name.clone(),
+                reason: reason.unwrap_or(RemovalReason::infer(account.status)),
+                note,
+                removed_at: now,
+                created_at: account.created_at,
+                last_status: account.status,
+                request_count: account.request_count,
+                error_count: account.error_count,
+                last_used_at: account.last_used_at,
+                machine_id: self.machine_ids.snapshot().remove(id),
+                ledger: self.ledger.snapshot(now).remove(id),
+                usage: self.usage_cache.read().await.get(id).cloned(),
+            };
+            tracing::info!("移除账号 {}（{:?}）", account.name, tombstone.reason);
+            self.tombstones.record(tombstone).await;
+        }
+        self.token_managers.remove(id);
+        self.providers.remove(id);
+        self.health.remove(id);
+        if let Some(budget) = &self.error_budget {
+            budget.reset(id);
+        }
+        self.in_flight.remove(id);
+        self.machine_ids.remove(id).await;
+        self.ledger.remove(id).await;
 
         // 保存到文件
-        drop(accounts);
-        drop(managers);
-        drop(providers);
         if let Err(e) = self.save_to_file().await {
             tracing::warn!("保存账号文件失败: {}", e);
         }
@@ -188,15 +516,32 @@ impl AccountPool {
         removed
     }
 
+    /// 已移除的账号（最近移除的在前），可按原因过滤
+    pub fn tombstones(&self, reason: Option<RemovalReason>) -> Vec<Tombstone> {
+        self.tombstones.list(reason)
+    }
+
     /// 获取所有账号（不含凭证）
     pub async fn list_accounts(&self) -> Vec<Account> {
-        let accounts = self.accounts.read().await;
-        accounts.values().cloned().collect()
+        self.accounts
+            .iter()
+            .map(|account| account.value().clone())
+            .collect()
     }
 
-    /// 设置选择策略
+    /// 设置选择策略，立即对后续请求生效并保存，重启后沿用
     pub async fn set_strategy(&self, strategy: SelectionStrategy) {
-        *self.strategy.write().await = strategy;
+        let previous = std::mem::replace(&mut *self.strategy.write().await, strategy);
+        if previous != strategy {
+            tracing::info!(
+                "选择策略已切换: {} -> {}",
+                previous.as_str(),
+                strategy.as_str()
+            );
+        }
+        if let Err(e) = self.storage.put_json(STRATEGY_FILE, &strategy).await {
+            tracing::warn!("保存选择策略失败: {}", e);
+        }
     }
 
     /// 获取当前策略
@@ -206,18 +551,33 @@ impl AccountPool {
 
     /// 选择一个可用账号并获取其 TokenManager
     pub async fn select_account(&self) -> Option<SelectedAccount> {
+        if self.overload_remaining().is_some() {
+            return None;
+        }
         let strategy = *self.strategy.read().await;
 
-        // 先用读锁快速收集可用账号（避免长时间持有写锁）
-        let available: Vec<(String, u64)> = {
-            let accounts = self.accounts.read().await;
-            accounts
-                .iter()
-                .filter(|(_, a)| a.is_available())
-                .map(|(id, a)| (id.clone(), a.request_count))
+        // 配额已用尽的账号不参与选择
+        let exhausted = self.exhausted_account_ids().await;
+
+        // 健康度加权策略需要先计算健康分
+        let health_scores: HashMap<String, f64> = if strategy == SelectionStrategy::HealthWeighted {
+            self.account_health()
+                .await
+                .into_iter()
+                .map(|h| (h.account_id, h.score))
                 .collect()
+        } else {
+            HashMap::new()
         };
 
+        // 收集可用账号（遍历时只短暂持有各分片的读锁）
+        let available: Vec<(String, u64)> = self
+            .accounts
+            .iter()
+            .filter(|a| a.is_available() && !exhausted.contains(a.key()))
+            .map(|a| (a.key().clone(), a.request_count))
+            .collect();
+
         if available.is_empty() {
             return None;
         }
@@ -225,10 +585,8 @@ impl AccountPool {
         // 根据策略选出候选 id（不持有 accounts 锁）
         let candidate_id = match strategy {
             SelectionStrategy::RoundRobin => {
-                let mut index = self.round_robin_index.write().await;
-                let id = available[*index % available.len()].0.clone();
-                *index = (*index + 1) % available.len();
-                id
+                let index = self.round_robin_index.fetch_add(1, Ordering::Relaxed);
+                available[index % available.len()].0.clone()
             }
             SelectionStrategy::Random => {
                 let idx = fastrand::usize(..available.len());
@@ -239,84 +597,198 @@ impl AccountPool {
                 .min_by_key(|(_, count)| *count)
                 .map(|(id, _)| id.clone())
                 .unwrap_or_else(|| available[0].0.clone()),
-        };
-
-        // 用写锁记录使用，并最终确认选中的账号
-        let (selected_id, selected_name) = {
-            let mut accounts = self.accounts.write().await;
-
-            if let Some(account) = accounts.get_mut(&candidate_id) {
-                if account.is_available() {
-                    account.record_use();
-                    (candidate_id.clone(), account.name.clone())
-                } else {
-                    // 候选账号在并发下变为不可用，退化为找一个可用账号
-                    let mut picked: Option<(String, String)> = None;
-                    for (id, a) in accounts.iter_mut() {
-                        if a.is_available() {
-                            a.record_use();
-                            picked = Some((id.clone(), a.name.clone()));
-                            break;
-                        }
-                    }
-                    picked?
-                }
-            } else {
-                // 候选账号已被删除，退化为找一个可用账号
-                let mut picked: Option<(String, String)> = None;
-                for (id, a) in accounts.iter_mut() {
-                    if a.is_available() {
-                        a.record_use();
-                        picked = Some((id.clone(), a.name.clone()));
-                        break;
-                    }
-                }
-                picked?
+            SelectionStrategy::HealthWeighted => {
+                let scores: Vec<f64> = available
+                    .iter()
+                    .map(|(id, _)| health_scores.get(id).copied().unwrap_or(100.0))
+                    .collect();
+                available[weighted_index(&scores, fastrand::f64())]
+                    .0
+                    .clone()
             }
         };
 
-        let provider = {
-            let providers = self.providers.read().await;
-            providers.get(&selected_id).cloned()?
+        // 记录使用，并最终确认选中的账号
+        let (selected_id, (selected_name, in_flight)) = match self.try_record_use(&candidate_id) {
+            Some(selected) => (candidate_id, selected),
+            // 候选账号在并发下变为不可用或已被删除，退化为找一个可用账号
+            None => available.iter().find_map(|(id, _)| {
+                self.try_record_use(id)
+                    .map(|selected| (id.clone(), selected))
+            })?,
         };
 
+        let provider = self.providers.get(&selected_id)?.value().clone();
+
         Some(SelectedAccount {
             id: selected_id,
             name: selected_name,
             provider,
+            in_flight,
         })
     }
 
+    /// 账号仍可用时记录一次使用，返回账号名称和进行中请求守卫（只锁定该账号所在的分片）
+    ///
+    /// 先取得守卫再检查状态：排空先切换状态再检查计数，两者不会同时错过对方
+    fn try_record_use(&self, id: &str) -> Option<(String, InFlightGuard)> {
+        let guard = self.in_flight.entry(id.to_string()).or_default().acquire();
+        let mut account = self.accounts.get_mut(id)?;
+        if !account.is_available() {
+            return None;
+        }
+        account.record_use();
+        Some((account.name.clone(), guard))
+    }
+
+    /// 配额已用尽的账号 ID
+    async fn exhausted_account_ids(&self) -> std::collections::HashSet<String> {
+        let cache = self.usage_cache.read().await;
+        cache
+            .iter()
+            .filter(|(id, usage)| self.is_quota_exhausted(id, usage))
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    /// 账号配额是否已用尽
+    ///
+    /// 启用 `meteringQuotaSource` 且账本中有本月记录时，以账本累计值对比配额上限；
+    /// 否则以配额缓存为准
+    fn is_quota_exhausted(&self, id: &str, usage: &UsageLimits) -> bool {
+        if self.config.metering_quota_source && usage.usage_limit > 0.0 {
+            if let Some(used) = self.ledger.month_usage(id, chrono::Utc::now()) {
+                return used >= usage.usage_limit;
+            }
+        }
+        usage.is_exhausted()
+    }
+
+    /// 获取账号池就绪状态
+    ///
+    /// 所有未失效、未禁用的账号都在冷却或配额已用尽时返回 Saturated，
+    /// retry_after 取最近的冷却结束时间或配额重置时间
+    pub async fn readiness(&self) -> PoolReadiness {
+        if let Some(remaining) = self.overload_remaining() {
+            return PoolReadiness::Saturated {
+                retry_after: Some(remaining),
+            };
+        }
+        let usage_cache = self.usage_cache.read().await;
+
+        let mut saturated = false;
+        let mut retry_after: Option<chrono::Duration> = None;
+        for entry in self.accounts.iter() {
+            let (id, account) = entry.pair();
+            if !matches!(
+                account.status,
+                AccountStatus::Active | AccountStatus::Cooldown
+            ) {
+                continue;
+            }
+
+            let exhausted = usage_cache
+                .get(id)
+                .filter(|usage| self.is_quota_exhausted(id, usage));
+            if account.is_available() && exhausted.is_none() {
+                return PoolReadiness::Ready;
+            }
+            saturated = true;
+
+            // 账号恢复时间：冷却结束、使用时间窗口开始与配额重置中较晚者；
+            // 配额用尽且重置时间未知时无法估算
+            let cooldown = match (account.cooldown_remaining(), account.window_opens_in()) {
+                (Some(c), Some(w)) => Some(c.max(w)),
+                (c, w) => c.or(w),
+            };
+            let recover = match exhausted {
+                Some(usage) => usage
+                    .next_reset
+                    .map(|reset| reset - chrono::Utc::now())
+                    .map(|reset| cooldown.map_or(reset, |c| c.max(reset))),
+                None => cooldown,
+            };
+            if let Some(recover) = recover {
+                retry_after = Some(retry_after.map_or(recover, |current| current.min(recover)));
+            }
+        }
+
+        if saturated {
+            PoolReadiness::Saturated {
+                retry_after: retry_after.and_then(|d| d.to_std().ok()),
+            }
+        } else {
+            PoolReadiness::Unavailable
+        }
+    }
+
     /// 启用账号
     pub async fn enable_account(&self, id: &str) -> bool {
-        let mut accounts = self.accounts.write().await;
-        if let Some(account) = accounts.get_mut(id) {
-            account.enable();
-            drop(accounts);
-            let _ = self.save_to_file().await;
-            true
-        } else {
-            false
+        match self.accounts.get_mut(id) {
+            Some(mut account) => account.enable(),
+            None => return false,
         }
+        if let Some(budget) = &self.error_budget {
+            budget.reset(id);
+        }
+        let _ = self.save_to_file().await;
+        true
     }
 
     /// 禁用账号
     pub async fn disable_account(&self, id: &str) -> bool {
-        let mut accounts = self.accounts.write().await;
-        if let Some(account) = accounts.get_mut(id) {
-            account.disable();
-            drop(accounts);
-            let _ = self.save_to_file().await;
-            true
-        } else {
-            false
+        match self.accounts.get_mut(id) {
+            Some(mut account) => account.disable(),
+            None => return false,
+        }
+        let _ = self.save_to_file().await;
+        self.check_degraded().await;
+        true
+    }
+
+    /// 排空账号：不再分配新请求，进行中的请求全部结束后转为禁用
+    ///
+    /// 返回开始排空时进行中的请求数；账号不存在、已失效或已禁用时返回 None
+    pub async fn drain_account(self: &Arc<Self>, id: &str) -> Option<usize> {
+        let in_flight = self.in_flight.entry(id.to_string()).or_default().clone();
+        if !self.accounts.get_mut(id)?.drain() {
+            return None;
         }
+        let _ = self.save_to_file().await;
+        let pending = in_flight.count();
+        tracing::info!("账号 {} 开始排空，进行中的请求: {}", id, pending);
+
+        let pool = self.clone();
+        let id = id.to_string();
+        tokio::spawn(async move {
+            in_flight.wait_idle().await;
+            pool.finish_drain(&id).await;
+        });
+        Some(pending)
+    }
+
+    /// 排空完成后禁用账号（排空期间被重新启用或移除时不处理）
+    async fn finish_drain(&self, id: &str) {
+        match self.accounts.get_mut(id) {
+            Some(mut account) if account.status == AccountStatus::Draining => account.disable(),
+            _ => return,
+        }
+        tracing::info!("账号 {} 排空完成，已禁用", id);
+        let _ = self.save_to_file().await;
+        self.check_degraded().await;
+    }
+
+    /// 账号进行中的请求数
+    pub fn in_flight_count(&self, id: &str) -> usize {
+        self.in_flight
+            .get(id)
+            .map_or(0, |in_flight| in_flight.count())
     }
 
     /// 记录账号错误
     pub async fn record_error(&self, id: &str, is_rate_limit: bool) {
-        let mut accounts = self.accounts.write().await;
-        if let Some(account) = accounts.get_mut(id) {
+        if let Some(mut account) = self.accounts.get_mut(id) {
+            let was_cooling = account.cooldown_remaining().is_some();
             account.record_error(is_rate_limit);
             tracing::info!(
                 "账号 {} 记录错误，限流: {}，当前错误数: {}，状态: {:?}",
@@ -325,112 +797,524 @@ impl AccountPool {
                 account.error_count,
                 account.status
             );
-            drop(accounts);
+            let event = (!was_cooling && account.cooldown_remaining().is_some()).then(|| {
+                PoolEvent::AccountCooldown {
+                    account_id: id.to_string(),
+                    account_name: account.name.clone(),
+                    cooldown_until: account.cooldown_until,
+                }
+            });
+            let cooldown = account
+                .cooldown_until
+                .filter(|_| is_rate_limit && account.status == AccountStatus::Cooldown);
+            drop(account);
+            if let Some(until) = cooldown {
+                self.share_cooldown(id, until).await;
+            }
             let _ = self.save_to_file().await;
+            if let Some(event) = event {
+                self.notify(event);
+                self.check_degraded().await;
+            }
+            if !is_rate_limit {
+                self.record_budget_error(id).await;
+            }
+        }
+    }
+
+    /// 计入账号错误预算，错误率超出预算时禁用可用或冷却中的账号并发送通知
+    async fn record_budget_error(&self, id: &str) {
+        let Some(budget) = &self.error_budget else {
+            return;
+        };
+        let Some(exceeded) = budget.record(id, false) else {
+            return;
+        };
+        let account_name = match self.accounts.get_mut(id) {
+            Some(mut account)
+                if matches!(
+                    account.status,
+                    AccountStatus::Active | AccountStatus::Cooldown
+                ) =>
+            {
+                account.disable();
+                account.cooldown_until = None;
+                account.name.clone()
+            }
+            _ => return,
+        };
+        budget.reset(id);
+        tracing::warn!(
+            "账号 {} 最近 {} 秒错误率 {:.0}%（{} 次请求），已自动禁用",
+            id,
+            budget.window_secs(),
+            exceeded.error_rate * 100.0,
+            exceeded.requests
+        );
+        let _ = self.save_to_file().await;
+        self.notify(PoolEvent::ErrorBudgetExceeded {
+            account_id: id.to_string(),
+            account_name,
+            error_rate: exceeded.error_rate,
+            requests: exceeded.requests,
+            window_secs: budget.window_secs(),
+        });
+        self.check_degraded().await;
+    }
+
+    /// 记录上游过载：整个账号池短暂停止分配请求，不计入账号错误
+    ///
+    /// 暂停时长取上游的 `Retry-After`，没有时使用 `overloadBackoffSecs`；已在暂停中时取较晚的截止时间
+    pub fn record_overload(&self, retry_after: Option<std::time::Duration>) {
+        let backoff = retry_after.unwrap_or(std::time::Duration::from_secs(
+            self.config.overload_backoff_secs,
+        ));
+        if backoff.is_zero() {
+            return;
+        }
+        let until = Instant::now() + backoff;
+        let mut overloaded_until = self.overloaded_until.lock().unwrap();
+        if overloaded_until.is_none_or(|current| current < until) {
+            *overloaded_until = Some(until);
+            tracing::warn!("上游过载，账号池暂停分配请求 {:?}", backoff);
         }
     }
 
+    /// 上游过载暂停的剩余时间
+    fn overload_remaining(&self) -> Option<std::time::Duration> {
+        let until = (*self.overloaded_until.lock().unwrap())?;
+        until
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero())
+    }
+
     /// 标记账号为失效
     pub async fn mark_invalid(&self, id: &str) {
-        let mut accounts = self.accounts.write().await;
-        if let Some(account) = accounts.get_mut(id) {
+        if let Some(mut account) = self.accounts.get_mut(id) {
+            let was_invalid = account.status == AccountStatus::Invalid;
             account.mark_invalid();
             tracing::warn!("账号 {} 已标记为失效，错误数: {}", id, account.error_count);
-            drop(accounts);
+            let event = (!was_invalid).then(|| PoolEvent::AccountInvalid {
+                account_id: id.to_string(),
+                account_name: account.name.clone(),
+            });
+            drop(account);
             let _ = self.save_to_file().await;
+            if let Some(event) = event {
+                self.notify(event);
+                self.check_degraded().await;
+            }
+        }
+    }
+
+    /// 发送 Webhook 通知
+    fn notify(&self, event: PoolEvent) {
+        if let Some(notifier) = &self.notifier {
+            notifier.notify(event);
+        }
+    }
+
+    /// 处理 Token 刷新错误
+    ///
+    /// 刷新失败时发送通知；首次熔断时发送告警并标记账号失效，熔断期间的后续错误不再重复处理。
+    /// 静态 Token 过期时直接标记账号失效
+    pub async fn handle_refresh_error(&self, id: &str, e: &KiroError) {
+        if e.is_not_refreshable() {
+            tracing::warn!("账号 {} {}", id, e);
+            self.mark_invalid(id).await;
+        } else if let Some(circuit) = e.circuit_open() {
+            if !circuit.just_opened {
+                return;
+            }
+            if let Some(account_name) = self.account_name(id).await {
+                self.notify(PoolEvent::RefreshCircuitOpen {
+                    account_id: id.to_string(),
+                    account_name,
+                    failures: circuit.failures,
+                    retry_at: circuit.retry_at,
+                    error: circuit.last_error.clone(),
+                });
+            }
+            self.mark_invalid(id).await;
+        } else if let Some(error) = e.refresh_error() {
+            if self.notifier.is_none() {
+                return;
+            }
+            if let Some(account_name) = self.account_name(id).await {
+                self.notify(PoolEvent::TokenRefreshFailed {
+                    account_id: id.to_string(),
+                    account_name,
+                    error: error.0.clone(),
+                });
+            }
+        }
+    }
+
+    /// 账号是否使用静态 Token（没有 refreshToken）
+    pub async fn has_static_token(&self, id: &str) -> bool {
+        let Some(tm) = self.token_managers.get(id).map(|tm| tm.value().clone()) else {
+            return false;
+        };
+        let is_static = tm.read().await.credentials().is_static();
+        is_static
+    }
+
+    /// 处理上游拒绝凭证：静态 Token 收到 401 时无法通过刷新恢复，标记账号失效
+    ///
+    /// 返回是否已标记失效
+    pub async fn handle_unauthorized(&self, id: &str, e: &KiroError) -> bool {
+        let is_401 = matches!(e, KiroError::Unauthorized(upstream) if upstream.status == 401);
+        if !is_401 || !self.has_static_token(id).await {
+            return false;
+        }
+        tracing::warn!(
+            "账号 {} 的静态 accessToken 被上游拒绝（401），已标记为失效",
+            id
+        );
+        self.mark_invalid(id).await;
+        true
+    }
+
+    /// 获取账号名称
+    async fn account_name(&self, id: &str) -> Option<String> {
+        self.accounts.get(id).map(|account| account.name.clone())
+    }
+
+    /// 检查账号池是否整体不可用，首次进入不可用状态时发送通知
+    async fn check_degraded(&self) {
+        if self.notifier.is_none() {
+            return;
+        }
+        let retry_after = match self.readiness().await {
+            PoolReadiness::Ready => {
+                self.degraded.store(false, Ordering::Relaxed);
+                return;
+            }
+            PoolReadiness::Saturated { retry_after } => retry_after,
+            PoolReadiness::Unavailable => None,
+        };
+        if !self.degraded.swap(true, Ordering::Relaxed) {
+            let total_accounts = self.accounts.len();
+            self.notify(PoolEvent::PoolDegraded {
+                total_accounts,
+                retry_after_secs: retry_after.map(|d| d.as_secs()),
+            });
         }
     }
 
+    /// 将 TokenManager 自动发现的 profileArn 同步到账号并持久化
+    pub async fn sync_profile_arn(&self, id: &str) {
+        let Some(tm) = self.token_managers.get(id).map(|tm| tm.value().clone()) else {
+            return;
+        };
+        let discovered = tm.read().await.credentials().profile_arn.clone();
+        let Some(arn) = discovered else {
+            return;
+        };
+
+        if let Some(mut account) = self.accounts.get_mut(id) {
+            if account.credentials.profile_arn.is_none() {
+                account.credentials.profile_arn = Some(arn);
+                tracing::info!("账号 {} 已保存自动发现的 profileArn", id);
+                drop(account);
+                let _ = self.save_to_file().await;
+            }
+        }
+    }
+
+    /// 为缺少 profileArn 的账号自动发现 profileArn 并持久化（Token 未过期时不刷新，直接查询）
+    pub async fn discover_profile_arn(&self, id: &str) -> anyhow::Result<()> {
+        let tm = self
+            .token_managers
+            .get(id)
+            .map(|tm| tm.value().clone())
+            .ok_or_else(|| anyhow::anyhow!("账号不存在"))?;
+        tm.write().await.ensure_profile_arn().await?;
+        self.sync_profile_arn(id).await;
+        Ok(())
+    }
+
     /// 获取统计信息
     pub async fn get_stats(&self) -> PoolStats {
-        let accounts = self.accounts.read().await;
-
-        let total = accounts.len();
-        let active = accounts
-            .values()
-            .filter(|a| a.status == AccountStatus::Active)
-            .count();
-        let cooldown = accounts
-            .values()
-            .filter(|a| a.status == AccountStatus::Cooldown)
-            .count();
-        let invalid = accounts
-            .values()
-            .filter(|a| a.status == AccountStatus::Invalid)
-            .count();
-        let disabled = accounts
-            .values()
-            .filter(|a| a.status == AccountStatus::Disabled)
-            .count();
-        let total_requests: u64 = accounts.values().map(|a| a.request_count).sum();
-        let total_errors: u64 = accounts.values().map(|a| a.error_count).sum();
-
-        PoolStats {
-            total,
-            active,
-            cooldown,
-            invalid,
-            disabled,
-            total_requests,
-            total_errors,
+        let mut stats = PoolStats {
+            total: 0,
+            active: 0,
+            cooldown: 0,
+            invalid: 0,
+            disabled: 0,
+            draining: 0,
+            total_requests: 0,
+            total_errors: 0,
+            strategy: *self.strategy.read().await,
+            health: self.account_health().await,
+            forecast: self.usage_forecast().await,
+        };
+        for account in self.accounts.iter() {
+            stats.total += 1;
+            match account.status {
+                AccountStatus::Active => stats.active += 1,
+                AccountStatus::Cooldown => stats.cooldown += 1,
+                AccountStatus::Invalid => stats.invalid += 1,
+                AccountStatus::Disabled => stats.disabled += 1,
+                AccountStatus::Draining => stats.draining += 1,
+            }
+            stats.total_requests += account.request_count;
+            stats.total_errors += account.error_count;
         }
+        stats
+    }
+
+    /// 计算所有账号的健康度，按健康分从高到低排序
+    ///
+    /// Token 正在同步刷新的账号（TokenManager 写锁被占用）本次不计入刷新成功率，避免阻塞
+    pub async fn account_health(&self) -> Vec<AccountHealth> {
+        let refresh_rates: HashMap<String, Option<f64>> = self
+            .token_managers
+            .iter()
+            .map(|tm| {
+                let rate = tm
+                    .try_read()
+                    .ok()
+                    .and_then(|tm| tm.refresh_stats().success_rate());
+                (tm.key().clone(), rate)
+            })
+            .collect();
+        let usage_cache = self.usage_cache.read().await;
+
+        let mut health: Vec<AccountHealth> = self
+            .accounts
+            .iter()
+            .map(|account| {
+                let quota_remaining = usage_cache
+                    .get(&account.id)
+                    .filter(|usage| usage.usage_limit > 0.0)
+                    .map(|usage| usage.available / usage.usage_limit);
+                AccountHealth::new(
+                    &account.id,
+                    &account.name,
+                    refresh_rates.get(&account.id).copied().flatten(),
+                    self.health.get(&account.id).as_deref(),
+                    quota_remaining,
+                )
+            })
+            .collect();
+        health.sort_by(|a, b| b.score.total_cmp(&a.score));
+        health
+    }
+
+    /// 按缓存的配额和使用量采样预测各账号配额用尽时间，预计最早用尽的账号在前
+    pub async fn usage_forecast(&self) -> Vec<UsageForecast> {
+        let usage_cache = self.usage_cache.read().await;
+        let now = chrono::Utc::now();
+        let mut forecast: Vec<UsageForecast> = self
+            .accounts
+            .iter()
+            .filter_map(|account| {
+                let usage = usage_cache.get(&account.id)?;
+                let history = self.usage_history.get(&account.id);
+                Some(UsageForecast::new(
+                    &account.id,
+                    &account.name,
+                    usage,
+                    history.as_deref(),
+                    now,
+                ))
+            })
+            .collect();
+        forecast.sort_by_key(|f| (f.exhausts_at.is_none(), f.exhausts_at));
+        forecast
     }
 
     /// 添加请求记录
     pub async fn add_request_log(&self, log: RequestLog) {
-        let mut logger = self.request_logger.write().await;
-        logger.add(log);
-
-        // 异步保存到文件（不阻塞）
-        if let Some(data_dir) = &self.data_dir {
-            let logs = logger.get_all();
-            let file_path = data_dir.join(LOGS_FILE);
-            tokio::spawn(async move {
-                if let Ok(content) = serde_json::to_string(&logs) {
-                    let _ = tokio::fs::write(&file_path, content).await;
+        if let Some(collector) = &self.collector {
+            collector.record(&log);
+        }
+        self.health
+            .entry(log.account_id.clone())
+            .or_default()
+            .record(log.success, log.duration_ms);
+        // 失败由 record_error 计入错误预算（排除限流等与账号无关的错误）
+        if let Some(budget) = self.error_budget.as_ref().filter(|_| log.success) {
+            budget.record(&log.account_id, true);
+        }
+        self.request_logger.lock().unwrap().add(log);
+        // 由后台任务定期保存，不在请求路径上写入存储
+        self.logs_dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 预写日志：记录请求开始
+    pub async fn journal_begin(&self, id: &str, model: &str, account_id: &str) {
+        if let Some(journal) = &self.journal {
+            journal.begin(id, model, account_id).await;
+        }
+    }
+
+    /// 预写日志：记录请求结束，`error` 为 None 表示成功
+    pub async fn journal_finish(&self, id: &str, error: Option<&str>) {
+        if let Some(journal) = &self.journal {
+            match error {
+                Some(error) => journal.fail(id, error).await,
+                None => journal.complete(id).await,
+            }
+        }
+    }
+
+    /// 获取预写日志状态（未启用时为 None）
+    pub async fn journal_snapshot(&self) -> Option<JournalSnapshot> {
+        match &self.journal {
+            Some(journal) => Some(journal.snapshot().await),
+            None => None,
+        }
+    }
+
+    /// 各账号当前使用的 Machine ID（用于排查与设备指纹相关的封禁）
+    pub async fn machine_ids(&self) -> Vec<AccountMachineId> {
+        let pinned = self.machine_ids.snapshot();
+        let token_managers: Vec<_> = self
+            .token_managers
+            .iter()
+            .map(|tm| (tm.key().clone(), tm.value().clone()))
+            .collect();
+
+        let mut ids = Vec::with_capacity(token_managers.len());
+        for (id, tm) in token_managers {
+            let machine_id =
+                machine_id::generate_from_credentials(tm.read().await.credentials(), &self.config);
+            ids.push(AccountMachineId {
+                account_name: self.account_name(&id).await.unwrap_or_default(),
+                pinned: machine_id.is_some() && machine_id.as_ref() == pinned.get(&id),
+                account_id: id,
+                machine_id,
+            });
+        }
+        ids.sort_by(|a, b| a.account_id.cmp(&b.account_id));
+        ids
+    }
+
+    /// 记录上游计费事件报告的额度消耗，`input_tokens`/`output_tokens` 为该请求的 token 用量
+    pub async fn record_metering(
+        &self,
+        id: &str,
+        usage: f64,
+        unit: Option<&str>,
+        input_tokens: i32,
+        output_tokens: i32,
+    ) {
+        if usage <= 0.0 || !usage.is_finite() {
+            return;
+        }
+        let now = chrono::Utc::now();
+        let before = self.ledger.month_usage(id, now).unwrap_or(0.0);
+        let (input_tokens, output_tokens) =
+            (input_tokens.max(0) as u64, output_tokens.max(0) as u64);
+        self.ledger
+            .record(id, usage, unit, input_tokens, output_tokens, now)
+            .await;
+
+        if !self.config.metering_quota_source {
+            return;
+        }
+        let limits = self.usage_cache.read().await.get(id).cloned();
+        let Some(limits) = limits.filter(|l| l.usage_limit > 0.0) else {
+            return;
+        };
+        if before < limits.usage_limit && before + usage >= limits.usage_limit {
+            let account_name = self.account_name(id).await.unwrap_or_default();
+            tracing::warn!(
+                "账号 {} 本月计费额度已达上限 {}",
+                account_name,
+                limits.usage_limit
+            );
+            self.notify(PoolEvent::QuotaExhausted {
+                account_id: id.to_string(),
+                account_name,
+                next_reset: limits.next_reset,
+            });
+            self.check_degraded().await;
+        }
+    }
+
+    /// 从历史计费事件得出的额度消耗速率
+    pub fn metering_rate(&self) -> Option<MeteringRate> {
+        self.ledger.rate()
+    }
+
+    /// 当前可选账号的订阅类型（去重，按配额缓存）
+    pub async fn available_subscription_types(&self) -> Vec<String> {
+        let usage_cache = self.usage_cache.read().await;
+        let mut types: Vec<String> = self
+            .accounts
+            .iter()
+            .filter(|a| a.is_available())
+            .filter_map(|a| {
+                let usage = usage_cache.get(a.key())?;
+                if self.is_quota_exhausted(a.key(), usage) {
+                    return None;
                 }
+                usage.subscription_type.clone()
+            })
+            .collect();
+        types.sort();
+        types.dedup();
+        types
+    }
+
+    /// 各账号的额度消耗账本
+    pub async fn quota_ledger(&self) -> Vec<AccountLedger> {
+        let usage_cache = self.usage_cache.read().await;
+        let mut ledger = Vec::new();
+        for (id, entry) in self.ledger.snapshot(chrono::Utc::now()) {
+            ledger.push(AccountLedger {
+                account_name: self.account_name(&id).await.unwrap_or_default(),
+                usage_limit: usage_cache.get(&id).map(|usage| usage.usage_limit),
+                account_id: id,
+                entry,
             });
         }
+        ledger
     }
 
     /// 获取最近的请求记录
     pub async fn get_recent_logs(&self, n: usize) -> Vec<RequestLog> {
-        let logger = self.request_logger.read().await;
+        let logger = self.request_logger.lock().unwrap();
         logger.get_recent(n)
     }
 
     /// 获取请求统计
     pub async fn get_request_stats(&self) -> RequestStats {
-        let logger = self.request_logger.read().await;
+        let logger = self.request_logger.lock().unwrap();
         logger.get_stats()
     }
 
-    /// 从文件加载请求记录
+    /// 记录请求的客户端信息
+    pub async fn record_client(&self, client: &ClientInfo) {
+        self.client_stats.lock().unwrap().record(client);
+    }
+
+    /// 获取客户端分布统计
+    pub async fn get_client_stats(&self) -> ClientStats {
+        self.client_stats.lock().unwrap().clone()
+    }
+
+    /// 从存储加载请求记录
     pub async fn load_logs_from_file(&self) -> anyhow::Result<usize> {
-        let Some(data_dir) = &self.data_dir else {
+        let Some(mut logs) = self.storage.get_json::<Vec<RequestLog>>(LOGS_FILE).await? else {
             return Ok(0);
         };
 
-        let file_path = data_dir.join(LOGS_FILE);
-        if !file_path.exists() {
-            return Ok(0);
-        }
-
-        let content = tokio::fs::read_to_string(&file_path).await?;
-        let mut logs: Vec<RequestLog> = serde_json::from_str(&content)?;
-
         // 只保留最新的 1000 条（如果超过的话）
         if logs.len() > 1000 {
             logs = logs.split_off(logs.len() - 1000);
         }
 
         let count = logs.len();
-        let mut logger = self.request_logger.write().await;
+        let mut logger = self.request_logger.lock().unwrap();
         for log in logs {
             logger.add(log);
         }
 
-        tracing::info!("从文件加载了 {} 条请求记录", count);
+        tracing::info!("从存储加载了 {} 条请求记录", count);
         Ok(count)
     }
 
@@ -440,35 +1324,115 @@ impl AccountPool {
         cache.get(id).cloned()
     }
 
+    /// 对单个账号执行端到端测试（获取 Token + 极短的生成请求）
+    ///
+    /// 不经过账号选择，也不计入使用统计；账号不存在时返回 None
+    pub async fn test_account(&self, id: &str, model: &str) -> Option<AccountTestReport> {
+        let tm = self.token_managers.get(id).map(|tm| tm.value().clone())?;
+        let provider = self.providers.get(id).map(|p| p.value().clone())?;
+        let start = Instant::now();
+
+        let token_result = tm.write().await.ensure_valid_token().await;
+        let token = ProbeStep::finish(start, &token_result);
+        let generation = match token_result {
+            Ok(_) => {
+                self.sync_profile_arn(id).await;
+                let step_start = Instant::now();
+                let decoder = DecoderConfig::from(&self.config.decoder);
+                let result = probe_generation(&provider, model, decoder).await;
+                if let Some(e) = result.as_ref().err().and_then(|e| e.downcast_ref()) {
+                    self.handle_unauthorized(id, e).await;
+                }
+                Some((ProbeStep::finish(step_start, &result), result.ok()))
+            }
+            Err(e) => {
+                self.handle_refresh_error(id, &e).await;
+                None
+            }
+        };
+
+        let (generation, reply) = match generation {
+            Some((step, reply)) => (Some(step), reply),
+            None => (None, None),
+        };
+        let model_available = reply.is_some();
+        let report = AccountTestReport {
+            id: id.to_string(),
+            success: token.ok && model_available,
+            model: model.to_string(),
+            model_available,
+            token,
+            generation,
+            reply,
+            total_latency_ms: start.elapsed().as_millis() as u64,
+        };
+        tracing::info!(
+            "账号 {} 测试{}，耗时 {}ms",
+            id,
+            if report.success { "通过" } else { "失败" },
+            report.total_latency_ms
+        );
+        Some(report)
+    }
+
+    /// 立即刷新单个账号的 Token，账号不存在时返回 None
+    ///
+    /// 跳过熔断等待，刷新失败按常规刷新错误处理（通知、熔断计数）
+    pub async fn refresh_account_token(&self, id: &str) -> Option<TokenRefreshReport> {
+        let tm = self.token_managers.get(id).map(|tm| tm.value().clone())?;
+        let start = Instant::now();
+
+        let mut tm_guard = tm.write().await;
+        let result = tm_guard.force_refresh().await;
+        let expires_at = tm_guard.credentials().expires_at.clone();
+        drop(tm_guard);
+
+        let step = ProbeStep::finish(start, &result);
+        match &result {
+            Ok(()) => {
+                self.sync_profile_arn(id).await;
+                tracing::info!("账号 {} 已手动刷新 Token，有效期至 {:?}", id, expires_at);
+            }
+            Err(e) => {
+                tracing::warn!("账号 {} 手动刷新 Token 失败: {}", id, e);
+                self.handle_refresh_error(id, e).await;
+            }
+        }
+        Some(TokenRefreshReport {
+            id: id.to_string(),
+            success: step.ok,
+            expires_at: expires_at.filter(|_| step.ok),
+            latency_ms: step.latency_ms,
+            error: step.error,
+        })
+    }
+
     /// 刷新账号配额
     pub async fn refresh_account_usage(&self, id: &str) -> anyhow::Result<UsageLimits> {
         // 获取 TokenManager
-        let managers = self.token_managers.read().await;
-        let tm = managers
+        let tm = self
+            .token_managers
             .get(id)
+            .map(|tm| tm.value().clone())
             .ok_or_else(|| anyhow::anyhow!("账号不存在"))?;
 
         // 获取 access_token
-        let mut tm_guard = tm.lock().await;
+        let mut tm_guard = tm.write().await;
         let token = match tm_guard.ensure_valid_token().await {
             Ok(t) => t,
             Err(e) => {
-                let error_msg = e.to_string();
-                // 检测 403/suspended 错误，自动禁用账号
-                if error_msg.contains("403")
-                    || error_msg.contains("suspended")
-                    || error_msg.contains("SUSPENDED")
-                {
-                    drop(tm_guard);
-                    drop(managers);
+                drop(tm_guard);
+                // 刷新端点报告账号已暂停时自动禁用账号
+                if e.to_string().to_ascii_lowercase().contains("suspended") {
                     self.mark_invalid(id).await;
-                    tracing::warn!("账号 {} 获取 token 失败，已标记为失效: {}", id, error_msg);
+                    tracing::warn!("账号 {} 获取 token 失败，已标记为失效: {}", id, e);
                 }
-                return Err(e);
+                self.handle_refresh_error(id, &e).await;
+                return Err(e.into());
             }
         };
         drop(tm_guard);
-        drop(managers);
+        self.sync_profile_arn(id).await;
 
         // 调用 API 获取配额
         let usage = match super::usage::check_usage_limits(&token).await {
@@ -482,60 +1446,101 @@ impl AccountPool {
                 {
                     self.mark_invalid(id).await;
                     tracing::warn!("账号 {} 获取配额失败，已标记为失效: {}", id, error_msg);
+                } else if error_msg.contains("401") && self.has_static_token(id).await {
+                    // 静态 Token 无法刷新，被拒绝后不会自行恢复
+                    self.mark_invalid(id).await;
+                    tracing::warn!("账号 {} 的静态 accessToken 已失效: {}", id, error_msg);
                 }
                 return Err(e);
             }
         };
 
-        // 更新缓存
+        // 更新缓存并记录使用量采样
         let mut cache = self.usage_cache.write().await;
-        cache.insert(id.to_string(), usage.clone());
+        let previous = cache.insert(id.to_string(), usage.clone());
         drop(cache);
+        let was_exhausted = previous.as_ref().is_some_and(|u| u.is_exhausted());
+        self.usage_history
+            .entry(id.to_string())
+            .or_default()
+            .record(chrono::Utc::now(), usage.current_usage);
 
         // 保存到文件
         self.save_usage_cache().await;
+        self.share_usage(id, &usage).await;
+
+        if usage.is_exhausted() && !was_exhausted && self.notifier.is_some() {
+            let account_name = self.account_name(id).await.unwrap_or_default();
+            self.notify(PoolEvent::QuotaExhausted {
+                account_id: id.to_string(),
+                account_name,
+                next_reset: usage.next_reset,
+            });
+            self.check_degraded().await;
+        } else if let Some(percent) = usage_percent(&usage).filter(|_| self.notifier.is_some()) {
+            // 配额重置后使用率下降，之前的使用率不再作为基准
+            let before = previous
+                .as_ref()
+                .filter(|p| p.current_usage <= usage.current_usage)
+                .and_then(usage_percent);
+            let thresholds = &self.config.quota_warning_thresholds;
+            if let Some(threshold) = crossed_threshold(thresholds, before, percent) {
+                let account_name = self.account_name(id).await.unwrap_or_default();
+                let exhausts_at = self
+                    .usage_history
+                    .get(id)
+                    .map(|history| {
+                        UsageForecast::new(
+                            id,
+                            &account_name,
+                            &usage,
+                            Some(&history),
+                            chrono::Utc::now(),
+                        )
+                    })
+                    .and_then(|forecast| forecast.exhausts_at);
+                self.notify(PoolEvent::QuotaWarning {
+                    account_id: id.to_string(),
+                    account_name,
+                    threshold_percent: threshold,
+                    usage_percent: percent,
+                    exhausts_at,
+                });
+            }
+        }
 
         Ok(usage)
     }
 
-    /// 保存配额缓存到文件
+    /// 保存配额缓存到存储
     async fn save_usage_cache(&self) {
-        if let Some(data_dir) = &self.data_dir {
-            let cache = self.usage_cache.read().await;
-            let file_path = data_dir.join(USAGE_CACHE_FILE);
-            if let Ok(content) = serde_json::to_string(&*cache) {
-                let _ = tokio::fs::write(&file_path, content).await;
-            }
+        let content = serde_json::to_vec(&*self.usage_cache.read().await);
+        if let Ok(content) = content {
+            let _ = self.storage.put(USAGE_CACHE_FILE, content).await;
         }
     }
 
-    /// 从文件加载配额缓存
+    /// 从存储加载配额缓存
     pub async fn load_usage_cache(&self) -> anyhow::Result<usize> {
-        let Some(data_dir) = &self.data_dir else {
+        let Some(loaded) = self
+            .storage
+            .get_json::<HashMap<String, UsageLimits>>(USAGE_CACHE_FILE)
+            .await?
+        else {
             return Ok(0);
         };
 
-        let file_path = data_dir.join(USAGE_CACHE_FILE);
-        if !file_path.exists() {
-            return Ok(0);
-        }
-
-        let content = tokio::fs::read_to_string(&file_path).await?;
-        let loaded: HashMap<String, UsageLimits> = serde_json::from_str(&content)?;
-
         let count = loaded.len();
         let mut cache = self.usage_cache.write().await;
         *cache = loaded;
 
-        tracing::info!("从文件加载了 {} 个配额缓存", count);
+        tracing::info!("从存储加载了 {} 个配额缓存", count);
         Ok(count)
     }
 
     /// 刷新所有账号配额
     pub async fn refresh_all_usage(&self) -> Vec<(String, Result<UsageLimits, String>)> {
-        let accounts = self.accounts.read().await;
-        let ids: Vec<String> = accounts.keys().cloned().collect();
-        drop(accounts);
+        let ids: Vec<String> = self.accounts.iter().map(|a| a.key().clone()).collect();
 
         let mut results = Vec::new();
         for id in ids {
@@ -553,6 +1558,138 @@ impl AccountPool {
         let cache = self.usage_cache.read().await;
         cache.clone()
     }
+
+    /// 导出账号池状态快照（账号、请求记录、配额缓存、选择策略）
+    pub async fn snapshot(&self) -> PoolSnapshot {
+        let accounts = self
+            .accounts
+            .iter()
+            .map(|account| StoredAccount::from_account(account.value()))
+            .collect();
+        let request_logs = self.request_logger.lock().unwrap().get_all();
+        PoolSnapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: chrono::Utc::now(),
+            strategy: *self.strategy.read().await,
+            accounts,
+            request_logs,
+            usage_cache: self.usage_cache.read().await.clone(),
+        }
+    }
+
+    /// 用快照替换账号池的全部状态并持久化，返回恢复的账号数
+    pub async fn restore(&self, snapshot: PoolSnapshot) -> anyhow::Result<usize> {
+        snapshot.check_version()?;
+
+        self.accounts.clear();
+        self.token_managers.clear();
+        self.providers.clear();
+        self.health.clear();
+
+        let mut count = 0;
+        for stored in snapshot.accounts {
+            match self.add_account_internal(stored.into_account()).await {
+                Ok(()) => count += 1,
+                Err(e) => tracing::warn!("恢复账号失败: {}", e),
+            }
+        }
+
+        {
+            let mut logger = self.request_logger.lock().unwrap();
+            *logger = RequestLogger::default();
+            for log in snapshot.request_logs {
+                logger.add(log);
+            }
+        }
+        *self.usage_cache.write().await = snapshot.usage_cache;
+        self.set_strategy(snapshot.strategy).await;
+
+        self.save_to_file().await?;
+        self.save_logs().await?;
+        self.save_usage_cache().await;
+        self.check_degraded().await;
+
+        tracing::info!(
+            "已从快照恢复 {} 个账号（快照版本 {}，创建于 {}）",
+            count,
+            snapshot.server_version,
+            snapshot.created_at
+        );
+        Ok(count)
+    }
+
+    /// 退出前保存账号、配额缓存和请求记录
+    pub async fn persist(&self) {
+        if let Err(e) = self.save_to_file().await {
+            tracing::warn!("保存账号失败: {}", e);
+        }
+        self.save_usage_cache().await;
+        self.flush_logs().await;
+    }
+
+    /// 保存请求记录（没有未保存的修改时跳过）
+    pub async fn flush_logs(&self) {
+        if !self.logs_dirty.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = self.save_logs().await {
+            tracing::warn!("保存请求记录失败: {}", e);
+        }
+    }
+
+    /// 保存请求记录到存储
+    async fn save_logs(&self) -> anyhow::Result<()> {
+        // 先清除标记再取快照，保存期间的新记录会重新标记；写入失败时恢复标记，下次继续保存
+        self.logs_dirty.store(false, Ordering::Relaxed);
+        let logs = self.request_logger.lock().unwrap().get_all();
+        let result = match serde_json::to_vec(&logs) {
+            Ok(content) => self.storage.put(LOGS_FILE, content).await,
+            Err(e) => Err(e.into()),
+        };
+        if result.is_err() {
+            self.logs_dirty.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// 账号当前使用的 Machine ID
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountMachineId {
+    pub account_id: String,
+    pub account_name: String,
+    /// 无法生成时为 None（缺少 profileArn 和 refreshToken）
+    pub machine_id: Option<String>,
+    /// 是否为按账号固定的 ID（为 false 时来自全局 `machineId` 配置或尚未固定）
+    pub pinned: bool,
+}
+
+/// 账号的额度消耗
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountLedger {
+    pub account_id: String,
+    pub account_name: String,
+    #[serde(flatten)]
+    pub entry: LedgerEntry,
+    /// 配额缓存中的使用上限（尚未查询配额时为 None）
+    pub usage_limit: Option<f64>,
+}
+
+/// 手动刷新 Token 的结果
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TokenRefreshReport {
+    pub id: String,
+    pub success: bool,
+    /// 刷新后的 Token 过期时间（RFC3339）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    pub latency_ms: u64,
+    /// 刷新失败的详细原因（含熔断信息）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 /// 账号池统计
@@ -563,29 +1700,55 @@ pub struct PoolStats {
     pub cooldown: usize,
     pub invalid: usize,
     pub disabled: usize,
+    pub draining: usize,
     pub total_requests: u64,
     pub total_errors: u64,
+    /// 当前的选择策略
+    pub strategy: SelectionStrategy,
+    /// 各账号健康度（按健康分从高到低）
+    pub health: Vec<AccountHealth>,
+    /// 各账号配额预测（预计最早用尽的在前）
+    pub forecast: Vec<UsageForecast>,
 }
 
 /// 用于持久化存储的账号结构
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
-struct StoredAccount {
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub(crate) struct StoredAccount {
     id: String,
     name: String,
     status: super::account::AccountStatus,
     request_count: u64,
     error_count: u64,
     created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cooldown_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// 每日使用时间窗口（UTC，如 `["00:00-08:00"]`）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    usage_windows: Vec<super::schedule::UsageWindow>,
     // 凭证信息
+    /// 静态 Token（没有 refreshToken）的 accessToken 和过期时间，可刷新的账号不保存
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    access_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
     refresh_token: Option<String>,
     auth_method: Option<String>,
     client_id: Option<String>,
     client_secret: Option<String>,
     profile_arn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    refresh_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    oidc_region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    region: Option<String>,
 }
 
 impl StoredAccount {
     fn from_account(account: &Account) -> Self {
+        let is_static = account.credentials.is_static();
         Self {
             id: account.id.clone(),
             name: account.name.clone(),
@@ -593,37 +1756,377 @@ impl StoredAccount {
             request_count: account.request_count,
             error_count: account.error_count,
             created_at: account.created_at,
+            last_used_at: account.last_used_at,
+            cooldown_until: account.cooldown_until,
+            usage_windows: account.usage_windows.clone(),
+            access_token: is_static
+                .then(|| account.credentials.access_token.clone())
+                .flatten(),
+            expires_at: is_static
+                .then(|| account.credentials.expires_at.clone())
+                .flatten(),
             refresh_token: account.credentials.refresh_token.clone(),
             auth_method: account.credentials.auth_method.clone(),
             client_id: account.credentials.client_id.clone(),
             client_secret: account.credentials.client_secret.clone(),
             profile_arn: account.credentials.profile_arn.clone(),
+            refresh_url: account.credentials.refresh_url.clone(),
+            oidc_region: account.credentials.oidc_region.clone(),
+            region: account.credentials.region.clone(),
         }
     }
 
     fn into_account(self) -> Account {
         use crate::kiro::model::credentials::KiroCredentials;
 
+        // 可刷新的账号重启后重新刷新 Token；静态 Token 没有 refreshToken，沿用保存的 Token
+        let expires_at = match &self.access_token {
+            Some(_) => self.expires_at,
+            None => Some("2000-01-01T00:00:00Z".to_string()),
+        };
         let credentials = KiroCredentials {
-            access_token: None,
+            access_token: self.access_token,
             refresh_token: self.refresh_token,
             profile_arn: self.profile_arn,
-            expires_at: Some("2000-01-01T00:00:00Z".to_string()),
+            expires_at,
             auth_method: self.auth_method,
             client_id: self.client_id,
             client_secret: self.client_secret,
+            refresh_url: self.refresh_url,
+            oidc_region: self.oidc_region,
+            region: self.region,
+            machine_id: None,
+        };
+
+        // 重启后没有进行中的请求，排空中的账号直接视为排空完成
+        let status = match self.status {
+            super::account::AccountStatus::Draining => super::account::AccountStatus::Disabled,
+            status => status,
         };
 
         Account {
             id: self.id,
             name: self.name,
             credentials,
-            status: self.status,
+            status,
             request_count: self.request_count,
             error_count: self.error_count,
-            last_used_at: None,
-            cooldown_until: None,
+            last_used_at: self.last_used_at,
+            cooldown_until: self.cooldown_until,
             created_at: self.created_at,
+            usage_windows: self.usage_windows,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::credentials::KiroCredentials;
+    use std::time::{Duration, Instant};
+
+    async fn pool_with_accounts(n: usize) -> Arc<AccountPool> {
+        let pool = Arc::new(AccountPool::new(Config::default(), None));
+        for i in 0..n {
+            let account = Account::new(format!("acc-{i}"), "bench", KiroCredentials::default());
+            pool.add_account(account).await.unwrap();
+        }
+        pool
+    }
+
+    /// 模拟一次请求对账号池的访问：选择账号、记录客户端和请求结果
+    ///
+    /// 返回账号池操作本身的耗时（不含模拟的上游调用）
+    async fn simulate_request(pool: &AccountPool) -> Duration {
+        let start = Instant::now();
+        let selected = pool.select_account().await.unwrap();
+        let client = ClientInfo {
+            caller: "main".to_string(),
+            user_agent: Some("bench".to_string()),
+            anthropic_version: None,
+        };
+        pool.record_client(&client).await;
+        let elapsed = start.elapsed();
+
+        // 模拟上游调用，让出执行权
+        tokio::task::yield_now().await;
+
+        let start = Instant::now();
+        pool.add_request_log(RequestLog {
+            id: String::new(),
+            account_id: selected.id,
+            account_name: selected.name,
+            model: "claude-sonnet-4".to_string(),
+            input_tokens: 0,
+            output_tokens: 0,
+            success: true,
+            error: None,
+            timestamp: chrono::Utc::now(),
+            duration_ms: 0,
+            client,
+        })
+        .await;
+        elapsed + start.elapsed()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_selection_counts_every_request() {
+        let pool = pool_with_accounts(4).await;
+        let handles: Vec<_> = (0..50)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    for _ in 0..20 {
+                        simulate_request(&pool).await;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let stats = pool.get_stats().await;
+        assert_eq!(stats.total_requests, 1000);
+        // 轮询策略下各账号的使用次数均匀
+        for account in pool.list_accounts().await {
+            assert_eq!(account.request_count, 250);
+        }
+        assert!(stats.health.iter().all(|h| h.score > 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_drain_disables_account_after_in_flight_requests_finish() {
+        let pool = pool_with_accounts(1).await;
+        let selected = pool.select_account().await.unwrap();
+        assert_eq!(pool.in_flight_count("acc-0"), 1);
+
+        assert_eq!(pool.drain_account("acc-0").await, Some(1));
+        assert!(pool.select_account().await.is_none());
+        tokio::task::yield_now().await;
+        assert_eq!(pool.get_stats().await.draining, 1);
+
+        drop(selected);
+        for _ in 0..100 {
+            if pool.get_stats().await.disabled == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(pool.get_stats().await.disabled, 1);
+        assert_eq!(pool.in_flight_count("acc-0"), 0);
+
+        // 已禁用的账号不能再排空
+        assert_eq!(pool.drain_account("acc-0").await, None);
+        assert_eq!(pool.drain_account("missing").await, None);
+    }
+
+    #[test]
+    fn test_stored_account_keeps_static_token() {
+        let credentials = KiroCredentials {
+            access_token: Some("static".to_string()),
+            expires_at: Some("2030-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        let account =
+            StoredAccount::from_account(&Account::new("s", "static", credentials)).into_account();
+        assert!(account.credentials.is_static());
+        assert_eq!(
+            account.credentials.expires_at.as_deref(),
+            Some("2030-01-01T00:00:00Z")
+        );
+
+        // 可刷新的账号不保存 accessToken，重启后重新刷新
+        let credentials = KiroCredentials {
+            access_token: Some("token".to_string()),
+            refresh_token: Some("refresh".to_string()),
+            ..Default::default()
+        };
+        let stored = StoredAccount::from_account(&Account::new("r", "refresh", credentials));
+        assert!(stored.access_token.is_none());
+        let account = stored.into_account();
+        assert!(account.credentials.access_token.is_none());
+        assert_eq!(
+            account.credentials.expires_at.as_deref(),
+            Some("2000-01-01T00:00:00Z")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_shared_state_adopts_cooldown_from_other_instance() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        let replica = |storage: Arc<dyn Storage>| {
+            AccountPool::with_storage(Config::default(), None, std::env::temp_dir(), storage)
+        };
+        let first = replica(storage.clone());
+        let account = Account::new("acc-0", "shared", KiroCredentials::default());
+        first.add_account(account).await.unwrap();
+
+        let second = replica(storage);
+        second.load_persisted().await;
+        assert!(second.select_account().await.is_some());
+
+        first.record_error("acc-0", true).await;
+        // 另一个实例随后保存整个账号列表，不会覆盖已共享的冷却
+        second.save_to_file().await.unwrap();
+        second.sync_shared_state().await.unwrap();
+        assert!(second.select_account().await.is_none());
+        assert_eq!(second.get_stats().await.cooldown, 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_logs_are_flushed_in_batches() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        let pool = AccountPool::with_storage(
+            Config::default(),
+            None,
+            std::env::temp_dir(),
+            storage.clone(),
+        );
+        for _ in 0..3 {
+            pool.add_request_log(RequestLog {
+                id: uuid::Uuid::new_v4().to_string(),
+                account_id: "acc-0".to_string(),
+                account_name: "acc-0".to_string(),
+                model: "claude-sonnet-4".to_string(),
+                input_tokens: 0,
+                output_tokens: 0,
+                success: true,
+                error: None,
+                timestamp: chrono::Utc::now(),
+                duration_ms: 0,
+                client: ClientInfo::default(),
+            })
+            .await;
+        }
+        // 请求路径不写入存储
+        tokio::task::yield_now().await;
+        assert!(storage.get(LOGS_FILE).await.unwrap().is_none());
+
+        pool.flush_logs().await;
+        let saved: Vec<RequestLog> = storage.get_json(LOGS_FILE).await.unwrap().unwrap();
+        assert_eq!(saved.len(), 3);
+
+        // 没有新记录时不重复写入
+        storage.delete(LOGS_FILE).await.unwrap();
+        pool.flush_logs().await;
+        assert!(storage.get(LOGS_FILE).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_strategy_switch_is_persisted() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        let pool = |storage: Arc<dyn Storage>| {
+            AccountPool::with_storage(Config::default(), None, std::env::temp_dir(), storage)
+        };
+        let first = pool(storage.clone());
+        assert_eq!(
+            first.get_stats().await.strategy,
+            SelectionStrategy::RoundRobin
+        );
+        first.set_strategy(SelectionStrategy::LeastUsed).await;
+        assert_eq!(
+            first.get_stats().await.strategy,
+            SelectionStrategy::LeastUsed
+        );
+
+        // 重启后沿用切换后的策略
+        let restarted = pool(storage);
+        restarted.load_persisted().await;
+        assert_eq!(restarted.get_strategy().await, SelectionStrategy::LeastUsed);
+        assert_eq!(
+            SelectionStrategy::parse("Health-Weighted"),
+            Some(SelectionStrategy::HealthWeighted)
+        );
+        assert_eq!(SelectionStrategy::parse("sticky"), None);
+    }
+
+    #[tokio::test]
+    async fn test_overload_pauses_pool_without_cooling_accounts() {
+        let pool = pool_with_accounts(2).await;
+        pool.record_overload(Some(Duration::from_millis(200)));
+
+        assert!(pool.select_account().await.is_none());
+        let PoolReadiness::Saturated {
+            retry_after: Some(retry_after),
+        } = pool.readiness().await
+        else {
+            panic!("过载期间账号池应饱和");
+        };
+        assert!(retry_after <= Duration::from_millis(200));
+        assert_eq!(pool.get_stats().await.cooldown, 0);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        assert!(pool.select_account().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_error_budget_disables_account() {
+        let config = Config {
+            error_budget: Some(crate::model::config::ErrorBudgetConfig {
+                min_requests: 3,
+                ..Default::default()
+            }),
+            ..Config::default()
+        };
+        let pool = AccountPool::new(config, None);
+        let account = Account::new("acc-0", "broken", KiroCredentials::default());
+        pool.add_account(account).await.unwrap();
+
+        // 限流不计入错误预算
+        for _ in 0..3 {
+            pool.record_error("acc-0", true).await;
+        }
+        assert_eq!(pool.get_stats().await.disabled, 0);
+
+        for _ in 0..3 {
+            pool.record_error("acc-0", false).await;
+        }
+        assert_eq!(pool.get_stats().await.disabled, 1);
+
+        // 重新启用后重新统计
+        assert!(pool.enable_account("acc-0").await);
+        pool.record_error("acc-0", false).await;
+        assert_eq!(pool.get_stats().await.disabled, 0);
+    }
+
+    /// 账号池热路径延迟基准：500 个并发请求，输出 p50/p99
+    ///
+    /// 运行：`cargo test --release pool_hot_path_latency -- --ignored --nocapture`
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    #[ignore]
+    async fn bench_pool_hot_path_latency() {
+        const CONCURRENCY: usize = 500;
+        const ROUNDS: usize = 200;
+
+        let pool = pool_with_accounts(16).await;
+        let mut handles = Vec::with_capacity(CONCURRENCY);
+        for _ in 0..CONCURRENCY {
+            let pool = pool.clone();
+            handles.push(tokio::spawn(async move {
+                let mut latencies = Vec::with_capacity(ROUNDS);
+                for _ in 0..ROUNDS {
+                    latencies.push(simulate_request(&pool).await);
+                }
+                latencies
+            }));
+        }
+        let mut latencies: Vec<Duration> = Vec::with_capacity(CONCURRENCY * ROUNDS);
+        for handle in handles {
+            latencies.extend(handle.await.unwrap());
         }
+        latencies.sort();
+        let percentile = |p: usize| latencies[latencies.len() * p / 100];
+        println!(
+            "账号池热路径延迟（{} 并发 × {} 轮）：p50 {:?}，p99 {:?}",
+            CONCURRENCY,
+            ROUNDS,
+            percentile(50),
+            percentile(99)
+        );
+        assert_eq!(
+            pool.get_stats().await.total_requests,
+            (CONCURRENCY * ROUNDS) as u64
+        );
     }
 }
@@ -1,13 +1,29 @@
 //! 账号池模块
 //!
-//! 提供多账号管理、负载均衡和状态追踪功能
+//! 提供多账号管理、负载均衡、状态追踪和多租户工作区功能
 
 pub mod account;
+pub mod collector;
+pub mod error_budget;
+pub mod forecast;
+pub mod health;
+pub mod import;
+pub mod in_flight;
+pub mod journal;
+pub mod ledger;
+pub mod machine_ids;
 pub mod manager;
+pub mod probe;
+pub mod schedule;
+pub mod snapshot;
 pub mod strategy;
+pub mod tombstone;
 pub mod usage;
+pub mod webhook;
+pub mod workspace;
 
 pub use account::Account;
-pub use manager::{AccountPool, PoolStats};
+pub use manager::{AccountPool, PoolReadiness, PoolStats};
 pub use strategy::SelectionStrategy;
-pub use usage::RequestLog;
+pub use usage::{ClientInfo, ClientStats, RequestLog};
+pub use workspace::Workspace;
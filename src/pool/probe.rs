@@ -0,0 +1,182 @@
+//! 账号连通性测试
+//!
+//! 对单个账号执行一次端到端检查：获取有效 Token 后发送一条极短的生成请求，
+//! 用于在新账号接入真实流量前确认其可用。测试请求不计入账号的使用统计。
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::kiro::model::events::Event;
+use crate::kiro::model::requests::conversation::{
+    ConversationState, CurrentMessage, UserInputMessage,
+};
+use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::parser::decoder::{DecoderConfig, EventStreamDecoder};
+use crate::kiro::provider::KiroProvider;
+use crate::model::config::{AgentMode, ChatTriggerType, KiroOrigin};
+
+/// 默认测试模型（Kiro 模型 ID）
+pub const DEFAULT_TEST_MODEL: &str = "claude-haiku-4.5";
+
+/// 测试提示词（要求模型只回复一个词，尽量少消耗额度）
+const TEST_PROMPT: &str = "Reply with the single word: OK";
+
+/// 回复预览的最大字符数
+const PREVIEW_CHARS: usize = 64;
+
+/// 单个检查步骤的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeStep {
+    pub ok: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ProbeStep {
+    pub fn finish<T, E: std::fmt::Display>(start: Instant, result: &Result<T, E>) -> Self {
+        Self {
+            ok: result.is_ok(),
+            latency_ms: start.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// 账号测试报告
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountTestReport {
+    pub id: String,
+    pub success: bool,
+    /// 测试使用的模型
+    pub model: String,
+    /// 模型是否可用（生成请求成功返回内容）
+    pub model_available: bool,
+    /// Token 获取/刷新
+    pub token: ProbeStep,
+    /// 生成请求（Token 获取失败时跳过）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation: Option<ProbeStep>,
+    /// 模型回复预览
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply: Option<String>,
+    pub total_latency_ms: u64,
+}
+
+/// 构造测试用的 Kiro 请求体
+fn test_request_body(model: &str, profile_arn: Option<String>) -> anyhow::Result<String> {
+    let message =
+        UserInputMessage::new(TEST_PROMPT, model).with_origin(KiroOrigin::default().as_str());
+    let state = ConversationState::new(uuid::Uuid::new_v4().to_string())
+        .with_agent_task_type(AgentMode::default().as_str())
+        .with_chat_trigger_type(ChatTriggerType::Manual.as_str())
+        .with_current_message(CurrentMessage::new(message));
+    let request = KiroRequest {
+        conversation_state: state,
+        profile_arn,
+    };
+    Ok(serde_json::to_string(&request)?)
+}
+
+/// 从事件流响应中提取回复文本，上游返回错误事件时视为失败
+fn extract_reply(body: &[u8], config: DecoderConfig) -> anyhow::Result<String> {
+    let mut decoder = EventStreamDecoder::from_config(config);
+    decoder.feed(body)?;
+    let mut reply = String::new();
+    for frame in decoder.decode_iter().flatten() {
+        match Event::from_frame(frame) {
+            Ok(Event::AssistantResponse(resp)) => reply.push_str(&resp.content),
+            Ok(Event::Error {
+                error_code,
+                error_message,
+            }) => anyhow::bail!("上游错误 {}: {}", error_code, error_message),
+            Ok(Event::Exception {
+                exception_type,
+                message,
+            }) => anyhow::bail!("上游异常 {}: {}", exception_type, message),
+            _ => {}
+        }
+    }
+    if reply.trim().is_empty() {
+        anyhow::bail!("上游未返回任何内容");
+    }
+    Ok(reply.trim().chars().take(PREVIEW_CHARS).collect())
+}
+
+/// 发送测试生成请求，返回回复预览
+pub async fn probe_generation(
+    provider: &KiroProvider,
+    model: &str,
+    decoder: DecoderConfig,
+) -> anyhow::Result<String> {
+    let body = test_request_body(model, provider.profile_arn().await)?;
+    let response = provider.call_api(&body, AgentMode::default()).await?;
+    let bytes = response.bytes().await?;
+    extract_reply(&bytes, decoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_eventstream_lite::crc::crc32;
+
+    fn frame(message_type: &str, type_header: &str, event_type: &str, payload: &str) -> Vec<u8> {
+        let mut headers = Vec::new();
+        for (name, value) in [(":message-type", message_type), (type_header, event_type)] {
+            headers.push(name.len() as u8);
+            headers.extend_from_slice(name.as_bytes());
+            headers.push(7);
+            headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            headers.extend_from_slice(value.as_bytes());
+        }
+        let total_len = 12 + headers.len() + payload.len() + 4;
+        let mut frame = Vec::with_capacity(total_len);
+        frame.extend_from_slice(&(total_len as u32).to_be_bytes());
+        frame.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&crc32(&frame[..8]).to_be_bytes());
+        frame.extend_from_slice(&headers);
+        frame.extend_from_slice(payload.as_bytes());
+        frame.extend_from_slice(&crc32(&frame).to_be_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_extract_reply() {
+        let mut body = frame(
+            "event",
+            ":event-type",
+            "assistantResponseEvent",
+            r#"{"content":"O"}"#,
+        );
+        body.extend(frame(
+            "event",
+            ":event-type",
+            "assistantResponseEvent",
+            r#"{"content":"K"}"#,
+        ));
+        assert_eq!(
+            extract_reply(&body, DecoderConfig::default()).unwrap(),
+            "OK"
+        );
+
+        assert!(extract_reply(&[], DecoderConfig::default()).is_err());
+        let exception = frame(
+            "exception",
+            ":exception-type",
+            "ThrottlingException",
+            r#"{"message":"slow down"}"#,
+        );
+        let err = extract_reply(&exception, DecoderConfig::default()).unwrap_err();
+        assert!(err.to_string().contains("ThrottlingException"));
+    }
+
+    #[test]
+    fn test_build_request_body() {
+        let body = test_request_body(DEFAULT_TEST_MODEL, None).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let message = &json["conversationState"]["currentMessage"]["userInputMessage"];
+        assert_eq!(message["modelId"], DEFAULT_TEST_MODEL);
+        assert!(json.get("profileArn").is_none());
+    }
+}
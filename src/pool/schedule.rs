@@ -0,0 +1,126 @@
+//! 账号使用时间窗口
+//!
+//! 在账号文件中为账号配置 `usage_windows`（UTC 时间，如 `["00:00-08:00"]`），
+//! 窗口之外账号视为不可用，便于按不同时区的每日配额重置时间错开使用。
+//! 结束时间早于开始时间表示跨越午夜（如 `22:00-06:00`），未配置时全天可用。
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// 一天的秒数
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// 每日使用时间窗口（UTC，左闭右开）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct UsageWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl UsageWindow {
+    /// 从 `HH:MM-HH:MM` 格式解析
+    pub fn parse(s: &str) -> Option<Self> {
+        let (start, end) = s.trim().split_once('-')?;
+        Some(Self {
+            start: NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?,
+            end: NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?,
+        })
+    }
+
+    /// 指定时刻是否在窗口内（开始与结束相同时表示全天）
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let time = now.time();
+        if self.start <= self.end {
+            self.start == self.end || (self.start <= time && time < self.end)
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// 距离下一次窗口开始的时长
+    fn opens_in(&self, now: DateTime<Utc>) -> chrono::Duration {
+        let secs = |t: NaiveTime| t.num_seconds_from_midnight() as i64;
+        let wait = (secs(self.start) - secs(now.time())).rem_euclid(DAY_SECS);
+        chrono::Duration::seconds(wait)
+    }
+}
+
+impl fmt::Display for UsageWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+impl TryFrom<String> for UsageWindow {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s).ok_or_else(|| format!("无效的使用时间窗口: {}（格式 HH:MM-HH:MM）", s))
+    }
+}
+
+impl From<UsageWindow> for String {
+    fn from(window: UsageWindow) -> Self {
+        window.to_string()
+    }
+}
+
+/// 指定时刻是否处于任一窗口内（未配置窗口时始终为 true）
+pub fn within_windows(windows: &[UsageWindow], now: DateTime<Utc>) -> bool {
+    windows.is_empty() || windows.iter().any(|w| w.contains(now))
+}
+
+/// 处于窗口之外时，距离最近一个窗口开始的时长
+pub fn next_window_in(windows: &[UsageWindow], now: DateTime<Utc>) -> Option<chrono::Duration> {
+    if within_windows(windows, now) {
+        return None;
+    }
+    windows.iter().map(|w| w.opens_in(now)).min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&format!("2025-01-01T{:02}:{:02}:00Z", hour, minute))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_windows() {
+        let night = UsageWindow::parse("22:00-06:00").unwrap();
+        let morning = UsageWindow::parse("00:00-08:00").unwrap();
+        assert!(night.contains(at(23, 0)) && night.contains(at(5, 59)));
+        assert!(!night.contains(at(6, 0)) && !night.contains(at(12, 0)));
+        assert!(morning.contains(at(0, 0)) && !morning.contains(at(8, 0)));
+        assert!(UsageWindow::parse("25:00-08:00").is_none());
+
+        assert!(within_windows(&[], at(12, 0)));
+        assert!(!within_windows(&[morning], at(12, 0)));
+        assert_eq!(
+            next_window_in(&[morning, night], at(12, 30)),
+            Some(chrono::Duration::minutes(9 * 60 + 30))
+        );
+        assert_eq!(next_window_in(&[morning], at(1, 0)), None);
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let windows: Vec<UsageWindow> = serde_json::from_str(r#"["00:00-08:00"]"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&windows).unwrap(),
+            r#"["00:00-08:00"]"#
+        );
+        assert!(serde_json::from_str::<Vec<UsageWindow>>(r#"["8am"]"#).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! 账号池状态快照
+//!
+//! 将账号（含凭证、冷却状态和计数）、请求记录、配额缓存和选择策略打包为单个 JSON 文件，
+//! 用于在主机之间迁移服务而不丢失冷却和配额跟踪。
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::manager::StoredAccount;
+use super::strategy::SelectionStrategy;
+use super::usage::{RequestLog, UsageLimits};
+
+/// 当前快照格式版本
+pub const SNAPSHOT_FORMAT_VERSION: u32 = 1;
+
+/// 账号池状态快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolSnapshot {
+    /// 快照格式版本
+    pub format_version: u32,
+    /// 生成快照的服务版本
+    pub server_version: String,
+    pub created_at: DateTime<Utc>,
+    pub strategy: SelectionStrategy,
+    pub(crate) accounts: Vec<StoredAccount>,
+    #[serde(default)]
+    pub request_logs: Vec<RequestLog>,
+    #[serde(default)]
+    pub usage_cache: HashMap<String, UsageLimits>,
+}
+
+impl PoolSnapshot {
+    /// 快照中的账号数
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// 检查快照格式版本是否受支持
+    pub fn check_version(&self) -> anyhow::Result<()> {
+        if self.format_version > SNAPSHOT_FORMAT_VERSION {
+            anyhow::bail!(
+                "快照格式版本 {} 高于当前支持的版本 {}，请升级服务",
+                self.format_version,
+                SNAPSHOT_FORMAT_VERSION
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::credentials::KiroCredentials;
+    use crate::model::config::Config;
+    use crate::pool::{Account, AccountPool, PoolReadiness};
+
+    fn temp_dir() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("kiro-snapshot-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_restore_keeps_cooldown_and_strategy() {
+        let (dir_a, dir_b) = (temp_dir(), temp_dir());
+        let source = AccountPool::with_data_dir(Config::default(), None, dir_a.clone());
+        let account = Account::new("acc", "work", KiroCredentials::default());
+        source.add_account(account).await.unwrap();
+        source.record_error("acc", true).await;
+        source.set_strategy(SelectionStrategy::LeastUsed).await;
+
+        let json = serde_json::to_string(&source.snapshot().await).unwrap();
+        let snapshot: PoolSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(snapshot.account_count(), 1);
+
+        let target = AccountPool::with_data_dir(Config::default(), None, dir_b.clone());
+        assert_eq!(target.restore(snapshot).await.unwrap(), 1);
+        assert_eq!(target.get_strategy().await, SelectionStrategy::LeastUsed);
+        assert!(matches!(
+            target.readiness().await,
+            PoolReadiness::Saturated { .. }
+        ));
+
+        // 恢复结果已持久化，重启后冷却状态仍然有效
+        let reloaded = AccountPool::with_data_dir(Config::default(), None, dir_b.clone());
+        reloaded.load_persisted().await;
+        assert_eq!(reloaded.get_stats().await.cooldown, 1);
+
+        let _ = std::fs::remove_dir_all(&dir_a);
+        let _ = std::fs::remove_dir_all(&dir_b);
+    }
+
+    #[test]
+    fn test_rejects_newer_format() {
+        let snapshot = PoolSnapshot {
+            format_version: SNAPSHOT_FORMAT_VERSION + 1,
+            server_version: "99.0.0".to_string(),
+            created_at: Utc::now(),
+            strategy: SelectionStrategy::default(),
+            accounts: Vec::new(),
+            request_logs: Vec::new(),
+            usage_cache: HashMap::new(),
+        };
+        assert!(snapshot.check_version().is_err());
+    }
+}
@@ -13,14 +13,28 @@ pub enum SelectionStrategy {
     Random,
     /// 最少使用
     LeastUsed,
+    /// 按健康分加权随机
+    HealthWeighted,
 }
 
 impl SelectionStrategy {
+    /// 解析策略名称（如 `round-robin`），无法识别时返回 None
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "round-robin" => Some(Self::RoundRobin),
+            "random" => Some(Self::Random),
+            "least-used" => Some(Self::LeastUsed),
+            "health-weighted" => Some(Self::HealthWeighted),
+            _ => None,
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::RoundRobin => "round-robin",
             Self::Random => "random",
             Self::LeastUsed => "least-used",
+            Self::HealthWeighted => "health-weighted",
         }
     }
 }
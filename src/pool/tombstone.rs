@@ -0,0 +1,199 @@
+//! 已移除账号的墓碑记录
+//!
+//! 移除账号时保留一条墓碑：移除原因、时间、移除前的状态和历史统计（请求数、错误数、
+//! Machine ID、计费台账和最近一次配额查询结果），持久化到 `tombstones.json`。
+//! 运营方可以据此追踪哪些账号因封禁或过期被移除，并与设备指纹、用量模式对照。
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+
+use super::account::AccountStatus;
+use super::ledger::LedgerEntry;
+use super::usage::UsageLimits;
+
+/// 存储键
+const TOMBSTONES_KEY: &str = "tombstones.json";
+
+/// 最多保留的墓碑数，超出时丢弃最早的记录
+const MAX_TOMBSTONES: usize = 1000;
+
+/// 移除原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemovalReason {
+    /// 运营方手动移除
+    Manual,
+    /// 账号被上游封禁或暂停
+    Banned,
+    /// 凭证过期且无法刷新
+    Expired,
+    /// 已失效（未细分原因）
+    Invalid,
+}
+
+impl RemovalReason {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "manual" => Some(Self::Manual),
+            "banned" => Some(Self::Banned),
+            "expired" => Some(Self::Expired),
+            "invalid" => Some(Self::Invalid),
+            _ => None,
+        }
+    }
+
+    /// 未指定原因时按账号移除前的状态推断
+    pub fn infer(status: AccountStatus) -> Self {
+        match status {
+            AccountStatus::Invalid => Self::Invalid,
+            _ => Self::Manual,
+        }
+    }
+}
+
+/// 已移除账号的记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    pub account_id: String,
+    pub account_name: String,
+    pub reason: RemovalReason,
+    /// 运营方备注
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub removed_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    /// 移除前的状态
+    pub last_status: AccountStatus,
+    pub request_count: u64,
+    pub error_count: u64,
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// 移除前固定的 Machine ID
+    pub machine_id: Option<String>,
+    /// 移除前的计费台账
+    pub ledger: Option<LedgerEntry>,
+    /// 移除前最近一次查询到的配额
+    pub usage: Option<UsageLimits>,
+}
+
+/// 墓碑存储，按移除时间排列
+pub struct TombstoneStore {
+    storage: Arc<dyn Storage>,
+    entries: Mutex<VecDeque<Tombstone>>,
+}
+
+impl TombstoneStore {
+    /// 创建空存储，调用 [`TombstoneStore::load`] 加载已保存的记录
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 从存储加载，不存在或解析失败时从空记录开始
+    pub async fn load(&self) {
+        let entries = match self.storage.get_json(TOMBSTONES_KEY).await {
+            Ok(entries) => entries.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("加载已移除账号记录失败: {}", e);
+                VecDeque::new()
+            }
+        };
+        *self.entries.lock().unwrap() = entries;
+    }
+
+    /// 记录一个已移除的账号
+    pub async fn record(&self, tombstone: Tombstone) {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.push_back(tombstone);
+            while entries.len() > MAX_TOMBSTONES {
+                entries.pop_front();
+            }
+        }
+        self.save().await;
+    }
+
+    /// 已移除的账号（最近移除的在前），可按原因过滤
+    pub fn list(&self, reason: Option<RemovalReason>) -> Vec<Tombstone> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|t| reason.is_none_or(|r| t.reason == r))
+            .cloned()
+            .collect()
+    }
+
+    async fn save(&self) {
+        let entries = self.entries.lock().unwrap().clone();
+        if let Err(e) = self.storage.put_json(TOMBSTONES_KEY, &entries).await {
+            tracing::warn!("保存已移除账号记录失败: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+
+    fn tombstone(id: &str, reason: RemovalReason) -> Tombstone {
+        Tombstone {
+            account_id: id.to_string(),
+            account_name: id.to_string(),
+            reason,
+            note: None,
+            removed_at: Utc::now(),
+            created_at: Utc::now(),
+            last_status: AccountStatus::Invalid,
+            request_count: 3,
+            error_count: 1,
+            last_used_at: None,
+            machine_id: None,
+            ledger: None,
+            usage: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tombstones_persist_and_filter() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        let store = TombstoneStore::new(storage.clone());
+        store.record(tombstone("a", RemovalReason::Banned)).await;
+        store.record(tombstone("b", RemovalReason::Manual)).await;
+
+        let reloaded = TombstoneStore::new(storage);
+        reloaded.load().await;
+        let ids: Vec<_> = reloaded
+            .list(None)
+            .into_iter()
+            .map(|t| t.account_id)
+            .collect();
+        assert_eq!(ids, vec!["b", "a"]);
+        let banned = reloaded.list(Some(RemovalReason::Banned));
+        assert_eq!(banned.len(), 1);
+        assert_eq!(banned[0].account_id, "a");
+
+        assert_eq!(
+            RemovalReason::parse("EXPIRED"),
+            Some(RemovalReason::Expired)
+        );
+        assert_eq!(RemovalReason::parse("gone"), None);
+        assert_eq!(
+            RemovalReason::infer(AccountStatus::Invalid),
+            RemovalReason::Invalid
+        );
+        assert_eq!(
+            RemovalReason::infer(AccountStatus::Active),
+            RemovalReason::Manual
+        );
+    }
+}
@@ -2,7 +2,7 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 /// 请求记录
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +27,21 @@ pub struct RequestLog {
     pub timestamp: DateTime<Utc>,
     /// 耗时（毫秒）
     pub duration_ms: u64,
+    /// 客户端信息
+    #[serde(flatten)]
+    pub client: ClientInfo,
+}
+
+/// 请求的客户端信息
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientInfo {
+    /// 调用方：主 API Key 为 `main`，工作区为 `workspace:{名称}`，托管 API Key 为 `key:{ID}`
+    pub caller: String,
+    /// User-Agent 请求头
+    pub user_agent: Option<String>,
+    /// anthropic-version 请求头
+    pub anthropic_version: Option<String>,
 }
 
 /// 使用限制信息（来自 AWS API）
@@ -50,6 +65,13 @@ pub struct UsageLimits {
     pub subscription_type: Option<String>,
 }
 
+impl UsageLimits {
+    /// 配额是否已用尽（且尚未到重置时间）
+    pub fn is_exhausted(&self) -> bool {
+        self.available <= 0.0 && self.next_reset.is_none_or(|reset| reset > Utc::now())
+    }
+}
+
 /// 免费试用信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FreeTrialInfo {
@@ -135,6 +157,93 @@ pub struct RequestStats {
     pub avg_duration_ms: u64,
 }
 
+/// 单独统计的 User-Agent、客户端名称和版本数量上限，超出后计入 "other"
+const MAX_TRACKED_USER_AGENTS: usize = 200;
+
+/// 单独统计的调用方数量上限，超出后计入 "other"
+const MAX_TRACKED_CALLERS: usize = 200;
+
+/// 客户端分布
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientDistribution {
+    /// 按客户端名称（User-Agent 的产品名）统计
+    pub clients: HashMap<String, u64>,
+    /// 按完整 User-Agent 统计
+    pub user_agents: HashMap<String, u64>,
+    /// 按 anthropic-version 请求头统计
+    pub anthropic_versions: HashMap<String, u64>,
+}
+
+impl ClientDistribution {
+    fn record(&mut self, user_agent: Option<&str>, anthropic_version: Option<&str>) {
+        let user_agent = user_agent.map(str::trim).filter(|ua| !ua.is_empty());
+        let version = anthropic_version.map(str::trim).filter(|v| !v.is_empty());
+
+        let name = user_agent.map(client_name);
+        count(&mut self.clients, name.as_deref(), MAX_TRACKED_USER_AGENTS);
+        count(&mut self.user_agents, user_agent, MAX_TRACKED_USER_AGENTS);
+        count(
+            &mut self.anthropic_versions,
+            version,
+            MAX_TRACKED_USER_AGENTS,
+        );
+    }
+}
+
+/// 计数加一：缺失计入 "unknown"，超出上限的新值计入 "other"
+fn count(map: &mut HashMap<String, u64>, key: Option<&str>, limit: usize) {
+    let key = match key {
+        Some(key) if map.contains_key(key) || map.len() < limit => key,
+        Some(_) => "other",
+        None => "unknown",
+    };
+    *map.entry(key.to_string()).or_default() += 1;
+}
+
+/// 客户端分布统计（自启动以来）
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ClientStats {
+    /// 全部请求
+    #[serde(flatten)]
+    pub total: ClientDistribution,
+    /// 按调用方统计
+    pub callers: HashMap<String, ClientDistribution>,
+}
+
+impl ClientStats {
+    /// 记录一次请求的客户端信息
+    pub fn record(&mut self, client: &ClientInfo) {
+        let user_agent = client.user_agent.as_deref();
+        let version = client.anthropic_version.as_deref();
+        self.total.record(user_agent, version);
+
+        let caller = if self.callers.contains_key(&client.caller)
+            || self.callers.len() < MAX_TRACKED_CALLERS
+        {
+            client.caller.clone()
+        } else {
+            "other".to_string()
+        };
+        self.callers
+            .entry(caller)
+            .or_default()
+            .record(user_agent, version);
+    }
+}
+
+/// 从 User-Agent 中提取客户端名称
+///
+/// 取第一个产品标识的名称部分，如 `claude-cli/1.0.83 (external, cli)` → `claude-cli`
+pub fn client_name(user_agent: &str) -> String {
+    user_agent
+        .split_whitespace()
+        .next()
+        .and_then(|product| product.split('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
 impl Default for RequestLogger {
     fn default() -> Self {
         Self::new(1000) // 默认保留 1000 条记录
@@ -144,6 +253,7 @@ impl Default for RequestLogger {
 /// AWS 使用限制 API 响应结构
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct AwsUsageLimitsResponse {
     pub usage_breakdown_list: Vec<AwsUsageBreakdown>,
     pub user_info: Option<AwsUserInfo>,
@@ -154,6 +264,7 @@ pub struct AwsUsageLimitsResponse {
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct AwsUsageBreakdown {
     pub resource_type: String,
     pub usage_limit: Option<i32>,
@@ -166,6 +277,7 @@ pub struct AwsUsageBreakdown {
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct AwsFreeTrialInfo {
     pub free_trial_status: String,
     pub usage_limit: Option<i32>,
@@ -177,6 +289,7 @@ pub struct AwsFreeTrialInfo {
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct AwsUserInfo {
     pub email: Option<String>,
     pub user_id: Option<String>,
@@ -184,6 +297,7 @@ pub struct AwsUserInfo {
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 pub struct AwsSubscriptionInfo {
     #[serde(rename = "type")]
     pub subscription_type: Option<String>,
@@ -265,3 +379,54 @@ pub async fn check_usage_limits(access_token: &str) -> anyhow::Result<UsageLimit
 
     anyhow::bail!("未找到 CREDIT 类型的使用限制")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_name() {
+        assert_eq!(
+            client_name("claude-cli/1.0.83 (external, cli)"),
+            "claude-cli"
+        );
+        assert_eq!(client_name("Anthropic/JS 0.39.0"), "Anthropic");
+        assert_eq!(client_name("SillyTavern"), "SillyTavern");
+    }
+
+    #[test]
+    fn test_client_stats_record() {
+        let client = |caller: &str, user_agent: Option<&str>, version: Option<&str>| ClientInfo {
+            caller: caller.to_string(),
+            user_agent: user_agent.map(str::to_string),
+            anthropic_version: version.map(str::to_string),
+        };
+        let mut stats = ClientStats::default();
+        stats.record(&client(
+            "main",
+            Some("claude-cli/1.0.83 (external, cli)"),
+            Some("2023-06-01"),
+        ));
+        stats.record(&client(
+            "key:k1",
+            Some("claude-cli/1.0.90 (external, cli)"),
+            Some("2023-06-01"),
+        ));
+        stats.record(&client("key:k1", None, None));
+
+        assert_eq!(stats.total.clients["claude-cli"], 2);
+        assert_eq!(stats.total.clients["unknown"], 1);
+        assert_eq!(stats.total.user_agents.len(), 3);
+        assert_eq!(stats.total.anthropic_versions["2023-06-01"], 2);
+        assert_eq!(stats.total.anthropic_versions["unknown"], 1);
+        assert_eq!(stats.callers["main"].clients["claude-cli"], 1);
+        assert_eq!(stats.callers["key:k1"].clients["unknown"], 1);
+
+        // 超出上限的新客户端名称计入 other
+        for i in 0..MAX_TRACKED_USER_AGENTS {
+            stats.record(&client("main", Some(&format!("c{}/1", i)), None));
+        }
+        assert_eq!(stats.total.clients.len(), MAX_TRACKED_USER_AGENTS + 1);
+        assert_eq!(stats.total.clients["other"], 2);
+    }
+}
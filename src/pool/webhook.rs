@@ -0,0 +1,244 @@
+//! 账号池事件 Webhook 通知
+//!
+//! 账号进入冷却、失效、Token 刷新失败、错误率超出预算被禁用、配额接近用尽或已用尽、整个账号池不可用时，
+//! 向配置的 URL 发送 JSON POST 请求。载荷包含 `text` 字段，可直接用于 Slack 等 Incoming Webhook。
+
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::http_client::{build_client, ProxyConfig};
+
+/// Webhook 请求超时（秒）
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
+/// 账号池事件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PoolEvent {
+    /// 账号被限流，进入冷却
+    AccountCooldown {
+        account_id: String,
+        account_name: String,
+        cooldown_until: Option<DateTime<Utc>>,
+    },
+    /// 账号被标记为失效
+    AccountInvalid {
+        account_id: String,
+        account_name: String,
+    },
+    /// Token 刷新失败
+    TokenRefreshFailed {
+        account_id: String,
+        account_name: String,
+        error: String,
+    },
+    /// Token 连续刷新失败，已熔断
+    RefreshCircuitOpen {
+        account_id: String,
+        account_name: String,
+        failures: u32,
+        retry_at: DateTime<Utc>,
+        error: String,
+    },
+    /// 账号错误率超出预算，已自动禁用
+    ErrorBudgetExceeded {
+        account_id: String,
+        account_name: String,
+        error_rate: f64,
+        requests: usize,
+        window_secs: u64,
+    },
+    /// 配额使用率跨过告警阈值
+    QuotaWarning {
+        account_id: String,
+        account_name: String,
+        threshold_percent: u8,
+        usage_percent: f64,
+        exhausts_at: Option<DateTime<Utc>>,
+    },
+    /// 配额已用尽
+    QuotaExhausted {
+        account_id: String,
+        account_name: String,
+        next_reset: Option<DateTime<Utc>>,
+    },
+    /// 账号池中没有可用账号
+    PoolDegraded {
+        total_accounts: usize,
+        retry_after_secs: Option<u64>,
+    },
+}
+
+impl PoolEvent {
+    /// 人类可读的事件描述
+    pub fn summary(&self) -> String {
+        match self {
+            Self::AccountCooldown {
+                account_name,
+                cooldown_until,
+                ..
+            } => match cooldown_until {
+                Some(until) => format!(
+                    "账号 {} 被限流，冷却至 {}",
+                    account_name,
+                    until.to_rfc3339()
+                ),
+                None => format!("账号 {} 被限流，进入冷却", account_name),
+            },
+            Self::AccountInvalid { account_name, .. } => {
+                format!("账号 {} 已被标记为失效", account_name)
+            }
+            Self::TokenRefreshFailed {
+                account_name,
+                error,
+                ..
+            } => format!("账号 {} Token 刷新失败: {}", account_name, error),
+            Self::RefreshCircuitOpen {
+                account_name,
+                failures,
+                retry_at,
+                error,
+                ..
+            } => format!(
+                "账号 {} Token 连续刷新失败 {} 次，已标记失效并暂停刷新至 {}: {}",
+                account_name,
+                failures,
+                retry_at.to_rfc3339(),
+                error
+            ),
+            Self::ErrorBudgetExceeded {
+                account_name,
+                error_rate,
+                requests,
+                window_secs,
+                ..
+            } => format!(
+                "账号 {} 最近 {} 秒错误率 {:.0}%（{} 次请求），已自动禁用",
+                account_name,
+                window_secs,
+                error_rate * 100.0,
+                requests
+            ),
+            Self::QuotaWarning {
+                account_name,
+                threshold_percent,
+                usage_percent,
+                exhausts_at,
+                ..
+            } => {
+                let mut summary = format!(
+                    "账号 {} 配额已使用 {:.1}%，超过告警阈值 {}%",
+                    account_name, usage_percent, threshold_percent
+                );
+                if let Some(at) = exhausts_at {
+                    summary.push_str(&format!("，按当前速率预计 {} 用尽", at.to_rfc3339()));
+                }
+                summary
+            }
+            Self::QuotaExhausted {
+                account_name,
+                next_reset,
+                ..
+            } => match next_reset {
+                Some(reset) => format!(
+                    "账号 {} 配额已用尽，将于 {} 重置",
+                    account_name,
+                    reset.to_rfc3339()
+                ),
+                None => format!("账号 {} 配额已用尽", account_name),
+            },
+            Self::PoolDegraded {
+                total_accounts,
+                retry_after_secs,
+            } => match retry_after_secs {
+                Some(secs) => format!(
+                    "账号池 {} 个账号均不可用，预计 {} 秒后恢复",
+                    total_accounts, secs
+                ),
+                None => format!("账号池 {} 个账号均不可用", total_accounts),
+            },
+        }
+    }
+
+    /// 构建 Webhook 载荷
+    pub fn payload(&self) -> serde_json::Value {
+        let mut payload = serde_json::to_value(self).unwrap_or_default();
+        if let Some(obj) = payload.as_object_mut() {
+            obj.insert("text".to_string(), self.summary().into());
+            obj.insert("timestamp".to_string(), Utc::now().to_rfc3339().into());
+        }
+        payload
+    }
+}
+
+/// Webhook 通知器
+pub struct WebhookNotifier {
+    urls: Vec<String>,
+    client: Client,
+}
+
+impl WebhookNotifier {
+    /// 创建通知器，未配置 URL 时返回 None
+    pub fn new(urls: &[String], proxy: Option<&ProxyConfig>) -> Option<Self> {
+        if urls.is_empty() {
+            return None;
+        }
+        let client = match build_client(proxy, WEBHOOK_TIMEOUT_SECS) {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::warn!("创建 Webhook HTTP 客户端失败: {}", e);
+                return None;
+            }
+        };
+        Some(Self {
+            urls: urls.to_vec(),
+            client,
+        })
+    }
+
+    /// 异步发送事件通知，不阻塞调用方
+    pub fn notify(&self, event: PoolEvent) {
+        tracing::info!("发送 Webhook 通知: {}", event.summary());
+        let payload = event.payload();
+        for url in &self.urls {
+            let client = self.client.clone();
+            let url = url.clone();
+            let payload = payload.clone();
+            tokio::spawn(async move {
+                match client.post(&url).json(&payload).send().await {
+                    Ok(resp) if !resp.status().is_success() => {
+                        tracing::warn!("Webhook {} 返回错误状态: {}", url, resp.status());
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Webhook {} 发送失败: {}", url, e),
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_contains_event_and_text() {
+        let event = PoolEvent::AccountInvalid {
+            account_id: "id-1".to_string(),
+            account_name: "work".to_string(),
+        };
+        let payload = event.payload();
+
+        assert_eq!(payload["event"], "account_invalid");
+        assert_eq!(payload["account_id"], "id-1");
+        assert_eq!(payload["text"], "账号 work 已被标记为失效");
+        assert!(payload["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_notifier_requires_urls() {
+        assert!(WebhookNotifier::new(&[], None).is_none());
+        assert!(WebhookNotifier::new(&["http://127.0.0.1:9/hook".to_string()], None).is_some());
+    }
+}
@@ -0,0 +1,71 @@
+//! 多租户工作区
+//!
+//! 每个工作区通过独立的 API Key 访问，拥有独立的账号池、配额缓存和请求记录，
+//! 数据存储在各自的数据目录中，不同工作区之间的 Kiro 账号严格隔离。
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::http_client::ProxyConfig;
+use crate::model::config::{Config, RequestPriority, RequestTimeouts, WorkspaceConfig};
+use crate::storage;
+
+use super::AccountPool;
+
+/// 工作区
+#[derive(Clone)]
+pub struct Workspace {
+    /// 工作区名称
+    pub name: String,
+    /// 工作区 API Key
+    pub api_key: String,
+    /// 工作区独立的账号池
+    pub pool: Arc<AccountPool>,
+    /// 工作区的系统提示词（覆盖全局配置）
+    pub system_prompt: Option<String>,
+    /// 工作区请求的默认优先级
+    pub priority: RequestPriority,
+    /// 工作区请求的时长限制（覆盖全局配置）
+    pub timeouts: RequestTimeouts,
+}
+
+impl Workspace {
+    /// 根据配置创建工作区并加载持久化数据
+    ///
+    /// 未指定 dataDir 时使用 `{data_dir}/workspaces/{name}`
+    pub async fn load(
+        workspace_config: &WorkspaceConfig,
+        config: &Config,
+        proxy: Option<ProxyConfig>,
+        data_dir: &Path,
+    ) -> Self {
+        let dir = workspace_config
+            .data_dir
+            .as_ref()
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|| data_dir.join("workspaces").join(&workspace_config.name));
+
+        tracing::info!("工作区 {} 数据目录: {:?}", workspace_config.name, dir);
+
+        let namespace = format!("workspaces:{}:", workspace_config.name);
+        let storage = storage::open(config, &dir, &namespace);
+        let pool = Arc::new(AccountPool::with_storage(
+            config.clone(),
+            proxy,
+            dir,
+            storage,
+        ));
+        pool.load_persisted().await;
+        pool.spawn_state_sync();
+        pool.spawn_log_flush();
+
+        Self {
+            name: workspace_config.name.clone(),
+            api_key: workspace_config.api_key.clone(),
+            pool,
+            system_prompt: workspace_config.system_prompt.clone(),
+            priority: workspace_config.priority,
+            timeouts: workspace_config.timeouts,
+        }
+    }
+}
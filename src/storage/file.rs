@@ -0,0 +1,64 @@
+//! 文件存储
+
+use std::path::PathBuf;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+
+use super::Storage;
+
+/// 每个键对应数据目录中的一个同名文件
+pub struct FileStorage {
+    dir: PathBuf,
+}
+
+impl FileStorage {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl Storage for FileStorage {
+    fn backend(&self) -> &'static str {
+        "file"
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        async move {
+            match tokio::fs::read(self.dir.join(key)).await {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+        .boxed()
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            // 先写临时文件再重命名，进程在写入中途退出时不会留下截断的文件
+            let path = self.dir.join(key);
+            let tmp = self
+                .dir
+                .join(format!(".{}.{}.tmp", key, uuid::Uuid::new_v4().simple()));
+            tokio::fs::write(&tmp, value).await?;
+            if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+                let _ = tokio::fs::remove_file(&tmp).await;
+                return Err(e.into());
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            match tokio::fs::remove_file(self.dir.join(key)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,60 @@
+//! 内存存储
+
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+
+use dashmap::DashMap;
+
+use super::Storage;
+
+/// 只保存在进程内的存储，重启后丢失
+///
+/// 按账号的共享状态在同一进程内使用该存储的账号池之间共享
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: DashMap<String, Vec<u8>>,
+    /// 共享状态及其过期时间
+    shared: DashMap<String, (Vec<u8>, Option<Instant>)>,
+}
+
+impl Storage for MemoryStorage {
+    fn backend(&self) -> &'static str {
+        "memory"
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        let value = self.entries.get(key).map(|v| v.clone());
+        async move { Ok(value) }.boxed()
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, anyhow::Result<()>> {
+        self.entries.insert(key.to_string(), value);
+        async { Ok(()) }.boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        self.entries.remove(key);
+        async { Ok(()) }.boxed()
+    }
+
+    fn get_shared<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        let now = Instant::now();
+        self.shared
+            .remove_if(key, |_, (_, expires)| expires.is_some_and(|at| at <= now));
+        let value = self.shared.get(key).map(|entry| entry.0.clone());
+        async move { Ok(value) }.boxed()
+    }
+
+    fn put_shared<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        let expires = ttl.map(|ttl| Instant::now() + ttl);
+        self.shared.insert(key.to_string(), (value, expires));
+        async { Ok(()) }.boxed()
+    }
+}
@@ -0,0 +1,242 @@
+//! 持久化存储后端
+//!
+//! 账号池的账号、请求记录、配额缓存、Machine ID、计费台账，以及托管 API Key 和提示词模板
+//! 都通过 [`Storage`] 读写，后端由配置项 `storageBackend` 选择：
+//! - `file`（默认）：每个键对应数据目录中的一个同名 JSON 文件，与旧版本的文件布局一致
+//! - `memory`：只保存在进程内，重启后丢失（用于测试或无状态部署）
+//! - `sqlite`：全部键保存在数据目录中的 `storage.db`
+//! - `redis`：保存在 Redis 中供多个实例共享，同时写入本地文件作为 Redis 不可达时的副本；
+//!   账号冷却和配额等实例间同步的状态按账号保存在独立的键中
+//!
+//! 只追加写入的预写日志和使用记录暂存文件不经过存储后端，始终写入数据目录。
+
+mod file;
+mod memory;
+mod rate_limit;
+#[cfg(feature = "redis")]
+mod redis;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::model::config::{Config, StorageBackend};
+
+pub use file::FileStorage;
+pub use memory::MemoryStorage;
+pub use rate_limit::{LocalRateLimiter, RateLimiter};
+#[cfg(feature = "redis")]
+pub use redis::{RedisConnection, RedisRateLimiter, RedisStorage};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+/// SQLite 后端的数据库文件名
+#[cfg(feature = "sqlite")]
+pub const SQLITE_FILE: &str = "storage.db";
+
+/// 键值存储
+///
+/// 键为文件名形式的字符串（如 `accounts.json`），值为序列化后的字节
+pub trait Storage: Send + Sync {
+    /// 后端名称（用于日志）
+    fn backend(&self) -> &'static str;
+
+    /// 是否与其他实例共享（共享时账号池和托管 Key 定期从存储同步其他实例写入的状态）
+    fn shared(&self) -> bool {
+        false
+    }
+
+    /// 读取键对应的值，键不存在时返回 None
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>>;
+
+    /// 写入键值，已存在时覆盖
+    fn put<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// 删除键，键不存在时忽略
+    #[allow(dead_code)]
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// 读取实例间共享的按账号状态（如冷却结束时间），不读取本地副本
+    fn get_shared<'a>(&'a self, _key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        async { Ok(None) }.boxed()
+    }
+
+    /// 写入实例间共享的按账号状态，`ttl` 后自动过期；不写本地副本，不共享的后端忽略
+    ///
+    /// 每个账号使用独立的键，各实例写入互不覆盖
+    fn put_shared<'a>(
+        &'a self,
+        _key: &'a str,
+        _value: Vec<u8>,
+        _ttl: Option<Duration>,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        async { Ok(()) }.boxed()
+    }
+}
+
+impl dyn Storage {
+    /// 读取并反序列化 JSON 值
+    pub async fn get_json<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
+        match self.get(key).await? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// 序列化为 JSON 后写入
+    pub async fn put_json<T: Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+    ) -> anyhow::Result<()> {
+        self.put(key, serde_json::to_vec_pretty(value)?).await
+    }
+}
+
+/// 按配置打开数据目录对应的存储后端
+///
+/// `namespace` 为 Redis 键中区分账号池的前缀（默认账号池为空）。
+/// SQLite 或 Redis 打开失败时记录错误并回退到文件存储，不阻止服务启动
+#[cfg_attr(not(feature = "redis"), allow(unused_variables))]
+pub fn open(config: &Config, data_dir: &Path, namespace: &str) -> Arc<dyn Storage> {
+    let file = || Arc::new(FileStorage::new(data_dir.to_path_buf()));
+    match config.storage_backend {
+        StorageBackend::File => file(),
+        StorageBackend::Memory => Arc::new(MemoryStorage::default()),
+        #[cfg(feature = "sqlite")]
+        StorageBackend::Sqlite => match SqliteStorage::open(&data_dir.join(SQLITE_FILE)) {
+            Ok(storage) => Arc::new(storage),
+            Err(e) => {
+                tracing::error!("打开 SQLite 存储失败，回退到文件存储: {}", e);
+                file()
+            }
+        },
+        #[cfg(feature = "redis")]
+        StorageBackend::Redis => {
+            let conn = match &config.redis {
+                Some(redis) => RedisConnection::shared(redis),
+                None => Err(anyhow::anyhow!("未配置 redis")),
+            };
+            match conn {
+                Ok(conn) => Arc::new(RedisStorage::new(conn, namespace, file())),
+                Err(e) => {
+                    tracing::error!("打开 Redis 存储失败，回退到文件存储: {}", e);
+                    file()
+                }
+            }
+        }
+        // 未编译的后端由配置校验提示，这里退回文件存储
+        #[allow(unreachable_patterns)]
+        _ => file(),
+    }
+}
+
+/// 托管 API Key 的限流计数后端：配置了 Redis 时所有实例共享计数
+#[cfg(feature = "redis")]
+pub fn rate_limiter(config: &Config) -> Arc<dyn RateLimiter> {
+    let Some(redis) = &config.redis else {
+        return Arc::new(LocalRateLimiter::default());
+    };
+    match RedisConnection::shared(redis) {
+        Ok(conn) => Arc::new(RedisRateLimiter::new(conn)),
+        Err(e) => {
+            tracing::error!("连接 Redis 失败，限流计数只在本实例生效: {}", e);
+            Arc::new(LocalRateLimiter::default())
+        }
+    }
+}
+
+/// 托管 API Key 的限流计数后端：未编译 Redis 支持时只在本实例计数
+#[cfg(not(feature = "redis"))]
+pub fn rate_limiter(_config: &Config) -> Arc<dyn RateLimiter> {
+    Arc::new(LocalRateLimiter::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn round_trip(storage: Arc<dyn Storage>) {
+        assert!(storage.get("a.json").await.unwrap().is_none());
+        storage.put_json("a.json", &vec![1, 2, 3]).await.unwrap();
+        storage.put("b.json", b"[]".to_vec()).await.unwrap();
+        assert_eq!(
+            storage.get_json::<Vec<u32>>("a.json").await.unwrap(),
+            Some(vec![1, 2, 3])
+        );
+
+        storage.put_json("a.json", &vec![4]).await.unwrap();
+        assert_eq!(
+            storage.get_json::<Vec<u32>>("a.json").await.unwrap(),
+            Some(vec![4])
+        );
+
+        storage.delete("a.json").await.unwrap();
+        storage.delete("missing.json").await.unwrap();
+        assert!(storage.get("a.json").await.unwrap().is_none());
+        assert_eq!(storage.get("b.json").await.unwrap(), Some(b"[]".to_vec()));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn test_backends_round_trip() {
+        let dir = std::env::temp_dir().join(format!("kiro-storage-{}", uuid::Uuid::new_v4()));
+
+        let config = |storage_backend| Config {
+            storage_backend,
+            ..Config::default()
+        };
+
+        for backend in [
+            StorageBackend::Memory,
+            StorageBackend::File,
+            StorageBackend::Sqlite,
+        ] {
+            let backend_dir = dir.join(format!("{:?}", backend));
+            round_trip(open(&config(backend), &backend_dir, "")).await;
+        }
+
+        // 文件和 SQLite 后端重新打开后数据仍在
+        for backend in [StorageBackend::File, StorageBackend::Sqlite] {
+            let reopened = open(&config(backend), &dir.join(format!("{:?}", backend)), "");
+            assert_eq!(reopened.get("b.json").await.unwrap(), Some(b"[]".to_vec()));
+        }
+        assert!(dir.join("File").join("b.json").exists());
+        assert!(dir.join("Sqlite").join(SQLITE_FILE).exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(feature = "redis")]
+    #[tokio::test]
+    async fn test_redis_unreachable_falls_back_to_local() {
+        let dir = std::env::temp_dir().join(format!("kiro-storage-redis-{}", uuid::Uuid::new_v4()));
+        // 未监听的端口：连接失败后读写回退到本地文件
+        let conn = RedisConnection::shared(&crate::model::config::RedisConfig {
+            url: "redis://127.0.0.1:1".to_string(),
+            key_prefix: "kiro-test:".to_string(),
+            timeout_ms: 200,
+            sync_secs: 10,
+        })
+        .unwrap();
+        let local: Arc<dyn Storage> = Arc::new(FileStorage::new(dir.clone()));
+        round_trip(Arc::new(RedisStorage::new(conn.clone(), "", local))).await;
+        assert!(dir.join("b.json").exists());
+        // 失败后熔断，短时间内不再尝试连接
+        assert!(conn.circuit_open());
+
+        let limiter = RedisRateLimiter::new(conn);
+        assert_eq!(limiter.hit("key", 1).await, 1);
+        assert_eq!(limiter.hit("key", 1).await, 2);
+        assert_eq!(limiter.hit("key", 2).await, 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
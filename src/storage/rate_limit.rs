@@ -0,0 +1,38 @@
+//! 固定窗口限流计数
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+
+/// 限流计数后端
+pub trait RateLimiter: Send + Sync {
+    /// 计入一次请求，返回 `key` 在窗口 `window`（如 Unix 分钟数）内的请求数（含本次）
+    fn hit<'a>(&'a self, key: &'a str, window: i64) -> BoxFuture<'a, u64>;
+}
+
+/// 进程内计数，每个键只保留当前窗口
+#[derive(Default)]
+pub struct LocalRateLimiter {
+    windows: Mutex<HashMap<String, (i64, u64)>>,
+}
+
+impl LocalRateLimiter {
+    pub fn hit_now(&self, key: &str, window: i64) -> u64 {
+        let mut windows = self.windows.lock().unwrap();
+        let entry = windows.entry(key.to_string()).or_insert((window, 0));
+        if entry.0 != window {
+            *entry = (window, 0);
+        }
+        entry.1 += 1;
+        entry.1
+    }
+}
+
+impl RateLimiter for LocalRateLimiter {
+    fn hit<'a>(&'a self, key: &'a str, window: i64) -> BoxFuture<'a, u64> {
+        let count = self.hit_now(key, window);
+        async move { count }.boxed()
+    }
+}
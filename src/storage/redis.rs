@@ -0,0 +1,254 @@
+//! Redis 共享状态
+//!
+//! 所有存储和限流计数共用一个多路复用连接（[`ConnectionManager`] 断线后自动重连）。
+//! 每次操作都有超时，Redis 不可达时读写回退到本地：存储回退到数据目录中的文件，
+//! 限流回退到进程内计数。写入总是同时写本地文件，Redis 恢复前重启也不会丢失数据。
+//! 操作失败后的 `CIRCUIT_OPEN` 内不再访问 Redis，直接使用本地状态，避免每次保存和限流计数
+//! 都等待超时。
+
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use tokio::sync::OnceCell;
+
+use crate::model::config::RedisConfig;
+
+use super::rate_limit::{LocalRateLimiter, RateLimiter};
+use super::Storage;
+
+/// 限流计数键的过期时间（秒），覆盖当前窗口并留出时钟偏差余量
+const RATE_LIMIT_TTL_SECS: i64 = 120;
+
+/// 操作失败后暂停访问 Redis 的时长
+const CIRCUIT_OPEN: Duration = Duration::from_secs(5);
+
+/// 进程内共享的 Redis 连接
+static SHARED: OnceLock<Arc<RedisConnection>> = OnceLock::new();
+
+/// Redis 连接
+pub struct RedisConnection {
+    client: redis::Client,
+    /// 首次使用时建立连接，连接失败时下次操作重试
+    manager: OnceCell<ConnectionManager>,
+    prefix: String,
+    timeout: Duration,
+    /// 最近一次操作是否失败（用于只在状态变化时记录日志）
+    degraded: AtomicBool,
+    /// 暂停访问 Redis 的截止时间
+    open_until: Mutex<Option<Instant>>,
+}
+
+impl RedisConnection {
+    /// 返回进程内共享的连接，首次调用时按配置创建
+    pub fn shared(config: &RedisConfig) -> anyhow::Result<Arc<Self>> {
+        if let Some(conn) = SHARED.get() {
+            return Ok(conn.clone());
+        }
+        let conn = Arc::new(Self {
+            client: redis::Client::open(config.url.as_str())?,
+            manager: OnceCell::new(),
+            prefix: config.key_prefix.clone(),
+            timeout: Duration::from_millis(config.timeout_ms.max(1)),
+            degraded: AtomicBool::new(false),
+            open_until: Mutex::new(None),
+        });
+        Ok(SHARED.get_or_init(|| conn).clone())
+    }
+
+    /// 加上键前缀
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// 是否处于失败后的暂停期
+    pub(super) fn circuit_open(&self) -> bool {
+        self.open_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    /// 执行一次 Redis 操作，连接失败、命令失败、超时或处于暂停期都返回错误
+    async fn run<T, F, Fut>(&self, op: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(ConnectionManager) -> Fut,
+        Fut: Future<Output = redis::RedisResult<T>>,
+    {
+        if self.circuit_open() {
+            anyhow::bail!("Redis 最近操作失败，暂停访问");
+        }
+        let result = tokio::time::timeout(self.timeout, async {
+            let conn = self
+                .manager
+                .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+                .await?
+                .clone();
+            op(conn).await
+        })
+        .await;
+        let result = match result {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) => Err(anyhow::Error::from(e)),
+            Err(_) => Err(anyhow::anyhow!("操作超时 ({:?})", self.timeout)),
+        };
+        match &result {
+            Ok(_) => {
+                if self.degraded.swap(false, Ordering::Relaxed) {
+                    tracing::info!("Redis 已恢复");
+                }
+            }
+            Err(e) => {
+                *self.open_until.lock().unwrap() = Some(Instant::now() + CIRCUIT_OPEN);
+                if !self.degraded.swap(true, Ordering::Relaxed) {
+                    tracing::warn!("Redis 不可用，{:?} 内回退到本地状态: {}", CIRCUIT_OPEN, e);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// 保存在 Redis 中的存储，`namespace` 区分默认账号池和各工作区
+pub struct RedisStorage {
+    conn: Arc<RedisConnection>,
+    namespace: String,
+    /// 本地副本，Redis 不可达或尚无数据时读取
+    local: Arc<dyn Storage>,
+}
+
+impl RedisStorage {
+    pub fn new(conn: Arc<RedisConnection>, namespace: &str, local: Arc<dyn Storage>) -> Self {
+        Self {
+            conn,
+            namespace: namespace.to_string(),
+            local,
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        self.conn.key(&format!("{}{}", self.namespace, key))
+    }
+}
+
+impl Storage for RedisStorage {
+    fn backend(&self) -> &'static str {
+        "redis"
+    }
+
+    fn shared(&self) -> bool {
+        true
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        async move {
+            let redis_key = self.key(key);
+            let result = self
+                .conn
+                .run(|mut conn| async move { conn.get::<_, Option<Vec<u8>>>(redis_key).await })
+                .await;
+            match result {
+                Ok(value) => Ok(value),
+                // 只在 Redis 不可达时读取本地副本；键不存在可能是其他实例删除的，不能用本地副本恢复
+                Err(_) => self.local.get(key).await,
+            }
+        }
+        .boxed()
+    }
+
+    fn put<'a>(&'a self, key: &'a str, value: Vec<u8>) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            self.local.put(key, value.clone()).await?;
+            let redis_key = self.key(key);
+            let _ = self
+                .conn
+                .run(|mut conn| async move { conn.set::<_, _, ()>(redis_key, value).await })
+                .await;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            self.local.delete(key).await?;
+            let redis_key = self.key(key);
+            let _ = self
+                .conn
+                .run(|mut conn| async move { conn.del::<_, ()>(redis_key).await })
+                .await;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn get_shared<'a>(&'a self, key: &'a str) -> BoxFuture<'a, anyhow::Result<Option<Vec<u8>>>> {
+        let redis_key = self.key(key);
+        self.conn
+            .run(|mut conn| async move { conn.get::<_, Option<Vec<u8>>>(redis_key).await })
+            .boxed()
+    }
+
+    fn put_shared<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        let redis_key = self.key(key);
+        self.conn
+            .run(move |mut conn| async move {
+                let mut cmd = redis::cmd("SET");
+                cmd.arg(redis_key).arg(value);
+                if let Some(ttl) = ttl {
+                    cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+                }
+                cmd.query_async::<()>(&mut conn).await
+            })
+            .boxed()
+    }
+}
+
+/// 在 Redis 中计数的限流器，所有实例共享同一窗口
+pub struct RedisRateLimiter {
+    conn: Arc<RedisConnection>,
+    /// Redis 不可达时的本地计数
+    local: LocalRateLimiter,
+}
+
+impl RedisRateLimiter {
+    pub fn new(conn: Arc<RedisConnection>) -> Self {
+        Self {
+            conn,
+            local: LocalRateLimiter::default(),
+        }
+    }
+}
+
+impl RateLimiter for RedisRateLimiter {
+    fn hit<'a>(&'a self, key: &'a str, window: i64) -> BoxFuture<'a, u64> {
+        async move {
+            let redis_key = self.conn.key(&format!("ratelimit:{}:{}", key, window));
+            let result = self
+                .conn
+                .run(|mut conn| async move {
+                    let (count,): (u64,) = redis::pipe()
+                        .atomic()
+                        .incr(&redis_key, 1)
+                        .expire(&redis_key, RATE_LIMIT_TTL_SECS)
+                        .ignore()
+                        .query_async(&mut conn)
+                        .await?;
+                    Ok(count)
+                })
+                .await;
+            result.unwrap_or_else(|_| self.local.hit_now(key, window))
+        }
+        .boxed()
+    }
+}
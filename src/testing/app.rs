@@ -0,0 +1,381 @@
+//! 指向模拟 Kiro 服务的完整路由
+//!
+//! 以单账号模式构建与生产相同的 axum 路由（认证、中间件、转换、流式输出），
+//! 运行在本地随机端口上，通过 HTTP 发送 Anthropic 请求。
+
+use futures::future::join_all;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+
+use crate::anthropic::create_router_with_provider;
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::provider::KiroProvider;
+use crate::kiro::token_manager::TokenManager;
+use crate::model::config::Config;
+
+use super::mock_kiro::MockKiro;
+
+/// 测试路由使用的 API Key
+pub const TEST_API_KEY: &str = "sk-test";
+
+/// 无需刷新的测试凭证（Token 一小时后过期）
+pub fn test_credentials() -> KiroCredentials {
+    KiroCredentials {
+        access_token: Some("test-access-token".to_string()),
+        refresh_token: Some("test-refresh-token".to_string()),
+        expires_at: Some((chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()),
+        ..Default::default()
+    }
+}
+
+/// 一个 SSE 事件
+#[derive(Debug, Clone)]
+pub struct SseMessage {
+    pub event: String,
+    pub data: Value,
+}
+
+/// 解析 SSE 响应体
+pub fn parse_sse(body: &str) -> Vec<SseMessage> {
+    body.split("\n\n")
+        .filter_map(|block| {
+            let mut event = None;
+            let mut data = None;
+            for line in block.lines() {
+                if let Some(value) = line.strip_prefix("event: ") {
+                    event = Some(value.to_string());
+                } else if let Some(value) = line.strip_prefix("data: ") {
+                    data = serde_json::from_str(value).ok();
+                }
+            }
+            Some(SseMessage {
+                event: event?,
+                data: data?,
+            })
+        })
+        .collect()
+}
+
+/// 拼接 SSE 事件中的文本增量
+pub fn sse_text(messages: &[SseMessage]) -> String {
+    messages
+        .iter()
+        .filter(|m| m.data["delta"]["type"] == "text_delta")
+        .filter_map(|m| m.data["delta"]["text"].as_str())
+        .collect()
+}
+
+/// 运行中的测试服务，drop 时停止
+pub struct TestApp {
+    url: String,
+    client: reqwest::Client,
+    handle: JoinHandle<()>,
+}
+
+impl TestApp {
+    /// 使用默认配置启动
+    pub async fn start(mock: &MockKiro) -> Self {
+        Self::with_config(mock, Config::default()).await
+    }
+
+    /// 使用指定配置启动，`kiroApiUrl` 指向模拟服务
+    pub async fn with_config(mock: &MockKiro, mut config: Config) -> Self {
+        config.kiro_api_url = Some(mock.url().to_string());
+        let token_manager = TokenManager::new(config.clone(), test_credentials(), None);
+        let router = create_router_with_provider(
+            TEST_API_KEY,
+            Some(KiroProvider::new(token_manager)),
+            None,
+            &config,
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("绑定测试服务端口失败");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        });
+        Self {
+            url,
+            client: reqwest::Client::new(),
+            handle,
+        }
+    }
+
+    /// 发送带认证的 GET 请求
+    pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .get(format!("{}{}", self.url, path))
+            .header("x-api-key", TEST_API_KEY)
+    }
+
+    /// 发送带认证的请求
+    pub fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .post(format!("{}{}", self.url, path))
+            .header("x-api-key", TEST_API_KEY)
+    }
+
+    /// POST /v1/messages
+    pub async fn messages(&self, body: &Value) -> reqwest::Response {
+        self.post("/v1/messages")
+            .json(body)
+            .send()
+            .await
+            .expect("发送请求失败")
+    }
+
+    /// 发送流式请求并读取全部 SSE 事件
+    pub async fn stream(&self, body: &Value) -> Vec<SseMessage> {
+        let mut body = body.clone();
+        body["stream"] = Value::Bool(true);
+        let response = self.messages(&body).await;
+        assert_eq!(response.status(), 200, "流式请求失败");
+        parse_sse(&response.text().await.expect("读取响应失败"))
+    }
+
+    /// 同时发送多个流式请求，按 `bodies` 的顺序返回各自的 SSE 事件
+    pub async fn stream_concurrently(&self, bodies: &[Value]) -> Vec<Vec<SseMessage>> {
+        join_all(bodies.iter().map(|body| self.stream(body))).await
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::Scenario;
+
+    fn request(text: &str) -> Value {
+        json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": text}]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_non_stream_text_and_tool_call() {
+        let mock = MockKiro::start([Scenario::new().text("Let me check.").tool_use(
+            "tool_1",
+            "get_weather",
+            &[r#"{"city":"#, r#""Paris"}"#],
+        )])
+        .await;
+        let app = TestApp::start(&mock).await;
+
+        let response = app.messages(&request("Weather in Paris?")).await;
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["content"][0]["text"], "Let me check.");
+        assert_eq!(body["content"][1]["type"], "tool_use");
+        assert_eq!(body["content"][1]["input"], json!({"city": "Paris"}));
+        assert_eq!(body["stop_reason"], "tool_use");
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0]["conversationState"]["currentMessage"]["userInputMessage"]["content"],
+            "Weather in Paris?"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upstream_throttling_is_forwarded() {
+        let mock =
+            MockKiro::start([Scenario::failure(429, "slow down").header("retry-after", "7")]).await;
+        let app = TestApp::start(&mock).await;
+
+        let response = app.messages(&request("hi")).await;
+        assert_eq!(response.status(), 429);
+        assert_eq!(response.headers()["retry-after"], "7");
+    }
+
+    #[tokio::test]
+    async fn test_non_stream_exception_event_is_mapped() {
+        let mock = MockKiro::start([Scenario::new()
+            .text("partial")
+            .exception("ThrottlingException", "Too many requests")
+            .text("never read")])
+        .await;
+        let app = TestApp::start(&mock).await;
+
+        let response = app.messages(&request("hi")).await;
+        assert_eq!(response.status(), 429);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_does_not_call_upstream() {
+        let mock = MockKiro::start([Scenario::reply("unused")]).await;
+        let app = TestApp::start(&mock).await;
+
+        let response = app
+            .post("/v1/estimate")
+            .header("x-priority", "batch")
+            .json(&request("How long is a piece of string?"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["input_tokens"].as_i64().unwrap() > 0);
+        assert_eq!(body["quota"], Value::Null);
+        assert_eq!(body["pool"]["name"], "single");
+        assert_eq!(body["priority"], "batch");
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_model_detail() {
+        let mock = MockKiro::start([]).await;
+        let app = TestApp::start(&mock).await;
+        let get = |path: &str| app.get(path).send();
+
+        let body: Value = get("/v1/models/claude-sonnet-4-5-20250929")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["type"], "model");
+        assert_eq!(body["display_name"], "Claude Sonnet 4.5");
+        assert_eq!(body["created_at"], "2024-09-29T00:00:00Z");
+
+        // 别名按模型映射返回目录中的条目
+        let body: Value = get("/v1/models/claude-opus-4-5")
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["id"], "claude-opus-4-5-20251101");
+
+        let response = get("/v1/models/gpt-4o").await.unwrap();
+        assert_eq!(response.status(), 404);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["type"], "not_found_error");
+    }
+
+    #[tokio::test]
+    async fn test_path_prefix() {
+        let mock = MockKiro::start([Scenario::reply("ok")]).await;
+        let config = Config {
+            path_prefix: "/kiro/".to_string(),
+            ..Config::default()
+        };
+        let app = TestApp::with_config(&mock, config).await;
+
+        let response = app.get("/kiro/v1/models").send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        let response = app.get("/v1/models").send().await.unwrap();
+        assert_eq!(response.status(), 404);
+
+        let response = app
+            .post("/kiro/v1/messages")
+            .json(&request("hi"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_conversation_id_reuse() {
+        let mock = MockKiro::start([Scenario::reply("ok")]).await;
+        let app = TestApp::start(&mock).await;
+        let send = |conversation: Option<&str>, text: &str| {
+            let mut builder = app.post("/v1/messages").json(&request(text));
+            if let Some(id) = conversation {
+                builder = builder.header("x-kiro-conversation-id", id);
+            }
+            builder.send()
+        };
+
+        send(Some("chat-1"), "first").await.unwrap();
+        send(Some("chat-1"), "second").await.unwrap();
+        send(None, "third").await.unwrap();
+
+        let ids: Vec<(Value, Value)> = mock
+            .requests()
+            .iter()
+            .map(|r| {
+                let state = &r["conversationState"];
+                (
+                    state["conversationId"].clone(),
+                    state["agentContinuationId"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0].0, ids[2].0);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_streams_fan_in() {
+        let scenario = Scenario::new()
+            .text("one ")
+            .text("two ")
+            .text("three")
+            .paced(Duration::from_millis(50));
+        let mock = MockKiro::start([scenario]).await;
+        let app = TestApp::start(&mock).await;
+
+        // 请求内容不同，避免被合并为同一个上游请求
+        let bodies: Vec<Value> = (0..8).map(|i| request(&format!("stream {}", i))).collect();
+        let results = app.stream_concurrently(&bodies).await;
+
+        for messages in &results {
+            assert_eq!(sse_text(messages), "one two three");
+            assert_eq!(messages.last().unwrap().event, "message_stop");
+        }
+        assert_eq!(mock.requests().len(), 8);
+        assert!(mock.peak_concurrency() > 1, "上游请求应并发进行");
+    }
+
+    /// 大响应非流式聚合基准：上游返回约 16 MB 的事件流，输出每次请求的耗时
+    ///
+    /// 运行：`cargo test --release non_stream_large_response -- --ignored --nocapture`
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore]
+    async fn bench_non_stream_large_response() {
+        const FRAMES: usize = 16 * 1024;
+        const ROUNDS: usize = 5;
+
+        let chunk = "x".repeat(1024);
+        let scenario = (0..FRAMES).fold(Scenario::new(), |s, _| s.text(&chunk));
+        let mock = MockKiro::start([scenario]).await;
+        let app = TestApp::start(&mock).await;
+
+        let mut latencies = Vec::with_capacity(ROUNDS);
+        for round in 0..ROUNDS {
+            let start = std::time::Instant::now();
+            let response = app.messages(&request(&format!("large {}", round))).await;
+            assert_eq!(response.status(), 200);
+            let body: Value = response.json().await.unwrap();
+            latencies.push(start.elapsed());
+            assert_eq!(
+                body["content"][0]["text"].as_str().unwrap().len(),
+                FRAMES * chunk.len()
+            );
+        }
+        latencies.sort();
+        println!(
+            "非流式聚合（{} 帧 × {} 字节）：最快 {:?}，中位数 {:?}，最慢 {:?}",
+            FRAMES,
+            chunk.len(),
+            latencies[0],
+            latencies[ROUNDS / 2],
+            latencies[ROUNDS - 1]
+        );
+    }
+}
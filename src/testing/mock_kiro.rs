@@ -0,0 +1,343 @@
+//! 模拟 Kiro 服务
+//!
+//! 在本地端口上提供 `POST /generateAssistantResponse`，按脚本返回 AWS Event Stream 响应
+//! （帧带正确的 CRC）或 HTTP 错误。脚本按请求顺序依次使用，用完后重复最后一个。
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aws_eventstream_lite::crc::crc32;
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use futures::stream;
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+
+/// 编码一个 AWS Event Stream 帧
+///
+/// `headers` 均为字符串类型的头部（如 `:message-type`、`:event-type`）
+pub fn encode_frame(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+    let mut encoded_headers = Vec::new();
+    for (name, value) in headers {
+        encoded_headers.push(name.len() as u8);
+        encoded_headers.extend_from_slice(name.as_bytes());
+        encoded_headers.push(7);
+        encoded_headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        encoded_headers.extend_from_slice(value.as_bytes());
+    }
+
+    let total_len = 12 + encoded_headers.len() + payload.len() + 4;
+    let mut frame = Vec::with_capacity(total_len);
+    frame.extend_from_slice(&(total_len as u32).to_be_bytes());
+    frame.extend_from_slice(&(encoded_headers.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&crc32(&frame[..8]).to_be_bytes());
+    frame.extend_from_slice(&encoded_headers);
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&crc32(&frame).to_be_bytes());
+    frame
+}
+
+/// 响应流中的一步
+#[derive(Debug, Clone)]
+enum Step {
+    Frame(Vec<u8>),
+    Delay(Duration),
+}
+
+/// 单次请求的响应脚本
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    status: StatusCode,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+    steps: Vec<Step>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            status: StatusCode::OK,
+            headers: Vec::new(),
+            body: None,
+            steps: Vec::new(),
+        }
+    }
+}
+
+impl Scenario {
+    /// 空的事件流响应，通过链式调用追加事件
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 只返回一段文本的事件流
+    pub fn reply(text: &str) -> Self {
+        Self::new().text(text)
+    }
+
+    /// 返回 HTTP 错误状态（如 429、403）
+    pub fn failure(status: u16, body: &str) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).expect("无效的状态码"),
+            body: Some(body.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// 添加响应头
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// 追加一个事件帧
+    pub fn event(mut self, event_type: &str, payload: Value) -> Self {
+        let frame = encode_frame(
+            &[(":message-type", "event"), (":event-type", event_type)],
+            payload.to_string().as_bytes(),
+        );
+        self.steps.push(Step::Frame(frame));
+        self
+    }
+
+    /// 追加一段文本（assistantResponseEvent）
+    pub fn text(self, content: &str) -> Self {
+        self.event("assistantResponseEvent", json!({ "content": content }))
+    }
+
+    /// 追加一次完整的工具调用：参数按 `chunks` 分多帧发送，最后一帧带 stop
+    pub fn tool_use(mut self, tool_use_id: &str, name: &str, chunks: &[&str]) -> Self {
+        for chunk in chunks {
+            self = self.event(
+                "toolUseEvent",
+                json!({ "toolUseId": tool_use_id, "name": name, "input": chunk }),
+            );
+        }
+        self.event(
+            "toolUseEvent",
+            json!({ "toolUseId": tool_use_id, "name": name, "stop": true }),
+        )
+    }
+
+    /// 追加上下文使用率事件
+    pub fn context_usage(self, percentage: f64) -> Self {
+        self.event(
+            "contextUsageEvent",
+            json!({ "contextUsagePercentage": percentage }),
+        )
+    }
+
+    /// 追加异常帧（如 ThrottlingException）
+    pub fn exception(mut self, exception_type: &str, message: &str) -> Self {
+        let frame = encode_frame(
+            &[
+                (":message-type", "exception"),
+                (":exception-type", exception_type),
+            ],
+            message.as_bytes(),
+        );
+        self.steps.push(Step::Frame(frame));
+        self
+    }
+
+    /// 追加一个 CRC 错误的帧
+    pub fn corrupt_frame(mut self) -> Self {
+        let mut frame = encode_frame(
+            &[
+                (":message-type", "event"),
+                (":event-type", "assistantResponseEvent"),
+            ],
+            br#"{"content":"corrupted"}"#,
+        );
+        let last = frame.len() - 1;
+        frame[last] ^= 0xff;
+        self.steps.push(Step::Frame(frame));
+        self
+    }
+
+    /// 发送下一帧前等待
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.steps.push(Step::Delay(delay));
+        self
+    }
+
+    /// 每两帧之间等待相同的时间
+    pub fn paced(mut self, interval: Duration) -> Self {
+        let mut steps = Vec::with_capacity(self.steps.len() * 2);
+        for step in self.steps {
+            if matches!(step, Step::Frame(_)) && !steps.is_empty() {
+                steps.push(Step::Delay(interval));
+            }
+            steps.push(step);
+        }
+        self.steps = steps;
+        self
+    }
+}
+
+/// 响应期间的并发计数，流结束（或客户端断开）时释放
+struct ActiveGuard(Arc<MockState>);
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    scenarios: Mutex<VecDeque<Scenario>>,
+    requests: Mutex<Vec<Value>>,
+    active: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl MockState {
+    fn next_scenario(&self) -> Scenario {
+        let mut scenarios = self.scenarios.lock().unwrap();
+        if scenarios.len() > 1 {
+            scenarios.pop_front().unwrap()
+        } else {
+            scenarios.front().cloned().unwrap_or_default()
+        }
+    }
+}
+
+/// 本地运行的模拟 Kiro 服务，drop 时停止
+pub struct MockKiro {
+    url: String,
+    state: Arc<MockState>,
+    handle: JoinHandle<()>,
+}
+
+impl MockKiro {
+    /// 在随机端口上启动，`scenarios` 按请求顺序使用，用完后重复最后一个
+    pub async fn start(scenarios: impl IntoIterator<Item = Scenario>) -> Self {
+        let state = Arc::new(MockState {
+            scenarios: Mutex::new(scenarios.into_iter().collect()),
+            ..MockState::default()
+        });
+        let app = Router::new()
+            .route("/generateAssistantResponse", post(generate))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("绑定模拟 Kiro 服务端口失败");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        Self { url, state, handle }
+    }
+
+    /// 服务地址，用作配置项 `kiroApiUrl`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// 已收到的请求体（Kiro 请求 JSON），按到达顺序
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// 同时进行中的响应数的峰值
+    pub fn peak_concurrency(&self) -> usize {
+        self.state.peak.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MockKiro {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn generate(State(state): State<Arc<MockState>>, body: Bytes) -> Response {
+    let request = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    state.requests.lock().unwrap().push(request);
+    let scenario = state.next_scenario();
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &scenario.headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            HeaderValue::from_str(value).unwrap(),
+        );
+    }
+    if let Some(body) = scenario.body {
+        return (scenario.status, headers, body).into_response();
+    }
+
+    let active = state.active.fetch_add(1, Ordering::SeqCst) + 1;
+    state.peak.fetch_max(active, Ordering::SeqCst);
+    let guard = ActiveGuard(state.clone());
+    let steps = stream::unfold(
+        (scenario.steps.into_iter(), guard),
+        |(mut steps, guard)| async move {
+            loop {
+                match steps.next()? {
+                    Step::Delay(delay) => tokio::time::sleep(delay).await,
+                    Step::Frame(frame) => {
+                        return Some((Ok::<_, std::io::Error>(Bytes::from(frame)), (steps, guard)))
+                    }
+                }
+            }
+        },
+    );
+    headers.insert(
+        "content-type",
+        HeaderValue::from_static("application/vnd.amazon.eventstream"),
+    );
+    (scenario.status, headers, Body::from_stream(steps)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::events::Event;
+    use crate::kiro::parser::frame::parse_frame;
+
+    #[tokio::test]
+    async fn test_scripted_frames_decode() {
+        let mock = MockKiro::start([
+            Scenario::reply("Hello")
+                .context_usage(12.5)
+                .exception("ThrottlingException", "slow down")
+                .corrupt_frame(),
+            Scenario::failure(403, "suspended"),
+        ])
+        .await;
+        let client = reqwest::Client::new();
+        let url = format!("{}/generateAssistantResponse", mock.url());
+
+        let body = client.post(&url).body("{}").send().await.unwrap();
+        let bytes = body.bytes().await.unwrap();
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while let Ok(Some((frame, consumed))) = parse_frame(&bytes[offset..]) {
+            events.push(Event::from_frame(frame).unwrap());
+            offset += consumed;
+        }
+        assert!(matches!(&events[0], Event::AssistantResponse(e) if e.content == "Hello"));
+        assert!(matches!(&events[1], Event::ContextUsage(_)));
+        assert!(
+            matches!(&events[2], Event::Exception { exception_type, .. } if exception_type == "ThrottlingException")
+        );
+        // 最后一帧 CRC 错误
+        assert_eq!(events.len(), 3);
+        assert!(parse_frame(&bytes[offset..]).is_err());
+
+        let response = client.post(&url).body("{}").send().await.unwrap();
+        assert_eq!(response.status(), 403);
+        // 脚本用完后重复最后一个
+        let response = client.post(&url).body("{}").send().await.unwrap();
+        assert_eq!(response.status(), 403);
+        assert_eq!(mock.requests().len(), 3);
+    }
+}
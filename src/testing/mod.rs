@@ -0,0 +1,21 @@
+//! 端到端测试工具
+//!
+//! - [`MockKiro`]：本地模拟 Kiro 服务，按脚本（[`Scenario`]）返回 AWS Event Stream 帧，
+//!   可模拟慢速输出、工具调用、异常帧、CRC 错误和 HTTP 错误状态
+//! - [`TestApp`]：指向模拟服务的完整 axum 路由，经 HTTP 发送 Anthropic 请求
+//!
+//! ```rust,ignore
+//! let mock = MockKiro::start([Scenario::reply("Hello")]).await;
+//! let app = TestApp::start(&mock).await;
+//! let events = app.stream(&json!({ ... })).await;
+//! assert_eq!(sse_text(&events), "Hello");
+//! ```
+
+// 供各模块的测试按需使用，未被引用的脚本步骤和辅助函数不视为死代码
+#![allow(dead_code, unused_imports)]
+
+mod app;
+mod mock_kiro;
+
+pub use app::{parse_sse, sse_text, test_credentials, SseMessage, TestApp, TEST_API_KEY};
+pub use mock_kiro::{encode_frame, MockKiro, Scenario};
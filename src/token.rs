@@ -11,7 +11,10 @@ use crate::anthropic::types::{
     CountTokensRequest, CountTokensResponse, Message, SystemMessage, Tool,
 };
 use crate::http_client::{build_client, ProxyConfig};
-use std::sync::OnceLock;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
 
 /// Count Tokens API 配置
 #[derive(Clone, Default)]
@@ -22,6 +25,8 @@ pub struct CountTokensConfig {
     pub api_key: Option<String>,
     /// count_tokens API 认证类型（"x-api-key" 或 "bearer"）
     pub auth_type: String,
+    /// 结果缓存条数（0 表示禁用）
+    pub cache_size: usize,
     /// 代理配置
     pub proxy: Option<ProxyConfig>,
 }
@@ -33,6 +38,9 @@ static COUNT_TOKENS_CONFIG: OnceLock<CountTokensConfig> = OnceLock::new();
 ///
 /// 应在应用启动时调用一次
 pub fn init_config(config: CountTokensConfig) {
+    if config.cache_size > 0 {
+        let _ = TOKEN_CACHE.set(Mutex::new(TokenCountCache::new(config.cache_size)));
+    }
     let _ = COUNT_TOKENS_CONFIG.set(config);
 }
 
@@ -41,6 +49,96 @@ fn get_config() -> Option<&'static CountTokensConfig> {
     COUNT_TOKENS_CONFIG.get()
 }
 
+/// 结果缓存（按请求内容哈希，未启用时为空）
+static TOKEN_CACHE: OnceLock<Mutex<TokenCountCache>> = OnceLock::new();
+
+/// 缓存命中统计
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TokenCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+/// count_tokens 结果 LRU 缓存
+struct TokenCountCache {
+    capacity: usize,
+    /// 内容哈希 -> (token 数, 最近访问序号)
+    entries: HashMap<[u8; 32], (u64, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl TokenCountCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn get(&mut self, key: &[u8; 32]) -> Option<u64> {
+        self.tick += 1;
+        match self.entries.get_mut(key) {
+            Some((tokens, used)) => {
+                *used = self.tick;
+                self.hits += 1;
+                Some(*tokens)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, key: [u8; 32], tokens: u64) {
+        self.tick += 1;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            // 淘汰最久未访问的条目
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| *k);
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (tokens, self.tick));
+    }
+
+    fn stats(&self) -> TokenCacheStats {
+        TokenCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+/// 获取 count_tokens 缓存统计（未启用缓存时为 None）
+pub fn cache_stats() -> Option<TokenCacheStats> {
+    TOKEN_CACHE.get().map(|cache| cache.lock().unwrap().stats())
+}
+
+/// 计算请求内容哈希作为缓存键
+fn cache_key(
+    model: &str,
+    system: &Option<Vec<SystemMessage>>,
+    messages: &[Message],
+    tools: &Option<Vec<Tool>>,
+) -> Option<[u8; 32]> {
+    let body = serde_json::to_vec(&(model, system, messages, tools)).ok()?;
+    Some(Sha256::digest(&body).into())
+}
+
 /// 判断字符是否为非西文字符
 ///
 /// 西文字符包括：
@@ -102,25 +200,47 @@ pub fn count_tokens(text: &str) -> u64 {
 /// 估算请求的输入 tokens
 ///
 /// 优先调用远程 API，失败时回退到本地计算
-pub(crate) fn count_all_tokens(
+pub(crate) async fn count_all_tokens(
+    model: String,
+    system: Option<Vec<SystemMessage>>,
+    messages: Vec<Message>,
+    tools: Option<Vec<Tool>>,
+) -> u64 {
+    count_all_tokens_with(get_config(), model, system, messages, tools).await
+}
+
+async fn count_all_tokens_with(
+    config: Option<&CountTokensConfig>,
     model: String,
     system: Option<Vec<SystemMessage>>,
     messages: Vec<Message>,
     tools: Option<Vec<Tool>>,
 ) -> u64 {
     // 检查是否配置了远程 API
-    if let Some(config) = get_config() {
+    if let Some(config) = config {
         if let Some(api_url) = &config.api_url {
+            // 内容相同的请求直接返回缓存结果，不再调用远程 API
+            let key = TOKEN_CACHE
+                .get()
+                .and_then(|_| cache_key(&model, &system, &messages, &tools));
+            if let (Some(cache), Some(key)) = (TOKEN_CACHE.get(), &key) {
+                if let Some(tokens) = cache.lock().unwrap().get(key) {
+                    tracing::debug!("count_tokens 缓存命中: {}", tokens);
+                    return tokens;
+                }
+            }
+
             // 尝试调用远程 API
-            let result = tokio::task::block_in_place(|| {
-                tokio::runtime::Handle::current().block_on(call_remote_count_tokens(
-                    api_url, config, model, &system, &messages, &tools,
-                ))
-            });
+            let result =
+                call_remote_count_tokens(api_url, config, model, &system, &messages, &tools).await;
 
             match result {
                 Ok(tokens) => {
                     tracing::debug!("远程 count_tokens API 返回: {}", tokens);
+                    // 远程调用失败时的本地估算值不缓存，下次仍会尝试远程 API
+                    if let (Some(cache), Some(key)) = (TOKEN_CACHE.get(), key) {
+                        cache.lock().unwrap().insert(key, tokens);
+                    }
                     return tokens;
                 }
                 Err(e) => {
@@ -135,6 +255,7 @@ pub(crate) fn count_all_tokens(
 }
 
 /// 调用远程 count_tokens API
+#[allow(clippy::ptr_arg, clippy::redundant_field_names)]
 async fn call_remote_count_tokens(
     api_url: &str,
     config: &CountTokensConfig,
@@ -240,3 +361,62 @@ pub(crate) fn estimate_output_tokens(content: &[serde_json::Value]) -> i32 {
 
     total.max(1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut cache = TokenCountCache::new(2);
+        cache.insert([1; 32], 10);
+        cache.insert([2; 32], 20);
+        assert_eq!(cache.get(&[1; 32]), Some(10));
+
+        // [2] 最久未访问，被淘汰
+        cache.insert([3; 32], 30);
+        assert_eq!(cache.get(&[2; 32]), None);
+        assert_eq!(cache.get(&[3; 32]), Some(30));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 2));
+    }
+
+    #[test]
+    fn test_cache_key_depends_on_content() {
+        let messages = |text: &str| {
+            vec![Message {
+                role: "user".to_string(),
+                content: serde_json::json!(text),
+            }]
+        };
+        let key = |text: &str| cache_key("claude-sonnet-4", &None, &messages(text), &None);
+
+        assert_eq!(key("hello"), key("hello"));
+        assert_ne!(key("hello"), key("hello!"));
+    }
+
+    /// 远程调用在 current_thread 运行时中直接 await，失败时回退到本地计算
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_remote_failure_falls_back_on_current_thread_runtime() {
+        let config = CountTokensConfig {
+            // 端口 9 (discard) 通常无服务监听，请求会立即失败
+            api_url: Some("http://127.0.0.1:9/v1/messages/count_tokens".to_string()),
+            ..CountTokensConfig::default()
+        };
+        let messages = vec![Message {
+            role: "user".to_string(),
+            content: serde_json::json!("hello world"),
+        }];
+
+        let tokens = count_all_tokens_with(
+            Some(&config),
+            "claude-sonnet-4".to_string(),
+            None,
+            messages,
+            None,
+        )
+        .await;
+        assert_eq!(tokens, count_tokens("hello world").max(1));
+    }
+}
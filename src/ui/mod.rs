@@ -1,19 +1,31 @@
 //! 管理 UI 模块
 
 use axum::{
-    extract::State,
-    http::{Request, StatusCode},
+    extract::{DefaultBodyLimit, Query, State},
+    http::{HeaderMap, Request, StatusCode},
     middleware::{self, Next},
-    response::{Html, IntoResponse, Json, Response},
-    routing::{delete, get, post},
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post, put},
     Router,
 };
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Instant;
 
+use crate::anthropic::{
+    constant_time_eq, prefix, ApiKeyStore, ConversationCache, NewApiKey, PreviousApiKey,
+    TemplateStore,
+};
 use crate::kiro::model::credentials::KiroCredentials;
-use crate::pool::{Account, AccountPool, SelectionStrategy};
+use crate::model::config::PromptTemplate;
+use crate::pool::probe::DEFAULT_TEST_MODEL;
+use crate::pool::snapshot::PoolSnapshot;
+use crate::pool::tombstone::RemovalReason;
+use crate::pool::{Account, AccountPool, SelectionStrategy, Workspace};
+
+/// 快照恢复请求体大小上限（16MB）
+const MAX_SNAPSHOT_SIZE: usize = 16 * 1024 * 1024;
 
 /// UI 共享状态
 #[derive(Clone)]
@@ -22,6 +34,18 @@ pub struct UiState {
     pub start_time: Instant,
     pub version: String,
     pub api_key: String,
+    /// 轮换期间仍然有效的旧 API 密钥
+    pub previous_api_key: Option<PreviousApiKey>,
+    /// 多租户工作区
+    pub workspaces: Vec<Workspace>,
+    /// 提示词模板
+    pub templates: Arc<TemplateStore>,
+    /// 托管 API Key
+    pub api_keys: Arc<ApiKeyStore>,
+    /// 会话缓存
+    pub conversations: Arc<ConversationCache>,
+    /// 路由路径前缀（`pathPrefix`）
+    pub path_prefix: String,
 }
 
 /// 认证中间件
@@ -43,29 +67,87 @@ async fn auth_middleware(
             .map(|p| p.trim_start_matches("key=").to_string())
     });
 
-    let provided_key = auth_header.or(query_key);
-
-    match provided_key {
-        Some(key) if key == state.api_key => next.run(request).await,
-        _ => (
-            StatusCode::UNAUTHORIZED,
-            Json(serde_json::json!({"error": "需要认证，请提供 API 密钥"})),
-        )
-            .into_response(),
+    if let Some(key) = auth_header.or(query_key) {
+        if constant_time_eq(&key, &state.api_key) {
+            return next.run(request).await;
+        }
+        if let Some(previous) = &state.previous_api_key {
+            if previous.accepts(&key, Utc::now()) {
+                tracing::warn!(
+                    "请求使用了即将失效的旧 API Key: {} {}（{} 失效）",
+                    request.method(),
+                    request.uri().path(),
+                    previous.expires_at.to_rfc3339()
+                );
+                return next.run(request).await;
+            }
+        }
     }
+
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({"error": "需要认证，请提供 API 密钥"})),
+    )
+        .into_response()
 }
 
 /// 创建 UI 路由
+///
+/// 工作区的管理 API 挂载在 `/workspaces/{name}/api/...` 下，使用全局 API Key 认证；
+/// 配置 `pathPrefix` 时全部路由挂载在该前缀下
 pub fn create_ui_router(state: UiState) -> Router {
-    // 需要认证的 API 路由
-    let protected_api = Router::new()
+    let mut router = Router::new()
+        .route("/", get(index_page).with_state(state.clone()))
+        .merge(protected_api(state.clone()));
+
+    for workspace in &state.workspaces {
+        let workspace_state = UiState {
+            pool: workspace.pool.clone(),
+            workspaces: Vec::new(),
+            ..state.clone()
+        };
+        router = router.nest(
+            &format!("/workspaces/{}", workspace.name),
+            protected_api(workspace_state),
+        );
+    }
+
+    let path_prefix = prefix::normalize(&state.path_prefix);
+    let router = prefix::mount(router, &path_prefix);
+    if path_prefix.is_empty() {
+        return router;
+    }
+    // 嵌套路由的根路径只匹配不带斜杠的前缀
+    router.route(
+        &format!("{}/", path_prefix),
+        get(index_page).with_state(state),
+    )
+}
+
+/// Playground 调试页面路由
+///
+/// 页面本身不需要认证，发送请求时使用用户填写的 API Key 调用本地 `/v1` 接口
+pub fn create_playground_router(path_prefix: &str) -> Router {
+    let router = Router::new()
+        .route("/playground", get(playground_page))
+        .with_state(path_prefix.to_string());
+    prefix::mount(router, path_prefix)
+}
+
+/// 需要认证的管理 API 路由
+fn protected_api(state: UiState) -> Router {
+    Router::new()
         .route("/api/status", get(get_status))
         .route("/api/accounts", get(list_accounts))
         .route("/api/accounts", post(add_account))
         .route("/api/accounts/import", post(import_account))
+        .route("/api/accounts/removed", get(list_removed_accounts))
         .route("/api/accounts/{id}", delete(remove_account))
         .route("/api/accounts/{id}/enable", post(enable_account))
         .route("/api/accounts/{id}/disable", post(disable_account))
+        .route("/api/accounts/{id}/drain", post(drain_account))
+        .route("/api/accounts/{id}/test", post(test_account))
+        .route("/api/accounts/{id}/refresh", post(refresh_account_token))
         .route("/api/accounts/{id}/usage", get(get_account_usage))
         .route(
             "/api/accounts/{id}/usage/refresh",
@@ -73,25 +155,54 @@ pub fn create_ui_router(state: UiState) -> Router {
         )
         .route("/api/strategy", get(get_strategy))
         .route("/api/strategy", post(set_strategy))
+        .route("/api/strategy", put(set_strategy))
         .route("/api/logs", get(get_request_logs))
         .route("/api/logs/stats", get(get_request_stats))
+        .route("/api/clients", get(get_client_stats))
+        .route("/api/journal", get(get_journal))
+        .route("/api/machine-ids", get(get_machine_ids))
+        .route("/api/ledger", get(get_quota_ledger))
+        .route("/api/snapshot", get(get_snapshot))
+        .route(
+            "/api/snapshot",
+            post(restore_snapshot).layer(DefaultBodyLimit::max(MAX_SNAPSHOT_SIZE)),
+        )
         .route("/api/usage/refresh", post(refresh_all_usage))
         .route("/api/usage", get(get_all_usage))
+        .route("/api/workspaces", get(list_workspaces))
+        .route("/api/keys", get(list_api_keys))
+        .route("/api/keys", post(create_api_key))
+        .route("/api/keys/{id}", delete(revoke_api_key))
+        .route("/api/keys/{id}/recent", get(get_recent_requests))
+        // 托管 Key 管理的 `/admin/keys` 别名
+        .route("/admin/keys", get(list_api_keys))
+        .route("/admin/keys", post(create_api_key))
+        .route("/admin/keys/{id}", delete(revoke_api_key))
+        .route("/admin/keys/{id}/recent", get(get_recent_requests))
+        .route("/api/conversations", get(list_conversations))
+        .route("/api/conversations", delete(purge_conversations))
+        .route("/api/conversations/{key}", delete(remove_conversation))
+        .route("/api/templates", get(list_templates))
+        .route("/api/templates/{name}", put(save_template))
+        .route("/api/templates/{name}", delete(remove_template))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ))
-        .with_state(state.clone());
-
-    // 公开路由（登录页面）
-    Router::new()
-        .route("/", get(index_page))
-        .merge(protected_api)
+        .with_state(state)
 }
 
 /// 首页
-async fn index_page() -> impl IntoResponse {
-    Html(include_str!("index.html"))
+async fn index_page(State(state): State<UiState>, headers: HeaderMap) -> impl IntoResponse {
+    prefix::render_page(include_str!("index.html"), &headers, &state.path_prefix)
+}
+
+/// Playground 页面
+async fn playground_page(
+    State(path_prefix): State<String>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    prefix::render_page(include_str!("playground.html"), &headers, &path_prefix)
 }
 
 /// 状态响应
@@ -101,6 +212,8 @@ struct StatusResponse {
     version: String,
     uptime_secs: u64,
     pool: crate::pool::PoolStats,
+    /// count_tokens 缓存命中统计（未启用缓存时为 null）
+    token_cache: Option<crate::token::TokenCacheStats>,
 }
 
 /// 获取状态
@@ -111,9 +224,168 @@ async fn get_status(State(state): State<UiState>) -> impl IntoResponse {
         version: state.version.clone(),
         uptime_secs: state.start_time.elapsed().as_secs(),
         pool: stats,
+        token_cache: crate::token::cache_stats(),
     })
 }
 
+/// 工作区响应
+#[derive(Serialize)]
+struct WorkspaceResponse {
+    name: String,
+    pool: crate::pool::PoolStats,
+    requests: crate::pool::usage::RequestStats,
+}
+
+/// 获取工作区列表
+async fn list_workspaces(State(state): State<UiState>) -> impl IntoResponse {
+    let mut response = Vec::with_capacity(state.workspaces.len());
+    for workspace in &state.workspaces {
+        response.push(WorkspaceResponse {
+            name: workspace.name.clone(),
+            pool: workspace.pool.get_stats().await,
+            requests: workspace.pool.get_request_stats().await,
+        });
+    }
+    Json(response)
+}
+
+/// 列出托管 API Key（不含 Key 本身）
+async fn list_api_keys(State(state): State<UiState>) -> impl IntoResponse {
+    Json(serde_json::json!({"keys": state.api_keys.list()}))
+}
+
+/// 签发托管 API Key，明文 Key 只在此响应中返回一次
+async fn create_api_key(
+    State(state): State<UiState>,
+    Json(new): Json<NewApiKey>,
+) -> impl IntoResponse {
+    if new.owner.trim().is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"success": false, "error": "owner 不能为空"})),
+        );
+    }
+    match state.api_keys.create(new).await {
+        Ok((info, key)) => (
+            StatusCode::CREATED,
+            Json(serde_json::json!({"success": true, "key": key, "info": info})),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"success": false, "error": e.to_string()})),
+        ),
+    }
+}
+
+/// 吊销托管 API Key
+async fn revoke_api_key(
+    State(state): State<UiState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    match state.api_keys.revoke(&id).await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("保存托管 API Key 失败: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// 托管 API Key 的最近请求（最新的在前）
+async fn get_recent_requests(
+    State(state): State<UiState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    if state.api_keys.recent().is_none() {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "success": false,
+                "error": "未启用最近请求记录，请配置 recentRequestsPerKey"
+            })),
+        );
+    }
+    match state.api_keys.recent_requests(&id) {
+        Some(requests) => (
+            StatusCode::OK,
+            Json(serde_json::json!({"requests": requests})),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"success": false, "error": "托管 API Key 不存在"})),
+        ),
+    }
+}
+
+/// 会话缓存统计和条目（最近访问的在前）
+async fn list_conversations(State(state): State<UiState>) -> impl IntoResponse {
+    let entries: Vec<serde_json::Value> = state
+        .conversations
+        .list()
+        .into_iter()
+        .map(|(key, entry)| serde_json::json!({"key": key, "entry": entry}))
+        .collect();
+    Json(serde_json::json!({
+        "stats": state.conversations.stats(),
+        "entries": entries,
+    }))
+}
+
+/// 清空会话缓存
+async fn purge_conversations(State(state): State<UiState>) -> impl IntoResponse {
+    let removed = state.conversations.clear();
+    tracing::info!("已清空会话缓存，移除 {} 条", removed);
+    Json(serde_json::json!({"success": true, "removed": removed}))
+}
+
+/// 移除一个会话（键为 `{调用方}/{会话 ID}`，需要 URL 编码）
+async fn remove_conversation(
+    State(state): State<UiState>,
+    axum::extract::Path(key): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    if state.conversations.remove(&key) {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// 列出提示词模板
+async fn list_templates(State(state): State<UiState>) -> impl IntoResponse {
+    Json(serde_json::json!({"templates": state.templates.list()}))
+}
+
+/// 新增或替换提示词模板
+async fn save_template(
+    State(state): State<UiState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+    Json(template): Json<PromptTemplate>,
+) -> impl IntoResponse {
+    match state.templates.upsert(name, template).await {
+        Ok(()) => (StatusCode::OK, Json(serde_json::json!({"success": true}))),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"success": false, "error": e.to_string()})),
+        ),
+    }
+}
+
+/// 删除提示词模板
+async fn remove_template(
+    State(state): State<UiState>,
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    match state.templates.remove(&name).await {
+        Ok(true) => StatusCode::NO_CONTENT,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!("保存提示词模板失败: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
 /// 账号列表响应
 #[derive(Serialize)]
 struct AccountResponse {
@@ -122,6 +394,8 @@ struct AccountResponse {
     status: String,
     request_count: u64,
     error_count: u64,
+    /// 进行中的请求数
+    in_flight: usize,
     last_used_at: Option<String>,
     created_at: String,
 }
@@ -132,6 +406,7 @@ async fn list_accounts(State(state): State<UiState>) -> impl IntoResponse {
     let response: Vec<AccountResponse> = accounts
         .into_iter()
         .map(|a| AccountResponse {
+            in_flight: state.pool.in_flight_count(&a.id),
             id: a.id,
             name: a.name,
             status: format!("{:?}", a.status).to_lowercase(),
@@ -156,11 +431,18 @@ struct AddAccountRequest {
     client_secret: Option<String>,
     #[serde(default)]
     profile_arn: Option<String>,
+    #[serde(default)]
+    refresh_url: Option<String>,
+    #[serde(default)]
+    oidc_region: Option<String>,
+    #[serde(default)]
+    region: Option<String>,
 }
 
 /// Kiro 原始凭证格式（直接导入）
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(dead_code)]
 struct KiroRawCredentials {
     email: Option<String>,
     label: Option<String>,
@@ -199,12 +481,22 @@ async fn add_account(
         auth_method: Some(req.auth_method),
         client_id: req.client_id,
         client_secret: req.client_secret,
+        refresh_url: req.refresh_url,
+        oidc_region: req.oidc_region,
+        region: req.region,
+        machine_id: None,
     };
 
+    let needs_discovery = credentials.profile_arn.is_none();
     let account = Account::new(&id, req.name, credentials);
 
     match state.pool.add_account(account).await {
-        Ok(_) => (StatusCode::CREATED, Json(serde_json::json!({"id": id}))),
+        Ok(_) => {
+            if needs_discovery {
+                spawn_profile_arn_discovery(state.pool.clone(), id.clone());
+            }
+            (StatusCode::CREATED, Json(serde_json::json!({"id": id})))
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({"error": e.to_string()})),
@@ -252,12 +544,19 @@ async fn import_account(
         auth_method: Some(auth_method),
         client_id: raw.client_id,
         client_secret: raw.client_secret,
+        refresh_url: None,
+        oidc_region: raw.region,
+        region: None,
+        machine_id: None,
     };
 
     let account = Account::new(&id, name, credentials);
 
     match state.pool.add_account(account).await {
-        Ok(_) => (StatusCode::CREATED, Json(serde_json::json!({"id": id}))),
+        Ok(_) => {
+            spawn_profile_arn_discovery(state.pool.clone(), id.clone());
+            (StatusCode::CREATED, Json(serde_json::json!({"id": id})))
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({"error": e.to_string()})),
@@ -265,17 +564,70 @@ async fn import_account(
     }
 }
 
-/// 移除账号
+/// 后台自动发现 profileArn（必要时刷新 Token）
+fn spawn_profile_arn_discovery(pool: Arc<AccountPool>, id: String) {
+    tokio::spawn(async move {
+        if let Err(e) = pool.discover_profile_arn(&id).await {
+            tracing::warn!("账号 {} 自动发现 profileArn 失败: {}", id, e);
+        }
+    });
+}
+
+/// 无效移除原因的 400 响应
+fn invalid_removal_reason() -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({"error": "无效的移除原因"})),
+    )
+        .into_response()
+}
+
+/// 移除账号查询参数
+#[derive(Deserialize)]
+struct RemoveAccountQuery {
+    /// 移除原因（manual/banned/expired/invalid），省略时按账号状态推断
+    #[serde(default)]
+    reason: Option<String>,
+    /// 备注
+    #[serde(default)]
+    note: Option<String>,
+}
+
+/// 移除账号（保留墓碑记录）
 async fn remove_account(
     State(state): State<UiState>,
     axum::extract::Path(id): axum::extract::Path<String>,
-) -> impl IntoResponse {
-    match state.pool.remove_account(&id).await {
-        Some(_) => StatusCode::NO_CONTENT,
-        None => StatusCode::NOT_FOUND,
+    Query(query): Query<RemoveAccountQuery>,
+) -> Response {
+    let reason = match query.reason.as_deref().map(RemovalReason::parse) {
+        Some(None) => return invalid_removal_reason(),
+        reason => reason.flatten(),
+    };
+    match state.pool.remove_account(&id, reason, query.note).await {
+        Some(_) => StatusCode::NO_CONTENT.into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
     }
 }
 
+/// 已移除账号查询参数
+#[derive(Deserialize)]
+struct RemovedAccountsQuery {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// 已移除账号的墓碑记录（最近移除的在前）
+async fn list_removed_accounts(
+    State(state): State<UiState>,
+    Query(query): Query<RemovedAccountsQuery>,
+) -> Response {
+    let reason = match query.reason.as_deref().map(RemovalReason::parse) {
+        Some(None) => return invalid_removal_reason(),
+        reason => reason.flatten(),
+    };
+    Json(serde_json::json!({"removed": state.pool.tombstones(reason)})).into_response()
+}
+
 /// 启用账号
 async fn enable_account(
     State(state): State<UiState>,
@@ -300,6 +652,59 @@ async fn disable_account(
     }
 }
 
+/// 排空账号（不再分配新请求，进行中的请求结束后禁用）
+async fn drain_account(
+    State(state): State<UiState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    match state.pool.drain_account(&id).await {
+        Some(in_flight) => Json(serde_json::json!({"success": true, "inFlight": in_flight})),
+        None => Json(serde_json::json!({
+            "success": false,
+            "error": "账号不存在、已失效或已禁用"
+        })),
+    }
+}
+
+/// 账号测试请求
+#[derive(Deserialize, Default)]
+struct TestAccountRequest {
+    /// 测试模型（Kiro 模型 ID，默认 claude-haiku-4.5）
+    #[serde(default)]
+    model: Option<String>,
+}
+
+/// 测试账号（刷新 Token 并发送一条极短的生成请求）
+async fn test_account(
+    State(state): State<UiState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    body: Option<Json<TestAccountRequest>>,
+) -> impl IntoResponse {
+    let Json(req) = body.unwrap_or_default();
+    let model = req.model.as_deref().unwrap_or(DEFAULT_TEST_MODEL);
+    match state.pool.test_account(&id, model).await {
+        Some(report) => (StatusCode::OK, Json(serde_json::json!(report))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "账号不存在"})),
+        ),
+    }
+}
+
+/// 立即刷新账号 Token
+async fn refresh_account_token(
+    State(state): State<UiState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    match state.pool.refresh_account_token(&id).await {
+        Some(report) => (StatusCode::OK, Json(serde_json::json!(report))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "账号不存在"})),
+        ),
+    }
+}
+
 /// 获取策略
 async fn get_strategy(State(state): State<UiState>) -> impl IntoResponse {
     let strategy = state.pool.get_strategy().await;
@@ -317,19 +722,17 @@ async fn set_strategy(
     State(state): State<UiState>,
     Json(req): Json<SetStrategyRequest>,
 ) -> impl IntoResponse {
-    let strategy = match req.strategy.as_str() {
-        "round-robin" => SelectionStrategy::RoundRobin,
-        "random" => SelectionStrategy::Random,
-        "least-used" => SelectionStrategy::LeastUsed,
-        _ => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({"error": "无效的策略"})),
-            )
-        }
+    let Some(strategy) = SelectionStrategy::parse(&req.strategy) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "无效的策略"})),
+        );
     };
     state.pool.set_strategy(strategy).await;
-    (StatusCode::OK, Json(serde_json::json!({"success": true})))
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({"success": true, "strategy": strategy.as_str()})),
+    )
 }
 
 /// 获取请求记录
@@ -344,6 +747,54 @@ async fn get_request_stats(State(state): State<UiState>) -> impl IntoResponse {
     Json(stats)
 }
 
+/// 获取客户端分布统计
+async fn get_client_stats(State(state): State<UiState>) -> impl IntoResponse {
+    Json(state.pool.get_client_stats().await)
+}
+
+/// 获取各账号使用的 Machine ID（只读）
+async fn get_machine_ids(State(state): State<UiState>) -> impl IntoResponse {
+    Json(serde_json::json!({"machineIds": state.pool.machine_ids().await}))
+}
+
+/// 获取各账号按计费事件累计的额度消耗
+async fn get_quota_ledger(State(state): State<UiState>) -> impl IntoResponse {
+    Json(serde_json::json!({"ledger": state.pool.quota_ledger().await}))
+}
+
+/// 获取请求预写日志状态
+async fn get_journal(State(state): State<UiState>) -> impl IntoResponse {
+    match state.pool.journal_snapshot().await {
+        Some(snapshot) => (StatusCode::OK, Json(serde_json::json!(snapshot))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "请求预写日志未启用"})),
+        ),
+    }
+}
+
+/// 导出账号池状态快照（包含账号凭证）
+async fn get_snapshot(State(state): State<UiState>) -> impl IntoResponse {
+    Json(state.pool.snapshot().await)
+}
+
+/// 从快照恢复账号池状态（替换现有的全部账号）
+async fn restore_snapshot(
+    State(state): State<UiState>,
+    Json(snapshot): Json<PoolSnapshot>,
+) -> impl IntoResponse {
+    match state.pool.restore(snapshot).await {
+        Ok(count) => (
+            StatusCode::OK,
+            Json(serde_json::json!({"success": true, "accounts": count})),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"success": false, "error": e.to_string()})),
+        ),
+    }
+}
+
 /// 获取账号配额
 async fn get_account_usage(
     State(state): State<UiState>,